@@ -166,6 +166,28 @@ public class MainActivity extends NativeActivity {
         return uris.toArray(new String[0]);
     }
 
+    // Returns the size and the last modified time (in milliseconds since epoch) of the document, or
+    // null if they could not be queried.
+    public long[] getUriInfo(String uriString) {
+        Uri uri = Uri.parse(uriString);
+        Cursor c = null;
+        try {
+            String[] projections = new String[] {
+                DocumentsContract.Document.COLUMN_SIZE,
+                DocumentsContract.Document.COLUMN_LAST_MODIFIED
+            };
+            c = getContentResolver().query(uri, projections, null, null, null);
+            if (c != null && c.moveToFirst()) {
+                return new long[] { c.getLong(0), c.getLong(1) };
+            }
+        } catch(Exception ex) {
+            Log.e(TAG, "error querying uri info: " + ex.toString());
+        } finally {
+            if (c!=null) c.close();
+        }
+        return null;
+    }
+
     public void saveRam(String filename, ByteBuffer data) {
         try {
             FileOutputStream fos = openFileOutput(filename, MODE_PRIVATE);
@@ -189,6 +211,11 @@ public class MainActivity extends NativeActivity {
         }
     }
 
+    public boolean deleteSave(String filename) {
+        // only deletes from the private storage of the app, where the saves are
+        return deleteFile(filename);
+    }
+
     public long getFileDate(String filename) {
         try {
             File file = new File(getFilesDir(), filename);
@@ -1,6 +1,34 @@
-use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashSet},
+    sync::{Arc, Mutex},
+};
 
-use crate::{gameboy::GameBoy, interpreter::Interpreter, save_state::SaveState};
+use crate::{
+    gameboy::{
+        cartridge::Cartridge, cpu::CpuState, instruction_history::InstructionHistory,
+        interrupt_log::InterruptLog, watchpoints::WatchpointHit, GameBoy,
+    },
+    interpreter::Interpreter,
+    profiling::ProfilingData,
+    save_state::SaveState,
+    trace_logger::TraceLogger,
+};
+
+pub mod history;
+use self::history::History;
+
+pub mod memory_view;
+
+pub mod overlay;
+use self::overlay::Overlay;
+
+pub mod ram_search;
+use self::ram_search::{RamSearch, RamSnapshot, SearchFilter, SearchSize};
+
+#[cfg(feature = "script")]
+pub mod script;
+#[cfg(feature = "script")]
+use self::script::Script;
 
 pub mod break_flags {
     pub const WRITE: u8 = 1 << 0;
@@ -9,38 +37,139 @@ pub mod break_flags {
     pub const JUMP: u8 = 1 << 3;
 }
 
+/// The address of a breakpoint. A breakpoint without a bank matches the address in any bank.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy, Debug)]
+pub struct BreakAddress {
+    pub address: u16,
+    /// The ROM bank, as returned by `GameBoy::pc_bank`.
+    pub bank: Option<u16>,
+}
+impl BreakAddress {
+    pub fn new(bank: Option<u16>, address: u16) -> Self {
+        Self { address, bank }
+    }
+
+    /// Parse a address in the form `aaaa` or `bb:aaaa`, both in hexadecimal.
+    pub fn parse(text: &str) -> Option<Self> {
+        let (bank, address) = match text.split_once(':') {
+            Some((bank, address)) => (Some(u16::from_str_radix(bank, 16).ok()?), address),
+            None => (None, text),
+        };
+        let address = u16::from_str_radix(address, 16).ok()?;
+        Some(Self { address, bank })
+    }
+}
+impl From<u16> for BreakAddress {
+    fn from(address: u16) -> Self {
+        Self {
+            address,
+            bank: None,
+        }
+    }
+}
+impl std::fmt::Display for BreakAddress {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.bank {
+            Some(bank) => write!(f, "{:02x}:{:04x}", bank, self.address),
+            None => write!(f, "{:04x}", self.address),
+        }
+    }
+}
+
+/// Check if there is a breakpoint in `set` that matches `address`, in the currently mapped bank.
+fn contains(set: &HashSet<BreakAddress>, gb: &GameBoy, address: u16) -> bool {
+    if set.is_empty() {
+        return false;
+    }
+    set.contains(&BreakAddress::from(address))
+        || set.contains(&BreakAddress::new(gb.pc_bank(address), address))
+}
+
 #[derive(PartialEq, Eq, Clone, Copy, Debug)]
 pub enum RunResult {
     ReachBreakpoint,
     ReachTargetAddress,
     ReachTargetClock,
+    ReachTargetDepth,
+    ReachTargetLine,
+    ReachTargetInstruction,
+    ReachTargetFrame,
+    ReachWatchpoint,
+    /// The next instruction is one of the illegal opcodes that lock up the CPU.
+    CpuLocked,
     TimeOut,
 }
+impl std::fmt::Display for RunResult {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let reason = match self {
+            RunResult::ReachBreakpoint => "reached a breakpoint",
+            RunResult::ReachTargetAddress => "reached the target address",
+            RunResult::ReachTargetClock => "reached the target clock count",
+            RunResult::ReachTargetDepth => "returned from the routine",
+            RunResult::ReachTargetLine => "reached the target line",
+            RunResult::ReachTargetInstruction => "executed the target number of instructions",
+            RunResult::ReachTargetFrame => "reached the start of the target frame",
+            RunResult::ReachWatchpoint => "reached a watchpoint",
+            RunResult::CpuLocked => "the CPU locked up on a illegal opcode",
+            RunResult::TimeOut => "timed out",
+        };
+        f.write_str(reason)
+    }
+}
+
+/// A condition where the execution stops, set by the `runto` and `runfor` commands, in addition to
+/// `Debugger::target_address` and `Debugger::target_clock`.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum RunTarget {
+    /// Stop when LY changes to the given line. V-Blank starts at line 144.
+    Line(u8),
+    /// Stop when `GameBoy::instruction_count` reaches the given value.
+    Instruction(u64),
+    /// Stop after the given number of frames start, when LY changes to 0.
+    Frames(u64),
+}
 
 pub enum DebuggerEvent {
     Step,
-    StepBack,
+    /// Step back the given number of instructions.
+    StepBack(u64),
     Reset,
     Run,
     BreakpointsUpdate,
     WatchsUpdate,
+    RamSearchUpdate,
 }
 
 #[derive(Default)]
 pub struct Debugger {
-    write_breakpoints: HashSet<u16>,
-    read_breakpoints: HashSet<u16>,
-    jump_breakpoints: HashSet<u16>,
-    execute_breakpoints: HashSet<u16>,
+    write_breakpoints: HashSet<BreakAddress>,
+    read_breakpoints: HashSet<BreakAddress>,
+    jump_breakpoints: HashSet<BreakAddress>,
+    execute_breakpoints: HashSet<BreakAddress>,
     interrupt_breakpoint: bool,
-    breakpoints: BTreeMap<u16, u8>,
+    breakpoints: BTreeMap<BreakAddress, u8>,
     watchs: BTreeSet<u16>,
     /// Address to stop at
     pub target_address: Option<u16>,
     /// Clock to stop at
     pub target_clock: Option<u64>,
-    /// The clock_count in the previous instruction, used for stepback.
+    /// Stop when the call stack gets smaller than this depth.
+    pub target_call_depth: Option<usize>,
+    /// Stop on a PPU line, a instruction count or a frame.
+    pub run_target: Option<RunTarget>,
+    /// The last memory access that matched a watchpoint.
+    pub last_watchpoint_hit: Option<WatchpointHit>,
+    /// The clock_count in the previous instruction.
     pub last_op_clock: u64,
+    /// The execution history, used for stepback.
+    pub history: History,
+    /// The current RAM search, started by the `search start` command.
+    pub ram_search: Option<RamSearch>,
+    /// The texts drawn over the screen, by the running script.
+    pub overlay: Arc<Mutex<Overlay>>,
+    /// The script started by the `script run` command.
+    #[cfg(feature = "script")]
+    pub script: Option<Arc<Mutex<Script>>>,
     /// Callback called when self is mutated
     #[cfg(not(target_arch = "wasm32"))]
     pub callback: Option<Box<dyn FnMut(&Self, DebuggerEvent) + Send>>,
@@ -48,7 +177,12 @@ pub struct Debugger {
     pub callback: Option<Box<dyn FnMut(&Self, DebuggerEvent)>>,
 }
 impl Debugger {
-    pub fn execute_command<'a>(&mut self, gb: &GameBoy, args: &[&'a str]) -> Result<(), String> {
+    /// Execute a debugger command. Return the text that the command outputs, if any.
+    pub fn execute_command<'a>(
+        &mut self,
+        gb: &mut GameBoy,
+        args: &[&'a str],
+    ) -> Result<String, String> {
         use DebuggerEvent::*;
         let callback = |a: &mut Debugger, b| {
             let mut callback = a.callback.take();
@@ -59,28 +193,157 @@ impl Debugger {
         };
         self.target_address = None;
         self.target_clock = None;
+        self.target_call_depth = None;
+        self.run_target = None;
+        let mut output = String::new();
         match args[0] {
             "step" | "" => callback(self, Step),
-            "stepback" => callback(self, StepBack),
+            "stepback" => {
+                let count = match args.get(1) {
+                    None => 1,
+                    Some(x) => match x.parse::<u64>() {
+                        Ok(x) => x,
+                        Err(_) => {
+                            return Err(format!(
+                            "'stepback' expected a number of instructions, '{}' is not a valid one",
+                            x
+                        ))
+                        }
+                    },
+                };
+                callback(self, StepBack(count))
+            }
+
+            "stepover" => {
+                let pc = gb.cpu.pc;
+                let op = gb.peek(pc);
+                match op {
+                    // CALL and RST
+                    0xc4 | 0xcc | 0xcd | 0xd4 | 0xdc | 0xc7 | 0xcf | 0xd7 | 0xdf | 0xe7 | 0xef
+                    | 0xf7 | 0xff => {
+                        let len = crate::consts::LEN[op as usize] as u16;
+                        self.target_address = Some(pc.wrapping_add(len));
+                        callback(self, Run);
+                    }
+                    _ => callback(self, Step),
+                }
+            }
+            "stepout" => {
+                let depth = gb.call_stack().len();
+                if depth == 0 {
+                    return Err("there is no routine to step out of".to_string());
+                }
+                self.target_call_depth = Some(depth);
+                callback(self, Run);
+            }
+            "backtrace" => {
+                use std::fmt::Write;
+                let frames = gb.call_stack();
+                if frames.is_empty() {
+                    output += "call stack is empty";
+                    return Ok(output);
+                }
+                let trace = gb.trace.borrow();
+                for (i, frame) in frames.iter().rev().enumerate() {
+                    let bank = frame
+                        .bank
+                        .map_or("--".to_string(), |x| format!("{:02x}", x));
+                    let symbol = trace
+                        .symbol(frame.bank, frame.address)
+                        .map_or(String::new(), |x| format!(" {}", x));
+                    let _ = writeln!(
+                        output,
+                        "#{:<3} {}:{:04x}{} sp: {:04x} return: {:04x}{}",
+                        i,
+                        bank,
+                        frame.address,
+                        symbol,
+                        frame.sp,
+                        frame.return_address,
+                        if frame.interrupt { " (interrupt)" } else { "" },
+                    );
+                }
+                // remove the trailing newline
+                output.pop();
+            }
 
             "reset" => callback(self, Reset),
             "runto" => {
-                if args.len() != 2 {
+                if args.len() < 2 {
+                    return Err(
+                        "'runto' expect a address, 'vblank', 'frame' or 'line <ly>'".to_string()
+                    );
+                }
+                let expected_args = if args[1] == "line" { 3 } else { 2 };
+                if args.len() != expected_args {
                     return Err(format!(
-                        "'runto' expect 1 argument, receive {}",
+                        "'runto {}' expect {} argument, receive {}",
+                        args[1],
+                        expected_args - 1,
                         args.len() - 1
                     ));
                 }
-                let address = match u16::from_str_radix(args[1], 16) {
-                    Ok(x) => x,
-                    Err(_) => {
+                match args[1] {
+                    "vblank" => self.run_target = Some(RunTarget::Line(144)),
+                    "frame" => self.run_target = Some(RunTarget::Frames(1)),
+                    "line" => {
+                        let line = match args[2].parse::<u8>() {
+                            Ok(x) if x <= 153 => x,
+                            _ => {
+                                return Err(format!(
+                                    "'runto line' expected a line from 0 to 153, '{}' is not a \
+                                     valid one",
+                                    args[2]
+                                ))
+                            }
+                        };
+                        self.run_target = Some(RunTarget::Line(line));
+                    }
+                    address => {
+                        let address = match u16::from_str_radix(address, 16) {
+                            Ok(x) => x,
+                            Err(_) => {
+                                return Err(format!(
+                                    "'runto' expected a address, '{}' is not a valid one",
+                                    address
+                                ))
+                            }
+                        };
+                        self.target_address = Some(address);
+                    }
+                }
+                callback(self, Run);
+            }
+            "runfor" => {
+                if args.len() != 3 {
+                    return Err(format!(
+                        "'runfor' expect 2 arguments, receive {}",
+                        args.len() - 1
+                    ));
+                }
+                let count = match args[1].parse::<u64>() {
+                    Ok(x) if x > 0 => x,
+                    _ => {
                         return Err(format!(
-                            "'runto' expected a address, '{}' is not a valid one",
+                            "'runfor' expected a positive number, '{}' is not a valid one",
                             args[1]
                         ))
                     }
                 };
-                self.target_address = Some(address);
+                match args[2] {
+                    "cycles" => self.target_clock = Some(gb.clock_count + count),
+                    "instructions" => {
+                        self.run_target = Some(RunTarget::Instruction(gb.instruction_count + count))
+                    }
+                    "frames" => self.run_target = Some(RunTarget::Frames(count)),
+                    x => {
+                        return Err(format!(
+                            "'runfor' expected 'cycles', 'instructions' or 'frames', '{}' is not \
+                             a valid one",
+                            x
+                        ))
+                    }
+                }
                 callback(self, Run);
             }
             "run" => {
@@ -124,7 +387,7 @@ impl Debugger {
                     match args[1] {
                         "interrupt" => {
                             self.interrupt_breakpoint = true;
-                            return Ok(());
+                            return Ok(output);
                         }
                         _ => {}
                     }
@@ -152,9 +415,9 @@ impl Debugger {
                 use break_flags::*;
                 let flags = (write * WRITE) | (read * READ) | (execute * EXECUTE) | (jump * JUMP);
 
-                let address = match u16::from_str_radix(args[2], 16) {
-                    Ok(x) => x,
-                    Err(_) => {
+                let address = match BreakAddress::parse(args[2]) {
+                    Some(x) => x,
+                    None => {
                         return Err(format!(
                             "'break' expected a address, '{}' is not a valid one",
                             args[2]
@@ -165,9 +428,33 @@ impl Debugger {
                 self.add_break(flags, address);
             }
             "watch" => {
+                if args.len() == 3 {
+                    let (read, write) = match args[1] {
+                        "read" => (true, false),
+                        "write" => (false, true),
+                        "rw" => (true, true),
+                        _ => {
+                            return Err(format!(
+                                "'{}' is not a valid subcommand for 'watch'",
+                                args[1]
+                            ))
+                        }
+                    };
+                    let (start, end) = match parse_range(args[2]) {
+                        Some(x) => x,
+                        None => {
+                            return Err(format!(
+                                "'watch' expected a address or a range, '{}' is not a valid one",
+                                args[2]
+                            ))
+                        }
+                    };
+                    self.add_watchpoint(gb, start, end, read, write);
+                    return Ok(output);
+                }
                 if args.len() != 2 {
                     return Err(format!(
-                        "'watch' expect 1 argument, receive {}",
+                        "'watch' expect 1 or 2 arguments, receive {}",
                         args.len() - 1
                     ));
                 }
@@ -195,9 +482,28 @@ impl Debugger {
                 let file = args[1];
                 let trace = gb.trace.borrow();
                 let mut string = String::new();
-                trace.fmt(gb, &mut string).map_err(|x| x.to_string())?;
+                trace.fmt(&*gb, &mut string).map_err(|x| x.to_string())?;
                 std::fs::write(file, string).map_err(|x| x.to_string())?;
             }
+            // write the traced disassembly to a file, as a RGBDS assembly file
+            "dumpasm" => {
+                if args.len() != 2 {
+                    return Err(format!(
+                        "'dumpasm' expect 1 argument, receive {}",
+                        args.len() - 1
+                    ));
+                }
+                let path = args[1];
+                let file = std::fs::File::create(path)
+                    .map_err(|x| format!("failed to create '{}': {}", path, x))?;
+                let mut writer = std::io::BufWriter::new(file);
+                gb.trace
+                    .borrow()
+                    .export_asm(&*gb, &mut writer)
+                    .and_then(|_| std::io::Write::flush(&mut writer))
+                    .map_err(|x| format!("failed to write '{}': {}", path, x))?;
+                output = format!("disassembly written to '{}'", path);
+            }
             // save some state to a file (for dev purposes)
             "save" => {
                 if args.len() != 2 {
@@ -253,17 +559,383 @@ impl Debugger {
                 // gb.serial_transfer.save_state(data)?;
                 // gb.v_blank.save_state(data)
             }
+            // collect and print execution statistics
+            "profile" => match args.get(1).copied() {
+                Some("on") => {
+                    if gb.profiling.is_none() {
+                        gb.profiling = Some(Box::new(ProfilingData::new()));
+                    }
+                }
+                Some("off") => gb.profiling = None,
+                Some("reset") => {
+                    if let Some(profiling) = &mut gb.profiling {
+                        profiling.reset();
+                    }
+                }
+                Some("export") => {
+                    let path = match args.get(2) {
+                        Some(x) => *x,
+                        None => return Err("expected a file path".to_string()),
+                    };
+                    let profiling = match &gb.profiling {
+                        Some(x) => x,
+                        None => return Err("profiling is not enabled".to_string()),
+                    };
+                    let file = std::fs::File::create(path)
+                        .map_err(|x| format!("failed to create '{}': {}", path, x))?;
+                    let mut writer = std::io::BufWriter::new(file);
+                    profiling
+                        .write_csv(&gb.trace.borrow(), &mut writer)
+                        .and_then(|_| std::io::Write::flush(&mut writer))
+                        .map_err(|x| format!("failed to write '{}': {}", path, x))?;
+                    output = format!("profile written to '{}'", path);
+                }
+                top => {
+                    let top = match top.map(|x| x.parse::<usize>()) {
+                        None => 10,
+                        Some(Ok(x)) => x,
+                        Some(Err(_)) => {
+                            return Err(format!(
+                                "'{}' is not a valid subcommand for 'profile'",
+                                args[1]
+                            ))
+                        }
+                    };
+                    let profiling = match &gb.profiling {
+                        Some(x) => x,
+                        None => return Err("profiling is not enabled".to_string()),
+                    };
+                    profiling
+                        .report(top, &gb.trace.borrow(), &mut output)
+                        .map_err(|x| x.to_string())?;
+                }
+            },
+            // write a line for each executed instruction to a file
+            "trace" => match args.get(1).copied() {
+                Some("start") => {
+                    let path = match args.get(2) {
+                        Some(x) => *x,
+                        None => return Err("expected a file path".to_string()),
+                    };
+                    let logger = TraceLogger::create(path)
+                        .map_err(|x| format!("failed to create '{}': {}", path, x))?;
+                    gb.trace_logger = Some(Box::new(logger));
+                    output = format!("tracing to '{}'", path);
+                }
+                Some("stop") => {
+                    let mut logger = match gb.trace_logger.take() {
+                        Some(x) => x,
+                        None => return Err("tracing is not enabled".to_string()),
+                    };
+                    logger.flush().map_err(|x| x.to_string())?;
+                    if let Some(err) = logger.error.take() {
+                        return Err(format!("failed to write trace: {}", err));
+                    }
+                    output = format!("traced {} instructions", logger.lines);
+                }
+                // forget the code discovered by the disassembler
+                Some("clear") => {
+                    gb.trace.borrow_mut().clear();
+                    output = "disassembly trace cleared".to_string();
+                }
+                _ => return Err("expected 'start <file>', 'stop' or 'clear'".to_string()),
+            },
+            // record the last executed instructions, and list or dump them
+            "history" => match args.get(1).copied() {
+                Some("on") => {
+                    let capacity = match args.get(2).map(|x| x.parse::<usize>()) {
+                        None => 10_000,
+                        Some(Ok(x)) if x > 0 => x,
+                        Some(_) => {
+                            return Err(format!(
+                                "'history on' expected a number of instructions, '{}' is not a \
+                                 valid one",
+                                args[2]
+                            ))
+                        }
+                    };
+                    gb.instruction_history = Some(Box::new(InstructionHistory::new(capacity)));
+                    output = format!("recording the last {} instructions", capacity);
+                }
+                Some("off") => gb.instruction_history = None,
+                Some("dump") => {
+                    let path = match args.get(2) {
+                        Some(x) => *x,
+                        None => return Err("expected a file path".to_string()),
+                    };
+                    let history = match &gb.instruction_history {
+                        Some(x) => x,
+                        None => return Err("the history is not enabled".to_string()),
+                    };
+                    let file = std::fs::File::create(path)
+                        .map_err(|x| format!("failed to create '{}': {}", path, x))?;
+                    let mut writer = std::io::BufWriter::new(file);
+                    history
+                        .dump(gb, &mut writer)
+                        .and_then(|_| std::io::Write::flush(&mut writer))
+                        .map_err(|x| format!("failed to write '{}': {}", path, x))?;
+                    output = format!("{} instructions written to '{}'", history.len(), path);
+                }
+                count => {
+                    let count = match count.map(|x| x.parse::<usize>()) {
+                        None => 20,
+                        Some(Ok(x)) => x,
+                        Some(Err(_)) => {
+                            return Err(format!(
+                                "'{}' is not a valid subcommand for 'history'",
+                                args[1]
+                            ))
+                        }
+                    };
+                    let history = match &gb.instruction_history {
+                        Some(x) => x,
+                        None => return Err("the history is not enabled".to_string()),
+                    };
+                    for entry in history.iter().take(count) {
+                        if !output.is_empty() {
+                            output.push('\n');
+                        }
+                        entry.fmt(gb, &mut output).map_err(|x| x.to_string())?;
+                    }
+                }
+            },
+            // record the interrupt dispatches and the changes to IME, and list them
+            "interrupts" => match args.get(1).copied() {
+                Some("on") => {
+                    let capacity = match args.get(2).map(|x| x.parse::<usize>()) {
+                        None => 256,
+                        Some(Ok(x)) if x > 0 => x,
+                        Some(_) => {
+                            return Err(format!(
+                                "'interrupts on' expected a number of events, '{}' is not a \
+                                 valid one",
+                                args[2]
+                            ))
+                        }
+                    };
+                    gb.interrupt_log = Some(Box::new(InterruptLog::new(capacity)));
+                    output = format!("recording the last {} interrupt events", capacity);
+                }
+                Some("off") => gb.interrupt_log = None,
+                count => {
+                    let count = match count.map(|x| x.parse::<usize>()) {
+                        None => 20,
+                        Some(Ok(x)) => x,
+                        Some(Err(_)) => {
+                            return Err(format!(
+                                "'{}' is not a valid subcommand for 'interrupts'",
+                                args[1]
+                            ))
+                        }
+                    };
+                    let log = match &gb.interrupt_log {
+                        Some(x) => x,
+                        None => return Err("the interrupt log is not enabled".to_string()),
+                    };
+                    let skip = log.len().saturating_sub(count);
+                    for event in log.recent().skip(skip) {
+                        if !output.is_empty() {
+                            output.push('\n');
+                        }
+                        event.fmt(&mut output).map_err(|x| x.to_string())?;
+                    }
+                }
+            },
+            // show how much of the ROM was traced as code, and count the executions of each
+            // instruction
+            "coverage" => match args.get(1).copied() {
+                None => {
+                    let report = gb.trace.borrow().coverage(gb.cartridge.rom.len());
+                    report.fmt_table(&mut output).map_err(|x| x.to_string())?;
+                }
+                Some("export") => {
+                    let path = match args.get(2) {
+                        Some(x) => *x,
+                        None => return Err("expected a file path".to_string()),
+                    };
+                    let report = gb.trace.borrow().coverage(gb.cartridge.rom.len());
+                    let file = std::fs::File::create(path)
+                        .map_err(|x| format!("failed to create '{}': {}", path, x))?;
+                    let mut writer = std::io::BufWriter::new(file);
+                    report
+                        .write_csv(&mut writer)
+                        .and_then(|_| std::io::Write::flush(&mut writer))
+                        .map_err(|x| format!("failed to write '{}': {}", path, x))?;
+                    output = format!("coverage written to '{}'", path);
+                }
+                Some("count") => {
+                    let enabled = match args.get(2).copied() {
+                        Some("on") => true,
+                        Some("off") => false,
+                        _ => return Err("expected 'on' or 'off'".to_string()),
+                    };
+                    let rom_len = gb.cartridge.rom.len();
+                    gb.trace.borrow_mut().set_counting(rom_len, enabled);
+                }
+                Some(x) => return Err(format!("'{}' is not a valid subcommand for 'coverage'", x)),
+            },
+            // run a script, that registers callbacks and draws over the screen
+            #[cfg(feature = "script")]
+            "script" => match args.get(1).copied() {
+                Some("run") => {
+                    let path = match args.get(2) {
+                        Some(x) => *x,
+                        None => return Err("expected a file path".to_string()),
+                    };
+                    // stop the current script first, so it don't draw over the new one
+                    self.script = None;
+                    self.overlay.lock().unwrap().clear();
+                    let mut script = Script::load(path, gb, self.overlay.clone())?;
+                    output = format!("running '{}'", path);
+                    for message in script.take_messages() {
+                        output += "\n";
+                        output += &message;
+                    }
+                    let script = Arc::new(Mutex::new(script));
+                    script::attach(&script, gb);
+                    self.script = Some(script);
+                }
+                Some("stop") => {
+                    self.script = None;
+                    self.overlay.lock().unwrap().clear();
+                }
+                None => {
+                    let script = match &self.script {
+                        Some(x) => x,
+                        None => return Err("no script is running".to_string()),
+                    };
+                    output = script.lock().unwrap().take_messages().join("\n");
+                }
+                Some(x) => return Err(format!("'{}' is not a valid subcommand for 'script'", x)),
+            },
+            #[cfg(not(feature = "script"))]
+            "script" => {
+                return Err("scripts are not supported, build with the 'script' feature".to_string())
+            }
+            // inspect or control the real time clock of the cartridge
+            "rtc" => {
+                let rtc = match gb.cartridge.rtc_mut() {
+                    Some(x) => x,
+                    None => return Err("the cartridge has no real time clock".to_string()),
+                };
+                match args.get(1).copied() {
+                    None => {}
+                    Some("freeze") => rtc.freeze(true),
+                    Some("run") => rtc.freeze(false),
+                    Some("set") => {
+                        let seconds = match args.get(2).map(|x| x.parse::<u64>()) {
+                            Some(Ok(x)) => x,
+                            _ => return Err("expected the number of seconds".to_string()),
+                        };
+                        rtc.set_seconds(seconds);
+                    }
+                    Some(x) => return Err(format!("'{}' is not a valid subcommand for 'rtc'", x)),
+                }
+                let seconds = rtc.seconds();
+                output = format!(
+                    "day {}, {:02}:{:02}:{:02}{}{}",
+                    seconds / (24 * 3600),
+                    seconds / 3600 % 24,
+                    seconds / 60 % 60,
+                    seconds % 60,
+                    if rtc.halted() { " (halted)" } else { "" },
+                    if rtc.frozen_time.is_some() {
+                        " (frozen)"
+                    } else {
+                        ""
+                    },
+                );
+            }
+            // inspect the state of the memory bank controller of the cartridge
+            "cartridge" => output = cartridge_state(&gb.cartridge),
+            "symbols" => match args.get(1).copied() {
+                Some("load") => {
+                    let path = match args.get(2) {
+                        Some(x) => *x,
+                        None => return Err("expected a file path".to_string()),
+                    };
+                    let file = std::fs::File::open(path)
+                        .map_err(|x| format!("failed to open '{}': {}", path, x))?;
+                    let count = gb
+                        .trace
+                        .borrow_mut()
+                        .load_symbols(std::io::BufReader::new(file))?;
+                    output = format!("loaded {} symbols from '{}'", count, path);
+                }
+                _ => return Err("expected 'load <file>'".to_string()),
+            },
+            "search" => {
+                output = self.ram_search_command(gb, &args[1..])?;
+                callback(self, RamSearchUpdate);
+            }
             x => return Err(format!("'{}' is not a valid command", x)),
         }
-        Ok(())
+        Ok(output)
     }
 
-    pub fn breakpoints(&self) -> &BTreeMap<u16, u8> {
+    /// Execute the subcommands of `search`, that start a RAM search and filter its results.
+    fn ram_search_command(&mut self, gb: &GameBoy, args: &[&str]) -> Result<String, String> {
+        let filter = match args {
+            ["start"] | ["start", "8"] => {
+                self.ram_search = Some(RamSearch::start(RamSnapshot::new(gb), SearchSize::Byte));
+                None
+            }
+            ["start", "16"] => {
+                self.ram_search = Some(RamSearch::start(RamSnapshot::new(gb), SearchSize::Word));
+                None
+            }
+            ["start", x] => {
+                return Err(format!(
+                    "'search start' expected a size of 8 or 16 bits, '{}' is not a valid one",
+                    x
+                ))
+            }
+            ["eq", x] => match u16::from_str_radix(x, 16) {
+                Ok(x) => Some(SearchFilter::Equal(x)),
+                Err(_) => {
+                    return Err(format!(
+                        "'search eq' expected a value, '{}' is not a valid one",
+                        x
+                    ))
+                }
+            },
+            ["inc"] => Some(SearchFilter::Increased),
+            ["dec"] => Some(SearchFilter::Decreased),
+            ["changed"] => Some(SearchFilter::Changed),
+            ["unchanged"] => Some(SearchFilter::Unchanged),
+            ["list"] => None,
+            [] => return Err("'search' expected a subcommand".to_string()),
+            [x, ..] => return Err(format!("'{}' is not a valid subcommand for 'search'", x)),
+        };
+
+        let search = match &mut self.ram_search {
+            Some(x) => x,
+            None => return Err("there is no search, start one with 'search start'".to_string()),
+        };
+        if let Some(filter) = filter {
+            search.filter(filter, RamSnapshot::new(gb));
+        }
+
+        let results = search.results();
+        let mut output = format!("{} results", results.len());
+        if args[0] == "list" {
+            const MAX_LISTED: usize = 32;
+            for &address in results.iter().take(MAX_LISTED) {
+                output += &format!("\n{:04x} = {:x}", address, search.previous_value(address));
+            }
+            if results.len() > MAX_LISTED {
+                output += "\n...";
+            }
+        }
+        Ok(output)
+    }
+
+    pub fn breakpoints(&self) -> &BTreeMap<BreakAddress, u8> {
         &self.breakpoints
     }
 
-    pub fn remove_break(&mut self, address: u16) {
-        let address = &address;
+    pub fn remove_break(&mut self, address: impl Into<BreakAddress>) {
+        let address = &address.into();
         self.breakpoints.remove(address);
         self.read_breakpoints.remove(address);
         self.jump_breakpoints.remove(address);
@@ -276,7 +948,8 @@ impl Debugger {
         self.callback = take;
     }
 
-    pub fn add_break(&mut self, flags: u8, address: u16) {
+    pub fn add_break(&mut self, flags: u8, address: impl Into<BreakAddress>) {
+        let address = address.into();
         debug_assert!(flags & 0xF0 == 0);
         *self.breakpoints.entry(address).or_default() |= flags;
         if (flags & break_flags::WRITE) != 0 {
@@ -315,25 +988,48 @@ impl Debugger {
         self.callback = take;
     }
 
+    /// Add a watchpoint to the addresses from `start` to `end`, inclusive.
+    pub fn add_watchpoint(
+        &mut self,
+        gb: &mut GameBoy,
+        start: u16,
+        end: u16,
+        read: bool,
+        write: bool,
+    ) {
+        gb.watchpoints.add(start, end, read, write);
+        let mut take = self.callback.take();
+        take.as_mut().map(|x| x(self, DebuggerEvent::WatchsUpdate));
+        self.callback = take;
+    }
+
+    pub fn remove_watchpoint(&mut self, gb: &mut GameBoy, index: usize) {
+        gb.watchpoints.remove(index);
+        let mut take = self.callback.take();
+        take.as_mut().map(|x| x(self, DebuggerEvent::WatchsUpdate));
+        self.callback = take;
+    }
+
     pub fn check_break(&self, inter: &mut Interpreter) -> bool {
+        let gb = &*inter.0;
         let writes = inter.will_write_to();
-        for w in &writes.1[..writes.0 as usize] {
-            if self.write_breakpoints.contains(w) {
+        for &w in &writes.1[..writes.0 as usize] {
+            if contains(&self.write_breakpoints, gb, w) {
                 return true;
             }
         }
         let reads = inter.will_read_from();
-        for r in &reads.1[..reads.0 as usize] {
-            if self.read_breakpoints.contains(r) {
+        for &r in &reads.1[..reads.0 as usize] {
+            if contains(&self.read_breakpoints, gb, r) {
                 return true;
             }
         }
         if let Some(jump) = inter.will_jump_to() {
-            if self.jump_breakpoints.contains(&jump) {
+            if contains(&self.jump_breakpoints, gb, jump) {
                 return true;
             }
         }
-        if self.execute_breakpoints.contains(&inter.0.cpu.pc) {
+        if contains(&self.execute_breakpoints, gb, gb.cpu.pc) {
             return true;
         }
         false
@@ -343,6 +1039,16 @@ impl Debugger {
         self.run_until(gb, gb.clock_count)
     }
 
+    /// Go back `count` instructions, by replaying the execution from the nearest checkpoint in
+    /// `history`.
+    pub fn step_back(&mut self, gb: &mut GameBoy, count: u64) -> Result<(), String> {
+        self.history.step_back(gb, count)?;
+        // clear the audio output
+        let clock_count = gb.clock_count;
+        let _ = gb.sound.borrow_mut().get_output(clock_count);
+        Ok(())
+    }
+
     pub fn run_for(&mut self, gb: &mut GameBoy, clocks: u64) -> RunResult {
         self.run_until(gb, gb.clock_count + clocks)
     }
@@ -356,13 +1062,54 @@ impl Debugger {
             target_clock
         };
 
+        // ignore any hit that happened outside of the debugger
+        inter.0.watchpoints.take_hit();
+
+        #[cfg(feature = "script")]
+        let script = self.script.clone();
+
         let result = loop {
             self.last_op_clock = inter.0.clock_count;
+            let last_ly = inter.0.ppu.borrow().ly;
+            #[cfg(feature = "script")]
+            let script_writes = match &script {
+                Some(script) => {
+                    let mut script = script.lock().unwrap();
+                    if script.has_breakpoint(inter.0.cpu.pc) {
+                        script.on_breakpoint(inter.0);
+                    }
+                    script.watched_writes(&inter)
+                }
+                None => (0, [0; 2]),
+            };
+            self.history.before_op(inter.0);
             inter.interpret_op();
+            self.history.after_op(inter.0, self.last_op_clock);
+            #[cfg(feature = "script")]
+            if let Some(script) = &script {
+                let mut script = script.lock().unwrap();
+                for &address in &script_writes.1[..script_writes.0 as usize] {
+                    let value = inter.0.peek(address);
+                    script.on_memory_write(inter.0, address, value);
+                }
+            }
+
+            if let Some(hit) = inter.0.watchpoints.take_hit() {
+                self.last_watchpoint_hit = Some(hit);
+                break RunResult::ReachWatchpoint;
+            }
 
             if Some(inter.0.cpu.pc) == self.target_address {
                 self.target_address = None;
                 break RunResult::ReachTargetAddress;
+            } else if self
+                .target_call_depth
+                .map_or(false, |depth| inter.0.call_stack().len() < depth)
+            {
+                self.target_call_depth = None;
+                break RunResult::ReachTargetDepth;
+            } else if let Some(result) = self.check_run_target(inter.0, last_ly) {
+                break result;
             } else if inter.0.clock_count >= target_clock {
                 if Some(target_clock) == self.target_clock {
                     self.target_clock = None;
@@ -375,6 +1122,11 @@ impl Debugger {
             if self.check_break(&mut inter) {
                 break RunResult::ReachBreakpoint;
             }
+            if inter.0.cpu.state == CpuState::Running
+                && is_lock_opcode(inter.0.peek(inter.0.cpu.pc))
+            {
+                break RunResult::CpuLocked;
+            }
             if self.interrupt_breakpoint {
                 let interrupts: u8 = inter.0.interrupt_flag & inter.0.interrupt_enabled;
                 if interrupts != 0 && inter.0.cpu.ime == crate::gameboy::cpu::ImeState::Enabled {
@@ -390,6 +1142,29 @@ impl Debugger {
         result
     }
 
+    /// Check if `run_target` was reached by the last instruction, and clear it if so. `last_ly` is
+    /// the value of LY before the instruction.
+    fn check_run_target(&mut self, gb: &GameBoy, last_ly: u8) -> Option<RunResult> {
+        let ly = gb.ppu.borrow().ly;
+        let line_changed = ly != last_ly;
+        let result = match self.run_target.as_mut()? {
+            RunTarget::Line(line) if line_changed && ly == *line => RunResult::ReachTargetLine,
+            RunTarget::Instruction(count) if gb.instruction_count >= *count => {
+                RunResult::ReachTargetInstruction
+            }
+            RunTarget::Frames(count) if line_changed && ly == 0 => {
+                *count -= 1;
+                if *count > 0 {
+                    return None;
+                }
+                RunResult::ReachTargetFrame
+            }
+            _ => return None,
+        };
+        self.run_target = None;
+        Some(result)
+    }
+
     // pub fn set_target_address(&mut self, address: Option<u16>) {
     //     self.target_address = address;
     // }
@@ -398,3 +1173,93 @@ impl Debugger {
     //     self.target_clock = clock;
     // }
 }
+
+/// If `opcode` is one of the illegal opcodes, that lock up the CPU when executed.
+fn is_lock_opcode(opcode: u8) -> bool {
+    matches!(
+        opcode,
+        0xD3 | 0xDB | 0xDD | 0xE3 | 0xE4 | 0xEB | 0xEC | 0xED | 0xF4 | 0xFC | 0xFD
+    )
+}
+
+/// Describe the mapper of the cartridge, and its current banks, in a single line.
+pub fn cartridge_state(cartridge: &Cartridge) -> String {
+    let mut text = format!(
+        "{} rom:{:02x} ram:{:02x} ({})",
+        cartridge.mapper_kind(),
+        cartridge.curr_rom_bank(),
+        cartridge.curr_ram_bank(),
+        if cartridge.ram_enabled() { "on" } else { "off" },
+    );
+    if let Some(mode) = cartridge.banking_mode() {
+        text += &format!(" mode:{}", mode as u8);
+    }
+    text
+}
+
+/// The state of the CPU and the PPU, and the instruction at PC, in a few lines, as shown by the
+/// debug overlay. Everything is read with `peek`, so calling this doesn't affect the emulation.
+pub fn cpu_ppu_state(gb: &GameBoy) -> String {
+    use crate::{consts::LEN, disassembler::disassembly_opcode, gameboy::cpu::ImeState};
+
+    let cpu = &gb.cpu;
+    let ime = match cpu.ime {
+        ImeState::Disabled => "off",
+        ImeState::Enabled => "on",
+        ImeState::ToBeEnable => "pending",
+    };
+    let mut text = format!(
+        "AF:{:04X} BC:{:04X} DE:{:04X} HL:{:04X}\n\
+         SP:{:04X} PC:{:04X} IME:{}\n\
+         LCDC:{:02X} STAT:{:02X} LY:{:02X}\n\
+         IF:{:02X} IE:{:02X}\n",
+        cpu.af(),
+        cpu.bc(),
+        cpu.de(),
+        cpu.hl(),
+        cpu.sp,
+        cpu.pc,
+        ime,
+        gb.peek_io(0x40),
+        gb.peek_io(0x41),
+        gb.peek_io(0x44),
+        gb.peek_io(0x0F),
+        gb.peek_io(0xFF),
+    );
+
+    let pc = cpu.pc;
+    match gb.pc_bank(pc) {
+        Some(bank) => text += &format!("{:02x}:{:04x} ", bank, pc),
+        None => text += &format!("--:{:04x} ", pc),
+    }
+    let opcode = [
+        gb.peek(pc),
+        gb.peek(pc.wrapping_add(1)),
+        gb.peek(pc.wrapping_add(2)),
+    ];
+    let len = (LEN[opcode[0] as usize] as usize).max(1);
+    let trace = gb.trace.borrow();
+    let label = |x| match trace.symbol_at(gb, x) {
+        Some(name) => name.to_string(),
+        None => format!("${:04x}", x),
+    };
+    let mut op = String::new();
+    // writing to a String never fails
+    let _ = disassembly_opcode(pc, &opcode[..len], label, &mut op);
+    text += op.trim_end();
+    text
+}
+
+/// Parse a hexadecimal address, or a range in the form `start-end`, inclusive.
+fn parse_range(text: &str) -> Option<(u16, u16)> {
+    let (start, end) = match text.split_once('-') {
+        Some((start, end)) => (start, end),
+        None => (text, text),
+    };
+    let start = u16::from_str_radix(start, 16).ok()?;
+    let end = u16::from_str_radix(end, 16).ok()?;
+    if start > end {
+        return None;
+    }
+    Some((start, end))
+}
@@ -0,0 +1,182 @@
+use std::collections::VecDeque;
+
+use crate::{
+    consts::{CLOCK_SPEED, FRAME_CYCLES},
+    gameboy::{call_stack::CallStack, GameBoy},
+    interpreter::Interpreter,
+    save_state::SaveState,
+};
+
+/// A save state of the GameBoy, taken before executing a instruction.
+struct Checkpoint {
+    instruction_count: u64,
+    clock_count: u64,
+    state: Vec<u8>,
+    /// The call stack is not part of the save state, so it is kept separately.
+    call_stack: CallStack,
+}
+
+/// The recent execution history, that allows stepping backwards.
+///
+/// A checkpoint is taken every `interval` frames, and every change to the joypad is recorded. To
+/// step back, the nearest checkpoint before the target instruction is loaded, and the execution is
+/// replayed from it, with the same joypad input.
+pub struct History {
+    /// The number of frames between each checkpoint.
+    pub interval: u64,
+    /// The maximum number of checkpoints kept. The oldest ones are discarded first.
+    pub capacity: usize,
+    checkpoints: VecDeque<Checkpoint>,
+    /// The changes to the joypad since the oldest checkpoint. Each one is the clock count of the
+    /// `interpret_op` call that observed the new value, and the value.
+    inputs: VecDeque<(u64, u8)>,
+    /// The last recorded value of the joypad.
+    joypad: u8,
+    /// The clock count after the last recorded instruction. If it doesn't match the clock count of
+    /// the next instruction, the GameBoy was run or changed elsewhere, and the history is discarded.
+    last_clock: Option<u64>,
+}
+impl Default for History {
+    fn default() -> Self {
+        Self {
+            interval: 60,
+            capacity: 30,
+            checkpoints: VecDeque::new(),
+            inputs: VecDeque::new(),
+            joypad: 0xFF,
+            last_clock: None,
+        }
+    }
+}
+impl History {
+    /// Discard all the recorded history.
+    pub fn clear(&mut self) {
+        self.checkpoints.clear();
+        self.inputs.clear();
+        self.last_clock = None;
+    }
+
+    /// The number of clocks that can be stepped back, starting from the given `GameBoy` state.
+    pub fn available_clocks(&self, gb: &GameBoy) -> u64 {
+        match self.checkpoints.front() {
+            Some(x) if self.last_clock == Some(gb.clock_count) => gb.clock_count - x.clock_count,
+            _ => 0,
+        }
+    }
+
+    /// The number of seconds that can be stepped back, starting from the given `GameBoy` state.
+    pub fn available_seconds(&self, gb: &GameBoy) -> f64 {
+        self.available_clocks(gb) as f64 / CLOCK_SPEED as f64
+    }
+
+    /// The instruction count of the oldest checkpoint.
+    pub fn oldest_instruction(&self) -> Option<u64> {
+        self.checkpoints.front().map(|x| x.instruction_count)
+    }
+
+    fn checkpoint(&mut self, gb: &GameBoy) {
+        let mut state = Vec::new();
+        gb.save_state(&mut state).unwrap();
+        self.checkpoints.push_back(Checkpoint {
+            instruction_count: gb.instruction_count,
+            clock_count: gb.clock_count,
+            state,
+            call_stack: gb.call_stack.clone(),
+        });
+        while self.checkpoints.len() > self.capacity.max(1) {
+            self.checkpoints.pop_front();
+        }
+        let oldest = self.checkpoints[0].clock_count;
+        while self.inputs.front().map_or(false, |x| x.0 < oldest) {
+            self.inputs.pop_front();
+        }
+    }
+
+    /// Called before each `interpret_op` made by the debugger.
+    pub(crate) fn before_op(&mut self, gb: &GameBoy) {
+        if self.last_clock != Some(gb.clock_count) {
+            self.clear();
+            self.joypad = gb.joypad;
+        }
+        let due = self.checkpoints.back().map_or(true, |x| {
+            gb.clock_count >= x.clock_count + self.interval * FRAME_CYCLES
+        });
+        if due {
+            self.checkpoint(gb);
+        }
+        if gb.joypad != self.joypad {
+            self.inputs.push_back((gb.clock_count, gb.joypad));
+            self.joypad = gb.joypad;
+        }
+    }
+
+    /// Called after each `interpret_op` made by the debugger, that started at `start_clock`.
+    pub(crate) fn after_op(&mut self, gb: &GameBoy, start_clock: u64) {
+        // The joypad may be changed by the v_blank callback, which is called at the start of
+        // interpret_op, so the new value is replayed before it.
+        if gb.joypad != self.joypad {
+            self.inputs.push_back((start_clock, gb.joypad));
+            self.joypad = gb.joypad;
+        }
+        self.last_clock = Some(gb.clock_count);
+    }
+
+    /// Restore the state of the GameBoy to `count` instructions before its current instruction.
+    pub fn step_back(&mut self, gb: &mut GameBoy, count: u64) -> Result<(), String> {
+        if self.last_clock != Some(gb.clock_count) {
+            self.clear();
+        }
+        let target = gb.instruction_count.saturating_sub(count);
+        let index = self
+            .checkpoints
+            .iter()
+            .rposition(|x| x.instruction_count <= target)
+            .ok_or_else(|| "there is not enough history to step back".to_string())?;
+
+        // the checkpoints after the target are in the future now
+        self.checkpoints.truncate(index + 1);
+        let checkpoint = &self.checkpoints[index];
+        gb.load_state(&mut checkpoint.state.as_slice())
+            .map_err(|x| format!("failed to load checkpoint: {:?}", x))?;
+        gb.call_stack = checkpoint.call_stack.clone();
+
+        // the replay must not have side effects outside of the GameBoy
+        let v_blank = gb.v_blank.take();
+        let serial_transfer_callback = gb.serial_transfer_callback.take();
+        let serial_output = gb.serial_output.take();
+        let rumble_callback = gb.cartridge.rumble_callback.take();
+        let trace_logger = gb.trace_logger.take();
+        let profiling = gb.profiling.take();
+
+        let mut inputs = self
+            .inputs
+            .iter()
+            .skip_while(|x| x.0 < checkpoint.clock_count)
+            .peekable();
+        let mut inter = Interpreter(gb);
+        while inter.0.instruction_count < target {
+            while let Some(&&(clock, joypad)) = inputs.peek() {
+                if clock > inter.0.clock_count {
+                    break;
+                }
+                inter.0.set_joypad(joypad);
+                inputs.next();
+            }
+            inter.interpret_op();
+        }
+
+        gb.v_blank = v_blank;
+        gb.serial_transfer_callback = serial_transfer_callback;
+        gb.serial_output = serial_output;
+        gb.cartridge.rumble_callback = rumble_callback;
+        gb.trace_logger = trace_logger;
+        gb.profiling = profiling;
+        gb.watchpoints.take_hit();
+
+        let clock_count = gb.clock_count;
+        self.inputs.retain(|x| x.0 < clock_count);
+        self.joypad = gb.joypad;
+        self.last_clock = Some(clock_count);
+        Ok(())
+    }
+}
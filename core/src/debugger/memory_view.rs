@@ -0,0 +1,204 @@
+use crate::gameboy::GameBoy;
+
+/// A region of memory, as shown by a memory viewer.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum MemoryRegion {
+    /// The whole address space, 0000-FFFF, as currently mapped.
+    All,
+    /// A bank of the cartridge ROM, mapped or not. Bank 0 is at 0000-3FFF, and the others at
+    /// 4000-7FFF.
+    RomBank(u16),
+    /// The Video RAM, at 8000-9FFF.
+    Vram,
+    /// A bank of the cartridge RAM, mapped or not, at A000-BFFF.
+    CartRamBank(u8),
+    /// The Work RAM, at C000-DFFF.
+    Wram,
+    /// The Object Attribute Memory, at FE00-FE9F.
+    Oam,
+    /// The High RAM, at FF80-FFFE.
+    Hram,
+}
+impl MemoryRegion {
+    /// Parse a region in the form `all`, `rom <bank>`, `vram`, `sram <bank>`, `wram`, `oam` or
+    /// `hram`. The banks are in hexadecimal.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let args: Vec<&str> = text.split_ascii_whitespace().collect();
+        let bank = |x: Option<&&str>| -> Result<u16, String> {
+            match x {
+                Some(x) => u16::from_str_radix(x, 16).map_err(|e| format!("invalid bank: {}", e)),
+                None => Err("expected a bank number".to_string()),
+            }
+        };
+        let region = match args.first().copied().unwrap_or("") {
+            "all" => MemoryRegion::All,
+            "rom" => MemoryRegion::RomBank(bank(args.get(1))?),
+            "vram" => MemoryRegion::Vram,
+            "sram" => {
+                let bank = bank(args.get(1))?;
+                if bank > 0xFF {
+                    return Err(format!("invalid bank: {:x}", bank));
+                }
+                MemoryRegion::CartRamBank(bank as u8)
+            }
+            "wram" => MemoryRegion::Wram,
+            "oam" => MemoryRegion::Oam,
+            "hram" => MemoryRegion::Hram,
+            x => {
+                return Err(format!(
+                    "unknown region '{}', expected 'all', 'rom <bank>', 'vram', 'sram <bank>', \
+                     'wram', 'oam' or 'hram'",
+                    x
+                ))
+            }
+        };
+        let expected_args = match region {
+            MemoryRegion::RomBank(_) | MemoryRegion::CartRamBank(_) => 2,
+            _ => 1,
+        };
+        if args.len() != expected_args {
+            return Err(format!("expected {} arguments", expected_args));
+        }
+        Ok(region)
+    }
+
+    /// The address where the region is mapped.
+    pub fn start(self) -> u16 {
+        match self {
+            MemoryRegion::All => 0x0000,
+            MemoryRegion::RomBank(0) => 0x0000,
+            MemoryRegion::RomBank(_) => 0x4000,
+            MemoryRegion::Vram => 0x8000,
+            MemoryRegion::CartRamBank(_) => 0xA000,
+            MemoryRegion::Wram => 0xC000,
+            MemoryRegion::Oam => 0xFE00,
+            MemoryRegion::Hram => 0xFF80,
+        }
+    }
+
+    /// The length of the region in bytes. It is 0 for banks that the cartridge doesn't have.
+    pub fn len(self, gb: &GameBoy) -> usize {
+        match self {
+            MemoryRegion::All => 0x10000,
+            MemoryRegion::RomBank(bank) => {
+                let start = bank as usize * 0x4000;
+                gb.cartridge.rom.len().saturating_sub(start).min(0x4000)
+            }
+            MemoryRegion::Vram => 0x2000,
+            MemoryRegion::CartRamBank(bank) => {
+                let start = bank as usize * 0x2000;
+                gb.cartridge.ram.len().saturating_sub(start).min(0x2000)
+            }
+            MemoryRegion::Wram => 0x2000,
+            MemoryRegion::Oam => 0xA0,
+            MemoryRegion::Hram => 0x7F,
+        }
+    }
+
+    /// The address of the byte at `offset` from the start of the region.
+    pub fn address(self, offset: usize) -> u16 {
+        self.start().wrapping_add(offset as u16)
+    }
+
+    /// The offset of `address` from the start of the region, if it is inside of it.
+    pub fn offset(self, gb: &GameBoy, address: u16) -> Option<usize> {
+        let offset = address.checked_sub(self.start())? as usize;
+        (offset < self.len(gb)).then(|| offset)
+    }
+
+    /// Read the byte at `offset`, without side effects. Banks are read directly from the
+    /// cartridge, even if they are not mapped.
+    pub fn read(self, gb: &GameBoy, offset: usize) -> u8 {
+        match self {
+            MemoryRegion::RomBank(bank) => gb.cartridge.rom[bank as usize * 0x4000 + offset],
+            MemoryRegion::CartRamBank(bank) => gb.cartridge.ram[bank as usize * 0x2000 + offset],
+            _ => gb.peek(self.address(offset)),
+        }
+    }
+
+    /// A copy of the whole region.
+    pub fn snapshot(self, gb: &GameBoy) -> Vec<u8> {
+        match self {
+            MemoryRegion::RomBank(_) | MemoryRegion::CartRamBank(_) => {
+                (0..self.len(gb)).map(|i| self.read(gb, i)).collect()
+            }
+            _ => {
+                let mut memory = vec![0; self.len(gb)];
+                gb.peek_into(self.start(), &mut memory);
+                memory
+            }
+        }
+    }
+
+    /// Write `value` at `offset`, through `GameBoy::write`. A cartridge RAM bank that is not
+    /// mapped, or is disabled, is written directly instead.
+    ///
+    /// Writes to ROM are not changes to the ROM, but are interpreted by the MBC as register writes
+    /// (see `writes_to_mbc`).
+    pub fn write(self, gb: &mut GameBoy, offset: usize, value: u8) {
+        match self {
+            MemoryRegion::CartRamBank(bank)
+                if gb.cartridge.curr_ram_bank() != bank || !gb.cartridge.ram_enabled() =>
+            {
+                gb.cartridge.ram[bank as usize * 0x2000 + offset] = value;
+            }
+            _ => gb.write(self.address(offset), value),
+        }
+    }
+
+    /// If writing at `offset` is interpreted by the MBC as a register write, because it is in the
+    /// ROM address range.
+    pub fn writes_to_mbc(self, offset: usize) -> bool {
+        self.address(offset) < 0x8000
+    }
+
+    /// Search for `pattern` in the region, starting at `from` and wrapping around at the end.
+    /// Return the offset of the first match.
+    pub fn search(self, gb: &GameBoy, pattern: &[u8], from: usize) -> Option<usize> {
+        let memory = self.snapshot(gb);
+        if pattern.is_empty() || pattern.len() > memory.len() {
+            return None;
+        }
+        let last = memory.len() - pattern.len();
+        let from = if from > last { 0 } else { from };
+        (from..=last)
+            .chain(0..from)
+            .find(|&i| &memory[i..i + pattern.len()] == pattern)
+    }
+}
+
+/// Parse a search pattern: a sequence of hexadecimal bytes, separated or not by spaces, like
+/// `3e 01` or `3e01`, or a ASCII string in double quotes, like `"POKEMON"`.
+pub fn parse_pattern(text: &str) -> Result<Vec<u8>, String> {
+    let text = text.trim();
+    if let Some(string) = text.strip_prefix('"') {
+        let string = string
+            .strip_suffix('"')
+            .ok_or_else(|| "missing closing '\"'".to_string())?;
+        if !string.is_ascii() || string.is_empty() {
+            return Err("expected a non empty ASCII string".to_string());
+        }
+        return Ok(string.as_bytes().to_vec());
+    }
+    let digits: String = text.split_ascii_whitespace().collect();
+    if digits.is_empty() || !digits.is_ascii() || digits.len() % 2 != 0 {
+        return Err("expected a sequence of hexadecimal bytes".to_string());
+    }
+    (0..digits.len())
+        .step_by(2)
+        .map(|i| {
+            u8::from_str_radix(&digits[i..i + 2], 16)
+                .map_err(|_| format!("invalid byte '{}'", &digits[i..i + 2]))
+        })
+        .collect()
+}
+
+/// The character shown for `value` in the ASCII column of a memory viewer. Non printable
+/// characters are shown as '.'.
+pub fn ascii_char(value: u8) -> char {
+    if value.is_ascii_graphic() || value == b' ' {
+        value as char
+    } else {
+        '.'
+    }
+}
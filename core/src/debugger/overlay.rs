@@ -0,0 +1,145 @@
+/// The width of a character of the overlay font, including the spacing between characters.
+pub const CHAR_WIDTH: usize = 4;
+/// The height of a line of the overlay font, including the spacing between lines.
+pub const LINE_HEIGHT: usize = 6;
+
+/// A text drawn in the overlay, at the given position in screen pixels.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct OverlayText {
+    pub x: i32,
+    pub y: i32,
+    pub text: String,
+}
+
+/// A layer of texts that is composited over the game screen by the frontend, like the ones drawn
+/// by a script with `draw_text`.
+///
+/// The texts are drawn with a built-in 3x5 pixel font, in black over a white box, so they are
+/// readable over any game. Lowercase letters are drawn as uppercase, and unknown characters as `?`.
+#[derive(Clone, Default, Debug)]
+pub struct Overlay {
+    pub texts: Vec<OverlayText>,
+}
+impl Overlay {
+    pub fn is_empty(&self) -> bool {
+        self.texts.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.texts.clear();
+    }
+
+    pub fn draw_text(&mut self, x: i32, y: i32, text: impl Into<String>) {
+        self.texts.push(OverlayText {
+            x,
+            y,
+            text: text.into(),
+        });
+    }
+
+    /// Draw the texts over a RGBA image of the given size, clipping the parts outside of it.
+    pub fn draw(&self, rgba: &mut [u8], width: usize, height: usize) {
+        debug_assert_eq!(rgba.len(), width * height * 4);
+        let mut put = |x: i32, y: i32, color: u8| {
+            if x < 0 || y < 0 || x as usize >= width || y as usize >= height {
+                return;
+            }
+            let i = (x as usize + y as usize * width) * 4;
+            rgba[i..i + 3].copy_from_slice(&[color; 3]);
+            rgba[i + 3] = 255;
+        };
+        for text in &self.texts {
+            let lines = text.text.lines().count().max(1);
+            let columns = text.text.lines().map(|x| x.chars().count()).max();
+            let columns = columns.unwrap_or(0);
+            if columns == 0 {
+                continue;
+            }
+
+            // the white box behind the text, with a margin of one pixel
+            let box_width = (columns * CHAR_WIDTH + 1) as i32;
+            let box_height = (lines * LINE_HEIGHT + 1) as i32;
+            for y in 0..box_height {
+                for x in 0..box_width {
+                    put(text.x - 1 + x, text.y - 1 + y, 255);
+                }
+            }
+
+            for (row, line) in text.text.lines().enumerate() {
+                for (column, c) in line.chars().enumerate() {
+                    let x0 = text.x + (column * CHAR_WIDTH) as i32;
+                    let y0 = text.y + (row * LINE_HEIGHT) as i32;
+                    for (y, bits) in glyph(c).iter().enumerate() {
+                        for x in 0..3 {
+                            if bits & (0b100 >> x) != 0 {
+                                put(x0 + x, y0 + y as i32, 0);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// The rows of the 3x5 glyph of the given character, from top to bottom. The bit 2 of each row is
+/// the leftmost pixel.
+fn glyph(c: char) -> [u8; 5] {
+    match c.to_ascii_uppercase() {
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+        '3' => [0b111, 0b001, 0b111, 0b001, 0b111],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b111, 0b001, 0b111],
+        '6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b001, 0b010, 0b010],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+        'A' => [0b010, 0b101, 0b111, 0b101, 0b101],
+        'B' => [0b110, 0b101, 0b110, 0b101, 0b110],
+        'C' => [0b011, 0b100, 0b100, 0b100, 0b011],
+        'D' => [0b110, 0b101, 0b101, 0b101, 0b110],
+        'E' => [0b111, 0b100, 0b110, 0b100, 0b111],
+        'F' => [0b111, 0b100, 0b110, 0b100, 0b100],
+        'G' => [0b011, 0b100, 0b101, 0b101, 0b011],
+        'H' => [0b101, 0b101, 0b111, 0b101, 0b101],
+        'I' => [0b111, 0b010, 0b010, 0b010, 0b111],
+        'J' => [0b001, 0b001, 0b001, 0b101, 0b010],
+        'K' => [0b101, 0b101, 0b110, 0b101, 0b101],
+        'L' => [0b100, 0b100, 0b100, 0b100, 0b111],
+        'M' => [0b101, 0b111, 0b111, 0b101, 0b101],
+        'N' => [0b110, 0b101, 0b101, 0b101, 0b101],
+        'O' => [0b010, 0b101, 0b101, 0b101, 0b010],
+        'P' => [0b110, 0b101, 0b110, 0b100, 0b100],
+        'Q' => [0b010, 0b101, 0b101, 0b110, 0b011],
+        'R' => [0b110, 0b101, 0b110, 0b101, 0b101],
+        'S' => [0b011, 0b100, 0b010, 0b001, 0b110],
+        'T' => [0b111, 0b010, 0b010, 0b010, 0b010],
+        'U' => [0b101, 0b101, 0b101, 0b101, 0b111],
+        'V' => [0b101, 0b101, 0b101, 0b101, 0b010],
+        'W' => [0b101, 0b101, 0b111, 0b111, 0b101],
+        'X' => [0b101, 0b101, 0b010, 0b101, 0b101],
+        'Y' => [0b101, 0b101, 0b010, 0b010, 0b010],
+        'Z' => [0b111, 0b001, 0b010, 0b100, 0b111],
+        ' ' => [0b000, 0b000, 0b000, 0b000, 0b000],
+        ':' => [0b000, 0b010, 0b000, 0b010, 0b000],
+        '.' => [0b000, 0b000, 0b000, 0b000, 0b010],
+        ',' => [0b000, 0b000, 0b000, 0b010, 0b100],
+        '-' => [0b000, 0b000, 0b111, 0b000, 0b000],
+        '+' => [0b000, 0b010, 0b111, 0b010, 0b000],
+        '=' => [0b000, 0b111, 0b000, 0b111, 0b000],
+        '*' => [0b000, 0b101, 0b010, 0b101, 0b000],
+        '/' => [0b001, 0b001, 0b010, 0b100, 0b100],
+        '%' => [0b101, 0b001, 0b010, 0b100, 0b101],
+        '#' => [0b101, 0b111, 0b101, 0b111, 0b101],
+        '!' => [0b010, 0b010, 0b010, 0b000, 0b010],
+        '(' => [0b010, 0b100, 0b100, 0b100, 0b010],
+        ')' => [0b010, 0b001, 0b001, 0b001, 0b010],
+        '<' => [0b001, 0b010, 0b100, 0b010, 0b001],
+        '>' => [0b100, 0b010, 0b001, 0b010, 0b100],
+        '_' => [0b000, 0b000, 0b000, 0b000, 0b111],
+        '\'' => [0b010, 0b010, 0b000, 0b000, 0b000],
+        _ => [0b111, 0b001, 0b010, 0b000, 0b010],
+    }
+}
@@ -0,0 +1,122 @@
+use crate::gameboy::GameBoy;
+
+/// The regions of memory that are searched, as inclusive ranges: the mapped cartridge RAM bank,
+/// the WRAM and the HRAM.
+pub const SEARCH_REGIONS: [(u16, u16); 3] = [(0xA000, 0xBFFF), (0xC000, 0xDFFF), (0xFF80, 0xFFFE)];
+
+/// The size of the values being searched. Words are little-endian.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum SearchSize {
+    Byte,
+    Word,
+}
+
+/// A condition that the value at a address must hold for it to be kept in the search results.
+/// All but `Equal` compare against the value in the previous snapshot.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum SearchFilter {
+    Equal(u16),
+    Increased,
+    Decreased,
+    Changed,
+    Unchanged,
+}
+impl SearchFilter {
+    fn matches(self, previous: u16, current: u16) -> bool {
+        match self {
+            SearchFilter::Equal(x) => current == x,
+            SearchFilter::Increased => current > previous,
+            SearchFilter::Decreased => current < previous,
+            SearchFilter::Changed => current != previous,
+            SearchFilter::Unchanged => current == previous,
+        }
+    }
+}
+
+/// A copy of the memory in `SEARCH_REGIONS`. Addresses outside of them read as 0.
+#[derive(Clone)]
+pub struct RamSnapshot {
+    memory: Box<[u8; 0x10000]>,
+}
+impl Default for RamSnapshot {
+    fn default() -> Self {
+        Self {
+            memory: Box::new([0; 0x10000]),
+        }
+    }
+}
+impl RamSnapshot {
+    /// Take a snapshot of the current state of the GameBoy. The cartridge RAM reads as 0xFF if it
+    /// is disabled, like in the hardware.
+    pub fn new(gb: &GameBoy) -> Self {
+        let mut snapshot = Self::default();
+        for &(start, end) in SEARCH_REGIONS.iter() {
+            for address in start..=end {
+                snapshot.memory[address as usize] = gb.peek(address);
+            }
+        }
+        snapshot
+    }
+
+    pub fn set(&mut self, address: u16, value: u8) {
+        self.memory[address as usize] = value;
+    }
+
+    pub fn value(&self, address: u16, size: SearchSize) -> u16 {
+        let low = self.memory[address as usize] as u16;
+        match size {
+            SearchSize::Byte => low,
+            SearchSize::Word => {
+                let high = self.memory[address.wrapping_add(1) as usize] as u16;
+                low | high << 8
+            }
+        }
+    }
+}
+
+/// A search for the addresses that hold a given value, by successively filtering all addresses
+/// in `SEARCH_REGIONS` by how their values change between snapshots.
+pub struct RamSearch {
+    pub size: SearchSize,
+    previous: RamSnapshot,
+    results: Vec<u16>,
+}
+impl RamSearch {
+    /// Start a new search, with all addresses as results. In word mode, the last address of each
+    /// region is excluded, because its high byte is outside of it.
+    pub fn start(snapshot: RamSnapshot, size: SearchSize) -> Self {
+        let results = SEARCH_REGIONS
+            .iter()
+            .flat_map(|&(start, end)| match size {
+                SearchSize::Byte => start..=end,
+                SearchSize::Word => start..=end - 1,
+            })
+            .collect();
+        Self {
+            size,
+            previous: snapshot,
+            results,
+        }
+    }
+
+    /// Keep only the results that match `filter`, comparing `snapshot` against the previous one.
+    /// `snapshot` becomes the previous one for the next filter.
+    pub fn filter(&mut self, filter: SearchFilter, snapshot: RamSnapshot) {
+        let size = self.size;
+        let previous = &self.previous;
+        self.results.retain(|&address| {
+            filter.matches(previous.value(address, size), snapshot.value(address, size))
+        });
+        self.previous = snapshot;
+    }
+
+    /// The addresses that matched all filters so far, in ascending order.
+    pub fn results(&self) -> &[u16] {
+        &self.results
+    }
+
+    /// The value at `address` in the last snapshot.
+    pub fn previous_value(&self, address: u16) -> u16 {
+        self.previous.value(address, self.size)
+    }
+}
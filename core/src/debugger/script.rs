@@ -0,0 +1,391 @@
+//! Scripts, written in [Rhai](https://rhai.rs), that can register callbacks and call into the
+//! emulator, for automating tests or building game specific tools.
+//!
+//! A script registers its callbacks when it is loaded:
+//!
+//! - `on_frame(|| ...)`: called after each VBlank.
+//! - `on_breakpoint(address, |pc| ...)`: called before executing the instruction at `address`.
+//! - `on_memory_write(range, |address, value| ...)`: called after a write to a address in `range`,
+//!   which can be a single address, or a range like `0xC000..=0xC0FF`.
+//!
+//! And can call the following functions:
+//!
+//! - `read(address)` and `write(address, value)`: read and write memory. Reads don't have side
+//!   effects, and writes are applied after the callback returns.
+//! - `set_button(name, pressed)`: hold a button down, or release it. The names are `A`, `B`,
+//!   `SELECT`, `START`, `RIGHT`, `LEFT`, `UP` and `DOWN`.
+//! - `save_state(slot)` and `load_state(slot)`: save and load the state of the emulator, kept in
+//!   memory, in the given slot.
+//! - `draw_text(x, y, text)`: draw a text over the screen. The texts are cleared before each frame.
+//!
+//! The breakpoint and memory write callbacks are only called while running under the debugger.
+
+use std::{
+    collections::HashMap,
+    ops::{Range, RangeInclusive},
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+use rhai::{Dynamic, Engine, EvalAltResult, FnPtr, AST};
+
+use super::overlay::Overlay;
+use crate::{gameboy::GameBoy, interpreter::Interpreter, save_state::SaveState};
+
+/// The maximum number of operations in a single callback, so a infinite loop in a script don't
+/// freeze the emulator.
+const MAX_OPERATIONS: u64 = 1_000_000;
+
+/// A change to the emulator requested by the script, applied after the callback returns.
+enum Request {
+    Write(u16, u8),
+    SaveState(i64),
+    LoadState(i64),
+}
+
+/// The state shared with the functions registered in the engine.
+struct Shared {
+    /// A copy of the memory, taken before each callback and updated by `write`, so `read` don't
+    /// need access to the GameBoy.
+    memory: Box<[u8; 0x10000]>,
+    requests: Vec<Request>,
+    /// The buttons held down by `set_button`. Same bit order of `GameBoy::joypad`, but 1 means
+    /// pressed.
+    held_buttons: u8,
+    /// The buttons released by `set_button` since the last callback.
+    released_buttons: u8,
+    on_frame: Vec<FnPtr>,
+    on_breakpoint: Vec<(u16, FnPtr)>,
+    /// The inclusive ranges of addresses watched for writes.
+    on_memory_write: Vec<(u16, u16, FnPtr)>,
+    /// The text printed by the script, and the errors of its callbacks.
+    messages: Vec<String>,
+}
+
+/// The bit of a button in `GameBoy::joypad`.
+fn button_mask(name: &str) -> Option<u8> {
+    let bit = match name.to_ascii_uppercase().as_str() {
+        "RIGHT" => 0,
+        "LEFT" => 1,
+        "UP" => 2,
+        "DOWN" => 3,
+        "A" => 4,
+        "B" => 5,
+        "SELECT" => 6,
+        "START" => 7,
+        _ => return None,
+    };
+    Some(1 << bit)
+}
+
+fn create_engine(shared: &Arc<Mutex<Shared>>, overlay: &Arc<Mutex<Overlay>>) -> Engine {
+    let mut engine = Engine::new();
+    engine.set_max_operations(MAX_OPERATIONS);
+
+    let s = shared.clone();
+    engine.on_print(move |text| s.lock().unwrap().messages.push(text.to_string()));
+
+    let s = shared.clone();
+    engine.register_fn("on_frame", move |callback: FnPtr| {
+        s.lock().unwrap().on_frame.push(callback);
+    });
+    let s = shared.clone();
+    engine.register_fn("on_breakpoint", move |address: i64, callback: FnPtr| {
+        s.lock()
+            .unwrap()
+            .on_breakpoint
+            .push((address as u16, callback));
+    });
+    let s = shared.clone();
+    engine.register_fn("on_memory_write", move |address: i64, callback: FnPtr| {
+        let address = address as u16;
+        s.lock()
+            .unwrap()
+            .on_memory_write
+            .push((address, address, callback));
+    });
+    let s = shared.clone();
+    engine.register_fn(
+        "on_memory_write",
+        move |range: RangeInclusive<i64>, callback: FnPtr| {
+            let (start, end) = (*range.start() as u16, *range.end() as u16);
+            s.lock()
+                .unwrap()
+                .on_memory_write
+                .push((start, end, callback));
+        },
+    );
+    let s = shared.clone();
+    engine.register_fn(
+        "on_memory_write",
+        move |range: Range<i64>, callback: FnPtr| {
+            if range.is_empty() {
+                return;
+            }
+            let (start, end) = (range.start as u16, (range.end - 1) as u16);
+            s.lock()
+                .unwrap()
+                .on_memory_write
+                .push((start, end, callback));
+        },
+    );
+
+    let s = shared.clone();
+    engine.register_fn("read", move |address: i64| -> i64 {
+        s.lock().unwrap().memory[address as u16 as usize] as i64
+    });
+    let s = shared.clone();
+    engine.register_fn("write", move |address: i64, value: i64| {
+        let (address, value) = (address as u16, value as u8);
+        let mut shared = s.lock().unwrap();
+        shared.memory[address as usize] = value;
+        shared.requests.push(Request::Write(address, value));
+    });
+    let s = shared.clone();
+    engine.register_fn(
+        "set_button",
+        move |name: &str, pressed: bool| -> Result<(), Box<EvalAltResult>> {
+            let mask = match button_mask(name) {
+                Some(x) => x,
+                None => return Err(format!("'{}' is not a button", name).into()),
+            };
+            let mut shared = s.lock().unwrap();
+            if pressed {
+                shared.held_buttons |= mask;
+                shared.released_buttons &= !mask;
+            } else {
+                shared.held_buttons &= !mask;
+                shared.released_buttons |= mask;
+            }
+            Ok(())
+        },
+    );
+    let s = shared.clone();
+    engine.register_fn("save_state", move |slot: i64| {
+        s.lock().unwrap().requests.push(Request::SaveState(slot));
+    });
+    let s = shared.clone();
+    engine.register_fn("load_state", move |slot: i64| {
+        s.lock().unwrap().requests.push(Request::LoadState(slot));
+    });
+    let o = overlay.clone();
+    engine.register_fn("draw_text", move |x: i64, y: i64, text: &str| {
+        o.lock().unwrap().draw_text(x as i32, y as i32, text);
+    });
+
+    engine
+}
+
+/// A loaded script, and the callbacks it registered.
+pub struct Script {
+    engine: Engine,
+    ast: AST,
+    shared: Arc<Mutex<Shared>>,
+    overlay: Arc<Mutex<Overlay>>,
+    /// The save states made by `save_state`, by slot. They are only kept in memory.
+    states: HashMap<i64, Vec<u8>>,
+}
+impl Script {
+    /// Compile and run the script, which registers its callbacks. The texts drawn by the script
+    /// go to `overlay`.
+    pub fn new(
+        source: &str,
+        gb: &mut GameBoy,
+        overlay: Arc<Mutex<Overlay>>,
+    ) -> Result<Self, String> {
+        let shared = Arc::new(Mutex::new(Shared {
+            memory: Box::new([0; 0x10000]),
+            requests: Vec::new(),
+            held_buttons: 0,
+            released_buttons: 0,
+            on_frame: Vec::new(),
+            on_breakpoint: Vec::new(),
+            on_memory_write: Vec::new(),
+            messages: Vec::new(),
+        }));
+        let engine = create_engine(&shared, &overlay);
+        let ast = engine.compile(source).map_err(|x| x.to_string())?;
+        let mut script = Self {
+            engine,
+            ast,
+            shared,
+            overlay,
+            states: HashMap::new(),
+        };
+
+        script.snapshot(gb);
+        script
+            .engine
+            .run_ast(&script.ast)
+            .map_err(|x| x.to_string())?;
+        script.apply(gb);
+
+        Ok(script)
+    }
+
+    /// Load the script from a file, see `new`.
+    pub fn load(
+        path: &str,
+        gb: &mut GameBoy,
+        overlay: Arc<Mutex<Overlay>>,
+    ) -> Result<Self, String> {
+        let source = std::fs::read_to_string(path)
+            .map_err(|x| format!("failed to read '{}': {}", path, x))?;
+        Self::new(&source, gb, overlay).map_err(|x| format!("error in '{}': {}", path, x))
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Shared> {
+        self.shared.lock().unwrap()
+    }
+
+    /// Take the text printed by the script, and the errors of its callbacks.
+    pub fn take_messages(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.lock().messages)
+    }
+
+    /// If the script has a `on_breakpoint` callback at `pc`.
+    pub fn has_breakpoint(&self, pc: u16) -> bool {
+        self.lock().on_breakpoint.iter().any(|&(x, _)| x == pc)
+    }
+
+    /// The addresses that the next instruction will write to, and that are watched by a
+    /// `on_memory_write` callback. Same format of `Interpreter::will_write_to`.
+    pub fn watched_writes(&self, inter: &Interpreter) -> (u8, [u16; 2]) {
+        let shared = self.lock();
+        let mut watched = (0, [0; 2]);
+        if shared.on_memory_write.is_empty() {
+            return watched;
+        }
+        let (len, writes) = inter.will_write_to();
+        for &address in &writes[..len as usize] {
+            let is_watched = shared
+                .on_memory_write
+                .iter()
+                .any(|&(start, end, _)| (start..=end).contains(&address));
+            if is_watched {
+                watched.1[watched.0 as usize] = address;
+                watched.0 += 1;
+            }
+        }
+        watched
+    }
+
+    /// Clear the overlay and call the `on_frame` callbacks.
+    pub fn on_frame(&mut self, gb: &mut GameBoy) {
+        let callbacks = self.lock().on_frame.clone();
+        self.overlay.lock().unwrap().clear();
+        self.call(gb, callbacks, Vec::new());
+    }
+
+    /// Call the `on_breakpoint` callbacks at the current PC, before it is executed.
+    pub fn on_breakpoint(&mut self, gb: &mut GameBoy) {
+        let pc = gb.cpu.pc;
+        let callbacks = self
+            .lock()
+            .on_breakpoint
+            .iter()
+            .filter(|&&(x, _)| x == pc)
+            .map(|(_, callback)| callback.clone())
+            .collect();
+        self.call(gb, callbacks, vec![Dynamic::from(pc as i64)]);
+    }
+
+    /// Call the `on_memory_write` callbacks that watch `address`, after `value` was written to it.
+    pub fn on_memory_write(&mut self, gb: &mut GameBoy, address: u16, value: u8) {
+        let callbacks = self
+            .lock()
+            .on_memory_write
+            .iter()
+            .filter(|&&(start, end, _)| (start..=end).contains(&address))
+            .map(|(_, _, callback)| callback.clone())
+            .collect();
+        let args = vec![Dynamic::from(address as i64), Dynamic::from(value as i64)];
+        self.call(gb, callbacks, args);
+    }
+
+    fn call(&mut self, gb: &mut GameBoy, callbacks: Vec<FnPtr>, args: Vec<Dynamic>) {
+        if callbacks.is_empty() {
+            return;
+        }
+        self.snapshot(gb);
+        for callback in callbacks {
+            let result = callback.call::<Dynamic>(&self.engine, &self.ast, args.clone());
+            if let Err(err) = result {
+                self.lock().messages.push(format!("error: {}", err));
+            }
+        }
+        self.apply(gb);
+    }
+
+    /// Copy the memory of the GameBoy, to be read by the script.
+    fn snapshot(&mut self, gb: &GameBoy) {
+        gb.peek_into(0, &mut self.lock().memory[..]);
+    }
+
+    /// Apply the changes requested by the script.
+    fn apply(&mut self, gb: &mut GameBoy) {
+        let (requests, held, released) = {
+            let mut shared = self.lock();
+            let released = std::mem::take(&mut shared.released_buttons);
+            (
+                std::mem::take(&mut shared.requests),
+                shared.held_buttons,
+                released,
+            )
+        };
+        for request in requests {
+            match request {
+                Request::Write(address, value) => gb.write(address, value),
+                Request::SaveState(slot) => {
+                    let mut state = Vec::new();
+                    match gb.save_state(&mut state) {
+                        Ok(()) => {
+                            self.states.insert(slot, state);
+                        }
+                        Err(err) => self
+                            .lock()
+                            .messages
+                            .push(format!("error: failed to save state: {}", err)),
+                    }
+                }
+                Request::LoadState(slot) => {
+                    let error = match self.states.get(&slot) {
+                        Some(state) => {
+                            let mut old_state = Vec::new();
+                            gb.save_state(&mut old_state).unwrap();
+                            match gb.load_state(&mut state.as_slice()) {
+                                Ok(()) => None,
+                                Err(err) => {
+                                    // restore the current state
+                                    gb.load_state(&mut old_state.as_slice()).unwrap();
+                                    Some(format!("error: failed to load state: {}", err))
+                                }
+                            }
+                        }
+                        None => Some(format!("error: there is no state in slot {}", slot)),
+                    };
+                    if let Some(error) = error {
+                        self.lock().messages.push(error);
+                    }
+                }
+            }
+        }
+        if held | released != 0 {
+            gb.set_joypad((gb.joypad | released) & !held);
+        }
+    }
+}
+
+/// Call the `on_frame` callbacks of the script after each VBlank, after the current `v_blank`
+/// callback of `gb`. The script is weakly referenced, so this stops when the script is dropped.
+pub fn attach(script: &Arc<Mutex<Script>>, gb: &mut GameBoy) {
+    let script = Arc::downgrade(script);
+    let mut old = gb.v_blank.take();
+    gb.v_blank = Some(Box::new(move |gb| {
+        if let Some(old) = &mut old {
+            old(gb);
+        }
+        if let Some(script) = script.upgrade() {
+            script.lock().unwrap().on_frame(gb);
+        }
+    }));
+}
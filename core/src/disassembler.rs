@@ -2,10 +2,20 @@ use std::{
     collections::{BTreeMap, BTreeSet},
     fmt,
     fmt::Write,
+    io::{self, BufRead},
     ops::Range,
 };
 
-use crate::{consts, gameboy::GameBoy};
+use crate::{
+    consts,
+    gameboy::GameBoy,
+    save_state::{LoadStateError, SaveState},
+};
+
+/// The magic constant at the start of a saved trace, "GameRoy Trace".
+const TRACE_MAGIC: [u8; 4] = *b"GRTR";
+/// The current version of the saved trace format.
+const TRACE_VERSION: u32 = 1;
 
 struct ReallySigned(i8);
 
@@ -69,6 +79,17 @@ impl Address {
     }
 }
 
+/// Format as `bb:aaaa` for the switchable bank region, or `--:aaaa` for the fixed bank.
+impl fmt::Display for Address {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.bank == 0 {
+            write!(f, "--:{:04x}", self.to_pc())
+        } else {
+            write!(f, "{:02x}:{:04x}", self.bank, self.to_pc())
+        }
+    }
+}
+
 pub struct Label {
     /// The address specifies the bank and memory address
     pub address: Address,
@@ -104,9 +125,9 @@ impl Cursor {
             self.pc as usize
         } else {
             let op = [
-                rom.read(self.pc),
-                rom.read(self.pc.wrapping_add(1)),
-                rom.read(self.pc.wrapping_add(2)),
+                rom.peek(self.pc),
+                rom.peek(self.pc.wrapping_add(1)),
+                rom.peek(self.pc.wrapping_add(2)),
             ];
             let len = consts::LEN[op[0] as usize];
             return (op, len);
@@ -137,6 +158,79 @@ pub struct Directive {
     pub op: [u8; 3],
 }
 
+/// The number of bytes of a ROM bank that were traced as code, in a `CoverageReport`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct BankCoverage {
+    /// The number of bytes traced as code.
+    pub executed: usize,
+    /// The size of the bank in the ROM. Only the last bank may be smaller than 0x4000.
+    pub len: usize,
+}
+impl BankCoverage {
+    /// The percentage of the bank traced as code, from 0.0 to 100.0.
+    pub fn percentage(&self) -> f64 {
+        if self.len == 0 {
+            return 0.0;
+        }
+        100.0 * self.executed as f64 / self.len as f64
+    }
+}
+
+/// How much of the ROM was traced as code, returned by `Trace::coverage`.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct CoverageReport {
+    /// The coverage of each bank, indexed by the bank number.
+    pub banks: Vec<BankCoverage>,
+}
+impl CoverageReport {
+    /// The coverage of the whole ROM.
+    pub fn total(&self) -> BankCoverage {
+        BankCoverage {
+            executed: self.banks.iter().map(|x| x.executed).sum(),
+            len: self.banks.iter().map(|x| x.len).sum(),
+        }
+    }
+
+    /// Write the report as a table, with a row for each bank and the total at the end.
+    pub fn fmt_table(&self, w: &mut impl Write) -> fmt::Result {
+        writeln!(w, "bank  executed      size  coverage")?;
+        for (bank, coverage) in self.banks.iter().enumerate() {
+            writeln!(
+                w,
+                "{:4x}  {:8}  {:8}  {:7.2}%",
+                bank,
+                coverage.executed,
+                coverage.len,
+                coverage.percentage()
+            )?;
+        }
+        let total = self.total();
+        write!(
+            w,
+            "total {:8}  {:8}  {:7.2}%",
+            total.executed,
+            total.len,
+            total.percentage()
+        )
+    }
+
+    /// Write the report as CSV, with a header and a row for each bank.
+    pub fn write_csv(&self, mut w: impl io::Write) -> io::Result<()> {
+        writeln!(w, "bank,executed,size,percentage")?;
+        for (bank, coverage) in self.banks.iter().enumerate() {
+            writeln!(
+                w,
+                "{},{},{},{:.2}",
+                bank,
+                coverage.executed,
+                coverage.len,
+                coverage.percentage()
+            )?;
+        }
+        Ok(())
+    }
+}
+
 pub struct Trace {
     /// Disassembled directives
     pub directives: BTreeSet<Directive>,
@@ -153,6 +247,12 @@ pub struct Trace {
     pub ram_directives: BTreeSet<(u16, [u8; 3], u8)>,
     /// Map between a ram address and a label
     pub ram_labels: BTreeMap<u16, String>,
+    /// Labels loaded from a symbol file, keyed by bank and address. Addresses in the fixed ROM bank
+    /// always have the bank 0.
+    pub symbols: BTreeMap<(u16, u16), String>,
+    /// How many times the instruction at each offset of the ROM was executed. It is only counted
+    /// when enabled with `set_counting`, because it slows down the emulation.
+    execution_counts: Option<Vec<u32>>,
 }
 impl Trace {
     pub fn new() -> Self {
@@ -164,6 +264,8 @@ impl Trace {
             ram_code_ranges: Vec::new(),
             ram_directives: BTreeSet::new(),
             ram_labels: BTreeMap::new(),
+            symbols: BTreeMap::new(),
+            execution_counts: None,
         };
 
         //         const ENTRY_POINT: u16 = 0x0;
@@ -214,6 +316,9 @@ impl Trace {
             if let Some(address) = self.jumps.get(&pc) {
                 return self.labels.get(&address).unwrap().name.clone();
             }
+            if let Some(name) = self.symbol(None, x) {
+                return name.to_string();
+            }
             format!("${:04x}", x)
         };
         while pc < curr {
@@ -247,6 +352,214 @@ impl Trace {
         Ok(())
     }
 
+    /// Load the labels of a symbol file, in the format emitted by RGBDS (`bb:aaaa Label`), or by
+    /// WLA-DX (the same, with 4 digits for the bank, inside a `[labels]` section). Comments, starting
+    /// with `;`, and other sections are ignored.
+    ///
+    /// If a address has more than one label, only the first one is used. Labels in banks that can't
+    /// exist are skipped. Return the number of labels loaded.
+    pub fn load_symbols(&mut self, reader: impl BufRead) -> Result<usize, String> {
+        /// The number of ROM banks of the biggest cartridges (8 MiB, in MBC5).
+        const MAX_BANKS: u16 = 0x200;
+
+        let mut section: Option<String> = None;
+        let mut count = 0;
+        for (i, line) in reader.lines().enumerate() {
+            let line = line.map_err(|e| format!("error reading symbols: {}", e))?;
+            let line = line.split(';').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+            if let Some(name) = line.strip_prefix('[') {
+                section = Some(name.trim_end_matches(']').to_string());
+                continue;
+            }
+            if section.as_ref().map_or(false, |x| x != "labels") {
+                continue;
+            }
+
+            let mut parts = line.split_whitespace();
+            let (location, name) = match (parts.next(), parts.next()) {
+                (Some(location), Some(name)) => (location, name),
+                _ => return Err(format!("line {}: expected a address and a label", i + 1)),
+            };
+            let parse = |location: &str| -> Option<(u16, u16)> {
+                let (bank, address) = location.split_once(':')?;
+                Some((
+                    u16::from_str_radix(bank, 16).ok()?,
+                    u16::from_str_radix(address, 16).ok()?,
+                ))
+            };
+            let (bank, address) = match parse(location) {
+                Some(x) => x,
+                None => return Err(format!("line {}: invalid address '{}'", i + 1, location)),
+            };
+            let bank = match address {
+                0x0000..=0x3FFF => 0,
+                // roms without banking have their second half in bank 0 for some assemblers
+                0x4000..=0x7FFF if bank == 0 => 1,
+                _ => bank,
+            };
+            if bank >= MAX_BANKS {
+                continue;
+            }
+            if self.symbols.contains_key(&(bank, address)) {
+                continue;
+            }
+            self.symbols.insert((bank, address), name.to_string());
+            count += 1;
+
+            match Address::from_pc(Some(bank), address) {
+                Some(address) => {
+                    self.labels
+                        .entry(address)
+                        .or_insert_with(|| Label::new(address))
+                        .name = name.to_string();
+                }
+                None => {
+                    let generated = format!("R{:04x}", address);
+                    let label = self.ram_labels.entry(address).or_insert(generated.clone());
+                    // keep the label of the first bank with this address
+                    if *label == generated {
+                        *label = name.to_string();
+                    }
+                }
+            }
+        }
+        Ok(count)
+    }
+
+    /// The label of a symbol file at the given address, if any. If the bank of a RAM address is
+    /// not known, the banks 0 and 1 are tried.
+    pub fn symbol(&self, bank: Option<u16>, address: u16) -> Option<&str> {
+        if self.symbols.is_empty() {
+            return None;
+        }
+        let name = match (address, bank) {
+            (0x0000..=0x3FFF, _) => self.symbols.get(&(0, address)),
+            (_, Some(bank)) => self.symbols.get(&(bank, address)),
+            (0x4000..=0x7FFF, None) => None,
+            (_, None) => self
+                .symbols
+                .get(&(0, address))
+                .or_else(|| self.symbols.get(&(1, address))),
+        };
+        name.map(|x| x.as_str())
+    }
+
+    /// The closest label of a symbol file at or before the given address, in the same memory
+    /// region, and the offset of the address from it. Used to name addresses in the middle of a
+    /// function, like `Main+12`.
+    pub fn nearest_symbol(&self, bank: Option<u16>, address: u16) -> Option<(&str, u16)> {
+        if self.symbols.is_empty() {
+            return None;
+        }
+        let region = match address {
+            0x0000..=0x3FFF => 0x0000,
+            0x4000..=0x7FFF => 0x4000,
+            0x8000..=0x9FFF => 0x8000,
+            0xA000..=0xBFFF => 0xA000,
+            0xC000..=0xCFFF => 0xC000,
+            0xD000..=0xDFFF => 0xD000,
+            0xE000..=0xFF7F => 0xE000,
+            0xFF80..=0xFFFF => 0xFF80,
+        };
+        let banks: &[u16] = match (address, &bank) {
+            (0x0000..=0x3FFF, _) => &[0],
+            (_, Some(bank)) => std::slice::from_ref(bank),
+            (0x4000..=0x7FFF, None) => &[],
+            (_, None) => &[0, 1],
+        };
+        banks.iter().find_map(|&bank| {
+            let (&(_, start), name) = self
+                .symbols
+                .range((bank, region)..=(bank, address))
+                .next_back()?;
+            Some((name.as_str(), address - start))
+        })
+    }
+
+    /// The label of a symbol file at the given address, in the banks currently mapped in `gb`.
+    pub fn symbol_at(&self, gb: &GameBoy, address: u16) -> Option<&str> {
+        let bank = match address {
+            0x0000..=0x7FFF => gb.pc_bank(address),
+            0xA000..=0xBFFF => Some(gb.cartridge.curr_ram_bank() as u16),
+            // the WRAM is in bank 0 or 1, depending on the assembler
+            _ => None,
+        };
+        self.symbol(bank, address)
+    }
+
+    /// Compute how many bytes of each bank of a ROM of `rom_len` bytes were traced as code.
+    pub fn coverage(&self, rom_len: usize) -> CoverageReport {
+        let mut executed = vec![false; rom_len];
+        for directive in &self.directives {
+            let start =
+                directive.address.bank as usize * 0x4000 + directive.address.address as usize;
+            let end = (start + directive.len as usize).min(rom_len);
+            if start < end {
+                executed[start..end].iter_mut().for_each(|x| *x = true);
+            }
+        }
+        let banks = executed
+            .chunks(0x4000)
+            .map(|bank| BankCoverage {
+                executed: bank.iter().filter(|&&x| x).count(),
+                len: bank.len(),
+            })
+            .collect();
+        CoverageReport { banks }
+    }
+
+    /// Enable or disable counting the executions of each instruction, for a ROM of `rom_len`
+    /// bytes. Disabling it discards the counts.
+    pub fn set_counting(&mut self, rom_len: usize, enabled: bool) {
+        self.execution_counts = match (enabled, self.execution_counts.take()) {
+            (true, Some(counts)) if counts.len() == rom_len => Some(counts),
+            (true, _) => Some(vec![0; rom_len]),
+            (false, _) => None,
+        };
+    }
+
+    /// If the executions of each instruction are being counted.
+    pub fn is_counting(&self) -> bool {
+        self.execution_counts.is_some()
+    }
+
+    /// The number of times the instruction at `address` was executed, if they are being counted.
+    pub fn execution_count(&self, address: Address) -> Option<u32> {
+        let offset = address.bank as usize * 0x4000 + address.address as usize;
+        self.execution_counts.as_ref()?.get(offset).copied()
+    }
+
+    /// The largest execution count of all instructions, if they are being counted.
+    pub fn max_execution_count(&self) -> Option<u32> {
+        Some(
+            self.execution_counts
+                .as_ref()?
+                .iter()
+                .copied()
+                .max()
+                .unwrap_or(0),
+        )
+    }
+
+    /// Count a execution of the instruction at `pc`, in the given ROM bank, if enabled. Addresses
+    /// outside of the ROM are ignored.
+    #[inline]
+    pub(crate) fn count_execution(&mut self, bank: u16, pc: u16) {
+        if let Some(counts) = &mut self.execution_counts {
+            let offset = match pc {
+                0x0000..=0x3FFF => pc as usize,
+                0x4000..=0x7FFF => bank as usize * 0x4000 + (pc as usize - 0x4000),
+                _ => return,
+            };
+            if let Some(count) = counts.get_mut(offset) {
+                *count = count.saturating_add(1);
+            }
+        }
+    }
+
     pub fn is_already_traced(&self, bank: u16, start: u16) -> bool {
         start > 0x3FFF || self.get_curr_code_range(bank, start).is_some()
     }
@@ -505,7 +818,7 @@ impl Trace {
                     writeln!(f, "{}:", label.name)?;
                 }
                 write!(f, "    ")?;
-                write!(f, "{:02x}_{:04x}: ", pc.bank, pc.address)?;
+                write!(f, "{}: ", pc)?;
                 disassembly_opcode(
                     pc.to_pc(),
                     &op,
@@ -513,6 +826,9 @@ impl Trace {
                         if let Some(address) = self.jumps.get(&pc) {
                             return self.labels.get(&address).unwrap().name.clone();
                         }
+                        if let Some(name) = self.symbol(None, x) {
+                            return name.to_string();
+                        }
                         format!("${:04x}", x)
                     },
                     f,
@@ -524,6 +840,289 @@ impl Trace {
         }
         Ok(())
     }
+
+    /// Write the traced disassembly as a RGBDS assembly file, with a section for each ROM bank.
+    ///
+    /// The traced code is written as instructions, with labels at the jump and call targets, named
+    /// by the loaded symbols or as `Label_bb_aaaa`. All other bytes are written as `db`
+    /// directives, and the original bytes of each instruction are written as comments. Instructions
+    /// that could be assembled to different bytes are also written as `db`, so the file assembles
+    /// back to the same ROM.
+    pub fn export_asm(&self, gb: &GameBoy, mut writer: impl io::Write) -> io::Result<()> {
+        const BANK_SIZE: usize = 0x4000;
+        let rom = &gb.cartridge.rom;
+        let num_banks = rom.len() / BANK_SIZE;
+        let index =
+            |address: &Address| address.bank as usize * BANK_SIZE + address.address as usize;
+
+        // the length of the instruction that starts at each byte of the rom, or 0 for data.
+        let mut layout = vec![0u8; num_banks * BANK_SIZE];
+        let mut end = 0;
+        for directive in self.directives.iter() {
+            let i = index(&directive.address);
+            let len = directive.len as usize;
+            // skip overlapping instructions, and the ones that cross the end of the bank
+            if i < end
+                || directive.address.address as usize + len > BANK_SIZE
+                || i + len > layout.len()
+            {
+                continue;
+            }
+            layout[i] = len as u8;
+            end = i + len;
+        }
+
+        // a label can only be placed at the start of a instruction, or between data
+        let label_name = |address: &Address| -> Option<String> {
+            let i = index(address);
+            let inside_instruction = (1..=2).any(|d| i >= d && layout[i - d] as usize > d);
+            if i >= layout.len() || inside_instruction {
+                return None;
+            }
+            Some(match self.symbols.get(&(address.bank, address.to_pc())) {
+                Some(name) => name.clone(),
+                None => format!("Label_{:02x}_{:04x}", address.bank, address.to_pc()),
+            })
+        };
+
+        writeln!(
+            writer,
+            "; disassembled by GameRoy from '{}'",
+            gb.cartridge.header.title_as_string()
+        )?;
+
+        // the symbols outside of the ROM are defined as constants
+        let mut constants: BTreeMap<&str, u16> = BTreeMap::new();
+        for (&(_, address), name) in self.symbols.iter() {
+            if address >= 0x8000 && !constants.contains_key(name.as_str()) {
+                constants.insert(name, address);
+                writeln!(writer, "{} EQU ${:04x}", name, address)?;
+            }
+        }
+
+        for bank in 0..num_banks {
+            writeln!(writer)?;
+            if bank == 0 {
+                writeln!(writer, "SECTION \"ROM Bank $00\", ROM0[$0000]")?;
+            } else {
+                writeln!(
+                    writer,
+                    "SECTION \"ROM Bank ${0:02x}\", ROMX[$4000], BANK[${0:02x}]",
+                    bank
+                )?;
+            }
+
+            let mut offset = 0;
+            while offset < BANK_SIZE {
+                let address = Address::new(bank as u16, offset as u16);
+                if self.labels.contains_key(&address) {
+                    if let Some(name) = label_name(&address) {
+                        writeln!(writer, "{}:", name)?;
+                    }
+                }
+
+                let i = index(&address);
+                let len = layout[i] as usize;
+                if len > 0 {
+                    let op = &rom[i..i + len];
+                    let label = |x: u16| {
+                        if let Some(to) = self.jumps.get(&address) {
+                            if let Some(name) = label_name(to).filter(|_| to.to_pc() == x) {
+                                return name;
+                            }
+                        }
+                        if let Some(name) = self.symbol(None, x) {
+                            if constants.get(name) == Some(&x) {
+                                return name.to_string();
+                            }
+                        }
+                        format!("${:04x}", x)
+                    };
+                    let text =
+                        asm_opcode(address.to_pc(), op, label).unwrap_or_else(|| asm_data(op));
+                    let bytes: Vec<String> = op.iter().map(|x| format!("{:02x}", x)).collect();
+                    writeln!(writer, "    {:<24}; {} {}", text, address, bytes.join(" "))?;
+                    offset += len;
+                } else {
+                    // data, until the next instruction or label
+                    let start = offset;
+                    offset += 1;
+                    while offset < BANK_SIZE
+                        && offset - start < 8
+                        && layout[i + offset - start] == 0
+                        && !self
+                            .labels
+                            .contains_key(&Address::new(bank as u16, offset as u16))
+                    {
+                        offset += 1;
+                    }
+                    let text = asm_data(&rom[i..i + offset - start]);
+                    writeln!(writer, "    {:<24}; {}", text, address)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Write the traced ROM code, with its jumps and labels, to be merged in a later session with
+    /// `load`. The RAM trace and the symbols are not saved.
+    pub fn save(&self, gb: &GameBoy, data: &mut impl io::Write) -> io::Result<()> {
+        TRACE_MAGIC.save_state(data)?;
+        TRACE_VERSION.save_state(data)?;
+        (gb.cartridge.rom.len() as u32).save_state(data)?;
+        gb.cartridge.header.global_checksum.save_state(data)?;
+
+        (self.directives.len() as u32).save_state(data)?;
+        for directive in self.directives.iter() {
+            [directive.address.bank, directive.address.address].save_state(data)?;
+            (directive.len as u8).save_state(data)?;
+            directive.op.save_state(data)?;
+        }
+        (self.jumps.len() as u32).save_state(data)?;
+        for (from, to) in self.jumps.iter() {
+            [from.bank, from.address, to.bank, to.address].save_state(data)?;
+        }
+        (self.labels.len() as u32).save_state(data)?;
+        for label in self.labels.values() {
+            [label.address.bank, label.address.address].save_state(data)?;
+            label.name.as_bytes().to_vec().save_state(data)?;
+        }
+        Ok(())
+    }
+
+    /// Merge a trace written by `save` into this one, as if its code was traced again. Return the
+    /// number of loaded instructions.
+    ///
+    /// Fails without changing this trace if the saved one is from a different ROM, checked by the
+    /// size and global checksum of the ROM, and by the bytes of each instruction.
+    pub fn load(&mut self, gb: &GameBoy, data: &mut impl io::Read) -> Result<usize, String> {
+        fn read<T: SaveState>(data: &mut impl io::Read, mut value: T) -> Result<T, String> {
+            value.load_state(data).map_err(|e| match e {
+                LoadStateError::IoError(e) => format!("malformed trace: {}", e),
+                e => format!("malformed trace: {:?}", e),
+            })?;
+            Ok(value)
+        }
+        const DIFFERENT_ROM: &str = "the trace is from a different rom";
+
+        let rom = &gb.cartridge.rom;
+        if read(data, [0u8; 4])? != TRACE_MAGIC {
+            return Err("not a trace file".to_string());
+        }
+        let version = read(data, 0u32)?;
+        if version != TRACE_VERSION {
+            return Err(format!("unknown trace version {}", version));
+        }
+        let rom_len = read(data, 0u32)?;
+        let global_checksum = read(data, 0u16)?;
+        if rom_len as usize != rom.len() || global_checksum != gb.cartridge.header.global_checksum {
+            return Err(DIFFERENT_ROM.to_string());
+        }
+
+        let read_address = |data: &mut _| -> Result<Address, String> {
+            let [bank, address] = read(data, [0u16; 2])?;
+            if address > 0x3FFF || (bank as usize + 1) * 0x4000 > rom.len() {
+                return Err(format!(
+                    "malformed trace: {:02x}:{:04x} is outside the rom",
+                    bank, address
+                ));
+            }
+            Ok(Address::new(bank, address))
+        };
+
+        let count = read(data, 0u32)?;
+        let mut directives = Vec::new();
+        for _ in 0..count {
+            let address = read_address(data)?;
+            let len = read(data, 0u8)? as usize;
+            let op = read(data, [0u8; 3])?;
+            let i = address.bank as usize * 0x4000 + address.address as usize;
+            if len == 0 || len > 3 || rom.get(i..i + len) != Some(&op[..len]) {
+                return Err(DIFFERENT_ROM.to_string());
+            }
+            directives.push((address, op, len));
+        }
+        let mut jumps = Vec::new();
+        for _ in 0..read(data, 0u32)? {
+            jumps.push((read_address(data)?, read_address(data)?));
+        }
+        let mut labels = Vec::new();
+        for _ in 0..read(data, 0u32)? {
+            let address = read_address(data)?;
+            let name = String::from_utf8(read(data, Vec::new())?)
+                .map_err(|_| "malformed trace: invalid label name".to_string())?;
+            labels.push(Label { address, name });
+        }
+
+        for (address, op, len) in directives {
+            self.add_opcode(address, &op[..len], len as u16);
+        }
+        for label in labels {
+            self.labels.entry(label.address).or_insert(label);
+        }
+        for (from, to) in jumps {
+            self.jumps.entry(from).or_insert(to);
+            self.labels.entry(to).or_insert_with(|| Label::new(to));
+        }
+        Ok(count as usize)
+    }
+
+    /// Forget all the traced code, jumps and labels, except for the labels of the loaded symbols
+    /// and the execution counts.
+    pub fn clear(&mut self) {
+        let symbols = std::mem::take(&mut self.symbols);
+        let execution_counts = self.execution_counts.take();
+        *self = Self::new();
+        self.execution_counts = execution_counts;
+        for (&(bank, address), name) in symbols.iter() {
+            match Address::from_pc(Some(bank), address) {
+                Some(address) => {
+                    self.labels.insert(
+                        address,
+                        Label {
+                            address,
+                            name: name.clone(),
+                        },
+                    );
+                }
+                None => {
+                    self.ram_labels.entry(address).or_insert(name.clone());
+                }
+            }
+        }
+        self.symbols = symbols;
+    }
+}
+
+/// Disassembly a opcode in the syntax of RGBDS. Return None for the opcodes that must be written
+/// as data, because they are invalid, or could be assembled to different bytes.
+fn asm_opcode(pc: u16, op: &[u8], label: impl FnOnce(u16) -> String) -> Option<String> {
+    let text = match op[0] {
+        // STOP, and the invalid opcodes
+        0x10 | 0xd3 | 0xdb | 0xdd | 0xe3 | 0xe4 | 0xeb | 0xec | 0xed | 0xf4 | 0xfc | 0xfd => {
+            return None
+        }
+        // LD (a16), A and LD A, (a16) to HRAM could be optimized to LDH
+        0xea | 0xfa if op[2] == 0xff => return None,
+        0xe2 => "LDH  [C], A".to_string(),
+        0xf2 => "LDH  A, [C]".to_string(),
+        0xe8 => format!("ADD  SP, {}", op[1] as i8),
+        0xf8 => format!("LD   HL, SP{:+}", op[1] as i8),
+        0xe9 => "JP   HL".to_string(),
+        x if x & 0b11000111 == 0b11000111 => format!("RST  ${:02x}", x & 0b00111000),
+        _ => {
+            let mut text = String::new();
+            disassembly_opcode(pc, op, label, &mut text).ok()?;
+            text.replace('(', "[").replace(')', "]")
+        }
+    };
+    Some(text.trim_end().to_string())
+}
+
+/// Write bytes as a `db` directive.
+fn asm_data(bytes: &[u8]) -> String {
+    let bytes: Vec<String> = bytes.iter().map(|x| format!("${:02x}", x)).collect();
+    format!("db   {}", bytes.join(", "))
 }
 
 fn compute_step(
@@ -1,24 +1,47 @@
-use std::cell::RefCell;
+use std::cell::{Ref, RefCell};
 
 use crate::{
     disassembler::Trace,
+    interpreter::{ExecutedInstruction, Interpreter},
+    profiling::ProfilingData,
     save_state::{LoadStateError, SaveState, SaveStateHeader},
+    trace_logger::TraceLogger,
 };
 
+pub mod call_stack;
 pub mod cartridge;
+pub mod cheats;
 pub mod cpu;
+pub mod instruction_history;
+pub mod interrupt_log;
 pub mod ppu;
 pub mod sound_controller;
 pub mod timer;
+pub mod watchpoints;
 
 use self::{
-    cartridge::Cartridge, cpu::Cpu, ppu::Ppu, sound_controller::SoundController, timer::Timer,
+    call_stack::{CallStack, StackFrame},
+    cartridge::Cartridge,
+    cheats::{CheatId, Cheats},
+    cpu::Cpu,
+    instruction_history::InstructionHistory,
+    interrupt_log::InterruptLog,
+    ppu::{Ppu, SpriteEntry, TileMap},
+    sound_controller::{SoundController, AFTER_BOOT_STATE},
+    timer::Timer,
+    watchpoints::Watchpoints,
 };
 
 /// The offset between `clock_count` and the serial transfer clock, in cycles. This is choose
 /// arbitrarily, in a way that pass the serial_boot_sclk_align_dmg_abc_mgb test.
 const SERIAL_OFFSET: u64 = 8;
 
+/// A serial transfer callback that prints each byte to stderr, as a char. Useful for test roms that
+/// print their results through the serial port.
+pub fn eprint_serial(byte: u8) {
+    eprint!("{}", byte as char);
+}
+
 pub struct GameBoy {
     pub trace: RefCell<Trace>,
     pub cpu: Cpu,
@@ -30,6 +53,8 @@ pub struct GameBoy {
     pub boot_rom: Option<[u8; 0x100]>,
     pub boot_rom_active: bool,
     pub clock_count: u64,
+    /// The number of instructions executed since the start, not counting interrupt dispatches.
+    pub instruction_count: u64,
     pub timer: Timer,
     pub sound: RefCell<SoundController>,
     pub ppu: RefCell<Ppu>,
@@ -49,6 +74,9 @@ pub struct GameBoy {
     pub serial_transfer_callback: Option<Box<dyn FnMut(u8) + Send>>,
     #[cfg(target_arch = "wasm32")]
     pub serial_transfer_callback: Option<Box<dyn FnMut(u8)>>,
+    /// When set, the bytes sent through the serial port are appended to it. They can be taken with
+    /// `take_serial_output`.
+    pub serial_output: Option<Vec<u8>>,
     /// FF0F: Interrupt Flag (IF)
     /// - bit 0: VBlank
     /// - bit 1: STAT
@@ -68,6 +96,36 @@ pub struct GameBoy {
     pub v_blank: Option<Box<dyn FnMut(&mut GameBoy) + Send>>,
     #[cfg(target_arch = "wasm32")]
     pub v_blank: Option<Box<dyn FnMut(&mut GameBoy)>>,
+
+    /// When set, every memory access goes to this flat 64 KiB memory, instead of the regular
+    /// memory map. This is only intended for testing the CPU in isolation, see `new_test_flat`.
+    #[cfg(feature = "test-util")]
+    pub flat_memory: Option<Box<[u8; 0x10000]>>,
+
+    /// When set, the interpreter collects execution statistics into it.
+    pub profiling: Option<Box<ProfilingData>>,
+
+    /// The routines that are currently being executed, tracked by the interpreter.
+    pub(crate) call_stack: CallStack,
+
+    /// The memory watchpoints, checked by the interpreter.
+    pub watchpoints: Watchpoints,
+
+    /// When set, the interpreter writes a line to it before executing each instruction.
+    pub trace_logger: Option<Box<TraceLogger>>,
+
+    /// When set, the interpreter records the last executed instructions in it.
+    pub instruction_history: Option<Box<InstructionHistory>>,
+
+    /// When set, the interpreter records the interrupt dispatches and the changes to IME in it.
+    pub interrupt_log: Option<Box<InterruptLog>>,
+
+    /// The Game Genie and GameShark codes. They are not included in save states.
+    pub cheats: Cheats,
+
+    /// The state of the sound controller loaded by `reset_after_boot`, in place of
+    /// `AFTER_BOOT_STATE`. See `set_after_boot_sound`.
+    after_boot_sound: Option<Vec<u8>>,
 }
 
 impl std::fmt::Debug for GameBoy {
@@ -104,6 +162,7 @@ impl PartialEq for GameBoy {
             && self.boot_rom == other.boot_rom
             && self.boot_rom_active == other.boot_rom_active
             && self.clock_count == other.clock_count
+            && self.instruction_count == other.instruction_count
             && self.timer == other.timer
             && self.sound == other.sound
             && self.ppu == other.ppu
@@ -117,34 +176,104 @@ impl PartialEq for GameBoy {
         // && self.v_blank == other.v_blank
     }
 }
-crate::save_state!(GameBoy, self, data {
-    SaveStateHeader::new();
-    // self.trace;
-    self.cpu;
-    self.cartridge;
-    self.wram;
-    self.hram;
-    // self.boot_rom;
-    self.clock_count;
-    self.timer;
-
-    self.sound.borrow_mut();
-    self.ppu.borrow_mut();
-
-    self.joypad_io;
-    self.joypad;
-    self.serial_data;
-    self.serial_control;
-    self.serial_transfer_started;
-    // self.serial_transfer;
-    self.interrupt_flag;
-    self.dma;
-    self.interrupt_enabled;
-
-    bitset [self.boot_rom_active, self.v_blank_trigger];
-    // self.v_blank;
-});
+impl SaveState for GameBoy {
+    fn save_state(&self, data: &mut impl std::io::Write) -> Result<(), std::io::Error> {
+        SaveStateHeader::new(self.cartridge.rom_crc32()).save_state(data)?;
+        // self.trace;
+        self.cpu.save_state(data)?;
+        self.cartridge.save_state(data)?;
+        self.wram.save_state(data)?;
+        self.hram.save_state(data)?;
+        // self.boot_rom;
+        self.clock_count.save_state(data)?;
+        self.timer.save_state(data)?;
+
+        self.sound.borrow_mut().save_state(data)?;
+        self.ppu.borrow_mut().save_state(data)?;
+
+        self.joypad_io.save_state(data)?;
+        self.joypad.save_state(data)?;
+        self.serial_data.save_state(data)?;
+        self.serial_control.save_state(data)?;
+        self.serial_transfer_started.save_state(data)?;
+        // self.serial_transfer;
+        self.interrupt_flag.save_state(data)?;
+        self.dma.save_state(data)?;
+        self.interrupt_enabled.save_state(data)?;
+
+        [&self.boot_rom_active, &self.v_blank_trigger].save_state(data)?;
+        // self.v_blank;
+        self.instruction_count.save_state(data)?;
+        if let Some(rtc) = self.cartridge.rtc() {
+            rtc.save_state(data)?;
+        }
+        self.ppu.borrow().color_ids.save_state(data)?;
+        Ok(())
+    }
+
+    fn load_state(&mut self, data: &mut impl std::io::Read) -> Result<(), LoadStateError> {
+        let header = self.read_state_header(data)?;
+        self.cpu.load_state(data)?;
+        self.cartridge.load_state(data)?;
+        self.wram.load_state(data)?;
+        self.hram.load_state(data)?;
+        self.clock_count.load_state(data)?;
+        self.timer.load_state(data)?;
+
+        self.sound.borrow_mut().load_state(data)?;
+        self.sound
+            .borrow_mut()
+            .set_div(self.timer.last_clock_count, self.timer.div);
+        self.ppu.borrow_mut().load_state(data)?;
+        self.ppu.borrow_mut().check_stat_outdated();
+
+        self.joypad_io.load_state(data)?;
+        self.joypad.load_state(data)?;
+        self.serial_data.load_state(data)?;
+        self.serial_control.load_state(data)?;
+        self.serial_transfer_started.load_state(data)?;
+        self.interrupt_flag.load_state(data)?;
+        self.dma.load_state(data)?;
+        self.interrupt_enabled.load_state(data)?;
+
+        [&mut self.boot_rom_active, &mut self.v_blank_trigger].load_state(data)?;
+        // the instruction count was added in version 3
+        if header.version >= 3 {
+            self.instruction_count.load_state(data)?;
+        } else {
+            self.instruction_count = 0;
+        }
+        // the real time clock was added in version 4
+        if header.version >= 4 {
+            if let Some(rtc) = self.cartridge.rtc_mut() {
+                rtc.load_state(data)?;
+            }
+        }
+        // the screen color ids were added in version 5
+        if header.version >= 5 {
+            self.ppu.borrow_mut().color_ids.load_state(data)?;
+        }
+        Ok(())
+    }
+}
 impl GameBoy {
+    /// Read the header of a save state, and check that it can be loaded in this GameBoy: that it
+    /// has a known version, and was saved with the same rom. Save states older than version 6
+    /// don't have the rom checksum, and are assumed to be of the same rom.
+    pub fn read_state_header(
+        &self,
+        data: &mut impl std::io::Read,
+    ) -> Result<SaveStateHeader, LoadStateError> {
+        let mut header = SaveStateHeader::default();
+        header.load_state(data)?;
+        match header.rom_crc32 {
+            Some(x) if x != self.cartridge.rom_crc32() => {
+                Err(LoadStateError::RomMismatch(x, self.cartridge.rom_crc32()))
+            }
+            _ => Ok(header),
+        }
+    }
+
     pub fn new(boot_rom: Option<[u8; 0x100]>, cartridge: Cartridge) -> Self {
         let mut this = Self {
             trace: RefCell::new(Trace::new()),
@@ -155,6 +284,7 @@ impl GameBoy {
             boot_rom,
             boot_rom_active: true,
             clock_count: 0,
+            instruction_count: 0,
             timer: Timer::new(),
             sound: RefCell::new(SoundController::default()),
             ppu: Ppu::default().into(),
@@ -164,15 +294,25 @@ impl GameBoy {
             serial_data: 0,
             serial_control: 0x7E,
             serial_transfer_started: 0,
-            serial_transfer_callback: Some(Box::new(|c| {
-                eprint!("{}", c as char);
-            })),
+            serial_transfer_callback: None,
+            serial_output: None,
             interrupt_flag: 0,
             dma: 0xff,
             interrupt_enabled: 0,
             v_blank_trigger: false,
             v_blank: None,
+            #[cfg(feature = "test-util")]
+            flat_memory: None,
+            profiling: None,
+            call_stack: CallStack::default(),
+            watchpoints: Watchpoints::default(),
+            trace_logger: None,
+            instruction_history: None,
+            interrupt_log: None,
+            cheats: Cheats::default(),
+            after_boot_sound: None,
         };
+        this.sound.get_mut().set_div(0, this.timer.div);
 
         if this.boot_rom.is_none() {
             this.reset_after_boot();
@@ -181,16 +321,126 @@ impl GameBoy {
         this
     }
 
-    /// call the `v_blank` callback
+    /// Create a GameBoy with a flat 64 KiB memory, zeroed, in place of the regular memory map (see
+    /// `flat_memory`), and with the interrupts and the serial output disabled. This is intended for
+    /// testing the CPU in isolation, by setting the registers in `cpu` and the memory directly.
+    #[cfg(feature = "test-util")]
+    pub fn new_test_flat() -> Self {
+        let cartridge = Cartridge::new(vec![0; 0x8000]).unwrap();
+        let mut this = Self::new(None, cartridge);
+        this.interrupt_enabled = 0;
+        this.interrupt_flag = 0;
+        this.flat_memory = Some(Box::new([0; 0x10000]));
+        this
+    }
+
+    /// Take the bytes captured in `serial_output` so far, leaving it empty. Return a empty `Vec` if
+    /// the serial output is not being captured.
+    pub fn take_serial_output(&mut self) -> Vec<u8> {
+        self.serial_output
+            .as_mut()
+            .map_or_else(Vec::new, std::mem::take)
+    }
+
+    /// call the `v_blank` callback, after applying the GameShark codes.
     pub fn call_v_blank_callback(&mut self) {
+        self.apply_ram_cheats();
         if let Some(mut v_blank) = self.v_blank.take() {
             v_blank(self);
             self.v_blank = Some(v_blank);
         }
     }
 
+    /// Execute a single instruction, or dispatch a interrupt, ticking all the other components
+    /// accordingly. Return what was executed.
+    pub fn step_instruction(&mut self) -> ExecutedInstruction {
+        Interpreter(self).step_instruction()
+    }
+
+    /// Parse and add a Game Genie or GameShark code, enabled. See `Cheat::parse`.
+    pub fn add_cheat(&mut self, code: &str) -> Result<CheatId, String> {
+        self.cheats.add(code)
+    }
+
+    /// Remove a cheat. Returns false if there is no cheat with the given id.
+    pub fn remove_cheat(&mut self, id: CheatId) -> bool {
+        self.cheats.remove(id)
+    }
+
+    /// Enable or disable a cheat. Returns false if there is no cheat with the given id.
+    pub fn set_cheat_enabled(&mut self, id: CheatId, enabled: bool) -> bool {
+        self.cheats.set_enabled(id, enabled)
+    }
+
+    /// Write the values of the enabled GameShark codes to RAM. This is done once per frame, at the
+    /// start of VBlank. The cartridge RAM is written directly, see `Cartridge::poke_ram`.
+    fn apply_ram_cheats(&mut self) {
+        let GameBoy {
+            cheats,
+            cartridge,
+            wram,
+            hram,
+            ..
+        } = self;
+        for (bank, address, value) in cheats.ram_writes() {
+            match address {
+                0xA000..=0xBFFF => cartridge.poke_ram(bank, address, value),
+                0xC000..=0xDFFF => wram[address as usize - 0xC000] = value,
+                0xFF80..=0xFFFE => hram[address as usize - 0xFF80] = value,
+                _ => unreachable!("GameShark codes only write to RAM"),
+            }
+        }
+    }
+
+    /// The current call stack, from the outermost to the innermost frame.
+    pub fn call_stack(&self) -> &[StackFrame] {
+        self.call_stack.frames()
+    }
+
+    /// Turn the GameBoy off and on again, running the boot ROM again if there is one.
+    ///
+    /// Unlike `reset`, all the state is recreated, as by `GameBoy::new`, including the state of the
+    /// cartridge MBC. The cartridge RAM is kept, unless `clear_ram` is true. The callbacks, the
+    /// cheats, the debugging tools and the sample frequency of the sound are also kept.
+    pub fn power_cycle(&mut self, clear_ram: bool) {
+        self.cartridge.reset();
+        if clear_ram {
+            self.cartridge.clear_ram();
+        }
+
+        let placeholder = Cartridge::new(vec![0; 0x8000]).unwrap();
+        let cartridge = std::mem::replace(&mut self.cartridge, placeholder);
+        let new = Self::new(self.boot_rom, cartridge);
+        let old = std::mem::replace(self, new);
+
+        self.trace = old.trace;
+        self.sound.borrow_mut().sample_frequency = old.sound.borrow().sample_frequency;
+        self.serial_transfer_callback = old.serial_transfer_callback;
+        self.serial_output = old.serial_output;
+        self.v_blank = old.v_blank;
+        #[cfg(feature = "test-util")]
+        {
+            self.flat_memory = old.flat_memory.map(|_| Box::new([0; 0x10000]));
+        }
+        self.profiling = old.profiling;
+        self.watchpoints = old.watchpoints;
+        self.trace_logger = old.trace_logger;
+        self.instruction_history = old.instruction_history;
+        self.interrupt_log = old.interrupt_log;
+        self.cheats = old.cheats;
+        self.after_boot_sound = old.after_boot_sound;
+        self.ppu.get_mut().color_correction = old.ppu.borrow().color_correction;
+        if self.boot_rom.is_none() {
+            self.reset_sound_after_boot();
+        }
+    }
+
     /// Reset the gameboy to its stating state.
+    ///
+    /// All the volatile state is reset, as in `GameBoy::new`, including pending serial transfers,
+    /// interrupts and OAM DMA, but the cartridge is kept as is.
     pub fn reset(&mut self) {
+        self.call_stack.clear();
         if self.boot_rom.is_none() {
             self.reset_after_boot();
             return;
@@ -201,11 +451,20 @@ impl GameBoy {
         self.hram = [0; 0x7F];
         self.boot_rom_active = true;
         self.clock_count = 0;
+        self.instruction_count = 0;
         self.timer = Timer::new();
-        self.sound = RefCell::new(SoundController::default());
+        self.sound = RefCell::new(self.new_sound());
+        self.sound.get_mut().set_div(0, self.timer.div);
         self.ppu = Ppu::default().into();
         self.joypad = 0xFF;
         self.joypad_io = 0x00;
+        self.serial_data = 0;
+        self.serial_control = 0x7E;
+        self.serial_transfer_started = 0;
+        self.interrupt_flag = 0;
+        self.dma = 0xff;
+        self.interrupt_enabled = 0;
+        self.v_blank_trigger = false;
     }
 
     /// Reset the gameboy to its state after disabling the boot.
@@ -231,6 +490,7 @@ impl GameBoy {
 
         self.boot_rom_active = false;
         self.clock_count = 23_440_324;
+        self.instruction_count = 0;
         self.ppu.borrow_mut().reset_after_boot();
 
         self.joypad = 0xFF;
@@ -238,6 +498,7 @@ impl GameBoy {
         self.joypad_io = 0xCF;
         self.serial_data = 0x00;
         self.serial_control = 0x7E;
+        self.serial_transfer_started = 0;
         self.timer = Timer {
             div: 0xabcc,
             tima: 0x00,
@@ -248,13 +509,76 @@ impl GameBoy {
             loading: 0,
         };
         self.interrupt_flag = 0xE1;
-        self.sound
-            .borrow_mut()
-            .load_state(&mut &include_bytes!("../after_boot/sound.sav")[..])
-            .unwrap();
+        self.dma = 0xff;
+        self.interrupt_enabled = 0;
+        self.v_blank_trigger = false;
+        self.reset_sound_after_boot();
+    }
+
+    /// Reset the sound controller to its state after the boot, `after_boot_sound` or
+    /// `AFTER_BOOT_STATE`.
+    fn reset_sound_after_boot(&mut self) {
+        let state = self.after_boot_sound.as_deref().unwrap_or(AFTER_BOOT_STATE);
+        let sound = self.sound.get_mut();
+        // `after_boot_sound` is validated when set, and a invalid state falls back to the default
+        let _ = sound.load_initial_state(state);
+        sound.set_div(self.clock_count, self.timer.div);
+    }
+
+    /// Use `state`, a save state of the `SoundController`, as the state of the sound after the
+    /// boot, in place of the bundled `AFTER_BOOT_STATE`. This allows matching a specific hardware
+    /// revision, like one with a different initial wave RAM. `None` restores the default.
+    ///
+    /// If the state is not valid, the default is used, and the error is returned. The state is
+    /// loaded by `reset_after_boot`, so only when there is no boot ROM, in which case it is loaded
+    /// right away. It should be set before running the emulation.
+    pub fn set_after_boot_sound(&mut self, state: Option<Vec<u8>>) -> Result<(), LoadStateError> {
+        let result = match &state {
+            Some(state) => self.new_sound().load_initial_state(state),
+            None => Ok(()),
+        };
+        self.after_boot_sound = if result.is_ok() { state } else { None };
+        if self.boot_rom.is_none() {
+            self.reset_sound_after_boot();
+        }
+        result
+    }
+
+    /// A sound controller in its initial state, with the same sample frequency as the current one.
+    fn new_sound(&self) -> SoundController {
+        let mut sound = SoundController::default();
+        sound.sample_frequency = self.sound.borrow().sample_frequency;
+        sound
+    }
+
+    /// The ROM bank that the given PC address is in, or `None` if it is outside the ROM.
+    pub fn pc_bank(&self, pc: u16) -> Option<u16> {
+        match pc {
+            0x0000..=0x3FFF => Some(0),
+            0x4000..=0x7FFF => Some(self.cartridge.curr_rom_bank()),
+            _ => None,
+        }
+    }
+
+    pub fn read(&self, address: u16) -> u8 {
+        #[cfg(feature = "test-util")]
+        if self.flat_memory.is_some() {
+            return self.read_memory(address);
+        }
+        if address < 0xFE00 {
+            if let Some(value) = Ppu::dma_conflict(self) {
+                return value;
+            }
+        }
+        self.read_memory(address)
     }
 
-    pub fn read(&self, mut address: u16) -> u8 {
+    /// Read from the memory map, ignoring the bus conflicts with the OAM DMA.
+    pub(crate) fn read_memory(&self, mut address: u16) -> u8 {
+        #[cfg(feature = "test-util")]
+        if let Some(memory) = &self.flat_memory {
+            return memory[address as usize];
+        }
         if self.boot_rom_active {
             if address < 0x100 {
                 let boot_rom = self
@@ -263,16 +587,24 @@ impl GameBoy {
                 return boot_rom[address as usize];
             }
         }
+        // The echo RAM, 0xE000 to 0xFDFF, mirrors 0xC000 to 0xDDFF. It stops before the OAM, so
+        // 0xFE00 to 0xFEFF is not a mirror of 0xDE00 to 0xDEFF.
         if (0xE000..=0xFDFF).contains(&address) {
             address -= 0x2000;
         }
         match address {
             // Cartridge ROM
-            0x0000..=0x7FFF => self.cartridge.read(address),
+            0x0000..=0x7FFF => {
+                let value = self.cartridge.read(address);
+                if self.cheats.any_rom_patch() {
+                    return self.cheats.patch_rom_read(address, value);
+                }
+                value
+            }
             // Video RAM
             0x8000..=0x9FFF => Ppu::read_vram(self, address),
             // Cartridge RAM
-            0xA000..=0xBFFF => self.cartridge.read(address),
+            0xA000..=0xBFFF => self.cartridge.read_at(address, self.clock_count),
             // Work RAM
             0xC000..=0xDFFF => self.wram[address as usize - 0xC000],
             // ECHO RAM
@@ -280,7 +612,7 @@ impl GameBoy {
             // Sprite Attribute table
             0xFE00..=0xFE9F => Ppu::read_oam(self, address),
             // Not Usable
-            0xFEA0..=0xFEFF => 0xff,
+            0xFEA0..=0xFEFF => Ppu::read_unusable(self),
             // I/O registers
             0xFF00..=0xFF7F => self.read_io(address as u8),
             // Hight RAM
@@ -290,7 +622,162 @@ impl GameBoy {
         }
     }
 
+    /// Read a value from memory, without side effects, as seen by a debugger. It doesn't update
+    /// the other components, and ignore the blocking of VRAM and OAM by the PPU and the OAM DMA.
+    pub fn peek(&self, mut address: u16) -> u8 {
+        #[cfg(feature = "test-util")]
+        if let Some(memory) = &self.flat_memory {
+            return memory[address as usize];
+        }
+        if self.boot_rom_active && address < 0x100 {
+            if let Some(boot_rom) = self.boot_rom {
+                return boot_rom[address as usize];
+            }
+        }
+        if (0xE000..=0xFDFF).contains(&address) {
+            address -= 0x2000;
+        }
+        match address {
+            0x8000..=0x9FFF => self.ppu.borrow().vram[address as usize - 0x8000],
+            0xFE00..=0xFE9F => self.ppu.borrow().oam[address as usize - 0xFE00],
+            0xFEA0..=0xFEFF => 0x00,
+            0xFF10..=0xFF14 | 0xFF16..=0xFF1E | 0xFF20..=0xFF26 | 0xFF30..=0xFF3F => {
+                self.sound.borrow().peek(address as u8)
+            }
+            0xFF40..=0xFF45 | 0xFF47..=0xFF4B => self.ppu.borrow().peek(address as u8),
+            _ => self.read_memory(address),
+        }
+    }
+
+    /// The content of the Video RAM, without copying it. The GameBoy can't be mutated while the
+    /// returned reference is alive.
+    pub fn vram_snapshot(&self) -> Ref<'_, [u8; 0x2000]> {
+        Ref::map(self.ppu.borrow(), |ppu| &ppu.vram)
+    }
+
+    /// The content of the Object Attribute Memory (the sprite table), without copying it.
+    pub fn oam_snapshot(&self) -> Ref<'_, [u8; 0xA0]> {
+        Ref::map(self.ppu.borrow(), |ppu| &ppu.oam)
+    }
+
+    /// The 40 entries of the OAM, decoded. Like `peek`, this has no side effects, and ignores the
+    /// blocking of the OAM during the PPU modes 2 and 3 and the OAM DMA.
+    pub fn sprites(&self) -> [SpriteEntry; 40] {
+        let ppu = self.ppu.borrow();
+        let mut sprites = [SpriteEntry::default(); 40];
+        for (sprite, bytes) in sprites.iter_mut().zip(ppu.oam.chunks_exact(4)) {
+            *sprite = SpriteEntry::from_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        }
+        sprites
+    }
+
+    /// The height of the sprites, 8 or 16, as selected by the bit 2 of LCDC.
+    pub fn sprite_height(&self) -> u8 {
+        if self.ppu.borrow().lcdc & 0x04 != 0 {
+            16
+        } else {
+            8
+        }
+    }
+
+    /// The indices in the OAM of the sprites that cover the scanline `ly`, in OAM order, with the
+    /// current sprite height. Only the first `MAX_SPRITES_PER_LINE` of them are drawn, the others
+    /// are culled.
+    pub fn sprites_on_line(&self, ly: u8) -> Vec<usize> {
+        let height = self.sprite_height();
+        self.sprites()
+            .iter()
+            .enumerate()
+            .filter(|(_, sprite)| sprite.is_on_line(ly, height))
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    /// The tile indices of the given tile map, indexed by `[y][x]`. See `ppu::map_tile_index` for
+    /// the tile each index refers to.
+    pub fn bg_map(&self, which: TileMap) -> [[u8; 32]; 32] {
+        self.ppu.borrow().tile_map(which)
+    }
+
+    /// The attributes of each tile in the given tile map, stored in the VRAM bank 1 of the CGB.
+    /// Only the DMG is emulated, so this always returns `None`, like `tile_data` for the bank 1.
+    pub fn bg_attribute_map(&self, _which: TileMap) -> Option<[[u8; 32]; 32]> {
+        None
+    }
+
+    /// Render the 256x256 pixels of the background map, with the part shown on the screen
+    /// outlined. See `Ppu::render_background`.
+    pub fn render_background(&self) -> Vec<[u8; 3]> {
+        self.ppu.borrow().render_background()
+    }
+
+    /// Render the 256x256 pixels of the window map, with the part shown on the screen outlined.
+    /// See `Ppu::render_window`.
+    pub fn render_window(&self) -> Vec<[u8; 3]> {
+        self.ppu.borrow().render_window()
+    }
+
+    /// Decode the tile at `index`, from 0 to 0x17F, in the given VRAM bank, into the color index of
+    /// each pixel, indexed by `[y][x]`. Only the CGB has more than one VRAM bank, so this returns
+    /// `None` for any bank other than 0, or for indexes out of range.
+    pub fn tile_data(&self, bank: u8, index: usize) -> Option<[[u8; 8]; 8]> {
+        if bank != 0 || index >= 0x180 {
+            return None;
+        }
+        Some(self.ppu.borrow().tile_data(index))
+    }
+
+    /// Read `len` bytes starting at `start`, through the normal memory map. The address wraps
+    /// around at 0xFFFF.
+    pub fn read_range(&self, start: u16, len: u16) -> Vec<u8> {
+        (0..len).map(|i| self.read(start.wrapping_add(i))).collect()
+    }
+
+    /// Write `data` starting at `start`, through the normal memory map. The address wraps around
+    /// at 0xFFFF.
+    pub fn write_range(&mut self, start: u16, data: &[u8]) {
+        for (i, &value) in data.iter().enumerate() {
+            self.write(start.wrapping_add(i as u16), value);
+        }
+    }
+
+    /// Same as `read_range`, but using `peek`.
+    pub fn peek_range(&self, start: u16, len: u16) -> Vec<u8> {
+        let mut data = vec![0; len as usize];
+        self.peek_into(start, &mut data);
+        data
+    }
+
+    /// Fill `buf` with the bytes starting at `start`, using `peek`. The address wraps around at
+    /// 0xFFFF. Useful for bulk reads without allocating, like in a hex viewer.
+    pub fn peek_into(&self, start: u16, buf: &mut [u8]) {
+        for (i, value) in buf.iter_mut().enumerate() {
+            *value = self.peek(start.wrapping_add(i as u16));
+        }
+    }
+
+    /// Same as `read16`, but using `peek`.
+    pub fn peek16(&self, address: u16) -> u16 {
+        u16::from_le_bytes([self.peek(address), self.peek(address.wrapping_add(1))])
+    }
+
+    /// Read the I/O register at `0xFF00 + address`, using `peek`. `0xFF` reads the IE register.
+    /// NR52 and the wave RAM may be outdated, because the sound controller is not updated.
+    pub fn peek_io(&self, address: u8) -> u8 {
+        self.peek(0xFF00 | address as u16)
+    }
+
     pub fn write(&mut self, mut address: u16, value: u8) {
+        #[cfg(feature = "test-util")]
+        if let Some(memory) = &mut self.flat_memory {
+            memory[address as usize] = value;
+            return;
+        }
+        // the OAM DMA is driving the bus
+        if address < 0xFE00 && Ppu::dma_conflict(self).is_some() {
+            return;
+        }
+        // the echo RAM, see `read_memory`
         if (0xE000..=0xFDFF).contains(&address) {
             address -= 0x2000;
         }
@@ -301,14 +788,14 @@ impl GameBoy {
             // Video RAM
             0x8000..=0x9FFF => Ppu::write_vram(self, address, value),
             // Cartridge RAM
-            0xA000..=0xBFFF => self.cartridge.write(address, value),
+            0xA000..=0xBFFF => self.cartridge.write_at(address, value, self.clock_count),
             // Work RAM
             0xC000..=0xDFFF => self.wram[address as usize - 0xC000] = value,
             // ECHO RAM
             0xE000..=0xFDFF => unreachable!(),
             // Sprite Attribute table
             0xFE00..=0xFE9F => Ppu::write_oam(self, address, value),
-            // Not Usable
+            // Not Usable. The writes are ignored on the DMG.
             0xFEA0..=0xFEFF => {}
             // I/O registers
             0xFF00..=0xFF7F => self.write_io(address as u8, value),
@@ -324,13 +811,18 @@ impl GameBoy {
         self.clock_count += count as u64;
 
         // ppu
-        let (v_blank_interrupt, stat_interrupt) = Ppu::update(self);
-        if stat_interrupt {
-            self.interrupt_flag |= 1 << 1;
-        }
-        if v_blank_interrupt {
-            self.interrupt_flag |= 1 << 0;
-            self.v_blank_trigger = true;
+        // Between its steps, the ppu only needs to be updated if a register was written or the
+        // DMA is running, so most ticks can skip it.
+        let needs_update = self.ppu.borrow().needs_update(self.clock_count);
+        if needs_update {
+            let (v_blank_interrupt, stat_interrupt) = Ppu::update(self);
+            if stat_interrupt {
+                self.interrupt_flag |= 1 << 1;
+            }
+            if v_blank_interrupt {
+                self.interrupt_flag |= 1 << 0;
+                self.v_blank_trigger = true;
+            }
         }
 
         // timer
@@ -360,9 +852,34 @@ impl GameBoy {
         self.write(address.wrapping_add(1), b);
     }
 
+    /// Update the state of the joypad buttons, requesting a joypad interrupt if a button on a
+    /// selected line was pressed. See `joypad` for the meaning of each bit.
+    pub fn set_joypad(&mut self, joypad: u8) {
+        let lines = self.joypad_lines();
+        self.joypad = joypad;
+        self.check_joypad_interrupt(lines);
+    }
+
+    /// The input lines P10-P13 of P1, as seen by the CPU. 0 bit means low.
+    pub(crate) fn joypad_lines(&self) -> u8 {
+        self.read_io(0x00) & 0x0F
+    }
+
+    /// Request a joypad interrupt if any input line went from high to low, since `old_lines`.
+    fn check_joypad_interrupt(&mut self, old_lines: u8) {
+        if old_lines & !self.joypad_lines() != 0 {
+            self.interrupt_flag |= 0x10;
+        }
+    }
+
     fn write_io(&mut self, address: u8, value: u8) {
         match address {
-            0x00 => self.joypad_io = 0b1100_1111 | (value & 0x30), // JOYPAD
+            0x00 => {
+                // JOYPAD
+                let lines = self.joypad_lines();
+                self.joypad_io = 0b1100_1111 | (value & 0x30);
+                self.check_joypad_interrupt(lines);
+            }
             0x01 => self.serial_data = value,
             0x02 => {
                 self.serial_control = value | 0x7E;
@@ -371,10 +888,18 @@ impl GameBoy {
                     self.serial_transfer_started = (self.clock_count + SERIAL_OFFSET) >> 9;
                     let data = self.serial_data;
                     self.serial_transfer_callback.as_mut().map(|x| x(data));
+                    if let Some(output) = &mut self.serial_output {
+                        output.push(data);
+                    }
                 }
             }
             0x03 => {}
-            0x04..=0x07 => self.timer.write(address, value),
+            0x04 => {
+                // resetting DIV may cause a falling edge in the frame sequencer
+                self.sound.borrow_mut().reset_div(self.clock_count);
+                self.timer.write(address, value);
+            }
+            0x05..=0x07 => self.timer.write(address, value),
             0x08..=0x0e => {}
             0x0f => self.interrupt_flag = value,
             0x10..=0x14 | 0x16..=0x1e | 0x20..=0x26 | 0x30..=0x3f => {
@@ -0,0 +1,53 @@
+/// The maximum number of frames tracked. Older frames are discarded when this is reached.
+const MAX_DEPTH: usize = 256;
+
+/// A entry in the call stack, created when a routine is called.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct StackFrame {
+    /// The address of the called routine.
+    pub address: u16,
+    /// The ROM bank of the called routine, or `None` if it is outside the ROM.
+    pub bank: Option<u16>,
+    /// The value of SP at the entry of the routine. It points to the return address.
+    pub sp: u16,
+    /// The address that the routine was called from, and where it should return to.
+    pub return_address: u16,
+    /// If the routine was called by a interrupt dispatch.
+    pub interrupt: bool,
+}
+
+/// A shadow call stack, tracked by the interpreter.
+///
+/// Frames are pushed on CALL, RST and interrupt dispatch, and are popped once SP gets above the
+/// frame's SP at entry. This handles RET and RETI, but also routines that discard their return
+/// address and jump out.
+#[derive(Default, Clone, Debug)]
+pub struct CallStack {
+    frames: Vec<StackFrame>,
+}
+impl CallStack {
+    pub fn frames(&self) -> &[StackFrame] {
+        &self.frames
+    }
+
+    pub fn clear(&mut self) {
+        self.frames.clear();
+    }
+
+    pub(crate) fn push(&mut self, frame: StackFrame) {
+        if self.frames.len() >= MAX_DEPTH {
+            self.frames.remove(0);
+        }
+        self.frames.push(frame);
+    }
+
+    /// Pop every frame that was returned from, given the current value of SP.
+    pub(crate) fn update(&mut self, sp: u16) {
+        while let Some(frame) = self.frames.last() {
+            if frame.sp >= sp {
+                break;
+            }
+            self.frames.pop();
+        }
+    }
+}
@@ -36,7 +36,7 @@ fn mbc_type_name(code: u8) -> &'static str {
     }
 }
 
-#[derive(PartialEq, Eq)]
+#[derive(PartialEq, Eq, Debug)]
 pub struct CartridgeHeader {
     /// 0104-0133: Logo
     pub logo: [u8; 48],
@@ -44,6 +44,8 @@ pub struct CartridgeHeader {
     pub title: [u8; 16],
     ///0143: CGB Flag
     pub cgb_flag: u8,
+    /// 0144-0145: New Licensee Code
+    pub new_licensee_code: [u8; 2],
     /// 0146: SGB Flag
     pub sgb_flag: u8,
     /// 0147: Cartridge Type
@@ -52,6 +54,10 @@ pub struct CartridgeHeader {
     pub rom_size: u8,
     /// 0149: RAM Size
     pub ram_size: u8,
+    /// 014A: Destination Code
+    pub destination_code: u8,
+    /// 014B: Old Licensee Code
+    pub old_licensee_code: u8,
     /// 014C: Mask ROM Version number
     pub version: u8,
     /// 014D: Header Checksum
@@ -60,6 +66,14 @@ pub struct CartridgeHeader {
     pub global_checksum: u16,
 }
 impl CartridgeHeader {
+    /// The Nintendo logo, that every cartridge must have at 0104-0133.
+    #[rustfmt::skip]
+    pub const NINTENDO_LOGO: [u8; 48] = [
+        0xCE ,0xED ,0x66 ,0x66 ,0xCC ,0x0D ,0x00 ,0x0B ,0x03 ,0x73 ,0x00 ,0x83 ,0x00 ,0x0C ,0x00 ,0x0D ,
+        0x00 ,0x08 ,0x11 ,0x1F ,0x88 ,0x89 ,0x00 ,0x0E ,0xDC ,0xCC ,0x6E ,0xE6 ,0xDD ,0xDD ,0xD9 ,0x99 ,
+        0xBB ,0xBB ,0x67 ,0x63 ,0x6E ,0x0E ,0xEC ,0xCC ,0xDD ,0xDC ,0x99 ,0x9F ,0xBB ,0xB9 ,0x33 ,0x3E ,
+    ];
+
     /// Return  Err(Some(Self)) if the load was sucessful but the checksum don't match.
     pub fn from_bytes(bytes: &[u8]) -> Result<Self, (Option<Self>, String)> {
         if bytes.len() < 0x150 {
@@ -69,22 +83,20 @@ impl CartridgeHeader {
             logo: bytes[0x0104..=0x0133].try_into().unwrap(),
             title: bytes[0x0134..=0x0143].try_into().unwrap(),
             cgb_flag: bytes[0x143],
+            new_licensee_code: [bytes[0x0144], bytes[0x0145]],
             sgb_flag: bytes[0x0146],
             cartridge_type: bytes[0x0147],
             rom_size: bytes[0x0148],
             ram_size: bytes[0x0149],
+            destination_code: bytes[0x014A],
+            old_licensee_code: bytes[0x014B],
             version: bytes[0x014C],
             header_checksum: bytes[0x014D],
             global_checksum: u16::from_le_bytes([bytes[0x014E], bytes[0x014F]]),
         };
 
-        {
-            let check_sum = bytes[0x134..=0x014C]
-                .iter()
-                .fold(0u8, |x, &b| x.wrapping_add(!b));
-            if check_sum != this.header_checksum {
-                return Err((Some(this), "checksum don't match".to_string()));
-            }
+        if !this.header_checksum_valid() {
+            return Err((Some(this), "checksum don't match".to_string()));
         }
 
         Ok(this)
@@ -102,13 +114,54 @@ impl CartridgeHeader {
 
     /// Return true if it has the correct values for the first  0x18  bytes of the Nintendo logo.
     pub fn check_logo(&self) -> bool {
-        #[rustfmt::skip]
-        const NINTENDOO_LOGO: [u8; 48] = [
-            0xCE ,0xED ,0x66 ,0x66 ,0xCC ,0x0D ,0x00 ,0x0B ,0x03 ,0x73 ,0x00 ,0x83 ,0x00 ,0x0C ,0x00 ,0x0D ,
-            0x00 ,0x08 ,0x11 ,0x1F ,0x88 ,0x89 ,0x00 ,0x0E ,0xDC ,0xCC ,0x6E ,0xE6 ,0xDD ,0xDD ,0xD9 ,0x99 ,
-            0xBB ,0xBB ,0x67 ,0x63 ,0x6E ,0x0E ,0xEC ,0xCC ,0xDD ,0xDC ,0x99 ,0x9F ,0xBB ,0xB9 ,0x33 ,0x3E ,
-        ];
-        self.logo[..0x18] == NINTENDOO_LOGO[..0x18]
+        self.logo[..0x18] == Self::NINTENDO_LOGO[..0x18]
+    }
+
+    /// Return true if the entire logo (0104-0133) matches the Nintendo logo. The boot ROM refuses
+    /// to run a cartridge with a invalid logo.
+    pub fn logo_valid(&self) -> bool {
+        self.logo == Self::NINTENDO_LOGO
+    }
+
+    /// Compute the header checksum over the bytes 0134-014C, in the same way as the boot ROM does.
+    pub fn compute_header_checksum(&self) -> u8 {
+        self.title
+            .iter()
+            .chain(self.new_licensee_code.iter())
+            .chain(
+                [
+                    self.sgb_flag,
+                    self.cartridge_type,
+                    self.rom_size,
+                    self.ram_size,
+                    self.destination_code,
+                    self.old_licensee_code,
+                    self.version,
+                ]
+                .iter(),
+            )
+            .fold(0u8, |x, &b| x.wrapping_add(!b))
+    }
+
+    /// Return true if the header checksum (014D) matches the one computed over the header. The boot
+    /// ROM refuses to run a cartridge with a invalid checksum.
+    pub fn header_checksum_valid(&self) -> bool {
+        self.compute_header_checksum() == self.header_checksum
+    }
+
+    /// Compute the global checksum of the given rom: the sum of all its bytes, except the two bytes
+    /// of the checksum itself (014E-014F).
+    pub fn compute_global_checksum(rom: &[u8]) -> u16 {
+        rom.iter()
+            .enumerate()
+            .filter(|&(i, _)| i != 0x14E && i != 0x14F)
+            .fold(0u16, |x, (_, &b)| x.wrapping_add(b as u16))
+    }
+
+    /// Return true if the global checksum (014E-014F) matches the one computed over the given rom.
+    /// The checksum is stored in big endian. The boot ROM doesn't check it.
+    pub fn global_checksum_valid(&self, rom: &[u8]) -> bool {
+        Self::compute_global_checksum(rom) == self.global_checksum.swap_bytes()
     }
 
     pub fn rom_size_in_bytes(&self) -> Result<usize, String> {
@@ -134,6 +187,24 @@ impl CartridgeHeader {
         Ok(rom_size)
     }
 
+    /// Return true if the cartridge type (0147) has a battery, that keeps the RAM or the real time
+    /// clock while the GameBoy is off. Only these cartridges have a battery save.
+    pub fn has_battery(&self) -> bool {
+        const TYPES: &[u8] = &[
+            0x03, 0x06, 0x09, 0x0D, 0x0F, 0x10, 0x13, 0x1B, 0x1E, 0x22, 0xFC, 0xFF,
+        ];
+        TYPES.contains(&self.cartridge_type)
+    }
+
+    /// Return true if the cartridge type (0147) has RAM, including the RAM integrated in the MBC2.
+    pub fn has_ram(&self) -> bool {
+        const TYPES: &[u8] = &[
+            0x02, 0x03, 0x05, 0x06, 0x08, 0x09, 0x0C, 0x0D, 0x10, 0x12, 0x13, 0x1A, 0x1B, 0x1D,
+            0x1E, 0x22, 0xFC, 0xFE, 0xFF,
+        ];
+        TYPES.contains(&self.cartridge_type)
+    }
+
     pub fn title_as_string(&self) -> String {
         let l = self
             .title
@@ -143,8 +214,368 @@ impl CartridgeHeader {
             .unwrap_or(self.title.len());
         String::from_utf8_lossy(&self.title[0..l]).into_owned()
     }
+
+    /// The name of the cartridge type (0147), like "MBC1+RAM+BATTERY".
+    pub fn cartridge_type_name(&self) -> &'static str {
+        mbc_type_name(self.cartridge_type)
+    }
+
+    /// The name of the memory bank controller of the cartridge type (0147), without its extra
+    /// hardware, like "MBC1".
+    pub fn mapper_name(&self) -> &'static str {
+        let name = mbc_type_name(self.cartridge_type);
+        name.split('+').next().unwrap_or(name)
+    }
+
+    /// The Game Boy Color support, given by the CGB flag (0143).
+    pub fn cgb_support(&self) -> CgbSupport {
+        match self.cgb_flag {
+            x if x & 0xC0 == 0xC0 => CgbSupport::Only,
+            x if x & 0x80 != 0 => CgbSupport::Enhanced,
+            _ => CgbSupport::None,
+        }
+    }
+
+    /// Return true if the game supports the Super Game Boy functions. The SGB flag (0146) is only
+    /// considered if the old licensee code (014B) is 0x33.
+    pub fn supports_sgb(&self) -> bool {
+        self.sgb_flag == 0x03 && self.old_licensee_code == 0x33
+    }
+
+    /// The name of the licensee (publisher) of the game, given by the old licensee code (014B), or
+    /// by the new licensee code (0144-0145) if the old one is 0x33. If the code is unknown, the code
+    /// itself is returned.
+    pub fn licensee(&self) -> String {
+        if self.old_licensee_code == 0x33 {
+            let code = String::from_utf8_lossy(&self.new_licensee_code).into_owned();
+            match NEW_LICENSEES.iter().find(|&&(x, _)| x == code) {
+                Some(&(_, name)) => name.to_string(),
+                None => code,
+            }
+        } else {
+            match OLD_LICENSEES
+                .iter()
+                .find(|&&(x, _)| x == self.old_licensee_code)
+            {
+                Some(&(_, name)) => name.to_string(),
+                None => format!("{:02X}", self.old_licensee_code),
+            }
+        }
+    }
+}
+
+/// The Game Boy Color support of a game, declared in its header.
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum CgbSupport {
+    /// A game made for the DMG. The CGB runs it in compatibility mode.
+    None,
+    /// A game that uses the CGB functions, but also runs on the DMG.
+    Enhanced,
+    /// A game that only runs on the CGB.
+    Only,
 }
 
+/// The names of the new licensee codes (0144-0145).
+const NEW_LICENSEES: &[(&str, &str)] = &[
+    ("00", "None"),
+    ("01", "Nintendo"),
+    ("08", "Capcom"),
+    ("13", "Electronic Arts"),
+    ("18", "Hudson Soft"),
+    ("19", "B-AI"),
+    ("20", "KSS"),
+    ("22", "POW"),
+    ("24", "PCM Complete"),
+    ("25", "San-X"),
+    ("28", "Kemco Japan"),
+    ("29", "SETA"),
+    ("30", "Viacom"),
+    ("31", "Nintendo"),
+    ("32", "Bandai"),
+    ("33", "Ocean/Acclaim"),
+    ("34", "Konami"),
+    ("35", "Hector"),
+    ("37", "Taito"),
+    ("38", "Hudson"),
+    ("39", "Banpresto"),
+    ("41", "Ubi Soft"),
+    ("42", "Atlus"),
+    ("44", "Malibu"),
+    ("46", "Angel"),
+    ("47", "Bullet-Proof"),
+    ("49", "Irem"),
+    ("50", "Absolute"),
+    ("51", "Acclaim"),
+    ("52", "Activision"),
+    ("53", "American Sammy"),
+    ("54", "Konami"),
+    ("55", "Hi Tech Entertainment"),
+    ("56", "LJN"),
+    ("57", "Matchbox"),
+    ("58", "Mattel"),
+    ("59", "Milton Bradley"),
+    ("60", "Titus"),
+    ("61", "Virgin"),
+    ("64", "LucasArts"),
+    ("67", "Ocean"),
+    ("69", "Electronic Arts"),
+    ("70", "Infogrames"),
+    ("71", "Interplay"),
+    ("72", "Broderbund"),
+    ("73", "Sculptured"),
+    ("75", "SCI"),
+    ("78", "THQ"),
+    ("79", "Accolade"),
+    ("80", "Misawa"),
+    ("83", "Lozc"),
+    ("86", "Tokuma Shoten"),
+    ("87", "Tsukuda Original"),
+    ("91", "Chunsoft"),
+    ("92", "Video System"),
+    ("93", "Ocean/Acclaim"),
+    ("95", "Varie"),
+    ("96", "Yonezawa/S'pal"),
+    ("97", "Kaneko"),
+    ("99", "Pack-In-Soft"),
+    ("A4", "Konami"),
+];
+
+/// The names of the old licensee codes (014B). 0x33 means that the new licensee code is used.
+const OLD_LICENSEES: &[(u8, &str)] = &[
+    (0x00, "None"),
+    (0x01, "Nintendo"),
+    (0x08, "Capcom"),
+    (0x09, "Hot-B"),
+    (0x0A, "Jaleco"),
+    (0x0B, "Coconuts"),
+    (0x0C, "Elite Systems"),
+    (0x13, "Electronic Arts"),
+    (0x18, "Hudson Soft"),
+    (0x19, "ITC Entertainment"),
+    (0x1A, "Yanoman"),
+    (0x1D, "Clary"),
+    (0x1F, "Virgin"),
+    (0x24, "PCM Complete"),
+    (0x25, "San-X"),
+    (0x28, "Kotobuki Systems"),
+    (0x29, "SETA"),
+    (0x30, "Infogrames"),
+    (0x31, "Nintendo"),
+    (0x32, "Bandai"),
+    (0x34, "Konami"),
+    (0x35, "Hector"),
+    (0x38, "Capcom"),
+    (0x39, "Banpresto"),
+    (0x3C, "Entertainment Interactive"),
+    (0x3E, "Gremlin"),
+    (0x41, "Ubi Soft"),
+    (0x42, "Atlus"),
+    (0x44, "Malibu"),
+    (0x46, "Angel"),
+    (0x47, "Spectrum Holobyte"),
+    (0x49, "Irem"),
+    (0x4A, "Virgin"),
+    (0x4D, "Malibu"),
+    (0x4F, "U.S. Gold"),
+    (0x50, "Absolute"),
+    (0x51, "Acclaim"),
+    (0x52, "Activision"),
+    (0x53, "American Sammy"),
+    (0x54, "GameTek"),
+    (0x55, "Park Place"),
+    (0x56, "LJN"),
+    (0x57, "Matchbox"),
+    (0x59, "Milton Bradley"),
+    (0x5A, "Mindscape"),
+    (0x5B, "Romstar"),
+    (0x5C, "Naxat Soft"),
+    (0x5D, "Tradewest"),
+    (0x60, "Titus"),
+    (0x61, "Virgin"),
+    (0x67, "Ocean"),
+    (0x69, "Electronic Arts"),
+    (0x6E, "Elite Systems"),
+    (0x6F, "Electro Brain"),
+    (0x70, "Infogrames"),
+    (0x71, "Interplay"),
+    (0x72, "Broderbund"),
+    (0x73, "Sculptured Soft"),
+    (0x75, "The Sales Curve"),
+    (0x78, "THQ"),
+    (0x79, "Accolade"),
+    (0x7A, "Triffix Entertainment"),
+    (0x7C, "Microprose"),
+    (0x7F, "Kemco"),
+    (0x80, "Misawa Entertainment"),
+    (0x83, "Lozc"),
+    (0x86, "Tokuma Shoten"),
+    (0x8B, "Bullet-Proof Software"),
+    (0x8C, "Vic Tokai"),
+    (0x8E, "Ape"),
+    (0x8F, "I'Max"),
+    (0x91, "Chunsoft"),
+    (0x92, "Video System"),
+    (0x93, "Tsubaraya Productions"),
+    (0x95, "Varie"),
+    (0x96, "Yonezawa/S'pal"),
+    (0x97, "Kaneko"),
+    (0x99, "Arc"),
+    (0x9A, "Nihon Bussan"),
+    (0x9B, "Tecmo"),
+    (0x9C, "Imagineer"),
+    (0x9D, "Banpresto"),
+    (0x9F, "Nova"),
+    (0xA1, "Hori Electric"),
+    (0xA2, "Bandai"),
+    (0xA4, "Konami"),
+    (0xA6, "Kawada"),
+    (0xA7, "Takara"),
+    (0xA9, "Technos Japan"),
+    (0xAA, "Broderbund"),
+    (0xAC, "Toei Animation"),
+    (0xAD, "Toho"),
+    (0xAF, "Namco"),
+    (0xB0, "Acclaim"),
+    (0xB1, "ASCII or Nexsoft"),
+    (0xB2, "Bandai"),
+    (0xB4, "Square Enix"),
+    (0xB6, "HAL Laboratory"),
+    (0xB7, "SNK"),
+    (0xB9, "Pony Canyon"),
+    (0xBA, "Culture Brain"),
+    (0xBB, "Sunsoft"),
+    (0xBD, "Sony Imagesoft"),
+    (0xBF, "Sammy"),
+    (0xC0, "Taito"),
+    (0xC2, "Kemco"),
+    (0xC3, "Squaresoft"),
+    (0xC4, "Tokuma Shoten"),
+    (0xC5, "Data East"),
+    (0xC6, "Tonkinhouse"),
+    (0xC8, "Koei"),
+    (0xC9, "UFL"),
+    (0xCA, "Ultra"),
+    (0xCB, "Vap"),
+    (0xCC, "Use Corporation"),
+    (0xCD, "Meldac"),
+    (0xCE, "Pony Canyon"),
+    (0xCF, "Angel"),
+    (0xD0, "Taito"),
+    (0xD1, "Sofel"),
+    (0xD2, "Quest"),
+    (0xD3, "Sigma Enterprises"),
+    (0xD4, "ASK Kodansha"),
+    (0xD6, "Naxat Soft"),
+    (0xD7, "Copya System"),
+    (0xD9, "Banpresto"),
+    (0xDA, "Tomy"),
+    (0xDB, "LJN"),
+    (0xDD, "NCS"),
+    (0xDE, "Human"),
+    (0xDF, "Altron"),
+    (0xE0, "Jaleco"),
+    (0xE1, "Towa Chiki"),
+    (0xE2, "Yutaka"),
+    (0xE3, "Varie"),
+    (0xE5, "Epoch"),
+    (0xE7, "Athena"),
+    (0xE8, "Asmik"),
+    (0xE9, "Natsume"),
+    (0xEA, "King Records"),
+    (0xEB, "Atlus"),
+    (0xEC, "Epic/Sony Records"),
+    (0xEE, "IGS"),
+    (0xF0, "A Wave"),
+    (0xF3, "Extreme Entertainment"),
+    (0xFF, "LJN"),
+];
+
+/// The result of checking a rom against its header, for finding bad dumps.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct RomIntegrity {
+    /// See `CartridgeHeader::logo_valid`.
+    pub logo_valid: bool,
+    /// See `CartridgeHeader::header_checksum_valid`.
+    pub header_checksum_valid: bool,
+    /// See `CartridgeHeader::global_checksum_valid`. It is computed over the first
+    /// `expected_size` bytes of the rom, so it ignores trailing bytes of overdumped roms. `None`
+    /// if only the header was checked.
+    pub global_checksum_valid: Option<bool>,
+    /// The rom size given by the header, or `None` if the rom size code is not supported.
+    pub expected_size: Option<usize>,
+    /// The size of the rom, or `None` if only the header was checked.
+    pub size: Option<usize>,
+}
+impl RomIntegrity {
+    /// Check only the header. The global checksum and the size require the entire rom.
+    pub fn from_header(header: &CartridgeHeader) -> Self {
+        Self {
+            logo_valid: header.logo_valid(),
+            header_checksum_valid: header.header_checksum_valid(),
+            global_checksum_valid: None,
+            expected_size: header.rom_size_in_bytes().ok(),
+            size: None,
+        }
+    }
+
+    /// Check the entire rom. Fails if the rom is too small to contain a header.
+    pub fn from_rom(rom: &[u8]) -> Result<Self, String> {
+        let header = match CartridgeHeader::from_bytes(rom) {
+            Ok(x) | Err((Some(x), _)) => x,
+            Err((None, err)) => return Err(err),
+        };
+        let mut this = Self::from_header(&header);
+        let len = this.expected_size.map_or(rom.len(), |x| x.min(rom.len()));
+        this.global_checksum_valid = Some(header.global_checksum_valid(&rom[..len]));
+        this.size = Some(rom.len());
+        Ok(this)
+    }
+
+    /// Return true if the size of the rom is the one given by the header, or if it is unknown.
+    pub fn size_matches(&self) -> bool {
+        match (self.expected_size, self.size) {
+            (Some(expected), Some(size)) => expected == size,
+            _ => true,
+        }
+    }
+
+    /// Return true if no problem was found.
+    pub fn is_valid(&self) -> bool {
+        self.warnings().is_empty()
+    }
+
+    /// A description of each problem found.
+    pub fn warnings(&self) -> Vec<String> {
+        let mut warnings = Vec::new();
+        if !self.logo_valid {
+            warnings.push("invalid logo".to_string());
+        }
+        if !self.header_checksum_valid {
+            warnings.push("invalid header checksum".to_string());
+        }
+        if self.global_checksum_valid == Some(false) {
+            warnings.push("invalid global checksum".to_string());
+        }
+        if self.expected_size.is_none() {
+            warnings.push("unsupported rom size".to_string());
+        }
+        if let (Some(expected), Some(size)) = (self.expected_size, self.size) {
+            if expected != size {
+                warnings.push(format!(
+                    "the rom has {} bytes, but the header expects {} bytes",
+                    size, expected
+                ));
+            }
+        }
+        warnings
+    }
+}
+
+/// The width of the image captured by the Pocket Camera sensor.
+pub const CAMERA_WIDTH: usize = 128;
+/// The height of the image captured by the Pocket Camera sensor.
+pub const CAMERA_HEIGHT: usize = 112;
+
 #[derive(PartialEq, Eq)]
 enum MBC {
     None(MBC0),
@@ -153,15 +584,45 @@ enum MBC {
     MBC2(MBC2),
     MBC3(MBC3),
     MBC5(MBC5),
+    Camera(Camera),
 }
 
-#[derive(PartialEq, Eq)]
 pub struct Cartridge {
     pub header: CartridgeHeader,
+    /// The integrity of the rom given to `Cartridge::new`, before its size was normalized.
+    pub integrity: RomIntegrity,
     pub rom: Vec<u8>,
     pub ram: Vec<u8>,
     mbc: MBC,
+    /// Called when the rumble motor of the cartridge is turned on or off.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) rumble_callback: Option<Box<dyn FnMut(bool) + Send>>,
+    #[cfg(target_arch = "wasm32")]
+    pub(crate) rumble_callback: Option<Box<dyn FnMut(bool)>>,
+    /// Called when the Pocket Camera captures a image, to fill the sensor image. See
+    /// `Cartridge::set_camera_callback`.
+    #[cfg(not(target_arch = "wasm32"))]
+    camera_callback: Option<Box<dyn FnMut(&mut [u8; CAMERA_WIDTH * CAMERA_HEIGHT]) + Send>>,
+    #[cfg(target_arch = "wasm32")]
+    camera_callback: Option<Box<dyn FnMut(&mut [u8; CAMERA_WIDTH * CAMERA_HEIGHT])>>,
+    /// Set when the RAM or the clock is written. Cleared by `take_battery_save`.
+    battery_dirty: bool,
+    /// The number of writes to the RAM or the clock.
+    ram_write_count: u64,
+    /// Set when the RAM is disabled after being written. Cleared by `take_battery_save`.
+    battery_save_finished: bool,
+    /// The CRC-32 of `rom`, computed when the cartridge is created.
+    rom_crc32: u32,
+}
+impl PartialEq for Cartridge {
+    fn eq(&self, other: &Self) -> bool {
+        self.header == other.header
+            && self.rom == other.rom
+            && self.ram == other.ram
+            && self.mbc == other.mbc
+    }
 }
+impl Eq for Cartridge {}
 impl SaveState for Cartridge {
     fn save_state(&self, data: &mut impl std::io::Write) -> Result<(), std::io::Error> {
         // self.rom.save_state(data)?;
@@ -173,6 +634,7 @@ impl SaveState for Cartridge {
             MBC::MBC2(x) => x.save_state(data),
             MBC::MBC3(x) => x.save_state(data),
             MBC::MBC5(x) => x.save_state(data),
+            MBC::Camera(x) => x.save_state(data),
         }
     }
 
@@ -186,15 +648,25 @@ impl SaveState for Cartridge {
             MBC::MBC2(x) => x.load_state(data),
             MBC::MBC3(x) => x.load_state(data),
             MBC::MBC5(x) => x.load_state(data),
+            MBC::Camera(x) => x.load_state(data),
         }
     }
 }
 impl Cartridge {
-    pub fn new(rom: Vec<u8>) -> Result<Self, String> {
+    /// Create a cartridge from the given rom.
+    ///
+    /// If the size of the rom doesn't match the size given in the header, it is padded with 0xFF
+    /// or truncated. Any problem found in the rom is reported in `Cartridge::integrity`.
+    pub fn new(mut rom: Vec<u8>) -> Result<Self, String> {
         let header = match CartridgeHeader::from_bytes(&rom) {
             Ok(x) | Err((Some(x), _)) => x,
             Err((None, err)) => return Err(err),
         };
+        let integrity = RomIntegrity::from_rom(&rom)?;
+
+        let rom_size = header.rom_size_in_bytes()?;
+        rom.resize(rom_size, 0xFF);
+
         // Cartridge Type
         let mbc_kind = header.cartridge_type;
         let mbc = match mbc_kind {
@@ -221,8 +693,11 @@ impl Cartridge {
                 break 'mbc1 MBC::MBC1(MBC1::new());
             },
             5 | 6 => MBC::MBC2(MBC2::new()),
-            0x0F | 0x10 | 0x11 | 0x12 | 0x13 => MBC::MBC3(MBC3::new()),
-            0x19 | 0x1A | 0x1B | 0x1C | 0x1D | 0x1E => MBC::MBC5(MBC5::new()),
+            0x0F | 0x10 => MBC::MBC3(MBC3::new(Some(Rtc::new()))),
+            0x11 | 0x12 | 0x13 => MBC::MBC3(MBC3::new(None)),
+            0x19 | 0x1A | 0x1B => MBC::MBC5(MBC5::new(false)),
+            0x1C | 0x1D | 0x1E => MBC::MBC5(MBC5::new(true)),
+            0xFC => MBC::Camera(Camera::new()),
             _ => {
                 return Err(format!(
                     "MBC type '{}' ({:02x}) is not supported",
@@ -232,16 +707,6 @@ impl Cartridge {
             }
         };
 
-        let rom_size = header.rom_size_in_bytes()?;
-
-        if rom_size != rom.len() {
-            return Err(format!(
-                "In the rom header the expected size is '{}' bytes, but the given rom has '{}' bytes",
-                rom_size,
-                rom.len()
-            ));
-        }
-
         let ram_sizes = [
             0,
             0x800,
@@ -265,19 +730,48 @@ impl Cartridge {
 
         Ok(Self {
             header,
+            integrity,
+            rom_crc32: crc32(&rom),
             rom,
             ram: vec![0; ram_size],
             mbc,
+            rumble_callback: None,
+            camera_callback: None,
+            battery_dirty: false,
+            ram_write_count: 0,
+            battery_save_finished: false,
         })
     }
 
+    /// Create a cartridge for testing, with the given cartridge type, number of 16 KiB ROM banks,
+    /// a power of two from 2 to 512, and header RAM size code. The first byte of each ROM bank,
+    /// except the first one, is the lower byte of its bank number.
+    #[cfg(any(test, feature = "test-util"))]
+    pub fn new_test(kind: u8, rom_banks: usize, ram_size: u8) -> Self {
+        assert!(rom_banks.is_power_of_two() && (2..=512).contains(&rom_banks));
+        let mut rom = vec![0; rom_banks * 0x4000];
+        rom[0x147] = kind;
+        rom[0x148] = rom_banks.trailing_zeros() as u8 - 1;
+        rom[0x149] = ram_size;
+        for bank in 1..rom_banks {
+            rom[bank * 0x4000] = bank as u8;
+        }
+        Self::new(rom).unwrap()
+    }
+
+    /// The CRC-32 of the rom, after its size was normalized. Used to identify the rom of a save
+    /// state.
+    pub fn rom_crc32(&self) -> u32 {
+        self.rom_crc32
+    }
+
     /// The number of banks in this cartridge. A cartridge without bank switching have 2 banks.
     pub fn num_banks(&self) -> u8 {
         (self.rom.len() / 0x4000) as u8
     }
 
-    /// Return a string with the kind of the cartridge.
-    pub fn kind_name(&self) -> &str {
+    /// Return a string with the kind of the MBC (memory bank controller) of the cartridge.
+    pub fn mapper_kind(&self) -> &'static str {
         match &self.mbc {
             MBC::None(_) => "None",
             MBC::MBC1(_) => "MBC1",
@@ -285,11 +779,126 @@ impl Cartridge {
             MBC::MBC2(_) => "MBC2",
             MBC::MBC3(_) => "MBC3",
             MBC::MBC5(_) => "MBC5",
+            MBC::Camera(_) => "Pocket Camera",
         }
     }
 
-    /// The current selected ROM bank
-    pub fn curr_bank(&self) -> u16 {
+    /// If the cartridge has a battery, and so its RAM or clock should be persisted. See
+    /// `CartridgeHeader::has_battery`.
+    pub fn has_battery(&self) -> bool {
+        self.header.has_battery()
+    }
+
+    /// If the cartridge has RAM. See `CartridgeHeader::has_ram`.
+    pub fn has_ram(&self) -> bool {
+        self.header.has_ram()
+    }
+
+    /// The real time clock of the cartridge, if it has one.
+    pub fn rtc(&self) -> Option<&Rtc> {
+        match &self.mbc {
+            MBC::MBC3(x) => x.clock.as_ref(),
+            _ => None,
+        }
+    }
+
+    pub fn rtc_mut(&mut self) -> Option<&mut Rtc> {
+        match &mut self.mbc {
+            MBC::MBC3(x) => x.clock.as_mut(),
+            _ => None,
+        }
+    }
+
+    /// The data persisted by the cartridge battery: the RAM, followed by the RTC footer if the
+    /// cartridge has a real time clock.
+    pub fn battery_save(&self) -> Vec<u8> {
+        let mut data = self.ram.clone();
+        if let MBC::MBC3(MBC3 {
+            clock: Some(clock),
+            rtc,
+            ..
+        }) = &self.mbc
+        {
+            data.extend_from_slice(&clock.footer(rtc));
+        }
+        data
+    }
+
+    /// If the RAM or the clock was written since the last call to `take_battery_save`.
+    pub fn battery_dirty(&self) -> bool {
+        self.battery_dirty
+    }
+
+    /// The number of writes to the RAM or the clock since the cartridge was created. It is not
+    /// included in save states. Useful for detecting when a game stopped writing its save.
+    pub fn ram_write_count(&self) -> u64 {
+        self.ram_write_count
+    }
+
+    /// If the RAM was disabled after being written, since the last call to `take_battery_save`.
+    /// Games usually disable the RAM when they finish saving, so this is a good moment to persist
+    /// the battery save.
+    pub fn battery_save_finished(&self) -> bool {
+        self.battery_save_finished
+    }
+
+    /// Return the same as `battery_save`, and clear the `battery_dirty` and
+    /// `battery_save_finished` flags.
+    pub fn take_battery_save(&mut self) -> Vec<u8> {
+        self.battery_dirty = false;
+        self.battery_save_finished = false;
+        self.battery_save()
+    }
+
+    /// Clear the `battery_dirty` and `battery_save_finished` flags, after the data returned by
+    /// `battery_save` was written, when `ram_write_count` was `write_count`. If the RAM or the
+    /// clock were written since then, the flags are kept, as the written data is outdated.
+    pub fn mark_battery_saved(&mut self, write_count: u64) {
+        if self.ram_write_count == write_count {
+            self.battery_dirty = false;
+            self.battery_save_finished = false;
+        }
+    }
+
+    /// Load the data returned by `battery_save`. The RTC footer can also be in its 44 bytes
+    /// variant, and is optional.
+    ///
+    /// If the data is shorter than the RAM, the rest of the RAM is filled with 0xFF. If it is
+    /// longer, the data is still loaded, but truncated, and a error is returned.
+    pub fn load_battery_save(&mut self, mut data: Vec<u8>) -> Result<(), String> {
+        let ram_len = self.ram.len();
+        if let MBC::MBC3(MBC3 {
+            clock: Some(clock),
+            rtc,
+            ..
+        }) = &mut self.mbc
+        {
+            let footer_len = data.len().wrapping_sub(ram_len);
+            if footer_len == 44 || footer_len == 48 {
+                *rtc = clock.load_footer(&data[ram_len..]);
+                data.truncate(ram_len);
+            }
+        }
+
+        let len = data.len();
+        data.resize(ram_len, 0xFF);
+        if let MBC::MBC2(_) = self.mbc {
+            // the RAM is 512 half-bytes, so the upper half of each byte is discarded
+            data.iter_mut().for_each(|x| *x &= 0x0F);
+        }
+        self.ram = data;
+
+        if len > ram_len {
+            return Err(format!(
+                "the battery save has {} bytes, but the cartridge RAM has only {} bytes. The extra bytes were discarded",
+                len, ram_len
+            ));
+        }
+        Ok(())
+    }
+
+    /// The ROM bank currently mapped to 4000-7FFF.
+    pub fn curr_rom_bank(&self) -> u16 {
         match &self.mbc {
             MBC::None(_) => 1,
             MBC::MBC1(x) => x.curr_bank(&self.rom),
@@ -297,10 +906,18 @@ impl Cartridge {
             MBC::MBC2(x) => x.curr_bank(&self.rom),
             MBC::MBC3(x) => x.curr_bank(&self.rom),
             MBC::MBC5(x) => x.curr_bank(&self.rom),
+            MBC::Camera(x) => x.curr_bank(&self.rom),
         }
     }
 
+    /// Same as `read_at`, at a clock count where any Pocket Camera capture has already finished.
     pub fn read(&self, address: u16) -> u8 {
+        self.read_at(address, u64::MAX)
+    }
+
+    /// Read the given address, at the given clock count. The clock count is only used for timing
+    /// the Pocket Camera captures.
+    pub fn read_at(&self, address: u16, clock_count: u64) -> u8 {
         match &self.mbc {
             MBC::None(x) => x.read(address, &self.rom, &self.ram),
             MBC::MBC1(x) => x.read(address, &self.rom, &self.ram),
@@ -308,18 +925,201 @@ impl Cartridge {
             MBC::MBC2(x) => x.read(address, &self.rom, &self.ram),
             MBC::MBC3(x) => x.read(address, &self.rom, &self.ram),
             MBC::MBC5(x) => x.read(address, &self.rom, &self.ram),
+            MBC::Camera(x) => x.read(address, &self.rom, &self.ram, clock_count),
         }
     }
 
+    /// The RAM bank currently mapped to A000-BFFF. For MBC3 cartridges, values from 0x08 to 0x0C
+    /// select a RTC register instead, and for the Pocket Camera, 0x10 selects the sensor
+    /// registers.
+    pub fn curr_ram_bank(&self) -> u8 {
+        match &self.mbc {
+            MBC::None(_) | MBC::MBC2(_) => 0,
+            MBC::MBC1(x) => {
+                let large_rom = self.rom.len() >= 0x10_0000;
+                if x.mode && !large_rom {
+                    (x.selected_bank >> 5) & 0x03
+                } else {
+                    0
+                }
+            }
+            MBC::MBC1M(x) => {
+                let large_rom = self.rom.len() >= 0x10_0000;
+                if x.mode && !large_rom {
+                    (x.selected_bank >> 4) & 0x03
+                } else {
+                    0
+                }
+            }
+            MBC::MBC3(x) => x.ram_bank,
+            MBC::MBC5(x) => x.ram_bank() as u8,
+            MBC::Camera(x) => x.selected_ram_bank,
+        }
+    }
+
+    /// The banking mode of MBC1 cartridges: `false` for mode 0, and `true` for mode 1. `None`
+    /// for other cartridges.
+    pub fn banking_mode(&self) -> Option<bool> {
+        match &self.mbc {
+            MBC::MBC1(x) => Some(x.mode),
+            MBC::MBC1M(x) => Some(x.mode),
+            _ => None,
+        }
+    }
+
+    /// If the RAM, or the RTC registers, are enabled for reading and writing. Cartridges without a
+    /// MBC are always enabled.
+    pub fn ram_enabled(&self) -> bool {
+        match &self.mbc {
+            MBC::None(_) => true,
+            MBC::MBC1(x) => x.ram_enabled,
+            MBC::MBC1M(x) => x.ram_enabled,
+            MBC::MBC2(x) => x.ram_enabled,
+            MBC::MBC3(x) => x.ram_enabled,
+            MBC::MBC5(x) => x.ram_enabled,
+            MBC::Camera(x) => x.ram_enabled,
+        }
+    }
+
+    /// Write a value directly to the RAM at the given address of A000-BFFF, in the given RAM bank,
+    /// or in the bank currently mapped if `bank` is `None`, like the GameShark does. Unlike `write`,
+    /// it works while the RAM is disabled, and doesn't mark the battery save as dirty, so a value
+    /// frozen by a cheat doesn't keep the save from settling.
+    pub fn poke_ram(&mut self, bank: Option<u8>, address: u16, value: u8) {
+        debug_assert!((0xA000..=0xBFFF).contains(&address));
+        if self.ram.is_empty() {
+            return;
+        }
+        let offset = address as usize - 0xA000;
+        let bank = match (&self.mbc, bank) {
+            (MBC::MBC2(_), _) => {
+                // 512x4bits RAM, echoed through all the area
+                self.ram[offset & 0x1FF] = value & 0x0F;
+                return;
+            }
+            (_, Some(bank)) => bank,
+            // the RTC registers are mapped in place of the RAM
+            (MBC::MBC3(_), None) if self.curr_ram_bank() >= 0x08 => return,
+            // the sensor registers are mapped in place of the RAM
+            (MBC::Camera(_), None) if self.curr_ram_bank() & 0x10 != 0 => return,
+            (_, None) => self.curr_ram_bank(),
+        };
+        let len = self.ram.len();
+        self.ram[(bank as usize * 0x2000 + offset) % len] = value;
+    }
+
+    /// Same as `write_at`, at the clock count 0.
     pub fn write(&mut self, address: u16, value: u8) {
+        self.write_at(address, value, 0)
+    }
+
+    /// Write to the given address, at the given clock count. The clock count is only used for
+    /// timing the Pocket Camera captures.
+    pub fn write_at(&mut self, address: u16, value: u8, clock_count: u64) {
+        let ram_enabled = self.ram_enabled();
+        if ram_enabled && (0xA000..=0xBFFF).contains(&address) {
+            self.battery_dirty = true;
+            self.ram_write_count += 1;
+        }
         match &mut self.mbc {
             MBC::None(x) => x.write(address, value, &self.rom, &mut self.ram),
             MBC::MBC1(x) => x.write(address, value, &self.rom, &mut self.ram),
             MBC::MBC1M(x) => x.write(address, value, &self.rom, &mut self.ram),
             MBC::MBC2(x) => x.write(address, value, &self.rom, &mut self.ram),
             MBC::MBC3(x) => x.write(address, value, &self.rom, &mut self.ram),
-            MBC::MBC5(x) => x.write(address, value, &self.rom, &mut self.ram),
+            MBC::MBC5(x) => {
+                let rumble = x.rumble_on();
+                x.write(address, value, &self.rom, &mut self.ram);
+                if x.rumble_on() != rumble {
+                    if let Some(callback) = &mut self.rumble_callback {
+                        callback(!rumble);
+                    }
+                }
+            }
+            MBC::Camera(x) => {
+                if x.write(address, value, &self.rom, &mut self.ram, clock_count) {
+                    let mut sensor = [0; CAMERA_WIDTH * CAMERA_HEIGHT];
+                    match &mut self.camera_callback {
+                        Some(callback) => callback(&mut sensor),
+                        None => Camera::test_pattern(&mut sensor),
+                    }
+                    x.capture(&sensor, &mut self.ram);
+                }
+            }
         }
+        if ram_enabled && self.battery_dirty && !self.ram_enabled() {
+            self.battery_save_finished = true;
+        }
+    }
+
+    /// Set a callback that is called when the rumble motor of the cartridge is turned on or off,
+    /// with the new state of the motor.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn set_rumble_callback(&mut self, callback: Box<dyn FnMut(bool) + Send>) {
+        self.rumble_callback = Some(callback);
+    }
+
+    /// Set a callback that is called when the rumble motor of the cartridge is turned on or off,
+    /// with the new state of the motor.
+    #[cfg(target_arch = "wasm32")]
+    pub fn set_rumble_callback(&mut self, callback: Box<dyn FnMut(bool)>) {
+        self.rumble_callback = Some(callback);
+    }
+
+    /// Set a callback that is called when the Pocket Camera captures a image. It receives the
+    /// image of the sensor, with one byte for each pixel, in rows of `CAMERA_WIDTH` pixels, where 0
+    /// is black and 255 is white. Without a callback, a test pattern is captured.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn set_camera_callback(
+        &mut self,
+        callback: Box<dyn FnMut(&mut [u8; CAMERA_WIDTH * CAMERA_HEIGHT]) + Send>,
+    ) {
+        self.camera_callback = Some(callback);
+    }
+
+    /// Set a callback that is called when the Pocket Camera captures a image. It receives the
+    /// image of the sensor, with one byte for each pixel, in rows of `CAMERA_WIDTH` pixels, where 0
+    /// is black and 255 is white. Without a callback, a test pattern is captured.
+    #[cfg(target_arch = "wasm32")]
+    pub fn set_camera_callback(
+        &mut self,
+        callback: Box<dyn FnMut(&mut [u8; CAMERA_WIDTH * CAMERA_HEIGHT])>,
+    ) {
+        self.camera_callback = Some(callback);
+    }
+
+    /// If the cartridge has a rumble motor, and it is turned on.
+    pub fn rumble_on(&self) -> bool {
+        match &self.mbc {
+            MBC::MBC5(x) => x.rumble_on(),
+            _ => false,
+        }
+    }
+
+    /// Reset the MBC to its power on state. The RAM and the real time clock are kept.
+    pub fn reset(&mut self) {
+        if self.rumble_on() {
+            if let Some(callback) = &mut self.rumble_callback {
+                callback(false);
+            }
+        }
+        self.mbc = match &mut self.mbc {
+            MBC::None(_) => MBC::None(MBC0 {}),
+            MBC::MBC1(_) => MBC::MBC1(MBC1::new()),
+            MBC::MBC1M(_) => MBC::MBC1M(MBC1M::new()),
+            MBC::MBC2(_) => MBC::MBC2(MBC2::new()),
+            MBC::MBC3(x) => MBC::MBC3(MBC3::new(x.clock.take())),
+            MBC::MBC5(x) => MBC::MBC5(MBC5::new(x.rumble)),
+            MBC::Camera(_) => MBC::Camera(Camera::new()),
+        };
+    }
+
+    /// Clear the RAM, like a new cartridge, erasing any save. It is marked as written, so the
+    /// empty save replaces the previous one when the battery save is flushed.
+    pub fn clear_ram(&mut self) {
+        self.ram.iter_mut().for_each(|x| *x = 0);
+        self.battery_dirty = true;
+        self.ram_write_count += 1;
     }
 }
 
@@ -332,8 +1132,13 @@ impl MBC0 {
         match address {
             // ROM
             0x0000..=0x7FFF => rom[address as usize],
-            // RAM
-            0xA000..=0xBFFF => ram[address as usize - 0xA000],
+            // RAM, if any
+            0xA000..=0xBFFF => {
+                if ram.is_empty() {
+                    return 0xff;
+                }
+                ram[(address as usize - 0xA000) % ram.len()]
+            }
             _ => unreachable!("read cartridge out of bounds"),
         }
     }
@@ -342,9 +1147,13 @@ impl MBC0 {
         match address {
             // ROM
             0x0000..=0x7FFF => {}
-            // RAM
+            // RAM, if any
             0xA000..=0xBFFF => {
-                ram[address as usize - 0xA000] = value;
+                if ram.is_empty() {
+                    return;
+                }
+                let len = ram.len();
+                ram[(address as usize - 0xA000) % len] = value;
             }
             _ => unreachable!("write cartridge out of bounds"),
         }
@@ -550,7 +1359,7 @@ impl MBC1M {
                     if large_rom {
                         0
                     } else {
-                        0x2000 * ((self.selected_bank >> 5) & 0x03) as usize
+                        0x2000 * ((self.selected_bank >> 4) & 0x03) as usize
                     }
                 } else {
                     // Mode 0
@@ -662,7 +1471,7 @@ impl MBC2 {
                 if !self.ram_enabled {
                     return 0xff;
                 }
-                // upper 4bits are undefined
+                // upper 4 bits are read as 1
                 ram[address as usize] | 0xF0
             }
             _ => unreachable!("read cartridge out of bounds"),
@@ -691,15 +1500,168 @@ impl MBC2 {
                 if !self.ram_enabled {
                     return;
                 }
-                let address = address & 0x1FF; // only the bottom 9 bits are used
-                                               // upper 4bits are undefined
-                ram[address as usize] = value | 0xF0;
+                // only the bottom 9 bits are used, and only the lower 4 bits are stored
+                let address = address & 0x1FF;
+                ram[address as usize] = value & 0x0F;
             }
             _ => unreachable!("write cartridge out of bounds"),
         }
     }
 }
 
+/// The real time clock of a MBC3 cartridge.
+///
+/// The clock counts seconds since a unix timestamp, so it keeps advancing with the real time, even
+/// while the emulator is closed.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct Rtc {
+    /// The value of the counter at `timestamp`, in seconds.
+    counter: u64,
+    /// The unix time, in seconds, in which the counter had the value `counter`.
+    timestamp: u64,
+    /// The clock is halted (bit 6 of DH).
+    halted: bool,
+    /// The day counter overflowed (bit 7 of DH).
+    day_carry: bool,
+    /// If set, this unix time is used instead of the system time, so the clock doesn't advance.
+    /// Can be used to freeze or to control the clock.
+    pub frozen_time: Option<u64>,
+}
+crate::save_state!(Rtc, self, data {
+    self.counter;
+    self.timestamp;
+    bitset [self.halted, self.day_carry];
+});
+impl Rtc {
+    const DAY: u64 = 24 * 60 * 60;
+    /// The day counter has 9 bits.
+    const MAX_DAYS: u64 = 512;
+
+    /// The size of the footer appended to the battery save, in the format used by others emulators.
+    pub const FOOTER_LEN: usize = 48;
+
+    pub fn new() -> Self {
+        Self {
+            counter: 0,
+            timestamp: Self::system_time(),
+            halted: false,
+            day_carry: false,
+            frozen_time: None,
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn system_time() -> u64 {
+        use std::time::SystemTime;
+        SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_or(0, |x| x.as_secs())
+    }
+
+    // The system time is not available in wasm32-unknown-unknown. The frontend can drive the clock
+    // through `frozen_time` instead.
+    #[cfg(target_arch = "wasm32")]
+    fn system_time() -> u64 {
+        0
+    }
+
+    /// The current unix time, in seconds.
+    pub fn now(&self) -> u64 {
+        self.frozen_time.unwrap_or_else(Self::system_time)
+    }
+
+    /// The current value of the counter, in seconds, and the day carry bit.
+    fn current(&self) -> (u64, bool) {
+        let mut counter = self.counter;
+        if !self.halted {
+            counter += self.now().saturating_sub(self.timestamp);
+        }
+        let carry = self.day_carry || counter >= Self::MAX_DAYS * Self::DAY;
+        (counter % (Self::MAX_DAYS * Self::DAY), carry)
+    }
+
+    /// The number of seconds counted by the clock, including the days.
+    pub fn seconds(&self) -> u64 {
+        self.current().0
+    }
+
+    /// Set the number of seconds counted by the clock. The days above 511 wrap around.
+    pub fn set_seconds(&mut self, seconds: u64) {
+        self.counter = seconds % (Self::MAX_DAYS * Self::DAY);
+        self.timestamp = self.now();
+    }
+
+    pub fn halted(&self) -> bool {
+        self.halted
+    }
+
+    /// Freeze the clock at the current time, or make it follow the system time again, continuing
+    /// from where it was frozen.
+    pub fn freeze(&mut self, freeze: bool) {
+        let seconds = self.seconds();
+        self.frozen_time = if freeze { Some(self.now()) } else { None };
+        self.set_seconds(seconds);
+    }
+
+    /// The values of the registers S, M, H, DL and DH.
+    fn registers(&self) -> [u8; 5] {
+        let (counter, carry) = self.current();
+        let days = counter / Self::DAY;
+        [
+            (counter % 60) as u8,
+            (counter / 60 % 60) as u8,
+            (counter / 3600 % 24) as u8,
+            days as u8,
+            (days >> 8) as u8 & 0x1 | (self.halted as u8) << 6 | (carry as u8) << 7,
+        ]
+    }
+
+    /// Write to one of the registers S, M, H, DL and DH, given by its index.
+    fn write(&mut self, register: u8, value: u8) {
+        let mut regs = self.registers();
+        regs[register as usize] = value;
+        let [secs, mins, hours, dayl, dayh] = regs;
+        let days = (dayh as u64 & 0x1) << 8 | dayl as u64;
+        let counter = days * Self::DAY + hours as u64 * 3600 + mins as u64 * 60 + secs as u64;
+
+        self.day_carry = dayh & 0x80 != 0;
+        self.halted = dayh & 0x40 != 0;
+        self.set_seconds(counter);
+    }
+
+    /// Encode the clock in the footer of the battery save, with the given latched registers.
+    fn footer(&self, latched: &[u8; 5]) -> [u8; Self::FOOTER_LEN] {
+        let mut footer = [0; Self::FOOTER_LEN];
+        for (i, &x) in self.registers().iter().chain(latched.iter()).enumerate() {
+            footer[i * 4..i * 4 + 4].copy_from_slice(&(x as u32).to_le_bytes());
+        }
+        footer[40..48].copy_from_slice(&self.now().to_le_bytes());
+        footer
+    }
+
+    /// Load the clock from the footer of a battery save, returning the latched registers. The
+    /// footer is 48 bytes long, or 44 bytes, with a 32-bit timestamp.
+    fn load_footer(&mut self, footer: &[u8]) -> [u8; 5] {
+        let reg = |i: usize| footer[i * 4];
+        let timestamp = match footer.len() {
+            48 => u64::from_le_bytes(footer[40..48].try_into().unwrap()),
+            _ => u32::from_le_bytes(footer[40..44].try_into().unwrap()) as u64,
+        };
+
+        let days = (reg(4) as u64 & 0x1) << 8 | reg(3) as u64;
+        self.counter = days * Self::DAY + reg(2) as u64 * 3600 + reg(1) as u64 * 60 + reg(0) as u64;
+        self.timestamp = timestamp;
+        self.halted = reg(4) & 0x40 != 0;
+        self.day_carry = reg(4) & 0x80 != 0;
+        [reg(5), reg(6), reg(7), reg(8), reg(9)]
+    }
+}
+impl Default for Rtc {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 /// Cartridge with a MBC3 chip
 #[derive(PartialEq, Eq)]
 struct MBC3 {
@@ -708,11 +1670,14 @@ struct MBC3 {
     // false is mode 0, true is mode 1
     ram_enabled: bool,
     ram_bank: u8,
+    /// The latched values of the RTC registers.
     rtc: [u8; 5],
     // the state in the latch clock data operation.
     // 0 is the intial state
     // 1 means that 0 was written
     latch_clock_data: u8,
+    /// The real time clock, if the cartridge has a timer.
+    clock: Option<Rtc>,
 }
 crate::save_state!(MBC3, self, data {
     self.selected_bank;
@@ -722,13 +1687,14 @@ crate::save_state!(MBC3, self, data {
     self.latch_clock_data;
 });
 impl MBC3 {
-    fn new() -> Self {
+    fn new(clock: Option<Rtc>) -> Self {
         Self {
             selected_bank: 1,
             ram_enabled: false,
             ram_bank: 0,
             rtc: [0; 5],
             latch_clock_data: 0,
+            clock,
         }
     }
 
@@ -770,12 +1736,14 @@ impl MBC3 {
                         if !self.ram_enabled || ram.is_empty() {
                             return 0xff;
                         }
-                        let start_address = 0x2000 * ((self.selected_bank >> 5) & 0x3) as usize;
-                        ram[address as usize - 0xA000 + start_address]
+                        let start_address = 0x2000 * self.ram_bank as usize;
+                        ram[(address as usize - 0xA000 + start_address) % ram.len()]
                     }
                     // RTC registers
                     0x8..=0xC => {
-                        // TODO: implement the rtc correctly
+                        if !self.ram_enabled || self.clock.is_none() {
+                            return 0xff;
+                        }
                         self.rtc[self.ram_bank as usize - 0x8]
                     }
                     _ => {
@@ -821,27 +1789,10 @@ impl MBC3 {
                     self.latch_clock_data = 1;
                 } else if value == 1 && self.latch_clock_data == 1 {
                     self.latch_clock_data = 0;
-                    // TODO: complete the rtc implementation
-
-                    let now = std::time::Duration::ZERO;
-                    let seconds = now.as_secs();
-                    let secs = (seconds % 60) as u8;
-                    let mins = ((seconds / 60) % 60) as u8;
-                    let hous = (((seconds / 60) / 60) % 24) as u8;
-                    let days = (((seconds / 60) / 60) / 24) as u16;
-                    let dayl = (days & 0xFF) as u8;
-                    let dayu = ((days >> 8) & 0x1) as u8;
                     // latch the current time into rtc registers
-                    self.rtc = [
-                        secs, // RTC S   Seconds   0-59 (0-3Bh)
-                        mins, // RTC M   Minutes   0-59 (0-3Bh)
-                        hous, // RTC H   Hours     0-23 (0-17h)
-                        dayl, // RTC DL  Lower 8 bits of Day Counter (0-FFh)
-                        dayu, // RTC DH  Upper 1 bit of Day Counter, Carry Bit, Halt Flag
-                              //        Bit 0  Most significant bit of Day Counter (Bit 8)
-                              //        Bit 6  Halt (0=Active, 1=Stop Timer)
-                              //        Bit 7  Day Counter Carry Bit (1=Counter Overflow)
-                    ];
+                    if let Some(clock) = &self.clock {
+                        self.rtc = clock.registers();
+                    }
                 } else {
                     self.latch_clock_data = 0;
                 }
@@ -854,13 +1805,20 @@ impl MBC3 {
                         if !self.ram_enabled || ram.is_empty() {
                             return;
                         }
-                        let start_address = 0x2000 * ((self.selected_bank >> 5) & 0x3) as usize;
-                        ram[address as usize - 0xA000 + start_address] = value;
+                        let start_address = 0x2000 * self.ram_bank as usize;
+                        let len = ram.len();
+                        ram[(address as usize - 0xA000 + start_address) % len] = value;
                     }
                     // RTC registers
                     0x8..=0xC => {
-                        // TODO: implement the rtc correctly
-                        self.rtc[self.ram_bank as usize - 0x8] = value;
+                        if !self.ram_enabled {
+                            return;
+                        }
+                        if let Some(clock) = &mut self.clock {
+                            let register = self.ram_bank - 0x8;
+                            clock.write(register, value);
+                            self.rtc[register as usize] = value;
+                        }
                     }
                     _ => {
                         // I don't know what happen here
@@ -876,8 +1834,11 @@ impl MBC3 {
 #[derive(PartialEq, Eq)]
 struct MBC5 {
     selected_bank: u16,
+    /// The RAM bank register. In cartridges with rumble, the bit 3 controls the motor instead.
     selected_ram_bank: u8,
     ram_enabled: bool,
+    /// The cartridge has a rumble motor.
+    rumble: bool,
 }
 crate::save_state!(MBC5, self, data {
     self.selected_bank;
@@ -885,17 +1846,30 @@ crate::save_state!(MBC5, self, data {
     bitset [self.ram_enabled];
 });
 impl MBC5 {
-    fn new() -> Self {
+    fn new(rumble: bool) -> Self {
         Self {
             selected_bank: 1,
             selected_ram_bank: 0,
             ram_enabled: false,
+            rumble,
         }
     }
     fn curr_bank(&self, rom: &[u8]) -> u16 {
         self.selected_bank % (rom.len() / 0x4000) as u16
     }
 
+    fn ram_bank(&self) -> usize {
+        if self.rumble {
+            (self.selected_ram_bank & 0x07) as usize
+        } else {
+            self.selected_ram_bank as usize
+        }
+    }
+
+    fn rumble_on(&self) -> bool {
+        self.rumble && self.selected_ram_bank & 0x08 != 0
+    }
+
     pub fn read(&self, address: u16, rom: &[u8], ram: &Vec<u8>) -> u8 {
         match address {
             // ROM Bank 00
@@ -912,8 +1886,8 @@ impl MBC5 {
                 if !self.ram_enabled || ram.is_empty() {
                     return 0xff;
                 }
-                let start_address = (self.selected_ram_bank as usize * 0x2000) % ram.len();
-                ram[address as usize - 0xA000 + start_address]
+                let start_address = self.ram_bank() * 0x2000;
+                ram[(address as usize - 0xA000 + start_address) % ram.len()]
             }
             _ => unreachable!("read cartridge out of bounds"),
         }
@@ -937,15 +1911,715 @@ impl MBC5 {
             0x4000..=0x5FFF => {
                 self.selected_ram_bank = value & 0x0F;
             }
+            0x6000..=0x7FFF => {}
             // RAM banks
             0xA000..=0xBFFF => {
                 if !self.ram_enabled || ram.is_empty() {
                     return;
                 }
-                let start_address = (self.selected_ram_bank as usize * 0x2000) % ram.len();
-                ram[address as usize - 0xA000 + start_address] = value;
+                let start_address = self.ram_bank() * 0x2000;
+                let len = ram.len();
+                ram[(address as usize - 0xA000 + start_address) % len] = value;
+            }
+            _ => unreachable!("write cartridge out of bounds"),
+        }
+    }
+}
+
+/// Pocket Camera (Game Boy Camera) cartridge, with its own MBC and a image sensor.
+///
+/// The sensor registers are mapped to A000-BFFF when the bit 4 of the RAM bank register is set.
+/// Only the first one, which starts a capture, can be read. A capture converts the sensor image to
+/// 2 bits per pixel using the dithering matrix in the registers, and write it as 16x14 tiles at
+/// A100-AEFF of the RAM bank 0. The gain, exposure and edge enhancement of the sensor are not
+/// emulated, but the exposure time affects the duration of the capture.
+#[derive(PartialEq, Eq)]
+struct Camera {
+    selected_bank: u8,
+    /// The RAM bank register. When the bit 4 is set, the sensor registers are mapped instead.
+    selected_ram_bank: u8,
+    /// Enable writes to the RAM. It can always be read.
+    ram_enabled: bool,
+    /// The sensor registers, A000-A035.
+    registers: [u8; 0x36],
+    /// The clock count in which the current capture finishes.
+    capture_end: u64,
+}
+crate::save_state!(Camera, self, data {
+    self.selected_bank;
+    self.selected_ram_bank;
+    bitset [self.ram_enabled];
+    self.registers;
+    self.capture_end;
+});
+impl Camera {
+    fn new() -> Self {
+        Self {
+            selected_bank: 1,
+            selected_ram_bank: 0,
+            ram_enabled: false,
+            registers: [0; 0x36],
+            capture_end: 0,
+        }
+    }
+
+    fn curr_bank(&self, rom: &[u8]) -> u16 {
+        self.selected_bank as u16 % (rom.len() / 0x4000) as u16
+    }
+
+    fn registers_mapped(&self) -> bool {
+        self.selected_ram_bank & 0x10 != 0
+    }
+
+    /// The number of clocks that a capture takes, for the current exposure time.
+    fn capture_duration(&self) -> u64 {
+        let exposure = u16::from_be_bytes([self.registers[2], self.registers[3]]) as u64;
+        let n = self.registers[1] & 0x80 != 0;
+        4 * (32446 + if n { 0 } else { 512 } + 16 * exposure)
+    }
+
+    /// Fill the sensor with a test pattern: vertical bars, from white to black.
+    fn test_pattern(sensor: &mut [u8; CAMERA_WIDTH * CAMERA_HEIGHT]) {
+        for (i, x) in sensor.iter_mut().enumerate() {
+            *x = 255 - ((i % CAMERA_WIDTH) / 16 * 255 / 7) as u8;
+        }
+    }
+
+    /// Write the image of the sensor to the RAM, dithered by the matrix in the registers.
+    fn capture(&self, sensor: &[u8; CAMERA_WIDTH * CAMERA_HEIGHT], ram: &mut Vec<u8>) {
+        if ram.len() < 0x1000 {
+            return;
+        }
+        for y in 0..CAMERA_HEIGHT {
+            for x in 0..CAMERA_WIDTH {
+                let value = sensor[y * CAMERA_WIDTH + x];
+                let i = 6 + ((y % 4) * 4 + x % 4) * 3;
+                let thresholds = &self.registers[i..i + 3];
+                let color = thresholds.iter().filter(|&&t| value < t).count() as u8;
+
+                let tile = (y / 8) * (CAMERA_WIDTH / 8) + x / 8;
+                let address = 0x100 + tile * 16 + (y % 8) * 2;
+                let bit = 7 - (x % 8);
+                ram[address] = (ram[address] & !(1 << bit)) | ((color & 1) << bit);
+                ram[address + 1] = (ram[address + 1] & !(1 << bit)) | ((color >> 1) << bit);
+            }
+        }
+    }
+
+    pub fn read(&self, address: u16, rom: &[u8], ram: &Vec<u8>, clock_count: u64) -> u8 {
+        match address {
+            // ROM Bank 00
+            0x0000..=0x3FFF => rom[address as usize],
+            // ROM Bank 01-3F
+            0x4000..=0x7FFF => {
+                let bank = self.curr_bank(rom);
+                rom[address as usize - 0x4000 + 0x4000 * bank as usize]
+            }
+            // Sensor registers
+            0xA000..=0xBFFF if self.registers_mapped() => {
+                // only the first register can be read, and the others read as 0x00
+                if address & 0x7F != 0 {
+                    return 0x00;
+                }
+                let busy = clock_count < self.capture_end;
+                (self.registers[0] & 0x06) | busy as u8
+            }
+            // RAM banks
+            0xA000..=0xBFFF => {
+                if ram.is_empty() {
+                    return 0xff;
+                }
+                let start_address = (self.selected_ram_bank & 0x0F) as usize * 0x2000;
+                ram[(address as usize - 0xA000 + start_address) % ram.len()]
+            }
+            _ => unreachable!("read cartridge out of bounds"),
+        }
+    }
+
+    /// Return true if a capture was started.
+    pub fn write(
+        &mut self,
+        address: u16,
+        value: u8,
+        _rom: &[u8],
+        ram: &mut Vec<u8>,
+        clock_count: u64,
+    ) -> bool {
+        match address {
+            // RAM Enable
+            0x0000..=0x1FFF => {
+                self.ram_enabled = value & 0x0F == 0x0A;
+            }
+            // ROM Bank
+            0x2000..=0x3FFF => {
+                self.selected_bank = value & 0x3F;
+            }
+            // RAM Bank, or sensor registers
+            0x4000..=0x5FFF => {
+                self.selected_ram_bank = value & 0x1F;
+            }
+            0x6000..=0x7FFF => {}
+            // Sensor registers
+            0xA000..=0xBFFF if self.registers_mapped() => {
+                let register = (address & 0x7F) as usize;
+                if register == 0 {
+                    self.registers[0] = value & 0x07;
+                    if value & 0x01 != 0 {
+                        self.capture_end = clock_count + self.capture_duration();
+                        return true;
+                    }
+                } else if register < self.registers.len() {
+                    self.registers[register] = value;
+                }
+            }
+            // RAM banks
+            0xA000..=0xBFFF => {
+                if !self.ram_enabled || ram.is_empty() {
+                    return false;
+                }
+                let start_address = (self.selected_ram_bank & 0x0F) as usize * 0x2000;
+                let len = ram.len();
+                ram[(address as usize - 0xA000 + start_address) % len] = value;
             }
             _ => unreachable!("write cartridge out of bounds"),
         }
+        false
+    }
+}
+
+/// Compute the CRC-32 (the one used by zip and PNG) of the given data.
+fn crc32(data: &[u8]) -> u32 {
+    const TABLE: [u32; 256] = {
+        let mut table = [0; 256];
+        let mut i = 0;
+        while i < 256 {
+            let mut c = i as u32;
+            let mut k = 0;
+            while k < 8 {
+                c = if c & 1 != 0 {
+                    0xEDB88320 ^ (c >> 1)
+                } else {
+                    c >> 1
+                };
+                k += 1;
+            }
+            table[i] = c;
+            i += 1;
+        }
+        table
+    };
+    !data.iter().fold(!0u32, |crc, &b| {
+        TABLE[((crc ^ b as u32) & 0xFF) as usize] ^ (crc >> 8)
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+
+    /// A MBC2+BATTERY cartridge with 4 ROM banks, and the RAM enabled.
+    fn mbc2_cartridge() -> Cartridge {
+        let mut cartridge = Cartridge::new_test(0x06, 4, 0x00);
+        cartridge.write(0x0000, 0x0A);
+        cartridge
+    }
+
+    #[test]
+    fn mbc2_ram_echo() {
+        let mut cartridge = mbc2_cartridge();
+        assert_eq!(cartridge.ram.len(), 0x200);
+
+        // only the lower half of each byte is stored
+        cartridge.write(0xA000, 0x5A);
+        assert_eq!(cartridge.read(0xA000), 0xFA);
+
+        // the 512 half-bytes are echoed through all the RAM area
+        for address in (0xA000..=0xBFFF).step_by(0x200) {
+            assert_eq!(cartridge.read(address), 0xFA, "{:04x}", address);
+        }
+        cartridge.write(0xBFFF, 0x03);
+        assert_eq!(cartridge.read(0xA1FF), 0xF3);
+        assert_eq!(cartridge.read(0xB3FF), 0xF3);
+
+        // the bit 8 of the address selects between RAM enable and ROM bank
+        cartridge.write(0x0100, 0x02);
+        assert_eq!(cartridge.read(0x4000), 2);
+        assert_eq!(cartridge.read(0xA000), 0xFA);
+        cartridge.write(0x0000, 0x00);
+        assert_eq!(cartridge.read(0xA000), 0xFF);
+        cartridge.write(0x3F00, 0x03);
+        assert_eq!(cartridge.read(0x4000), 3);
+        cartridge.write(0x3E00, 0x0A);
+        assert_eq!(cartridge.read(0xA000), 0xFA);
+    }
+
+    #[test]
+    fn mbc2_battery_save() {
+        let mut cartridge = mbc2_cartridge();
+        cartridge.write(0xA010, 0x07);
+        let data = cartridge.battery_save();
+        assert_eq!(data.len(), 0x200);
+
+        assert_eq!(data[0x10], 0x07);
+
+        // the upper half of the bytes is discarded
+        let mut loaded = mbc2_cartridge();
+        loaded
+            .load_battery_save(data.iter().map(|x| x | 0xA0).collect())
+            .unwrap();
+        assert_eq!(loaded.ram, cartridge.ram);
+        assert_eq!(loaded.read(0xA010), 0xF7);
+
+        // a short save is padded
+        let mut loaded = mbc2_cartridge();
+        loaded.load_battery_save(vec![0x01; 0x100]).unwrap();
+        assert_eq!(loaded.ram.len(), 0x200);
+        assert_eq!(loaded.read(0xA0FF), 0xF1);
+        assert_eq!(loaded.read(0xA100), 0xFF);
+    }
+
+    #[test]
+    fn mbc5_rom_banking() {
+        let mut cartridge = Cartridge::new_test(0x1B, 512, 0x04);
+        cartridge.rom[0x0000] = 0xAA;
+        assert_eq!(cartridge.read(0x4000), 1);
+
+        // the bank 0 can be mapped to 4000-7FFF
+        cartridge.write(0x2000, 0x00);
+        assert_eq!(cartridge.curr_rom_bank(), 0);
+        assert_eq!(cartridge.read(0x4000), 0xAA);
+
+        // the 9-bit bank number is split in two registers
+        cartridge.write(0x2000, 0x23);
+        cartridge.write(0x3000, 0x01);
+        assert_eq!(cartridge.curr_rom_bank(), 0x123);
+        assert_eq!(cartridge.read(0x4000), 0x23);
+        cartridge.write(0x2FFF, 0x45);
+        assert_eq!(cartridge.curr_rom_bank(), 0x145);
+        // only the bit 0 of the upper register is used
+        cartridge.write(0x3FFF, 0xFE);
+        assert_eq!(cartridge.curr_rom_bank(), 0x045);
+        assert_eq!(cartridge.read(0x4000), 0x45);
+
+        // the bank number wraps around the ROM size
+        let mut cartridge = Cartridge::new_test(0x19, 4, 0x00);
+        cartridge.write(0x2000, 0x05);
+        cartridge.write(0x3000, 0x01);
+        assert_eq!(cartridge.curr_rom_bank(), 1);
+    }
+
+    #[test]
+    fn mbc5_rumble() {
+        let mut cartridge = Cartridge::new_test(0x1E, 512, 0x04);
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let events2 = events.clone();
+        cartridge.set_rumble_callback(Box::new(move |x| events2.lock().unwrap().push(x)));
+        cartridge.write(0x0000, 0x0A);
+
+        // write to each RAM bank
+        for bank in 0..8 {
+            cartridge.write(0x4000, bank);
+            cartridge.write(0xA000, bank);
+        }
+        assert!(events.lock().unwrap().is_empty());
+
+        // the bit 3 turns the motor on, and is not part of the RAM bank
+        cartridge.write(0x4000, 0x0B);
+        assert!(cartridge.rumble_on());
+        assert_eq!(cartridge.read(0xA000), 3);
+        cartridge.write(0x4000, 0x0C);
+        cartridge.write(0x4000, 0x04);
+        assert!(!cartridge.rumble_on());
+        assert_eq!(cartridge.read(0xA000), 4);
+        assert_eq!(*events.lock().unwrap(), [true, false]);
+
+        // without rumble, the bit 3 selects the RAM bank
+        let mut cartridge = Cartridge::new_test(0x1B, 512, 0x04);
+        cartridge.write(0x0000, 0x0A);
+        cartridge.write(0x4000, 0x0B);
+        cartridge.write(0xA000, 0x0B);
+        cartridge.write(0x4000, 0x03);
+        assert_eq!(cartridge.read(0xA000), 0x00);
+        assert!(!cartridge.rumble_on());
+    }
+
+    /// A cartridge with the given type and header RAM size code, with the RAM enabled.
+    fn ram_cartridge(kind: u8, ram_size: u8) -> Cartridge {
+        let mut cartridge = Cartridge::new_test(kind, 2, ram_size);
+        cartridge.write(0x0000, 0x0A);
+        cartridge
+    }
+
+    #[test]
+    fn cartridge_ram_sizes() {
+        let sizes = [
+            (0x00, 0),
+            (0x01, 0x800),
+            (0x02, 0x2000),
+            (0x03, 0x8000),
+            (0x04, 0x20000),
+            (0x05, 0x10000),
+        ];
+        for &kind in &[0x00, 0x03, 0x13, 0x1B] {
+            for &(code, size) in &sizes {
+                let mut cartridge = ram_cartridge(kind, code);
+                assert_eq!(cartridge.ram.len(), size, "{:02x} {:02x}", kind, code);
+
+                // MBC1 only switches RAM banks in mode 1
+                cartridge.write(0x6000, 0x01);
+                // out of range banks are masked to the available ones, and the RAM is mirrored
+                let banks = if kind == 0x13 { 4 } else { 16 };
+                for bank in 0..banks {
+                    cartridge.write(0x4000, bank);
+                    cartridge.write(0xA000, bank);
+                    cartridge.write(0xBFFF, !bank);
+                    if size == 0 {
+                        assert_eq!(cartridge.read(0xA000), 0xFF);
+                        assert_eq!(cartridge.read(0xBFFF), 0xFF);
+                    } else {
+                        assert_eq!(cartridge.read(0xA000), bank);
+                        assert_eq!(cartridge.read(0xBFFF), !bank);
+                    }
+                }
+                if size == 0x800 {
+                    assert_eq!(cartridge.read(0xA800), cartridge.read(0xA000));
+                }
+            }
+        }
+
+        let mut rom = vec![0; 0x8000];
+        rom[0x149] = 0x06;
+        assert!(Cartridge::new(rom).is_err());
+    }
+
+    #[test]
+    fn cartridge_ram_banks() {
+        for &kind in &[0x03, 0x13, 0x1B] {
+            let mut cartridge = ram_cartridge(kind, 0x03);
+            cartridge.write(0x6000, 0x01);
+            for bank in 0..4 {
+                cartridge.write(0x4000, bank);
+                cartridge.write(0xA123, 0x10 + bank);
+            }
+            for bank in 0..4 {
+                cartridge.write(0x4000, bank);
+                assert_eq!(cartridge.read(0xA123), 0x10 + bank, "{:02x}", kind);
+                assert_eq!(cartridge.ram[bank as usize * 0x2000 + 0x123], 0x10 + bank);
+            }
+        }
+    }
+
+    #[test]
+    fn cartridge_ram_disabled() {
+        for &kind in &[0x03, 0x13, 0x1B] {
+            let mut cartridge = ram_cartridge(kind, 0x02);
+            cartridge.write(0xA000, 0x12);
+            cartridge.write(0x0000, 0x00);
+            assert_eq!(cartridge.read(0xA000), 0xFF);
+            cartridge.write(0xA000, 0x34);
+            cartridge.write(0x0000, 0x0A);
+            assert_eq!(cartridge.read(0xA000), 0x12);
+        }
+    }
+
+    #[test]
+    fn battery_save_lengths() {
+        let mut cartridge = ram_cartridge(0x03, 0x02);
+
+        // a short save is padded with 0xFF
+        assert_eq!(cartridge.load_battery_save(vec![0x11; 0x1000]), Ok(()));
+        assert_eq!(cartridge.ram.len(), 0x2000);
+        assert_eq!(cartridge.read(0xAFFF), 0x11);
+        assert_eq!(cartridge.read(0xB000), 0xFF);
+
+        // a long save is truncated
+        assert!(cartridge.load_battery_save(vec![0x22; 0x2001]).is_err());
+        assert_eq!(cartridge.ram, vec![0x22; 0x2000]);
+
+        // a save for a cartridge without RAM
+        let mut cartridge = ram_cartridge(0x01, 0x00);
+        assert!(cartridge.load_battery_save(vec![0x33; 0x2000]).is_err());
+        assert!(cartridge.ram.is_empty());
+        assert_eq!(cartridge.read(0xA000), 0xFF);
+    }
+
+    #[test]
+    fn battery_dirty_flags() {
+        let mut cartridge = ram_cartridge(0x03, 0x02);
+        assert!(!cartridge.battery_dirty());
+
+        // toggling the RAM without writing to it don't finish a save
+        cartridge.write(0x0000, 0x00);
+        cartridge.write(0x0000, 0x0A);
+        assert!(!cartridge.battery_dirty());
+        assert!(!cartridge.battery_save_finished());
+
+        assert_eq!(cartridge.ram_write_count(), 0);
+        cartridge.write(0xA000, 0x12);
+        assert!(cartridge.battery_dirty());
+        assert!(!cartridge.battery_save_finished());
+        assert_eq!(cartridge.ram_write_count(), 1);
+
+        // disabling the RAM finishes the save
+        cartridge.write(0x0000, 0x00);
+        assert!(cartridge.battery_save_finished());
+
+        // writes while the RAM is disabled are ignored
+        let save = cartridge.take_battery_save();
+        assert_eq!(save[0], 0x12);
+        assert!(!cartridge.battery_dirty());
+        assert!(!cartridge.battery_save_finished());
+        cartridge.write(0xA000, 0x34);
+        cartridge.write(0x0000, 0x0A);
+        cartridge.write(0x0000, 0x00);
+        assert!(!cartridge.battery_dirty());
+        assert_eq!(cartridge.ram_write_count(), 1);
+        assert!(!cartridge.battery_save_finished());
+
+        // a save is only marked as saved if the RAM was not written after it was taken
+        cartridge.write(0x0000, 0x0A);
+        cartridge.write(0xA000, 0x56);
+        let write_count = cartridge.ram_write_count();
+        cartridge.write(0xA000, 0x78);
+        cartridge.mark_battery_saved(write_count);
+        assert!(cartridge.battery_dirty());
+        cartridge.mark_battery_saved(cartridge.ram_write_count());
+        assert!(!cartridge.battery_dirty());
+    }
+
+    #[test]
+    fn camera_banking() {
+        let mut cartridge = Cartridge::new_test(0xFC, 64, 0x04);
+        assert_eq!(cartridge.mapper_kind(), "Pocket Camera");
+        assert_eq!(cartridge.ram.len(), 0x20000);
+
+        for bank in 1..0x40 {
+            cartridge.write(0x2000, bank);
+            assert_eq!(cartridge.curr_rom_bank(), bank as u16);
+            assert_eq!(cartridge.read(0x4000), bank);
+        }
+
+        // RAM writes need the RAM to be enabled, but reads don't
+        cartridge.write(0xA000, 0x12);
+        assert_eq!(cartridge.ram[0], 0x00);
+        cartridge.write(0x0000, 0x0A);
+        for bank in 0..0x10 {
+            cartridge.write(0x4000, bank);
+            cartridge.write(0xA123, bank + 1);
+        }
+        cartridge.write(0x0000, 0x00);
+        for bank in 0..0x10 {
+            cartridge.write(0x4000, bank);
+            assert_eq!(cartridge.read(0xA123), bank + 1);
+            assert_eq!(cartridge.ram[bank as usize * 0x2000 + 0x123], bank + 1);
+        }
+    }
+
+    #[test]
+    fn camera_registers() {
+        let mut cartridge = Cartridge::new_test(0xFC, 64, 0x04);
+        cartridge.write(0x0000, 0x0A);
+        cartridge.write(0xA001, 0x55);
+
+        // the bit 4 of the RAM bank register maps the registers
+        cartridge.write(0x4000, 0x10);
+        assert_eq!(cartridge.read(0xA000), 0x00);
+        cartridge.write(0xA000, 0x06);
+        cartridge.write(0xA001, 0xAA);
+        cartridge.write(0xA035, 0xAA);
+        // only the first register can be read, and it is mirrored every 0x80 bytes
+        assert_eq!(cartridge.read(0xA000), 0x06);
+        assert_eq!(cartridge.read(0xA080), 0x06);
+        assert_eq!(cartridge.read(0xBF80), 0x06);
+        assert_eq!(cartridge.read(0xA001), 0x00);
+        assert_eq!(cartridge.read(0xA035), 0x00);
+
+        // the writes to the registers don't reach the RAM
+        cartridge.write(0x4000, 0x00);
+        assert_eq!(cartridge.read(0xA000), 0x00);
+        assert_eq!(cartridge.read(0xA001), 0x55);
+        assert_eq!(cartridge.read(0xA035), 0x00);
+    }
+
+    #[test]
+    fn camera_capture() {
+        let mut cartridge = Cartridge::new_test(0xFC, 64, 0x04);
+        cartridge.set_camera_callback(Box::new(|sensor| {
+            // black in the left half, gray in the right half
+            for (i, x) in sensor.iter_mut().enumerate() {
+                *x = if i % 128 < 64 { 0x00 } else { 0x90 };
+            }
+        }));
+
+        cartridge.write(0x4000, 0x10);
+        // the same thresholds for all the dithering matrix
+        for i in 0..16 {
+            cartridge.write(0xA006 + i * 3, 0x40);
+            cartridge.write(0xA007 + i * 3, 0x80);
+            cartridge.write(0xA008 + i * 3, 0xC0);
+        }
+        // exposure time of 0x0100
+        cartridge.write(0xA001, 0x80);
+        cartridge.write(0xA002, 0x01);
+        cartridge.write(0xA003, 0x00);
+
+        // the capture is busy until it finishes
+        let start = 1000;
+        let duration = 4 * (32446 + 16 * 0x100);
+        cartridge.write_at(0xA000, 0x03, start);
+        assert_eq!(cartridge.read_at(0xA000, start), 0x03);
+        assert_eq!(cartridge.read_at(0xA000, start + duration - 1), 0x03);
+        assert_eq!(cartridge.read_at(0xA000, start + duration), 0x02);
+
+        // the image is written as tiles to RAM bank 0, at A100-AEFF
+        cartridge.write(0x4000, 0x00);
+        for tile in 0..16 * 14 {
+            let expected = if tile % 16 < 8 {
+                [0xFF, 0xFF]
+            } else {
+                [0xFF, 0x00]
+            };
+            for row in 0..8 {
+                let address = 0xA100 + tile * 16 + row * 2;
+                assert_eq!(
+                    [cartridge.read(address), cartridge.read(address + 1)],
+                    expected,
+                    "{:04x}",
+                    address
+                );
+            }
+        }
+        assert_eq!(cartridge.read(0xA0FF), 0x00);
+        assert_eq!(cartridge.read(0xAF00), 0x00);
+    }
+
+    /// A MBC3+TIMER+RAM+BATTERY cartridge, with its clock zeroed and frozen at the given unix time.
+    fn rtc_cartridge(time: u64) -> Cartridge {
+        let mut cartridge = Cartridge::new_test(0x10, 2, 0x03);
+        let rtc = cartridge.rtc_mut().unwrap();
+        rtc.frozen_time = Some(time);
+        rtc.set_seconds(0);
+        // enable RAM and RTC registers
+        cartridge.write(0x0000, 0x0A);
+        cartridge
+    }
+
+    fn rtc_latch(cartridge: &mut Cartridge) {
+        cartridge.write(0x6000, 0x00);
+        cartridge.write(0x6000, 0x01);
+    }
+
+    /// Read the latched S, M, H, DL and DH registers.
+    fn rtc_read(cartridge: &mut Cartridge) -> [u8; 5] {
+        let mut regs = [0; 5];
+        for (i, x) in regs.iter_mut().enumerate() {
+            cartridge.write(0x4000, 0x08 + i as u8);
+            *x = cartridge.read(0xA000);
+        }
+        regs
+    }
+
+    fn rtc_write(cartridge: &mut Cartridge, register: u8, value: u8) {
+        cartridge.write(0x4000, register);
+        cartridge.write(0xA000, value);
+    }
+
+    #[test]
+    fn rtc_latch_and_advance() {
+        const DAY: u64 = 24 * 60 * 60;
+        let start = 1_600_000_000;
+        let mut cartridge = rtc_cartridge(start);
+        rtc_latch(&mut cartridge);
+        assert_eq!(rtc_read(&mut cartridge), [0, 0, 0, 0, 0]);
+
+        // the registers only change when latched
+        cartridge.rtc_mut().unwrap().frozen_time = Some(start + 90_000);
+        assert_eq!(rtc_read(&mut cartridge), [0, 0, 0, 0, 0]);
+        rtc_latch(&mut cartridge);
+        assert_eq!(rtc_read(&mut cartridge), [0, 0, 1, 1, 0]);
+
+        // a incomplete latch sequence does nothing
+        cartridge.rtc_mut().unwrap().frozen_time = Some(start + 90_061);
+        cartridge.write(0x6000, 0x01);
+        assert_eq!(rtc_read(&mut cartridge), [0, 0, 1, 1, 0]);
+        rtc_latch(&mut cartridge);
+        assert_eq!(rtc_read(&mut cartridge), [1, 1, 1, 1, 0]);
+
+        // the ninth bit of the day counter
+        cartridge.rtc_mut().unwrap().set_seconds(300 * DAY);
+        rtc_latch(&mut cartridge);
+        assert_eq!(rtc_read(&mut cartridge), [0, 0, 0, (300 - 256) as u8, 0x01]);
+
+        // the day counter overflows, and the carry stays set
+        let time = start + 90_061 + 211 * DAY + 90_000;
+        cartridge.rtc_mut().unwrap().frozen_time = Some(time);
+        rtc_latch(&mut cartridge);
+        assert_eq!(rtc_read(&mut cartridge), [0, 0, 1, 0, 0x80]);
+        cartridge.rtc_mut().unwrap().frozen_time = Some(time + DAY);
+        rtc_latch(&mut cartridge);
+        assert_eq!(rtc_read(&mut cartridge), [0, 0, 1, 1, 0x80]);
+
+        // the carry is cleared by writing to DH
+        rtc_write(&mut cartridge, 0x0C, 0x00);
+        rtc_latch(&mut cartridge);
+        assert_eq!(rtc_read(&mut cartridge), [0, 0, 1, 1, 0x00]);
+
+        // while halted, the clock doesn't advance
+        rtc_write(&mut cartridge, 0x0C, 0x40);
+        rtc_write(&mut cartridge, 0x08, 30);
+        cartridge.rtc_mut().unwrap().frozen_time = Some(time + 2 * DAY);
+        rtc_latch(&mut cartridge);
+        assert_eq!(rtc_read(&mut cartridge), [30, 0, 1, 1, 0x40]);
+        rtc_write(&mut cartridge, 0x0C, 0x00);
+        cartridge.rtc_mut().unwrap().frozen_time = Some(time + 2 * DAY + 10);
+        rtc_latch(&mut cartridge);
+        assert_eq!(rtc_read(&mut cartridge), [40, 0, 1, 1, 0x00]);
+
+        // the registers cannot be accessed while the RAM is disabled
+        cartridge.write(0x0000, 0x00);
+        assert_eq!(rtc_read(&mut cartridge), [0xFF; 5]);
+    }
+
+    #[test]
+    fn rtc_battery_save() {
+        let start = 1_600_000_000;
+        let mut cartridge = rtc_cartridge(start);
+        cartridge.rtc_mut().unwrap().set_seconds(3661);
+        rtc_latch(&mut cartridge);
+        cartridge.write(0x4000, 0x00);
+        cartridge.write(0xA000, 0x42);
+
+        let data = cartridge.battery_save();
+        assert_eq!(data.len(), 0x8000 + 48);
+        assert_eq!(data[0], 0x42);
+        assert_eq!(
+            &data[0x8000..0x8000 + 20],
+            &[1, 0, 0, 0, 1, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]
+        );
+        assert_eq!(data[0x8000 + 20..0x8000 + 40], data[0x8000..0x8000 + 20]);
+        assert_eq!(data[0x8000 + 40..], start.to_le_bytes());
+
+        // the clock advanced while the emulator was closed
+        let mut loaded = rtc_cartridge(start + 60);
+        loaded.load_battery_save(data.clone()).unwrap();
+        assert_eq!(loaded.ram, cartridge.ram);
+        assert_eq!(rtc_read(&mut loaded), [1, 1, 1, 0, 0]);
+        rtc_latch(&mut loaded);
+        assert_eq!(rtc_read(&mut loaded), [1, 2, 1, 0, 0]);
+
+        // the 44 bytes variant, with a 32-bit timestamp
+        let mut short = data[..0x8000 + 44].to_vec();
+        short[0x8000 + 40..].copy_from_slice(&(start as u32).to_le_bytes());
+        let mut loaded = rtc_cartridge(start + 60);
+        loaded.load_battery_save(short).unwrap();
+        assert_eq!(loaded.ram.len(), 0x8000);
+        assert_eq!(loaded.rtc().unwrap().seconds(), 3661 + 60);
+
+        // a save without the footer is only RAM
+        let mut loaded = rtc_cartridge(start);
+        loaded.load_battery_save(vec![0x11; 0x8000]).unwrap();
+        assert_eq!(loaded.ram, vec![0x11; 0x8000]);
     }
 }
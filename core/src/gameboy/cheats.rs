@@ -0,0 +1,225 @@
+use std::collections::BTreeMap;
+
+/// The identifier of a cheat added to `Cheats`.
+pub type CheatId = u32;
+
+/// A decoded cheat code.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Cheat {
+    /// A Game Genie code, that replaces the value read from a ROM address. If there is a `compare`
+    /// value, the replacement only happens when the original value is equal to it, which
+    /// disambiguates between ROM banks.
+    GameGenie {
+        address: u16,
+        value: u8,
+        compare: Option<u8>,
+    },
+    /// A GameShark code, that writes a value to a RAM address once per frame. For the cartridge
+    /// RAM, at A000-BFFF, the types 0x80 to 0x9F select the RAM bank `kind - 0x80`, and 0x00 and
+    /// 0x01 the bank currently mapped. The DMG has a single WRAM bank, so the type is ignored for
+    /// the other addresses. See `Cheat::ram_bank`.
+    GameShark { kind: u8, address: u16, value: u8 },
+}
+impl Cheat {
+    /// Parse a Game Genie code, in the format `ABC-DEF` or `ABC-DEF-GHI`, or a GameShark code, in
+    /// the format `TTVVLLHH`. The dashes are optional, and case is ignored.
+    pub fn parse(code: &str) -> Result<Self, String> {
+        let digits: String = code
+            .chars()
+            .filter(|&c| c != '-' && !c.is_whitespace())
+            .collect();
+        if let Some(c) = digits.chars().find(|c| !c.is_ascii_hexdigit()) {
+            return Err(format!("invalid character '{}' in cheat '{}'", c, code));
+        }
+        let nibbles: Vec<u8> = digits
+            .chars()
+            .map(|c| c.to_digit(16).unwrap() as u8)
+            .collect();
+
+        match nibbles.len() {
+            6 | 9 => Self::parse_game_genie(&nibbles, code),
+            8 => Self::parse_game_shark(&nibbles, code),
+            len => Err(format!(
+                "cheat '{}' has {} digits, expected 6 or 9 (Game Genie) or 8 (GameShark)",
+                code, len
+            )),
+        }
+    }
+
+    /// The GameShark code that writes `value` to `address` once per frame, freezing it.
+    pub fn game_shark_code(address: u16, value: u8) -> String {
+        let [low, high] = address.to_le_bytes();
+        format!("01{:02X}{:02X}{:02X}", value, low, high)
+    }
+
+    /// The cartridge RAM bank written by a GameShark code, or `None` if it writes to the bank
+    /// currently mapped, or if it is not a GameShark code.
+    pub fn ram_bank(&self) -> Option<u8> {
+        match *self {
+            Cheat::GameShark { kind, .. } if kind >= 0x80 => Some(kind - 0x80),
+            _ => None,
+        }
+    }
+
+    fn parse_game_genie(n: &[u8], code: &str) -> Result<Self, String> {
+        let value = n[0] << 4 | n[1];
+        let address = ((n[5] ^ 0xF) as u16) << 12 | (n[2] as u16) << 8 | (n[3] as u16) << 4;
+        let address = address | n[4] as u16;
+        if address >= 0x8000 {
+            return Err(format!(
+                "Game Genie code '{}' patches {:04x}, outside of the ROM",
+                code, address
+            ));
+        }
+        let compare = if n.len() == 9 {
+            Some((n[6] << 4 | n[8]).rotate_right(2) ^ 0xBA)
+        } else {
+            None
+        };
+        Ok(Cheat::GameGenie {
+            address,
+            value,
+            compare,
+        })
+    }
+
+    fn parse_game_shark(n: &[u8], code: &str) -> Result<Self, String> {
+        let byte = |i: usize| n[i * 2] << 4 | n[i * 2 + 1];
+        let kind = byte(0);
+        let value = byte(1);
+        let address = u16::from_le_bytes([byte(2), byte(3)]);
+        if !matches!(kind, 0x00 | 0x01 | 0x80..=0x9F) {
+            return Err(format!(
+                "GameShark code '{}' has a unknown type {:02x}",
+                code, kind
+            ));
+        }
+        if !matches!(address, 0xA000..=0xDFFF | 0xFF80..=0xFFFE) {
+            return Err(format!(
+                "GameShark code '{}' writes to {:04x}, outside of the RAM",
+                code, address
+            ));
+        }
+        Ok(Cheat::GameShark {
+            kind,
+            address,
+            value,
+        })
+    }
+}
+
+/// A cheat added to `Cheats`.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct CheatEntry {
+    pub id: CheatId,
+    /// The code as given to `Cheats::add`.
+    pub code: String,
+    pub cheat: Cheat,
+    pub enabled: bool,
+}
+
+/// The active cheat codes.
+///
+/// The Game Genie patches of enabled cheats are kept in a sorted map, that is only checked on ROM
+/// reads when it is not empty.
+#[derive(Default, Debug)]
+pub struct Cheats {
+    list: Vec<CheatEntry>,
+    next_id: CheatId,
+    /// For each patched address, the compare value and the replacement value.
+    rom_patches: BTreeMap<u16, Vec<(Option<u8>, u8)>>,
+}
+impl Cheats {
+    pub fn list(&self) -> &[CheatEntry] {
+        &self.list
+    }
+
+    /// If there is at least one enabled Game Genie code.
+    #[inline]
+    pub fn any_rom_patch(&self) -> bool {
+        !self.rom_patches.is_empty()
+    }
+
+    /// Parse and add a cheat code, enabled.
+    pub fn add(&mut self, code: &str) -> Result<CheatId, String> {
+        let cheat = Cheat::parse(code)?;
+        let id = self.next_id;
+        self.next_id += 1;
+        self.list.push(CheatEntry {
+            id,
+            code: code.trim().to_string(),
+            cheat,
+            enabled: true,
+        });
+        self.update_patches();
+        Ok(id)
+    }
+
+    /// Remove a cheat. Returns false if there is no cheat with the given id.
+    pub fn remove(&mut self, id: CheatId) -> bool {
+        let len = self.list.len();
+        self.list.retain(|x| x.id != id);
+        self.update_patches();
+        self.list.len() != len
+    }
+
+    /// Enable or disable a cheat. Returns false if there is no cheat with the given id.
+    pub fn set_enabled(&mut self, id: CheatId, enabled: bool) -> bool {
+        match self.list.iter_mut().find(|x| x.id == id) {
+            Some(entry) => {
+                entry.enabled = enabled;
+                self.update_patches();
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.list.clear();
+        self.update_patches();
+    }
+
+    fn update_patches(&mut self) {
+        self.rom_patches.clear();
+        for entry in self.list.iter().filter(|x| x.enabled) {
+            if let Cheat::GameGenie {
+                address,
+                value,
+                compare,
+            } = entry.cheat
+            {
+                self.rom_patches
+                    .entry(address)
+                    .or_default()
+                    .push((compare, value));
+            }
+        }
+    }
+
+    /// Apply the Game Genie codes to a value read from the ROM.
+    pub fn patch_rom_read(&self, address: u16, value: u8) -> u8 {
+        let patches = match self.rom_patches.get(&address) {
+            Some(x) => x,
+            None => return value,
+        };
+        patches
+            .iter()
+            .find(|(compare, _)| compare.map_or(true, |x| x == value))
+            .map_or(value, |&(_, new)| new)
+    }
+
+    /// The RAM writes of the enabled GameShark codes, as the cartridge RAM bank, the address and
+    /// the value. See `Cheat::ram_bank`.
+    pub fn ram_writes(&self) -> impl Iterator<Item = (Option<u8>, u16, u8)> + '_ {
+        self.list
+            .iter()
+            .filter(|x| x.enabled)
+            .filter_map(|x| match x.cheat {
+                Cheat::GameShark { address, value, .. } => {
+                    Some((x.cheat.ram_bank(), address, value))
+                }
+                _ => None,
+            })
+    }
+}
@@ -0,0 +1,139 @@
+use std::fmt::Write;
+
+use crate::{consts::LEN, disassembler::disassembly_opcode, gameboy::GameBoy};
+
+/// A executed instruction, recorded by `InstructionHistory`.
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub struct HistoryEntry {
+    /// The clock count before the instruction was executed.
+    pub clock_count: u64,
+    /// The ROM bank of the instruction, or `None` if it is outside the ROM.
+    pub bank: Option<u16>,
+    pub pc: u16,
+    /// The bytes at PC. Only the first `LEN[opcode[0]]` are part of the instruction.
+    pub opcode: [u8; 3],
+    pub a: u8,
+    pub f: u8,
+    pub sp: u16,
+}
+impl HistoryEntry {
+    /// The entry for the instruction at the current PC of `gb`, before it is executed.
+    pub fn new(gb: &GameBoy) -> Self {
+        let pc = gb.cpu.pc;
+        Self {
+            clock_count: gb.clock_count,
+            bank: gb.pc_bank(pc),
+            pc,
+            opcode: [
+                gb.peek(pc),
+                gb.peek(pc.wrapping_add(1)),
+                gb.peek(pc.wrapping_add(2)),
+            ],
+            a: gb.cpu.a,
+            f: gb.cpu.f.0,
+            sp: gb.cpu.sp,
+        }
+    }
+
+    /// Format the entry in a single line, with the instruction disassembled. The labels are
+    /// resolved from the symbols in the trace of `gb`.
+    pub fn fmt(&self, gb: &GameBoy, w: &mut impl Write) -> std::fmt::Result {
+        match self.bank {
+            Some(bank) => write!(w, "{:02x}:{:04x}  ", bank, self.pc)?,
+            None => write!(w, "--:{:04x}  ", self.pc)?,
+        }
+        let len = (LEN[self.opcode[0] as usize] as usize).max(1);
+        let mut text = String::new();
+        let trace = gb.trace.borrow();
+        let label = |x| match trace.symbol_at(gb, x) {
+            Some(name) => name.to_string(),
+            None => format!("${:04x}", x),
+        };
+        disassembly_opcode(self.pc, &self.opcode[..len], label, &mut text)?;
+        write!(
+            w,
+            "{:<20} A:{:02x} F:{:02x} SP:{:04x} (cy: {})",
+            text.trim_end(),
+            self.a,
+            self.f,
+            self.sp,
+            self.clock_count
+        )
+    }
+}
+
+/// A ring buffer of the last executed instructions, recorded by the interpreter when set in
+/// `GameBoy::instruction_history`.
+///
+/// The buffer is allocated once, so recording a instruction don't allocate.
+#[derive(Clone, Debug)]
+pub struct InstructionHistory {
+    entries: Box<[HistoryEntry]>,
+    /// The index where the next entry will be written.
+    next: usize,
+    len: usize,
+}
+impl InstructionHistory {
+    /// Create a history that keeps the last `capacity` instructions. The capacity is at least 1.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            entries: vec![HistoryEntry::default(); capacity.max(1)].into_boxed_slice(),
+            next: 0,
+            len: 0,
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn clear(&mut self) {
+        self.next = 0;
+        self.len = 0;
+    }
+
+    /// Record a instruction, overwriting the oldest one if the history is full.
+    pub fn push(&mut self, entry: HistoryEntry) {
+        self.entries[self.next] = entry;
+        self.next += 1;
+        if self.next == self.entries.len() {
+            self.next = 0;
+        }
+        if self.len < self.entries.len() {
+            self.len += 1;
+        }
+    }
+
+    /// The `i`-th most recent instruction, where 0 is the last executed one.
+    pub fn get(&self, i: usize) -> Option<&HistoryEntry> {
+        if i >= self.len {
+            return None;
+        }
+        let capacity = self.entries.len();
+        Some(&self.entries[(self.next + capacity - 1 - i) % capacity])
+    }
+
+    /// Iterate over the recorded instructions, from the most recent to the oldest.
+    pub fn iter(&self) -> impl Iterator<Item = &HistoryEntry> + '_ {
+        (0..self.len).map(move |i| self.get(i).unwrap())
+    }
+
+    /// Write the recorded instructions, one per line, from the oldest to the most recent.
+    pub fn dump(&self, gb: &GameBoy, w: &mut impl std::io::Write) -> std::io::Result<()> {
+        let mut line = String::new();
+        for i in (0..self.len).rev() {
+            line.clear();
+            let _ = self.get(i).unwrap().fmt(gb, &mut line);
+            writeln!(w, "{}", line)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,133 @@
+use std::{collections::VecDeque, fmt::Write};
+
+use crate::gameboy::{cpu::ImeState, GameBoy};
+
+/// The name of each interrupt, by its bit in IE and IF.
+pub const INTERRUPT_NAMES: [&str; 5] = ["V-Blank", "STAT", "Timer", "Serial", "Joypad"];
+
+/// What happened in a `InterruptEvent`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum InterruptEventKind {
+    /// A EI was executed with IME disabled. IME will only be enabled after the next instruction.
+    EiScheduled,
+    /// The delay of a EI ended, and IME was enabled, before executing the instruction at `pc`.
+    ImeEnabled,
+    /// A DI was executed.
+    Di,
+    /// A RETI was executed, enabling IME immediately.
+    Reti,
+    /// A interrupt was dispatched. `interrupt` is the bit of the interrupt in IE and IF, or `None`
+    /// if the dispatch was canceled by the push of PC overwriting IE, jumping to 0x0000 instead.
+    Dispatch { interrupt: Option<u8>, handler: u16 },
+}
+
+/// A change to the interrupt state, recorded by `InterruptLog`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct InterruptEvent {
+    /// The clock count when the event happened.
+    pub clock_count: u64,
+    pub kind: InterruptEventKind,
+    /// The address of the instruction that caused the event, or, for a dispatch, the address that
+    /// was interrupted (the return address of the handler).
+    pub pc: u16,
+    /// The value of IME before the event.
+    pub ime: ImeState,
+    /// The value of IE before the event.
+    pub interrupt_enabled: u8,
+    /// The value of IF before the event.
+    pub interrupt_flag: u8,
+}
+impl InterruptEvent {
+    /// The event at the current state of `gb`.
+    pub fn new(gb: &GameBoy, kind: InterruptEventKind, pc: u16) -> Self {
+        Self {
+            clock_count: gb.clock_count,
+            kind,
+            pc,
+            ime: gb.cpu.ime,
+            interrupt_enabled: gb.interrupt_enabled,
+            interrupt_flag: gb.interrupt_flag,
+        }
+    }
+
+    /// Format the event in a single line.
+    pub fn fmt(&self, w: &mut impl Write) -> std::fmt::Result {
+        match self.kind {
+            InterruptEventKind::EiScheduled => write!(w, "{:<24}", "EI (IME delayed)")?,
+            InterruptEventKind::ImeEnabled => write!(w, "{:<24}", "IME enabled")?,
+            InterruptEventKind::Di => write!(w, "{:<24}", "DI")?,
+            InterruptEventKind::Reti => write!(w, "{:<24}", "RETI")?,
+            InterruptEventKind::Dispatch {
+                interrupt: Some(interrupt),
+                handler,
+            } => write!(
+                w,
+                "{:<24}",
+                format!("{} -> {:04x}", INTERRUPT_NAMES[interrupt as usize], handler)
+            )?,
+            InterruptEventKind::Dispatch {
+                interrupt: None,
+                handler,
+            } => write!(w, "{:<24}", format!("canceled -> {:04x}", handler))?,
+        }
+        let ime = match self.ime {
+            ImeState::Disabled => "0",
+            ImeState::Enabled => "1",
+            ImeState::ToBeEnable => "EI",
+        };
+        write!(
+            w,
+            " PC:{:04x} IME:{:<2} IE:{:02x} IF:{:02x} (cy: {})",
+            self.pc, ime, self.interrupt_enabled, self.interrupt_flag, self.clock_count
+        )
+    }
+}
+
+/// A ring buffer of the last interrupt events, recorded by the interpreter when set in
+/// `GameBoy::interrupt_log`.
+///
+/// The buffer is allocated once, so recording a event don't allocate.
+#[derive(Clone, Debug)]
+pub struct InterruptLog {
+    events: VecDeque<InterruptEvent>,
+    capacity: usize,
+}
+impl InterruptLog {
+    /// Create a log that keeps the last `capacity` events. The capacity is at least 1.
+    pub fn new(capacity: usize) -> Self {
+        let capacity = capacity.max(1);
+        Self {
+            events: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.events.clear();
+    }
+
+    /// Record a event, overwriting the oldest one if the log is full.
+    pub fn push(&mut self, event: InterruptEvent) {
+        if self.events.len() == self.capacity {
+            self.events.pop_front();
+        }
+        self.events.push_back(event);
+    }
+
+    /// Iterate over the recorded events, from the oldest to the most recent.
+    pub fn recent(&self) -> impl DoubleEndedIterator<Item = &InterruptEvent> + '_ {
+        self.events.iter()
+    }
+}
@@ -3,6 +3,100 @@ use crate::{
     save_state::{LoadStateError, SaveState},
 };
 
+/// The colors of the 4 shades of gray of the DMG, from the lightest to the darkest.
+pub const DMG_COLORS: [[u8; 3]; 4] = [[255, 255, 255], [170, 170, 170], [85, 85, 85], [0, 0, 0]];
+
+/// The color used to outline the part of a tile map shown on the screen, in `Ppu::render_background`
+/// and `Ppu::render_window`.
+pub const OUTLINE_COLOR: [u8; 3] = [255, 0, 0];
+
+/// One of the two 32x32 tile maps in the VRAM, used by the background and the window.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum TileMap {
+    /// The map at 0x9800-0x9BFF.
+    Map9800,
+    /// The map at 0x9C00-0x9FFF.
+    Map9C00,
+}
+impl TileMap {
+    /// The map used by the background, selected by the bit 3 of LCDC.
+    pub fn background(lcdc: u8) -> Self {
+        if lcdc & 0x08 != 0 {
+            Self::Map9C00
+        } else {
+            Self::Map9800
+        }
+    }
+
+    /// The map used by the window, selected by the bit 6 of LCDC.
+    pub fn window(lcdc: u8) -> Self {
+        if lcdc & 0x40 != 0 {
+            Self::Map9C00
+        } else {
+            Self::Map9800
+        }
+    }
+
+    /// The offset of the map in the VRAM.
+    fn vram_offset(self) -> usize {
+        match self {
+            Self::Map9800 => 0x1800,
+            Self::Map9C00 => 0x1C00,
+        }
+    }
+}
+
+/// How a 15-bit CGB color is converted to a 24-bit color.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum ColorCorrection {
+    /// Each 5-bit component is scaled linearly to 8 bits.
+    Raw,
+    /// Mix the components and darken them, to approximate the less saturated colors of the CGB
+    /// LCD. Uses the same matrix as higan.
+    Lcd,
+}
+impl Default for ColorCorrection {
+    fn default() -> Self {
+        Self::Raw
+    }
+}
+impl ColorCorrection {
+    /// The colors of the four DMG shades, `DMG_COLORS`, with this correction applied. With `Lcd`,
+    /// the shades are first truncated to RGB555 colors and then corrected, like the grays of a DMG
+    /// game on the CGB.
+    pub fn dmg_colors(self) -> [[u8; 3]; 4] {
+        DMG_COLORS.map(|[r, g, b]| match self {
+            ColorCorrection::Raw => [r, g, b],
+            ColorCorrection::Lcd => {
+                let color = (r >> 3) as u16 | ((g >> 3) as u16) << 5 | ((b >> 3) as u16) << 10;
+                rgb555_to_rgb888(color, self)
+            }
+        })
+    }
+}
+
+/// Convert a CGB color, in the RGB555 format of the palette memory (red in the lowest bits), to
+/// RGB888.
+pub fn rgb555_to_rgb888(color: u16, correction: ColorCorrection) -> [u8; 3] {
+    let r = (color & 0x1F) as u32;
+    let g = ((color >> 5) & 0x1F) as u32;
+    let b = ((color >> 10) & 0x1F) as u32;
+    match correction {
+        ColorCorrection::Raw => {
+            let scale = |x: u32| ((x << 3) | (x >> 2)) as u8;
+            [scale(r), scale(g), scale(b)]
+        }
+        ColorCorrection::Lcd => {
+            let mix = |x: u32| (x.min(960) >> 2) as u8;
+            [
+                mix(r * 26 + g * 4 + b * 2),
+                mix(g * 24 + b * 8),
+                mix(r * 6 + g * 4 + b * 22),
+            ]
+        }
+    }
+}
+
 #[derive(PartialEq, Eq, Default, Clone, Debug)]
 pub struct PixelFifo {
     queue: [u8; 16],
@@ -146,6 +240,62 @@ impl SaveState for Sprite {
     }
 }
 
+/// The maximum number of sprites drawn in a scanline. The PPU selects the first ones in the OAM
+/// that are on the line, and ignores the others.
+pub const MAX_SPRITES_PER_LINE: usize = 10;
+
+/// If a sprite at the vertical position `sy` (as in the OAM, so plus 16), with the given height,
+/// covers the scanline `ly`.
+fn sprite_on_line(sy: u8, ly: u8, height: u8) -> bool {
+    let line = ly as u16 + 16;
+    line >= sy as u16 && line < sy as u16 + height as u16
+}
+
+/// A entry of the Object Attribute Memory, with its attributes decoded. See `GameBoy::sprites`.
+#[derive(PartialEq, Eq, Default, Clone, Copy, Debug)]
+pub struct SpriteEntry {
+    /// The vertical position of the sprite, plus 16.
+    pub y: u8,
+    /// The horizontal position of the sprite, plus 8.
+    pub x: u8,
+    /// The index of the tile. The lowest bit is ignored by 8x16 sprites.
+    pub tile: u8,
+    /// The background and the window colors 1-3 are drawn over the sprite (bit 7).
+    pub bg_priority: bool,
+    /// The sprite is flipped vertically (bit 6).
+    pub y_flip: bool,
+    /// The sprite is flipped horizontally (bit 5).
+    pub x_flip: bool,
+    /// The palette used in the DMG: 0 for OBP0, and 1 for OBP1 (bit 4).
+    pub palette: u8,
+    /// The VRAM bank of the tile, only used by the CGB (bit 3).
+    pub cgb_bank: u8,
+    /// The palette used in the CGB, from 0 to 7 (bits 0-2).
+    pub cgb_palette: u8,
+}
+impl SpriteEntry {
+    /// Decode the 4 bytes of a OAM entry: Y, X, the tile index, and the attributes.
+    pub fn from_bytes(bytes: [u8; 4]) -> Self {
+        let [y, x, tile, flags] = bytes;
+        Self {
+            y,
+            x,
+            tile,
+            bg_priority: flags & 0x80 != 0,
+            y_flip: flags & 0x40 != 0,
+            x_flip: flags & 0x20 != 0,
+            palette: (flags >> 4) & 1,
+            cgb_bank: (flags >> 3) & 1,
+            cgb_palette: flags & 0x07,
+        }
+    }
+
+    /// If the sprite covers the scanline `ly`, for sprites with the given height, 8 or 16.
+    pub fn is_on_line(&self, ly: u8, height: u8) -> bool {
+        sprite_on_line(self.y, ly, height)
+    }
+}
+
 #[derive(PartialEq, Eq, Clone, Copy)]
 enum Mode {
     HBlank = 0,
@@ -177,6 +327,11 @@ impl SaveState for Mode {
     }
 }
 
+/// The Pixel Processing Unit.
+///
+/// The rendering is emulated with the background and sprite pixel FIFOs and the pixel fetcher,
+/// advanced one cycle at a time during mode 3, so its duration depends on the scroll, the window
+/// and the sprites in the line, and mid-scanline register writes take effect at the right pixel.
 #[derive(PartialEq, Eq, Clone)]
 pub struct Ppu {
     /// 8000-9FFF: Video RAM
@@ -199,6 +354,8 @@ pub struct Ppu {
     /// The current screen been render.
     /// Each pixel is a shade of gray, from 0 to 3
     pub screen: [u8; 144 * 160],
+    /// The color index of each pixel of the screen, before the palette is applied.
+    pub(crate) color_ids: [u8; 144 * 160],
     /// sprites that will be rendered in the next mode 3 scanline
     pub sprite_buffer: [Sprite; 10],
     /// the length of the `sprite_buffer`
@@ -248,6 +405,10 @@ pub struct Ppu {
 
     /// Next clock cycle where the PPU will be updated
     pub next_clock_count: u64,
+    /// If the STAT register or signal may change in the next update, because a register was
+    /// written or the state changed since the last `update_stat`. This is not saved, because it is
+    /// derived from the rest of the state.
+    stat_outdated: bool,
     /// The clock count in which the current scanline has started.
     pub line_start_clock_count: u64,
 
@@ -278,6 +439,10 @@ pub struct Ppu {
     /// (represented by positives between 241 and 255) are use for detecting sprites that starts
     /// to the left of the screen, and for discarding pixels for scrolling.
     scanline_x: u8,
+
+    /// How the colors are converted by `screen_rgb`. This is a setting of the emulator, so it is
+    /// not saved, and is kept by `GameBoy::reset`.
+    pub color_correction: ColorCorrection,
 }
 
 impl std::fmt::Debug for Ppu {
@@ -286,6 +451,7 @@ impl std::fmt::Debug for Ppu {
             .field("vram", &"[...]")
             .field("oam", &"[...]")
             .field("screen", &"[...]")
+            .field("color_ids", &"[...]")
             .field("dma_started", &self.dma_started)
             .field("dma_running", &self.dma_running)
             .field("dma_block_oam", &self.dma_block_oam)
@@ -313,6 +479,7 @@ impl std::fmt::Debug for Ppu {
             .field("ly_compare_signal", &self.ly_compare_signal)
             .field("stat_mode_for_interrupt", &self.stat_mode_for_interrupt)
             .field("next_clock_count", &self.next_clock_count)
+            .field("stat_outdated", &self.stat_outdated)
             .field("line_start_clock_count", &self.line_start_clock_count)
             .field("background_fifo", &self.background_fifo)
             .field("sprite_fifo", &self.sprite_fifo)
@@ -329,6 +496,7 @@ impl std::fmt::Debug for Ppu {
             .field("sprite_at_0_penalty", &self.sprite_at_0_penalty)
             .field("screen_x", &self.screen_x)
             .field("scanline_x", &self.scanline_x)
+            .field("color_correction", &self.color_correction)
             .finish()
     }
 }
@@ -410,6 +578,7 @@ impl Default for Ppu {
             vram_read_block: false,
             vram_write_block: false,
             screen: [0; 144 * 160],
+            color_ids: [0; 144 * 160],
             sprite_buffer: Default::default(),
             sprite_buffer_len: Default::default(),
             wyc: Default::default(),
@@ -430,6 +599,7 @@ impl Default for Ppu {
             ly_compare_signal: false,
             stat_mode_for_interrupt: 0xff,
             next_clock_count: 0,
+            stat_outdated: true,
             line_start_clock_count: 0,
             background_fifo: Default::default(),
             sprite_fifo: Default::default(),
@@ -446,6 +616,7 @@ impl Default for Ppu {
             sprite_at_0_penalty: 0,
             screen_x: 0,
             scanline_x: 0,
+            color_correction: ColorCorrection::default(),
         }
     }
 }
@@ -478,6 +649,7 @@ impl Ppu {
                 screen.load_state(&mut ppu_state).unwrap();
                 screen
             },
+            color_ids: [0; 144 * 160],
             sprite_buffer: [Sprite::default(); 10],
             sprite_buffer_len: 0,
             wyc: 0,
@@ -495,6 +667,7 @@ impl Ppu {
             state: 23,
             ly_for_compare: 0,
             next_clock_count: 23_440_377,
+            stat_outdated: true,
             line_start_clock_count: 23_435_361,
 
             background_fifo: PixelFifo::default(),
@@ -520,14 +693,86 @@ impl Ppu {
 
             screen_x: 0xa0,
             scanline_x: 0x00,
+            color_correction: self.color_correction,
         }
     }
+    /// The color index, from 0 to 3, of each pixel of the screen, before the palettes are applied.
+    /// The pixels are in row-major order, starting at the top-left corner.
+    ///
+    /// This is not included in save states older than version 5.
+    pub fn screen_color_ids(&self) -> &[u8; 144 * 160] {
+        &self.color_ids
+    }
+
+    /// Decode the tile at the given index, from 0 to 0x17F, in the tile data at 0x8000-0x97FF. See
+    /// `decode_tile`.
+    pub fn tile_data(&self, index: usize) -> [[u8; 8]; 8] {
+        let i = index * 0x10;
+        let mut data = [0; 0x10];
+        data.copy_from_slice(&self.vram[i..i + 0x10]);
+        decode_tile(&data)
+    }
+
+    /// The tile index of each tile in the given map, indexed by `[y][x]`. See `map_tile_index` for
+    /// the tile that each index refers to.
+    pub fn tile_map(&self, which: TileMap) -> [[u8; 32]; 32] {
+        let offset = which.vram_offset();
+        let mut map = [[0; 32]; 32];
+        for (y, row) in map.iter_mut().enumerate() {
+            row.copy_from_slice(&self.vram[offset + y * 32..offset + (y + 1) * 32]);
+        }
+        map
+    }
+
+    /// Render the 256x256 pixels of the given map, with the BGP palette and the tile data selected
+    /// by LCDC, in row-major order starting at the top-left corner.
+    pub fn render_tile_map(&self, which: TileMap) -> Vec<[u8; 3]> {
+        let mut pixels = vec![DMG_COLORS[0]; 256 * 256];
+        draw_tile_map(self, which, &mut |x, y, c| {
+            pixels[(x + y * 256) as usize] = DMG_COLORS[c as usize];
+        });
+        pixels
+    }
+
+    /// Render the background map selected by LCDC, like `render_tile_map`, with the part shown on
+    /// the screen at the current scroll outlined by `OUTLINE_COLOR`. The outline wraps around the
+    /// edges of the map, like the background.
+    pub fn render_background(&self) -> Vec<[u8; 3]> {
+        let mut pixels = self.render_tile_map(TileMap::background(self.lcdc));
+        outline(&mut pixels, [self.scx, self.scy], [160, 144]);
+        pixels
+    }
+
+    /// Render the window map selected by LCDC, like `render_tile_map`, with the part that is shown
+    /// on the screen at the current window position outlined by `OUTLINE_COLOR`. Nothing is
+    /// outlined if the window is out of the screen.
+    pub fn render_window(&self) -> Vec<[u8; 3]> {
+        let mut pixels = self.render_tile_map(TileMap::window(self.lcdc));
+        if self.wx <= 166 && self.wy <= 143 {
+            // with WX below 7, the window starts to the left of the screen
+            let x = 7u8.saturating_sub(self.wx);
+            let width = (167 - self.wx.max(7)).min(160);
+            outline(&mut pixels, [x, 0], [width, 144 - self.wy]);
+        }
+        pixels
+    }
+
+    /// The color of each pixel of the screen, with the DMG palette applied, in row-major order
+    /// starting at the top-left corner.
+    ///
+    /// The shades are converted by `color_correction`, see `ColorCorrection::dmg_colors`.
+    pub fn screen_rgb(&self) -> Vec<[u8; 3]> {
+        let colors = self.color_correction.dmg_colors();
+        self.screen.iter().map(|&x| colors[x as usize]).collect()
+    }
+
     pub fn write(gb: &mut GameBoy, address: u8, value: u8) {
         // for now, ppu update on read or write,should never trigger a interrupt.
         let (v, s) = Self::update(gb);
         debug_assert!(!v && !s);
 
         let this = &mut *gb.ppu.borrow_mut();
+        this.stat_outdated = true;
         match address {
             0x40 => {
                 if value & 0x80 != this.lcdc & 0x80 {
@@ -538,6 +783,9 @@ impl Ppu {
                         // set to mode 0
                         this.stat &= !0b11;
                         this.state = 0;
+                        // the screen is blank (white) while the LCD is off
+                        this.screen = [0; 144 * 160];
+                        this.color_ids = [0; 144 * 160];
                     } else {
                         // enable ppu
                         debug_assert_eq!(this.ly, 0);
@@ -560,6 +808,16 @@ impl Ppu {
             0x4B => this.wx = value,
             _ => unreachable!(),
         }
+
+        // Writing LYC, or the STAT interrupt sources, re-evaluates the LY==LYC coincidence and the
+        // STAT interrupt line right away, instead of in the next update.
+        if matches!(address, 0x41 | 0x45) && this.lcdc & 0x80 != 0 {
+            let mut stat_interrupt = false;
+            this.update_stat(&mut stat_interrupt);
+            if stat_interrupt {
+                gb.interrupt_flag |= 1 << 1;
+            }
+        }
     }
 
     pub fn read(gb: &GameBoy, address: u8) -> u8 {
@@ -601,7 +859,7 @@ impl Ppu {
             let t = data[2];
             let flags = data[3];
 
-            if self.ly as u16 + 16 >= sy as u16 && self.ly as u16 + 16 < sy as u16 + sprite_height {
+            if sprite_on_line(sy, self.ly, sprite_height) {
                 self.sprite_buffer[self.sprite_buffer_len as usize] = Sprite {
                     sy,
                     sx,
@@ -610,7 +868,7 @@ impl Ppu {
                 };
                 self.sprite_buffer_len += 1;
             }
-            if self.sprite_buffer_len == 10 {
+            if self.sprite_buffer_len as usize == MAX_SPRITES_PER_LINE {
                 break;
             }
         }
@@ -620,36 +878,63 @@ impl Ppu {
         self.sprite_buffer[0..self.sprite_buffer_len as usize].sort_by_key(|x| !x.sx);
     }
 
+    /// The start address of the memory being copied by the OAM DMA.
+    fn dma_source(gb: &GameBoy) -> u16 {
+        let mut value = gb.dma;
+        if value >= 0xFE {
+            value -= 0x20;
+        }
+        (value as u16) << 8
+    }
+
+    /// Read a byte of the memory being copied by the OAM DMA.
+    fn dma_read(gb: &GameBoy, ppu: &Ppu, address: u16) -> u8 {
+        // avoid borrowing the ppu twice
+        match address {
+            0x8000..=0x9FFF => ppu.vram[address as usize - 0x8000],
+            _ => gb.read_memory(address),
+        }
+    }
+
     fn update_dma(gb: &GameBoy, ppu: &mut Ppu, clock_count: u64) {
         if ppu.dma_running {
             let elapsed = clock_count.wrapping_sub(ppu.dma_started);
             if elapsed >= 8 {
                 ppu.dma_block_oam = true;
             }
+
+            // After a 8 cycles delay, one byte is copied every machine cycle. The CPU cannot write
+            // to the source while the DMA is running, so copying the same byte again is harmless.
+            let copied = (elapsed.saturating_sub(8) / 4).min(160) as u16;
+            let start = Self::dma_source(gb);
+            for i in 0..copied {
+                ppu.oam[i as usize] = Self::dma_read(gb, ppu, start + i);
+            }
+
             // 8 cycles delay + 160 machine cycles
             if elapsed >= 8 + 160 * 4 {
                 // Finish running
                 ppu.dma_block_oam = false;
                 ppu.dma_running = false;
-
-                // copy memory
-                let mut value = gb.dma;
-                if value >= 0xFE {
-                    value -= 0x20;
-                }
-                let start = (value as u16) << 8;
-                for (i, j) in (0x00..=0x9F).zip(start..=start + 0x9F) {
-                    // avoid borrowing the ppu twice
-                    let value = match j {
-                        0x8000..=0x9FFF => ppu.vram[j as usize - 0x8000],
-                        j => gb.read(j),
-                    };
-                    ppu.oam[i] = value;
-                }
             }
         }
     }
 
+    /// If the OAM DMA is transferring, return the byte in the bus. While transferring, the CPU
+    /// reads this byte from any address outside of the OAM, IO registers and HRAM.
+    pub fn dma_conflict(gb: &GameBoy) -> Option<u8> {
+        let ppu = gb.ppu.borrow();
+        if !ppu.dma_running {
+            return None;
+        }
+        let elapsed = gb.clock_count.wrapping_sub(ppu.dma_started);
+        if !(8..8 + 160 * 4).contains(&elapsed) {
+            return None;
+        }
+        let i = ((elapsed - 8) / 4) as u16;
+        Some(Self::dma_read(gb, &ppu, Self::dma_source(gb) + i))
+    }
+
     pub fn start_dma(gb: &mut GameBoy, value: u8) {
         Self::update(gb);
         gb.dma = value;
@@ -665,6 +950,24 @@ impl Ppu {
         ppu.dma_running = true;
     }
 
+    /// Read a register without updating the ppu first, so STAT and LY may be outdated.
+    pub fn peek(&self, address: u8) -> u8 {
+        match address {
+            0x40 => self.lcdc,
+            0x41 => self.stat | 0x80,
+            0x42 => self.scy,
+            0x43 => self.scx,
+            0x44 => self.ly,
+            0x45 => self.lyc,
+            0x47 => self.bgp,
+            0x48 => self.obp0,
+            0x49 => self.obp1,
+            0x4A => self.wy,
+            0x4B => self.wx,
+            _ => unreachable!(),
+        }
+    }
+
     pub fn read_oam(gb: &GameBoy, address: u16) -> u8 {
         Self::update(gb);
         let ppu = &mut *gb.ppu.borrow_mut();
@@ -675,6 +978,18 @@ impl Ppu {
         }
     }
 
+    /// Read from the not usable region, 0xFEA0 to 0xFEFF, after the OAM. On the DMG it reads as
+    /// 0x00, except while the OAM is blocked, by the PPU or the OAM DMA, when it reads as 0xFF.
+    pub fn read_unusable(gb: &GameBoy) -> u8 {
+        Self::update(gb);
+        let ppu = &*gb.ppu.borrow();
+        if ppu.dma_block_oam || ppu.oam_read_block {
+            0xff
+        } else {
+            0x00
+        }
+    }
+
     pub fn write_oam(gb: &mut GameBoy, address: u16, value: u8) {
         Self::update(gb);
         let ppu = &mut *gb.ppu.borrow_mut();
@@ -710,6 +1025,7 @@ impl Ppu {
             // ppu is disabled
             ppu.next_clock_count = gb.clock_count;
             Self::update_dma(gb, ppu, gb.clock_count);
+            ppu.check_stat_outdated();
             return (false, false);
         }
 
@@ -1210,6 +1526,7 @@ impl Ppu {
             }
         }
         ppu.state = state;
+        ppu.check_stat_outdated();
         (vblank_interrupt, stat_interrupt)
     }
 
@@ -1217,7 +1534,8 @@ impl Ppu {
         self.stat = (self.stat & !0b11) | mode;
     }
 
-    fn update_stat(&mut self, stat_interrupt: &mut bool) {
+    /// Compute the values of `stat`, `ly_compare_signal` and `stat_signal` after a `update_stat`.
+    fn next_stat(&self) -> (u8, bool, bool) {
         let stat_mode = self.stat_mode_for_interrupt;
         let mut stat_line = false;
 
@@ -1234,26 +1552,50 @@ impl Ppu {
         }
 
         // LY==LYC
-        self.stat = self.stat & !0x04;
+        let mut stat = self.stat & !0x04;
+        let mut ly_compare_signal = self.ly_compare_signal;
         if self.ly_for_compare == self.lyc {
-            self.ly_compare_signal = true;
+            ly_compare_signal = true;
             // STAT Coincident Flag
-            self.stat |= 0x04;
+            stat |= 0x04;
         } else {
             if self.ly_for_compare != 0xff {
-                self.ly_compare_signal = false;
+                ly_compare_signal = false;
             }
         }
         // LY == LYC STAT Interrupt
-        stat_line |= (self.stat & (1 << 6) != 0) && self.ly_compare_signal;
+        stat_line |= (stat & (1 << 6) != 0) && ly_compare_signal;
+
+        (stat, ly_compare_signal, stat_line)
+    }
+
+    fn update_stat(&mut self, stat_interrupt: &mut bool) {
+        let (stat, ly_compare_signal, stat_line) = self.next_stat();
 
         // on rising edge
         if !self.stat_signal && stat_line {
             *stat_interrupt = true;
         }
 
+        self.stat = stat;
+        self.ly_compare_signal = ly_compare_signal;
         self.stat_signal = stat_line;
     }
+
+    /// Recompute `stat_outdated`. Must be called after loading the state of the ppu.
+    pub fn check_stat_outdated(&mut self) {
+        self.stat_outdated =
+            self.next_stat() != (self.stat, self.ly_compare_signal, self.stat_signal);
+    }
+
+    /// If a call to `update` at the given clock count would change the state of the ppu. If not,
+    /// it can be skipped.
+    pub fn needs_update(&self, clock_count: u64) -> bool {
+        self.lcdc & 0x80 == 0
+            || self.dma_running
+            || self.stat_outdated
+            || self.next_clock_count < clock_count
+    }
 }
 
 fn tick_pixel_fetcher(ppu: &mut Ppu, ly: u8) {
@@ -1368,6 +1710,7 @@ fn output_pixel(ppu: &mut Ppu) {
         // background color, with pallete applied
         let palette = ppu.bgp;
         let mut color = (palette >> (bcolor * 2)) & 0b11;
+        let mut color_id = bcolor;
 
         if let Some(sprite_pixel) = sprite_pixel {
             let scolor = sprite_pixel & 0b11;
@@ -1379,15 +1722,32 @@ fn output_pixel(ppu: &mut Ppu) {
                 let palette = (sprite_pixel >> 4) & 0x1;
                 let palette = [ppu.obp0, ppu.obp1][palette as usize];
                 color = (palette >> (scolor * 2)) & 0b11;
+                color_id = scolor;
             }
         }
         debug_assert!(color < 4);
         ppu.screen[i] = color;
+        ppu.color_ids[i] = color_id;
         ppu.screen_x += 1;
         ppu.scanline_x += 1;
     }
 }
 
+/// Decode a tile in the 2bpp planar format, where each row is formed by two bytes, the first one
+/// with the low bit of the color index of each pixel, and the second with the high bit. Returns the
+/// color index, from 0 to 3, of each pixel, indexed by `[y][x]`.
+pub fn decode_tile(data: &[u8; 16]) -> [[u8; 8]; 8] {
+    let mut tile = [[0; 8]; 8];
+    for (y, row) in tile.iter_mut().enumerate() {
+        let a = data[y * 2];
+        let b = data[y * 2 + 1];
+        for (x, pixel) in row.iter_mut().enumerate() {
+            *pixel = (((b >> (7 - x)) << 1) & 0b10) | ((a >> (7 - x)) & 0b1);
+        }
+    }
+    tile
+}
+
 pub fn draw_tile(
     ppu: &Ppu,
     draw_pixel: &mut impl FnMut(i32, i32, u8),
@@ -1397,12 +1757,10 @@ pub fn draw_tile(
     palette: u8,
     alpha: bool,
 ) {
-    let i = index * 0x10;
+    let tile = ppu.tile_data(index);
     for y in 0..8 {
-        let a = ppu.vram[i + y as usize * 2];
-        let b = ppu.vram[i + y as usize * 2 + 1];
         for x in 0..8 {
-            let color = (((b >> (7 - x)) << 1) & 0b10) | ((a >> (7 - x)) & 0b1);
+            let color = tile[y as usize][x as usize];
             if alpha && color == 0 {
                 continue;
             }
@@ -1421,43 +1779,53 @@ pub fn draw_tiles(ppu: &Ppu, draw_pixel: &mut impl FnMut(i32, i32, u8), palette:
     }
 }
 
-pub fn draw_background(ppu: &Ppu, draw_pixel: &mut impl FnMut(i32, i32, u8)) {
-    for i in 0..(32 * 32) {
-        let tx = 8 * (i % 32);
-        let ty = 8 * (i / 32);
-        // BG Tile Map Select
-        let address = if ppu.lcdc & 0x08 != 0 { 0x9C00 } else { 0x9800 };
-        let mut tile = ppu.vram[address - 0x8000 + i as usize] as usize;
-
-        // if is using 8800 method
-        if ppu.lcdc & 0x10 == 0 {
-            tile += 0x100;
-            if tile >= 0x180 {
-                tile -= 0x100;
-            }
+/// The index of the tile, from 0 to 0x17F, that a tile index in a tile map refers to, in the tile
+/// data addressing selected by the bit 4 of LCDC.
+pub fn map_tile_index(lcdc: u8, tile: u8) -> usize {
+    let mut tile = tile as usize;
+    // if is using 8800 method
+    if lcdc & 0x10 == 0 {
+        tile += 0x100;
+        if tile >= 0x180 {
+            tile -= 0x100;
         }
+    }
+    tile
+}
 
-        draw_tile(ppu, draw_pixel, tx, ty, tile, ppu.bgp, false);
+pub fn draw_tile_map(ppu: &Ppu, which: TileMap, draw_pixel: &mut impl FnMut(i32, i32, u8)) {
+    for (y, row) in ppu.tile_map(which).iter().enumerate() {
+        for (x, &tile) in row.iter().enumerate() {
+            let tile = map_tile_index(ppu.lcdc, tile);
+            let [tx, ty] = [8 * x as i32, 8 * y as i32];
+            draw_tile(ppu, draw_pixel, tx, ty, tile, ppu.bgp, false);
+        }
     }
 }
 
+pub fn draw_background(ppu: &Ppu, draw_pixel: &mut impl FnMut(i32, i32, u8)) {
+    draw_tile_map(ppu, TileMap::background(ppu.lcdc), draw_pixel);
+}
+
 pub fn draw_window(ppu: &Ppu, draw_pixel: &mut impl FnMut(i32, i32, u8)) {
-    for i in 0..(32 * 32) {
-        let tx = 8 * (i % 32);
-        let ty = 8 * (i / 32);
-        // BG Tile Map Select
-        let address = if ppu.lcdc & 0x40 != 0 { 0x9C00 } else { 0x9800 };
-        let mut tile = ppu.vram[address - 0x8000 + i as usize] as usize;
-
-        // if is using 8800 method
-        if ppu.lcdc & 0x10 == 0 {
-            tile += 0x100;
-            if tile >= 0x180 {
-                tile -= 0x100;
-            }
-        }
+    draw_tile_map(ppu, TileMap::window(ppu.lcdc), draw_pixel);
+}
 
-        draw_tile(ppu, draw_pixel, tx, ty, tile, ppu.bgp, false);
+/// Draw the border of a rectangle in a 256x256 tile map rendering, wrapping around its edges.
+fn outline(pixels: &mut [[u8; 3]], pos: [u8; 2], size: [u8; 2]) {
+    let [x0, y0] = pos;
+    let [width, height] = size;
+    if width == 0 || height == 0 {
+        return;
+    }
+    let mut set = |x: u8, y: u8| pixels[x as usize + y as usize * 256] = OUTLINE_COLOR;
+    for i in 0..width {
+        set(x0.wrapping_add(i), y0);
+        set(x0.wrapping_add(i), y0.wrapping_add(height - 1));
+    }
+    for i in 0..height {
+        set(x0, y0.wrapping_add(i));
+        set(x0.wrapping_add(width - 1), y0.wrapping_add(i));
     }
 }
 
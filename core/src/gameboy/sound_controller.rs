@@ -66,6 +66,10 @@ pub struct SoundController {
 
     /// The current step of the frame sequencer
     frame_sequencer_step: u8,
+    /// The value of the DIV counter at clock count 0, which gives the phase of the frame
+    /// sequencer. The DIV counter is kept by `Timer`, so this is not part of the save state, and is
+    /// kept in sync by `set_div` and `reset_div`.
+    div_offset: u16,
     // From SameBoy source code: "When turning the APU on while DIV's bit 4 (or 5 in
     // double speed mode) is on, the first DIV/APU event is skipped."
     // frame_sequencer_skip: u8,
@@ -137,6 +141,7 @@ impl PartialEq for SoundController {
             && self.nr51 == other.nr51
             && self.on == other.on
             && self.frame_sequencer_step == other.frame_sequencer_step
+            && self.div_offset == other.div_offset
             && self.ch1_channel_enable == other.ch1_channel_enable
             && self.ch1_length_timer == other.ch1_length_timer
             && self.ch1_sweep_enabled == other.ch1_sweep_enabled
@@ -269,6 +274,7 @@ impl Default for SoundController {
             nr51: 0,
             on: false,
             frame_sequencer_step: 0,
+            div_offset: 0,
             ch1_channel_enable: false,
             ch1_length_timer: 0,
             ch1_sweep_enabled: false,
@@ -307,7 +313,69 @@ impl Default for SoundController {
 
 const WAVE_DUTY_TABLE: [u8; 4] = [0b0000_0001, 0b0000_0011, 0b0000_1111, 0b1111_1100];
 
+/// The maximum value of a output sample: the four channels at their maximum amplitude, 15, scaled by
+/// the maximum master volume, 7.
+pub const MAX_SAMPLE: u16 = 4 * 15 * 7;
+
+/// Limit a mixed sample to `MAX_SAMPLE`, the range the output samples are expected to be in.
+///
+/// The channel amplitudes can only go above 15 with a corrupted save state, but the output should
+/// not go out of range nor wrap around in that case either.
+pub fn limit_sample(sample: u32) -> u16 {
+    sample.min(MAX_SAMPLE as u32) as u16
+}
+
+/// The number of clocks between each step of the frame sequencer.
+const FRAME_SEQUENCER_PERIOD: u64 = CLOCK_SPEED / 512;
+
+/// Advance a frequency timer by the given number of ticks. Each tick it decreases by one, and when
+/// it is 0 it is reloaded with `reload` instead. Return the number of times it was reloaded.
+fn advance_timer(timer: &mut u16, reload: u16, ticks: u64) -> u64 {
+    let t = *timer as u64;
+    if ticks <= t {
+        *timer = (t - ticks) as u16;
+        return 0;
+    }
+    let period = reload as u64 + 1;
+    let after_first = ticks - (t + 1);
+    *timer = (reload as u64 - after_first % period) as u16;
+    1 + after_first / period
+}
+
+/// The state of the sound controller right after the DMG boot ROM, in the `SaveState` format.
+pub const AFTER_BOOT_STATE: &[u8] = include_bytes!("../../after_boot/sound.sav");
+
 impl SoundController {
+    /// Reset the sound controller to the state right after the boot ROM, loaded from `state`, in
+    /// the `SaveState` format, like `AFTER_BOOT_STATE`. The state that is not saved, like the
+    /// pending output, is also reset, but the sample frequency is kept.
+    ///
+    /// If `state` is not a valid state, or has bytes past its end, `AFTER_BOOT_STATE` is loaded
+    /// instead, and the error is returned.
+    pub fn load_initial_state(&mut self, state: &[u8]) -> Result<(), LoadStateError> {
+        let result = self.reset_to_state(state);
+        if result.is_err() {
+            self.reset_to_state(AFTER_BOOT_STATE).unwrap();
+        }
+        result
+    }
+
+    fn reset_to_state(&mut self, mut state: &[u8]) -> Result<(), LoadStateError> {
+        let mut sound = SoundController {
+            sample_frequency: self.sample_frequency,
+            ..Default::default()
+        };
+        sound.load_state(&mut state)?;
+        if !state.is_empty() {
+            return Err(LoadStateError::IoError(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("{} bytes past the end of the state", state.len()),
+            )));
+        }
+        *self = sound;
+        Ok(())
+    }
+
     /// Return the currently generated audio output. The buffer is cleared.
     pub fn get_output(&mut self, clock_count: u64) -> Vec<u16> {
         self.update(clock_count);
@@ -317,6 +385,10 @@ impl SoundController {
     /// Emulator the sound controller until to the currently `clock_count`, since the `clock_count` of
     /// the last update.
     pub fn update(&mut self, clock_count: u64) {
+        // it may already be updated past `clock_count` by `reset_div`
+        if clock_count < self.last_clock {
+            return;
+        }
         // if it is off, there is no need for audio generation
         if !self.on {
             if self.sample_frequency != 0 {
@@ -344,268 +416,334 @@ impl SoundController {
                 (self.sample_mod + elapsed_clock * self.sample_frequency) % CLOCK_SPEED;
             return;
         }
-        // channel 1
-        let ch1_duty = (self.nr11 >> 6) & 0x3;
-        let mut ch1_freq = u16::from_be_bytes([self.nr14, self.nr13]) & 0x07FF;
-        let ch1_sweep_period = (self.nr10 & 0x70) >> 4;
-        let ch1_sweep_direction = (self.nr10 & 0x08) != 0;
-        let ch1_sweep_shift = self.nr10 & 0x7;
-        let ch1_env_period = self.nr12 & 0x7;
-        let ch1_env_direction = (self.nr12 & 0x08) != 0;
-
-        // channel 2
-        let ch2_duty = (self.nr21 >> 6) & 0x3;
-        let ch2_freq = u16::from_be_bytes([self.nr24, self.nr23]) & 0x07FF;
-        let ch2_env_period = self.nr22 & 0x7;
-        let ch2_env_direction = (self.nr22 & 0x08) != 0;
-
-        // channel 3
-        let ch3_output_level = [4, 0, 1, 2][(self.nr32 as usize & 0x60) >> 5];
-        let ch3_freq = u16::from_be_bytes([self.nr34, self.nr33]) & 0x07FF;
+        // The sound controller is clocked at every even clock count. Instead of emulating each one
+        // of these ticks, the frequency timers are advanced at once up to the next tick that has a
+        // event (a frame sequencer step or the collection of a sample), and only that tick is fully
+        // emulated.
+        let mut clock = self.last_clock + self.last_clock % 2;
+        while clock < clock_count {
+            let last_tick = (clock_count - 1) & !1;
+            let div = clock + self.div_offset as u64;
+            let next_step = (div + FRAME_SEQUENCER_PERIOD - 1) / FRAME_SEQUENCER_PERIOD
+                * FRAME_SEQUENCER_PERIOD
+                - self.div_offset as u64;
+            let next_sample = if self.sample_frequency != 0 {
+                clock + 2 * (self.ticks_until_sample() - 1)
+            } else {
+                u64::MAX
+            };
+            let event = last_tick.min(next_step).min(next_sample);
 
-        // channel 4
-        let ch4_env_period = self.nr42 & 0x7;
-        let ch4_env_direction = (self.nr42 & 0x08) != 0;
-        let ch4_shift_amount = (self.nr43 & 0xF0) >> 4;
-        let ch4_counter_width = (self.nr43 & 0x08) != 0;
-        let ch4_divisor: u16 = [8, 16, 32, 48, 64, 80, 96, 112][self.nr43 as usize & 0x07];
+            let skipped = (event - clock) / 2;
+            if skipped > 0 {
+                self.tick_channels(skipped);
+                self.sample_mod =
+                    (self.sample_mod + skipped * 2 * self.sample_frequency) % CLOCK_SPEED;
+            }
+            self.tick(event);
+            clock = event + 2;
+        }
+        self.last_clock = clock_count;
+    }
 
-        // mixing
-        let volume_left = (self.nr50 & 0x70) >> 4;
-        let ch1_left = (self.nr51 & 0x10) != 0;
-        let ch2_left = (self.nr51 & 0x20) != 0;
-        let ch3_left = (self.nr51 & 0x40) != 0;
-        let ch4_left = (self.nr51 & 0x80) != 0;
-        let volume_right = self.nr50 & 0x7;
-        let ch1_right = (self.nr51 & 0x01) != 0;
-        let ch2_right = (self.nr51 & 0x02) != 0;
-        let ch3_right = (self.nr51 & 0x04) != 0;
-        let ch4_right = (self.nr51 & 0x08) != 0;
+    /// Emulate the sound controller one tick at a time, until the given `clock_count`. This is the
+    /// straightforward implementation of `update`, and is only kept for testing and benchmarking it.
+    #[doc(hidden)]
+    pub fn update_per_clock(&mut self, clock_count: u64) {
+        if !self.on {
+            return self.update(clock_count);
+        }
         for clock in (self.last_clock..clock_count).filter(|x| x % 2 == 0) {
-            // The frequency timer decreases in one every clock. When it reaches 0, it is reloaded.
-            if self.ch1_channel_enable {
-                if self.ch1_frequency_timer == 0 {
-                    self.ch1_frequency_timer = (0x07FF ^ ch1_freq) * 2;
-                    self.ch1_wave_duty_position = (self.ch1_wave_duty_position + 1) % 8;
-                } else {
-                    self.ch1_frequency_timer -= 1;
-                }
-            }
+            self.tick(clock);
+        }
+        self.last_clock = clock_count;
+    }
 
-            if self.ch2_channel_enable {
-                if self.ch2_frequency_timer == 0 {
-                    self.ch2_frequency_timer = (0x07FF ^ ch2_freq) * 2;
-                    self.ch2_wave_duty_position = (self.ch2_wave_duty_position + 1) % 8;
-                } else {
-                    self.ch2_frequency_timer -= 1;
-                }
+    /// Set the phase of the frame sequencer, given that the DIV counter has the value `div` at
+    /// `clock_count`. This doesn't emulate up to `clock_count`, so it should only be called when the
+    /// DIV counter is set directly, like after loading a save state.
+    ///
+    /// The sound controller is only clocked in even clock counts, so the phase is rounded to them.
+    pub fn set_div(&mut self, clock_count: u64, div: u16) {
+        self.div_offset = div.wrapping_sub(clock_count as u16) & !1;
+    }
+
+    /// Reset the DIV counter at `clock_count`, as a write to the DIV register does. If the bit 12 of
+    /// the counter was set, this is a falling edge, and the frame sequencer steps immediately.
+    pub fn reset_div(&mut self, clock_count: u64) {
+        // the counter is incremented before the reset, so the tick at `clock_count` still uses the
+        // old phase.
+        self.update(clock_count + 1);
+        let div = (clock_count as u16).wrapping_add(self.div_offset);
+        if self.on && div & 0x1000 != 0 {
+            self.step_frame_sequencer();
+        }
+        self.set_div(clock_count, 0);
+    }
+
+    /// The number of ticks until the next sample is collected, counting the next tick.
+    fn ticks_until_sample(&self) -> u64 {
+        // A sample is collected when `sample_mod` overflows CLOCK_SPEED (see `tick`).
+        let step = 2 * self.sample_frequency;
+        if step >= CLOCK_SPEED {
+            return 1;
+        }
+        (CLOCK_SPEED - self.sample_mod + step - 1) / step
+    }
+
+    /// Emulate a single tick, at the given clock count.
+    fn tick(&mut self, clock: u64) {
+        self.tick_channels(1);
+
+        // a step happens in a falling edge of the bit 12 of the DIV counter (bit 4 of the DIV
+        // register).
+        if (clock + self.div_offset as u64) % FRAME_SEQUENCER_PERIOD == 0 {
+            self.step_frame_sequencer();
+        }
+
+        if self.sample_frequency != 0 {
+            // collect a sample
+
+            // c % (fc/fs) == 0 ~> c % (fc/fs) < 1 => (c*fs) % fc < fs
+            // => ( (c-1)*fs)%fc + fs) % fc < fs
+            // => (    last      + fs) % fc < fs
+
+            // I multiple fs by 2, because the clock increase in two by two
+            self.sample_mod = (self.sample_mod + 2 * self.sample_frequency) % CLOCK_SPEED;
+            if self.sample_mod < 2 * self.sample_frequency {
+                self.sample();
             }
+        }
+    }
 
-            if self.ch3_channel_enable {
-                if self.ch3_frequency_timer == 0 {
-                    self.ch3_wave_position = (self.ch3_wave_position + 1) % 32;
-                    self.ch3_sample_buffer = (self.ch3_wave_pattern
-                        [self.ch3_wave_position as usize / 2]
-                        >> [4, 0][self.ch3_wave_position as usize % 2])
-                        & 0xF;
-                    self.ch3_frequency_timer = 0x07FF ^ ch3_freq;
-                    self.ch3_wave_just_read = true;
-                } else {
-                    self.ch3_frequency_timer -= 1;
-                    self.ch3_wave_just_read = false;
-                }
-            } else {
-                self.ch3_wave_just_read = false;
+    /// Advance the frequency timers of all channels by the given number of ticks.
+    fn tick_channels(&mut self, ticks: u64) {
+        // The frequency timer decreases in one every tick. When it reaches 0, it is reloaded.
+        if self.ch1_channel_enable {
+            let ch1_freq = u16::from_be_bytes([self.nr14, self.nr13]) & 0x07FF;
+            let reloads = advance_timer(
+                &mut self.ch1_frequency_timer,
+                (0x07FF ^ ch1_freq) * 2,
+                ticks,
+            );
+            self.ch1_wave_duty_position =
+                ((self.ch1_wave_duty_position as u64 + reloads) % 8) as u8;
+        }
+
+        if self.ch2_channel_enable {
+            let ch2_freq = u16::from_be_bytes([self.nr24, self.nr23]) & 0x07FF;
+            let reloads = advance_timer(
+                &mut self.ch2_frequency_timer,
+                (0x07FF ^ ch2_freq) * 2,
+                ticks,
+            );
+            self.ch2_wave_duty_position =
+                ((self.ch2_wave_duty_position as u64 + reloads) % 8) as u8;
+        }
+
+        if self.ch3_channel_enable {
+            let ch3_freq = u16::from_be_bytes([self.nr34, self.nr33]) & 0x07FF;
+            let reload = 0x07FF ^ ch3_freq;
+            let reloads = advance_timer(&mut self.ch3_frequency_timer, reload, ticks);
+            if reloads > 0 {
+                self.ch3_wave_position = ((self.ch3_wave_position as u64 + reloads) % 32) as u8;
+                self.ch3_sample_buffer = (self.ch3_wave_pattern
+                    [self.ch3_wave_position as usize / 2]
+                    >> [4, 0][self.ch3_wave_position as usize % 2])
+                    & 0xF;
             }
+            // the wave was just read if the timer was reloaded in the last tick
+            self.ch3_wave_just_read = reloads > 0 && self.ch3_frequency_timer == reload;
+        } else {
+            self.ch3_wave_just_read = false;
+        }
 
-            if self.ch4_channel_enable {
-                if self.ch4_frequency_timer == 0 {
-                    self.ch4_frequency_timer = ch4_divisor << ch4_shift_amount;
-                    let xor = (self.ch4_lfsr & 0x1 != 0) ^ (self.ch4_lfsr & 0x2 != 0);
-                    self.ch4_lfsr = (self.ch4_lfsr >> 1) | ((xor as u16) << 14);
-                    if ch4_counter_width {
-                        self.ch4_lfsr &= !(1 << 6);
-                        self.ch4_lfsr |= (xor as u16) << 6;
-                    }
-                } else {
-                    self.ch4_frequency_timer -= 1;
+        if self.ch4_channel_enable {
+            let ch4_shift_amount = (self.nr43 & 0xF0) >> 4;
+            let ch4_counter_width = (self.nr43 & 0x08) != 0;
+            let ch4_divisor: u16 = [8, 16, 32, 48, 64, 80, 96, 112][self.nr43 as usize & 0x07];
+            let reloads = advance_timer(
+                &mut self.ch4_frequency_timer,
+                ch4_divisor << ch4_shift_amount,
+                ticks,
+            );
+            for _ in 0..reloads {
+                let xor = (self.ch4_lfsr & 0x1 != 0) ^ (self.ch4_lfsr & 0x2 != 0);
+                self.ch4_lfsr = (self.ch4_lfsr >> 1) | ((xor as u16) << 14);
+                if ch4_counter_width {
+                    self.ch4_lfsr &= !(1 << 6);
+                    self.ch4_lfsr |= (xor as u16) << 6;
                 }
             }
+        }
+    }
 
-            // frame sequencer
-
-            // TODO: a step should happens in a falling edge of the bit 5 of the DIV timer.
-            if clock % (CLOCK_SPEED / 512) == 0 {
-                // step
-                let lenght_ctr = self.frame_sequencer_step % 2 == 0;
-                let volume_env = self.frame_sequencer_step % 8 == 7;
-                let sweep = self.frame_sequencer_step % 4 == 2;
-                self.frame_sequencer_step = (self.frame_sequencer_step + 1) % 8;
+    fn step_frame_sequencer(&mut self) {
+        let lenght_ctr = self.frame_sequencer_step % 2 == 0;
+        let volume_env = self.frame_sequencer_step % 8 == 7;
+        let sweep = self.frame_sequencer_step % 4 == 2;
+        self.frame_sequencer_step = (self.frame_sequencer_step + 1) % 8;
 
-                if lenght_ctr {
-                    if self.nr14 & 0x40 != 0 && self.ch1_length_timer != 0 {
-                        self.ch1_length_timer -= 1;
-                        if self.ch1_length_timer == 0 {
-                            self.ch1_channel_enable = false;
-                        }
-                    }
-                    if self.nr24 & 0x40 != 0 && self.ch2_length_timer != 0 {
-                        self.ch2_length_timer -= 1;
-                        if self.ch2_length_timer == 0 {
-                            self.ch2_channel_enable = false;
-                        }
-                    }
-                    if self.nr34 & 0x40 != 0 && self.ch3_length_timer != 0 {
-                        self.ch3_length_timer -= 1;
-                        if self.ch3_length_timer == 0 {
-                            self.ch3_channel_enable = false;
-                        }
-                    }
-                    if self.nr44 & 0x40 != 0 && self.ch4_length_timer != 0 {
-                        self.ch4_length_timer -= 1;
-                        if self.ch4_length_timer == 0 {
-                            self.ch4_channel_enable = false;
-                        }
-                    }
+        if lenght_ctr {
+            if self.nr14 & 0x40 != 0 && self.ch1_length_timer != 0 {
+                self.ch1_length_timer -= 1;
+                if self.ch1_length_timer == 0 {
+                    self.ch1_channel_enable = false;
                 }
+            }
+            if self.nr24 & 0x40 != 0 && self.ch2_length_timer != 0 {
+                self.ch2_length_timer -= 1;
+                if self.ch2_length_timer == 0 {
+                    self.ch2_channel_enable = false;
+                }
+            }
+            if self.nr34 & 0x40 != 0 && self.ch3_length_timer != 0 {
+                self.ch3_length_timer -= 1;
+                if self.ch3_length_timer == 0 {
+                    self.ch3_channel_enable = false;
+                }
+            }
+            if self.nr44 & 0x40 != 0 && self.ch4_length_timer != 0 {
+                self.ch4_length_timer -= 1;
+                if self.ch4_length_timer == 0 {
+                    self.ch4_channel_enable = false;
+                }
+            }
+        }
 
-                if volume_env {
-                    fn env(
-                        period: u8,
-                        period_timer: &mut u8,
-                        current_volume: &mut u8,
-                        is_upwards: bool,
-                    ) {
-                        if period != 0 {
-                            if *period_timer > 0 {
-                                *period_timer -= 1;
-                            }
-
-                            if *period_timer == 0 {
-                                *period_timer = period;
-
-                                if (*current_volume < 0xF && is_upwards)
-                                    || (*current_volume > 0x0 && !is_upwards)
-                                {
-                                    if is_upwards {
-                                        *current_volume += 1;
-                                    } else {
-                                        *current_volume -= 1;
-                                    }
-                                }
-                            }
-                        }
+        if volume_env {
+            fn env(period: u8, period_timer: &mut u8, current_volume: &mut u8, is_upwards: bool) {
+                if period != 0 {
+                    if *period_timer > 0 {
+                        *period_timer -= 1;
                     }
 
-                    env(
-                        ch1_env_period,
-                        &mut self.ch1_env_period_timer,
-                        &mut self.ch1_current_volume,
-                        ch1_env_direction,
-                    );
-                    env(
-                        ch2_env_period,
-                        &mut self.ch2_env_period_timer,
-                        &mut self.ch2_current_volume,
-                        ch2_env_direction,
-                    );
-                    env(
-                        ch4_env_period,
-                        &mut self.ch4_env_period_timer,
-                        &mut self.ch4_current_volume,
-                        ch4_env_direction,
-                    );
-                }
+                    if *period_timer == 0 {
+                        *period_timer = period;
 
-                if sweep {
-                    if self.ch1_sweep_timer > 0 {
-                        self.ch1_sweep_timer -= 1;
-                    }
-                    if self.ch1_sweep_timer == 0 {
-                        self.ch1_sweep_timer = if ch1_sweep_period == 0 {
-                            8
-                        } else {
-                            ch1_sweep_period
-                        };
-                        if self.ch1_sweep_enabled && ch1_sweep_period != 0 {
-                            let new_freq =
-                                self.calculate_frequency(ch1_sweep_shift, ch1_sweep_direction);
-                            if new_freq < 2048 && ch1_sweep_shift > 0 {
-                                ch1_freq = new_freq & 0x07FF;
-                                let [upper, lower] = ch1_freq.to_be_bytes();
-                                self.nr14 = (self.nr14 & 0xF8) | (upper & 0x7);
-                                self.nr13 = lower;
-
-                                self.ch1_shadow_freq = new_freq;
-
-                                // do overflow check again
-                                self.calculate_frequency(ch1_sweep_shift, ch1_sweep_direction);
+                        if (*current_volume < 0xF && is_upwards)
+                            || (*current_volume > 0x0 && !is_upwards)
+                        {
+                            if is_upwards {
+                                *current_volume += 1;
+                            } else {
+                                *current_volume -= 1;
                             }
                         }
                     }
                 }
             }
 
-            if self.sample_frequency != 0 {
-                // collect a sample
-
-                // c % (fc/fs) == 0 ~> c % (fc/fs) < 1 => (c*fs) % fc < fs
-                // => ( (c-1)*fs)%fc + fs) % fc < fs
-                // => (    last      + fs) % fc < fs
-
-                // I multiple fs by 2, because the clock increase in two by two
-                self.sample_mod = (self.sample_mod + 2 * self.sample_frequency) % CLOCK_SPEED;
-                if self.sample_mod < 2 * self.sample_frequency {
-                    let ch1_amp =
-                        ((WAVE_DUTY_TABLE[ch1_duty as usize] >> self.ch1_wave_duty_position) & 0x1)
-                            * self.ch1_current_volume;
-                    let ch2_amp =
-                        ((WAVE_DUTY_TABLE[ch2_duty as usize] >> self.ch2_wave_duty_position) & 0x1)
-                            * self.ch2_current_volume;
-                    let ch3_amp = self.ch3_sample_buffer >> ch3_output_level;
-                    let ch4_amp = ((!self.ch4_lfsr as u8) & 0x01) * self.ch4_current_volume;
-                    let mut left = 0;
-                    let mut right = 0;
-                    if self.ch1_channel_enable {
-                        if ch1_left {
-                            left += ch1_amp as u16;
-                        }
-                        if ch1_right {
-                            right += ch1_amp as u16;
-                        }
-                    }
-                    if self.ch2_channel_enable {
-                        if ch2_left {
-                            left += ch2_amp as u16;
-                        }
-                        if ch2_right {
-                            right += ch2_amp as u16;
-                        }
-                    }
-                    if self.ch3_channel_enable && self.nr30 & 0x80 != 0 {
-                        if ch3_left {
-                            left += ch3_amp as u16;
-                        }
-                        if ch3_right {
-                            right += ch3_amp as u16;
-                        }
-                    }
-                    if self.ch4_channel_enable {
-                        if ch4_left {
-                            left += ch4_amp as u16;
-                        }
-                        if ch4_right {
-                            right += ch4_amp as u16;
-                        }
+            env(
+                self.nr12 & 0x7,
+                &mut self.ch1_env_period_timer,
+                &mut self.ch1_current_volume,
+                (self.nr12 & 0x08) != 0,
+            );
+            env(
+                self.nr22 & 0x7,
+                &mut self.ch2_env_period_timer,
+                &mut self.ch2_current_volume,
+                (self.nr22 & 0x08) != 0,
+            );
+            env(
+                self.nr42 & 0x7,
+                &mut self.ch4_env_period_timer,
+                &mut self.ch4_current_volume,
+                (self.nr42 & 0x08) != 0,
+            );
+        }
+
+        if sweep {
+            let ch1_sweep_period = (self.nr10 & 0x70) >> 4;
+            let ch1_sweep_direction = (self.nr10 & 0x08) != 0;
+            let ch1_sweep_shift = self.nr10 & 0x7;
+            if self.ch1_sweep_timer > 0 {
+                self.ch1_sweep_timer -= 1;
+            }
+            if self.ch1_sweep_timer == 0 {
+                self.ch1_sweep_timer = if ch1_sweep_period == 0 {
+                    8
+                } else {
+                    ch1_sweep_period
+                };
+                if self.ch1_sweep_enabled && ch1_sweep_period != 0 {
+                    let new_freq = self.calculate_frequency(ch1_sweep_shift, ch1_sweep_direction);
+                    if new_freq < 2048 && ch1_sweep_shift > 0 {
+                        let [upper, lower] = (new_freq & 0x07FF).to_be_bytes();
+                        self.nr14 = (self.nr14 & 0xF8) | (upper & 0x7);
+                        self.nr13 = lower;
+
+                        self.ch1_shadow_freq = new_freq;
+
+                        // do overflow check again
+                        self.calculate_frequency(ch1_sweep_shift, ch1_sweep_direction);
                     }
-                    self.output.push(left * volume_left as u16);
-                    self.output.push(right * volume_right as u16);
                 }
             }
         }
-        self.last_clock = clock_count;
     }
 
+    /// Mix the current output of the channels, and push it to the output buffer.
+    fn sample(&mut self) {
+        let ch1_duty = (self.nr11 >> 6) & 0x3;
+        let ch2_duty = (self.nr21 >> 6) & 0x3;
+        let ch3_output_level = [4, 0, 1, 2][(self.nr32 as usize & 0x60) >> 5];
+
+        let volume_left = (self.nr50 & 0x70) >> 4;
+        let ch1_left = (self.nr51 & 0x10) != 0;
+        let ch2_left = (self.nr51 & 0x20) != 0;
+        let ch3_left = (self.nr51 & 0x40) != 0;
+        let ch4_left = (self.nr51 & 0x80) != 0;
+        let volume_right = self.nr50 & 0x7;
+        let ch1_right = (self.nr51 & 0x01) != 0;
+        let ch2_right = (self.nr51 & 0x02) != 0;
+        let ch3_right = (self.nr51 & 0x04) != 0;
+        let ch4_right = (self.nr51 & 0x08) != 0;
+
+        let ch1_amp = ((WAVE_DUTY_TABLE[ch1_duty as usize] >> self.ch1_wave_duty_position) & 0x1)
+            * self.ch1_current_volume;
+        let ch2_amp = ((WAVE_DUTY_TABLE[ch2_duty as usize] >> self.ch2_wave_duty_position) & 0x1)
+            * self.ch2_current_volume;
+        let ch3_amp = self.ch3_sample_buffer >> ch3_output_level;
+        let ch4_amp = ((!self.ch4_lfsr as u8) & 0x01) * self.ch4_current_volume;
+        let mut left = 0;
+        let mut right = 0;
+        if self.ch1_channel_enable {
+            if ch1_left {
+                left += ch1_amp as u32;
+            }
+            if ch1_right {
+                right += ch1_amp as u32;
+            }
+        }
+        if self.ch2_channel_enable {
+            if ch2_left {
+                left += ch2_amp as u32;
+            }
+            if ch2_right {
+                right += ch2_amp as u32;
+            }
+        }
+        if self.ch3_channel_enable && self.nr30 & 0x80 != 0 {
+            if ch3_left {
+                left += ch3_amp as u32;
+            }
+            if ch3_right {
+                right += ch3_amp as u32;
+            }
+        }
+        if self.ch4_channel_enable {
+            if ch4_left {
+                left += ch4_amp as u32;
+            }
+            if ch4_right {
+                right += ch4_amp as u32;
+            }
+        }
+        self.output.push(limit_sample(left * volume_left as u32));
+        self.output.push(limit_sample(right * volume_right as u32));
+    }
     fn calculate_frequency(&mut self, ch1_sweep_shift: u8, is_downwards: bool) -> u16 {
         if is_downwards {
             self.ch1_has_done_sweep_calculation = true;
@@ -962,6 +1100,15 @@ impl SoundController {
     }
 
     pub fn read(&mut self, clock_count: u64, address: u8) -> u8 {
+        if self.on && matches!(address, 0x26 | 0x30..=0x3F) {
+            self.update(clock_count);
+        }
+        self.peek(address)
+    }
+
+    /// Read a register without updating the sound controller first. NR52 and the wave RAM may be
+    /// outdated.
+    pub fn peek(&self, address: u8) -> u8 {
         let value = if self.on {
             match address {
                 0x10 => self.nr10 | 0x80,
@@ -985,7 +1132,6 @@ impl SoundController {
                 0x24 => self.nr50 | 0x00,
                 0x25 => self.nr51 | 0x00,
                 0x26 => {
-                    self.update(clock_count);
                     let r = ((self.on as u8) << 7)
                         | ((self.ch4_channel_enable as u8) << 3)
                         | ((self.ch3_channel_enable as u8) << 2)
@@ -995,7 +1141,6 @@ impl SoundController {
                     r
                 }
                 0x30..=0x3F => {
-                    self.update(clock_count);
                     if self.ch3_channel_enable {
                         // if it had read recently, return the currently value, otherwise 0xFF
                         if self.ch3_wave_just_read {
@@ -15,8 +15,10 @@ pub struct Timer {
     pub last_counter_bit: bool,
     /// The last clock cycle where Timer was updated
     pub last_clock_count: u64,
-    /// Keep track of TIMA reloading. TIMA is reloading if < 4, reloading is scheduled if >= 4, and
-    /// there is no reload if = 0.
+    /// Keep track of TIMA reloading. After a overflow, TIMA stays 00 for 4 cycles, while this is
+    /// greater than 4, and a write to TIMA cancels the reload. Then, while this is between 1 and 4,
+    /// TIMA is reloaded from TMA on every cycle, so writes to TIMA are ignored and writes to TMA
+    /// take effect immediately. There is no reload if this is 0.
     pub loading: u8,
 }
 crate::save_state!(Timer, self, data {
@@ -54,7 +56,7 @@ impl Timer {
             let f = [9, 3, 5, 7][(self.tac & 0b11) as usize];
             let counter_bit = ((self.div >> f) as u8 & (self.tac >> 2)) & 0b1 != 0;
 
-            // faling edge
+            // faling edge. Writes to DIV and TAC can also cause a falling edge, incrementing TIMA.
             if self.last_counter_bit && !counter_bit {
                 let (v, overflow) = self.tima.overflowing_add(1);
                 self.tima = v;
@@ -70,6 +72,10 @@ impl Timer {
                 self.loading -= 1;
                 if self.loading <= 4 {
                     self.tima = self.tma;
+                }
+                // the interrupt is only requested at the start of the reload, so clearing IF
+                // during the reload don't request it again.
+                if self.loading == 4 {
                     interrupt = true;
                 }
             }
@@ -0,0 +1,150 @@
+/// A range of addresses that is watched for reads and/or writes.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Watchpoint {
+    /// The first address of the range.
+    pub start: u16,
+    /// The last address of the range, inclusive.
+    pub end: u16,
+    pub read: bool,
+    pub write: bool,
+    /// The number of accesses that matched this watchpoint.
+    pub hits: u64,
+}
+impl Watchpoint {
+    fn contains(&self, address: u16) -> bool {
+        (self.start..=self.end).contains(&address)
+    }
+}
+
+/// A memory access that matched a watchpoint.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct WatchpointHit {
+    /// The address of the instruction that made the access.
+    pub pc: u16,
+    /// The accessed address.
+    pub address: u16,
+    /// The value that was read or written.
+    pub value: u8,
+    /// If the access was a write, otherwise it was a read.
+    pub write: bool,
+}
+impl std::fmt::Display for WatchpointHit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.write {
+            write!(f, "write {:02x} to {:04x}", self.value, self.address)?;
+        } else {
+            write!(f, "read {:02x} from {:04x}", self.value, self.address)?;
+        }
+        write!(f, " at pc {:04x}", self.pc)
+    }
+}
+
+/// The memory watchpoints, checked by the interpreter in every memory access made by a instruction.
+///
+/// The watched addresses are kept in two bitmaps, one for reads and other for writes, and there is
+/// a flag for when there are no watchpoints, so the cost of a access is a single branch.
+pub struct Watchpoints {
+    list: Vec<Watchpoint>,
+    read_map: Box<[u64; 0x400]>,
+    write_map: Box<[u64; 0x400]>,
+    any: bool,
+    /// The address of the instruction being executed.
+    pub(crate) pc: u16,
+    /// The first access that matched a watchpoint, since the last call to `take_hit`.
+    hit: Option<WatchpointHit>,
+}
+impl Default for Watchpoints {
+    fn default() -> Self {
+        Self {
+            list: Vec::new(),
+            read_map: Box::new([0; 0x400]),
+            write_map: Box::new([0; 0x400]),
+            any: false,
+            pc: 0,
+            hit: None,
+        }
+    }
+}
+impl Watchpoints {
+    pub fn list(&self) -> &[Watchpoint] {
+        &self.list
+    }
+
+    /// If there is at least one watchpoint.
+    #[inline]
+    pub fn any(&self) -> bool {
+        self.any
+    }
+
+    /// Watch the addresses from `start` to `end`, inclusive.
+    pub fn add(&mut self, start: u16, end: u16, read: bool, write: bool) {
+        self.list.push(Watchpoint {
+            start,
+            end,
+            read,
+            write,
+            hits: 0,
+        });
+        self.rebuild();
+    }
+
+    pub fn remove(&mut self, index: usize) {
+        if index < self.list.len() {
+            self.list.remove(index);
+            self.rebuild();
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.list.clear();
+        self.hit = None;
+        self.rebuild();
+    }
+
+    /// Return the last access that matched a watchpoint, and clear it.
+    pub fn take_hit(&mut self) -> Option<WatchpointHit> {
+        self.hit.take()
+    }
+
+    fn rebuild(&mut self) {
+        *self.read_map = [0; 0x400];
+        *self.write_map = [0; 0x400];
+        for w in &self.list {
+            for address in w.start..=w.end {
+                let (i, bit) = (address as usize / 64, address % 64);
+                if w.read {
+                    self.read_map[i] |= 1 << bit;
+                }
+                if w.write {
+                    self.write_map[i] |= 1 << bit;
+                }
+            }
+        }
+        self.any = !self.list.is_empty();
+    }
+
+    /// Check a access made by the current instruction.
+    pub(crate) fn check(&mut self, address: u16, value: u8, write: bool) {
+        let map = if write {
+            &self.write_map
+        } else {
+            &self.read_map
+        };
+        if map[address as usize / 64] & (1 << (address % 64)) == 0 {
+            return;
+        }
+        for w in self.list.iter_mut() {
+            if w.contains(address) && if write { w.write } else { w.read } {
+                w.hits += 1;
+            }
+        }
+        if self.hit.is_none() {
+            self.hit = Some(WatchpointHit {
+                pc: self.pc,
+                address,
+                value,
+                write,
+            });
+        }
+    }
+}
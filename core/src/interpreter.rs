@@ -1,11 +1,75 @@
 use crate::{
     consts,
+    disassembler::disassembly_opcode,
     gameboy::{
-        cpu::{CpuState, ImeState},
+        call_stack::StackFrame,
+        cpu::{Cpu, CpuState, ImeState},
+        instruction_history::HistoryEntry,
+        interrupt_log::{InterruptEvent, InterruptEventKind},
         GameBoy,
     },
 };
 
+/// What was done by a call to `Interpreter::step_instruction`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum StepKind {
+    /// A instruction was executed.
+    Instruction,
+    /// A interrupt was dispatched, instead of executing a instruction.
+    Interrupt,
+    /// The CPU is halted or stopped, so only time has passed.
+    Halted,
+}
+
+/// A register whose value was changed by a step.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct RegisterChange {
+    /// The name of the register, like `"A"` or `"SP"`.
+    pub register: &'static str,
+    pub before: u16,
+    pub after: u16,
+}
+
+/// The result of `Interpreter::step_instruction`.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ExecutedInstruction {
+    pub kind: StepKind,
+    /// The address of the instruction.
+    pub pc: u16,
+    /// The ROM bank of the instruction, or `None` if it is outside the ROM.
+    pub bank: Option<u16>,
+    /// The bytes of the instruction, only the first `len` are valid. `len` is 0 if no instruction
+    /// was executed.
+    pub opcode: [u8; 3],
+    pub len: u8,
+    /// The disassembled instruction, or a empty string if no instruction was executed.
+    pub text: String,
+    /// The number of clock cycles consumed.
+    pub cycles: u64,
+    /// The registers that were changed, including F and SP, but not PC.
+    pub changes: Vec<RegisterChange>,
+}
+impl ExecutedInstruction {
+    /// The bytes of the executed instruction.
+    pub fn bytes(&self) -> &[u8] {
+        &self.opcode[..self.len as usize]
+    }
+}
+
+fn registers(cpu: &Cpu) -> [(&'static str, u16); 9] {
+    [
+        ("A", cpu.a as u16),
+        ("F", cpu.f.0 as u16),
+        ("B", cpu.b as u16),
+        ("C", cpu.c as u16),
+        ("D", cpu.d as u16),
+        ("E", cpu.e as u16),
+        ("H", cpu.h as u16),
+        ("L", cpu.l as u16),
+        ("SP", cpu.sp),
+    ]
+}
+
 #[derive(PartialEq, Eq, Clone, Copy)]
 enum Condition {
     None,
@@ -78,7 +142,7 @@ impl Interpreter<'_> {
         self.0
             .trace
             .borrow_mut()
-            .print_around(self.0.cartridge.curr_bank(), pc, &self.0, &mut string)
+            .print_around(self.0.cartridge.curr_rom_bank(), pc, &self.0, &mut string)
             .unwrap();
         writeln!(std, "{}", string).unwrap();
         writeln!(std, "{}", self.0.cpu).unwrap();
@@ -118,7 +182,7 @@ impl Interpreter<'_> {
     fn jump_to(&mut self, address: u16) {
         let pc = self.0.cpu.pc;
         self.0.cpu.pc = address;
-        let bank = self.0.cartridge.curr_bank();
+        let bank = self.0.cartridge.curr_rom_bank();
         let mut trace = self.0.trace.borrow_mut();
 
         // check early if this address is already traced, and return if it is
@@ -172,17 +236,17 @@ impl Interpreter<'_> {
     fn pushr(&mut self, value: u16) {
         let [lsb, msb] = value.to_le_bytes();
         self.0.tick(4); // 1 M-cycle with SP in address buss
-        self.0.write(sub16(self.0.cpu.sp, 1), msb);
+        self.bus_write(sub16(self.0.cpu.sp, 1), msb);
         self.0.tick(4); // 1 M-cycle with SP-1 in address buss
-        self.0.write(sub16(self.0.cpu.sp, 2), lsb);
+        self.bus_write(sub16(self.0.cpu.sp, 2), lsb);
         self.0.tick(4); // 1 M-cycle with SP-2 in address buss
         self.0.cpu.sp = sub16(self.0.cpu.sp, 2);
     }
 
     fn popr(&mut self) -> u16 {
-        let lsp = self.0.read(self.0.cpu.sp);
+        let lsp = self.bus_read(self.0.cpu.sp);
         self.0.tick(4); // 1 M-cycle with SP in address buss
-        let msp = self.0.read(add16(self.0.cpu.sp, 1));
+        let msp = self.bus_read(add16(self.0.cpu.sp, 1));
         self.0.tick(4); // 1 M-cycle with SP+1 in address buss
         self.0.cpu.sp = add16(self.0.cpu.sp, 2);
         u16::from_be_bytes([msp, lsp])
@@ -214,11 +278,37 @@ impl Interpreter<'_> {
         // JP cc, nn
         let c = self.check_condition(c);
         if c {
-            self.pushr(self.0.cpu.pc);
+            let return_address = self.0.cpu.pc;
+            self.pushr(return_address);
             self.jump_to(address);
+            self.push_frame(return_address, false);
+        }
+    }
+
+    /// Record a event in the interrupt log, if it is enabled. `pc` is the address of the
+    /// instruction that caused it.
+    fn log_interrupt_event(&mut self, kind: InterruptEventKind, pc: u16) {
+        if self.0.interrupt_log.is_some() {
+            let event = InterruptEvent::new(self.0, kind, pc);
+            if let Some(log) = &mut self.0.interrupt_log {
+                log.push(event);
+            }
         }
     }
 
+    /// Push a new frame to the call stack, after jumping to a routine.
+    fn push_frame(&mut self, return_address: u16, interrupt: bool) {
+        let address = self.0.cpu.pc;
+        let frame = StackFrame {
+            address,
+            bank: self.0.pc_bank(address),
+            sp: self.0.cpu.sp,
+            return_address,
+            interrupt,
+        };
+        self.0.call_stack.push(frame);
+    }
+
     fn ret(&mut self, cond: Condition) {
         // JP cc, nn
         let c = self.check_condition(cond);
@@ -232,6 +322,23 @@ impl Interpreter<'_> {
         }
     }
 
+    /// Read from memory, checking the watchpoints.
+    fn bus_read(&mut self, address: u16) -> u8 {
+        let value = self.0.read(address);
+        if self.0.watchpoints.any() {
+            self.0.watchpoints.check(address, value, false);
+        }
+        value
+    }
+
+    /// Write to memory, checking the watchpoints.
+    fn bus_write(&mut self, address: u16, value: u8) {
+        if self.0.watchpoints.any() {
+            self.0.watchpoints.check(address, value, true);
+        }
+        self.0.write(address, value);
+    }
+
     /// Read from PC, tick 4 cycles, and increase it by 1
     fn read_next_pc(&mut self) -> u8 {
         let v = self.0.read(self.0.cpu.pc);
@@ -259,33 +366,33 @@ impl Interpreter<'_> {
             Reg::Im8 => self.read_next_pc(),
             Reg::Im16 => {
                 let address = self.read_next_pc16();
-                let v = self.0.read(address);
+                let v = self.bus_read(address);
                 self.0.tick(4);
                 v
             }
             Reg::BC => {
-                let v = self.0.read(self.0.cpu.bc());
+                let v = self.bus_read(self.0.cpu.bc());
                 self.0.tick(4);
                 v
             }
             Reg::DE => {
-                let v = self.0.read(self.0.cpu.de());
+                let v = self.bus_read(self.0.cpu.de());
                 self.0.tick(4);
                 v
             }
             Reg::HL => {
-                let v = self.0.read(self.0.cpu.hl());
+                let v = self.bus_read(self.0.cpu.hl());
                 self.0.tick(4);
                 v
             }
             Reg::HLI => {
-                let v = self.0.read(self.0.cpu.hl());
+                let v = self.bus_read(self.0.cpu.hl());
                 self.0.tick(4);
                 self.0.cpu.set_hl(add16(self.0.cpu.hl(), 1));
                 v
             }
             Reg::HLD => {
-                let v = self.0.read(self.0.cpu.hl());
+                let v = self.bus_read(self.0.cpu.hl());
                 self.0.tick(4);
                 self.0.cpu.set_hl(sub16(self.0.cpu.hl(), 1));
                 v
@@ -304,33 +411,33 @@ impl Interpreter<'_> {
             Reg::H => self.0.cpu.h = value,
             Reg::L => self.0.cpu.l = value,
             Reg::Im8 => {
-                self.0.write(add16(self.0.cpu.pc, 1), value);
+                self.bus_write(add16(self.0.cpu.pc, 1), value);
                 self.0.tick(4);
             }
             Reg::Im16 => {
                 let adress = self.read_next_pc16();
-                self.0.write(adress, value);
+                self.bus_write(adress, value);
                 self.0.tick(4);
             }
             Reg::BC => {
-                self.0.write(self.0.cpu.bc(), value);
+                self.bus_write(self.0.cpu.bc(), value);
                 self.0.tick(4);
             }
             Reg::DE => {
-                self.0.write(self.0.cpu.de(), value);
+                self.bus_write(self.0.cpu.de(), value);
                 self.0.tick(4);
             }
             Reg::HL => {
-                self.0.write(self.0.cpu.hl(), value);
+                self.bus_write(self.0.cpu.hl(), value);
                 self.0.tick(4);
             }
             Reg::HLI => {
-                self.0.write(self.0.cpu.hl(), value);
+                self.bus_write(self.0.cpu.hl(), value);
                 self.0.tick(4);
                 self.0.cpu.set_hl(add16(self.0.cpu.hl(), 1));
             }
             Reg::HLD => {
-                self.0.write(self.0.cpu.hl(), value);
+                self.bus_write(self.0.cpu.hl(), value);
                 self.0.tick(4);
                 self.0.cpu.set_hl(sub16(self.0.cpu.hl(), 1));
             }
@@ -347,13 +454,13 @@ impl Interpreter<'_> {
         let src = match src {
             Reg::A => self.0.cpu.a,
             Reg::C => {
-                let v = self.0.read(0xFF00 | self.0.cpu.c as u16);
+                let v = self.bus_read(0xFF00 | self.0.cpu.c as u16);
                 self.0.tick(4);
                 v
             }
             Reg::Im8 => {
                 let r8 = self.read_next_pc();
-                let v = self.0.read(0xFF00 | r8 as u16);
+                let v = self.bus_read(0xFF00 | r8 as u16);
                 self.0.tick(4);
                 v
             }
@@ -363,12 +470,12 @@ impl Interpreter<'_> {
         match dst {
             Reg::A => self.0.cpu.a = src,
             Reg::C => {
-                self.0.write(0xFF00 | self.0.cpu.c as u16, src);
+                self.bus_write(0xFF00 | self.0.cpu.c as u16, src);
                 self.0.tick(4);
             }
             Reg::Im8 => {
                 let r8 = self.read_next_pc();
-                self.0.write(0xFF00 | r8 as u16, src);
+                self.bus_write(0xFF00 | r8 as u16, src);
                 self.0.tick(4);
             }
             _ => unreachable!(),
@@ -397,7 +504,9 @@ impl Interpreter<'_> {
             Reg16::SP => self.0.cpu.sp = v,
             Reg16::Im16 => {
                 let adress = self.read_next_pc16();
-                self.0.write16(adress, v)
+                let [lsb, msb] = v.to_le_bytes();
+                self.bus_write(adress, lsb);
+                self.bus_write(add16(adress, 1), msb);
             }
             _ => unreachable!(),
         }
@@ -576,8 +685,10 @@ impl Interpreter<'_> {
     }
 
     fn rst(&mut self, address: u8) {
-        self.pushr(self.0.cpu.pc);
+        let return_address = self.0.cpu.pc;
+        self.pushr(return_address);
         self.jump_to(address as u16);
+        self.push_frame(return_address, false);
     }
 
     fn rlc(&mut self, reg: Reg) {
@@ -684,7 +795,7 @@ impl Interpreter<'_> {
     }
 
     pub fn will_read_from(&self) -> (u8, [u16; 2]) {
-        let op = self.0.read(self.0.cpu.pc);
+        let op = self.0.peek(self.0.cpu.pc);
         let none = (0, [0, 0]);
         let some = |x| (1, [x, 0]);
         match op {
@@ -692,7 +803,7 @@ impl Interpreter<'_> {
             0x1a => some(self.0.cpu.de()),
             0x2a | 0x3a | 0x46 | 0x4e | 0x56 | 0x5e | 0x66 | 0x6e | 0x7e | 0x86 | 0x8e | 0x96
             | 0x9e | 0xa6 | 0xae | 0xb6 | 0xbe => some(self.0.cpu.hl()),
-            0xcb => match self.0.read(add16(self.0.cpu.pc, 1)) {
+            0xcb => match self.0.peek(add16(self.0.cpu.pc, 1)) {
                 0x06 | 0x0e | 0x16 | 0x1e | 0x26 | 0x2e | 0x36 | 0x3e | 0x46 | 0x4e | 0x56
                 | 0x5e | 0x66 | 0x6e | 0x76 | 0x7e | 0x86 | 0x8e | 0x96 | 0x9e | 0xa6 | 0xae
                 | 0xb6 | 0xbe | 0xc6 | 0xce | 0xd6 | 0xde | 0xe6 | 0xee | 0xf6 | 0xfe => {
@@ -701,11 +812,11 @@ impl Interpreter<'_> {
                 _ => none,
             },
             0xf0 => {
-                let r8 = self.0.read(add16(self.0.cpu.pc, 1));
+                let r8 = self.0.peek(add16(self.0.cpu.pc, 1));
                 some(0xff00 | r8 as u16)
             }
             0xf2 => some(0xff00 | self.0.cpu.c as u16),
-            0xfa => some(self.0.read16(add16(self.0.cpu.pc, 1))),
+            0xfa => some(self.0.peek16(add16(self.0.cpu.pc, 1))),
             0xc0 | 0xc1 | 0xc8 | 0xc9 | 0xd0 | 0xd1 | 0xd8 | 0xd9 | 0xe1 | 0xf1 => {
                 // POP or RET
                 (2, [add16(self.0.cpu.sp, 1), self.0.cpu.sp])
@@ -715,13 +826,13 @@ impl Interpreter<'_> {
     }
 
     pub fn will_write_to(&self) -> (u8, [u16; 2]) {
-        let op = self.0.read(self.0.cpu.pc);
+        let op = self.0.peek(self.0.cpu.pc);
         let none = (0, [0, 0]);
         let some = |x| (1, [x, 0]);
         match op {
             0x02 => some(self.0.cpu.bc()),
             0x08 => {
-                let adress = self.0.read16(add16(self.0.cpu.pc, 1));
+                let adress = self.0.peek16(add16(self.0.cpu.pc, 1));
                 (2, [adress, add16(adress, 1)])
             }
             0x12 => some(self.0.cpu.de()),
@@ -734,18 +845,18 @@ impl Interpreter<'_> {
                 // PUSH .. or CALL .. or RST
                 (2, [sub16(self.0.cpu.sp, 1), sub16(self.0.cpu.sp, 2)])
             }
-            0xcb => match self.0.read(self.0.cpu.pc + 1) {
+            0xcb => match self.0.peek(self.0.cpu.pc + 1) {
                 0x06 | 0x0e | 0x16 | 0x1e | 0x26 | 0x2e | 0x36 | 0x3e | 0x86 | 0x8e | 0x96
                 | 0x9e | 0xa6 | 0xae | 0xb6 | 0xbe | 0xc6 | 0xce | 0xd6 | 0xde | 0xe6 | 0xee
                 | 0xf6 | 0xfe => some(self.0.cpu.hl()),
                 _ => none,
             },
             0xe0 => {
-                let r8 = self.0.read(add16(self.0.cpu.pc, 1));
+                let r8 = self.0.peek(add16(self.0.cpu.pc, 1));
                 some(0xFF00 | r8 as u16)
             }
             0xe2 => some(0xFF00 | self.0.cpu.c as u16),
-            0xea => some(self.0.read16(add16(self.0.cpu.pc, 1))),
+            0xea => some(self.0.peek16(add16(self.0.cpu.pc, 1))),
             _ => none,
         }
     }
@@ -753,9 +864,9 @@ impl Interpreter<'_> {
     pub fn will_jump_to(&self) -> Option<u16> {
         let pc = self.0.cpu.pc;
         let op = &[
-            self.0.read(pc),
-            self.0.read(add16(pc, 1)),
-            self.0.read(add16(pc, 2)),
+            self.0.peek(pc),
+            self.0.peek(add16(pc, 1)),
+            self.0.peek(add16(pc, 2)),
         ];
         let len = consts::LEN[op[0] as usize];
         match op[0] {
@@ -862,7 +973,85 @@ impl Interpreter<'_> {
         }
     }
 
+    /// Run `interpret_op` once, and return what was executed.
+    pub fn step_instruction(&mut self) -> ExecutedInstruction {
+        let pc = self.0.cpu.pc;
+        let bank = self.0.pc_bank(pc);
+        let interrupts = self.0.interrupt_flag & self.0.interrupt_enabled;
+        let kind = if interrupts != 0 && self.0.cpu.ime == ImeState::Enabled {
+            StepKind::Interrupt
+        } else if interrupts == 0 && self.0.cpu.state != CpuState::Running {
+            StepKind::Halted
+        } else {
+            StepKind::Instruction
+        };
+
+        let mut opcode = [0; 3];
+        let mut len = 0;
+        let mut text = String::new();
+        if kind == StepKind::Instruction {
+            opcode = [
+                self.0.read(pc),
+                self.0.read(add16(pc, 1)),
+                self.0.read(add16(pc, 2)),
+            ];
+            len = consts::LEN[opcode[0] as usize];
+            let _ = disassembly_opcode(
+                pc,
+                &opcode[..len as usize],
+                |x| format!("${:04x}", x),
+                &mut text,
+            );
+            text.truncate(text.trim_end().len());
+        }
+
+        let before = registers(&self.0.cpu);
+        let clock_count = self.0.clock_count;
+        self.interpret_op();
+        let cycles = self.0.clock_count - clock_count;
+        let after = registers(&self.0.cpu);
+
+        let changes = before
+            .iter()
+            .zip(after.iter())
+            .filter(|(a, b)| a.1 != b.1)
+            .map(|(&(register, before), &(_, after))| RegisterChange {
+                register,
+                before,
+                after,
+            })
+            .collect();
+
+        ExecutedInstruction {
+            kind,
+            pc,
+            bank,
+            opcode,
+            len,
+            text,
+            cycles,
+            changes,
+        }
+    }
+
     pub fn interpret_op(&mut self) {
+        self.interpret_op_checking::<true>()
+    }
+
+    /// Like `interpret_op`, but never checks `GameBoy::profiling`, as if the profiling didn't
+    /// exist. This is the baseline of the benchmark of the profiling overhead.
+    #[cfg(feature = "test-util")]
+    pub fn interpret_op_without_profiling(&mut self) {
+        self.interpret_op_checking::<false>()
+    }
+
+    /// Interpret the next operation, checking `GameBoy::profiling` only if `PROFILING` is true.
+    #[inline(always)]
+    fn interpret_op_checking<const PROFILING: bool>(&mut self) {
+        if self.0.watchpoints.any() {
+            self.0.watchpoints.pc = self.0.cpu.pc;
+        }
+
         if self.0.v_blank_trigger {
             self.0.v_blank_trigger = false;
             self.0.call_v_blank_callback();
@@ -878,26 +1067,38 @@ impl Interpreter<'_> {
             self.0.tick(2);
         }
 
-        // TODO: I don't know the behaviour of Stopped state. Treating the same as Halt.
+        // The STOP mode is only exited when a selected input line of the joypad is low, even if the
+        // joypad interrupt is disabled. Other interrupts don't wake it.
         if self.0.cpu.state == CpuState::Stopped {
             self.0.tick(2);
+            if self.0.joypad_lines() == 0x0F {
+                return;
+            }
+            self.0.cpu.state = CpuState::Running;
         }
 
         if interrupts != 0 {
             self.0.cpu.state = CpuState::Running;
 
             if self.0.cpu.ime == ImeState::Enabled {
+                let event = self.0.interrupt_log.is_some().then(|| {
+                    let kind = InterruptEventKind::Dispatch {
+                        interrupt: None,
+                        handler: 0x0000,
+                    };
+                    InterruptEvent::new(self.0, kind, self.0.cpu.pc)
+                });
                 self.0.cpu.ime = ImeState::Disabled;
                 self.0.tick(4);
                 let mut interrupt = 8;
                 let mut address = 0x00;
                 // The push could overwrite IE, canceling the jump, but only it first write. PC is
                 // set to 0x0000 instead.
+                let return_address = self.0.cpu.pc;
                 {
-                    let value = self.0.cpu.pc;
-                    let [lsb, msb] = value.to_le_bytes();
+                    let [lsb, msb] = return_address.to_le_bytes();
                     self.0.tick(4); // 1 M-cycle with SP in address buss
-                    self.0.write(sub16(self.0.cpu.sp, 1), msb);
+                    self.bus_write(sub16(self.0.cpu.sp, 1), msb);
                     if self.0.interrupt_enabled & self.0.interrupt_flag != 0 {
                         interrupt = (self.0.interrupt_flag & self.0.interrupt_enabled)
                             .trailing_zeros() as usize;
@@ -910,7 +1111,7 @@ impl Interpreter<'_> {
                         ][interrupt];
                     }
                     self.0.tick(4); // 1 M-cycle with SP-1 in address buss
-                    self.0.write(sub16(self.0.cpu.sp, 2), lsb);
+                    self.bus_write(sub16(self.0.cpu.sp, 2), lsb);
                     self.0.tick(4); // 1 M-cycle with SP-2 in address buss
                     self.0.cpu.sp = sub16(self.0.cpu.sp, 2);
                 };
@@ -921,6 +1122,14 @@ impl Interpreter<'_> {
                 } else {
                     self.jump_to(0x0000);
                 }
+                if let (Some(mut event), Some(log)) = (event, &mut self.0.interrupt_log) {
+                    event.kind = InterruptEventKind::Dispatch {
+                        interrupt: (interrupt != 8).then(|| interrupt as u8),
+                        handler: self.0.cpu.pc,
+                    };
+                    log.push(event);
+                }
+                self.push_frame(return_address, true);
                 self.0.tick(4);
 
                 // return, to allow detecting the interrupt
@@ -928,6 +1137,7 @@ impl Interpreter<'_> {
             }
         }
         if self.0.cpu.ime == ImeState::ToBeEnable {
+            self.log_interrupt_event(InterruptEventKind::ImeEnabled, self.0.cpu.pc);
             self.0.cpu.ime = ImeState::Enabled;
         }
 
@@ -935,25 +1145,59 @@ impl Interpreter<'_> {
             return;
         }
 
+        self.0.instruction_count += 1;
+
+        if let Some(mut trace_logger) = self.0.trace_logger.take() {
+            trace_logger.log(self.0);
+            self.0.trace_logger = Some(trace_logger);
+        }
+
+        {
+            let mut trace = self.0.trace.borrow_mut();
+            if trace.is_counting() {
+                let bank = self.0.cartridge.curr_rom_bank();
+                trace.count_execution(bank, self.0.cpu.pc);
+            }
+        }
+
+        if self.0.instruction_history.is_some() {
+            let entry = HistoryEntry::new(self.0);
+            if let Some(history) = &mut self.0.instruction_history {
+                history.push(entry);
+            }
+        }
+
+        if PROFILING && self.0.profiling.is_some() {
+            self.interpret_instruction_profiled();
+        } else {
+            self.interpret_instruction();
+        }
+        self.0.call_stack.update(self.0.cpu.sp);
+    }
+
+    /// Interpret the current instruction, and record it in the `GameBoy`'s `ProfilingData`.
+    fn interpret_instruction_profiled(&mut self) {
+        let pc = self.0.cpu.pc;
+        let bank = self.0.pc_bank(pc);
+        let op = self.0.read(pc);
+        let cb_op = if op == 0xcb {
+            Some(self.0.read(add16(pc, 1)))
+        } else {
+            None
+        };
+        let clock_count = self.0.clock_count;
+
+        self.interpret_instruction();
+
+        let cycles = self.0.clock_count - clock_count;
+        if let Some(profiling) = &mut self.0.profiling {
+            profiling.record(bank, pc, op, cb_op, cycles);
+        }
+    }
+
+    fn interpret_instruction(&mut self) {
         use Condition::*;
         let op = self.read_next_pc();
-        let trace = false;
-        if trace {
-            println!(
-                "{:04x}: {:02x} {:04x} {:02x} {:02x} {:02x} {:02x} {:02x} {:02x} {:02x} {:02x}",
-                self.0.cpu.pc,
-                op,
-                self.0.cpu.sp,
-                self.0.cpu.a,
-                self.0.cpu.f.0,
-                self.0.cpu.b,
-                self.0.cpu.c,
-                self.0.cpu.d,
-                self.0.cpu.e,
-                self.0.cpu.h,
-                self.0.cpu.l,
-            );
-        }
         match op {
             0x00 => {
                 // NOP 1:4 - - - -
@@ -1900,6 +2144,7 @@ impl Interpreter<'_> {
             }
             0xd9 => {
                 // RETI 1:16 - - - -
+                self.log_interrupt_event(InterruptEventKind::Reti, sub16(self.0.cpu.pc, 1));
                 self.ret(None);
                 self.0.cpu.ime = ImeState::Enabled;
                 return;
@@ -2021,6 +2266,7 @@ impl Interpreter<'_> {
             }
             0xf3 => {
                 // DI 1:4 - - - -
+                self.log_interrupt_event(InterruptEventKind::Di, sub16(self.0.cpu.pc, 1));
                 self.0.cpu.ime = ImeState::Disabled;
             }
             0xf4 => {
@@ -2071,6 +2317,10 @@ impl Interpreter<'_> {
             0xfb => {
                 // EI 1:4 - - - -
                 if self.0.cpu.ime == ImeState::Disabled {
+                    self.log_interrupt_event(
+                        InterruptEventKind::EiScheduled,
+                        sub16(self.0.cpu.pc, 1),
+                    );
                     self.0.cpu.ime = ImeState::ToBeEnable;
                 }
                 return;
@@ -4,4 +4,6 @@ pub mod disassembler;
 pub mod gameboy;
 pub mod interpreter;
 pub mod parser;
+pub mod profiling;
 pub mod save_state;
+pub mod trace_logger;
@@ -0,0 +1,202 @@
+use std::{collections::BTreeMap, fmt::Write, io};
+
+use crate::{
+    consts::LEN,
+    disassembler::{disassembly_opcode, Trace},
+};
+
+/// The size of each address range that `ProfilingData` aggregates cycles into.
+pub const RANGE_SIZE: u16 = 0x100;
+
+/// Execution statistics collected by the interpreter, when `GameBoy::profiling` is set.
+#[derive(Clone)]
+pub struct ProfilingData {
+    /// Number of times each opcode was executed.
+    pub op_count: [u64; 256],
+    /// Number of cycles consumed by each opcode.
+    pub op_cycles: [u64; 256],
+    /// Number of times each CB-prefixed opcode was executed.
+    pub cb_op_count: [u64; 256],
+    /// Number of cycles consumed by each CB-prefixed opcode.
+    pub cb_op_cycles: [u64; 256],
+    /// Number of cycles consumed in each ROM bank. `None` is for code running outside the ROM.
+    pub bank_cycles: BTreeMap<Option<u16>, u64>,
+    /// Number of cycles consumed in each range of `RANGE_SIZE` bytes, indexed by the bank and the
+    /// start address of the range.
+    pub range_cycles: BTreeMap<(Option<u16>, u16), u64>,
+    /// Number of cycles consumed by the instruction at each address, indexed by the bank and the
+    /// address of the instruction.
+    pub pc_cycles: BTreeMap<(Option<u16>, u16), u64>,
+}
+impl Default for ProfilingData {
+    fn default() -> Self {
+        Self {
+            op_count: [0; 256],
+            op_cycles: [0; 256],
+            cb_op_count: [0; 256],
+            cb_op_cycles: [0; 256],
+            bank_cycles: BTreeMap::new(),
+            range_cycles: BTreeMap::new(),
+            pc_cycles: BTreeMap::new(),
+        }
+    }
+}
+impl ProfilingData {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Clear all collected data.
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    /// Record the execution of a instruction, that take `cycles` clock cycles.
+    pub fn record(&mut self, bank: Option<u16>, pc: u16, op: u8, cb_op: Option<u8>, cycles: u64) {
+        match cb_op {
+            Some(cb_op) => {
+                self.cb_op_count[cb_op as usize] += 1;
+                self.cb_op_cycles[cb_op as usize] += cycles;
+            }
+            None => {
+                self.op_count[op as usize] += 1;
+                self.op_cycles[op as usize] += cycles;
+            }
+        }
+        *self.bank_cycles.entry(bank).or_default() += cycles;
+        *self
+            .range_cycles
+            .entry((bank, pc - pc % RANGE_SIZE))
+            .or_default() += cycles;
+        *self.pc_cycles.entry((bank, pc)).or_default() += cycles;
+    }
+
+    /// The total number of cycles recorded.
+    pub fn total_cycles(&self) -> u64 {
+        self.op_cycles.iter().sum::<u64>() + self.cb_op_cycles.iter().sum::<u64>()
+    }
+
+    /// The `top` addresses whose instructions consumed the most cycles, and their cycles, from the
+    /// hottest to the coldest.
+    pub fn hot_addresses(&self, top: usize) -> Vec<((Option<u16>, u16), u64)> {
+        let mut addresses: Vec<_> = self.pc_cycles.iter().map(|(&k, &v)| (k, v)).collect();
+        addresses.sort_by_key(|&(_, cycles)| std::cmp::Reverse(cycles));
+        addresses.truncate(top);
+        addresses
+    }
+
+    /// Write the cycles of each address as CSV, with the columns `bank,address,cycles,symbol`. The
+    /// bank is empty for code running outside the ROM, and the symbol is the closest label in
+    /// `trace`, if any.
+    pub fn write_csv(&self, trace: &Trace, w: &mut impl io::Write) -> io::Result<()> {
+        writeln!(w, "bank,address,cycles,symbol")?;
+        for (&(bank, address), &cycles) in self.pc_cycles.iter() {
+            let symbol = symbol_name(trace, bank, address).unwrap_or_default();
+            let bank = bank.map_or(String::new(), |x| format!("{:02x}", x));
+            writeln!(w, "{},{:04x},{},{}", bank, address, cycles, symbol)?;
+        }
+        Ok(())
+    }
+
+    /// Write a report of the `top` opcodes, address ranges and addresses that consumed the most
+    /// cycles. The addresses are named by the labels in `trace`, if a symbol file was loaded.
+    pub fn report(&self, top: usize, trace: &Trace, w: &mut impl Write) -> std::fmt::Result {
+        let total = self.total_cycles().max(1) as f64;
+        let percent = |cycles: u64| 100.0 * cycles as f64 / total;
+
+        let mut ops: Vec<(bool, u8, u64, u64)> = (0..=255u8)
+            .map(|op| {
+                let i = op as usize;
+                (false, op, self.op_count[i], self.op_cycles[i])
+            })
+            .chain((0..=255u8).map(|op| {
+                let i = op as usize;
+                (true, op, self.cb_op_count[i], self.cb_op_cycles[i])
+            }))
+            .filter(|&(_, _, count, _)| count != 0)
+            .collect();
+        ops.sort_by(|a, b| b.3.cmp(&a.3));
+
+        writeln!(w, "total cycles: {}", self.total_cycles())?;
+        writeln!(w, "hot opcodes:")?;
+        for &(cb, op, count, cycles) in ops.iter().take(top) {
+            let mut name = String::new();
+            let (bytes, len) = if cb {
+                ([0xcb, op, 0], 2)
+            } else {
+                ([op, 0, 0], LEN[op as usize] as usize)
+            };
+            let _ = disassembly_opcode(0, &bytes[..len], |x| format!("${:04x}", x), &mut name);
+            let code = if cb {
+                format!("cb {:02x}", op)
+            } else {
+                format!("{:02x}", op)
+            };
+            writeln!(
+                w,
+                "  {:5} {:16} {:10} times {:12} cycles ({:5.2}%)",
+                code,
+                name,
+                count,
+                cycles,
+                percent(cycles),
+            )?;
+        }
+
+        writeln!(w, "cycles by bank:")?;
+        for (&bank, &cycles) in self.bank_cycles.iter() {
+            let bank = bank.map_or("ram".to_string(), |x| format!("{:02x}", x));
+            writeln!(
+                w,
+                "  {:3} {:12} cycles ({:5.2}%)",
+                bank,
+                cycles,
+                percent(cycles)
+            )?;
+        }
+
+        let mut ranges: Vec<_> = self.range_cycles.iter().collect();
+        ranges.sort_by(|a, b| b.1.cmp(a.1));
+        writeln!(w, "hot ranges:")?;
+        for (&(bank, start), &cycles) in ranges.iter().take(top) {
+            let bank = bank.map_or("--".to_string(), |x| format!("{:02x}", x));
+            writeln!(
+                w,
+                "  {}:{:04x}-{:04x} {:12} cycles ({:5.2}%)",
+                bank,
+                start,
+                start + (RANGE_SIZE - 1),
+                cycles,
+                percent(cycles),
+            )?;
+        }
+
+        writeln!(w, "hot addresses:")?;
+        for ((bank, address), cycles) in self.hot_addresses(top) {
+            let symbol =
+                symbol_name(trace, bank, address).map_or(String::new(), |x| format!(" {}", x));
+            let bank = bank.map_or("--".to_string(), |x| format!("{:02x}", x));
+            writeln!(
+                w,
+                "  {}:{:04x} {:12} cycles ({:5.2}%){}",
+                bank,
+                address,
+                cycles,
+                percent(cycles),
+                symbol,
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The name of a address from the closest label before it, like `Main` or `Main+12`.
+fn symbol_name(trace: &Trace, bank: Option<u16>, address: u16) -> Option<String> {
+    let (name, offset) = trace.nearest_symbol(bank, address)?;
+    Some(if offset == 0 {
+        name.to_string()
+    } else {
+        format!("{}+{}", name, offset)
+    })
+}
@@ -12,6 +12,29 @@ pub enum LoadStateError {
     IoError(std::io::Error),
     InvalidMagicConst([u8; 4]),
     UnknownVersion(u32),
+    /// The save state is from a different rom. Contains the CRC-32 of the rom in the save state,
+    /// and of the current one.
+    RomMismatch(u32, u32),
+}
+impl std::fmt::Display for LoadStateError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::IoError(e) => write!(f, "malformed save state: {}", e),
+            Self::InvalidMagicConst(_) => write!(f, "not a save state"),
+            Self::UnknownVersion(v) => write!(
+                f,
+                "the save state has version {}, but only up to version {} is supported",
+                v,
+                SaveStateHeader::SAVE_STATE_VERSION
+            ),
+            Self::RomMismatch(state, rom) => write!(
+                f,
+                "the save state is from a different rom (CRC-32 {:08x}, expected {:08x})",
+                state, rom
+            ),
+            e => write!(f, "malformed save state: {:?}", e),
+        }
+    }
 }
 impl From<std::io::Error> for LoadStateError {
     fn from(error: std::io::Error) -> Self {
@@ -30,14 +53,17 @@ pub struct SaveStateHeader {
     pub version: u32,
     /// The instant that this file was saved, in number of milliseconds since the UNIX_EPOCH
     pub time: Option<u64>,
+    /// The CRC-32 of the rom of the saved GameBoy. Added in version 6.
+    pub rom_crc32: Option<u32>,
 }
 impl SaveStateHeader {
     /// The current version of the save state format
-    const SAVE_STATE_VERSION: u32 = 2;
+    const SAVE_STATE_VERSION: u32 = 6;
     const MAGIC_CONST: [u8; 4] = *b"GRST";
 
-    /// Create a new SaveStateHeader with default values and current SystemTime.
-    pub fn new() -> Self {
+    /// Create a new SaveStateHeader with default values, current SystemTime, and the given rom
+    /// checksum.
+    pub fn new(rom_crc32: u32) -> Self {
         use std::{
             convert::TryInto,
             time::{Duration, SystemTime},
@@ -54,6 +80,7 @@ impl SaveStateHeader {
             magic: Self::MAGIC_CONST,
             version: Self::SAVE_STATE_VERSION,
             time: Some(time),
+            rom_crc32: Some(rom_crc32),
         }
     }
 }
@@ -63,6 +90,7 @@ impl Default for SaveStateHeader {
             magic: Self::MAGIC_CONST,
             version: Self::SAVE_STATE_VERSION,
             time: None,
+            rom_crc32: None,
         }
     }
 }
@@ -73,6 +101,9 @@ impl SaveState for SaveStateHeader {
         self.time
             .expect("SaveStateHeader::new() always have time")
             .save_state(data)?;
+        self.rom_crc32
+            .expect("SaveStateHeader::new() always have rom_crc32")
+            .save_state(data)?;
         Ok(())
     }
 
@@ -88,6 +119,14 @@ impl SaveState for SaveStateHeader {
             self.time = None;
         }
 
+        if self.version >= 6 {
+            let mut rom_crc32 = 0;
+            rom_crc32.load_state(data)?;
+            self.rom_crc32 = Some(rom_crc32);
+        } else {
+            self.rom_crc32 = None;
+        }
+
         if self.magic != Self::MAGIC_CONST {
             return Err(LoadStateError::InvalidMagicConst(self.magic));
         }
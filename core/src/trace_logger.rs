@@ -0,0 +1,109 @@
+use std::{
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+use crate::{
+    consts::LEN,
+    disassembler::disassembly_opcode,
+    gameboy::{cpu::Cpu, GameBoy},
+};
+
+/// Writes one line per executed instruction, when set in `GameBoy::trace_logger`.
+///
+/// Each line is in the format used by others emulators for comparing execution logs:
+///
+/// ```text
+/// A:01 F:Z-HC B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0100 (cy: 23440324) |[00]0x0100: NOP
+/// ```
+///
+/// The output is buffered, and is flushed when the logger is dropped, including when unwinding
+/// from a panic.
+pub struct TraceLogger {
+    writer: BufWriter<Box<dyn Write + Send>>,
+    /// The number of lines written so far.
+    pub lines: u64,
+    /// The first error that happened while writing, if any. After it the logger stops writing.
+    pub error: Option<io::Error>,
+    line: String,
+}
+impl TraceLogger {
+    pub fn new(writer: impl Write + Send + 'static) -> Self {
+        Self {
+            writer: BufWriter::with_capacity(1 << 16, Box::new(writer)),
+            lines: 0,
+            error: None,
+            line: String::new(),
+        }
+    }
+
+    /// Create a logger that writes to the file at `path`, truncating it.
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self::new(std::fs::File::create(path)?))
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+
+    /// Write the line for the instruction at the current PC of `gb`, before it is executed.
+    pub fn log(&mut self, gb: &GameBoy) {
+        if self.error.is_some() {
+            return;
+        }
+        self.line.clear();
+        let _ = format_line(gb, &mut self.line);
+        self.line.push('\n');
+        match self.writer.write_all(self.line.as_bytes()) {
+            Ok(()) => self.lines += 1,
+            Err(err) => self.error = Some(err),
+        }
+    }
+}
+impl Drop for TraceLogger {
+    fn drop(&mut self) {
+        let _ = self.writer.flush();
+    }
+}
+
+fn flags(cpu: &Cpu) -> [char; 4] {
+    let f = &cpu.f;
+    [
+        if f.z() { 'Z' } else { '-' },
+        if f.n() { 'N' } else { '-' },
+        if f.h() { 'H' } else { '-' },
+        if f.c() { 'C' } else { '-' },
+    ]
+}
+
+/// Format the trace line of the instruction at the current PC of `gb`, without the newline.
+pub fn format_line(gb: &GameBoy, w: &mut impl std::fmt::Write) -> std::fmt::Result {
+    let cpu = &gb.cpu;
+    let pc = cpu.pc;
+    let [z, n, h, c] = flags(cpu);
+    write!(
+        w,
+        "A:{:02X} F:{}{}{}{} B:{:02X} C:{:02X} D:{:02X} E:{:02X} H:{:02X} L:{:02X} SP:{:04X} PC:{:04X} (cy: {}) |",
+        cpu.a, z, n, h, c, cpu.b, cpu.c, cpu.d, cpu.e, cpu.h, cpu.l, cpu.sp, pc, gb.clock_count,
+    )?;
+    match gb.pc_bank(pc) {
+        Some(bank) => write!(w, "[{:02x}]", bank)?,
+        None => write!(w, "[--]")?,
+    }
+    write!(w, "0x{:04X}: ", pc)?;
+
+    let opcode = [
+        gb.peek(pc),
+        gb.peek(pc.wrapping_add(1)),
+        gb.peek(pc.wrapping_add(2)),
+    ];
+    let len = (LEN[opcode[0] as usize] as usize).max(1);
+    let mut text = String::new();
+    let trace = gb.trace.borrow();
+    let label = |x| match trace.symbol_at(gb, x) {
+        Some(name) => name.to_string(),
+        None => format!("${:04x}", x),
+    };
+    disassembly_opcode(pc, &opcode[..len], label, &mut text)?;
+    w.write_str(text.trim_end())
+}
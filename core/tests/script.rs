@@ -0,0 +1,101 @@
+#![cfg(feature = "script")]
+
+use gameroy::{
+    debugger::{overlay::OverlayText, Debugger},
+    gameboy::{cartridge::Cartridge, GameBoy},
+};
+
+fn program_cartridge(program: &[u8]) -> Cartridge {
+    let mut rom = vec![0; 0x8000];
+    rom[0x100..0x100 + program.len()].copy_from_slice(program);
+    Cartridge::new(rom).unwrap()
+}
+
+#[test]
+fn tetris_score_overlay() {
+    // write the score 123456 where Tetris keeps it, in BCD
+    let cartridge = program_cartridge(&[
+        0x3E, 0x56, // LD A,$56
+        0xEA, 0xA0, 0xC0, // LD ($C0A0),A
+        0x3E, 0x34, // LD A,$34
+        0xEA, 0xA1, 0xC0, // LD ($C0A1),A
+        0x3E, 0x12, // LD A,$12
+        0xEA, 0xA2, 0xC0, // LD ($C0A2),A
+        0x18, 0xFE, // JR -2
+    ]);
+    let mut game_boy = GameBoy::new(None, cartridge);
+    let mut debugger = Debugger::default();
+
+    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/../examples/tetris_score.rhai");
+    debugger
+        .execute_command(&mut game_boy, &["script", "run", path])
+        .unwrap();
+    debugger.run_for(&mut game_boy, 3 * 70224);
+
+    let overlay = debugger.overlay.lock().unwrap().clone();
+    assert_eq!(
+        overlay.texts,
+        [OverlayText {
+            x: 2,
+            y: 2,
+            text: "SCORE 123456".to_string(),
+        }]
+    );
+
+    // the text is drawn in black over a white box
+    let mut rgba = vec![0x80; 160 * 144 * 4];
+    overlay.draw(&mut rgba, 160, 144);
+    let pixel = |x: usize, y: usize| rgba[(x + y * 160) * 4];
+    assert_eq!(pixel(1, 1), 255); // the margin of the box
+    assert_eq!(pixel(2, 2), 255); // the top left of 'S' is empty
+    assert_eq!(pixel(3, 2), 0);
+    assert_eq!(pixel(100, 100), 0x80);
+
+    debugger
+        .execute_command(&mut game_boy, &["script", "stop"])
+        .unwrap();
+    assert!(debugger.overlay.lock().unwrap().is_empty());
+}
+
+#[test]
+fn script_callbacks() {
+    let cartridge = program_cartridge(&[
+        0x3E, 0x07, // LD A,$07
+        0xEA, 0x00, 0xC0, // LD ($C000),A
+        0x18, 0xF9, // JR -7
+    ]);
+    let mut game_boy = GameBoy::new(None, cartridge);
+    let mut debugger = Debugger::default();
+
+    let script = std::env::temp_dir().join("gameroy_script_callbacks.rhai");
+    std::fs::write(
+        &script,
+        r#"
+        on_breakpoint(0x0100, |pc| {
+            // count the loops, until 100
+            let count = read(0xC001);
+            if count < 100 {
+                write(0xC001, count + 1);
+            }
+        });
+        on_memory_write(0xC000..=0xC000, |address, value| write(0xC002, value + 1));
+        on_frame(|| set_button("start", true));
+        save_state(0);
+        print("loaded");
+        "#,
+    )
+    .unwrap();
+    let output = debugger
+        .execute_command(&mut game_boy, &["script", "run", script.to_str().unwrap()])
+        .unwrap();
+    assert!(output.ends_with("loaded"), "{}", output);
+
+    debugger.run_for(&mut game_boy, 3 * 70224);
+    assert_eq!(game_boy.peek(0xC001), 100);
+    assert_eq!(game_boy.peek(0xC002), 0x08);
+    // Start is bit 7, and 0 means pressed
+    assert_eq!(game_boy.joypad & 0x80, 0);
+
+    let err = debugger.execute_command(&mut game_boy, &["script", "run", "missing.rhai"]);
+    assert!(err.is_err());
+}
@@ -0,0 +1,257 @@
+//! Runs the single instruction tests from [SingleStepTests/sm83](https://github.com/SingleStepTests/sm83).
+//!
+//! Each test case gives the initial state of the CPU and memory, and the expected state after
+//! executing a single instruction, together with the bus activity of each M-cycle (only the number
+//! of M-cycles is checked). The memory is replaced by a flat 64 KiB memory, so the tests can read
+//! and write to any address.
+//!
+//! The tests are expected to be at `tests/sm83/v1/`, or at the directory in the `SM83_TESTS_PATH`
+//! environment variable. Only a subset of the opcodes is run by default, to run all of them use
+//! `cargo test -p gameroy-core --test sm83 -- --ignored`. The base and the CB prefixed opcodes can
+//! also be run separately, with the `sm83_base` and `sm83_cb` tests.
+
+use std::collections::BTreeMap;
+
+use gameroy::{
+    gameboy::{
+        cpu::{CpuState, Flags, ImeState},
+        GameBoy,
+    },
+    interpreter::Interpreter,
+};
+use serde_json::Value;
+
+const SM83_TESTS_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/sm83/v1/");
+
+/// The maximum number of failed test cases reported for each opcode.
+const MAX_REPORTED_FAILURES: usize = 5;
+
+/// The directory with the test files.
+fn tests_path() -> std::path::PathBuf {
+    std::env::var_os("SM83_TESTS_PATH").map_or_else(|| SM83_TESTS_PATH.into(), |x| x.into())
+}
+
+/// Opcodes that are run in the normal test run.
+const SUBSET: &[&str] = &[
+    "00", "01", "09", "0e", "18", "20", "27", "31", "3c", "80", "88", "90", "98", "a8", "c1", "c5",
+    "cd", "ce", "de", "e8", "f8", "cb 11", "cb 37", "cb 7c", "cb c6",
+];
+
+/// The number of passed and failed tests of a opcode file.
+#[derive(Default)]
+struct Summary {
+    passed: usize,
+    failed: usize,
+    /// The name and the first mismatching field of the first failed test cases.
+    failures: Vec<(String, String)>,
+}
+
+fn get(state: &Value, name: &str) -> u16 {
+    state[name]
+        .as_u64()
+        .unwrap_or_else(|| panic!("missing field '{}'", name)) as u16
+}
+
+fn set_state(gb: &mut GameBoy, state: &Value) {
+    let cpu = &mut gb.cpu;
+    cpu.a = get(state, "a") as u8;
+    cpu.f = Flags(get(state, "f") as u8);
+    cpu.b = get(state, "b") as u8;
+    cpu.c = get(state, "c") as u8;
+    cpu.d = get(state, "d") as u8;
+    cpu.e = get(state, "e") as u8;
+    cpu.h = get(state, "h") as u8;
+    cpu.l = get(state, "l") as u8;
+    cpu.sp = get(state, "sp");
+    // The tests already consider that the opcode was fetched in the previous instruction.
+    cpu.pc = get(state, "pc").wrapping_sub(1);
+    cpu.ime = if get(state, "ime") != 0 {
+        ImeState::Enabled
+    } else {
+        ImeState::Disabled
+    };
+    cpu.state = CpuState::Running;
+
+    let memory = gb.flat_memory.as_mut().unwrap();
+    for entry in state["ram"].as_array().unwrap() {
+        let address = entry[0].as_u64().unwrap() as usize;
+        memory[address] = entry[1].as_u64().unwrap() as u8;
+    }
+}
+
+fn check_state(gb: &GameBoy, state: &Value, clock_count: u64, cycles: usize) -> Result<(), String> {
+    let cpu = &gb.cpu;
+    let mut errors = Vec::new();
+    let mut check = |name: &str, value: u16| {
+        let expected = get(state, name);
+        if value != expected {
+            errors.push(format!(
+                "{}: {:04x}, expected {:04x}",
+                name, value, expected
+            ));
+        }
+    };
+    check("a", cpu.a as u16);
+    check("f", cpu.f.0 as u16);
+    check("b", cpu.b as u16);
+    check("c", cpu.c as u16);
+    check("d", cpu.d as u16);
+    check("e", cpu.e as u16);
+    check("h", cpu.h as u16);
+    check("l", cpu.l as u16);
+    check("sp", cpu.sp);
+    check("pc", cpu.pc.wrapping_add(1));
+
+    let memory = gb.flat_memory.as_ref().unwrap();
+    for entry in state["ram"].as_array().unwrap() {
+        let address = entry[0].as_u64().unwrap() as u16;
+        let expected = entry[1].as_u64().unwrap() as u8;
+        let value = memory[address as usize];
+        if value != expected {
+            errors.push(format!(
+                "[{:04x}]: {:02x}, expected {:02x}",
+                address, value, expected
+            ));
+        }
+    }
+
+    let elapsed = gb.clock_count - clock_count;
+    if elapsed != cycles as u64 * 4 {
+        errors.push(format!("clocks: {}, expected {}", elapsed, cycles * 4));
+    }
+
+    match errors.into_iter().next() {
+        None => Ok(()),
+        Some(first) => Err(first),
+    }
+}
+
+fn run_file(opcode: &str) -> Summary {
+    let path = tests_path().join(format!("{}.json", opcode));
+    let file = std::fs::read_to_string(&path)
+        .unwrap_or_else(|e| panic!("failed to read '{}': {}", path.display(), e));
+    let tests: Value = serde_json::from_str(&file).unwrap();
+
+    let mut gb = GameBoy::new_test_flat();
+
+    let mut summary = Summary::default();
+    for test in tests.as_array().unwrap() {
+        gb.flat_memory = Some(Box::new([0; 0x10000]));
+        gb.interrupt_flag = 0;
+        set_state(&mut gb, &test["initial"]);
+
+        let clock_count = gb.clock_count;
+        Interpreter(&mut gb).interpret_op();
+
+        let cycles = test["cycles"].as_array().map_or(0, |x| x.len());
+        match check_state(&gb, &test["final"], clock_count, cycles) {
+            Ok(()) => summary.passed += 1,
+            Err(msg) => {
+                summary.failed += 1;
+                if summary.failures.len() < MAX_REPORTED_FAILURES {
+                    let name = test["name"].as_str().unwrap_or("").to_string();
+                    summary.failures.push((name, msg));
+                }
+            }
+        }
+    }
+    summary
+}
+
+fn run_opcodes<'a>(opcodes: impl IntoIterator<Item = &'a str>) {
+    let mut summaries = BTreeMap::new();
+    for opcode in opcodes {
+        summaries.insert(opcode.to_string(), run_file(opcode));
+    }
+
+    let mut failed = 0;
+    println!("opcode  passed  failed");
+    for (opcode, summary) in summaries.iter() {
+        println!("{:6}  {:6}  {:6}", opcode, summary.passed, summary.failed);
+        for (name, msg) in &summary.failures {
+            println!("    '{}': {}", name, msg);
+        }
+        if summary.failed != 0 {
+            failed += 1;
+        }
+    }
+
+    if failed != 0 {
+        panic!("{} of {} opcodes failed", failed, summaries.len());
+    }
+}
+
+/// All opcodes that have a test file, which excludes the illegal opcodes, STOP and HALT.
+fn all_opcodes() -> Vec<String> {
+    let path = tests_path();
+    let mut opcodes: Vec<String> = std::fs::read_dir(&path)
+        .unwrap_or_else(|e| panic!("failed to read '{}': {}", path.display(), e))
+        .filter_map(|x| {
+            let path = x.ok()?.path();
+            if path.extension()? != "json" {
+                return None;
+            }
+            Some(path.file_stem()?.to_string_lossy().into_owned())
+        })
+        .filter(|x| x != "10" && x != "76")
+        .collect();
+    opcodes.sort();
+    opcodes
+}
+
+#[test]
+fn sm83_subset() {
+    run_opcodes(SUBSET.iter().copied());
+}
+
+#[test]
+#[ignore]
+fn sm83_all() {
+    let opcodes = all_opcodes();
+    run_opcodes(opcodes.iter().map(|x| x.as_str()));
+}
+
+#[test]
+#[ignore]
+fn sm83_base() {
+    let opcodes = all_opcodes();
+    run_opcodes(
+        opcodes
+            .iter()
+            .map(|x| x.as_str())
+            .filter(|x| !x.starts_with("cb ")),
+    );
+}
+
+#[test]
+#[ignore]
+fn sm83_cb() {
+    let opcodes = all_opcodes();
+    run_opcodes(
+        opcodes
+            .iter()
+            .map(|x| x.as_str())
+            .filter(|x| x.starts_with("cb ")),
+    );
+}
+
+#[test]
+fn flat_add_a_b() {
+    let mut gb = GameBoy::new_test_flat();
+    gb.flat_memory.as_mut().unwrap()[0x0000] = 0x80; // ADD A,B
+    gb.cpu.pc = 0x0000;
+    gb.cpu.a = 0x3A;
+    gb.cpu.b = 0xC6;
+    gb.cpu.f = Flags(0x00);
+
+    let clock_count = gb.clock_count;
+    Interpreter(&mut gb).interpret_op();
+
+    assert_eq!(gb.cpu.a, 0x00);
+    assert!(gb.cpu.f.z());
+    assert!(!gb.cpu.f.n());
+    assert!(gb.cpu.f.h());
+    assert!(gb.cpu.f.c());
+    assert_eq!(gb.cpu.pc, 0x0001);
+    assert_eq!(gb.clock_count - clock_count, 4);
+}
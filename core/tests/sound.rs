@@ -0,0 +1,264 @@
+//! Checks the output of the `SoundController` against golden values, for some sequences of register
+//! writes.
+//!
+//! The golden values are a hash of the generated samples and of the save state of the sound
+//! controller after each write, captured from the original per clock implementation of
+//! `SoundController::update`.
+
+use gameroy::{
+    consts::CLOCK_SPEED, gameboy::sound_controller::SoundController, save_state::SaveState,
+};
+
+/// A write to a sound register, at the given clock count.
+#[derive(Clone, Copy)]
+struct Write {
+    clock: u64,
+    address: u8,
+    value: u8,
+}
+
+fn write(clock: u64, address: u8, value: u8) -> Write {
+    Write {
+        clock,
+        address,
+        value,
+    }
+}
+
+/// Play a tone in each channel, retriggering them with different settings, and turning the sound
+/// off and on again.
+fn tones() -> Vec<Write> {
+    let mut writes = vec![
+        write(1001, 0x26, 0x80), // NR52: sound on
+        write(1003, 0x24, 0x77), // NR50: max volume
+        write(1005, 0x25, 0xF3), // NR51: ch1 and ch2 in right, all in left
+        // channel 1: sweep up, 50% duty, decreasing envelope
+        write(2000, 0x10, 0x15),
+        write(2004, 0x11, 0x80),
+        write(2008, 0x12, 0xF3),
+        write(2012, 0x13, 0x00),
+        write(2016, 0x14, 0x86),
+        // channel 2: 12.5% duty, length enabled, increasing envelope
+        write(3001, 0x16, 0x20),
+        write(3005, 0x17, 0x0A),
+        write(3009, 0x18, 0x83),
+        write(3013, 0x19, 0xC7),
+    ];
+    // channel 3: a saw wave
+    for i in 0..16 {
+        writes.push(write(4000 + 4 * i, 0x30 + i as u8, (i as u8 * 0x11) ^ 0x0F));
+    }
+    writes.extend_from_slice(&[
+        write(5000, 0x1A, 0x80),
+        write(5004, 0x1B, 0x00),
+        write(5008, 0x1C, 0x20),
+        write(5012, 0x1D, 0x40),
+        write(5016, 0x1E, 0x87),
+        // channel 4: 15-bit lfsr
+        write(6000, 0x21, 0xF1),
+        write(6004, 0x22, 0x21),
+        write(6008, 0x23, 0x80),
+        // change the frequency while playing
+        write(100_000, 0x13, 0x80),
+        write(150_001, 0x18, 0x10),
+        write(200_000, 0x1D, 0xC0),
+        // channel 4: 7-bit lfsr, with length
+        write(250_000, 0x20, 0x30),
+        write(250_004, 0x22, 0x0A),
+        write(250_008, 0x23, 0xC0),
+        // channel 1: sweep down
+        write(300_000, 0x10, 0x2B),
+        write(300_004, 0x12, 0x87),
+        write(300_008, 0x14, 0x87),
+        // read the wave ram while playing, in the middle of a long span
+        write(1_000_003, 0x1E, 0x07),
+        // turn off and on again
+        write(1_500_000, 0x26, 0x00),
+        write(1_600_001, 0x26, 0x80),
+        write(1_600_005, 0x24, 0x35),
+        write(1_600_009, 0x25, 0xFF),
+        write(1_600_013, 0x12, 0xF0),
+        write(1_600_017, 0x14, 0x85),
+        write(1_600_021, 0x1A, 0x80),
+        write(1_600_025, 0x1E, 0x86),
+    ]);
+    writes
+}
+
+/// Random writes to the sound registers, with random intervals between them.
+fn random_writes() -> Vec<Write> {
+    // xorshift64
+    let mut state = 0x2545_F491_4F6C_DD1Du64;
+    let mut next = || {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        state
+    };
+    const ADDRESSES: &[u8] = &[
+        0x10, 0x11, 0x12, 0x13, 0x14, 0x16, 0x17, 0x18, 0x19, 0x1A, 0x1B, 0x1C, 0x1D, 0x1E, 0x20,
+        0x21, 0x22, 0x23, 0x24, 0x25, 0x30, 0x35, 0x3A, 0x3F,
+    ];
+
+    let mut clock = 12_345;
+    let mut writes = vec![write(clock, 0x26, 0x80)];
+    for _ in 0..3000 {
+        let r = next();
+        clock += r % 5000 + 1;
+        let address = if r % 97 == 0 {
+            0x26
+        } else {
+            ADDRESSES[(r >> 16) as usize % ADDRESSES.len()]
+        };
+        let mut value = (r >> 32) as u8;
+        if address == 0x26 {
+            // mostly keep the sound on
+            value = if r % 3 == 0 { 0x00 } else { 0x80 };
+        }
+        writes.push(write(clock, address, value));
+    }
+    writes
+}
+
+/// FNV-1a hash.
+fn hash(hash: &mut u64, data: &[u8]) {
+    for &b in data {
+        *hash ^= b as u64;
+        *hash = hash.wrapping_mul(0x0000_0100_0000_01B3);
+    }
+}
+
+/// Apply the writes, and return a hash of all the generated samples and of the state after each
+/// write. At the end, run for a extra second, and flush it at once.
+fn run(writes: &[Write], sample_frequency: u64) -> (u64, usize) {
+    let mut sound = SoundController::default();
+    sound.sample_frequency = sample_frequency;
+    sound.last_clock = writes[0].clock - 1;
+
+    let mut h = 0xCBF2_9CE4_8422_2325;
+    let mut samples = 0;
+    let mut check = |sound: &mut SoundController, clock: u64, h: &mut u64| {
+        let output = sound.get_output(clock);
+        samples += output.len();
+        for x in output {
+            hash(h, &x.to_le_bytes());
+        }
+        let mut state = Vec::new();
+        sound.save_state(&mut state).unwrap();
+        hash(h, &state);
+    };
+
+    for w in writes {
+        sound.write(w.clock, w.address, w.value);
+        check(&mut sound, w.clock, &mut h);
+        let wave = sound.read(w.clock + 1, 0x30);
+        hash(&mut h, &[wave]);
+    }
+    let last = writes.last().unwrap().clock;
+    check(&mut sound, last + CLOCK_SPEED + 1, &mut h);
+
+    (h, samples)
+}
+
+#[test]
+fn tones_golden() {
+    let writes = tones();
+    assert_eq!(run(&writes, 0), (13056586040947509977, 0));
+    assert_eq!(run(&writes, 44100), (7855475366784199736, 121824));
+    assert_eq!(run(&writes, 48000), (5222669346425562634, 132596));
+}
+
+#[test]
+fn random_golden() {
+    let writes = random_writes();
+    assert_eq!(run(&writes, 0), (14745354111128046810, 0));
+    assert_eq!(run(&writes, 44100), (16386443438241510787, 245952));
+}
+
+/// Apply the writes in two sound controllers, one updated by `update` and other by the per clock
+/// `update_per_clock`, and check that their outputs and states are always the same.
+fn compare_with_per_clock(writes: &[Write], sample_frequency: u64) {
+    let mut a = SoundController::default();
+    let mut b = SoundController::default();
+    a.sample_frequency = sample_frequency;
+    b.sample_frequency = sample_frequency;
+
+    for w in writes {
+        a.update(w.clock);
+        b.update_per_clock(w.clock);
+        a.write(w.clock, w.address, w.value);
+        b.write(w.clock, w.address, w.value);
+        assert!(a == b, "state diverged after write at clock {}", w.clock);
+        assert_eq!(a.get_output(w.clock), b.get_output(w.clock));
+    }
+}
+
+#[test]
+fn same_as_per_clock() {
+    compare_with_per_clock(&tones(), 0);
+    compare_with_per_clock(&tones(), 44100);
+    compare_with_per_clock(&random_writes(), 48000);
+    compare_with_per_clock(&random_writes(), 1_000_000);
+}
+
+/// Compare the time of flushing one second of audio at once, with `update` and `update_per_clock`.
+///
+/// Run with `cargo test --release -p gameroy-core --test sound -- --ignored --nocapture`.
+#[test]
+#[ignore]
+fn flush_benchmark() {
+    let setup = || {
+        let mut sound = SoundController::default();
+        sound.sample_frequency = 48000;
+        for w in tones().iter().take_while(|w| w.clock < 10_000) {
+            sound.write(w.clock, w.address, w.value);
+        }
+        sound
+    };
+    let start_clock = 10_000;
+    let times = 20;
+
+    let mut per_clock = std::time::Duration::ZERO;
+    let mut event_driven = std::time::Duration::ZERO;
+    for i in 0..times {
+        let clock = start_clock + (i + 1) * CLOCK_SPEED;
+
+        let mut sound = setup();
+        let start = std::time::Instant::now();
+        let a = sound.get_output(clock);
+        event_driven += start.elapsed();
+
+        let mut sound = setup();
+        let start = std::time::Instant::now();
+        sound.update_per_clock(clock);
+        let b = sound.get_output(clock);
+        per_clock += start.elapsed();
+
+        assert_eq!(a, b);
+    }
+    println!(
+        "one second flush: per clock {:?}, event driven {:?}",
+        per_clock / times as u32,
+        event_driven / times as u32
+    );
+}
+
+#[test]
+fn limit_sample() {
+    use gameroy::gameboy::sound_controller::{limit_sample, MAX_SAMPLE};
+
+    // in range samples are unchanged
+    for x in 0..=MAX_SAMPLE {
+        assert_eq!(limit_sample(x as u32), x);
+    }
+
+    // over range sums, like from channels with a corrupted volume, are clamped instead of wrapping
+    let mut last = 0;
+    for x in (0..4 * 255 * 7 * 16).chain([u16::MAX as u32 + 1, u32::MAX]) {
+        let y = limit_sample(x);
+        assert!(y <= MAX_SAMPLE, "{} -> {}", x, y);
+        assert!(y >= last, "{} -> {}", x, y);
+        last = y;
+    }
+    assert_eq!(last, MAX_SAMPLE);
+}
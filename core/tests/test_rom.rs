@@ -1,15 +1,20 @@
 use std::{
     path::PathBuf,
-    sync::{
-        atomic::{AtomicBool, Ordering},
-        Arc, Mutex,
-    },
+    sync::{Arc, Mutex},
 };
 
 use gameroy::{
-    gameboy::{cartridge::Cartridge, GameBoy},
-    interpreter::Interpreter,
-    save_state::SaveState,
+    consts::FRAME_CYCLES,
+    debugger::{Debugger, RunResult},
+    gameboy::{
+        cartridge::{Cartridge, CartridgeHeader, RomIntegrity},
+        cpu::ImeState,
+        interrupt_log::{InterruptEventKind, InterruptLog},
+        GameBoy,
+    },
+    interpreter::{Interpreter, StepKind},
+    profiling::ProfilingData,
+    save_state::{LoadStateError, SaveState},
 };
 use rand::{Rng, SeedableRng};
 
@@ -20,6 +25,20 @@ const CLOCK_SPEED: u64 = 2u64.pow(22);
 
 const TEST_ROM_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/gameboy-test-roms/");
 
+/// The directory with the test roms, which can be overridden by the `TEST_ROM_PATH` environment
+/// variable, so the roms don't need to be inside the repository.
+fn test_rom_path() -> String {
+    match std::env::var("TEST_ROM_PATH") {
+        Ok(mut path) => {
+            if !path.ends_with('/') {
+                path.push('/');
+            }
+            path
+        }
+        Err(_) => TEST_ROM_PATH.to_string(),
+    }
+}
+
 macro_rules! screen {
     { $( $(#[$($attrib:meta)*])* $test:ident($rom:expr, $expec:expr, $timeout:expr, ); )* } => {
         $(#[test] $(#[$($attrib)*])*
@@ -41,8 +60,8 @@ fn lcd_to_rgb(screen: &[u8; 144 * 160], img_data: &mut [u8]) {
 }
 
 fn test_screen(rom: &str, reference: &str, timeout: u64) {
-    let rom_path: PathBuf = (TEST_ROM_PATH.to_string() + rom).into();
-    let reference_path = TEST_ROM_PATH.to_string() + reference;
+    let rom_path: PathBuf = (test_rom_path() + rom).into();
+    let reference_path = test_rom_path() + reference;
     let rom = std::fs::read(&rom_path).unwrap();
 
     let cartridge = Cartridge::new(rom).unwrap();
@@ -75,7 +94,7 @@ fn test_screen(rom: &str, reference: &str, timeout: u64) {
 
     if img_data != reference_img_data {
         let path: PathBuf = ("test_output/".to_string()
-            + &rom_path.file_stem().unwrap().to_string_lossy()
+            + &*rom_path.file_stem().unwrap().to_string_lossy()
             + "_output.png")
             .into();
         path.parent().map(|x| std::fs::create_dir_all(x).unwrap());
@@ -92,7 +111,7 @@ fn test_screen(rom: &str, reference: &str, timeout: u64) {
 }
 
 fn test_registers(rom: &str, timeout: u64) {
-    let rom_path: PathBuf = (TEST_ROM_PATH.to_string() + rom).into();
+    let rom_path: PathBuf = (test_rom_path() + rom).into();
     let rom = std::fs::read(&rom_path).unwrap();
 
     let cartridge = Cartridge::new(rom).unwrap();
@@ -204,47 +223,33 @@ mod blargg {
     }
 
     fn test_rom_serial(path: &str, timeout: u64) -> Result<(), String> {
-        let rom_path = TEST_ROM_PATH.to_string() + "blargg/" + path;
+        let rom_path = test_rom_path() + "blargg/" + path;
         let rom = std::fs::read(rom_path).unwrap();
 
         let cartridge = Cartridge::new(rom).unwrap();
 
         let mut game_boy = GameBoy::new(None, cartridge);
-        // let mut game_boy = GameBoy::new(&vec![0; 256][..], rom_file);
-        let string = Arc::new(Mutex::new(String::new()));
-        let string_clone = string.clone();
-        let stop = Arc::new(AtomicBool::new(false));
-        game_boy.serial_transfer_callback = Some(Box::new({
-            let stop = stop.clone();
-            move |byte| {
-                let mut string = string.lock().unwrap();
-                string.push(byte as char);
-                if string.ends_with("Passed") {
-                    stop.store(true, Ordering::Relaxed);
-                }
-            }
-        }));
+        game_boy.serial_output = Some(Vec::new());
 
         let mut inter = Interpreter(&mut game_boy);
         while inter.0.clock_count < timeout {
             inter.interpret_op();
-            if stop.load(Ordering::Relaxed) {
+            let output = inter.0.serial_output.as_deref().unwrap_or_default();
+            if output.ends_with(b"Passed") || output.ends_with(b"Failed") {
                 break;
             }
         }
-        // panic!("ahh");
-        if stop.load(Ordering::Relaxed) {
+        let output = game_boy.take_serial_output();
+        let string = String::from_utf8_lossy(&output);
+        if string.contains("Passed") && !string.contains("Failed") {
             Ok(())
-            // let string = string_clone.lock().unwrap();
-            // Err(format!("test rom failed: \n{}", string))
         } else {
-            let string = string_clone.lock().unwrap();
             Err(format!("test rom failed: \n{}", string))
         }
     }
 
     fn test_rom_memory(path: &str, timeout: u64) -> Result<(), String> {
-        let rom_path = TEST_ROM_PATH.to_string() + "blargg/" + path;
+        let rom_path = test_rom_path() + "blargg/" + path;
         let rom = std::fs::read(rom_path).unwrap();
 
         let cartridge = Cartridge::new(rom).unwrap();
@@ -301,7 +306,7 @@ mod blargg {
 /// Run cpu_instrs for a random ammount of instructions, do a save state, and compare the load
 /// state with the original. They should always be equal.
 fn save_state1() {
-    let rom_path = TEST_ROM_PATH.to_string() + "blargg/cpu_instrs/cpu_instrs.gb";
+    let rom_path = test_rom_path() + "blargg/cpu_instrs/cpu_instrs.gb";
     let rom = std::fs::read(rom_path).unwrap();
 
     let cartridge = Cartridge::new(rom.clone()).unwrap();
@@ -347,7 +352,7 @@ fn save_state1() {
 /// run the same ammount of instructions, and compare with the first one. They should always be
 /// equal.
 fn save_state2() {
-    let rom_path = TEST_ROM_PATH.to_string() + "blargg/cpu_instrs/cpu_instrs.gb";
+    let rom_path = test_rom_path() + "blargg/cpu_instrs/cpu_instrs.gb";
     let rom = std::fs::read(rom_path).unwrap();
 
     let cartridge = Cartridge::new(rom.clone()).unwrap();
@@ -396,7 +401,7 @@ fn save_state2() {
 /// state, run the same number of instructions, and compare with the first one. They should always
 /// be equal.
 fn save_state3() {
-    let rom_path = TEST_ROM_PATH.to_string() + "blargg/cpu_instrs/cpu_instrs.gb";
+    let rom_path = test_rom_path() + "blargg/cpu_instrs/cpu_instrs.gb";
     let rom = std::fs::read(rom_path).unwrap();
 
     let cartridge = Cartridge::new(rom.clone()).unwrap();
@@ -504,6 +509,2727 @@ fn assert_gb_eq(a: &GameBoy, b: &GameBoy) -> bool {
     true
 }
 
+/// Create a cartridge with the given program at the entry point (0x0100).
+fn program_cartridge(program: &[u8]) -> Cartridge {
+    let mut rom = vec![0; 0x8000];
+    rom[0x100..0x100 + program.len()].copy_from_slice(program);
+    Cartridge::new(rom).unwrap()
+}
+
+#[test]
+fn profiling_counts() {
+    let cartridge = program_cartridge(&[
+        0x06, 0x03, // LD B,3
+        0x05, // DEC B
+        0x20, 0xFD, // JR NZ,-3
+        0x18, 0xFE, // JR -2
+    ]);
+    let mut game_boy = GameBoy::new(None, cartridge);
+    game_boy.profiling = Some(Box::new(ProfilingData::new()));
+
+    let mut inter = Interpreter(&mut game_boy);
+    while inter.0.cpu.pc != 0x0105 {
+        inter.interpret_op();
+    }
+
+    let profiling = game_boy.profiling.as_ref().unwrap();
+    assert_eq!(profiling.op_count[0x06], 1);
+    assert_eq!(profiling.op_cycles[0x06], 8);
+    assert_eq!(profiling.op_count[0x05], 3);
+    assert_eq!(profiling.op_cycles[0x05], 3 * 4);
+    assert_eq!(profiling.op_count[0x20], 3);
+    assert_eq!(profiling.op_cycles[0x20], 2 * 12 + 8);
+    assert_eq!(profiling.op_count.iter().sum::<u64>(), 7);
+    assert_eq!(profiling.cb_op_count.iter().sum::<u64>(), 0);
+    assert_eq!(profiling.total_cycles(), 8 + 12 + 32);
+    assert_eq!(profiling.bank_cycles.get(&Some(0)), Some(&52));
+    assert_eq!(profiling.range_cycles.get(&(Some(0), 0x0100)), Some(&52));
+}
+
+#[test]
+fn profiling_hot_addresses() {
+    let cartridge = program_cartridge(&[
+        0x06, 0x00, // LD B,0
+        0x00, // Loop: NOP
+        0x05, // DEC B
+        0x20, 0xFC, // JR NZ,Loop
+        0x18, 0xFE, // JR -2
+    ]);
+    let mut game_boy = GameBoy::new(None, cartridge);
+    game_boy
+        .trace
+        .borrow_mut()
+        .load_symbols("00:0100 Start\n00:0102 Loop\n".as_bytes())
+        .unwrap();
+    game_boy.profiling = Some(Box::new(ProfilingData::new()));
+
+    let mut inter = Interpreter(&mut game_boy);
+    while inter.0.cpu.pc != 0x0106 {
+        inter.interpret_op();
+    }
+
+    let profiling = game_boy.profiling.as_ref().unwrap();
+    // the loop runs 256 times
+    assert_eq!(
+        profiling.hot_addresses(4),
+        vec![
+            ((Some(0), 0x0104), 255 * 12 + 8),
+            ((Some(0), 0x0102), 256 * 4),
+            ((Some(0), 0x0103), 256 * 4),
+            ((Some(0), 0x0100), 8),
+        ]
+    );
+    assert_eq!(profiling.hot_addresses(1).len(), 1);
+    let loop_cycles: u64 = (0x0102..=0x0104)
+        .map(|pc| profiling.pc_cycles[&(Some(0), pc)])
+        .sum();
+    assert_eq!(loop_cycles + 8, profiling.total_cycles());
+
+    let trace = game_boy.trace.borrow();
+    let mut report = String::new();
+    profiling.report(2, &trace, &mut report).unwrap();
+    let hot = report.split("hot addresses:\n").nth(1).unwrap();
+    let lines: Vec<&str> = hot.lines().collect();
+    assert_eq!(lines.len(), 2, "{}", report);
+    assert!(lines[0].starts_with("  00:0104") && lines[0].ends_with(" Loop+2"));
+    assert!(lines[1].starts_with("  00:0102") && lines[1].ends_with(" Loop"));
+
+    let mut csv = Vec::new();
+    profiling.write_csv(&trace, &mut csv).unwrap();
+    assert_eq!(
+        String::from_utf8(csv).unwrap(),
+        "bank,address,cycles,symbol\n\
+         00,0100,8,Start\n\
+         00,0102,1024,Loop\n\
+         00,0103,1024,Loop+1\n\
+         00,0104,3068,Loop+2\n"
+    );
+}
+
+/// Compare the speed of `interpret_op` with the profiling disabled against a interpreter that
+/// doesn't check for the profiling at all. Run it with
+/// `cargo test --release -p gameroy-core --test test_rom -- --ignored profiling_overhead`.
+#[test]
+#[ignore]
+fn profiling_overhead() {
+    use std::time::{Duration, Instant};
+
+    let cartridge = program_cartridge(&[
+        0x3C, // Loop: INC A
+        0x04, // INC B
+        0xCB, 0x11, // RL C
+        0x18, 0xFA, // JR Loop
+    ]);
+    let mut game_boy = GameBoy::new(None, cartridge);
+    game_boy.sound.borrow_mut().sample_frequency = 0;
+
+    let mut run = |profiled: bool| -> Duration {
+        game_boy.reset();
+        let mut inter = Interpreter(&mut game_boy);
+        let begin = Instant::now();
+        while inter.0.clock_count < 600 * FRAME_CYCLES {
+            if profiled {
+                inter.interpret_op();
+            } else {
+                inter.interpret_op_without_profiling();
+            }
+        }
+        begin.elapsed()
+    };
+
+    // interleave the runs, and keep the fastest of each, to reduce the noise
+    let mut disabled = Duration::MAX;
+    let mut baseline = Duration::MAX;
+    for _ in 0..10 {
+        disabled = disabled.min(run(true));
+        baseline = baseline.min(run(false));
+    }
+    let overhead = disabled.as_secs_f64() / baseline.as_secs_f64() - 1.0;
+    println!(
+        "profiling disabled: {:?}, baseline: {:?}, overhead: {:.2}%",
+        disabled,
+        baseline,
+        overhead * 100.0
+    );
+    assert!(overhead < 0.02, "overhead of {:.2}%", overhead * 100.0);
+}
+
+#[test]
+fn call_stack_frames() {
+    let mut program = vec![0; 0x30];
+    // 0x0100
+    program[0x00..0x05].copy_from_slice(&[
+        0xCD, 0x10, 0x01, // CALL $0110
+        0x18, 0xFE, // JR -2
+    ]);
+    // 0x0110
+    program[0x10..0x14].copy_from_slice(&[
+        0xCD, 0x20, 0x01, // CALL $0120
+        0xC9, // RET
+    ]);
+    // 0x0120
+    program[0x20] = 0xC9; // RET
+    let cartridge = program_cartridge(&program);
+    let mut game_boy = GameBoy::new(None, cartridge);
+    let sp = game_boy.cpu.sp;
+
+    let mut inter = Interpreter(&mut game_boy);
+    while inter.0.cpu.pc != 0x0120 {
+        inter.interpret_op();
+    }
+
+    let frames = inter.0.call_stack();
+    assert_eq!(frames.len(), 2);
+    assert_eq!(frames[0].address, 0x0110);
+    assert_eq!(frames[0].bank, Some(0));
+    assert_eq!(frames[0].return_address, 0x0103);
+    assert_eq!(frames[0].sp, sp.wrapping_sub(2));
+    assert!(!frames[0].interrupt);
+    assert_eq!(frames[1].address, 0x0120);
+    assert_eq!(frames[1].return_address, 0x0113);
+    assert_eq!(frames[1].sp, sp.wrapping_sub(4));
+
+    while inter.0.cpu.pc != 0x0113 {
+        inter.interpret_op();
+    }
+    assert_eq!(inter.0.call_stack().len(), 1);
+
+    while inter.0.cpu.pc != 0x0103 {
+        inter.interpret_op();
+    }
+    assert!(inter.0.call_stack().is_empty());
+}
+
+#[test]
+fn step_instruction() {
+    let mut program = vec![0; 0x14];
+    program[..0x08].copy_from_slice(&[
+        0x3E, 0x12, // LD A,$12
+        0x47, // LD B,A
+        0x04, // INC B
+        0xCD, 0x10, 0x01, // CALL $0110
+        0x00, // NOP
+    ]);
+    program[0x10..0x12].copy_from_slice(&[0xCB, 0x37]); // SWAP A
+    let cartridge = program_cartridge(&program);
+    let mut game_boy = GameBoy::new(None, cartridge);
+    game_boy.interrupt_enabled = 0;
+
+    let steps: Vec<_> = (0..5).map(|_| game_boy.step_instruction()).collect();
+    for step in &steps {
+        assert_eq!(step.kind, StepKind::Instruction);
+    }
+
+    let texts: Vec<_> = steps.iter().map(|x| x.text.as_str()).collect();
+    assert_eq!(
+        texts,
+        ["LD   A, $12", "LD   B, A", "INC  B", "CALL $0110", "SWAP A"]
+    );
+    let cycles: Vec<_> = steps.iter().map(|x| x.cycles).collect();
+    assert_eq!(cycles, [8, 4, 4, 24, 8]);
+    let pcs: Vec<_> = steps.iter().map(|x| x.pc).collect();
+    assert_eq!(pcs, [0x0100, 0x0102, 0x0103, 0x0104, 0x0110]);
+    assert_eq!(steps[3].bytes(), &[0xCD, 0x10, 0x01]);
+
+    let change = |step: usize, register: &str| {
+        steps[step]
+            .changes
+            .iter()
+            .find(|x| x.register == register)
+            .map(|x| (x.before, x.after))
+    };
+    assert_eq!(change(0, "A"), Some((0x01, 0x12)));
+    assert_eq!(change(1, "B"), Some((0x00, 0x12)));
+    assert_eq!(change(2, "B"), Some((0x12, 0x13)));
+    assert_eq!(change(3, "SP"), Some((0xFFFE, 0xFFFC)));
+    assert_eq!(change(4, "A"), Some((0x12, 0x21)));
+    assert_eq!(game_boy.cpu.pc, 0x0112);
+}
+
+#[test]
+fn memory_range_access() {
+    let cartridge = program_cartridge(&[0x18, 0xFE]); // JR -2
+    let mut game_boy = GameBoy::new(None, cartridge);
+
+    let data: Vec<u8> = (0..0x100).map(|x| x as u8).collect();
+    game_boy.write_range(0xC000, &data);
+    assert_eq!(game_boy.read_range(0xC000, 0x100), data);
+
+    // patch a single byte
+    game_boy.write_range(0xC010, &[0xAA]);
+    let dump = game_boy.read_range(0xC000, 0x2000);
+    assert_eq!(dump[0x0F], 0x0F);
+    assert_eq!(dump[0x10], 0xAA);
+    assert_eq!(dump[0x11], 0x11);
+
+    // echo RAM mirrors WRAM
+    assert_eq!(game_boy.read_range(0xE010, 1), [0xAA]);
+
+    // peek doesn't advance the clock
+    let clock_count = game_boy.clock_count;
+    assert_eq!(game_boy.peek(0xC010), 0xAA);
+    assert_eq!(game_boy.peek_range(0xC000, 0x100)[0x10], 0xAA);
+    assert_eq!(game_boy.peek(0xFF40), game_boy.read(0xFF40));
+    assert_eq!(game_boy.clock_count, clock_count);
+}
+
+#[test]
+fn peek_has_no_side_effects() {
+    let program = [
+        0x3E, 0x80, // LD A,$80
+        0xE0, 0x26, // LDH (NR52),A
+        0x3E, 0xF0, // LD A,$F0
+        0xE0, 0x12, // LDH (NR12),A
+        0x3E, 0x87, // LD A,$87
+        0xE0, 0x14, // LDH (NR14),A
+        0x3C, // INC A
+        0xEA, 0x00, 0xC0, // LD ($C000),A
+        0x18, 0xFA, // JR -6
+    ];
+    let mut a = GameBoy::new(None, program_cartridge(&program));
+    let mut b = GameBoy::new(None, program_cartridge(&program));
+
+    let run = |gb: &mut GameBoy, frames: u64| {
+        let target = gb.clock_count + frames * FRAME_CYCLES;
+        let mut inter = Interpreter(gb);
+        while inter.0.clock_count < target {
+            inter.interpret_op();
+        }
+    };
+    // the save state without its 20 bytes header, which contains the time of the save
+    let save_state = |gb: &GameBoy| {
+        let mut state = Vec::new();
+        gb.save_state(&mut state).unwrap();
+        state.split_off(20)
+    };
+
+    let mut rng = rand::rngs::StdRng::seed_from_u64(0x5EED);
+    for _ in 0..4 {
+        let frames = rng.gen_range(1..4);
+        run(&mut a, frames);
+        run(&mut b, frames);
+
+        for _ in 0..4000 {
+            a.peek(rng.gen());
+        }
+        for address in 0..=0xFF {
+            a.peek_io(address);
+        }
+        let mut buf = [0; 0x100];
+        a.peek_into(rng.gen(), &mut buf);
+        assert_eq!(save_state(&a), save_state(&b));
+    }
+
+    // the emulation also don't diverge after the peeks
+    run(&mut a, 2);
+    run(&mut b, 2);
+    assert_eq!(save_state(&a), save_state(&b));
+    assert_eq!(a.peek_range(0xC000, 1), b.peek_range(0xC000, 1));
+}
+
+#[test]
+fn watchpoint_write() {
+    let cartridge = program_cartridge(&[
+        0x3E, 0x42, // LD A,$42
+        0x21, 0xA0, 0xC0, // LD HL,$C0A0
+        0x00, // NOP
+        0x77, // LD (HL),A
+        0x18, 0xFE, // JR -2
+    ]);
+    let mut game_boy = GameBoy::new(None, cartridge);
+    let mut debugger = Debugger::default();
+    debugger.add_watchpoint(&mut game_boy, 0xC000, 0xC0FF, false, true);
+
+    let result = debugger.run_for(&mut game_boy, 1000);
+    assert_eq!(result, RunResult::ReachWatchpoint);
+    let hit = debugger.last_watchpoint_hit.unwrap();
+    assert_eq!(hit.pc, 0x0106);
+    assert_eq!(hit.address, 0xC0A0);
+    assert_eq!(hit.value, 0x42);
+    assert!(hit.write);
+    assert_eq!(game_boy.cpu.pc, 0x0107);
+    assert_eq!(game_boy.watchpoints.list()[0].hits, 1);
+
+    // the watched range is not accessed anymore
+    assert_eq!(debugger.run_for(&mut game_boy, 1000), RunResult::TimeOut);
+}
+
+#[test]
+fn serial_output() {
+    let cartridge = program_cartridge(&[
+        0x3E, b'O', // LD A,'O'
+        0xE0, 0x01, // LDH (SB),A
+        0x3E, 0x81, // LD A,81
+        0xE0, 0x02, // LDH (SC),A
+        0x3E, b'K', // LD A,'K'
+        0xE0, 0x01, // LDH (SB),A
+        0x3E, 0x81, // LD A,81
+        0xE0, 0x02, // LDH (SC),A
+        0x18, 0xFE, // JR -2
+    ]);
+    let mut game_boy = GameBoy::new(None, cartridge);
+
+    // not captured by default
+    game_boy.step_instruction();
+    game_boy.step_instruction();
+    game_boy.step_instruction();
+    game_boy.step_instruction();
+    assert_eq!(game_boy.serial_output, None);
+    assert_eq!(game_boy.take_serial_output(), b"");
+
+    game_boy.serial_output = Some(Vec::new());
+    for _ in 0..10 {
+        game_boy.step_instruction();
+    }
+    assert_eq!(game_boy.take_serial_output(), b"K");
+
+    game_boy.reset();
+    for _ in 0..10 {
+        game_boy.step_instruction();
+    }
+    assert_eq!(game_boy.take_serial_output(), b"OK");
+    assert_eq!(game_boy.take_serial_output(), b"");
+    assert_eq!(game_boy.serial_output, Some(Vec::new()));
+}
+
+#[test]
+fn reset_clears_serial_transfer() {
+    // a boot rom that loops forever, to also test the reset with a boot rom
+    let mut boot_rom = [0; 0x100];
+    boot_rom[..2].copy_from_slice(&[0x18, 0xFE]); // JR -2
+
+    for boot_rom in [None, Some(boot_rom)] {
+        let cartridge = program_cartridge(&[
+            0x3E, 0x08, // LD A,08
+            0xE0, 0xFF, // LDH (IE),A
+            0x3E, 0x81, // LD A,81
+            0xE0, 0x02, // LDH (SC),A
+            0x18, 0xFE, // JR -2
+        ]);
+        let mut game_boy = GameBoy::new(boot_rom, cartridge);
+        if boot_rom.is_some() {
+            // `serial_transfer_started` is 0 when there is no transfer, so the transfer can't start
+            // in the first cycles.
+            for _ in 0..2000 {
+                game_boy.step_instruction();
+            }
+            game_boy.boot_rom_active = false;
+            game_boy.cpu.pc = 0x0100;
+        }
+
+        // start the serial transfer, and reset before it completes
+        for _ in 0..4 {
+            game_boy.step_instruction();
+        }
+        assert_ne!(game_boy.serial_transfer_started, 0);
+        assert_eq!(game_boy.interrupt_enabled, 0x08);
+        game_boy.reset();
+
+        assert_eq!(game_boy.serial_transfer_started, 0);
+        assert_eq!(game_boy.serial_control, 0x7E);
+        assert_eq!(game_boy.interrupt_enabled, 0);
+        assert_eq!(game_boy.dma, 0xff);
+
+        // after reset, with the boot rom, the program never runs, and without it, the program is
+        // run only up to before the transfer starts.
+        let steps = if boot_rom.is_some() { 2000 } else { 2 };
+        for _ in 0..steps {
+            game_boy.step_instruction();
+        }
+        let target = game_boy.clock_count + 8 * 512 * 2;
+        while game_boy.clock_count < target {
+            game_boy.tick(4);
+        }
+        assert_eq!(
+            game_boy.interrupt_flag & 0x08,
+            0,
+            "spurious serial interrupt"
+        );
+    }
+}
+
+#[test]
+fn interrupt_log_ei_delay() {
+    let mut rom = vec![0; 0x8000];
+    rom[0x40] = 0xD9; // RETI
+    let program = [
+        0x3E, 0x01, // LD A,1
+        0xE0, 0xFF, // LDH (IE),A
+        0xE0, 0x0F, // LDH (IF),A
+        0xFB, // EI
+        0x00, // NOP
+        0x18, 0xFE, // JR -2
+    ];
+    rom[0x100..0x100 + program.len()].copy_from_slice(&program);
+    let mut game_boy = GameBoy::new(None, Cartridge::new(rom).unwrap());
+    game_boy.interrupt_log = Some(Box::new(InterruptLog::new(16)));
+
+    let mut inter = Interpreter(&mut game_boy);
+    // LD, LDH, LDH, EI, NOP, the dispatch and RETI
+    for _ in 0..7 {
+        inter.interpret_op();
+    }
+    assert_eq!(game_boy.cpu.pc, 0x108);
+
+    let log = game_boy.interrupt_log.as_ref().unwrap();
+    let events: Vec<_> = log
+        .recent()
+        .map(|x| {
+            (
+                x.kind,
+                x.pc,
+                x.ime,
+                x.interrupt_enabled,
+                x.interrupt_flag & 0x1F,
+            )
+        })
+        .collect();
+    assert_eq!(
+        events,
+        [
+            (
+                InterruptEventKind::EiScheduled,
+                0x106,
+                ImeState::Disabled,
+                0x01,
+                0x01
+            ),
+            // IME is only enabled after the instruction following EI
+            (
+                InterruptEventKind::ImeEnabled,
+                0x107,
+                ImeState::ToBeEnable,
+                0x01,
+                0x01
+            ),
+            // so the interrupt is dispatched only after the NOP
+            (
+                InterruptEventKind::Dispatch {
+                    interrupt: Some(0),
+                    handler: 0x40
+                },
+                0x108,
+                ImeState::Enabled,
+                0x01,
+                0x01
+            ),
+            (
+                InterruptEventKind::Reti,
+                0x40,
+                ImeState::Disabled,
+                0x01,
+                0x00
+            ),
+        ]
+    );
+
+    // when disabled, nothing is recorded
+    game_boy.interrupt_log = None;
+    game_boy.interrupt_flag |= 0x01;
+    let mut inter = Interpreter(&mut game_boy);
+    for _ in 0..4 {
+        inter.interpret_op();
+    }
+    assert!(game_boy.interrupt_log.is_none());
+}
+
+#[test]
+fn lcd_off() {
+    let cartridge = program_cartridge(&[
+        0x3E, 0x11, // LD A,$11
+        0xE0, 0x40, // LDH ($40),A
+        0x18, 0xFE, // JR -2
+    ]);
+    let mut game_boy = GameBoy::new(None, cartridge);
+    game_boy.interrupt_enabled = 0;
+    game_boy.ppu.borrow_mut().screen = [3; SCREEN_WIDTH * SCREEN_HEIGHT];
+
+    let mut inter = Interpreter(&mut game_boy);
+    while inter.0.cpu.pc != 0x0104 {
+        inter.interpret_op();
+    }
+    assert_eq!(inter.0.read(0xFF40) & 0x80, 0);
+    assert!(inter.0.ppu.borrow().screen.iter().all(|&x| x == 0));
+
+    // run for 3 frames, with the LCD off
+    inter.0.interrupt_flag = 0;
+    let target = inter.0.clock_count + 3 * FRAME_CYCLES;
+    while inter.0.clock_count < target {
+        inter.interpret_op();
+        assert_eq!(inter.0.read(0xFF44), 0);
+        assert_eq!(inter.0.read(0xFF41) & 0b11, 0);
+    }
+    assert_eq!(inter.0.interrupt_flag & 0b11, 0);
+
+    // turn the LCD back on
+    inter.0.write(0xFF40, 0x91);
+    let target = inter.0.clock_count + FRAME_CYCLES;
+    let mut max_ly = 0;
+    while inter.0.clock_count < target {
+        inter.interpret_op();
+        max_ly = max_ly.max(inter.0.read(0xFF44));
+    }
+    assert!(max_ly >= 144);
+    assert_eq!(inter.0.interrupt_flag & 0b1, 0b1);
+}
+
+#[test]
+fn stat_interrupt_blocking() {
+    let cartridge = program_cartridge(&[0x18, 0xFE]); // JR -2
+    let mut game_boy = GameBoy::new(None, cartridge);
+    game_boy.interrupt_enabled = 0;
+    game_boy.write(0xFF45, 0x10); // LYC = 16
+    game_boy.write(0xFF41, 0x48); // enable mode 0 and LY=LYC STAT interrupts
+
+    let mut inter = Interpreter(&mut game_boy);
+    inter.0.interrupt_flag = 0;
+    // the LY and mode in which each STAT interrupt was requested
+    let mut interrupts = Vec::new();
+    let target = inter.0.clock_count + FRAME_CYCLES;
+    while inter.0.clock_count < target {
+        inter.interpret_op();
+        if inter.0.interrupt_flag & 0b10 != 0 {
+            inter.0.interrupt_flag = 0;
+            interrupts.push((inter.0.read(0xFF44), inter.0.read(0xFF41) & 0b11));
+        }
+    }
+
+    let line = |ly: u8| {
+        interrupts
+            .iter()
+            .filter(|x| x.0 == ly)
+            .copied()
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(line(15), [(15, 0)]);
+    // The LY=LYC condition keeps the STAT line high during the mode 0 of line 16, so there is
+    // only the interrupt at the rising edge.
+    assert_eq!(line(16), [(16, 2)]);
+    assert_eq!(line(17), [(17, 0)]);
+    assert_eq!(interrupts.len(), 144);
+}
+
+#[test]
+fn lyc_write_mid_line() {
+    let cartridge = program_cartridge(&[0x18, 0xFE]); // JR -2
+    let mut game_boy = GameBoy::new(None, cartridge);
+    game_boy.interrupt_enabled = 0;
+    game_boy.write(0xFF45, 0xFF); // LYC = 255, never equal to LY
+    game_boy.write(0xFF41, 0x40); // enable the LY=LYC STAT interrupt
+
+    let mut inter = Interpreter(&mut game_boy);
+    let run_until = |inter: &mut Interpreter, ly: u8, mode: u8| {
+        while inter.0.read(0xFF44) != ly || inter.0.read(0xFF41) & 0b11 != mode {
+            inter.interpret_op();
+        }
+    };
+    let stat_interrupt = |inter: &mut Interpreter| {
+        let requested = inter.0.interrupt_flag & 0b10 != 0;
+        inter.0.interrupt_flag = 0;
+        requested
+    };
+
+    // in the middle of the line 10, in mode 3
+    run_until(&mut inter, 10, 3);
+    inter.0.interrupt_flag = 0;
+    assert_eq!(inter.0.read(0xFF41) & 0x04, 0);
+
+    // writing LYC = LY sets the coincidence flag and requests the interrupt right away
+    inter.0.write(0xFF45, 10);
+    assert_eq!(inter.0.read(0xFF41) & 0x04, 0x04);
+    assert!(stat_interrupt(&mut inter));
+
+    // the STAT line is still high, so writing the same value don't request it again
+    inter.0.write(0xFF45, 10);
+    assert!(!stat_interrupt(&mut inter));
+
+    // making LY != LYC clears the flag, and a new rising edge requests the interrupt again
+    inter.0.write(0xFF45, 11);
+    assert_eq!(inter.0.read(0xFF41) & 0x04, 0);
+    assert!(!stat_interrupt(&mut inter));
+    inter.0.write(0xFF45, 10);
+    assert_eq!(inter.0.read(0xFF41) & 0x04, 0x04);
+    assert!(stat_interrupt(&mut inter));
+
+    // the flag follows LY when the line changes
+    run_until(&mut inter, 11, 2);
+    assert_eq!(inter.0.read(0xFF41) & 0x04, 0);
+    stat_interrupt(&mut inter);
+
+    // During mode 0 the HBlank interrupt keeps the STAT line high, so the coincidence sets the flag
+    // but don't request a interrupt.
+    inter.0.write(0xFF41, 0x48);
+    run_until(&mut inter, 20, 0);
+    inter.0.interrupt_flag = 0;
+    inter.0.write(0xFF45, 20);
+    assert_eq!(inter.0.read(0xFF41) & 0x03, 0);
+    assert_eq!(inter.0.read(0xFF41) & 0x04, 0x04);
+    assert!(!stat_interrupt(&mut inter));
+}
+
+#[test]
+fn custom_after_boot_sound() {
+    let wave_ram =
+        |game_boy: &GameBoy| -> Vec<u8> { (0xFF30..=0xFF3F).map(|x| game_boy.read(x)).collect() };
+    let custom_wave: Vec<u8> = (0..16).map(|x| x * 0x11).collect();
+
+    let cartridge = program_cartridge(&[0x18, 0xFE]); // JR -2
+    let mut game_boy = GameBoy::new(None, cartridge);
+    let default_wave = wave_ram(&game_boy);
+    assert_ne!(default_wave, custom_wave);
+
+    // make a state with a different wave RAM
+    for (i, &x) in custom_wave.iter().enumerate() {
+        game_boy.write(0xFF30 + i as u16, x);
+    }
+    let mut state = Vec::new();
+    game_boy.sound.borrow().save_state(&mut state).unwrap();
+
+    let cartridge = program_cartridge(&[0x18, 0xFE]);
+    let mut game_boy = GameBoy::new(None, cartridge);
+    game_boy.set_after_boot_sound(Some(state.clone())).unwrap();
+    assert_eq!(wave_ram(&game_boy), custom_wave);
+
+    // it is applied again after a reset and a power cycle
+    game_boy.write(0xFF30, 0x5A);
+    game_boy.reset();
+    assert_eq!(wave_ram(&game_boy), custom_wave);
+    game_boy.write(0xFF30, 0x5A);
+    game_boy.power_cycle(false);
+    assert_eq!(wave_ram(&game_boy), custom_wave);
+
+    // invalid states fall back to the default
+    let truncated = state[..state.len() - 1].to_vec();
+    assert!(game_boy.set_after_boot_sound(Some(truncated)).is_err());
+    assert_eq!(wave_ram(&game_boy), default_wave);
+
+    let mut trailing = state.clone();
+    trailing.push(0);
+    game_boy.set_after_boot_sound(Some(state)).unwrap();
+    assert!(game_boy.set_after_boot_sound(Some(trailing)).is_err());
+    assert_eq!(wave_ram(&game_boy), default_wave);
+    game_boy.reset();
+    assert_eq!(wave_ram(&game_boy), default_wave);
+
+    game_boy.set_after_boot_sound(None).unwrap();
+    assert_eq!(wave_ram(&game_boy), default_wave);
+}
+
+#[test]
+fn not_usable_region() {
+    let cartridge = program_cartridge(&[0x18, 0xFE]); // JR -2
+    let mut game_boy = GameBoy::new(None, cartridge);
+    let not_usable = |gb: &GameBoy| gb.read_range(0xFEA0, 0x60);
+
+    // the echo RAM mirrors the work RAM up to 0xFDFF
+    game_boy.write(0xE000, 0x12);
+    game_boy.write(0xFDFF, 0x34);
+    assert_eq!(game_boy.read(0xC000), 0x12);
+    assert_eq!(game_boy.read(0xDDFF), 0x34);
+    game_boy.write(0xDE00, 0x56);
+    game_boy.write(0xFE00, 0x78);
+    assert_eq!(game_boy.read(0xDE00), 0x56);
+    assert_eq!(game_boy.read(0xFE00), 0x78);
+
+    // writes are ignored
+    game_boy.write(0xFEA0, 0x9A);
+
+    let mut inter = Interpreter(&mut game_boy);
+    let run_until_mode = |inter: &mut Interpreter, mode: u8| {
+        while inter.0.read(0xFF41) & 0b11 != mode {
+            inter.interpret_op();
+        }
+    };
+
+    // reads as 0x00 while the OAM is accessible, and 0xFF while the PPU blocks it
+    for (mode, expected) in [(0, 0x00), (2, 0xFF), (3, 0xFF), (1, 0x00)] {
+        run_until_mode(&mut inter, mode);
+        assert_eq!(not_usable(inter.0), [expected; 0x60], "mode {}", mode);
+        assert_eq!(inter.0.peek(0xFEA0), 0x00);
+    }
+
+    // and with the LCD off
+    inter.0.write(0xFF40, 0x00);
+    assert_eq!(not_usable(inter.0), [0x00; 0x60]);
+}
+
+#[test]
+fn bank_breakpoint() {
+    // a 64 KiB MBC1 rom, with 4 banks
+    let mut rom = vec![0; 0x10000];
+    rom[0x147] = 0x01;
+    rom[0x148] = 0x01;
+    let program = [
+        0x3E, 0x02, // LD A,$02
+        0xEA, 0x00, 0x20, // LD ($2000),A
+        0xCD, 0x00, 0x40, // CALL $4000
+        0x3E, 0x03, // LD A,$03
+        0xEA, 0x00, 0x20, // LD ($2000),A
+        0xCD, 0x00, 0x40, // CALL $4000
+        0x18, 0xFE, // JR -2
+    ];
+    rom[0x150..0x150 + program.len()].copy_from_slice(&program);
+    rom[0x100..0x104].copy_from_slice(&[0x00, 0xC3, 0x50, 0x01]); // NOP; JP $0150
+    rom[0x2 * 0x4000] = 0xC9; // RET
+    rom[0x3 * 0x4000] = 0xC9; // RET
+
+    // break only in bank 3
+    let mut game_boy = GameBoy::new(None, Cartridge::new(rom.clone()).unwrap());
+    let mut debugger = Debugger::default();
+    debugger
+        .execute_command(&mut game_boy, &["break", "x", "03:4000"])
+        .unwrap();
+    assert_eq!(
+        debugger.run_for(&mut game_boy, 1000),
+        RunResult::ReachBreakpoint
+    );
+    assert_eq!(game_boy.cpu.pc, 0x4000);
+    assert_eq!(game_boy.cartridge.curr_rom_bank(), 3);
+
+    // break in any bank
+    let mut game_boy = GameBoy::new(None, Cartridge::new(rom).unwrap());
+    let mut debugger = Debugger::default();
+    debugger
+        .execute_command(&mut game_boy, &["break", "x", "4000"])
+        .unwrap();
+    assert_eq!(
+        debugger.run_for(&mut game_boy, 1000),
+        RunResult::ReachBreakpoint
+    );
+    assert_eq!(game_boy.cpu.pc, 0x4000);
+    assert_eq!(game_boy.cartridge.curr_rom_bank(), 2);
+}
+
+#[test]
+fn run_control_commands() {
+    let cartridge = program_cartridge(&[
+        0x00, // NOP
+        0x18, 0xFD, // JR -3
+    ]);
+    let mut game_boy = GameBoy::new(None, cartridge);
+    let mut debugger = Debugger::default();
+    let run = |game_boy: &mut GameBoy, debugger: &mut Debugger, args: &[&str]| {
+        debugger.execute_command(game_boy, args).unwrap();
+        debugger.run_for(game_boy, 4 * FRAME_CYCLES)
+    };
+
+    let result = run(&mut game_boy, &mut debugger, &["runto", "line", "80"]);
+    assert_eq!(result, RunResult::ReachTargetLine);
+    assert_eq!(game_boy.ppu.borrow().ly, 80);
+
+    let result = run(&mut game_boy, &mut debugger, &["runto", "vblank"]);
+    assert_eq!(result, RunResult::ReachTargetLine);
+    assert_eq!(game_boy.ppu.borrow().ly, 144);
+    assert_eq!(game_boy.interrupt_flag & 0x01, 0x01);
+
+    let result = run(&mut game_boy, &mut debugger, &["runto", "frame"]);
+    assert_eq!(result, RunResult::ReachTargetFrame);
+    assert_eq!(game_boy.ppu.borrow().ly, 0);
+
+    // a frame later, give or take a instruction
+    let start = game_boy.clock_count;
+    let result = run(&mut game_boy, &mut debugger, &["runfor", "3", "frames"]);
+    assert_eq!(result, RunResult::ReachTargetFrame);
+    assert_eq!(game_boy.ppu.borrow().ly, 0);
+    let elapsed = game_boy.clock_count - start;
+    assert!(elapsed.abs_diff(3 * FRAME_CYCLES) <= 12, "{}", elapsed);
+
+    let start = game_boy.instruction_count;
+    let result = run(
+        &mut game_boy,
+        &mut debugger,
+        &["runfor", "100", "instructions"],
+    );
+    assert_eq!(result, RunResult::ReachTargetInstruction);
+    assert_eq!(game_boy.instruction_count - start, 100);
+
+    let start = game_boy.clock_count;
+    let result = run(&mut game_boy, &mut debugger, &["runfor", "1000", "cycles"]);
+    assert_eq!(result, RunResult::ReachTargetClock);
+    let elapsed = game_boy.clock_count - start;
+    assert!((1000..1012).contains(&elapsed), "{}", elapsed);
+
+    // the targets are cleared when reached
+    assert_eq!(
+        debugger.run_for(&mut game_boy, 2 * FRAME_CYCLES),
+        RunResult::TimeOut
+    );
+
+    // a breakpoint stops first
+    debugger
+        .execute_command(&mut game_boy, &["break", "x", "0101"])
+        .unwrap();
+    let result = run(&mut game_boy, &mut debugger, &["runto", "vblank"]);
+    assert_eq!(result, RunResult::ReachBreakpoint);
+    assert_eq!(game_boy.cpu.pc, 0x0101);
+
+    for args in [
+        &["runto", "line", "154"][..],
+        &["runto", "line"],
+        &["runto", "vblank", "1"],
+        &["runfor", "3"],
+        &["runfor", "0", "frames"],
+        &["runfor", "3", "seconds"],
+    ] {
+        assert!(debugger.execute_command(&mut game_boy, args).is_err());
+    }
+}
+
+#[test]
+fn oam_dma_bus_conflict() {
+    let cartridge = program_cartridge(&[0x18, 0xFE]); // JR -2
+    let mut game_boy = GameBoy::new(None, cartridge);
+    let source: Vec<u8> = (0..0xA0).map(|x| 0x20 + x as u8).collect();
+    game_boy.write_range(0xC000, &source);
+    game_boy.write(0xFF80, 0x55);
+
+    game_boy.write(0xFF46, 0xC0);
+    // the 8 cycles startup delay, plus 10 machine cycles
+    for _ in 0..2 + 10 {
+        game_boy.tick(4);
+    }
+    // reads outside HRAM return the byte being transferred
+    assert_eq!(game_boy.read(0xD123), source[10]);
+    assert_eq!(game_boy.read(0x0100), source[10]);
+    assert_eq!(game_boy.read(0xFF80), 0x55);
+    // and writes are ignored
+    game_boy.write(0xC000, 0xAA);
+    // OAM is not accessible
+    assert_eq!(game_boy.read(0xFE00), 0xFF);
+
+    game_boy.tick(4);
+    assert_eq!(game_boy.read(0xD123), source[11]);
+
+    // finish the transfer
+    for _ in 0..160 {
+        game_boy.tick(4);
+    }
+    assert_eq!(game_boy.read(0xC000), source[0]);
+    assert_eq!(game_boy.read(0x0100), 0x18);
+    assert_eq!(game_boy.peek_range(0xFE00, 0xA0), source);
+}
+
+/// Create a 32 KiB rom with a valid header.
+#[test]
+fn trace_logger() {
+    let cartridge = program_cartridge(&[
+        0x00, // NOP
+        0x3C, // INC A
+        0x18, 0xFD, // JR $0101
+    ]);
+    let mut game_boy = GameBoy::new(None, cartridge);
+    game_boy.interrupt_enabled = 0;
+    let mut debugger = Debugger::default();
+
+    let path = std::env::temp_dir().join(format!("gameroy_trace_{}.log", std::process::id()));
+    let path_str = path.to_str().unwrap();
+    debugger
+        .execute_command(&mut game_boy, &["trace", "start", path_str])
+        .unwrap();
+    for _ in 0..100 {
+        game_boy.step_instruction();
+    }
+    let output = debugger
+        .execute_command(&mut game_boy, &["trace", "stop"])
+        .unwrap();
+    assert_eq!(output, "traced 100 instructions");
+
+    let log = std::fs::read_to_string(&path).unwrap();
+    let _ = std::fs::remove_file(&path);
+    let lines: Vec<&str> = log.lines().collect();
+    assert_eq!(lines.len(), 100);
+
+    let (regs, rest) = lines[0].split_at(lines[0].find(" (cy: ").unwrap());
+    assert_eq!(
+        regs,
+        "A:01 F:Z-HC B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0100"
+    );
+    assert!(rest.ends_with(") |[00]0x0100: NOP"));
+    assert!(lines[1].starts_with("A:01 F:Z-HC "));
+    assert!(lines[1].ends_with("|[00]0x0101: INC  A"));
+    assert!(lines[2].starts_with("A:02 F:---C "));
+    assert!(lines[2].ends_with("|[00]0x0102: JR   $0101"));
+
+    // the cycle count increases with each instruction
+    let clock = |line: &str| -> u64 {
+        let start = line.find("(cy: ").unwrap() + 5;
+        let end = line[start..].find(')').unwrap() + start;
+        line[start..end].parse().unwrap()
+    };
+    assert_eq!(clock(lines[1]) - clock(lines[0]), 4);
+    assert_eq!(clock(lines[2]) - clock(lines[1]), 4);
+    assert_eq!(clock(lines[3]) - clock(lines[2]), 12);
+
+    // stopping again is an error
+    assert!(debugger
+        .execute_command(&mut game_boy, &["trace", "stop"])
+        .is_err());
+}
+
+#[test]
+fn cpu_ppu_state() {
+    let cartridge = program_cartridge(&[
+        0x3C, // INC A
+        0x18, 0xFD, // JR $0100
+    ]);
+    let mut game_boy = GameBoy::new(None, cartridge);
+    game_boy.interrupt_enabled = 0x05;
+
+    let state = gameroy::debugger::cpu_ppu_state(&game_boy);
+    let lines: Vec<&str> = state.lines().collect();
+    assert_eq!(lines[0], "AF:01B0 BC:0013 DE:00D8 HL:014D");
+    assert_eq!(lines[1], "SP:FFFE PC:0100 IME:off");
+    assert!(lines[2].starts_with("LCDC:91 STAT:"));
+    assert!(lines[3].ends_with("IE:05"));
+    assert_eq!(lines[4], "00:0100 INC  A");
+
+    // gathering the state has no side effects
+    let clock_count = game_boy.clock_count;
+    assert_eq!(gameroy::debugger::cpu_ppu_state(&game_boy), state);
+    assert_eq!(game_boy.clock_count, clock_count);
+
+    game_boy.step_instruction();
+    let state = gameroy::debugger::cpu_ppu_state(&game_boy);
+    assert!(state.starts_with("AF:0210 "));
+    assert!(state.ends_with("00:0101 JR   $0100"));
+}
+
+#[test]
+fn instruction_history() {
+    let cartridge = program_cartridge(&[
+        0x3C, // INC A
+        0x04, // INC B
+        0x18, 0xFC, // JR $0100
+    ]);
+    let mut game_boy = GameBoy::new(None, cartridge);
+    game_boy.interrupt_enabled = 0;
+    let mut debugger = Debugger::default();
+
+    debugger
+        .execute_command(&mut game_boy, &["history", "on", "4"])
+        .unwrap();
+    for _ in 0..10 {
+        game_boy.step_instruction();
+    }
+
+    // the ring wrapped around, and only keeps the last 4 instructions
+    let history = game_boy.instruction_history.as_ref().unwrap();
+    assert_eq!(history.capacity(), 4);
+    assert_eq!(history.len(), 4);
+    let pcs: Vec<u16> = history.iter().map(|x| x.pc).collect();
+    assert_eq!(pcs, [0x0100, 0x0102, 0x0101, 0x0100]);
+    assert_eq!(history.get(0).unwrap().a, 0x04);
+    assert_eq!(history.get(3).unwrap().a, 0x03);
+    assert_eq!(history.get(0).unwrap().bank, Some(0));
+    assert_eq!(history.get(1).unwrap().opcode, [0x18, 0xFC, 0x00]);
+    assert!(history.get(4).is_none());
+
+    let output = debugger
+        .execute_command(&mut game_boy, &["history", "2"])
+        .unwrap();
+    let lines: Vec<&str> = output.lines().collect();
+    assert_eq!(lines.len(), 2);
+    assert!(lines[0].starts_with("00:0100  INC  A"));
+    assert!(lines[1].starts_with("00:0102  JR   $0100"));
+
+    // the dump is from the oldest to the most recent
+    let path = std::env::temp_dir().join(format!("gameroy_history_{}.txt", std::process::id()));
+    let path_str = path.to_str().unwrap();
+    debugger
+        .execute_command(&mut game_boy, &["history", "dump", path_str])
+        .unwrap();
+    let dump = std::fs::read_to_string(&path).unwrap();
+    let _ = std::fs::remove_file(&path);
+    let lines: Vec<&str> = dump.lines().collect();
+    assert_eq!(lines.len(), 4);
+    assert!(lines[0].starts_with("00:0100  INC  A"));
+    assert!(lines[3].starts_with("00:0100  INC  A"));
+
+    debugger
+        .execute_command(&mut game_boy, &["history", "off"])
+        .unwrap();
+    assert!(game_boy.instruction_history.is_none());
+    assert!(debugger
+        .execute_command(&mut game_boy, &["history"])
+        .is_err());
+}
+
+#[test]
+fn cpu_lock() {
+    let cartridge = program_cartridge(&[
+        0x00, // NOP
+        0x00, // NOP
+        0xD3, // illegal opcode
+    ]);
+    let mut game_boy = GameBoy::new(None, cartridge);
+    let mut debugger = Debugger::default();
+    debugger
+        .execute_command(&mut game_boy, &["history", "on"])
+        .unwrap();
+    assert_eq!(debugger.run_for(&mut game_boy, 1000), RunResult::CpuLocked);
+    assert_eq!(game_boy.cpu.pc, 0x0102);
+
+    let history = game_boy.instruction_history.as_ref().unwrap();
+    assert_eq!(history.capacity(), 10_000);
+    let pcs: Vec<u16> = history.iter().map(|x| x.pc).collect();
+    assert_eq!(pcs, [0x0101, 0x0100]);
+}
+
+#[test]
+fn rom_coverage() {
+    // A MBC1 cartridge of 4 banks, filled with RET, that calls a routine in bank 2.
+    let mut rom = vec![0xC9; 0x10000];
+    rom[0x147] = 0x01;
+    rom[0x148] = 0x01;
+    rom[0x149] = 0x00;
+    rom[0x100..0x104].copy_from_slice(&[0x00, 0xC3, 0x50, 0x01]);
+    let program = [
+        0x3E, 0x02, // LD A,$02
+        0xEA, 0x00, 0x20, // LD ($2000),A
+        0xCD, 0x00, 0x40, // CALL $4000
+        0x18, 0xFE, // JR -2
+    ];
+    rom[0x150..0x150 + program.len()].copy_from_slice(&program);
+    rom[0x8000..0x8004].copy_from_slice(&[0x00, 0x00, 0x00, 0xC9]);
+
+    let mut game_boy = GameBoy::new(None, Cartridge::new(rom).unwrap());
+    let mut debugger = Debugger::default();
+    debugger
+        .execute_command(&mut game_boy, &["coverage", "count", "on"])
+        .unwrap();
+    debugger.run_for(&mut game_boy, 10_000);
+
+    let report = game_boy.trace.borrow().coverage(0x10000);
+    let executed: Vec<usize> = report.banks.iter().map(|x| x.executed).collect();
+    assert_eq!(executed, [11, 0, 4, 0]);
+    assert!(report.banks.iter().all(|x| x.len == 0x4000));
+    assert_eq!(report.total().executed, 15);
+
+    let table = debugger
+        .execute_command(&mut game_boy, &["coverage"])
+        .unwrap();
+    assert!(
+        table.contains("   2         4     16384     0.02%"),
+        "{}",
+        table
+    );
+
+    {
+        use gameroy::disassembler::Address;
+        let trace = game_boy.trace.borrow();
+        let count = |bank, address| trace.execution_count(Address { bank, address });
+        assert_eq!(count(2, 0x0000), Some(1));
+        assert_eq!(count(2, 0x0003), Some(1));
+        assert_eq!(count(1, 0x0000), Some(0));
+        let jr = count(0, 0x0158).unwrap();
+        assert!(jr > 100);
+        assert_eq!(trace.max_execution_count(), Some(jr));
+    }
+
+    let mut csv = Vec::new();
+    report.write_csv(&mut csv).unwrap();
+    let csv = String::from_utf8(csv).unwrap();
+    let lines: Vec<&str> = csv.lines().collect();
+    assert_eq!(
+        lines,
+        [
+            "bank,executed,size,percentage",
+            "0,11,16384,0.07",
+            "1,0,16384,0.00",
+            "2,4,16384,0.02",
+            "3,0,16384,0.00",
+        ]
+    );
+
+    debugger
+        .execute_command(&mut game_boy, &["coverage", "count", "off"])
+        .unwrap();
+    assert!(!game_boy.trace.borrow().is_counting());
+}
+
+#[test]
+fn step_back() {
+    let program = [
+        0x3E, 0x20, // LD A,$20
+        0xE0, 0x00, // LDH ($00),A
+        0xF0, 0x00, // LDH A,($00)
+        0xEA, 0x00, 0xC0, // LD ($C000),A
+        0x3C, // INC A
+        0xEA, 0x01, 0xC0, // LD ($C001),A
+        0x18, 0xF5, // JR $0104
+    ];
+    let mut game_boy = GameBoy::new(None, program_cartridge(&program));
+    game_boy.interrupt_enabled = 0;
+    let mut debugger = Debugger::default();
+    debugger.history.interval = 1;
+    debugger.history.capacity = 3;
+
+    // the save states of some instructions, to compare after stepping back
+    let mut references = Vec::new();
+    for i in 0..30_000 {
+        // change the input from time to time
+        if i % 1000 == 0 {
+            game_boy.set_joypad(!(1 << (i / 1000 % 8)));
+        }
+        debugger.step(&mut game_boy);
+        if i >= 27_000 && (i % 500 == 0 || i >= 29_980) {
+            let mut state = Vec::new();
+            game_boy.save_state(&mut state).unwrap();
+            references.push((game_boy.instruction_count, state));
+        }
+    }
+    assert!(debugger.history.available_seconds(&game_boy) > 0.0);
+
+    let check = |game_boy: &GameBoy| {
+        let count = game_boy.instruction_count;
+        let (_, state) = references.iter().find(|x| x.0 == count).unwrap();
+        let mut reference = GameBoy::new(None, program_cartridge(&program));
+        reference.load_state(&mut state.as_slice()).unwrap();
+        assert!(assert_gb_eq(&reference, game_boy));
+    };
+
+    let count = game_boy.instruction_count;
+    debugger.step_back(&mut game_boy, 10).unwrap();
+    assert_eq!(game_boy.instruction_count, count - 10);
+    check(&game_boy);
+
+    // step back to before some joypad changes
+    let target = references[0].0;
+    let count = game_boy.instruction_count - target;
+    debugger.step_back(&mut game_boy, count).unwrap();
+    assert_eq!(game_boy.instruction_count, target);
+    check(&game_boy);
+
+    // the execution continues normally after stepping back
+    debugger.step(&mut game_boy);
+    assert_eq!(game_boy.instruction_count, target + 1);
+
+    // only the last 3 frames are kept
+    let count = game_boy.instruction_count;
+    assert!(debugger.step_back(&mut game_boy, count).is_err());
+}
+
+#[test]
+fn joypad_interrupt() {
+    let mut game_boy = GameBoy::new(None, program_cartridge(&[]));
+
+    // select the direction keys (P14 low)
+    game_boy.write(0xFF00, 0x20);
+    game_boy.interrupt_flag = 0;
+
+    // press Right, on a selected line
+    game_boy.set_joypad(!0x01);
+    assert_eq!(game_boy.read(0xFF00) & 0x0F, 0x0E);
+    assert_eq!(game_boy.interrupt_flag & 0x10, 0x10);
+
+    // keep Right pressed and release it, without new presses
+    game_boy.interrupt_flag = 0;
+    game_boy.set_joypad(!0x01);
+    game_boy.set_joypad(0xFF);
+    assert_eq!(game_boy.interrupt_flag & 0x10, 0);
+
+    // press A, on a unselected line
+    game_boy.set_joypad(!0x10);
+    assert_eq!(game_boy.read(0xFF00) & 0x0F, 0x0F);
+    assert_eq!(game_boy.interrupt_flag & 0x10, 0);
+
+    // selecting the action buttons (P15 low) exposes the pressed A
+    game_boy.write(0xFF00, 0x10);
+    assert_eq!(game_boy.read(0xFF00) & 0x0F, 0x0E);
+    assert_eq!(game_boy.interrupt_flag & 0x10, 0x10);
+
+    // deselecting both lines don't trigger it
+    game_boy.interrupt_flag = 0;
+    game_boy.write(0xFF00, 0x30);
+    assert_eq!(game_boy.interrupt_flag & 0x10, 0);
+}
+
+#[test]
+fn joypad_interrupt_wakes_halt() {
+    let cartridge = program_cartridge(&[
+        0x3E, 0x20, // LD A,$20
+        0xE0, 0x00, // LDH ($00),A
+        0x3E, 0x10, // LD A,$10
+        0xE0, 0xFF, // LDH ($FF),A
+        0xAF, // XOR A
+        0xE0, 0x0F, // LDH ($0F),A
+        0x76, // HALT
+        0x00, // NOP
+        0x18, 0xFE, // JR -2
+    ]);
+    let mut game_boy = GameBoy::new(None, cartridge);
+    let mut inter = Interpreter(&mut game_boy);
+    for _ in 0..1000 {
+        inter.interpret_op();
+    }
+    assert_eq!(game_boy.cpu.pc, 0x010C);
+
+    game_boy.set_joypad(!0x04);
+    let mut inter = Interpreter(&mut game_boy);
+    for _ in 0..10 {
+        inter.interpret_op();
+    }
+    assert_eq!(game_boy.cpu.pc, 0x010D);
+}
+
+#[test]
+fn joypad_wakes_stop() {
+    let cartridge = program_cartridge(&[
+        0x3E, 0x20, // LD A,$20
+        0xE0, 0x00, // LDH ($00),A
+        0x3E, 0x01, // LD A,$01
+        0xE0, 0xFF, // LDH ($FF),A
+        0x10, 0x00, // STOP
+        0x00, // NOP
+        0x18, 0xFE, // JR -2
+    ]);
+    let mut game_boy = GameBoy::new(None, cartridge);
+    let mut inter = Interpreter(&mut game_boy);
+    for _ in 0..50_000 {
+        inter.interpret_op();
+    }
+    // the enabled V-Blank interrupt don't exit STOP
+    assert_eq!(game_boy.interrupt_flag & 0x01, 0x01);
+    assert_eq!(game_boy.cpu.pc, 0x010A);
+
+    // a button on a unselected line neither
+    game_boy.set_joypad(!0x10);
+    let mut inter = Interpreter(&mut game_boy);
+    for _ in 0..10 {
+        inter.interpret_op();
+    }
+    assert_eq!(game_boy.cpu.pc, 0x010A);
+
+    game_boy.set_joypad(!0x04);
+    assert_eq!(game_boy.interrupt_flag & 0x10, 0x10);
+    let mut inter = Interpreter(&mut game_boy);
+    for _ in 0..10 {
+        inter.interpret_op();
+    }
+    assert_eq!(game_boy.cpu.pc, 0x010B);
+}
+
+/// FNV-1a hash.
+fn hash(hash: &mut u64, data: &[u8]) {
+    for &b in data {
+        *hash ^= b as u64;
+        *hash = hash.wrapping_mul(0x0000_0100_0000_01B3);
+    }
+}
+
+/// A GameBoy with random tiles, tile maps and sprites, that changes the scroll and LYC registers
+/// in the middle of the scanlines.
+fn scrolling_demo() -> GameBoy {
+    let cartridge = program_cartridge(&[
+        0x21, 0x43, 0xFF, // LD HL,$FF43
+        0x34, // INC (HL) ; SCX += 1
+        0xF0, 0x44, // LDH A,($44)
+        0xE0, 0x42, // LDH ($42),A ; SCY = LY
+        0xE6, 0xF8, // AND $F8
+        0xF6, 0x04, // OR $04
+        0xE0, 0x45, // LDH ($45),A ; LYC = LY & $F8 | 4
+        0x18, 0xF3, // JR -13
+    ]);
+    let mut game_boy = GameBoy::new(None, cartridge);
+    game_boy.interrupt_enabled = 0;
+
+    let mut rng = rand_pcg(0x2545_F491_4F6C_DD1D);
+    {
+        let ppu = &mut *game_boy.ppu.borrow_mut();
+        ppu.vram.iter_mut().for_each(|x| *x = rng() as u8);
+        ppu.oam.iter_mut().for_each(|x| *x = rng() as u8);
+        ppu.stat |= 0x48; // enable mode 0 and LY=LYC STAT interrupts
+        ppu.wy = 0x50;
+        ppu.wx = 0x30;
+        ppu.lcdc = 0xB3; // enable window and sprites
+    }
+    game_boy
+}
+
+/// A xorshift64 generator.
+fn rand_pcg(mut state: u64) -> impl FnMut() -> u64 {
+    move || {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        state
+    }
+}
+
+/// Run the scrolling demo for some frames, and return a hash of each rendered frame and of the
+/// clock count of each requested interrupt.
+fn run_scrolling_demo(frames: u32) -> u64 {
+    let mut game_boy = scrolling_demo();
+    let mut h = 0xCBF2_9CE4_8422_2325;
+    let mut inter = Interpreter(&mut game_boy);
+    let mut frame = 0;
+    while frame < frames {
+        inter.interpret_op();
+        let interrupt_flag = inter.0.interrupt_flag;
+        if interrupt_flag == 0 {
+            continue;
+        }
+        inter.0.interrupt_flag = 0;
+        hash(&mut h, &inter.0.clock_count.to_le_bytes());
+        hash(&mut h, &[interrupt_flag]);
+        if interrupt_flag & 0b1 != 0 {
+            hash(&mut h, &inter.0.ppu.borrow().screen);
+            frame += 1;
+        }
+    }
+    h
+}
+
+#[test]
+fn scrolling_demo_golden() {
+    // captured before the ppu started skipping updates between its steps
+    assert_eq!(run_scrolling_demo(30), 2655549380343334507);
+}
+
+/// Measure the emulation speed of the scrolling demo.
+///
+/// Run with `cargo test --release -p gameroy-core --test test_rom -- --ignored --nocapture
+/// scrolling_demo_benchmark`.
+#[test]
+#[ignore]
+fn scrolling_demo_benchmark() {
+    let frames = 600;
+    let start = std::time::Instant::now();
+    run_scrolling_demo(frames);
+    let elapsed = start.elapsed();
+    println!(
+        "{} frames in {:?}: {:.1} times the real speed",
+        frames,
+        elapsed,
+        (frames as u64 * FRAME_CYCLES) as f64 / CLOCK_SPEED as f64 / elapsed.as_secs_f64()
+    );
+}
+
+/// Measure the duration of the mode 3 of the line 10, in clock cycles, with the given SCX and with
+/// a sprite in the line at each given OAM x position.
+fn mode_3_duration(scx: u8, sprites: &[u8]) -> u64 {
+    let cartridge = program_cartridge(&[0x18, 0xFE]); // JR -2
+    let mut game_boy = GameBoy::new(None, cartridge);
+    game_boy.interrupt_enabled = 0;
+    {
+        let ppu = &mut *game_boy.ppu.borrow_mut();
+        ppu.scx = scx;
+        ppu.lcdc = 0x93; // enable sprites
+        ppu.oam = [0; 0xA0];
+        for (i, &x) in sprites.iter().enumerate() {
+            ppu.oam[i * 4] = 10 + 16;
+            ppu.oam[i * 4 + 1] = x;
+        }
+    }
+
+    let mode = |gb: &GameBoy| {
+        let ppu = gb.ppu.borrow();
+        (ppu.ly, ppu.stat & 0b11)
+    };
+    while mode(&game_boy) != (10, 3) {
+        game_boy.tick(1);
+    }
+    let start = game_boy.clock_count;
+    while mode(&game_boy) == (10, 3) {
+        game_boy.tick(1);
+    }
+    game_boy.clock_count - start
+}
+
+#[test]
+fn mode_3_duration_scroll() {
+    // the fine scroll discards SCX % 8 pixels at the start of the line
+    for scx in 0..=16 {
+        assert_eq!(
+            mode_3_duration(scx, &[]),
+            172 + scx as u64 % 8,
+            "scx {}",
+            scx
+        );
+    }
+}
+
+#[test]
+fn mode_3_duration_sprites() {
+    // Each sprite costs 6 cycles, plus the wait for the background fetcher to finish its tile,
+    // up to 5 cycles. A sprite at x = 0 always waits the full tile.
+    let penalty = |x: u8, scx: u8| match x {
+        0 => 11,
+        _ => 6 + 5u64.saturating_sub((x as u64 + scx as u64) % 8),
+    };
+    for scx in [0, 3] {
+        for x in 0..20 {
+            assert_eq!(
+                mode_3_duration(scx, &[x]),
+                172 + scx as u64 + penalty(x, scx),
+                "scx {}, x {}",
+                scx,
+                x
+            );
+        }
+    }
+
+    // sprites in the same tile only wait for the fetcher once
+    assert_eq!(mode_3_duration(0, &[8, 8]), 172 + 11 + 6);
+    // at most 10 sprites are rendered per line
+    assert_eq!(mode_3_duration(0, &[8; 10]), 172 + 11 + 9 * 6);
+    assert_eq!(mode_3_duration(0, &[8; 11]), 172 + 11 + 9 * 6);
+    // sprites after the right edge of the screen are not fetched
+    assert_eq!(mode_3_duration(0, &[160, 168]), 172 + 11);
+}
+
+#[test]
+fn screen_buffers() {
+    let cartridge = program_cartridge(&[0x18, 0xFE]); // JR -2
+    let mut game_boy = GameBoy::new(None, cartridge);
+    // trace the program before filling the VRAM, or else the tracer would follow the empty ROM
+    // into the tiles, and from there into the missing cartridge RAM
+    game_boy
+        .trace
+        .borrow_mut()
+        .trace_starting_at(&game_boy, 0, 0x100, None);
+    game_boy.interrupt_enabled = 0;
+    {
+        let ppu = &mut *game_boy.ppu.borrow_mut();
+        // every tile has color 2, and the tile map only uses the tile 0
+        for (i, x) in ppu.vram[..0x1800].iter_mut().enumerate() {
+            *x = if i % 2 == 0 { 0x00 } else { 0xFF };
+        }
+        ppu.vram[0x1800..].iter_mut().for_each(|x| *x = 0);
+        ppu.bgp = 0x1B; // inverted palette
+    }
+
+    let mut inter = Interpreter(&mut game_boy);
+    for _ in 0..2 {
+        let target = inter.0.clock_count + FRAME_CYCLES;
+        while inter.0.clock_count < target {
+            inter.interpret_op();
+        }
+    }
+
+    let ppu = game_boy.ppu.borrow();
+    assert!(ppu.screen_color_ids().iter().all(|&x| x == 2));
+    assert!(ppu.screen.iter().all(|&x| x == 1));
+    let rgb = ppu.screen_rgb();
+    assert_eq!(rgb.len(), SCREEN_WIDTH * SCREEN_HEIGHT);
+    assert!(rgb.iter().all(|&x| x == [170, 170, 170]));
+}
+
+#[test]
+fn color_correction() {
+    use gameroy::gameboy::ppu::{rgb555_to_rgb888, ColorCorrection, DMG_COLORS};
+
+    let red = 0x001F;
+    assert_eq!(rgb555_to_rgb888(red, ColorCorrection::Raw), [255, 0, 0]);
+    assert_eq!(rgb555_to_rgb888(red, ColorCorrection::Lcd), [201, 0, 46]);
+
+    assert_eq!(rgb555_to_rgb888(0x7FFF, ColorCorrection::Raw), [255; 3]);
+    assert_eq!(rgb555_to_rgb888(0x7FFF, ColorCorrection::Lcd), [240; 3]);
+    assert_eq!(rgb555_to_rgb888(0, ColorCorrection::Lcd), [0; 3]);
+
+    // the DMG shades are only changed when corrected
+    assert_eq!(ColorCorrection::Raw.dmg_colors(), DMG_COLORS);
+    assert_eq!(
+        ColorCorrection::Lcd.dmg_colors(),
+        [[240; 3], [168; 3], [80; 3], [0; 3]]
+    );
+}
+
+#[test]
+fn power_cycle() {
+    // MBC1+RAM+BATTERY
+    let mut cartridge = Cartridge::new_test(0x03, 2, 0x02);
+    cartridge.write(0x0000, 0x0A);
+    cartridge.write(0xA000, 0x12);
+    cartridge.write(0x2000, 0x01);
+    let mut gb = GameBoy::new(Some([0; 0x100]), cartridge);
+    {
+        let mut inter = Interpreter(&mut gb);
+        for _ in 0..0x20 {
+            inter.interpret_op();
+        }
+    }
+    assert_ne!(gb.cpu.pc, 0);
+
+    // the boot rom runs again, and the RAM is kept
+    gb.power_cycle(false);
+    assert!(gb.boot_rom_active);
+    assert_eq!(gb.cpu.pc, 0);
+    assert_eq!(gb.clock_count, 0);
+    assert!(!gb.cartridge.ram_enabled());
+    assert_eq!(gb.cartridge.ram[0], 0x12);
+
+    gb.power_cycle(true);
+    assert!(gb.boot_rom_active);
+    assert_eq!(gb.cartridge.ram[0], 0x00);
+    assert!(gb.cartridge.battery_dirty());
+
+    // without a boot rom, it starts in the state after the boot
+    let mut gb = GameBoy::new(None, Cartridge::new_test(0x03, 2, 0x02));
+    Interpreter(&mut gb).interpret_op();
+    gb.power_cycle(false);
+    assert!(!gb.boot_rom_active);
+    assert_eq!(gb.cpu.pc, 0x100);
+}
+
+#[test]
+fn tile_decoding() {
+    use gameroy::gameboy::ppu::decode_tile;
+
+    #[rustfmt::skip]
+    let data = [
+        0x3C, 0x7E, 0x42, 0x42, 0x42, 0x42, 0x42, 0x42,
+        0x7E, 0x5E, 0x7E, 0x0A, 0x7C, 0x56, 0x38, 0x7C,
+    ];
+    let expected = [
+        [0, 2, 3, 3, 3, 3, 2, 0],
+        [0, 3, 0, 0, 0, 0, 3, 0],
+        [0, 3, 0, 0, 0, 0, 3, 0],
+        [0, 3, 0, 0, 0, 0, 3, 0],
+        [0, 3, 1, 3, 3, 3, 3, 0],
+        [0, 1, 1, 1, 3, 1, 3, 0],
+        [0, 3, 1, 3, 1, 3, 2, 0],
+        [0, 2, 3, 3, 3, 2, 0, 0],
+    ];
+    assert_eq!(decode_tile(&data), expected);
+
+    let gb = GameBoy::new(None, program_cartridge(&[]));
+    gb.ppu.borrow_mut().vram[0x17F0..0x1800].copy_from_slice(&data);
+    gb.ppu.borrow_mut().oam[0x9F] = 0x12;
+    assert_eq!(gb.vram_snapshot()[0x17F0], 0x3C);
+    assert_eq!(gb.oam_snapshot()[0x9F], 0x12);
+    assert_eq!(gb.tile_data(0, 0x17F), Some(expected));
+    assert_eq!(gb.tile_data(0, 0x180), None);
+    assert_eq!(gb.tile_data(1, 0x17F), None);
+}
+
+#[test]
+fn sprite_decoding() {
+    use gameroy::gameboy::ppu::{SpriteEntry, MAX_SPRITES_PER_LINE};
+
+    let mut gb = GameBoy::new(None, program_cartridge(&[]));
+    // turn the LCD off, so the OAM is not blocked
+    gb.write(0xFF40, 0x00);
+    gb.write_range(0xFE04, &[0x20, 0x18, 0x42, 0b1011_1101]);
+    let sprites = gb.sprites();
+    assert_eq!(
+        sprites[1],
+        SpriteEntry {
+            y: 0x20,
+            x: 0x18,
+            tile: 0x42,
+            bg_priority: true,
+            y_flip: false,
+            x_flip: true,
+            palette: 1,
+            cgb_bank: 1,
+            cgb_palette: 5,
+        }
+    );
+    // reading the sprites don't change the OAM
+    assert_eq!(gb.peek(0xFE07), 0b1011_1101);
+
+    // the sprite covers the lines 16 to 23, or to 31 with 8x16 sprites
+    assert_eq!(gb.sprite_height(), 8);
+    assert!(!gb.sprites_on_line(15).contains(&1));
+    assert!(gb.sprites_on_line(16).contains(&1));
+    assert!(gb.sprites_on_line(23).contains(&1));
+    assert!(!gb.sprites_on_line(24).contains(&1));
+    gb.write(0xFF40, 0x04);
+    assert_eq!(gb.sprite_height(), 16);
+    assert!(gb.sprites_on_line(31).contains(&1));
+    assert!(!gb.sprites_on_line(32).contains(&1));
+
+    // only the first 10 sprites of a line are drawn
+    for i in 0..12 {
+        gb.write_range(0xFE50 + i * 4, &[0x40, 8 * i as u8, 0, 0]);
+    }
+    let line = gb.sprites_on_line(0x30);
+    assert_eq!(line, (20..32).collect::<Vec<_>>());
+    assert_eq!(&line[MAX_SPRITES_PER_LINE..], &[30, 31]);
+}
+
+#[test]
+fn tile_map_decoding() {
+    use gameroy::gameboy::ppu::{map_tile_index, TileMap, DMG_COLORS, OUTLINE_COLOR};
+
+    let mut gb = GameBoy::new(None, program_cartridge(&[]));
+    // turn the LCD off, so the VRAM is not blocked, and use the 0x8000 tile data
+    gb.write(0xFF40, 0x10);
+    gb.write(0x9800 + 3 * 32 + 5, 0x42);
+    gb.write(0x9C00 + 31 * 32 + 31, 0x81);
+    let map = gb.bg_map(TileMap::Map9800);
+    assert_eq!(map[3][5], 0x42);
+    assert_eq!(map[5][3], 0x00);
+    assert_eq!(gb.bg_map(TileMap::Map9C00)[31][31], 0x81);
+    assert_eq!(gb.bg_attribute_map(TileMap::Map9800), None);
+
+    assert_eq!(map_tile_index(0x10, 0x81), 0x81);
+    assert_eq!(map_tile_index(0x00, 0x81), 0x81);
+    assert_eq!(map_tile_index(0x00, 0x01), 0x101);
+
+    // the tile 0x42 is filled with the color 3, which the palette maps to the color 2
+    gb.write_range(0x8420, &[0xFF; 16]);
+    gb.write(0xFF47, 0b10_00_00_00);
+    gb.write(0xFF42, 0);
+    gb.write(0xFF43, 0);
+    let background = gb.render_background();
+    assert_eq!(background.len(), 256 * 256);
+    assert_eq!(background[(3 * 8 + 1) * 256 + 5 * 8 + 1], DMG_COLORS[2]);
+    assert_eq!(background[(3 * 8 + 1) * 256 + 4 * 8 + 1], DMG_COLORS[0]);
+    // the screen is outlined at the scroll position
+    assert_eq!(background[0], OUTLINE_COLOR);
+    assert_eq!(background[143 * 256 + 159], OUTLINE_COLOR);
+    assert_eq!(background[144 * 256 + 160], DMG_COLORS[0]);
+}
+
+#[test]
+fn cheat_parsing() {
+    use gameroy::gameboy::cheats::Cheat;
+
+    assert_eq!(
+        Cheat::parse("341-50F").unwrap(),
+        Cheat::GameGenie {
+            address: 0x0150,
+            value: 0x34,
+            compare: None
+        }
+    );
+    assert_eq!(
+        Cheat::parse("3ea-01f-3b1").unwrap(),
+        Cheat::GameGenie {
+            address: 0x0A01,
+            value: 0x3E,
+            compare: Some(0xF6)
+        }
+    );
+    assert_eq!(
+        Cheat::parse("00A-17B-C49").unwrap(),
+        Cheat::GameGenie {
+            address: 0x4A17,
+            value: 0x00,
+            compare: Some(0xC8)
+        }
+    );
+    assert_eq!(
+        Cheat::parse("01FFE1C6").unwrap(),
+        Cheat::GameShark {
+            kind: 0x01,
+            address: 0xC6E1,
+            value: 0xFF
+        }
+    );
+
+    // wrong length
+    assert!(Cheat::parse("341-50").is_err());
+    assert!(Cheat::parse("01FFE1C6A").is_err());
+    // not hexadecimal
+    assert!(Cheat::parse("34G-50F").is_err());
+    // patching outside of the ROM
+    assert!(Cheat::parse("341-507").is_err());
+    // writing outside of the RAM, or unknown type
+    assert!(Cheat::parse("01FF0080").is_err());
+    assert!(Cheat::parse("42FFE1C6").is_err());
+}
+
+#[test]
+fn game_genie_cheat() {
+    let mut rom = vec![0; 0x8000];
+    rom[0x150] = 0x12;
+    rom[0x151] = 0x12;
+    let mut gb = GameBoy::new(None, Cartridge::new(rom).unwrap());
+
+    let id = gb.add_cheat("341-50F-AB2").unwrap();
+    assert_eq!(gb.read(0x150), 0x34);
+    assert_eq!(gb.read(0x151), 0x12);
+    assert_eq!(gb.peek(0x150), 0x34);
+
+    assert!(gb.set_cheat_enabled(id, false));
+    assert_eq!(gb.read(0x150), 0x12);
+    assert!(gb.set_cheat_enabled(id, true));
+    assert_eq!(gb.read(0x150), 0x34);
+
+    // the compare value don't match
+    assert!(gb.remove_cheat(id));
+    assert!(!gb.remove_cheat(id));
+    gb.add_cheat("341-50F-8BC").unwrap();
+    assert_eq!(gb.read(0x150), 0x12);
+}
+
+#[test]
+fn game_shark_cheat() {
+    // jr -2
+    let mut gb = GameBoy::new(None, program_cartridge(&[0x18, 0xFE]));
+    let id = gb.add_cheat("01FFE1C6").unwrap();
+
+    let start = gb.clock_count;
+    let mut inter = Interpreter(&mut gb);
+    while inter.0.clock_count < start + 2 * 70224 {
+        inter.interpret_op();
+    }
+    assert_eq!(gb.read(0xC6E1), 0xFF);
+
+    // disabled codes are not applied
+    gb.write(0xC6E1, 0x00);
+    gb.set_cheat_enabled(id, false);
+    let mut inter = Interpreter(&mut gb);
+    while inter.0.clock_count < start + 4 * 70224 {
+        inter.interpret_op();
+    }
+    assert_eq!(gb.read(0xC6E1), 0x00);
+}
+
+#[test]
+fn game_shark_cartridge_ram() {
+    use gameroy::gameboy::cheats::Cheat;
+
+    // MBC5+RAM+BATTERY, with 128 KiB of RAM, and the bank 1 mapped
+    let mut cartridge = Cartridge::new_test(0x1B, 4, 0x04);
+    // jr -2
+    cartridge.rom[0x100..0x102].copy_from_slice(&[0x18, 0xFE]);
+    cartridge.write(0x4000, 0x01);
+    let mut gb = GameBoy::new(None, cartridge);
+
+    // writes to the bank 3, and to the mapped bank, even with the RAM disabled
+    assert_eq!(Cheat::parse("834200A0").unwrap().ram_bank(), Some(3));
+    assert_eq!(Cheat::parse("012410A0").unwrap().ram_bank(), None);
+    gb.add_cheat("834200A0").unwrap();
+    gb.add_cheat("012410A0").unwrap();
+
+    let start = gb.clock_count;
+    let mut inter = Interpreter(&mut gb);
+    while inter.0.clock_count < start + 2 * 70224 {
+        inter.interpret_op();
+    }
+    assert!(!gb.cartridge.ram_enabled());
+    assert_eq!(gb.cartridge.ram[3 * 0x2000], 0x42);
+    assert_eq!(gb.cartridge.ram[0x2000], 0x00);
+    assert_eq!(gb.cartridge.ram[0x2010], 0x24);
+    assert_eq!(gb.cartridge.ram[0x0010], 0x00);
+
+    // the cheats don't mark the battery save as dirty
+    assert!(!gb.cartridge.battery_dirty());
+    assert_eq!(gb.cartridge.ram_write_count(), 0);
+}
+
+#[test]
+fn ram_search_filters() {
+    use gameroy::debugger::ram_search::{RamSearch, RamSnapshot, SearchFilter, SearchSize};
+
+    let mut snapshot = RamSnapshot::default();
+    snapshot.set(0xC000, 3);
+    snapshot.set(0xC001, 3);
+    snapshot.set(0xD000, 5);
+    snapshot.set(0xFF80, 3);
+
+    let mut search = RamSearch::start(snapshot.clone(), SearchSize::Byte);
+    assert_eq!(search.results().len(), 0x2000 + 0x2000 + 0x7F);
+
+    search.filter(SearchFilter::Equal(3), snapshot.clone());
+    assert_eq!(search.results(), &[0xC000, 0xC001, 0xFF80]);
+
+    // lose a life
+    snapshot.set(0xC000, 2);
+    snapshot.set(0xFF80, 4);
+    search.filter(SearchFilter::Decreased, snapshot.clone());
+    assert_eq!(search.results(), &[0xC000]);
+    assert_eq!(search.previous_value(0xC000), 2);
+
+    let mut search = RamSearch::start(snapshot.clone(), SearchSize::Byte);
+    snapshot.set(0xC001, 9);
+    snapshot.set(0xD000, 4);
+    let mut changed = RamSearch::start(snapshot.clone(), SearchSize::Byte);
+    search.filter(SearchFilter::Increased, snapshot.clone());
+    assert_eq!(search.results(), &[0xC001]);
+
+    snapshot.set(0xC001, 0);
+    changed.filter(SearchFilter::Changed, snapshot.clone());
+    assert_eq!(changed.results(), &[0xC001]);
+    snapshot.set(0xA000, 1);
+    changed.filter(SearchFilter::Unchanged, snapshot.clone());
+    assert_eq!(changed.results(), &[0xC001]);
+}
+
+#[test]
+fn ram_search_words() {
+    use gameroy::debugger::ram_search::{RamSearch, RamSnapshot, SearchFilter, SearchSize};
+
+    let mut snapshot = RamSnapshot::default();
+    snapshot.set(0xC100, 0x34);
+    snapshot.set(0xC101, 0x12);
+    assert_eq!(snapshot.value(0xC100, SearchSize::Word), 0x1234);
+
+    let mut search = RamSearch::start(snapshot.clone(), SearchSize::Word);
+    // the last address of each region is excluded
+    assert_eq!(search.results().len(), 0x1FFF + 0x1FFF + 0x7E);
+    assert!(!search.results().contains(&0xBFFF));
+
+    search.filter(SearchFilter::Equal(0x1234), snapshot.clone());
+    assert_eq!(search.results(), &[0xC100]);
+
+    // a carry into the high byte
+    snapshot.set(0xC100, 0x00);
+    snapshot.set(0xC101, 0x13);
+    search.filter(SearchFilter::Increased, snapshot.clone());
+    assert_eq!(search.results(), &[0xC100]);
+}
+
+#[test]
+fn ram_search_command() {
+    // jr -2
+    let mut gb = GameBoy::new(None, program_cartridge(&[0x18, 0xFE]));
+    let mut debugger = Debugger::default();
+
+    assert!(debugger
+        .execute_command(&mut gb, &["search", "eq", "3"])
+        .is_err());
+    assert!(debugger
+        .execute_command(&mut gb, &["search", "start", "4"])
+        .is_err());
+
+    gb.write(0xC123, 0x03);
+    debugger
+        .execute_command(&mut gb, &["search", "start"])
+        .unwrap();
+    debugger
+        .execute_command(&mut gb, &["search", "eq", "3"])
+        .unwrap();
+    gb.write(0xC123, 0x02);
+    let output = debugger
+        .execute_command(&mut gb, &["search", "dec"])
+        .unwrap();
+    assert_eq!(output, "1 results");
+    let output = debugger
+        .execute_command(&mut gb, &["search", "list"])
+        .unwrap();
+    assert_eq!(output, "1 results\nc123 = 2");
+
+    // freeze it
+    let address = debugger.ram_search.as_ref().unwrap().results()[0];
+    let code = gameroy::gameboy::cheats::Cheat::game_shark_code(address, 0x03);
+    assert_eq!(code, "010323C1");
+    gb.add_cheat(&code).unwrap();
+    let start = gb.clock_count;
+    let mut inter = Interpreter(&mut gb);
+    while inter.0.clock_count < start + 2 * 70224 {
+        inter.interpret_op();
+    }
+    assert_eq!(gb.read(0xC123), 0x03);
+}
+
+#[test]
+fn memory_view_regions() {
+    use gameroy::debugger::memory_view::{parse_pattern, MemoryRegion};
+
+    let mut rom = vec![0; 0x8000];
+    rom[0x147] = 0x1B;
+    rom[0x149] = 0x03;
+    rom[0x4010..0x4014].copy_from_slice(b"GAME");
+    let cartridge = Cartridge::new(rom).unwrap();
+    let mut gb = GameBoy::new(None, cartridge);
+
+    assert_eq!(MemoryRegion::parse("rom 1"), Ok(MemoryRegion::RomBank(1)));
+    assert_eq!(
+        MemoryRegion::parse("sram 3"),
+        Ok(MemoryRegion::CartRamBank(3))
+    );
+    assert_eq!(MemoryRegion::parse("hram"), Ok(MemoryRegion::Hram));
+    assert!(MemoryRegion::parse("rom").is_err());
+    assert!(MemoryRegion::parse("vram 1").is_err());
+    assert!(MemoryRegion::parse("sram 100").is_err());
+    assert!(MemoryRegion::parse("xram").is_err());
+
+    // the region length follows the cartridge size
+    assert_eq!(MemoryRegion::RomBank(1).len(&gb), 0x4000);
+    assert_eq!(MemoryRegion::RomBank(2).len(&gb), 0);
+    assert_eq!(MemoryRegion::CartRamBank(3).len(&gb), 0x2000);
+    assert_eq!(MemoryRegion::CartRamBank(4).len(&gb), 0);
+    assert_eq!(MemoryRegion::Hram.offset(&gb, 0xFF81), Some(1));
+    assert_eq!(MemoryRegion::Hram.offset(&gb, 0xFFFF), None);
+
+    // a unmapped RAM bank is read and written directly
+    let region = MemoryRegion::CartRamBank(2);
+    region.write(&mut gb, 0x10, 0x42);
+    assert_eq!(gb.cartridge.ram[2 * 0x2000 + 0x10], 0x42);
+    assert_eq!(region.read(&gb, 0x10), 0x42);
+    assert_eq!(gb.peek(0xA010), 0xFF);
+
+    // the mapped one through the memory map
+    gb.write(0x0000, 0x0A);
+    gb.write(0x4000, 0x02);
+    region.write(&mut gb, 0x11, 0x43);
+    assert_eq!(gb.peek(0xA011), 0x43);
+    assert_eq!(gb.cartridge.ram[2 * 0x2000 + 0x11], 0x43);
+
+    // writes to ROM are MBC register writes
+    let region = MemoryRegion::RomBank(1);
+    assert!(region.writes_to_mbc(0x1000));
+    assert!(!MemoryRegion::Wram.writes_to_mbc(0));
+    region.write(&mut gb, 0x0000, 0x00);
+    assert_eq!(gb.cartridge.rom[0x4000], 0x00);
+    assert!(gb.cartridge.ram_enabled());
+
+    // search
+    assert_eq!(parse_pattern("47 41"), Ok(b"GA".to_vec()));
+    assert_eq!(parse_pattern("4741"), Ok(b"GA".to_vec()));
+    assert_eq!(parse_pattern("\"ME\""), Ok(b"ME".to_vec()));
+    assert!(parse_pattern("474").is_err());
+    assert!(parse_pattern("zz").is_err());
+    assert!(parse_pattern("\"ME").is_err());
+    let pattern = parse_pattern("\"GAME\"").unwrap();
+    assert_eq!(region.search(&gb, &pattern, 0), Some(0x10));
+    // it wraps around
+    assert_eq!(region.search(&gb, &pattern, 0x11), Some(0x10));
+    assert_eq!(MemoryRegion::All.search(&gb, &pattern, 0), Some(0x4010));
+    assert_eq!(MemoryRegion::Wram.search(&gb, &pattern, 0), None);
+}
+
+#[test]
+fn symbols_loading() {
+    // in the format of RGBDS
+    const RGBDS_SYMBOLS: &str = "; File generated by rgblink
+00:0150 Start
+00:0150 Start.duplicate ; a second label in the same address is ignored
+01:4000 Bank1Routine
+200:4000 OutOfRange
+00:c000 wPlayerHealth
+";
+    // in the format of WLA-DX
+    const WLA_SYMBOLS: &str = "[information]
+version 2
+
+[labels]
+0000:0150 Start
+0000:c000 wPlayerHealth
+
+[definitions]
+00000010 _sizeof_Start
+";
+
+    let mut program = vec![0; 0x51];
+    program[..8].copy_from_slice(&[
+        0xCD, 0x50, 0x01, // CALL $0150
+        0xFA, 0x00, 0xC0, // LD A,($C000)
+        0x18, 0xFE, // JR -2
+    ]);
+    program[0x50] = 0xC9; // RET
+
+    for (symbols, expected_count) in [(RGBDS_SYMBOLS, 3), (WLA_SYMBOLS, 2)] {
+        let mut gb = GameBoy::new(None, program_cartridge(&program));
+        let count = gb
+            .trace
+            .borrow_mut()
+            .load_symbols(symbols.as_bytes())
+            .unwrap();
+        assert_eq!(count, expected_count);
+
+        let trace = gb.trace.borrow();
+        assert_eq!(trace.symbol(Some(0), 0x0150), Some("Start"));
+        assert_eq!(trace.symbol(None, 0xC000), Some("wPlayerHealth"));
+        assert_eq!(trace.symbol(None, 0xC001), None);
+        drop(trace);
+
+        gb.trace.borrow_mut().trace_starting_at(&gb, 0, 0x100, None);
+        let mut text = String::new();
+        gb.trace.borrow().fmt(&gb, &mut text).unwrap();
+        assert!(text.contains("CALL Start"), "{}", text);
+        assert!(text.contains("LD   A, (wPlayerHealth)"), "{}", text);
+        assert!(text.contains("Start:\n"), "{}", text);
+
+        // the trace log also use the labels
+        gb.cpu.pc = 0x100;
+        let mut line = String::new();
+        gameroy::trace_logger::format_line(&gb, &mut line).unwrap();
+        assert!(line.ends_with("CALL Start"), "{}", line);
+    }
+
+    let mut gb = GameBoy::new(None, program_cartridge(&program));
+    let mut trace = gb.trace.borrow_mut();
+    assert_eq!(
+        trace.load_symbols("00:0150".as_bytes()),
+        Err("line 1: expected a address and a label".to_string())
+    );
+    assert!(trace.load_symbols("0150 Start".as_bytes()).is_err());
+    assert!(trace.load_symbols("xx:0150 Start".as_bytes()).is_err());
+    drop(trace);
+
+    // the symbols can be loaded from the debugger
+    let path = std::env::temp_dir().join("gameroy_symbols_loading.sym");
+    std::fs::write(&path, RGBDS_SYMBOLS).unwrap();
+    let mut debugger = Debugger::default();
+    let output = debugger
+        .execute_command(&mut gb, &["symbols", "load", path.to_str().unwrap()])
+        .unwrap();
+    assert!(output.starts_with("loaded 3 symbols"), "{}", output);
+    assert!(debugger
+        .execute_command(&mut gb, &["symbols", "load"])
+        .is_err());
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn export_asm() {
+    let mut program = vec![0; 0x101];
+    program[..3].copy_from_slice(&[0xC3, 0x50, 0x01]); // JP $0150
+    program[0x50..0x56].copy_from_slice(&[
+        0xCD, 0x00, 0x02, // CALL $0200
+        0x18, 0xFB, // JR $0150
+        0xC9, // RET (the tracing continues after a JR)
+    ]);
+    program[0x100] = 0xC9; // RET
+
+    let gb = GameBoy::new(None, program_cartridge(&program));
+    let mut trace = gb.trace.borrow_mut();
+    trace.load_symbols("00:0200 Sub".as_bytes()).unwrap();
+    trace.trace_starting_at(&gb, 0, 0x100, None);
+    drop(trace);
+
+    let mut asm = Vec::new();
+    gb.trace.borrow().export_asm(&gb, &mut asm).unwrap();
+    let asm = String::from_utf8(asm).unwrap();
+
+    assert!(
+        asm.contains("SECTION \"ROM Bank $00\", ROM0[$0000]\n"),
+        "{}",
+        asm
+    );
+    assert!(
+        asm.contains("SECTION \"ROM Bank $01\", ROMX[$4000], BANK[$01]\n"),
+        "{}",
+        asm
+    );
+    assert!(asm.contains("\nLabel_00_0150:\n    CALL Sub "), "{}", asm);
+    assert!(asm.contains("\n    JR   Label_00_0150 "), "{}", asm);
+    assert!(asm.contains("\nSub:\n    RET "), "{}", asm);
+    assert!(asm.contains("    JP   Label_00_0150 "), "{}", asm);
+    assert!(asm.contains("; --:0100 c3 50 01\n"), "{}", asm);
+
+    // every byte of the rom is written exactly once, as a instruction or as data
+    let (mut code_bytes, mut data_bytes) = (0, 0);
+    for line in asm.lines().filter(|x| x.starts_with("    ")) {
+        let (text, comment) = line.split_once(';').unwrap();
+        if text.trim_start().starts_with("db") {
+            data_bytes += text.matches('$').count();
+        } else {
+            code_bytes += comment.split_whitespace().count() - 1;
+        }
+    }
+    assert_eq!(code_bytes, 3 + 3 + 2 + 1 + 1);
+    assert_eq!(code_bytes + data_bytes, 0x8000);
+
+    // the listing can be written from the debugger
+    let mut gb = gb;
+    let path = std::env::temp_dir().join("gameroy_export_asm.asm");
+    let mut debugger = Debugger::default();
+    debugger
+        .execute_command(&mut gb, &["dumpasm", path.to_str().unwrap()])
+        .unwrap();
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), asm);
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn trace_persistence() {
+    let mut program = vec![0; 0x105];
+    program[..4].copy_from_slice(&[
+        0x21, 0x00, 0x02, // LD HL,$0200
+        0xE9, // JP (HL)
+    ]);
+    program[0x100..0x105].copy_from_slice(&[
+        0x3E, 0x12, // LD A,$12
+        0x18, 0xFE, // JR -2
+        0xC9, // RET (the tracing continues after a JR)
+    ]);
+    let disassembly = |gb: &GameBoy| {
+        let mut text = String::new();
+        gb.trace.borrow().fmt(gb, &mut text).unwrap();
+        text
+    };
+
+    // the code after JP (HL) is only discovered by executing it
+    let mut gb = GameBoy::new(None, program_cartridge(&program));
+    gb.trace.borrow_mut().trace_starting_at(&gb, 0, 0x100, None);
+    let mut inter = Interpreter(&mut gb);
+    while inter.0.cpu.pc != 0x0202 {
+        inter.interpret_op();
+    }
+    let executed = disassembly(&gb);
+    assert!(executed.contains("LD   A, $12"), "{}", executed);
+
+    let mut saved = Vec::new();
+    gb.trace.borrow().save(&gb, &mut saved).unwrap();
+
+    // reloading gives the same disassembly, without executing the code again
+    let mut gb = GameBoy::new(None, program_cartridge(&program));
+    gb.trace.borrow_mut().trace_starting_at(&gb, 0, 0x100, None);
+    assert!(!disassembly(&gb).contains("LD   A, $12"));
+    let count = gb
+        .trace
+        .borrow_mut()
+        .load(&gb, &mut saved.as_slice())
+        .unwrap();
+    assert_eq!(count, 5);
+    assert_eq!(disassembly(&gb), executed);
+
+    // the trace can be cleared from the debugger
+    let mut debugger = Debugger::default();
+    debugger
+        .execute_command(&mut gb, &["trace", "clear"])
+        .unwrap();
+    assert!(gb.trace.borrow().directives.is_empty());
+    assert!(gb.trace.borrow().labels.is_empty());
+
+    // a trace of a different revision of the rom is not loaded
+    program[0x101] = 0x34;
+    let gb = GameBoy::new(None, program_cartridge(&program));
+    assert_eq!(
+        gb.trace.borrow_mut().load(&gb, &mut saved.as_slice()),
+        Err("the trace is from a different rom".to_string())
+    );
+    assert!(gb.trace.borrow().directives.is_empty());
+    assert!(gb
+        .trace
+        .borrow_mut()
+        .load(&gb, &mut &saved[..10])
+        .unwrap_err()
+        .starts_with("malformed trace"));
+}
+
+/// Trigger the square channel 1 with its length counter enabled, and run the GameBoy until the
+/// length counter disables the channel, writing to DIV every `div_write_period` clocks, if any.
+/// Return the number of clocks that the channel was enabled.
+fn channel_1_length_duration(div_write_period: Option<u64>) -> u64 {
+    let mut gb = GameBoy::new(None, program_cartridge(&[]));
+    gb.write(0xFF26, 0x80);
+    // length of 64
+    gb.write(0xFF11, 0x80);
+    gb.write(0xFF12, 0xF0);
+    gb.write(0xFF14, 0xC0);
+    let start = gb.clock_count;
+    let mut last_write = start;
+    while gb.read(0xFF26) & 0x01 != 0 {
+        if gb.clock_count - start > 4 * CLOCK_SPEED {
+            break;
+        }
+        gb.tick(4);
+        if let Some(period) = div_write_period {
+            if gb.clock_count - last_write >= period {
+                gb.write(0xFF04, 0);
+                last_write = gb.clock_count;
+            }
+        }
+    }
+    gb.clock_count - start
+}
+
+#[test]
+fn div_write_frame_sequencer() {
+    // the length counter is clocked at 256 Hz, so 64 clocks takes a quarter of a second
+    let duration = channel_1_length_duration(None);
+    let quarter = CLOCK_SPEED / 4;
+    assert!(duration.abs_diff(quarter) <= 2 * 16384, "{}", duration);
+
+    // resetting DIV before its bit 4 is set prevents the frame sequencer from stepping
+    let duration = channel_1_length_duration(Some(4000));
+    assert!(duration > 4 * CLOCK_SPEED, "{}", duration);
+
+    // resetting DIV after its bit 4 is set is a falling edge, so the frame sequencer steps every
+    // 6000 clocks instead of every 8192
+    let duration = channel_1_length_duration(Some(6000));
+    let expected = 64 * 2 * 6000;
+    assert!(duration.abs_diff(expected) <= 2 * 12000, "{}", duration);
+}
+
+/// A GameBoy with the timer enabled at 262144 Hz (incrementing TIMA every 16 cycles), with DIV at
+/// 0, and TIMA about to overflow, 16 cycles from now.
+fn timer_about_to_overflow() -> GameBoy {
+    let mut gb = GameBoy::new(None, program_cartridge(&[]));
+    gb.interrupt_flag = 0;
+    gb.timer.div = 0;
+    gb.timer.tima = 0xFF;
+    gb.timer.tma = 0x42;
+    gb.timer.tac = 0b101;
+    gb.timer.last_counter_bit = false;
+    gb.timer.last_clock_count = gb.clock_count;
+    gb
+}
+
+#[test]
+fn timer_tima_reload() {
+    let mut gb = timer_about_to_overflow();
+
+    // TIMA stays 00 for 4 cycles after the overflow, without requesting the interrupt
+    gb.tick(16);
+    assert_eq!(gb.read(0xFF05), 0x00);
+    assert_eq!(gb.interrupt_flag & 0x04, 0);
+
+    gb.tick(4);
+    assert_eq!(gb.read(0xFF05), 0x42);
+    assert_eq!(gb.interrupt_flag & 0x04, 0x04);
+
+    // the interrupt is requested only once
+    gb.interrupt_flag = 0;
+    gb.tick(4);
+    assert_eq!(gb.read(0xFF05), 0x42);
+    assert_eq!(gb.interrupt_flag & 0x04, 0);
+}
+
+#[test]
+fn timer_tima_write_reloading() {
+    // writing TIMA before the reload cancels it
+    let mut gb = timer_about_to_overflow();
+    gb.tick(16);
+    gb.write(0xFF05, 0x10);
+    gb.tick(8);
+    assert_eq!(gb.read(0xFF05), 0x10);
+    assert_eq!(gb.interrupt_flag & 0x04, 0);
+
+    // writing TIMA during the reload is ignored
+    let mut gb = timer_about_to_overflow();
+    gb.tick(20);
+    gb.write(0xFF05, 0x10);
+    gb.tick(4);
+    assert_eq!(gb.read(0xFF05), 0x42);
+    assert_eq!(gb.interrupt_flag & 0x04, 0x04);
+
+    // after the reload, writes work again
+    gb.write(0xFF05, 0x10);
+    assert_eq!(gb.read(0xFF05), 0x10);
+}
+
+#[test]
+fn timer_tma_write_reloading() {
+    // writing TMA during the reload takes effect immediately
+    let mut gb = timer_about_to_overflow();
+    gb.tick(20);
+    gb.write(0xFF06, 0x55);
+    gb.tick(4);
+    assert_eq!(gb.read(0xFF05), 0x55);
+
+    // after the reload, it only affects the next one
+    gb.write(0xFF06, 0x66);
+    gb.tick(4);
+    assert_eq!(gb.read(0xFF05), 0x55);
+}
+
+#[test]
+fn timer_div_write_glitch() {
+    // writing DIV while the selected bit is set is a falling edge, that increments TIMA
+    let mut gb = timer_about_to_overflow();
+    gb.timer.tima = 0x10;
+    gb.tick(8);
+    gb.write(0xFF04, 0x00);
+    gb.tick(4);
+    assert_eq!(gb.read(0xFF05), 0x11);
+
+    // but not while it is clear
+    let mut gb = timer_about_to_overflow();
+    gb.timer.tima = 0x10;
+    gb.tick(4);
+    gb.write(0xFF04, 0x00);
+    gb.tick(4);
+    assert_eq!(gb.read(0xFF05), 0x10);
+}
+
+#[test]
+fn camera_capture_wait() {
+    let program = [
+        0x3E, 0x10, // LD A,$10
+        0xEA, 0x00, 0x40, // LD ($4000),A
+        0x3E, 0x03, // LD A,$03
+        0xEA, 0x00, 0xA0, // LD ($A000),A
+        0xFA, 0x00, 0xA0, // LD A,($A000)
+        0xE6, 0x01, // AND $01
+        0x20, 0xF9, // JR NZ,-7
+        0x18, 0xFE, // JR -2
+    ];
+    // Pocket Camera, with 128 KiB of RAM
+    let mut cartridge = Cartridge::new_test(0xFC, 64, 0x04);
+    cartridge.rom[0x100..0x100 + program.len()].copy_from_slice(&program);
+    let mut game_boy = GameBoy::new(None, cartridge);
+    game_boy.write(0x4000, 0x10);
+    for i in 0..16 * 3 {
+        game_boy.write(0xA006 + i, 0x40 * (i % 3 + 1) as u8);
+    }
+    let start = game_boy.clock_count;
+
+    let mut inter = Interpreter(&mut game_boy);
+    while inter.0.cpu.pc != 0x0111 {
+        inter.interpret_op();
+        assert!(
+            inter.0.clock_count < start + CLOCK_SPEED,
+            "capture never finished"
+        );
+    }
+    assert!(game_boy.clock_count - start >= 4 * (32446 + 512));
+
+    // the test pattern goes from white to black
+    game_boy.write(0x4000, 0x00);
+    assert_eq!(game_boy.cartridge.read(0xA100), 0x00);
+    assert_eq!(game_boy.cartridge.read(0xA100 + 15 * 16), 0xFF);
+}
+
+#[test]
+fn cartridge_bank_state() {
+    // MBC1, with 512 KiB of ROM and 32 KiB of RAM
+    let mut cartridge = Cartridge::new_test(0x03, 32, 0x03);
+    assert_eq!(cartridge.mapper_kind(), "MBC1");
+    assert_eq!(cartridge.curr_rom_bank(), 1);
+    assert_eq!(cartridge.curr_ram_bank(), 0);
+    assert!(!cartridge.ram_enabled());
+    assert_eq!(cartridge.banking_mode(), Some(false));
+
+    cartridge.write(0x0000, 0x0A);
+    cartridge.write(0x2000, 0x05);
+    cartridge.write(0x4000, 0x02);
+    assert!(cartridge.ram_enabled());
+    assert_eq!(cartridge.curr_rom_bank(), 0x05);
+    // in mode 0, the RAM bank is always 0
+    assert_eq!(cartridge.curr_ram_bank(), 0);
+    cartridge.write(0x6000, 0x01);
+    assert_eq!(cartridge.banking_mode(), Some(true));
+    assert_eq!(cartridge.curr_ram_bank(), 2);
+
+    let mut debugger = Debugger::default();
+    let mut game_boy = GameBoy::new(None, cartridge);
+    let output = debugger
+        .execute_command(&mut game_boy, &["cartridge"])
+        .unwrap();
+    assert_eq!(output, "MBC1 rom:05 ram:02 (on) mode:1");
+
+    // MBC3, where the RAM bank register also selects the RTC registers
+    let mut cartridge = Cartridge::new_test(0x13, 2, 0x03);
+    cartridge.write(0x0000, 0x0A);
+    cartridge.write(0x4000, 0x03);
+    assert_eq!(cartridge.curr_ram_bank(), 0x03);
+    cartridge.write(0x4000, 0x08);
+    assert_eq!(cartridge.curr_ram_bank(), 0x08);
+    cartridge.write(0x0000, 0x00);
+    assert!(!cartridge.ram_enabled());
+    assert_eq!(cartridge.banking_mode(), None);
+
+    // MBC5, where the bit 3 of the RAM bank controls the rumble, if any
+    for &(kind, ram_bank) in &[(0x1B, 0x0B), (0x1E, 0x03)] {
+        let mut cartridge = Cartridge::new_test(kind, 512, 0x04);
+        cartridge.write(0x2000, 0x23);
+        cartridge.write(0x3000, 0x01);
+        cartridge.write(0x4000, 0x0B);
+        assert_eq!(cartridge.curr_rom_bank(), 0x123);
+        assert_eq!(cartridge.curr_ram_bank(), ram_bank);
+    }
+
+    // Pocket Camera, with the sensor registers mapped
+    let mut cartridge = Cartridge::new_test(0xFC, 64, 0x04);
+    cartridge.write(0x2000, 0x3F);
+    cartridge.write(0x4000, 0x10);
+    assert_eq!(cartridge.curr_rom_bank(), 0x3F);
+    assert_eq!(cartridge.curr_ram_bank(), 0x10);
+}
+
+#[test]
+fn rtc_save_state() {
+    // MBC3+TIMER+RAM+BATTERY, with its clock frozen
+    let rtc_cartridge = || {
+        let mut cartridge = Cartridge::new_test(0x10, 2, 0x03);
+        cartridge.rtc_mut().unwrap().frozen_time = Some(1_600_000_000);
+        cartridge
+    };
+    let mut game_boy = GameBoy::new(None, rtc_cartridge());
+    game_boy.cartridge.rtc_mut().unwrap().set_seconds(12345);
+    let mut state = Vec::new();
+    game_boy.save_state(&mut state).unwrap();
+
+    let mut loaded = GameBoy::new(None, rtc_cartridge());
+    loaded.load_state(&mut state.as_slice()).unwrap();
+    assert_eq!(loaded.cartridge.rtc().unwrap().seconds(), 12345);
+}
+
+#[test]
+fn save_state_rom_mismatch() {
+    let mut game_boy = GameBoy::new(None, program_cartridge(&[0x3C])); // INC A
+    game_boy.cpu.a = 0x42;
+    let mut state = Vec::new();
+    game_boy.save_state(&mut state).unwrap();
+
+    // a save state of the same rom is loaded
+    let mut same = GameBoy::new(None, program_cartridge(&[0x3C]));
+    assert_eq!(
+        same.read_state_header(&mut state.as_slice())
+            .unwrap()
+            .rom_crc32,
+        Some(game_boy.cartridge.rom_crc32())
+    );
+    same.load_state(&mut state.as_slice()).unwrap();
+    assert_eq!(same.cpu.a, 0x42);
+
+    // and one from a different rom is rejected, without changing the GameBoy
+    let mut other = GameBoy::new(None, program_cartridge(&[0x3D])); // DEC A
+    let a = other.cpu.a;
+    let err = other.load_state(&mut state.as_slice()).unwrap_err();
+    assert!(matches!(err, LoadStateError::RomMismatch(x, y)
+        if x == game_boy.cartridge.rom_crc32() && y == other.cartridge.rom_crc32()));
+    assert!(err
+        .to_string()
+        .starts_with("the save state is from a different rom"));
+    assert_eq!(other.cpu.a, a);
+}
+
+fn rom_with_valid_header() -> Vec<u8> {
+    let mut rom = vec![0; 0x8000];
+    rom[0x104..=0x133].copy_from_slice(&CartridgeHeader::NINTENDO_LOGO);
+    rom[0x134..0x134 + 4].copy_from_slice(b"TEST");
+    rom[0x14D] = rom[0x134..=0x14C]
+        .iter()
+        .fold(0u8, |x, &b| x.wrapping_sub(b).wrapping_sub(1));
+    rom
+}
+
+#[test]
+fn header_valid() {
+    let rom = rom_with_valid_header();
+    let header = CartridgeHeader::from_bytes(&rom).unwrap();
+    assert!(header.logo_valid());
+    assert!(header.header_checksum_valid());
+    assert_eq!(header.title_as_string(), "TEST");
+}
+
+#[test]
+fn header_invalid_checksum() {
+    let mut rom = rom_with_valid_header();
+    rom[0x14D] ^= 0x01;
+    let header = match CartridgeHeader::from_bytes(&rom) {
+        Err((Some(header), _)) => header,
+        _ => panic!("expected checksum error"),
+    };
+    assert!(header.logo_valid());
+    assert!(!header.header_checksum_valid());
+
+    let mut rom = rom_with_valid_header();
+    rom[0x110] ^= 0x01;
+    let header = CartridgeHeader::from_bytes(&rom).unwrap();
+    assert!(!header.logo_valid());
+}
+
+#[test]
+fn header_info() {
+    use gameroy::gameboy::cartridge::CgbSupport;
+
+    let header = |edit: &dyn Fn(&mut [u8])| {
+        let mut rom = rom_with_valid_header();
+        edit(&mut rom);
+        match CartridgeHeader::from_bytes(&rom) {
+            Ok(x) | Err((Some(x), _)) => x,
+            Err((None, err)) => panic!("{}", err),
+        }
+    };
+
+    let dmg = header(&|_| {});
+    assert_eq!(dmg.mapper_name(), "ROM ONLY");
+    assert_eq!(dmg.cgb_support(), CgbSupport::None);
+    assert!(!dmg.supports_sgb());
+    assert_eq!(dmg.licensee(), "None");
+
+    let game = header(&|rom| {
+        rom[0x143] = 0x80;
+        rom[0x144..=0x145].copy_from_slice(b"01");
+        rom[0x146] = 0x03;
+        rom[0x147] = 0x1B;
+        rom[0x14B] = 0x33;
+    });
+    assert_eq!(game.cartridge_type_name(), "MBC5+RAM+BATTERY");
+    assert_eq!(game.mapper_name(), "MBC5");
+    assert_eq!(game.cgb_support(), CgbSupport::Enhanced);
+    assert!(game.supports_sgb());
+    assert_eq!(game.licensee(), "Nintendo");
+
+    let game = header(&|rom| {
+        rom[0x143] = 0xC0;
+        rom[0x146] = 0x03;
+        rom[0x14B] = 0x01;
+    });
+    assert_eq!(game.cgb_support(), CgbSupport::Only);
+    // the SGB flag is ignored without the new licensee code
+    assert!(!game.supports_sgb());
+    assert_eq!(game.licensee(), "Nintendo");
+
+    // unknown codes are shown as they are
+    assert_eq!(header(&|rom| rom[0x14B] = 0x02).licensee(), "02");
+    let game = header(&|rom| {
+        rom[0x144..=0x145].copy_from_slice(b"ZZ");
+        rom[0x14B] = 0x33;
+    });
+    assert_eq!(game.licensee(), "ZZ");
+    assert_eq!(header(&|rom| rom[0x147] = 0x42).mapper_name(), "Unknown");
+}
+
+/// Write the global checksum of the rom, in big endian.
+fn fix_global_checksum(rom: &mut [u8]) {
+    let checksum = CartridgeHeader::compute_global_checksum(rom);
+    rom[0x14E..=0x14F].copy_from_slice(&checksum.to_be_bytes());
+}
+
+#[test]
+fn rom_integrity_flags() {
+    let mut rom = rom_with_valid_header();
+    rom[0x1000] = 0x42;
+    fix_global_checksum(&mut rom);
+    let integrity = RomIntegrity::from_rom(&rom).unwrap();
+    assert_eq!(
+        integrity,
+        RomIntegrity {
+            logo_valid: true,
+            header_checksum_valid: true,
+            global_checksum_valid: Some(true),
+            expected_size: Some(0x8000),
+            size: Some(0x8000),
+        }
+    );
+    assert!(integrity.is_valid());
+    assert_eq!(Cartridge::new(rom.clone()).unwrap().integrity, integrity);
+
+    // only the header
+    let header = CartridgeHeader::from_bytes(&rom).unwrap();
+    let integrity = RomIntegrity::from_header(&header);
+    assert_eq!(integrity.global_checksum_valid, None);
+    assert_eq!(integrity.size, None);
+    assert!(integrity.is_valid());
+
+    // corrupted logo
+    let mut bad = rom.clone();
+    bad[0x120] ^= 0x10;
+    let integrity = RomIntegrity::from_rom(&bad).unwrap();
+    assert!(!integrity.logo_valid);
+    assert!(integrity.header_checksum_valid);
+    assert_eq!(integrity.global_checksum_valid, Some(false));
+    assert!(Cartridge::new(bad).is_ok());
+
+    // corrupted header checksum
+    let mut bad = rom.clone();
+    bad[0x14D] ^= 0x01;
+    let integrity = RomIntegrity::from_rom(&bad).unwrap();
+    assert!(integrity.logo_valid);
+    assert!(!integrity.header_checksum_valid);
+    assert_eq!(integrity.global_checksum_valid, Some(false));
+    assert!(Cartridge::new(bad).is_ok());
+
+    // corrupted data
+    let mut bad = rom.clone();
+    bad[0x7FFF] ^= 0x01;
+    let integrity = RomIntegrity::from_rom(&bad).unwrap();
+    assert!(integrity.logo_valid && integrity.header_checksum_valid);
+    assert_eq!(integrity.global_checksum_valid, Some(false));
+    assert_eq!(integrity.warnings(), vec!["invalid global checksum"]);
+    assert!(Cartridge::new(bad).is_ok());
+
+    // unsupported rom size
+    let mut bad = rom.clone();
+    bad[0x148] = 0x20;
+    let integrity = RomIntegrity::from_rom(&bad).unwrap();
+    assert_eq!(integrity.expected_size, None);
+    assert!(!integrity.is_valid());
+    assert!(Cartridge::new(bad).is_err());
+
+    // too small for a header
+    assert!(RomIntegrity::from_rom(&rom[..0x100]).is_err());
+}
+
+#[test]
+fn rom_size_mismatch() {
+    let mut rom = rom_with_valid_header();
+    rom[0x147] = 0x01; // MBC1
+    rom[0x148] = 0x01; // 64 KiB
+    rom[0x14D] = rom[0x134..=0x14C]
+        .iter()
+        .fold(0u8, |x, &b| x.wrapping_sub(b).wrapping_sub(1));
+    rom.resize(0x10000, 0);
+    for bank in 0..4 {
+        rom[bank * 0x4000 + 0x200] = bank as u8;
+    }
+    fix_global_checksum(&mut rom);
+
+    // a overdumped rom, with trailing garbage
+    let mut overdump = rom.clone();
+    overdump.extend((0..0x1234).map(|x| x as u8));
+    let mut cartridge = Cartridge::new(overdump).unwrap();
+    assert_eq!(cartridge.rom, rom);
+    assert_eq!(cartridge.integrity.global_checksum_valid, Some(true));
+    assert!(!cartridge.integrity.size_matches());
+    assert_eq!(
+        cartridge.integrity.warnings(),
+        vec!["the rom has 70196 bytes, but the header expects 65536 bytes"]
+    );
+    cartridge.write(0x2000, 3);
+    assert_eq!(cartridge.read(0x4200), 3);
+
+    // a underdumped rom is padded with 0xFF
+    let mut cartridge = Cartridge::new(rom[..0xC000].to_vec()).unwrap();
+    assert_eq!(cartridge.rom.len(), 0x10000);
+    assert_eq!(cartridge.integrity.size, Some(0xC000));
+    assert_eq!(cartridge.integrity.global_checksum_valid, Some(false));
+    cartridge.write(0x2000, 2);
+    assert_eq!(cartridge.read(0x4200), 2);
+    cartridge.write(0x2000, 3);
+    assert_eq!(cartridge.read(0x4200), 0xFF);
+
+    // a small rom that reports the size of a MBC1M multicart
+    let mut rom = rom_with_valid_header();
+    rom[0x147] = 0x01;
+    rom[0x148] = 0x05;
+    let cartridge = Cartridge::new(rom).unwrap();
+    assert_eq!(cartridge.rom.len(), 64 * 0x4000);
+    assert_eq!(cartridge.mapper_kind(), "MBC1");
+}
+
+#[test]
+fn header_battery_and_ram() {
+    // cartridge type, has RAM, has battery
+    let types = [
+        (0x00, false, false), // ROM ONLY
+        (0x01, false, false), // MBC1
+        (0x02, true, false),  // MBC1+RAM
+        (0x03, true, true),   // MBC1+RAM+BATTERY
+        (0x05, true, false),  // MBC2
+        (0x06, true, true),   // MBC2+BATTERY
+        (0x08, true, false),  // ROM+RAM
+        (0x09, true, true),   // ROM+RAM+BATTERY
+        (0x0B, false, false), // MMM01
+        (0x0C, true, false),  // MMM01+RAM
+        (0x0D, true, true),   // MMM01+RAM+BATTERY
+        (0x0F, false, true),  // MBC3+TIMER+BATTERY
+        (0x10, true, true),   // MBC3+TIMER+RAM+BATTERY
+        (0x11, false, false), // MBC3
+        (0x12, true, false),  // MBC3+RAM
+        (0x13, true, true),   // MBC3+RAM+BATTERY
+        (0x19, false, false), // MBC5
+        (0x1A, true, false),  // MBC5+RAM
+        (0x1B, true, true),   // MBC5+RAM+BATTERY
+        (0x1C, false, false), // MBC5+RUMBLE
+        (0x1D, true, false),  // MBC5+RUMBLE+RAM
+        (0x1E, true, true),   // MBC5+RUMBLE+RAM+BATTERY
+        (0x20, false, false), // MBC6
+        (0x22, true, true),   // MBC7+SENSOR+RUMBLE+RAM+BATTERY
+        (0xFC, true, true),   // POCKET CAMERA
+        (0xFD, false, false), // BANDAI TAMA5
+        (0xFE, true, false),  // HuC3
+        (0xFF, true, true),   // HuC1+RAM+BATTERY
+    ];
+    for &(kind, ram, battery) in &types {
+        let mut rom = rom_with_valid_header();
+        rom[0x147] = kind;
+        let header = match CartridgeHeader::from_bytes(&rom) {
+            Ok(x) | Err((Some(x), _)) => x,
+            Err((None, err)) => panic!("{}", err),
+        };
+        assert_eq!(header.has_ram(), ram, "{:02x}", kind);
+        assert_eq!(header.has_battery(), battery, "{:02x}", kind);
+    }
+
+    let cartridge = Cartridge::new_test(0x13, 2, 0x03);
+    assert!(cartridge.has_ram() && cartridge.has_battery());
+    let cartridge = Cartridge::new_test(0x1A, 2, 0x03);
+    assert!(cartridge.has_ram() && !cartridge.has_battery());
+}
+
 mod mattcurrie {
     use super::*;
 
@@ -4,9 +4,10 @@ use std::{
 };
 
 use gameroy_lib::gameroy::{
-    consts::CLOCK_SPEED,
+    consts::{CLOCK_SPEED, FRAME_CYCLES},
     gameboy::{cartridge::Cartridge, GameBoy},
     interpreter::Interpreter,
+    profiling::ProfilingData,
 };
 
 // Return the mean and standart error of the samples
@@ -31,7 +32,7 @@ fn print_val(val: f64, err: f64) -> String {
     format!("{:.p$} +/- {:.p$}", val, err, p = p)
 }
 
-pub fn benchmark(path: &str, timeout: u64, len: usize) {
+pub fn benchmark(path: &str, timeout: u64, len: usize, profiling: bool) {
     let rom_path = PathBuf::from(path);
     let rom = std::fs::read(rom_path);
 
@@ -43,8 +44,9 @@ pub fn benchmark(path: &str, timeout: u64, len: usize) {
     let cartridge = Cartridge::new(rom).unwrap();
     let mut game_boy = GameBoy::new(None, cartridge);
 
-    // remove serial transfer console output
-    game_boy.serial_transfer_callback = None;
+    if profiling {
+        game_boy.profiling = Some(Box::new(ProfilingData::new()));
+    }
 
     let mut times = Vec::with_capacity(len);
     for _ in 0..len {
@@ -68,4 +70,95 @@ pub fn benchmark(path: &str, timeout: u64, len: usize) {
         print_val(times, times_err),
     );
     log::info!("faster than real time: {} +/- {}", times, times_err);
+
+    if let Some(profiling) = &game_boy.profiling {
+        let mut report = String::new();
+        profiling
+            .report(10, &game_boy.trace.borrow(), &mut report)
+            .unwrap();
+        println!("{}", report);
+    }
+}
+
+/// The speed of the emulation, measured by `run_for_seconds`.
+#[derive(Debug)]
+struct Speed {
+    /// Emulated clock cycles per real second, in MHz.
+    mhz: f64,
+    /// Emulated frames per real second.
+    fps: f64,
+}
+
+/// Emulate the rom as fast as possible, for `seconds` of real time, and return the speed of the
+/// emulation. There is no audio output and no V-Blank callback, so nothing throttles the emulation.
+fn run_for_seconds(rom: Vec<u8>, seconds: f64) -> Result<Speed, String> {
+    let cartridge = Cartridge::new(rom)?;
+    let mut game_boy = GameBoy::new(None, cartridge);
+    game_boy.sound.borrow_mut().sample_frequency = 0;
+    game_boy.v_blank = None;
+
+    let duration = Duration::from_secs_f64(seconds);
+    let mut inter = Interpreter(&mut game_boy);
+    let start_clock = inter.0.clock_count;
+    let start = Instant::now();
+    // check the time once per frame, to not measure the clock itself
+    let mut target = start_clock;
+    while start.elapsed() < duration {
+        target += FRAME_CYCLES;
+        while inter.0.clock_count < target {
+            inter.interpret_op();
+        }
+    }
+    let elapsed = start.elapsed().as_secs_f64();
+
+    let clocks = (game_boy.clock_count - start_clock) as f64;
+    Ok(Speed {
+        mhz: clocks / elapsed / 1e6,
+        fps: clocks / FRAME_CYCLES as f64 / elapsed,
+    })
+}
+
+/// Emulate the rom at `path` for `seconds` of real time, and print the emulated clock cycles and
+/// frames per second.
+pub fn benchmark_seconds(path: &str, seconds: f64) {
+    let rom = match std::fs::read(path) {
+        Ok(x) => x,
+        Err(e) => return eprintln!("failed to load '{}': {}", path, e),
+    };
+
+    let speed = match run_for_seconds(rom, seconds) {
+        Ok(x) => x,
+        Err(e) => return eprintln!("failed to load rom: {}", e),
+    };
+
+    let real_mhz = CLOCK_SPEED as f64 / 1e6;
+    println!("emulated clock: {:.2} MHz", speed.mhz);
+    println!("emulated frames: {:.1} frames per second", speed.fps);
+    println!(
+        "                {:.2} times faster than real time.",
+        speed.mhz / real_mhz
+    );
+    log::info!("emulated speed: {:.2} MHz, {:.1} fps", speed.mhz, speed.fps);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn bench_speed() {
+        let mut rom = vec![0; 0x8000];
+        // a busy loop that keeps the LCD on
+        let program = [
+            0x3C, // INC A
+            0x04, // INC B
+            0x18, 0xFC, // JR -4
+        ];
+        rom[0x100..0x100 + program.len()].copy_from_slice(&program);
+
+        let speed = run_for_seconds(rom, 0.2).unwrap();
+        assert!(speed.mhz > 1.0, "{:?}", speed);
+        let fps = speed.mhz * 1e6 / FRAME_CYCLES as f64;
+        assert!((speed.fps - fps).abs() < 1e-6 * fps, "{:?}", speed);
+    }
 }
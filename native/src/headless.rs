@@ -0,0 +1,134 @@
+use gameroy_lib::gameroy::{
+    consts::FRAME_CYCLES,
+    gameboy::{cartridge::Cartridge, GameBoy},
+    interpreter::Interpreter,
+};
+
+/// The exit status when the serial output contains "Passed".
+pub const PASSED: i32 = 0;
+/// The exit status when the serial output contains "Failed".
+pub const FAILED: i32 = 1;
+/// The exit status when the serial output contains neither, or the rom could not be run.
+pub const INCONCLUSIVE: i32 = 2;
+
+/// Run the rom for the given number of frames, without a window, and return the bytes sent
+/// through the serial port.
+fn run_rom(rom: Vec<u8>, frames: u64) -> Result<Vec<u8>, String> {
+    let cartridge = Cartridge::new(rom)?;
+    let mut game_boy = GameBoy::new(None, cartridge);
+    game_boy.serial_output = Some(Vec::new());
+
+    let mut inter = Interpreter(&mut game_boy);
+    let target_clock = inter.0.clock_count + frames * FRAME_CYCLES;
+    while inter.0.clock_count < target_clock {
+        inter.interpret_op();
+    }
+
+    Ok(game_boy.take_serial_output())
+}
+
+/// The exit status for the given serial output, following the convention of the test roms, which
+/// print "Passed" or "Failed" at the end.
+fn exit_status(serial: &[u8]) -> i32 {
+    let contains = |text: &[u8]| serial.windows(text.len()).any(|x| x == text);
+    if contains(b"Failed") {
+        FAILED
+    } else if contains(b"Passed") {
+        PASSED
+    } else {
+        INCONCLUSIVE
+    }
+}
+
+/// Run the rom at `rom_path` headless for `frames` frames, print its serial output, and write it
+/// to `serial_out`, if given. Return the exit status.
+pub fn run(rom_path: &str, frames: u64, serial_out: Option<&str>) -> i32 {
+    let rom = match std::fs::read(rom_path) {
+        Ok(x) => x,
+        Err(e) => {
+            eprintln!("failed to load '{}': {}", rom_path, e);
+            return INCONCLUSIVE;
+        }
+    };
+
+    let serial = match run_rom(rom, frames) {
+        Ok(x) => x,
+        Err(e) => {
+            eprintln!("failed to load rom: {}", e);
+            return INCONCLUSIVE;
+        }
+    };
+
+    println!("{}", String::from_utf8_lossy(&serial));
+
+    if let Some(serial_out) = serial_out {
+        if let Err(e) = std::fs::write(serial_out, &serial) {
+            eprintln!("failed to write '{}': {}", serial_out, e);
+            return INCONCLUSIVE;
+        }
+    }
+
+    exit_status(&serial)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// A rom that sends `message` through the serial port, and then loops forever.
+    fn serial_rom(message: &[u8]) -> Vec<u8> {
+        let mut rom = vec![0; 0x8000];
+        let program = [
+            0x21, 0x50, 0x01, // LD HL,$0150
+            0x2A, // LD A,(HL+)
+            0xA7, // AND A
+            0x28, 0x0E, // JR Z,+14
+            0xE0, 0x01, // LDH ($01),A
+            0x3E, 0x81, // LD A,$81
+            0xE0, 0x02, // LDH ($02),A
+            0xF0, 0x02, // LDH A,($02)
+            0xCB, 0x7F, // BIT 7,A
+            0x20, 0xFA, // JR NZ,-6
+            0x18, 0xEE, // JR -18
+            0x18, 0xFE, // JR -2
+        ];
+        rom[0x100..0x100 + program.len()].copy_from_slice(&program);
+        rom[0x150..0x150 + message.len()].copy_from_slice(message);
+        rom
+    }
+
+    #[test]
+    fn serial_output() {
+        let dir = std::env::temp_dir();
+        let rom_path = dir.join("gameroy_headless_test.gb");
+        let serial_path = dir.join("gameroy_headless_test.txt");
+
+        std::fs::write(&rom_path, serial_rom(b"Test\nPassed\n")).unwrap();
+        let status = run(
+            rom_path.to_str().unwrap(),
+            10,
+            Some(serial_path.to_str().unwrap()),
+        );
+        assert_eq!(std::fs::read(&serial_path).unwrap(), b"Test\nPassed\n");
+        assert_eq!(status, PASSED);
+
+        std::fs::write(&rom_path, serial_rom(b"Failed 3 tests")).unwrap();
+        let status = run(rom_path.to_str().unwrap(), 10, None);
+        assert_eq!(status, FAILED);
+
+        // not enough frames to send the whole message
+        std::fs::write(&rom_path, serial_rom(b"Passed")).unwrap();
+        let status = run(
+            rom_path.to_str().unwrap(),
+            0,
+            Some(serial_path.to_str().unwrap()),
+        );
+        assert_eq!(std::fs::read(&serial_path).unwrap(), b"");
+        assert_eq!(status, INCONCLUSIVE);
+
+        assert_eq!(run("this rom don't exist.gb", 10, None), INCONCLUSIVE);
+
+        let _ = std::fs::remove_file(rom_path);
+        let _ = std::fs::remove_file(serial_path);
+    }
+}
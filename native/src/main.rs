@@ -13,6 +13,8 @@ use clap::{arg, Command};
 use gameroy_lib::{config, gameroy, rom_loading::load_gameboy, RomFile, VERSION};
 
 mod bench;
+mod headless;
+mod screenshot;
 
 pub fn main() {
     let _logger = flexi_logger::Logger::try_with_env_or_str("gameroy=info")
@@ -43,8 +45,18 @@ pub fn main() {
                 .required(false)
                 .requires("ROM_PATH"),
         )
+        .arg(
+            arg!(--dumpasm <PATH> "write the disassembly of the rom, traced from its entry points, to the given file, as RGBDS assembly")
+                .required(false)
+                .requires("ROM_PATH"),
+        )
         .arg(arg!(--movie <PATH> "play the given .vbm file").required(false))
         .arg(arg!(--boot_rom <PATH> "dump of the bootrom to be used").required(false))
+        .arg(
+            arg!(--trace <PATH> "write a line for each executed instruction to the given file")
+                .required(false)
+                .requires("ROM_PATH"),
+        )
         .arg(arg!(--rom_folder <PATH> "specify the path of the folder for listing .gb roms").required(false))
         .arg(arg!(<ROM_PATH> "path to the game rom to be emulated").required(false))
         .subcommand(Command::new("bench")
@@ -59,12 +71,49 @@ pub fn main() {
                  .default_value("10")
                  .validator(|x| x.parse::<u64>())
             )
+            .arg(arg!(-p --profiling "enable the collection of execution statistics").required(false))
+            .arg(arg!(-s --seconds <NUMBER> "run as fast as possible for the given number of seconds, and print the emulated MHz and frames per second")
+                 .required(false)
+                 .validator(|x| x.parse::<f64>())
+            )
+            .arg(arg!(<ROM_PATH> "path to the game rom to be emulated").required(true)))
+        .subcommand(Command::new("run")
+            .about("Run a given rom without a window, for some ammount of frames, and capture its serial output. Exit with 0 if the output contains \"Passed\", 1 if it contains \"Failed\", and 2 otherwise.")
+            .arg(arg!(-f --frames <NUMBER> "the number of frames to run")
+                 .required(false)
+                 .default_value("600")
+                 .validator(|x| x.parse::<u64>())
+            )
+            .arg(arg!(--"serial-out" <PATH> "write the bytes sent through the serial port to the given file").required(false))
+            .arg(arg!(<ROM_PATH> "path to the game rom to be emulated").required(true)))
+        .subcommand(Command::new("screenshot")
+            .about("Run a given rom without a window, for some ammount of frames, and save the screen as a PNG.")
+            .arg(arg!(-f --frames <NUMBER> "the number of frames to run")
+                 .required(false)
+                 .default_value("600")
+                 .validator(|x| x.parse::<u64>())
+            )
+            .arg(arg!(-o --out <PATH> "the path of the PNG file to write").required(true))
+            .arg(arg!(-s --scale <NUMBER> "the upscale factor of the image")
+                 .required(false)
+                 .default_value("1")
+                 .validator(|x| x.parse::<u32>())
+            )
+            .arg(arg!(-p --palette <NAME> "the DMG palette used for the image")
+                 .required(false)
+                 .default_value("gray")
+                 .possible_values(["gray", "green", "pocket"])
+            )
             .arg(arg!(<ROM_PATH> "path to the game rom to be emulated").required(true)))
         .get_matches();
 
     match matches.subcommand() {
         Some(("bench", matches)) => {
             let rom_path = matches.value_of("ROM_PATH").unwrap();
+            if let Some(seconds) = matches.value_of("seconds") {
+                let seconds: f64 = seconds.parse().unwrap();
+                return bench::benchmark_seconds(rom_path, seconds);
+            }
             let frames: u64 = matches
                 .value_of("frames")
                 .and_then(|x| x.parse().ok())
@@ -73,16 +122,52 @@ pub fn main() {
                 .value_of("times")
                 .and_then(|x| x.parse().ok())
                 .unwrap();
-            return bench::benchmark(rom_path, frames * gameroy::consts::FRAME_CYCLES, len);
+            let profiling = matches.is_present("profiling");
+            return bench::benchmark(
+                rom_path,
+                frames * gameroy::consts::FRAME_CYCLES,
+                len,
+                profiling,
+            );
+        }
+        Some(("run", matches)) => {
+            let rom_path = matches.value_of("ROM_PATH").unwrap();
+            let frames: u64 = matches
+                .value_of("frames")
+                .and_then(|x| x.parse().ok())
+                .unwrap();
+            let serial_out = matches.value_of("serial-out");
+            let status = headless::run(rom_path, frames, serial_out);
+            std::process::exit(status);
+        }
+        Some(("screenshot", matches)) => {
+            let rom_path = matches.value_of("ROM_PATH").unwrap();
+            let frames: u64 = matches
+                .value_of("frames")
+                .and_then(|x| x.parse().ok())
+                .unwrap();
+            let out = matches.value_of("out").unwrap();
+            let scale: u32 = matches
+                .value_of("scale")
+                .and_then(|x| x.parse().ok())
+                .unwrap();
+            let palette = matches.value_of("palette").unwrap();
+            if let Err(e) = screenshot::screenshot(rom_path, frames, out, scale, palette) {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+            return;
         }
         _ => {}
     }
 
     let debug = matches.is_present("debug");
     let diss = matches.is_present("disassembly");
+    let dumpasm_path = matches.value_of("dumpasm");
     let boot_rom_path = matches.value_of("boot_rom");
     let rom_folder = matches.value_of("rom_folder");
     let rom_path = matches.value_of("ROM_PATH");
+    let trace_path = matches.value_of("trace");
     let movie = matches.value_of("movie").map(|path| {
         let mut file = std::fs::File::open(path).unwrap();
         let vbm = gameroy::parser::vbm(&mut file).unwrap();
@@ -94,9 +179,9 @@ pub fn main() {
             .map_err(|e| log::error!("error loading config file 'gameroy.toml': {}", e))
             .unwrap_or_default();
         config.start_in_debug |= debug;
-        config.rom_folder = config
-            .rom_folder
-            .or_else(|| rom_folder.map(|x| x.to_string()));
+        if config.rom_folders.is_empty() {
+            config.rom_folders.extend(rom_folder.map(|x| x.to_string()));
+        }
         config.boot_rom = boot_rom_path.map(|x| x.to_string());
         config
     });
@@ -128,6 +213,37 @@ pub fn main() {
         }
     }
 
+    // write the disassembly as assembly and return early
+    if let Some(dumpasm_path) = dumpasm_path {
+        let rom_path = rom_path.expect("the --dumpasm flag already requires <ROM_PATH>");
+        let rom = match std::fs::read(&rom_path) {
+            Ok(x) => x,
+            Err(e) => return eprintln!("failed to load '{}': {}", rom_path, e),
+        };
+
+        let gb = match load_gameboy(rom, None) {
+            Ok(x) => x,
+            Err(e) => return eprintln!("failed to load rom: {}", e),
+        };
+        gameroy_lib::rom_loading::load_symbols(&RomFile::from_path(PathBuf::from(rom_path)), &gb);
+
+        let file = match std::fs::File::create(dumpasm_path) {
+            Ok(x) => x,
+            Err(e) => return eprintln!("failed to create '{}': {}", dumpasm_path, e),
+        };
+        let mut writer = std::io::BufWriter::new(file);
+        let result = gb
+            .trace
+            .borrow()
+            .export_asm(&gb, &mut writer)
+            .and_then(|_| std::io::Write::flush(&mut writer));
+        if let Err(e) = result {
+            eprintln!("failed to write '{}': {}", dumpasm_path, e);
+        }
+
+        return;
+    }
+
     // load rom if necesary
     let gb = if let Some(rom_path) = rom_path {
         let rom = std::fs::read(&rom_path);
@@ -140,10 +256,19 @@ pub fn main() {
         let file = RomFile::from_path(PathBuf::from(rom_path));
 
         let gb = load_gameboy(rom, None);
-        match gb {
-            Ok(x) => Some((file, x)),
+        let mut gb = match gb {
+            Ok(x) => x,
             Err(e) => return eprintln!("failed to load rom: {}", e),
+        };
+
+        if let Some(trace_path) = trace_path {
+            match gameroy::trace_logger::TraceLogger::create(trace_path) {
+                Ok(x) => gb.trace_logger = Some(Box::new(x)),
+                Err(e) => return eprintln!("failed to create '{}': {}", trace_path, e),
+            }
         }
+
+        Some((file, gb))
     } else {
         None
     };
@@ -0,0 +1,152 @@
+use std::sync::{Arc, Mutex};
+
+use gameroy_lib::gameroy::{
+    consts::FRAME_CYCLES,
+    gameboy::{cartridge::Cartridge, ppu::DMG_COLORS, GameBoy},
+    interpreter::Interpreter,
+};
+
+const SCREEN_WIDTH: usize = 160;
+const SCREEN_HEIGHT: usize = 144;
+
+/// The palettes that can be selected for the screenshot, by name.
+pub const PALETTES: [(&str, [[u8; 3]; 4]); 3] = [
+    ("gray", DMG_COLORS),
+    (
+        "green",
+        [[155, 188, 15], [139, 172, 15], [48, 98, 48], [15, 56, 15]],
+    ),
+    (
+        "pocket",
+        [[196, 207, 161], [139, 149, 109], [77, 83, 60], [31, 31, 31]],
+    ),
+];
+
+/// Run the rom until the end of the given frame, and return the shade of each pixel of the
+/// screen. If the LCD is off, the rom runs for the same amount of cycles instead, and the screen
+/// is blank.
+fn run_until_frame(rom: Vec<u8>, frames: u64) -> Result<Vec<u8>, String> {
+    let cartridge = Cartridge::new(rom)?;
+    let mut game_boy = GameBoy::new(None, cartridge);
+
+    // (number of frames, screen at the end of the last one)
+    let frame = Arc::new(Mutex::new((0, vec![0; SCREEN_WIDTH * SCREEN_HEIGHT])));
+    game_boy.v_blank = Some(Box::new({
+        let frame = frame.clone();
+        move |gb| {
+            let mut frame = frame.lock().unwrap();
+            frame.0 += 1;
+            frame.1.copy_from_slice(&gb.ppu.borrow().screen);
+        }
+    }));
+
+    let timeout = game_boy.clock_count + (frames + 1) * FRAME_CYCLES;
+    let mut inter = Interpreter(&mut game_boy);
+    while frame.lock().unwrap().0 < frames && inter.0.clock_count < timeout {
+        inter.interpret_op();
+    }
+
+    let frame = frame.lock().unwrap();
+    if frame.0 < frames {
+        return Ok(vec![0; SCREEN_WIDTH * SCREEN_HEIGHT]);
+    }
+    Ok(frame.1.clone())
+}
+
+/// Convert the shades of the screen to RGB, with each pixel scaled to a `scale` by `scale`
+/// square.
+fn to_rgb(screen: &[u8], palette: &[[u8; 3]; 4], scale: usize) -> Vec<u8> {
+    let width = SCREEN_WIDTH * scale;
+    let mut data = Vec::with_capacity(width * SCREEN_HEIGHT * scale * 3);
+    for y in 0..SCREEN_HEIGHT * scale {
+        for x in 0..width {
+            let shade = screen[(y / scale) * SCREEN_WIDTH + x / scale];
+            data.extend_from_slice(&palette[shade as usize]);
+        }
+    }
+    data
+}
+
+/// Run the rom at `rom_path` headless until the end of the given frame, and write the screen as
+/// a PNG to `out`. The emulation always starts from the same state, so the output is
+/// deterministic.
+pub fn screenshot(
+    rom_path: &str,
+    frames: u64,
+    out: &str,
+    scale: u32,
+    palette: &str,
+) -> Result<(), String> {
+    let palette = PALETTES
+        .iter()
+        .find(|(name, _)| *name == palette)
+        .map(|(_, colors)| colors)
+        .ok_or_else(|| format!("unknown palette '{}'", palette))?;
+    if scale == 0 {
+        return Err("the scale must be at least 1".to_string());
+    }
+
+    let rom =
+        std::fs::read(rom_path).map_err(|e| format!("failed to load '{}': {}", rom_path, e))?;
+    let screen = run_until_frame(rom, frames)?;
+    let data = to_rgb(&screen, palette, scale as usize);
+
+    image::save_buffer_with_format(
+        out,
+        &data,
+        SCREEN_WIDTH as u32 * scale,
+        SCREEN_HEIGHT as u32 * scale,
+        image::ColorType::Rgb8,
+        image::ImageFormat::Png,
+    )
+    .map_err(|e| format!("failed to write '{}': {}", out, e))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn deterministic_output() {
+        let mut rom = vec![0; 0x8000];
+        // fill the VRAM with 0xF0, in a loop, to get past the PPU blocking it
+        let program = [
+            0x21, 0x00, 0x80, // LD HL,$8000
+            0x3E, 0xF0, // LD A,$F0
+            0x22, // LD (HL+),A
+            0xCB, 0x6C, // BIT 5,H
+            0x28, 0xFB, // JR Z,-5
+            0x18, 0xF4, // JR -12
+        ];
+        rom[0x100..0x100 + program.len()].copy_from_slice(&program);
+
+        let dir = std::env::temp_dir();
+        let rom_path = dir.join("gameroy_screenshot_test.gb");
+        let out = [
+            dir.join("gameroy_screenshot_test_a.png"),
+            dir.join("gameroy_screenshot_test_b.png"),
+        ];
+        std::fs::write(&rom_path, &rom).unwrap();
+        let rom_path = rom_path.to_str().unwrap();
+
+        for out in &out {
+            screenshot(rom_path, 10, out.to_str().unwrap(), 2, "green").unwrap();
+        }
+        let a = std::fs::read(&out[0]).unwrap();
+        let b = std::fs::read(&out[1]).unwrap();
+        assert_eq!(a, b);
+
+        let image = image::open(&out[0]).unwrap().to_rgb8();
+        assert_eq!(image.dimensions(), (320, 288));
+        // the tiles have vertical stripes
+        assert_ne!(image.get_pixel(0, 0), image.get_pixel(319, 0));
+
+        assert!(screenshot(rom_path, 10, out[0].to_str().unwrap(), 1, "blue").is_err());
+        assert!(screenshot(rom_path, 10, out[0].to_str().unwrap(), 0, "gray").is_err());
+
+        let _ = std::fs::remove_file(rom_path);
+        for out in &out {
+            let _ = std::fs::remove_file(out);
+        }
+    }
+}
@@ -0,0 +1,496 @@
+use std::collections::VecDeque;
+
+/// The maximum relative change of the playback rate, used to steer the buffer toward its target
+/// length.
+const MAX_RATE_NUDGE: f64 = 0.005;
+
+/// The number of interleaved channels in the buffer.
+const CHANNELS: usize = 2;
+
+/// The number of frames output by each step of the time stretching, half the length of its grains.
+/// About 10 ms at 48 kHz.
+const STRETCH_HOP: usize = 512;
+
+/// How far, in frames, a grain of the time stretching can be moved from its nominal position, to
+/// better continue the previous one.
+const STRETCH_SEARCH: usize = 256;
+
+/// Changes the duration of the sound without changing its pitch, with WSOLA (waveform similarity
+/// overlap-add).
+///
+/// The input is cut in grains of `2 * STRETCH_HOP` frames, that start `speed` times further apart
+/// in the input than in the output, and are cross faded over each other. Each grain is moved, by
+/// up to `STRETCH_SEARCH` frames, to where it best matches the continuation of the previous grain,
+/// so their waves are in phase where they overlap.
+#[derive(Debug)]
+struct TimeStretch {
+    /// The number of input frames for each output frame.
+    speed: f64,
+    /// The interleaved input samples that may still be part of a grain.
+    input: Vec<i16>,
+    /// The end of the part of the last grain that was output, where the next grain is cross faded
+    /// from, in frames of `input`.
+    tail: usize,
+    /// The nominal start of the next grain, in frames of `input`.
+    next_start: f64,
+}
+
+impl TimeStretch {
+    fn new() -> Self {
+        Self {
+            speed: 1.0,
+            input: Vec::new(),
+            tail: 0,
+            next_start: 0.0,
+        }
+    }
+
+    /// The sum of the channels of the frame at the given index of `input`.
+    fn mono(&self, frame: usize) -> f32 {
+        let i = frame * CHANNELS;
+        self.input[i..i + CHANNELS].iter().map(|&x| x as f32).sum()
+    }
+
+    /// How similar the `STRETCH_HOP` frames at `start` are to the ones at `other`, as their
+    /// correlation normalized by the energy of the first ones. Only every fourth frame is
+    /// compared, to keep it cheap.
+    fn similarity(&self, start: usize, other: usize) -> f32 {
+        let (mut dot, mut energy) = (0.0, 0.0);
+        for i in (0..STRETCH_HOP).step_by(4) {
+            let x = self.mono(start + i);
+            dot += x * self.mono(other + i);
+            energy += x * x;
+        }
+        dot / (energy.sqrt() + 1.0)
+    }
+
+    /// Time stretch the interleaved `input`, appending the result to `output`. The last frames of
+    /// the input are kept, until there is enough input after them to form the next grain.
+    fn process(&mut self, input: &[i16], output: &mut Vec<i16>) {
+        self.input.extend_from_slice(input);
+        let frames = self.input.len() / CHANNELS;
+        loop {
+            let tail = self.tail;
+            let nominal = self.next_start as usize;
+            let (lo, hi) = (
+                nominal.saturating_sub(STRETCH_SEARCH),
+                nominal + STRETCH_SEARCH,
+            );
+            if hi + STRETCH_HOP > frames || tail + STRETCH_HOP > frames {
+                break;
+            }
+
+            let mut start = nominal;
+            let mut best = f32::MIN;
+            for candidate in (lo..=hi).step_by(2) {
+                let similarity = self.similarity(candidate, tail);
+                if similarity > best {
+                    best = similarity;
+                    start = candidate;
+                }
+            }
+
+            // cross fade the end of the last grain into the start of the new one
+            for i in 0..STRETCH_HOP {
+                let t = 0.5 - 0.5 * (std::f32::consts::PI * i as f32 / STRETCH_HOP as f32).cos();
+                for c in 0..CHANNELS {
+                    let a = self.input[(tail + i) * CHANNELS + c] as f32;
+                    let b = self.input[(start + i) * CHANNELS + c] as f32;
+                    output.push((a * (1.0 - t) + b * t).round() as i16);
+                }
+            }
+            self.tail = start + STRETCH_HOP;
+            self.next_start += self.speed * STRETCH_HOP as f64;
+        }
+
+        // drop the frames that can't be part of a grain anymore
+        let used = self
+            .tail
+            .min((self.next_start as usize).saturating_sub(STRETCH_SEARCH))
+            .min(frames);
+        self.input.drain(..used * CHANNELS);
+        self.tail -= used;
+        self.next_start -= used as f64;
+    }
+
+    /// Append the kept input that was not output yet to `output`, and start over.
+    fn flush(&mut self, output: &mut Vec<i16>) {
+        let tail = self.tail * CHANNELS;
+        if tail < self.input.len() {
+            output.extend_from_slice(&self.input[tail..]);
+        }
+        *self = Self {
+            speed: self.speed,
+            ..Self::new()
+        };
+    }
+}
+
+/// A buffer of interleaved stereo samples, between the emulated sound output and the audio
+/// device.
+///
+/// The input is slightly resampled, at most by `MAX_RATE_NUDGE`, to keep the number of buffered
+/// samples around `target_len`, because the emulation and the audio device never run at exactly
+/// the same rate. An underrun is filled with silence, and on overrun the oldest samples are
+/// dropped, to keep the latency bounded.
+///
+/// When the emulation doesn't run at the real speed, the input is first time stretched by
+/// `TimeStretch`, so it plays in real time with its original pitch. See `set_speed`.
+#[derive(Debug)]
+pub struct AudioBuffer {
+    samples: VecDeque<i16>,
+    /// The number of samples the buffer tries to keep.
+    target_len: usize,
+    /// The number of samples above which the oldest ones are dropped.
+    max_len: usize,
+    /// The last input frame, used to interpolate the next one.
+    last_frame: [i16; CHANNELS],
+    /// The position of the next output frame, in input frames, relative to `last_frame`.
+    phase: f64,
+    /// The number of silence samples written by `pop` because the buffer was empty.
+    underrun_samples: u64,
+    /// The number of samples dropped because the buffer was full.
+    overrun_samples: u64,
+    /// Time stretches the input, when `set_speed` is not 1.0.
+    stretch: TimeStretch,
+    /// The output of `stretch`, reused between pushes.
+    stretched: Vec<i16>,
+}
+
+impl AudioBuffer {
+    /// Create a buffer that tries to keep `target_len` samples. `target_len` is rounded up to a
+    /// whole number of frames.
+    pub fn new(target_len: usize) -> Self {
+        let target_len = ((target_len + CHANNELS - 1) / CHANNELS).max(1) * CHANNELS;
+        Self {
+            samples: VecDeque::with_capacity(target_len * 4),
+            target_len,
+            max_len: target_len * 4,
+            last_frame: [0; CHANNELS],
+            phase: 0.0,
+            underrun_samples: 0,
+            overrun_samples: 0,
+            stretch: TimeStretch::new(),
+            stretched: Vec::new(),
+        }
+    }
+
+    /// The number of samples for the given latency, in milliseconds, and sample rate.
+    pub fn latency_len(latency_ms: u32, sample_rate: u32) -> usize {
+        latency_ms as usize * sample_rate as usize / 1000 * CHANNELS
+    }
+
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    /// The number of samples the buffer tries to keep.
+    pub fn target_len(&self) -> usize {
+        self.target_len
+    }
+
+    pub fn underrun_samples(&self) -> u64 {
+        self.underrun_samples
+    }
+
+    pub fn overrun_samples(&self) -> u64 {
+        self.overrun_samples
+    }
+
+    /// Discard all buffered samples.
+    pub fn clear(&mut self) {
+        self.samples.clear();
+        self.phase = 0.0;
+        self.stretch.flush(&mut Vec::new());
+    }
+
+    /// Set the number of input samples pushed for each sample played, the speed at which the
+    /// emulation runs. The input is time stretched by it, so its pitch doesn't change.
+    pub fn set_speed(&mut self, speed: f64) {
+        if speed == self.stretch.speed {
+            return;
+        }
+        if speed == 1.0 {
+            let mut rest = std::mem::take(&mut self.stretched);
+            rest.clear();
+            self.stretch.flush(&mut rest);
+            self.resample(&rest);
+            self.stretched = rest;
+        }
+        self.stretch.speed = speed;
+    }
+
+    /// Push interleaved samples into the buffer. If the buffer is empty, it is first filled with
+    /// silence up to the target length.
+    pub fn push(&mut self, input: &[i16]) {
+        if self.stretch.speed == 1.0 {
+            self.resample(input);
+        } else {
+            let mut stretched = std::mem::take(&mut self.stretched);
+            stretched.clear();
+            self.stretch.process(input, &mut stretched);
+            self.resample(&stretched);
+            self.stretched = stretched;
+        }
+    }
+
+    /// Resample the interleaved samples by the rate nudge, and push them into the buffer.
+    fn resample(&mut self, input: &[i16]) {
+        if self.samples.is_empty() {
+            self.samples.extend((0..self.target_len).map(|_| 0));
+        }
+
+        // A buffer below the target is played slower (each input frame produces more output
+        // frames), and a buffer above it faster.
+        let error = (self.target_len as f64 - self.samples.len() as f64) / self.target_len as f64;
+        let step = 1.0 - MAX_RATE_NUDGE * error.clamp(-1.0, 1.0);
+
+        let frames = input.len() / CHANNELS;
+        let frame = |i: usize| -> [i16; CHANNELS] {
+            if i == 0 {
+                self.last_frame
+            } else {
+                let i = (i - 1) * CHANNELS;
+                [input[i], input[i + 1]]
+            }
+        };
+        while self.phase < frames as f64 {
+            let i = self.phase as usize;
+            let t = self.phase - i as f64;
+            let (a, b) = (frame(i), frame(i + 1));
+            for c in 0..CHANNELS {
+                let x = a[c] as f64 * (1.0 - t) + b[c] as f64 * t;
+                self.samples.push_back(x.round() as i16);
+            }
+            self.phase += step;
+        }
+        if frames > 0 {
+            self.last_frame = frame(frames);
+            self.phase -= frames as f64;
+        }
+
+        if self.samples.len() > self.max_len {
+            let excess = self.samples.len() - self.max_len;
+            self.samples.drain(0..excess);
+            self.overrun_samples += excess as u64;
+        }
+    }
+
+    /// Fill `output` with the buffered samples. If there are not enough, the rest is filled with
+    /// silence.
+    pub fn pop(&mut self, output: &mut [i16]) {
+        let len = self.samples.len().min(output.len());
+        for (a, b) in output[..len].iter_mut().zip(self.samples.drain(..len)) {
+            *a = b;
+        }
+        if len < output.len() {
+            output[len..].fill(0);
+            self.underrun_samples += (output.len() - len) as u64;
+        }
+    }
+}
+
+/// The fraction of the full scale above which the samples are soft clipped.
+const CLIP_KNEE: f32 = 0.75;
+
+/// Compress a sample, normalized so the full scale is 1.0, so that it never goes above the full
+/// scale. Below `CLIP_KNEE` it is unchanged, and above it smoothly approaches the full scale.
+pub fn soft_clip(x: f32) -> f32 {
+    let a = x.abs();
+    if a <= CLIP_KNEE {
+        return x;
+    }
+    let range = 1.0 - CLIP_KNEE;
+    (CLIP_KNEE + range * ((a - CLIP_KNEE) / range).tanh()).copysign(x)
+}
+
+/// Multiply the samples by `gain`, soft clipping the ones that would get near the full scale.
+pub fn apply_gain(samples: &mut [i16], gain: f32) {
+    for x in samples {
+        let y = *x as f32 * gain / i16::MAX as f32;
+        *x = (soft_clip(y) * i16::MAX as f32).round() as i16;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{apply_gain, soft_clip, AudioBuffer, TimeStretch, STRETCH_HOP, STRETCH_SEARCH};
+
+    #[test]
+    fn fill_and_drain() {
+        let mut buffer = AudioBuffer::new(200);
+        assert_eq!(buffer.len(), 0);
+
+        // the empty buffer is primed with silence, and at the target length the rate is not
+        // changed.
+        buffer.push(&[1000; 200]);
+        assert_eq!(buffer.len(), 400);
+
+        let mut output = [-1; 150];
+        buffer.pop(&mut output);
+        assert_eq!(buffer.len(), 250);
+        assert_eq!(output, [0; 150]);
+        assert_eq!(buffer.underrun_samples(), 0);
+        assert_eq!(buffer.overrun_samples(), 0);
+    }
+
+    #[test]
+    fn underrun() {
+        let mut buffer = AudioBuffer::new(100);
+        buffer.push(&[1000; 100]);
+        assert_eq!(buffer.len(), 200);
+
+        let mut output = [-1; 300];
+        buffer.pop(&mut output);
+        assert_eq!(buffer.len(), 0);
+        assert_eq!(buffer.underrun_samples(), 100);
+        // the silence is after the buffered samples, not a repetition of them.
+        assert_eq!(output[199], 1000);
+        assert!(output[200..].iter().all(|&x| x == 0));
+
+        // after a underrun the buffer is primed again.
+        buffer.push(&[1000; 10]);
+        assert!(buffer.len() >= 100);
+    }
+
+    #[test]
+    fn overrun() {
+        let mut buffer = AudioBuffer::new(100);
+        for _ in 0..10 {
+            buffer.push(&[1000; 100]);
+        }
+        assert_eq!(buffer.len(), 400);
+
+        let mut output = [0; 400];
+        buffer.pop(&mut output);
+        // all samples pushed are either buffered or dropped, with a small error from the rate
+        // nudge.
+        let pushed = 100 + 10 * 100;
+        let accounted = 400 + buffer.overrun_samples() as i64;
+        assert!((pushed - accounted).abs() <= 10, "{}", accounted);
+        assert_eq!(buffer.underrun_samples(), 0);
+    }
+
+    #[test]
+    fn rate_nudge() {
+        // below the target, each frame produces more output frames
+        let mut buffer = AudioBuffer::new(10_000);
+        buffer.push(&[0; 2]);
+        buffer.pop(&mut [0; 10_000]);
+        buffer.push(&[0; 2000]);
+        assert!(buffer.len() > 2000, "{}", buffer.len());
+        assert!(buffer.len() <= 2000 + 2 * 10, "{}", buffer.len());
+
+        // above the target, less
+        let mut buffer = AudioBuffer::new(1000);
+        buffer.push(&[0; 2000]);
+        let len = buffer.len();
+        buffer.push(&[0; 2000]);
+        assert!(buffer.len() - len < 2000, "{}", buffer.len() - len);
+    }
+
+    /// A stereo sine wave with the given period in frames, and length in frames.
+    fn sine(period: f64, frames: usize) -> Vec<i16> {
+        (0..frames)
+            .flat_map(|i| {
+                let x = (i as f64 * std::f64::consts::TAU / period).sin();
+                let x = (x * 8000.0).round() as i16;
+                [x, x]
+            })
+            .collect()
+    }
+
+    /// The mean number of frames between the rising zero crossings of the left channel.
+    fn period(samples: &[i16]) -> f64 {
+        let crossings: Vec<usize> = (1..samples.len() / 2)
+            .filter(|&i| samples[i * 2 - 2] < 0 && samples[i * 2] >= 0)
+            .collect();
+        let (first, last) = (crossings[0], crossings[crossings.len() - 1]);
+        (last - first) as f64 / (crossings.len() - 1) as f64
+    }
+
+    #[test]
+    fn time_stretch() {
+        for &speed in &[0.5, 0.75, 1.5, 2.0] {
+            let mut stretch = TimeStretch::new();
+            stretch.speed = speed;
+            let input = sine(100.0, 48_000);
+            let mut output = Vec::new();
+            for chunk in input.chunks(800) {
+                stretch.process(chunk, &mut output);
+            }
+
+            // the duration changes by the speed, except for the frames kept for the next grain
+            let expected = 48_000.0 / speed;
+            let frames = (output.len() / 2) as f64;
+            let kept = (STRETCH_HOP + STRETCH_SEARCH) as f64 / speed;
+            let hop = STRETCH_HOP as f64;
+            assert!(
+                frames <= expected + hop && frames >= expected - kept,
+                "{}: {}",
+                speed,
+                frames
+            );
+
+            // but the pitch doesn't
+            let period = period(&output);
+            assert!((period - 100.0).abs() < 1.0, "{}: {}", speed, period);
+        }
+    }
+
+    #[test]
+    fn set_speed() {
+        let mut buffer = AudioBuffer::new(100_000);
+        buffer.push(&sine(100.0, 500));
+        let len = buffer.len();
+
+        // at half the speed, the input is stretched to twice its length
+        buffer.set_speed(0.5);
+        buffer.push(&sine(100.0, 10_000));
+        let stretched = buffer.len() - len;
+        assert!(stretched > 2 * 2 * 8000, "{}", stretched);
+
+        // going back to the real speed outputs the input kept for the next grain
+        let len = buffer.len();
+        buffer.set_speed(1.0);
+        assert!(buffer.len() > len);
+        let len = buffer.len();
+        buffer.push(&[0; 200]);
+        assert!(buffer.len() - len <= 202, "{}", buffer.len() - len);
+    }
+
+    #[test]
+    fn gain() {
+        let mut samples = [0, 1000, -1000, 8000, -8000];
+        apply_gain(&mut samples, 1.0);
+        assert_eq!(samples, [0, 1000, -1000, 8000, -8000]);
+        apply_gain(&mut samples, 0.5);
+        assert_eq!(samples, [0, 500, -500, 4000, -4000]);
+        apply_gain(&mut samples, 0.0);
+        assert_eq!(samples, [0; 5]);
+    }
+
+    #[test]
+    fn soft_clip_curve() {
+        // unchanged below the knee
+        assert_eq!(soft_clip(0.5), 0.5);
+        assert_eq!(soft_clip(-0.75), -0.75);
+
+        // monotonic, symmetric and never above the full scale
+        let mut last = 0.0;
+        for i in 1..=400 {
+            let x = i as f32 / 100.0;
+            let y = soft_clip(x);
+            assert!(y >= last && y <= 1.0, "{} -> {}", x, y);
+            assert_eq!(soft_clip(-x), -y);
+            last = y;
+        }
+
+        // a loud sample at high volume is compressed instead of wrapping around
+        let mut samples = [i16::MAX, i16::MIN, 30_000];
+        apply_gain(&mut samples, 1.5);
+        assert!(samples[0] > 30_000);
+        assert!(samples[1] < -30_000);
+        assert!(samples[2] > 24_575 && samples[2] < samples[0]);
+    }
+}
@@ -0,0 +1,227 @@
+use instant::{Duration, Instant};
+use serde::{Deserialize, Serialize};
+
+/// A button of the GameBoy joypad.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum JoypadButton {
+    Right,
+    Left,
+    Up,
+    Down,
+    A,
+    B,
+    Select,
+    Start,
+}
+
+impl JoypadButton {
+    /// The bit of this button in the joypad bitmask.
+    fn mask(self) -> u8 {
+        1 << self as u8
+    }
+}
+
+/// A emulator action that can be triggered by holding a combination of joypad buttons.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum ComboAction {
+    SaveState,
+    LoadState,
+    Reset,
+    /// Pause the game and open the game menu.
+    Menu,
+}
+
+/// A combination of joypad buttons, and the action triggered when all of them are held.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ButtonCombo {
+    pub buttons: Vec<JoypadButton>,
+    pub action: ComboAction,
+}
+
+impl ButtonCombo {
+    fn mask(&self) -> u8 {
+        self.buttons.iter().fold(0, |mask, b| mask | b.mask())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    /// No combo is held.
+    Idle,
+    /// All the buttons of the combo at `index` are being held, since `since`.
+    Holding { index: usize, since: Instant },
+    /// A combo was fired or interrupted. The buttons in `mask` are hidden from the game until all
+    /// of them are released.
+    Releasing { mask: u8 },
+}
+
+/// Detects held combinations of joypad buttons, and hides them from the game.
+///
+/// While all the buttons of a combo are held, they are released from the point of view of the
+/// game. If they are held for the hold time, the action of the combo is fired once. In both cases,
+/// the buttons stay hidden until all of them are released, so releasing them one by one don't
+/// leak a button press to the game, and a new combo is only detected after that.
+///
+/// Pressing only part of a combo is passed to the game as usual.
+#[derive(Debug)]
+pub struct ComboDetector {
+    /// The combos and their button masks. Combos without buttons are ignored.
+    combos: Vec<(u8, ComboAction)>,
+    hold_time: Duration,
+    state: State,
+}
+
+impl ComboDetector {
+    pub fn new(combos: &[ButtonCombo], hold_time: Duration) -> Self {
+        Self {
+            combos: combos
+                .iter()
+                .map(|c| (c.mask(), c.action))
+                .filter(|&(mask, _)| mask != 0)
+                .collect(),
+            hold_time,
+            state: State::Idle,
+        }
+    }
+
+    /// The combo with the most buttons whose buttons are all pressed, so a combo that contains
+    /// another one can still be triggered.
+    fn held_combo(&self, pressed: u8) -> Option<usize> {
+        self.combos
+            .iter()
+            .enumerate()
+            .filter(|(_, &(mask, _))| pressed & mask == mask)
+            .max_by_key(|(_, &(mask, _))| mask.count_ones())
+            .map(|(i, _)| i)
+    }
+
+    /// Update the detector with the current state of the `joypad` at `now`, where a bit 0 means
+    /// pressed. Return the joypad state that should be passed to the game, and the action fired
+    /// in this update, if any.
+    pub fn update(&mut self, joypad: u8, now: Instant) -> (u8, Option<ComboAction>) {
+        let pressed = !joypad;
+
+        if let State::Releasing { mask } = self.state {
+            if pressed & mask != 0 {
+                return (joypad | mask, None);
+            }
+            self.state = State::Idle;
+        }
+
+        let held = self.held_combo(pressed);
+        let (index, since) = match (self.state, held) {
+            (State::Holding { index, since }, Some(held)) if held == index => (index, since),
+            (State::Holding { index, .. }, None) => {
+                // the combo was released before the hold time
+                let mask = self.combos[index].0;
+                self.state = State::Releasing { mask };
+                return (joypad | mask, None);
+            }
+            (_, Some(index)) => (index, now),
+            (_, None) => return (joypad, None),
+        };
+
+        let (mask, action) = self.combos[index];
+        if now.duration_since(since) >= self.hold_time {
+            self.state = State::Releasing { mask };
+            (joypad | mask, Some(action))
+        } else {
+            self.state = State::Holding { index, since };
+            (joypad | mask, None)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const SELECT: u8 = 1 << 6;
+    const START: u8 = 1 << 7;
+    const A: u8 = 1 << 4;
+    const B: u8 = 1 << 5;
+
+    fn detector() -> ComboDetector {
+        use JoypadButton::*;
+        ComboDetector::new(
+            &[
+                ButtonCombo {
+                    buttons: vec![Select, Start],
+                    action: ComboAction::Menu,
+                },
+                ButtonCombo {
+                    buttons: vec![A, B, Select, Start],
+                    action: ComboAction::Reset,
+                },
+            ],
+            Duration::from_millis(500),
+        )
+    }
+
+    #[test]
+    fn fires_after_hold_time() {
+        let mut combo = detector();
+        let t0 = Instant::now();
+        let ms = |x| t0 + Duration::from_millis(x);
+
+        // a partial combo is passed to the game
+        assert_eq!(combo.update(!SELECT, ms(0)), (!SELECT, None));
+        // the full combo is hidden from the game while held
+        assert_eq!(combo.update(!(SELECT | START), ms(16)), (0xFF, None));
+        assert_eq!(combo.update(!(SELECT | START), ms(500)), (0xFF, None));
+        assert_eq!(
+            combo.update(!(SELECT | START), ms(516)),
+            (0xFF, Some(ComboAction::Menu))
+        );
+        // fired only once
+        assert_eq!(combo.update(!(SELECT | START), ms(1100)), (0xFF, None));
+        // the remaining button is still hidden, and can't start the combo again
+        assert_eq!(combo.update(!START, ms(1116)), (0xFF, None));
+        assert_eq!(combo.update(!(SELECT | START), ms(2000)), (0xFF, None));
+        // other buttons are passed through
+        assert_eq!(combo.update(!(START | A), ms(2016)), (!A, None));
+        assert_eq!(combo.update(0xFF, ms(2032)), (0xFF, None));
+        assert_eq!(combo.update(!START, ms(2048)), (!START, None));
+    }
+
+    #[test]
+    fn released_before_hold_time() {
+        let mut combo = detector();
+        let t0 = Instant::now();
+        let ms = |x| t0 + Duration::from_millis(x);
+
+        assert_eq!(combo.update(!(SELECT | START), ms(0)), (0xFF, None));
+        assert_eq!(combo.update(!SELECT, ms(100)), (0xFF, None));
+        // pressing it again don't resume the previous hold
+        assert_eq!(combo.update(!(SELECT | START), ms(600)), (0xFF, None));
+        assert_eq!(combo.update(0xFF, ms(616)), (0xFF, None));
+        assert_eq!(combo.update(!(SELECT | START), ms(632)), (0xFF, None));
+        assert_eq!(combo.update(!(SELECT | START), ms(1100)), (0xFF, None));
+        assert_eq!(
+            combo.update(!(SELECT | START), ms(1132)),
+            (0xFF, Some(ComboAction::Menu))
+        );
+    }
+
+    #[test]
+    fn larger_combo_restarts_hold() {
+        let mut combo = detector();
+        let t0 = Instant::now();
+        let ms = |x| t0 + Duration::from_millis(x);
+
+        assert_eq!(combo.update(!(SELECT | START), ms(0)), (0xFF, None));
+        assert_eq!(combo.update(!(SELECT | START | A), ms(400)), (!A, None));
+        assert_eq!(
+            combo.update(!(SELECT | START | A | B), ms(450)),
+            (0xFF, None)
+        );
+        assert_eq!(
+            combo.update(!(SELECT | START | A | B), ms(900)),
+            (0xFF, None)
+        );
+        assert_eq!(
+            combo.update(!(SELECT | START | A | B), ms(950)),
+            (0xFF, Some(ComboAction::Reset))
+        );
+    }
+}
@@ -1,19 +1,29 @@
 use std::path::{Path, PathBuf};
 
 use cfg_if::cfg_if;
+use gameroy::gameboy::cartridge::CgbSupport;
 use once_cell::sync::OnceCell;
 use parking_lot::{Mutex, MutexGuard};
 use serde::{Deserialize, Serialize};
-use winit::event::VirtualKeyCode;
+
+use crate::{
+    button_combo::ButtonCombo,
+    key_bindings::{KeyMap, DEFAULT_KEYMAP},
+};
 
 cfg_if! {
     if #[cfg(target_arch = "wasm32")] {
+        /// The key of the config in the local storage.
+        const CONFIG_KEY: &str = "gameroy.config";
+
         pub fn load_config() -> Result<Config, String> {
-            Err("unimplemented".to_string())
+            let data = crate::rom_loading::load_file(CONFIG_KEY)?;
+            serde_json::from_slice(&data).map_err(|e| e.to_string())
         }
 
-        pub fn save_config(_config: &Config) -> Result<(), String> {
-            Err("unimplemented".to_string())
+        pub fn save_config(config: &Config) -> Result<(), String> {
+            let data = serde_json::to_vec(config).map_err(|e| e.to_string())?;
+            crate::rom_loading::save_file(CONFIG_KEY, &data)
         }
     } else if #[cfg(target_os = "android")] {
         use jni::objects::JValue;
@@ -61,7 +71,7 @@ cfg_if! {
 
             jni::Executor::new(vm)
                 .with_attached(|env| {
-                    let serialized = toml::to_string(&conf).unwrap();
+                    let serialized = conf.to_toml().unwrap();
                     log::info!("saving config: {:?}", serialized );
                     let conf= env.new_string(&serialized)?;
 
@@ -79,38 +89,371 @@ cfg_if! {
             Ok(())
         }
     } else {
+        /// The config written by the user. It is never written by the program, so its comments are
+        /// kept.
+        const CONFIG_PATH: &str = "gameroy.toml";
+        /// The config values changed by the program, that override the ones in `CONFIG_PATH`.
+        const SAVED_CONFIG_PATH: &str = "gameroy_saved.toml";
+
+        /// The config values that the program changes, and so are written to `SAVED_CONFIG_PATH`.
+        const SAVED_KEYS: &[&str] = &[
+            "rom_folders",
+            "sort_list",
+            "rom_list_columns",
+            "column_widths",
+            "recent_roms",
+            "scale",
+            "fullscreen",
+            "speed_multiplier",
+            "volume",
+            "muted",
+            "turbo_rate",
+            "keymap",
+        ];
+
         pub fn load_config() -> Result<Config, String> {
-            let config_path = normalize_config_path("gameroy.toml");
+            let config_path = normalize_config_path(CONFIG_PATH);
             log::info!("using '{}' as config path", config_path.display());
-            let config = std::fs::read_to_string(config_path).map_err(|e| e.to_string())?;
-            let config: Config = toml::from_str(&config).map_err(|e| e.to_string())?;
-            Ok(config)
+            read_config(&config_path, &normalize_config_path(SAVED_CONFIG_PATH))
+        }
+
+        pub fn save_config(config: &Config) -> Result<(), String> {
+            write_config(
+                &normalize_config_path(CONFIG_PATH),
+                &normalize_config_path(SAVED_CONFIG_PATH),
+                config,
+            )
+        }
+
+        /// Read the text of the file, or `None` if it doesn't exist.
+        fn read_optional(path: &Path) -> Result<Option<String>, String> {
+            match std::fs::read_to_string(path) {
+                Ok(text) => Ok(Some(text)),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+                Err(e) => Err(e.to_string()),
+            }
+        }
+
+        /// Read the config at `path`, with the `SAVED_KEYS` in `saved_path` over it. A invalid
+        /// `saved_path` is ignored, so it never prevents the config from loading.
+        fn read_config(path: &Path, saved_path: &Path) -> Result<Config, String> {
+            let config = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+            let mut table: toml::value::Table =
+                toml::from_str(&config).map_err(|e| e.to_string())?;
+            let saved = read_optional(saved_path).and_then(|saved| {
+                saved
+                    .map(|x| toml::from_str::<toml::value::Table>(&x).map_err(|e| e.to_string()))
+                    .transpose()
+            });
+            match saved {
+                Ok(Some(saved)) => table.extend(
+                    saved
+                        .into_iter()
+                        .filter(|(key, _)| SAVED_KEYS.contains(&key.as_str())),
+                ),
+                Ok(None) => {}
+                Err(e) => log::error!("error reading '{}': {}", saved_path.display(), e),
+            }
+            toml::Value::Table(table)
+                .try_into()
+                .map_err(|e| e.to_string())
+        }
+
+        /// Write the `SAVED_KEYS` that differ from the ones in the config at `path` to a temporary
+        /// file next to `saved_path`, and then rename it over `saved_path`, so a crash while
+        /// writing doesn't leave a truncated file.
+        fn write_config(path: &Path, saved_path: &Path, config: &Config) -> Result<(), String> {
+            let base = match read_optional(path)? {
+                Some(text) => {
+                    let mut base: Config = toml::from_str(&text).map_err(|e| e.to_string())?;
+                    base.migrate();
+                    base
+                }
+                None => Config::default(),
+            };
+            let base = toml::Value::try_from(&base).map_err(|e| e.to_string())?;
+            let value = toml::Value::try_from(config).map_err(|e| e.to_string())?;
+
+            let mut saved = toml::value::Table::new();
+            for &key in SAVED_KEYS {
+                if let Some(x) = value.get(key).filter(|&x| base.get(key) != Some(x)) {
+                    saved.insert(key.to_string(), x.clone());
+                }
+            }
+            let text = toml::to_string(&toml::Value::Table(saved)).map_err(|e| e.to_string())?;
+            let text = format!(
+                "# The config changed by GameRoy, over the values in '{}'. Remove this file to use\n\
+                 # only the values in '{}'.\n\n{}",
+                CONFIG_PATH, CONFIG_PATH, text
+            );
+
+            let temp_path = saved_path.with_extension("toml.tmp");
+            std::fs::write(&temp_path, text).map_err(|e| e.to_string())?;
+            std::fs::rename(&temp_path, saved_path).map_err(|e| e.to_string())
         }
+    }
+}
+
+/// The GameBoy model that runs the games.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum Model {
+    Dmg,
+    Cgb,
+    /// Choose the model from the CGB flag of the rom header: the CGB for the games that only run
+    /// on it, the DMG for the games made for it, and the one given by `prefer_cgb` for the games
+    /// that run on both.
+    Auto,
+}
+impl Model {
+    /// The model that runs a game with the given CGB support, either `Dmg` or `Cgb`.
+    pub fn resolve(self, support: CgbSupport, prefer_cgb: bool) -> Model {
+        match (self, support) {
+            (Model::Auto, CgbSupport::Only) => Model::Cgb,
+            (Model::Auto, CgbSupport::Enhanced) if prefer_cgb => Model::Cgb,
+            (Model::Auto, _) => Model::Dmg,
+            (x, _) => x,
+        }
+    }
 
-        pub fn save_config(_config: &Config) -> Result<(), String> {
-            Err("unimplemented".to_string())
+    pub fn name(self) -> &'static str {
+        match self {
+            Model::Dmg => "DMG",
+            Model::Cgb => "CGB",
+            Model::Auto => "Auto",
         }
     }
 }
 
+/// How the colors of the screen are converted to the colors shown, see
+/// `gameroy::gameboy::ppu::ColorCorrection`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum ColorCorrection {
+    /// The colors are shown as they are.
+    Raw,
+    /// The colors are darkened and mixed, to approximate the LCD of the CGB.
+    Lcd,
+}
+impl From<ColorCorrection> for gameroy::gameboy::ppu::ColorCorrection {
+    fn from(x: ColorCorrection) -> Self {
+        match x {
+            ColorCorrection::Raw => Self::Raw,
+            ColorCorrection::Lcd => Self::Lcd,
+        }
+    }
+}
+
+/// The color of the bars around the screen, when the window doesn't have the aspect ratio of the
+/// screen, or with `integer_scale`. In the config it is the name of a preset, or a custom color in
+/// the format "#rrggbb".
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum BorderColor {
+    /// The background color of the style.
+    Theme,
+    Black,
+    DarkGray,
+    LightGray,
+    White,
+    Custom([u8; 3]),
+}
+impl BorderColor {
+    /// The presets, with their names in the config.
+    pub const PRESETS: [(&'static str, BorderColor); 5] = [
+        ("Theme", BorderColor::Theme),
+        ("Black", BorderColor::Black),
+        ("DarkGray", BorderColor::DarkGray),
+        ("LightGray", BorderColor::LightGray),
+        ("White", BorderColor::White),
+    ];
+
+    /// The color of the bars, or `None` for the background color of the style. The grays are the
+    /// shades of the screen.
+    pub fn rgb(self) -> Option<[u8; 3]> {
+        match self {
+            BorderColor::Theme => None,
+            BorderColor::Black => Some([0, 0, 0]),
+            BorderColor::DarkGray => Some([85, 85, 85]),
+            BorderColor::LightGray => Some([170, 170, 170]),
+            BorderColor::White => Some([255, 255, 255]),
+            BorderColor::Custom(rgb) => Some(rgb),
+        }
+    }
+}
+impl TryFrom<String> for BorderColor {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        if let Some(&(_, color)) = Self::PRESETS.iter().find(|(name, _)| *name == value) {
+            return Ok(color);
+        }
+        let hex = value
+            .strip_prefix('#')
+            .filter(|x| x.len() == 6 && x.is_ascii());
+        let rgb = hex.and_then(|hex| {
+            let channel = |i: usize| u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).ok();
+            Some([channel(0)?, channel(1)?, channel(2)?])
+        });
+        rgb.map(BorderColor::Custom).ok_or_else(|| {
+            format!(
+                "invalid border color {:?}, expected a preset or \"#rrggbb\"",
+                value
+            )
+        })
+    }
+}
+impl From<BorderColor> for String {
+    fn from(color: BorderColor) -> Self {
+        match color {
+            BorderColor::Custom([r, g, b]) => format!("#{:02x}{:02x}{:02x}", r, g, b),
+            _ => BorderColor::PRESETS
+                .iter()
+                .find(|(_, x)| *x == color)
+                .map(|(name, _)| name.to_string())
+                .unwrap(),
+        }
+    }
+}
+
+/// The maximum number of roms in `Config::recent_roms`.
+pub const RECENT_ROMS_LEN: usize = 10;
+
 #[derive(Debug, Deserialize, Serialize)]
 #[serde(default)]
 pub struct Config {
     pub start_in_debug: bool,
-    pub rom_folder: Option<String>,
+    /// The folders listed in the rom list.
+    pub rom_folders: Vec<String>,
+    /// The single rom folder of older configs. It is moved to `rom_folders` when the config is
+    /// loaded.
+    #[serde(skip_serializing)]
+    rom_folder: Option<String>,
     pub boot_rom: Option<String>,
+    /// A save state of the sound controller, loaded after the boot in place of the bundled one,
+    /// when there is no `boot_rom`.
+    pub after_boot_sound: Option<String>,
+    /// The GameBoy model that runs the games. See `Model::resolve`.
+    pub model: Model,
+    /// Run the games that support both the DMG and the CGB on the CGB, when `model` is `Auto`.
+    pub prefer_cgb: bool,
     pub sort_list: Option<String>,
+    /// Sort the file names in natural order, ignoring case and comparing numbers by their value,
+    /// instead of by their code points.
+    pub natural_sort: bool,
+    /// The titles of the collumns shown in the rom list. The "File" collumn is always shown. When
+    /// not set, "File", "Header Name", "Size" and "Last played" are shown.
+    pub rom_list_columns: Option<Vec<String>>,
+    /// The width of each collumn of the rom list, in the order "File", "Header Name", "Size", "Last
+    /// played", "Mapper", "Color", "Licensee", "State" and "Play time", including the hidden ones.
+    /// It is updated when a collumn is resized. The collumns without a width, like the ones added
+    /// after it was saved, use their default widths.
+    pub column_widths: Vec<f32>,
+    /// Show a thumbnail of each rom in the rom list, read from a png next to the rom, with the same
+    /// name.
+    pub thumbnails: bool,
+    /// The last played roms, the most recent first, as given by `RomFile::recent_key`. Up to
+    /// `RECENT_ROMS_LEN` are kept.
+    pub recent_roms: Vec<String>,
+    /// A No-Intro style .dat file, used to show the proper names of the roms, identified by their
+    /// hash, instead of the title in the header.
+    pub game_database: Option<String>,
+    /// The initial size of the window, as a integer multiple of the GameBoy screen (160x144). If
+    /// the window would not fit in the monitor, the largest multiple that fits is used instead. It
+    /// is updated when the window is resized while playing.
+    pub scale: Option<u32>,
+    /// Only scale the screen by integer multiples, so all pixels have the same size. The remaining
+    /// space is filled by bars of `border_color`.
+    pub integer_scale: bool,
+    /// The color of the bars around the screen.
+    pub border_color: BorderColor,
+    /// Start in borderless fullscreen. It is updated when fullscreen is toggled.
+    pub fullscreen: bool,
+    /// The target latency of the audio output, in milliseconds. The emulated sound is buffered up
+    /// to this amount before reaching the audio device.
+    pub audio_latency: u32,
+    /// The emulation speed, as a multiple of the real speed, between 0.25 and 4.0. It is updated
+    /// when changed by the `speed_up`, `speed_down` and `speed_reset` keys.
+    pub speed_multiplier: f32,
+    /// The speed while the `speed` key is active, between 0.25 and 4.0. If not set, the emulation
+    /// runs as fast as possible.
+    pub fast_forward_speed: Option<f32>,
+    /// The speed while the `slow_motion` key is active, between 0.25 and 4.0.
+    pub slow_motion_speed: f32,
+    /// If true, the `speed` and `slow_motion` keys are toggled on and off by each press, instead of
+    /// being active while held.
+    pub speed_toggle: bool,
+    /// The volume of the sound, between 0.0 and 1.5. Above 1.0 the sound is amplified, and soft
+    /// clipped. It is updated when changed by the `volume_up` and `volume_down` keys.
+    pub volume: f32,
+    /// Mute the sound. It is updated when toggled by the `mute` key.
+    pub muted: bool,
+    /// How many times per second the turbo keys press their button, between 1.0 and 30.0.
+    pub turbo_rate: f32,
+    /// Blend each frame with the previous one, to emulate the ghosting of the LCD, in all games.
+    pub frame_blend: bool,
+    /// The weight of the previous frame in the frame blending, between 0.0 and 1.0.
+    pub frame_blend_weight: f32,
+    /// The SHA-1 of the roms, in hexadecimal, of the games that use frame blending, even if
+    /// `frame_blend` is disabled.
+    pub frame_blend_games: Vec<String>,
+    /// How the colors of the screen are converted, in the game screen and in the screenshots.
+    pub color_correction: ColorCorrection,
+    /// Show the frame rate and audio buffer stats over the game screen at startup. They can be
+    /// toggled with the `stats_overlay` key.
+    pub show_stats: bool,
+    /// Combinations of joypad buttons that trigger a emulator action when held, for setups
+    /// without a keyboard.
+    pub combos: Vec<ButtonCombo>,
+    /// How long a button combo must be held before its action is triggered, in milliseconds.
+    pub combo_hold_time: u32,
+    /// The keys bound to each joypad button and emulator action. It is updated when changed in the
+    /// key bindings dialog.
     pub keymap: KeyMap,
 }
 
 impl Config {
     pub fn load() -> Result<Self, String> {
-        load_config()
+        let mut config = load_config()?;
+        config.migrate();
+        Ok(config)
+    }
+
+    /// The config in the toml format. It is converted to a `toml::Value` first, that writes the
+    /// tables after the values, because `combos` comes before other values in the struct.
+    pub fn to_toml(&self) -> Result<String, String> {
+        let value = toml::Value::try_from(self).map_err(|e| e.to_string())?;
+        toml::to_string(&value).map_err(|e| e.to_string())
+    }
+
+    /// Move the old singular `rom_folder` to the front of `rom_folders`.
+    fn migrate(&mut self) {
+        if let Some(folder) = self.rom_folder.take() {
+            if !self.rom_folders.contains(&folder) {
+                self.rom_folders.insert(0, folder);
+            }
+        }
     }
 
     pub fn save(&self) -> Result<(), String> {
         save_config(self)
     }
+
+    /// Move the rom with the given key to the front of `recent_roms`, adding it if it is not
+    /// there, and drop the oldest ones past `RECENT_ROMS_LEN`.
+    pub fn add_recent_rom(&mut self, key: String) {
+        self.recent_roms.retain(|x| *x != key);
+        self.recent_roms.insert(0, key);
+        self.recent_roms.truncate(RECENT_ROMS_LEN);
+    }
+
+    /// Add the folder to `rom_folders`, if it is not there yet. Return false if it was already
+    /// there.
+    pub fn add_rom_folder(&mut self, folder: String) -> bool {
+        if self.rom_folders.contains(&folder) {
+            return false;
+        }
+        self.rom_folders.push(folder);
+        true
+    }
 }
 
 /// Transform a path relative to the executable folder to a absolute path.
@@ -155,64 +498,40 @@ impl Default for Config {
     }
 }
 
-#[derive(Debug, Deserialize, Serialize)]
-#[serde(default)]
-pub struct KeyMap {
-    pub left: VirtualKeyCode,
-    pub right: VirtualKeyCode,
-    pub up: VirtualKeyCode,
-    pub down: VirtualKeyCode,
-    pub a: VirtualKeyCode,
-    pub b: VirtualKeyCode,
-    pub select: VirtualKeyCode,
-    pub start: VirtualKeyCode,
-
-    pub speed: VirtualKeyCode,
-    pub rewind: VirtualKeyCode,
-    pub save_state: VirtualKeyCode,
-    pub load_state: VirtualKeyCode,
-
-    pub open_debugger: VirtualKeyCode,
-    pub debug_step: VirtualKeyCode,
-    pub debug_stepback: VirtualKeyCode,
-    pub debug_run: VirtualKeyCode,
-}
-
-impl Default for KeyMap {
-    fn default() -> Self {
-        DEFAULT_KEYMAP
-    }
-}
-
-const DEFAULT_KEYMAP: KeyMap = {
-    use VirtualKeyCode::*;
-    KeyMap {
-        left: Left,
-        right: Right,
-        up: Up,
-        down: Down,
-        a: A,
-        b: S,
-        select: Back,
-        start: Return,
-
-        speed: LShift,
-        rewind: R,
-        save_state: F5,
-        load_state: F6,
-
-        open_debugger: F12,
-        debug_stepback: F7,
-        debug_step: F8,
-        debug_run: F9,
-    }
-};
-
 const DEFAULT_CONFIG: Config = Config {
     start_in_debug: false,
+    rom_folders: Vec::new(),
     rom_folder: None,
     boot_rom: None,
+    after_boot_sound: None,
+    model: Model::Auto,
+    prefer_cgb: true,
     sort_list: None,
+    natural_sort: true,
+    rom_list_columns: None,
+    column_widths: Vec::new(),
+    thumbnails: false,
+    recent_roms: Vec::new(),
+    game_database: None,
+    scale: None,
+    integer_scale: true,
+    border_color: BorderColor::Theme,
+    fullscreen: false,
+    audio_latency: 80,
+    speed_multiplier: 1.0,
+    fast_forward_speed: None,
+    slow_motion_speed: 0.5,
+    speed_toggle: false,
+    volume: 1.0,
+    muted: false,
+    turbo_rate: 15.0,
+    frame_blend: false,
+    frame_blend_weight: 0.5,
+    frame_blend_games: Vec::new(),
+    color_correction: ColorCorrection::Raw,
+    show_stats: false,
+    combos: Vec::new(),
+    combo_hold_time: 500,
     keymap: DEFAULT_KEYMAP,
 };
 
@@ -225,3 +544,228 @@ pub fn config() -> MutexGuard<'static, Config> {
 pub fn init_config(config: Config) {
     *CONFIG.lock() = config
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn migrate_rom_folder() {
+        let mut config: Config = toml::from_str("rom_folder = \"roms\"").unwrap();
+        config.migrate();
+        assert_eq!(config.rom_folders, ["roms"]);
+        assert!(!config.to_toml().unwrap().contains("rom_folder ="));
+
+        let mut config: Config =
+            toml::from_str("rom_folder = \"roms\"\nrom_folders = [\"gbc\", \"roms\"]").unwrap();
+        config.migrate();
+        assert_eq!(config.rom_folders, ["gbc", "roms"]);
+    }
+
+    #[test]
+    fn add_rom_folder() {
+        let mut config = Config::default();
+        assert!(config.add_rom_folder("roms".to_string()));
+        assert!(config.add_rom_folder("gbc".to_string()));
+        assert!(!config.add_rom_folder("roms".to_string()));
+        assert_eq!(config.rom_folders, ["roms", "gbc"]);
+    }
+
+    #[test]
+    fn add_recent_rom() {
+        let mut config = Config::default();
+        config.add_recent_rom("a.gb".to_string());
+        config.add_recent_rom("b.gb".to_string());
+        config.add_recent_rom("c.gb".to_string());
+        assert_eq!(config.recent_roms, ["c.gb", "b.gb", "a.gb"]);
+
+        // reloading moves it to the front, without duplicating
+        config.add_recent_rom("a.gb".to_string());
+        assert_eq!(config.recent_roms, ["a.gb", "c.gb", "b.gb"]);
+        config.add_recent_rom("a.gb".to_string());
+        assert_eq!(config.recent_roms, ["a.gb", "c.gb", "b.gb"]);
+
+        for i in 0..RECENT_ROMS_LEN {
+            config.add_recent_rom(format!("{}.gb", i));
+        }
+        assert_eq!(config.recent_roms.len(), RECENT_ROMS_LEN);
+        assert_eq!(config.recent_roms[0], format!("{}.gb", RECENT_ROMS_LEN - 1));
+        assert!(!config.recent_roms.contains(&"a.gb".to_string()));
+    }
+
+    #[test]
+    fn parse_combos() {
+        use crate::button_combo::{ComboAction, JoypadButton};
+
+        let config: Config =
+            toml::from_str("[[combos]]\nbuttons = [\"Select\", \"Start\"]\naction = \"Menu\"\n")
+                .unwrap();
+        assert_eq!(
+            config.combos,
+            [ButtonCombo {
+                buttons: vec![JoypadButton::Select, JoypadButton::Start],
+                action: ComboAction::Menu,
+            }]
+        );
+        assert_eq!(config.combo_hold_time, 500);
+    }
+
+    #[test]
+    fn resolve_model() {
+        use CgbSupport::{Enhanced, Only};
+
+        let config: Config = toml::from_str("model = \"Auto\"\nprefer_cgb = false").unwrap();
+        assert_eq!(config.model, Model::Auto);
+        // a CGB only game runs on the CGB, even if the DMG is preferred
+        assert_eq!(config.model.resolve(Only, config.prefer_cgb), Model::Cgb);
+        assert_eq!(
+            config.model.resolve(Enhanced, config.prefer_cgb),
+            Model::Dmg
+        );
+        assert_eq!(
+            config.model.resolve(CgbSupport::None, config.prefer_cgb),
+            Model::Dmg
+        );
+
+        assert_eq!(Model::Auto.resolve(Enhanced, true), Model::Cgb);
+        assert_eq!(Model::Auto.resolve(CgbSupport::None, true), Model::Dmg);
+        // a explicit model is always used
+        assert_eq!(Model::Dmg.resolve(Only, true), Model::Dmg);
+        assert_eq!(Model::Cgb.resolve(CgbSupport::None, false), Model::Cgb);
+    }
+
+    #[test]
+    #[cfg(not(any(target_arch = "wasm32", target_os = "android")))]
+    fn save_and_load() {
+        use crate::button_combo::{ComboAction, JoypadButton};
+
+        let dir = std::env::temp_dir().join(format!("gameroy_config_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("gameroy.toml");
+        let saved_path = dir.join("gameroy_saved.toml");
+
+        // a hand written config, with comments
+        let text =
+            "# the folders of the roms\nrom_folders = [\"roms\"]\n\n# the volume\nvolume = 0.5\n\
+                    # the latency\naudio_latency = 40\n";
+        std::fs::write(&path, text).unwrap();
+        let config = read_config(&path, &saved_path).unwrap();
+        assert_eq!(config.volume, 0.5);
+        assert_eq!(config.audio_latency, 40);
+
+        let config = Config {
+            rom_folders: vec!["roms".to_string(), "gbc".to_string()],
+            scale: Some(4),
+            fullscreen: true,
+            speed_multiplier: 1.5,
+            volume: 0.75,
+            muted: true,
+            column_widths: vec![200.0, 120.5],
+            recent_roms: vec!["roms/a.gb".to_string()],
+            // not changed by the program, so it is not saved
+            fast_forward_speed: Some(3.0),
+            combos: vec![ButtonCombo {
+                buttons: vec![JoypadButton::Select, JoypadButton::Start],
+                action: ComboAction::Menu,
+            }],
+            ..config
+        };
+        write_config(&path, &saved_path, &config).unwrap();
+        assert!(!saved_path.with_extension("toml.tmp").exists());
+        // the hand written config is kept as is
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), text);
+
+        // only the changed values are saved
+        let saved: toml::value::Table =
+            toml::from_str(&std::fs::read_to_string(&saved_path).unwrap()).unwrap();
+        let mut keys: Vec<&str> = saved.keys().map(|x| x.as_str()).collect();
+        keys.sort_unstable();
+        assert_eq!(
+            keys,
+            [
+                "column_widths",
+                "fullscreen",
+                "muted",
+                "recent_roms",
+                "rom_folders",
+                "scale",
+                "speed_multiplier",
+                "volume"
+            ]
+        );
+
+        let loaded = read_config(&path, &saved_path).unwrap();
+        // overwriting a existing saved config
+        write_config(&path, &saved_path, &loaded).unwrap();
+        let reloaded = read_config(&path, &saved_path).unwrap();
+
+        for loaded in [loaded, reloaded] {
+            assert_eq!(loaded.rom_folders, config.rom_folders);
+            assert_eq!(loaded.scale, Some(4));
+            assert!(loaded.fullscreen && loaded.muted);
+            assert_eq!(loaded.volume, 0.75);
+            assert_eq!(loaded.audio_latency, 40);
+            assert_eq!(loaded.column_widths, config.column_widths);
+            assert_eq!(loaded.recent_roms, config.recent_roms);
+            assert_eq!(loaded.fast_forward_speed, None);
+            assert!(loaded.combos.is_empty());
+            assert_eq!(loaded.keymap, config.keymap);
+        }
+
+        // the saved values override the ones written later in the config, and a invalid saved
+        // config is ignored
+        std::fs::write(&path, "volume = 1.25\nspeed_multiplier = 2.0\n").unwrap();
+        let loaded = read_config(&path, &saved_path).unwrap();
+        assert_eq!((loaded.volume, loaded.speed_multiplier), (0.75, 1.5));
+        std::fs::write(&saved_path, "volume = ").unwrap();
+        let loaded = read_config(&path, &saved_path).unwrap();
+        assert_eq!((loaded.volume, loaded.speed_multiplier), (1.25, 2.0));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn border_color() {
+        let parse = |text: &str| toml::from_str::<Config>(text).map(|x| x.border_color);
+        assert_eq!(parse("").unwrap(), BorderColor::Theme);
+        assert_eq!(
+            parse("border_color = \"DarkGray\"").unwrap(),
+            BorderColor::DarkGray
+        );
+        assert_eq!(
+            parse("border_color = \"#1A2b3c\"").unwrap(),
+            BorderColor::Custom([0x1a, 0x2b, 0x3c])
+        );
+        assert!(parse("border_color = \"darkgray\"").is_err());
+        assert!(parse("border_color = \"#1a2b3\"").is_err());
+        assert!(parse("border_color = \"#1a2b3g\"").is_err());
+        assert!(parse("border_color = \"#1é2b3\"").is_err());
+
+        for (_, color) in BorderColor::PRESETS {
+            let config = Config {
+                border_color: color,
+                ..Config::default()
+            };
+            let text = config.to_toml().unwrap();
+            assert_eq!(parse(&text).unwrap(), color);
+        }
+        let config = Config {
+            border_color: BorderColor::Custom([0, 0x80, 0xff]),
+            ..Config::default()
+        };
+        let text = config.to_toml().unwrap();
+        assert!(text.contains("border_color = \"#0080ff\""));
+        assert_eq!(parse(&text).unwrap(), BorderColor::Custom([0, 0x80, 0xff]));
+    }
+
+    #[test]
+    fn color_correction() {
+        let parse = |text: &str| toml::from_str::<Config>(text).map(|x| x.color_correction);
+        assert_eq!(parse("").unwrap(), ColorCorrection::Raw);
+        assert_eq!(
+            parse("color_correction = \"Lcd\"").unwrap(),
+            ColorCorrection::Lcd
+        );
+        assert!(parse("color_correction = \"lcd\"").is_err());
+    }
+}
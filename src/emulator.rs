@@ -3,9 +3,9 @@ use std::{collections::VecDeque, io::Write, sync::Arc, time::Duration};
 #[cfg(feature = "audio-engine")]
 use audio_engine::{AudioEngine, SoundSource};
 use gameroy::{
-    consts::CLOCK_SPEED,
+    consts::{CLOCK_SPEED, FRAME_CYCLES},
     debugger::{Debugger, RunResult},
-    gameboy::GameBoy,
+    gameboy::{cartridge::Cartridge, GameBoy},
     interpreter::Interpreter,
     parser::Vbm,
     save_state::SaveState,
@@ -15,27 +15,85 @@ use parking_lot::Mutex as ParkMutex;
 use winit::event_loop::EventLoopProxy;
 
 use super::UserEvent;
-use crate::rom_loading::RomFile;
+#[cfg(feature = "audio-engine")]
+use crate::audio_buffer::AudioBuffer;
+use crate::{frame_stats::EmulatorCounters, game_database::RomHash, rom_loading::RomFile};
 
 #[derive(Debug)]
 pub enum EmulatorEvent {
     Kill,
     RunFrame,
-    FrameLimit(bool),
+    /// Run at a different speed than the one set by `SetSpeed`, while the fast forward or slow
+    /// motion key is active, or go back to it if `None`.
+    OverrideSpeed(Option<SpeedOverride>),
     Rewind(bool),
     SetJoypad(u8),
+    /// Set the joypad buttons held by a turbo key, as a bitmask where 1 means held. See `Turbo`.
+    SetTurbo(u8),
+    /// Set the emulation speed, as a multiple of the real speed. It is clamped between
+    /// `MIN_SPEED` and `MAX_SPEED`.
+    SetSpeed(f32),
+    /// Set the volume of the sound, between 0.0 and `MAX_VOLUME`, and if it is muted.
+    SetVolume {
+        volume: f32,
+        muted: bool,
+    },
     Debug(bool),
     Step,
-    StepBack,
+    StepBack(u64),
     Run,
     Reset,
+    /// Turn the GameBoy off and on again. If true, the cartridge RAM is also cleared.
+    PowerCycle(bool),
     SaveState,
     LoadState,
+    /// Write the current save state to the given file, to be shared.
+    #[cfg(all(
+        feature = "rfd",
+        not(any(target_arch = "wasm32", target_os = "android"))
+    ))]
+    ExportState(std::path::PathBuf),
+    /// Load a save state from a shared file, whose header should be already checked by
+    /// `GameBoy::read_state_header`, or, in wasm, the one read from the IndexedDB by `LoadState`.
+    #[cfg(any(
+        target_arch = "wasm32",
+        all(feature = "rfd", not(target_os = "android"))
+    ))]
+    ImportState(Vec<u8>),
+    /// Save the screen to a png, in the `screenshots` folder. See `RomFile::save_screenshot`.
+    #[cfg(not(any(target_arch = "wasm32", target_os = "android")))]
+    Screenshot,
     SaveRam,
+    /// Save the RAM, if it was written since the last save.
+    FlushRam,
     Pause,
     Resume,
 }
 
+/// A temporary emulation speed, that replaces the one set by `SetSpeed`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SpeedOverride {
+    /// Run at the given speed, as a multiple of the real speed, clamped between `MIN_SPEED` and
+    /// `MAX_SPEED`.
+    Speed(f32),
+    /// Run as fast as possible, without the frame limit. The sound is muted.
+    Unlimited,
+}
+impl SpeedOverride {
+    /// The speed of the `speed` key, from the `fast_forward_speed` config.
+    pub fn fast_forward() -> Self {
+        match crate::config::config().fast_forward_speed {
+            Some(speed) => Self::Speed(speed),
+            None => Self::Unlimited,
+        }
+    }
+
+    /// The speed of the `slow_motion` key, from the `slow_motion_speed` config.
+    pub fn slow_motion() -> Self {
+        Self::Speed(crate::config::config().slow_motion_speed)
+    }
+}
+
 #[derive(PartialEq, Eq, Debug)]
 enum EmulatorState {
     Idle,
@@ -45,7 +103,7 @@ enum EmulatorState {
 
 #[cfg(feature = "audio-engine")]
 struct Buffer {
-    buffer: Arc<ParkMutex<VecDeque<i16>>>,
+    buffer: Arc<ParkMutex<AudioBuffer>>,
     sample_rate: u32,
 }
 #[cfg(feature = "audio-engine")]
@@ -63,24 +121,27 @@ impl SoundSource for Buffer {
     }
 
     fn write_samples(&mut self, buffer: &mut [i16]) -> usize {
-        let mut lock = self.buffer.lock();
-        let len = lock.len().min(buffer.len());
-        for (a, b) in buffer[0..len].iter_mut().zip(lock.iter()) {
-            *a = *b;
-        }
-        lock.drain(0..len);
+        self.buffer.lock().pop(buffer);
 
-        // even if the writed length is smaller than the buffer, return the entire length to avoid
-        // the audio source to be reseted
+        // on underrun the rest of the buffer is filled with silence, so always return the entire
+        // length, to avoid the audio source to be reseted
         buffer.len()
     }
 }
 
 #[cfg(test)]
 mod test {
-    use std::io::Write;
+    use std::{io::Write, time::Duration};
+
+    use gameroy::gameboy::{cartridge::Cartridge, GameBoy};
+    use instant::Instant;
 
-    use super::CircularBuffer;
+    use gameroy::consts::CLOCK_SPEED;
+
+    use super::{
+        BatteryFlush, CircularBuffer, FramePacer, Timeline, Turbo, FLUSH_INTERVAL,
+        MIN_FLUSH_INTERVAL, WRITE_DEBOUNCE,
+    };
     #[test]
     fn circular_buffer() {
         let mut buffer = CircularBuffer {
@@ -132,6 +193,432 @@ mod test {
         eprintln!("{:?}", buffer);
         assert_eq!(&*buffer.buffer, &[11, 14, 15, 16, 17, 5, 6, 7, 10])
     }
+
+    /// The number of clock cycles in a emulated frame.
+    const FRAME_CLOCK_COUNT: u64 = 154 * 456;
+
+    /// Emulate one second at the given speed, presenting `fps` frames, and return the clock count
+    /// reached.
+    fn pace_one_second(speed: f32, fps: u32) -> u64 {
+        let start = Instant::now();
+        let mut pacer = FramePacer::new(0, start, speed);
+        let mut clock_count = 0;
+        for i in 1..=fps {
+            let now = start + Duration::from_secs(1) * i / fps;
+            clock_count = pacer.target_clock(clock_count, now);
+        }
+        clock_count
+    }
+
+    #[test]
+    fn frame_pacer() {
+        let clock_count = pace_one_second(0.5, 60);
+        assert!(clock_count.abs_diff(CLOCK_SPEED / 2) <= 1);
+        assert_eq!(clock_count / FRAME_CLOCK_COUNT, 29);
+
+        let clock_count = pace_one_second(1.0, 60);
+        assert!(clock_count.abs_diff(CLOCK_SPEED) <= 1);
+        assert_eq!(clock_count / FRAME_CLOCK_COUNT, 59);
+
+        let clock_count = pace_one_second(4.0, 240);
+        assert!(clock_count.abs_diff(CLOCK_SPEED * 4) <= 1);
+
+        // the speed is clamped
+        assert_eq!(pace_one_second(100.0, 240), pace_one_second(4.0, 240));
+        assert_eq!(pace_one_second(0.0, 60), pace_one_second(0.25, 60));
+    }
+
+    #[test]
+    fn frame_pacer_multipliers() {
+        // presenting at 60 fps, the emulated clocks follow the speed, even when more than the lag
+        // limit of a frame at the real speed is emulated per presented frame
+        for speed in [0.25, 0.5, 1.0, 2.0, 3.0, 4.0] {
+            let clock_count = pace_one_second(speed, 60) as f64;
+            let expected = CLOCK_SPEED as f64 * speed as f64;
+            assert!(
+                (clock_count / expected - 1.0).abs() < 0.02,
+                "speed {}: {} clocks, expected {}",
+                speed,
+                clock_count,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn frame_pacer_set_speed() {
+        let start = Instant::now();
+        let mut pacer = FramePacer::new(0, start, 1.0);
+        let mut clock_count = 0;
+        for i in 1..=30 {
+            let now = start + Duration::from_secs(1) * i / 60;
+            clock_count = pacer.target_clock(clock_count, now);
+        }
+        assert!(clock_count.abs_diff(CLOCK_SPEED / 2) <= 1);
+
+        // the change of speed only affects the time after it
+        let half = start + Duration::from_millis(500);
+        pacer.set_speed(0.5, clock_count, half);
+        for i in 31..=60 {
+            let now = start + Duration::from_secs(1) * i / 60;
+            clock_count = pacer.target_clock(clock_count, now);
+        }
+        assert!(clock_count.abs_diff(CLOCK_SPEED / 2 + CLOCK_SPEED / 4) <= 1);
+    }
+
+    #[test]
+    fn speed_steps() {
+        use super::step_speed;
+        assert_eq!(step_speed(1.0, true), 1.25);
+        assert_eq!(step_speed(1.0, false), 0.75);
+        assert_eq!(step_speed(1.1, false), 1.0);
+        assert_eq!(step_speed(4.0, true), 4.0);
+        assert_eq!(step_speed(0.25, false), 0.25);
+        assert_eq!(step_speed(8.0, false), 4.0);
+    }
+
+    #[test]
+    fn turbo_rate_steps() {
+        use super::step_turbo_rate;
+        assert_eq!(step_turbo_rate(15.0, true), 20.0);
+        assert_eq!(step_turbo_rate(15.0, false), 12.0);
+        assert_eq!(step_turbo_rate(8.0, false), 7.5);
+        assert_eq!(step_turbo_rate(30.0, true), 30.0);
+        assert_eq!(step_turbo_rate(1.0, false), 1.0);
+        assert_eq!(step_turbo_rate(50.0, false), 30.0);
+    }
+
+    #[test]
+    fn volume_steps() {
+        use super::step_volume;
+        assert_eq!(step_volume(1.0, true), 1.1);
+        assert_eq!(step_volume(1.0, false), 0.9);
+        assert_eq!(step_volume(0.54, false), 0.4);
+        assert_eq!(step_volume(1.5, true), 1.5);
+        assert_eq!(step_volume(0.0, false), 0.0);
+    }
+
+    #[test]
+    fn frame_pacer_lag() {
+        let start = Instant::now();
+        let mut pacer = FramePacer::new(0, start, 1.0);
+        // the emulation stalled for a second, and is limited to 1/30 seconds ahead
+        let now = start + Duration::from_secs(1);
+        assert_eq!(pacer.target_clock(0, now), CLOCK_SPEED / 30);
+        // and then continues from there
+        let now = now + Duration::from_millis(10);
+        let target = pacer.target_clock(CLOCK_SPEED / 30, now);
+        assert!(target.abs_diff(CLOCK_SPEED / 30 + CLOCK_SPEED / 100) <= 1);
+    }
+
+    /// A MBC1+RAM+BATTERY cartridge, with 8 KiB of RAM.
+    fn battery_cartridge() -> Cartridge {
+        Cartridge::new_test(0x03, 2, 0x02)
+    }
+
+    /// Poll `flush`, and push the due battery save to `saves`, as if it was written.
+    fn poll(
+        flush: &mut BatteryFlush,
+        cartridge: &mut Cartridge,
+        now: Instant,
+        saves: &mut Vec<Vec<u8>>,
+    ) {
+        if let Some((data, write_count)) = flush.poll(cartridge, now) {
+            saves.push(data);
+            cartridge.mark_battery_saved(write_count);
+        }
+    }
+
+    #[test]
+    fn battery_flush() {
+        let mut cartridge = battery_cartridge();
+        let start = Instant::now();
+        let mut flush = BatteryFlush::new(start);
+        let mut saves = Vec::new();
+
+        // the game enables the RAM, writes to it, and disables it
+        cartridge.write(0x0000, 0x0A);
+        cartridge.write(0xA000, 0x12);
+        cartridge.write(0xA001, 0x34);
+        poll(&mut flush, &mut cartridge, start, &mut saves);
+        assert!(saves.is_empty());
+        cartridge.write(0x0000, 0x00);
+
+        let now = start + MIN_FLUSH_INTERVAL;
+        poll(&mut flush, &mut cartridge, now, &mut saves);
+        poll(&mut flush, &mut cartridge, now, &mut saves);
+        assert_eq!(saves.len(), 1);
+        assert_eq!(saves[0].len(), 0x2000);
+        assert_eq!(&saves[0][..3], &[0x12, 0x34, 0x00]);
+
+        // a save finished right after the last flush is debounced
+        cartridge.write(0x0000, 0x0A);
+        cartridge.write(0xA000, 0x56);
+        cartridge.write(0x0000, 0x00);
+        poll(&mut flush, &mut cartridge, now, &mut saves);
+        assert_eq!(saves.len(), 1);
+        let now = now + MIN_FLUSH_INTERVAL;
+        poll(&mut flush, &mut cartridge, now, &mut saves);
+        assert_eq!(saves.len(), 2);
+        assert_eq!(saves[1][0], 0x56);
+
+        // RAM that keeps being written is flushed periodically
+        cartridge.write(0x0000, 0x0A);
+        let mut time = now;
+        while time < now + FLUSH_INTERVAL {
+            cartridge.write(0xA000, 0x78);
+            poll(&mut flush, &mut cartridge, time, &mut saves);
+            assert_eq!(saves.len(), 2);
+            time += WRITE_DEBOUNCE / 2;
+        }
+        poll(&mut flush, &mut cartridge, time, &mut saves);
+        assert_eq!(saves.len(), 3);
+        assert_eq!(saves[2][0], 0x78);
+    }
+
+    #[test]
+    fn battery_flush_debounce() {
+        let mut cartridge = battery_cartridge();
+        let start = Instant::now();
+        let mut flush = BatteryFlush::new(start);
+        let mut saves = Vec::new();
+
+        // the game writes to the RAM, without disabling it
+        cartridge.write(0x0000, 0x0A);
+        cartridge.write(0xA000, 0x12);
+        poll(&mut flush, &mut cartridge, start, &mut saves);
+        let now = start + WRITE_DEBOUNCE / 2;
+        cartridge.write(0xA001, 0x34);
+        poll(&mut flush, &mut cartridge, now, &mut saves);
+        assert!(saves.is_empty());
+
+        // the save is written once there are no writes for `WRITE_DEBOUNCE`
+        let now = now + WRITE_DEBOUNCE;
+        for i in 0..4 {
+            let time = now + i * WRITE_DEBOUNCE;
+            poll(&mut flush, &mut cartridge, time, &mut saves);
+        }
+        assert_eq!(saves.len(), 1);
+        assert_eq!(&saves[0][..2], &[0x12, 0x34]);
+    }
+
+    #[test]
+    fn battery_flush_failed() {
+        let mut cartridge = battery_cartridge();
+        let start = Instant::now();
+        let mut flush = BatteryFlush::new(start);
+
+        cartridge.write(0x0000, 0x0A);
+        cartridge.write(0xA000, 0x12);
+        cartridge.write(0x0000, 0x00);
+
+        // the write fails, so the save is not marked as saved, and is written again later
+        let now = start + MIN_FLUSH_INTERVAL;
+        assert!(flush.poll(&cartridge, now).is_some());
+        assert!(cartridge.battery_dirty());
+        assert!(flush.poll(&cartridge, now).is_none());
+        let now = now + MIN_FLUSH_INTERVAL;
+        let (data, write_count) = flush.poll(&cartridge, now).unwrap();
+        assert_eq!(data[0], 0x12);
+
+        // the RAM is written while the save is being written, so it is still pending
+        cartridge.write(0x0000, 0x0A);
+        cartridge.write(0xA000, 0x34);
+        cartridge.mark_battery_saved(write_count);
+        assert!(cartridge.battery_dirty());
+    }
+
+    /// The A button in each joypad, as 'X' if pressed, or '.' if released.
+    fn a_presses(joypads: &[u8]) -> String {
+        joypads
+            .iter()
+            .map(|x| if x & 0x10 == 0 { 'X' } else { '.' })
+            .collect()
+    }
+
+    #[test]
+    fn turbo_frames() {
+        let gb = GameBoy::new(None, battery_cartridge());
+        let mut timeline = Timeline::new(0, Vec::new(), 15.0);
+        let run = |timeline: &mut Timeline, frames: usize| -> Vec<u8> {
+            (0..frames).map(|_| timeline.next_frame(&gb)).collect()
+        };
+
+        // at 15 Hz, the button is pressed for 2 frames, and released for 2
+        timeline.current_turbo = 0x10;
+        let joypads = run(&mut timeline, 60);
+        assert_eq!(a_presses(&joypads), "XX..".repeat(15));
+        assert!(joypads.iter().all(|&x| x | 0x10 == 0xFF));
+        // the joypad timeline has the pressed and released frames
+        assert_eq!(timeline.joypad_timeline, joypads);
+
+        // holding A too keeps it pressed
+        timeline.current_joypad = !0x10;
+        assert_eq!(a_presses(&run(&mut timeline, 4)), "XXXX");
+        timeline.current_joypad = 0xFF;
+
+        // releasing the turbo and holding it again starts with a press
+        timeline.current_turbo = 0;
+        assert_eq!(a_presses(&run(&mut timeline, 3)), "...");
+        timeline.current_turbo = 0x10;
+        assert_eq!(a_presses(&run(&mut timeline, 8)), "XX..XX..");
+
+        // the rate is clamped, and at most toggles each frame
+        assert_eq!(Turbo::new(30.0).period, 2);
+        assert_eq!(Turbo::new(100.0).period, 2);
+        assert_eq!(Turbo::new(0.0).period, Turbo::new(1.0).period);
+        let mut turbo = Turbo::new(10.0);
+        let joypads: Vec<u8> = (0..12).map(|i| turbo.apply(0xFF, 0x10, i)).collect();
+        assert_eq!(a_presses(&joypads), "XXX...XXX...");
+    }
+}
+
+/// The minimum emulation speed, as a multiple of the real speed.
+pub const MIN_SPEED: f32 = 0.25;
+/// The maximum emulation speed, as a multiple of the real speed. Faster than that is only
+/// reachable by disabling the frame limit.
+pub const MAX_SPEED: f32 = 4.0;
+
+/// The range of speeds in which the sound is time stretched, keeping its pitch. Beyond it, the
+/// remaining of the speed changes the pitch.
+const STRETCH_SPEEDS: (f32, f32) = (0.5, 2.0);
+
+/// The speeds stepped through by the `speed_up` and `speed_down` keys.
+const SPEED_STEPS: &[f32] = &[0.25, 0.5, 0.75, 1.0, 1.25, 1.5, 2.0, 3.0, 4.0];
+
+/// The next speed in `SPEED_STEPS` after `speed`, if `up`, or before it otherwise. If there is
+/// none, the speed is returned clamped.
+pub fn step_speed(speed: f32, up: bool) -> f32 {
+    let next = if up {
+        SPEED_STEPS.iter().find(|&&x| x > speed)
+    } else {
+        SPEED_STEPS.iter().rev().find(|&&x| x < speed)
+    };
+    next.copied().unwrap_or(speed.clamp(MIN_SPEED, MAX_SPEED))
+}
+
+/// The maximum volume. Above 1.0 the sound is amplified, and soft clipped near the full scale.
+pub const MAX_VOLUME: f32 = 1.5;
+
+/// The volume after a step of the `volume_up` or `volume_down` keys, which move it by 0.1,
+/// clamped between 0.0 and `MAX_VOLUME`.
+pub fn step_volume(volume: f32, up: bool) -> f32 {
+    let step = if up { 1.0 } else { -1.0 };
+    (((volume * 10.0).round() + step) / 10.0).clamp(0.0, MAX_VOLUME)
+}
+
+/// The gain applied to the sound output, for the given volume.
+#[cfg(feature = "audio-engine")]
+fn volume_gain(volume: f32, muted: bool) -> f32 {
+    if muted {
+        0.0
+    } else {
+        volume.clamp(0.0, MAX_VOLUME)
+    }
+}
+
+/// Keeps the emulated time in sync with the real time, scaled by the emulation speed.
+///
+/// The clocks to be emulated are counted from a anchor, the clock count at a instant, which is
+/// moved each time the speed changes or the emulation is interrupted, so the changes don't make
+/// the emulation jump forward or backward.
+struct FramePacer {
+    anchor_time: Instant,
+    anchor_clock: u64,
+    speed: f32,
+}
+impl FramePacer {
+    fn new(clock_count: u64, now: Instant, speed: f32) -> Self {
+        Self {
+            anchor_time: now,
+            anchor_clock: clock_count,
+            speed: speed.clamp(MIN_SPEED, MAX_SPEED),
+        }
+    }
+
+    /// Start counting from the given clock count, at `now`.
+    fn reset(&mut self, clock_count: u64, now: Instant) {
+        self.anchor_time = now;
+        self.anchor_clock = clock_count;
+    }
+
+    /// Change the speed from now on, clamped between `MIN_SPEED` and `MAX_SPEED`.
+    fn set_speed(&mut self, speed: f32, clock_count: u64, now: Instant) {
+        self.reset(clock_count, now);
+        self.speed = speed.clamp(MIN_SPEED, MAX_SPEED);
+    }
+
+    /// The clock count that the emulation should reach at `now`. If it is too far ahead of the
+    /// current `clock_count`, because the emulation can't keep up, it is limited, and the pacer
+    /// restarts from the limited clock count.
+    fn target_clock(&mut self, clock_count: u64, now: Instant) -> u64 {
+        let elapsed = now.duration_since(self.anchor_time).as_secs_f64();
+        let target = self.anchor_clock + (elapsed * self.speed as f64 * CLOCK_SPEED as f64) as u64;
+
+        // make sure that the target_clock don't increase indefinitely if the program can't keep up.
+        // The limit is 1/30 seconds of real time, at the current speed.
+        let max_target = clock_count + (CLOCK_SPEED as f64 * self.speed as f64 / 30.0) as u64;
+        if target > max_target {
+            self.reset(max_target, now);
+            return max_target;
+        }
+        target
+    }
+}
+
+/// The minimum time between two flushes of the battery save.
+const MIN_FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+/// The time without writes to the RAM after which the battery save is flushed.
+const WRITE_DEBOUNCE: Duration = Duration::from_secs(2);
+/// The time after which a battery save with pending writes is flushed, even if the game keeps
+/// writing to the RAM.
+const FLUSH_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Decides when to write the battery save while the game is running, so that progress is not lost
+/// if the emulator is closed abruptly.
+///
+/// The save is written when the game disables the cartridge RAM after writing to it, which games
+/// usually do after finishing saving, when the RAM was not written for `WRITE_DEBOUNCE`, or
+/// periodically if the RAM keeps being written. The writes are debounced by `MIN_FLUSH_INTERVAL`.
+struct BatteryFlush {
+    last_flush: Instant,
+    /// The last observed `Cartridge::ram_write_count`.
+    write_count: u64,
+    /// When `write_count` last changed.
+    last_write: Instant,
+}
+impl BatteryFlush {
+    fn new(now: Instant) -> Self {
+        Self {
+            last_flush: now,
+            write_count: 0,
+            last_write: now,
+        }
+    }
+
+    /// If the battery save is due to be written, return it, together with the
+    /// `Cartridge::ram_write_count` when it was taken, to be passed to
+    /// `Cartridge::mark_battery_saved` once it is written.
+    fn poll(&mut self, cartridge: &Cartridge, now: Instant) -> Option<(Vec<u8>, u64)> {
+        if cartridge.ram_write_count() != self.write_count {
+            self.write_count = cartridge.ram_write_count();
+            self.last_write = now;
+        }
+        if !cartridge.has_battery() || !cartridge.battery_dirty() {
+            return None;
+        }
+        let since_flush = now.duration_since(self.last_flush);
+        let due = if cartridge.battery_save_finished() {
+            since_flush >= MIN_FLUSH_INTERVAL
+        } else {
+            now.duration_since(self.last_write) >= WRITE_DEBOUNCE || since_flush >= FLUSH_INTERVAL
+        };
+        if !due {
+            return None;
+        }
+        self.last_flush = now;
+        Some((cartridge.battery_save(), cartridge.ram_write_count()))
+    }
 }
 
 #[derive(Debug)]
@@ -213,6 +700,71 @@ impl std::io::Write for CircularBuffer {
     }
 }
 
+/// The minimum rate of the turbo buttons, in presses per second.
+pub const MIN_TURBO_RATE: f32 = 1.0;
+/// The maximum rate of the turbo buttons, in presses per second. At this rate the button is pressed
+/// in a frame, and released in the next.
+pub const MAX_TURBO_RATE: f32 = 30.0;
+
+/// The rates stepped through in the keys dialog.
+const TURBO_RATE_STEPS: &[f32] = &[
+    1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.5, 10.0, 12.0, 15.0, 20.0, 30.0,
+];
+
+/// The next rate in `TURBO_RATE_STEPS` after `rate`, if `up`, or before it otherwise. If there is
+/// none, the rate is returned clamped.
+pub fn step_turbo_rate(rate: f32, up: bool) -> f32 {
+    let next = if up {
+        TURBO_RATE_STEPS.iter().find(|&&x| x > rate)
+    } else {
+        TURBO_RATE_STEPS.iter().rev().find(|&&x| x < rate)
+    };
+    next.copied()
+        .unwrap_or(rate.clamp(MIN_TURBO_RATE, MAX_TURBO_RATE))
+}
+
+/// Presses and releases the buttons held by a turbo key repeatedly, at a fixed rate.
+///
+/// The rate is counted in emulated frames, not in real time, so the presses are the same at any
+/// emulation speed, and in the joypad timeline of a recording.
+struct Turbo {
+    /// The length of a press and release cycle, in frames.
+    period: usize,
+    /// The frame each button started being held by a turbo key, by its joypad bit.
+    start: [Option<usize>; 8],
+}
+impl Turbo {
+    /// A turbo that presses the buttons `rate` times per second, clamped between `MIN_TURBO_RATE`
+    /// and `MAX_TURBO_RATE`.
+    fn new(rate: f32) -> Self {
+        let frame_rate = CLOCK_SPEED as f32 / FRAME_CYCLES as f32;
+        let rate = rate.clamp(MIN_TURBO_RATE, MAX_TURBO_RATE);
+        Self {
+            period: ((frame_rate / rate).round() as usize).max(2),
+            start: [None; 8],
+        }
+    }
+
+    /// Apply the turbo buttons `held` to the `joypad` of the given frame. Each button is pressed
+    /// in the first half of each period since it started being held, and released in the second.
+    /// A button that is also pressed in `joypad` is kept pressed.
+    fn apply(&mut self, joypad: u8, held: u8, frame: usize) -> u8 {
+        let mut joypad = joypad;
+        for (bit, start) in self.start.iter_mut().enumerate() {
+            if held & (1 << bit) == 0 {
+                *start = None;
+                continue;
+            }
+            // after a rewind the frame can be before the start
+            let start = *start.insert(start.map_or(frame, |x| x.min(frame)));
+            if (frame - start) % self.period < self.period / 2 {
+                joypad &= !(1 << bit);
+            }
+        }
+        joypad
+    }
+}
+
 struct Timeline {
     /// a buffer for transient use.
     buffer: Vec<u8>,
@@ -223,6 +775,9 @@ struct Timeline {
     savestate_timeline: VecDeque<(usize, u64, (usize, usize))>,
     /// Current pressed keys by the user
     current_joypad: u8,
+    /// The buttons currently held by a turbo key, as a bitmask where 1 means held.
+    current_turbo: u8,
+    turbo: Turbo,
     /// Current frame being emulated
     current_frame: usize,
     /// The state of the joypad for each frame
@@ -232,7 +787,7 @@ struct Timeline {
     rewinding: bool,
 }
 impl Timeline {
-    fn new(current_frame: usize, joypad_timeline: Vec<u8>) -> Self {
+    fn new(current_frame: usize, joypad_timeline: Vec<u8>, turbo_rate: f32) -> Self {
         let kib = 2usize.pow(10);
         let mib = 2usize.pow(20);
         Self {
@@ -242,6 +797,8 @@ impl Timeline {
             savestate_buffer: CircularBuffer::new(32 * mib),
             savestate_timeline: VecDeque::new(),
             current_joypad: 0xff,
+            current_turbo: 0,
+            turbo: Turbo::new(turbo_rate),
             rewinding: false,
         }
     }
@@ -269,10 +826,6 @@ impl Timeline {
             .push_back((self.current_frame, gb.clock_count, (start, end)));
     }
 
-    fn last_frame_clock_count(&self) -> Option<u64> {
-        self.savestate_timeline.back().map(|&(_, x, _)| x)
-    }
-
     /// Load the save sate of the last frame in the given `GameBoy`.
     fn load_last_frame(&mut self, gb: &mut GameBoy) -> bool {
         let &(_last_frame, clock_count, range) = if let Some(x) = self.savestate_timeline.back() {
@@ -318,8 +871,11 @@ impl Timeline {
             let diff = self.current_frame - self.joypad_timeline.len();
             self.joypad_timeline.extend((0..diff).map(|_| 0xff));
 
-            self.joypad_timeline.push(self.current_joypad);
-            self.current_joypad
+            let joy = self
+                .turbo
+                .apply(self.current_joypad, self.current_turbo, self.current_frame);
+            self.joypad_timeline.push(joy);
+            joy
         };
         self.save_state(gb);
         self.current_frame += 1;
@@ -336,9 +892,15 @@ impl Timeline {
 
 #[cfg(feature = "audio-engine")]
 struct SoundBackend {
-    _audio_engine: AudioEngine,
-    audio_buffer: Arc<ParkMutex<VecDeque<i16>>>,
-    last_buffer_len: usize,
+    audio_engine: AudioEngine,
+    audio_buffer: Arc<ParkMutex<AudioBuffer>>,
+    /// The underrun and overrun counts of `audio_buffer`, at the last time they were logged.
+    last_underrun: u64,
+    last_overrun: u64,
+    /// Where the fill of `audio_buffer` is published, for the stats overlay.
+    counters: Arc<EmulatorCounters>,
+    /// The gain applied to the samples, from the volume.
+    gain: f32,
 }
 
 pub struct Emulator {
@@ -353,13 +915,18 @@ pub struct Emulator {
     state: EmulatorState,
     // When true, the program will sync the time that passed, and the time that is emulated.
     frame_limit: bool,
+    /// The emulation speed set by `SetSpeed`.
+    speed: f32,
+    /// The speed that replaces `speed` while the fast forward or slow motion key is active.
+    speed_override: Option<SpeedOverride>,
     rewind: bool,
-    // The instant in time that the gameboy supposedly was turned on.
-    // Change when frame_limit is disabled.
-    start_time: Instant,
+    /// Computes the clocks to be emulated each frame, when `frame_limit` is enabled.
+    pacer: FramePacer,
 
     debugger: Arc<ParkMutex<Debugger>>,
 
+    battery_flush: BatteryFlush,
+
     #[cfg(feature = "audio-engine")]
     /// The sound backend.
     sound: Option<SoundBackend>,
@@ -379,11 +946,14 @@ impl Emulator {
         proxy: EventLoopProxy<UserEvent>,
         movie: Option<Vbm>,
         rom: RomFile,
+        counters: Arc<EmulatorCounters>,
     ) -> Self {
         #[cfg(feature = "audio-engine")]
         let sound = match AudioEngine::new() {
             Ok(audio_engine) => {
-                let audio_buffer = Arc::new(ParkMutex::new(VecDeque::<i16>::new()));
+                let latency = crate::config::config().audio_latency;
+                let target_len = AudioBuffer::latency_len(latency, audio_engine.sample_rate());
+                let audio_buffer = Arc::new(ParkMutex::new(AudioBuffer::new(target_len)));
                 let buffer = Buffer {
                     buffer: audio_buffer.clone(),
                     sample_rate: audio_engine.sample_rate(),
@@ -398,9 +968,15 @@ impl Emulator {
                 gb.sound.borrow_mut().sample_frequency = audio_engine.sample_rate() as u64;
 
                 Some(SoundBackend {
-                    _audio_engine: audio_engine,
+                    audio_engine,
                     audio_buffer,
-                    last_buffer_len: 0,
+                    last_underrun: 0,
+                    last_overrun: 0,
+                    counters: counters.clone(),
+                    gain: {
+                        let config = crate::config::config();
+                        volume_gain(config.volume, config.muted)
+                    },
                 })
             }
             Err(e) => {
@@ -428,6 +1004,7 @@ impl Emulator {
         let joypad = Arc::new(ParkMutex::new(Timeline::new(
             current_frame,
             joypad_timeline,
+            crate::config::config().turbo_rate,
         )));
         {
             let game_boy = &mut gb.lock();
@@ -435,14 +1012,19 @@ impl Emulator {
             let joypad = joypad.clone();
             game_boy.v_blank = Some(Box::new(move |gb| {
                 old.as_mut().map(|x| x(gb));
+                counters.set_clock_count(gb.clock_count);
                 let joypad = &mut *joypad.lock();
                 if !joypad.rewinding {
-                    gb.joypad = joypad.next_frame(gb);
+                    let joypad = joypad.next_frame(gb);
+                    gb.set_joypad(joypad);
                 }
             }));
         }
-        let start_time = Instant::now() - clock_to_duration(gb.lock().clock_count);
-        let this = Self {
+        let speed = crate::config::config()
+            .speed_multiplier
+            .clamp(MIN_SPEED, MAX_SPEED);
+        let pacer = FramePacer::new(gb.lock().clock_count, Instant::now(), speed);
+        let mut this = Self {
             gb,
             proxy,
             joypad,
@@ -450,15 +1032,51 @@ impl Emulator {
             debug: false,
             state: EmulatorState::Idle,
             frame_limit: true,
+            speed,
+            speed_override: None,
             rewind: false,
-            start_time,
+            pacer,
             debugger,
+            battery_flush: BatteryFlush::new(Instant::now()),
             #[cfg(feature = "audio-engine")]
             sound,
         };
+        this.update_sample_frequency();
         this
     }
 
+    /// Set the speed of the audio buffer, that time stretches the sound so it keeps its pitch at
+    /// speeds in `STRETCH_SPEEDS`, and the sample frequency of the sound controller, so that at the
+    /// current speed the audio buffer outputs the sample rate of the audio output in real time.
+    /// Beyond `STRETCH_SPEEDS`, the sample frequency also changes, and the rest of the speed
+    /// changes the pitch, like a tape.
+    fn update_sample_frequency(&mut self) {
+        #[cfg(feature = "audio-engine")]
+        if let Some(sound) = &self.sound {
+            let speed = self.pacer.speed;
+            let stretch = speed.clamp(STRETCH_SPEEDS.0, STRETCH_SPEEDS.1) as f64;
+            sound.audio_buffer.lock().set_speed(stretch);
+            let sample_rate = sound.audio_engine.sample_rate() as f64;
+            let frequency = (sample_rate * stretch / speed as f64).round() as u64;
+            self.gb.lock().sound.borrow_mut().sample_frequency = frequency;
+        }
+    }
+
+    /// Apply `speed`, or `speed_override` if any, to the frame pacer, the frame limit and the
+    /// sound.
+    fn update_speed(&mut self) {
+        let (frame_limit, speed) = match self.speed_override {
+            Some(SpeedOverride::Unlimited) => (false, self.speed),
+            Some(SpeedOverride::Speed(speed)) => (true, speed),
+            None => (true, self.speed),
+        };
+        self.frame_limit = frame_limit;
+        // the pacer restarts from now, so the emulation doesn't jump after running unlimited
+        let clock_count = self.gb.lock().clock_count;
+        self.pacer.set_speed(speed, clock_count, Instant::now());
+        self.update_sample_frequency();
+    }
+
     fn set_state(&mut self, new_state: EmulatorState) {
         if self.state == EmulatorState::Idle {
             self.proxy.send_event(UserEvent::EmulatorStarted).unwrap();
@@ -496,72 +1114,169 @@ impl Emulator {
 
         log::info!("exiting emulator thread");
 
+        self.save_ram();
+        self.save_trace();
+    }
+
+    /// Save the disassembly trace, to be reloaded the next time the same rom is opened.
+    fn save_trace(&mut self) {
+        let gb = self.gb.lock();
+        let hash = RomHash::from_bytes(&gb.cartridge.rom);
+        crate::rom_loading::save_trace(&gb, &hash);
+    }
+
+    /// Write the battery save of the cartridge, if it has a battery.
+    fn save_ram(&mut self) {
+        let gb = self.gb.lock();
+        if !gb.cartridge.has_battery() {
+            log::info!("cartridge has no battery, skipping saving game ram data");
+            return;
+        }
         log::info!("saving game ram data... ");
-        match self.rom.save_ram_data(&mut self.gb.lock().cartridge.ram) {
-            Ok(_) => log::info!("save success"),
+        let data = gb.cartridge.battery_save();
+        let write_count = gb.cartridge.ram_write_count();
+        drop(gb);
+        self.write_battery_save(data, write_count);
+    }
+
+    /// Write the battery save, taken when the RAM had the given write count, and mark it as saved
+    /// if it succeeds. In wasm the write to the IndexedDB finishes asynchronously, after this
+    /// returns.
+    fn write_battery_save(&self, data: Vec<u8>, write_count: u64) {
+        #[cfg(not(target_arch = "wasm32"))]
+        match self.rom.save_ram_data(&data) {
+            Ok(_) => {
+                log::info!("save success");
+                self.gb.lock().cartridge.mark_battery_saved(write_count);
+            }
             Err(x) => log::error!("saving failed: {}", x),
         }
+        #[cfg(target_arch = "wasm32")]
+        {
+            let (rom, gb) = (self.rom.clone(), self.gb.clone());
+            wasm_bindgen_futures::spawn_local(async move {
+                match rom.save_ram_data(&data).await {
+                    Ok(_) => {
+                        log::info!("save success");
+                        gb.lock().cartridge.mark_battery_saved(write_count);
+                    }
+                    Err(x) => log::error!("saving failed: {}", x),
+                }
+            });
+        }
+    }
+
+    fn save_state(&self) -> Vec<u8> {
+        let mut state = Vec::new();
+        self.gb.lock().save_state(&mut state).unwrap();
+        state
+    }
+
+    /// Load the given save state. If it fails, the current state is kept.
+    fn load_state(&mut self, state: Vec<u8>) {
+        let mut gb = self.gb.lock();
+
+        let mut old_state = Vec::new();
+        gb.save_state(&mut old_state).unwrap();
+
+        match gb.load_state(&mut state.as_slice()) {
+            Ok(_) => {
+                log::info!("load state")
+            }
+            Err(e) => {
+                log::error!("error loading save state: {}", e);
+                // restore current state
+                gb.load_state(&mut old_state.as_slice()).unwrap();
+            }
+        }
+        self.pacer.reset(gb.clock_count, Instant::now());
+        drop(gb);
+        self.proxy.send_event(UserEvent::EmulatorPaused).unwrap();
     }
 
     /// Return true if should terminate event_loop.
     pub fn handle_event(&mut self, event: EmulatorEvent) -> bool {
         use EmulatorEvent::*;
         match event {
-            SaveRam => {
-                log::info!("saving game ram data... ");
-                match self.rom.save_ram_data(&mut self.gb.lock().cartridge.ram) {
-                    Ok(_) => log::info!("save success"),
-                    Err(x) => log::error!("saving failed: {}", x),
+            SaveRam => self.save_ram(),
+            FlushRam => {
+                if self.gb.lock().cartridge.battery_dirty() {
+                    self.save_ram();
                 }
             }
             SaveState => {
                 log::info!("save state");
-                let mut state = Vec::new();
-                self.gb.lock().save_state(&mut state).unwrap();
+                let state = self.save_state();
+                #[cfg(not(target_arch = "wasm32"))]
                 match self.rom.save_state(&state) {
                     Ok(_) => {}
                     Err(e) => log::error!("error saving state: {}", e),
                 }
+                #[cfg(target_arch = "wasm32")]
+                {
+                    let rom = self.rom.clone();
+                    wasm_bindgen_futures::spawn_local(async move {
+                        match rom.save_state(&state).await {
+                            Ok(_) => {}
+                            Err(e) => log::error!("error saving state: {}", e),
+                        }
+                    });
+                }
             }
+            #[cfg(not(target_arch = "wasm32"))]
             LoadState => {
                 match self.rom.load_state() {
-                    Ok(state) => {
-                        let mut gb = self.gb.lock();
-
-                        let mut old_state = Vec::new();
-                        gb.save_state(&mut old_state).unwrap();
-
-                        match gb.load_state(&mut state.as_slice()) {
-                            Ok(_) => {
-                                log::info!("load state")
-                            }
-                            Err(_) => {
-                                log::error!("error loading save state: save state is malformatted");
-                                // restore current state
-                                gb.load_state(&mut old_state.as_slice()).unwrap();
-                            }
-                        }
-                        self.start_time = recompute_start_time(gb.clock_count);
-                        drop(gb);
-                        self.proxy.send_event(UserEvent::EmulatorPaused).unwrap();
-                    }
+                    Ok(state) => self.load_state(state),
                     Err(e) => log::error!("error loading saved state: {}", e),
                 };
             }
+            // the state is read asynchronously, and sent back through `UserEvent::StateLoaded`
+            #[cfg(target_arch = "wasm32")]
+            LoadState => {
+                let (rom, proxy) = (self.rom.clone(), self.proxy.clone());
+                wasm_bindgen_futures::spawn_local(async move {
+                    match rom.load_state().await {
+                        Ok(state) => proxy.send_event(UserEvent::StateLoaded(state)).unwrap(),
+                        Err(e) => log::error!("error loading saved state: {}", e),
+                    }
+                });
+            }
+            #[cfg(all(
+                feature = "rfd",
+                not(any(target_arch = "wasm32", target_os = "android"))
+            ))]
+            ExportState(path) => {
+                let state = self.save_state();
+                match std::fs::write(&path, state) {
+                    Ok(_) => log::info!("exported save state to '{}'", path.display()),
+                    Err(e) => log::error!("error exporting state to '{}': {}", path.display(), e),
+                }
+            }
+            #[cfg(any(
+                target_arch = "wasm32",
+                all(feature = "rfd", not(target_os = "android"))
+            ))]
+            ImportState(state) => self.load_state(state),
+            #[cfg(not(any(target_arch = "wasm32", target_os = "android")))]
+            Screenshot => {
+                let pixels = self.gb.lock().ppu.borrow().screen_rgb();
+                match self.rom.save_screenshot(&pixels) {
+                    Ok(path) => log::info!("saved screenshot to '{}'", path.display()),
+                    Err(e) => log::error!("error saving screenshot: {}", e),
+                }
+            }
             Kill => return true,
             RunFrame => {
                 if !self.debug {
                     self.set_state(EmulatorState::RunNoBreak);
                 }
             }
-            FrameLimit(value) => {
-                if self.frame_limit == value {
+            OverrideSpeed(value) => {
+                if self.speed_override == value {
                     return false;
                 }
-                self.frame_limit = value;
-                if self.frame_limit {
-                    self.start_time = recompute_start_time(self.gb.lock().clock_count);
-                }
+                self.speed_override = value;
+                self.update_speed();
             }
             Rewind(value) => {
                 if self.rewind == value {
@@ -574,12 +1289,36 @@ impl Emulator {
                     joypad.joypad_timeline.clear();
                 }
                 if !self.rewind {
-                    self.start_time = recompute_start_time(self.gb.lock().clock_count);
+                    self.pacer.reset(self.gb.lock().clock_count, Instant::now());
                 }
             }
             SetJoypad(joypad) => {
                 self.joypad.lock().current_joypad = joypad;
             }
+            SetTurbo(turbo) => {
+                self.joypad.lock().current_turbo = turbo;
+            }
+            SetSpeed(speed) => {
+                self.speed = speed.clamp(MIN_SPEED, MAX_SPEED);
+                self.update_speed();
+                log::info!("emulation speed: {}x", self.speed);
+                crate::config::config().speed_multiplier = self.speed;
+            }
+            SetVolume { volume, muted } => {
+                let volume = volume.clamp(0.0, MAX_VOLUME);
+                log::info!(
+                    "volume: {:.0}%{}",
+                    volume * 100.0,
+                    if muted { " (muted)" } else { "" }
+                );
+                #[cfg(feature = "audio-engine")]
+                if let Some(sound) = &mut self.sound {
+                    sound.gain = volume_gain(volume, muted);
+                }
+                let mut config = crate::config::config();
+                config.volume = volume;
+                config.muted = muted;
+            }
             Debug(value) => {
                 if self.frame_limit == value {
                     return false;
@@ -600,30 +1339,15 @@ impl Emulator {
                     self.set_state(EmulatorState::Idle);
                 }
             }
-            StepBack => {
+            StepBack(count) => {
                 if self.debug {
-                    let mut gb = self.gb.lock();
-                    let mut joypad = self.joypad.lock();
-                    if let Some(last_clock_count) = joypad.last_frame_clock_count() {
-                        let clock_count = gb.clock_count;
-                        // currently, the maximum number of clocks that interpret_op
-                        // elapses is 24, so if the last_frame_clock_count is recent than
-                        // that, this could be after the last instruction. So pop it.
-                        if last_clock_count > clock_count - 24 {
-                            joypad.pop_last_frame();
-                        }
-                        if joypad.load_last_frame(&mut *gb) {
-                            drop(joypad);
-                            drop(gb);
-                            {
-                                let debugger = &mut *self.debugger.lock();
-                                debugger.target_clock = Some(debugger.last_op_clock);
-                            }
-                            self.set_state(EmulatorState::Run);
+                    {
+                        let gb = &mut *self.gb.lock();
+                        if let Err(err) = self.debugger.lock().step_back(gb, count) {
+                            log::warn!("{}", err);
                         }
-                    } else {
-                        log::warn!("there is no last frame");
                     }
+                    self.set_state(EmulatorState::Idle);
                 }
             }
             Run => {
@@ -641,6 +1365,13 @@ impl Emulator {
                 // This will send EmulatorUpdated to the gui
                 self.proxy.send_event(UserEvent::EmulatorPaused).unwrap();
             }
+            PowerCycle(clear_ram) => {
+                self.gb.lock().power_cycle(clear_ram);
+                log::info!("power cycle (clear ram: {})", clear_ram);
+                self.state = EmulatorState::Idle;
+                // This will send EmulatorUpdated to the gui
+                self.proxy.send_event(UserEvent::EmulatorPaused).unwrap();
+            }
             Pause => {
                 self.debug = true;
             }
@@ -651,7 +1382,25 @@ impl Emulator {
         false
     }
 
+    /// Write the battery save if it is due. Only done when running in its own thread, to not block
+    /// the UI thread with file writes. The GameBoy is not locked while writing, and the save is
+    /// only marked as saved if the write succeeds, so a failed one is retried.
+    fn flush_battery_save(&mut self) {
+        if !cfg!(feature = "threads") {
+            return;
+        }
+        let due = {
+            let gb = self.gb.lock();
+            self.battery_flush.poll(&gb.cartridge, Instant::now())
+        };
+        if let Some((data, write_count)) = due {
+            log::info!("flushing game ram data");
+            self.write_battery_save(data, write_count);
+        }
+    }
+
     pub fn poll(&mut self) -> Control {
+        self.flush_battery_save();
         match self.state {
             EmulatorState::Idle => {}
             EmulatorState::Run => {
@@ -661,7 +1410,30 @@ impl Emulator {
                     let mut debugger = self.debugger.lock();
                     use RunResult::*;
                     match debugger.run_for(&mut *gb, CLOCK_SPEED / 600) {
-                        ReachBreakpoint | ReachTargetAddress | ReachTargetClock => {
+                        ReachWatchpoint => {
+                            if let Some(hit) = debugger.last_watchpoint_hit {
+                                log::info!("watchpoint: {}", hit);
+                            }
+                            drop(gb);
+                            drop(debugger);
+                            self.set_state(EmulatorState::Idle);
+                            return Control::Wait;
+                        }
+                        result @ (ReachBreakpoint
+                        | ReachTargetAddress
+                        | ReachTargetClock
+                        | ReachTargetDepth
+                        | ReachTargetLine
+                        | ReachTargetInstruction
+                        | ReachTargetFrame
+                        | CpuLocked) => {
+                            log::info!(
+                                "stopped: {} (pc: {:04x}, ly: {}, clock: {})",
+                                result,
+                                gb.cpu.pc,
+                                gb.ppu.borrow().ly,
+                                gb.clock_count
+                            );
                             drop(gb);
                             drop(debugger);
                             self.set_state(EmulatorState::Idle);
@@ -690,15 +1462,7 @@ impl Emulator {
                 } else if self.frame_limit {
                     let mut gb = self.gb.lock();
                     let mut inter = Interpreter(&mut *gb);
-                    let elapsed = self.start_time.elapsed();
-                    let mut target_clock = CLOCK_SPEED * elapsed.as_secs()
-                        + (CLOCK_SPEED as f64 * (elapsed.subsec_nanos() as f64 * 1e-9)) as u64;
-
-                    // make sure that the target_clock don't increase indefinitely if the program can't keep up.
-                    if target_clock > inter.0.clock_count + CLOCK_SPEED / 30 {
-                        target_clock = inter.0.clock_count + CLOCK_SPEED / 30;
-                        self.start_time = recompute_start_time(inter.0.clock_count);
-                    }
+                    let target_clock = self.pacer.target_clock(inter.0.clock_count, Instant::now());
 
                     while inter.0.clock_count < target_clock {
                         inter.interpret_op();
@@ -740,28 +1504,30 @@ impl Emulator {
         #[cfg(feature = "audio-engine")]
         if let Some(SoundBackend {
             audio_buffer,
-            last_buffer_len,
+            last_underrun,
+            last_overrun,
+            counters,
+            gain,
             ..
         }) = &mut self.sound
         {
+            let mut samples: Vec<i16> = buffer.iter().map(|&x| (x as i16 - 128) * 30).collect();
+            crate::audio_buffer::apply_gain(&mut samples, *gain);
             let mut lock = audio_buffer.lock();
-            if lock.len() == 0 {
-                // if the buffer is empty, add zeros to increase it
-                lock.extend((0..1600 * 5).map(|_| 0));
+            lock.push(&samples);
+            counters.set_audio_fill(lock.len(), lock.target_len());
+
+            if lock.underrun_samples() != *last_underrun || lock.overrun_samples() != *last_overrun
+            {
+                log::debug!(
+                    "audio underrun: {} samples, overrun: {} samples, buffer len: {}",
+                    lock.underrun_samples() - *last_underrun,
+                    lock.overrun_samples() - *last_overrun,
+                    lock.len()
+                );
+                *last_underrun = lock.underrun_samples();
+                *last_overrun = lock.overrun_samples();
             }
-            lock.extend(buffer.iter().map(|&x| (x as i16 - 128) * 30));
-
-            *last_buffer_len = lock.len();
         }
     }
 }
-
-fn clock_to_duration(clock_count: u64) -> Duration {
-    let secs = clock_count / CLOCK_SPEED;
-    let nanos = (clock_count % CLOCK_SPEED) * 1_000_000_000 / CLOCK_SPEED;
-    Duration::new(secs, nanos as u32)
-}
-
-fn recompute_start_time(clock_count: u64) -> Instant {
-    Instant::now() - clock_to_duration(clock_count)
-}
@@ -14,6 +14,16 @@ pub trait Event: Clone + 'static {}
 pub struct Debug(pub bool);
 impl Event for Debug {}
 
+/// The debug overlay was shown or hidden.
+#[derive(Clone, Copy)]
+pub struct DebugOverlay(pub bool);
+impl Event for DebugOverlay {}
+
+/// The stats overlay was shown or hidden.
+#[derive(Clone, Copy)]
+pub struct StatsOverlay(pub bool);
+impl Event for StatsOverlay {}
+
 #[derive(Clone, Copy)]
 pub struct FrameUpdated;
 impl Event for FrameUpdated {}
@@ -30,8 +40,18 @@ impl Event for BreakpointsUpdated {}
 pub struct WatchsUpdated;
 impl Event for WatchsUpdated {}
 
+#[derive(Clone, Copy)]
+pub struct RamSearchUpdated;
+impl Event for RamSearchUpdated {}
+
 pub struct UpdatedRomList;
 
+/// The filter of the rom list was cleared, because the list is being reloaded.
+pub struct FilterCleared;
+
+/// The ages in the rom list must be updated, see `UserEvent::RefreshAges`.
+pub struct RefreshAges;
+
 /// A handle to a registered event callback. When this is dropped, the callback is unregistered.
 pub struct Handle<E: Event> {
     /// The value of the pointer that the callback have.
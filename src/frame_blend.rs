@@ -0,0 +1,74 @@
+/// Blends each frame with the previous one, to emulate the slow response of the LCD.
+///
+/// Some games flicker sprites every other frame to make them look transparent, which on real
+/// hardware is smoothed by the LCD ghosting, but shows as strobing when emulated crisply.
+#[derive(Debug)]
+pub struct FrameBlender {
+    /// The previous frame, before blending.
+    previous: Option<Vec<u8>>,
+    /// The weight of the previous frame, between 0.0 and 1.0.
+    weight: f32,
+}
+
+impl FrameBlender {
+    /// Create a blender with the given weight of the previous frame. The weight is clamped
+    /// between 0.0 (no blending) and 1.0.
+    pub fn new(weight: f32) -> Self {
+        Self {
+            previous: None,
+            weight: weight.clamp(0.0, 1.0),
+        }
+    }
+
+    /// Blend `frame` with the previous frame, in place. `frame` is a RGBA image, after the color
+    /// mapping. The first frame, or a frame of a different size, is left unchanged.
+    pub fn blend(&mut self, frame: &mut [u8]) {
+        let previous = match &mut self.previous {
+            Some(previous) if previous.len() == frame.len() => previous,
+            _ => {
+                self.previous = Some(frame.to_vec());
+                return;
+            }
+        };
+        for (a, b) in frame.iter_mut().zip(previous.iter_mut()) {
+            let current = *a;
+            let x = current as f32 * (1.0 - self.weight) + *b as f32 * self.weight;
+            *a = x.round() as u8;
+            *b = current;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::FrameBlender;
+
+    #[test]
+    fn alternating_frames() {
+        let black = [0, 0, 0, 255].repeat(4);
+        let white = [255, 255, 255, 255].repeat(4);
+
+        let mut blender = FrameBlender::new(0.5);
+        let mut frame = black.clone();
+        blender.blend(&mut frame);
+        assert_eq!(frame, black);
+
+        // the blending is between the unblended frames, so the output don't drift.
+        for i in 0..4 {
+            let mut frame = if i % 2 == 0 {
+                white.clone()
+            } else {
+                black.clone()
+            };
+            blender.blend(&mut frame);
+            assert_eq!(frame, [128, 128, 128, 255].repeat(4));
+        }
+
+        let mut blender = FrameBlender::new(0.0);
+        let mut frame = black.clone();
+        blender.blend(&mut frame);
+        let mut frame = white.clone();
+        blender.blend(&mut frame);
+        assert_eq!(frame, white);
+    }
+}
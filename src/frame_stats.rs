@@ -0,0 +1,180 @@
+use std::sync::{
+    atomic::{AtomicU64, AtomicUsize, Ordering},
+    Arc,
+};
+
+use gameroy::consts::CLOCK_SPEED;
+use instant::Instant;
+
+/// The number of presented frames averaged by `FrameStats`.
+const WINDOW: usize = 60;
+
+/// The number of clock cycles in a emulated frame.
+const FRAME_CLOCK_COUNT: u64 = 154 * 456;
+
+/// Counters written by the emulator thread, and read by `FrameStats` in the UI thread.
+#[derive(Debug, Default)]
+pub struct EmulatorCounters {
+    /// The clock count of the GameBoy at the last V-Blank.
+    clock_count: AtomicU64,
+    /// The number of samples in the audio buffer, after the last push.
+    audio_len: AtomicUsize,
+    /// The number of samples the audio buffer tries to keep. 0 if there is no audio output.
+    audio_target_len: AtomicUsize,
+}
+
+impl EmulatorCounters {
+    pub fn set_clock_count(&self, clock_count: u64) {
+        self.clock_count.store(clock_count, Ordering::Relaxed);
+    }
+
+    pub fn set_audio_fill(&self, len: usize, target_len: usize) {
+        self.audio_len.store(len, Ordering::Relaxed);
+        self.audio_target_len.store(target_len, Ordering::Relaxed);
+    }
+}
+
+/// A presented frame.
+#[derive(Debug, Clone, Copy, Default)]
+struct Sample {
+    /// The time since the previous presented frame, in seconds.
+    time: f64,
+    /// The number of frames emulated since the previous presented frame.
+    emulated_frames: f64,
+}
+
+/// The moving average of the real and emulated frame rates, over the last `WINDOW` presented
+/// frames.
+///
+/// The real frame rate is how many frames are presented to the screen each second, and the
+/// emulated one is how many GameBoy frames are emulated in the same time. They only differ when
+/// the emulation is not limited to the real speed, or can't keep up with it. Nothing is allocated
+/// per frame.
+#[derive(Debug)]
+pub struct FrameStats {
+    counters: Arc<EmulatorCounters>,
+    samples: [Sample; WINDOW],
+    /// The index of the next sample to be written.
+    next: usize,
+    /// The number of samples written, up to `WINDOW`.
+    len: usize,
+    /// The instant and clock count of the last presented frame.
+    last: Option<(Instant, u64)>,
+}
+
+impl FrameStats {
+    pub fn new(counters: Arc<EmulatorCounters>) -> Self {
+        Self {
+            counters,
+            samples: [Sample::default(); WINDOW],
+            next: 0,
+            len: 0,
+            last: None,
+        }
+    }
+
+    /// Record a frame presented at `now`.
+    pub fn frame(&mut self, now: Instant) {
+        let clock_count = self.counters.clock_count.load(Ordering::Relaxed);
+        if let Some((last_time, last_clock)) = self.last {
+            let time = now.duration_since(last_time).as_secs_f64();
+            self.push(time, clock_count.saturating_sub(last_clock));
+        }
+        self.last = Some((now, clock_count));
+    }
+
+    fn push(&mut self, time: f64, clock_delta: u64) {
+        self.samples[self.next] = Sample {
+            time,
+            emulated_frames: clock_delta as f64 / FRAME_CLOCK_COUNT as f64,
+        };
+        self.next = (self.next + 1) % WINDOW;
+        self.len = (self.len + 1).min(WINDOW);
+    }
+
+    fn total_time(&self) -> f64 {
+        self.samples[..self.len].iter().map(|x| x.time).sum()
+    }
+
+    /// The number of frames presented per second.
+    pub fn real_fps(&self) -> f64 {
+        let time = self.total_time();
+        if time > 0.0 {
+            self.len as f64 / time
+        } else {
+            0.0
+        }
+    }
+
+    /// The number of GameBoy frames emulated per second.
+    pub fn emulated_fps(&self) -> f64 {
+        let time = self.total_time();
+        if time > 0.0 {
+            self.samples[..self.len]
+                .iter()
+                .map(|x| x.emulated_frames)
+                .sum::<f64>()
+                / time
+        } else {
+            0.0
+        }
+    }
+
+    /// The average time between presented frames, in milliseconds.
+    pub fn frame_time_ms(&self) -> f64 {
+        if self.len > 0 {
+            self.total_time() / self.len as f64 * 1000.0
+        } else {
+            0.0
+        }
+    }
+
+    /// The number of samples in the audio buffer, and the number it tries to keep. None if there
+    /// is no audio output.
+    pub fn audio_fill(&self) -> Option<(usize, usize)> {
+        let target_len = self.counters.audio_target_len.load(Ordering::Relaxed);
+        let len = self.counters.audio_len.load(Ordering::Relaxed);
+        (target_len != 0).then(|| (len, target_len))
+    }
+
+    /// The speed of the emulation relative to the real GameBoy, from the emulated frame rate.
+    pub fn speed(&self) -> f64 {
+        self.emulated_fps() * FRAME_CLOCK_COUNT as f64 / CLOCK_SPEED as f64
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use super::{FrameStats, FRAME_CLOCK_COUNT, WINDOW};
+
+    #[test]
+    fn moving_average() {
+        let mut stats = FrameStats::new(Arc::default());
+        assert_eq!(stats.real_fps(), 0.0);
+        assert_eq!(stats.emulated_fps(), 0.0);
+
+        // presenting at 50 fps, emulating 2 frames per presented frame
+        for _ in 0..WINDOW {
+            stats.push(0.02, 2 * FRAME_CLOCK_COUNT);
+        }
+        assert!((stats.real_fps() - 50.0).abs() < 1e-9);
+        assert!((stats.emulated_fps() - 100.0).abs() < 1e-9);
+        assert!((stats.frame_time_ms() - 20.0).abs() < 1e-9);
+
+        // the old samples leave the window
+        for _ in 0..WINDOW / 2 {
+            stats.push(0.01, FRAME_CLOCK_COUNT);
+        }
+        let time = 0.02 * (WINDOW / 2) as f64 + 0.01 * (WINDOW / 2) as f64;
+        assert!((stats.real_fps() - WINDOW as f64 / time).abs() < 1e-9);
+        let frames = 2.0 * (WINDOW / 2) as f64 + (WINDOW / 2) as f64;
+        assert!((stats.emulated_fps() - frames / time).abs() < 1e-9);
+        for _ in 0..WINDOW {
+            stats.push(0.01, FRAME_CLOCK_COUNT);
+        }
+        assert!((stats.real_fps() - 100.0).abs() < 1e-9);
+        assert!((stats.emulated_fps() - 100.0).abs() < 1e-9);
+    }
+}
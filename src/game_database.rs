@@ -0,0 +1,339 @@
+//! Identification of roms by their hash, using a No-Intro style .dat file, which gives proper names
+//! to the games, instead of the truncated title in the cartridge header.
+
+use std::collections::HashMap;
+
+use once_cell::sync::OnceCell;
+
+use crate::config::config;
+
+/// The CRC32 and SHA-1 of a rom file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct RomHash {
+    pub crc32: u32,
+    pub sha1: [u8; 20],
+}
+impl RomHash {
+    pub fn from_bytes(data: &[u8]) -> Self {
+        let mut hasher = RomHasher::new();
+        hasher.update(data);
+        hasher.finish()
+    }
+
+    /// The SHA-1 as a lowercase hexadecimal string.
+    pub fn sha1_hex(&self) -> String {
+        self.sha1.iter().map(|x| format!("{:02x}", x)).collect()
+    }
+}
+
+/// Computes a `RomHash` incrementally, so a rom file can be hashed without loading it entirely.
+pub struct RomHasher {
+    crc: flate2::Crc,
+    sha1: Sha1,
+}
+impl RomHasher {
+    pub fn new() -> Self {
+        Self {
+            crc: flate2::Crc::new(),
+            sha1: Sha1::new(),
+        }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        self.crc.update(data);
+        self.sha1.update(data);
+    }
+
+    pub fn finish(self) -> RomHash {
+        RomHash {
+            crc32: self.crc.sum(),
+            sha1: self.sha1.finish(),
+        }
+    }
+}
+impl Default for RomHasher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+struct Sha1 {
+    state: [u32; 5],
+    block: [u8; 64],
+    block_len: usize,
+    len: u64,
+}
+impl Sha1 {
+    fn new() -> Self {
+        Self {
+            state: [0x67452301, 0xEFCDAB89, 0x98BADCFE, 0x10325476, 0xC3D2E1F0],
+            block: [0; 64],
+            block_len: 0,
+            len: 0,
+        }
+    }
+
+    fn update(&mut self, mut data: &[u8]) {
+        self.len += data.len() as u64;
+        while !data.is_empty() {
+            let n = (64 - self.block_len).min(data.len());
+            self.block[self.block_len..self.block_len + n].copy_from_slice(&data[..n]);
+            self.block_len += n;
+            data = &data[n..];
+            if self.block_len == 64 {
+                self.process_block();
+                self.block_len = 0;
+            }
+        }
+    }
+
+    fn finish(mut self) -> [u8; 20] {
+        let bit_len = self.len.wrapping_mul(8);
+        self.update(&[0x80]);
+        while self.block_len != 56 {
+            self.update(&[0]);
+        }
+        self.update(&bit_len.to_be_bytes());
+
+        let mut digest = [0; 20];
+        for (i, x) in self.state.iter().enumerate() {
+            digest[i * 4..i * 4 + 4].copy_from_slice(&x.to_be_bytes());
+        }
+        digest
+    }
+
+    fn process_block(&mut self) {
+        let mut w = [0u32; 80];
+        for (w, b) in w.iter_mut().zip(self.block.chunks(4)) {
+            *w = u32::from_be_bytes([b[0], b[1], b[2], b[3]]);
+        }
+        for i in 16..80 {
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e] = self.state;
+        for (i, &w) in w.iter().enumerate() {
+            let (f, k) = match i {
+                0..=19 => ((b & c) | (!b & d), 0x5A827999),
+                20..=39 => (b ^ c ^ d, 0x6ED9EBA1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8F1BBCDC),
+                _ => (b ^ c ^ d, 0xCA62C1D6),
+            };
+            let temp = a
+                .rotate_left(5)
+                .wrapping_add(f)
+                .wrapping_add(e)
+                .wrapping_add(k)
+                .wrapping_add(w);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = temp;
+        }
+
+        for (s, x) in self.state.iter_mut().zip([a, b, c, d, e]) {
+            *s = s.wrapping_add(x);
+        }
+    }
+}
+
+/// The game names of a No-Intro style .dat file, indexed by the hashes of their roms.
+#[derive(Default, Debug)]
+pub struct GameDatabase {
+    by_sha1: HashMap<[u8; 20], String>,
+    by_crc32: HashMap<u32, String>,
+}
+impl GameDatabase {
+    /// Parse a .dat file, in the XML format used by No-Intro. Only the `name` of each `game`, and
+    /// the `crc` and `sha1` of its `rom`s are used.
+    pub fn parse(dat: &str) -> Result<Self, String> {
+        let mut database = Self::default();
+        let mut game_name: Option<String> = None;
+
+        let mut rest = dat;
+        while let Some(start) = rest.find('<') {
+            rest = &rest[start + 1..];
+            let end = rest
+                .find('>')
+                .ok_or_else(|| "unexpected end of file, inside a tag".to_string())?;
+            let tag = &rest[..end];
+            rest = &rest[end + 1..];
+
+            let tag_name = tag.split(|c: char| c.is_whitespace()).next().unwrap_or("");
+            match tag_name {
+                "game" | "machine" => {
+                    let name = attribute(tag, "name")
+                        .ok_or_else(|| format!("game without a name: <{}>", tag))?;
+                    game_name = Some(name);
+                }
+                "/game" | "/machine" => game_name = None,
+                "rom" => {
+                    let name = match &game_name {
+                        Some(x) => x,
+                        None => return Err(format!("rom outside of a game: <{}>", tag)),
+                    };
+                    if let Some(crc) = attribute(tag, "crc") {
+                        let crc = u32::from_str_radix(&crc, 16)
+                            .map_err(|_| format!("invalid crc '{}' in '{}'", crc, name))?;
+                        database.by_crc32.insert(crc, name.clone());
+                    }
+                    if let Some(sha1) = attribute(tag, "sha1") {
+                        let sha1 = parse_sha1(&sha1)
+                            .ok_or_else(|| format!("invalid sha1 '{}' in '{}'", sha1, name))?;
+                        database.by_sha1.insert(sha1, name.clone());
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        Ok(database)
+    }
+
+    /// The number of games with a rom hash.
+    pub fn game_count(&self) -> usize {
+        self.by_crc32.len().max(self.by_sha1.len())
+    }
+
+    /// The name of the game with the given hash. The SHA-1 is preferred, falling back to the CRC32
+    /// for databases that don't have it.
+    pub fn lookup(&self, hash: &RomHash) -> Option<&str> {
+        self.by_sha1
+            .get(&hash.sha1)
+            .or_else(|| self.by_crc32.get(&hash.crc32))
+            .map(|x| x.as_str())
+    }
+}
+
+/// Get the value of the attribute `name` in the content of a XML tag.
+fn attribute(tag: &str, name: &str) -> Option<String> {
+    let mut rest = tag;
+    loop {
+        let eq = rest.find('=')?;
+        let key = rest[..eq].trim();
+        let key = key.rsplit(|c: char| c.is_whitespace()).next().unwrap_or("");
+        let value = rest[eq + 1..].trim_start();
+        let quote = value.chars().next()?;
+        if quote != '"' && quote != '\'' {
+            return None;
+        }
+        let len = value[1..].find(quote)?;
+        if key == name {
+            return Some(unescape(&value[1..1 + len]));
+        }
+        rest = &value[len + 2..];
+    }
+}
+
+fn unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+fn parse_sha1(hex: &str) -> Option<[u8; 20]> {
+    if hex.len() != 40 {
+        return None;
+    }
+    let mut sha1 = [0; 20];
+    for (i, x) in sha1.iter_mut().enumerate() {
+        *x = u8::from_str_radix(hex.get(i * 2..i * 2 + 2)?, 16).ok()?;
+    }
+    Some(sha1)
+}
+
+/// The database at `Config::game_database`, if any. It is loaded on the first call, and cached
+/// after that.
+pub fn game_database() -> Option<&'static GameDatabase> {
+    static DATABASE: OnceCell<Option<GameDatabase>> = OnceCell::new();
+    DATABASE
+        .get_or_init(|| {
+            let path = config().game_database.clone()?;
+            let path = crate::config::normalize_config_path(path);
+            let dat = std::fs::read_to_string(&path)
+                .map_err(|e| log::error!("error reading '{}': {}", path.display(), e))
+                .ok()?;
+            let database = GameDatabase::parse(&dat)
+                .map_err(|e| log::error!("error parsing '{}': {}", path.display(), e))
+                .ok()?;
+            log::info!(
+                "loaded {} games from '{}'",
+                database.game_count(),
+                path.display()
+            );
+            Some(database)
+        })
+        .as_ref()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const DAT: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE datafile PUBLIC "-//Logiqx//DTD ROM Management Datafile//EN" "http://www.logiqx.com/Dats/datafile.dtd">
+<datafile>
+	<header>
+		<name>Nintendo - Game Boy</name>
+		<description>Nintendo - Game Boy</description>
+	</header>
+	<game name="Test Game (World) (Rev 1)">
+		<description>Test Game (World) (Rev 1)</description>
+		<rom name="Test Game (World) (Rev 1).gb" size="4" crc="ED82CD11" md5="e2fc714c4727ee9395f324cd2e7f331f" sha1="81fe8bfe87576c3ecb22426f8e57847382917acf"/>
+	</game>
+	<game name="Tom &amp; Jerry (USA)">
+		<description>Tom &amp; Jerry (USA)</description>
+		<rom name="Tom &amp; Jerry (USA).gb" size="32768" crc="B2FF246B"/>
+	</game>
+</datafile>
+"#;
+
+    #[test]
+    fn hash() {
+        let hash = RomHash::from_bytes(b"abc");
+        assert_eq!(hash.crc32, 0x352441C2);
+        assert_eq!(hash.sha1_hex(), "a9993e364706816aba3e25717850c26c9cd0d89d");
+
+        let hash = RomHash::from_bytes(b"");
+        assert_eq!(hash.crc32, 0);
+        assert_eq!(hash.sha1_hex(), "da39a3ee5e6b4b0d3255bfef95601890afd80709");
+
+        // hashing in chunks gives the same result
+        let data = (0..1000).map(|x| (x * 7) as u8).collect::<Vec<u8>>();
+        let mut hasher = RomHasher::new();
+        for chunk in data.chunks(63) {
+            hasher.update(chunk);
+        }
+        assert_eq!(hasher.finish(), RomHash::from_bytes(&data));
+    }
+
+    #[test]
+    fn lookup() {
+        let database = GameDatabase::parse(DAT).unwrap();
+        assert_eq!(database.game_count(), 2);
+
+        // match by sha1
+        let hash = RomHash::from_bytes(b"abcd");
+        assert_eq!(database.lookup(&hash), Some("Test Game (World) (Rev 1)"));
+
+        // match by crc32, for entries without sha1
+        let rom = (0..0x8000).map(|x| (x >> 8) as u8).collect::<Vec<u8>>();
+        let hash = RomHash::from_bytes(&rom);
+        assert_eq!(hash.crc32, 0xB2FF246B);
+        assert_eq!(database.lookup(&hash), Some("Tom & Jerry (USA)"));
+
+        let hash = RomHash::from_bytes(b"not in the database");
+        assert_eq!(database.lookup(&hash), None);
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert!(GameDatabase::parse("<game><rom crc=\"00\"/></game>").is_err());
+        assert!(GameDatabase::parse("<game name=\"a\"><rom crc=\"xyz\"/></game>").is_err());
+        assert!(GameDatabase::parse("<rom crc=\"00000000\"/>").is_err());
+        assert!(GameDatabase::parse("<game name=\"a\"").is_err());
+    }
+}
@@ -0,0 +1,650 @@
+use std::{borrow::Cow, cmp::Ordering, collections::BTreeMap, fmt, hash::Hash, str::FromStr};
+
+use serde::{
+    de::{Error, IntoDeserializer, MapAccess, Visitor},
+    ser::SerializeMap,
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+use winit::event::VirtualKeyCode;
+
+mod scancodes;
+
+/// A physical key of the keyboard, identified by its scancode, so the bindings don't depend on the
+/// keyboard layout. Two keys are equal if they have the same scancode.
+///
+/// In the config, a key is written by its name, for the key in that position in a US layout, like
+/// `"A"`, or by its name and scancode, like `"A 16"`, for a key bound in another layout, or by its
+/// scancode alone, for a key without a name.
+#[derive(Debug, Clone, Copy)]
+pub struct Key {
+    pub scancode: u32,
+    /// The virtual key of the key when it was bound, only used to show it to the user.
+    pub name: Option<VirtualKeyCode>,
+}
+
+impl Key {
+    pub const fn new(scancode: u32, name: Option<VirtualKeyCode>) -> Self {
+        Self { scancode, name }
+    }
+
+    /// The key in the position of `name` in a US layout, if its scancode is known.
+    pub const fn from_name(name: VirtualKeyCode) -> Option<Self> {
+        match scancodes::scancode(name) {
+            Some(scancode) => Some(Self::new(scancode, Some(name))),
+            None => None,
+        }
+    }
+
+    /// Like `from_name`, for the keys of `DEFAULT_KEYMAP`, which are all known.
+    const fn us(name: VirtualKeyCode) -> Self {
+        match Self::from_name(name) {
+            Some(x) => x,
+            None => panic!("missing the scancode of a default key"),
+        }
+    }
+}
+
+impl PartialEq for Key {
+    fn eq(&self, other: &Self) -> bool {
+        self.scancode == other.scancode
+    }
+}
+impl Eq for Key {}
+impl PartialOrd for Key {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl Ord for Key {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.scancode.cmp(&other.scancode)
+    }
+}
+impl Hash for Key {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.scancode.hash(state)
+    }
+}
+
+/// The name of the key shown to the user, like "A", or "Scancode 16" if it has no name.
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.name {
+            Some(name) => write!(f, "{:?}", name),
+            None => write!(f, "Scancode {}", self.scancode),
+        }
+    }
+}
+
+impl FromStr for Key {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split_whitespace();
+        let (name, scancode) = match (parts.next(), parts.next(), parts.next()) {
+            (Some(name), Some(scancode), None) => (Some(name), Some(scancode)),
+            (Some(x), None, None) if x.bytes().all(|x| x.is_ascii_digit()) => (None, Some(x)),
+            (Some(name), None, None) => (Some(name), None),
+            _ => return Err(format!("invalid key '{}'", s)),
+        };
+        let name = name
+            .map(|name| {
+                VirtualKeyCode::deserialize(name.into_deserializer())
+                    .map_err(|_: serde::de::value::Error| format!("unknown key '{}'", name))
+            })
+            .transpose()?;
+        match (name, scancode) {
+            (name, Some(scancode)) => scancode
+                .parse()
+                .map(|scancode| Key::new(scancode, name))
+                .map_err(|_| format!("invalid scancode in '{}'", s)),
+            (Some(name), None) => Key::from_name(name).ok_or_else(|| {
+                format!(
+                    "the scancode of '{}' is not known, write it as '{:?} <scancode>'",
+                    s, name
+                )
+            }),
+            (None, None) => unreachable!(),
+        }
+    }
+}
+
+impl Serialize for Key {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self.name {
+            Some(name) if Key::from_name(name).map(|x| x.scancode) == Some(self.scancode) => {
+                serializer.collect_str(&format_args!("{:?}", name))
+            }
+            Some(name) => serializer.collect_str(&format_args!("{:?} {}", name, self.scancode)),
+            None => serializer.collect_str(&self.scancode),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Key {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let text = Cow::<str>::deserialize(deserializer)?;
+        text.parse().map_err(D::Error::custom)
+    }
+}
+
+/// A action that can be bound to keys: a button of the joypad, or a emulator action.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Action {
+    Left,
+    Right,
+    Up,
+    Down,
+    A,
+    B,
+    Select,
+    Start,
+    /// Press and release the A button repeatedly, at the `turbo_rate` of the config, while held.
+    TurboA,
+    TurboB,
+    /// Keep the A button pressed, until pressed again.
+    LatchA,
+    LatchB,
+
+    /// Run at the `fast_forward_speed` of the config, while held, or until pressed again if
+    /// `speed_toggle` is set.
+    Speed,
+    /// Run at the `slow_motion_speed` of the config, like `Speed`.
+    SlowMotion,
+    SpeedUp,
+    SpeedDown,
+    SpeedReset,
+    VolumeUp,
+    VolumeDown,
+    Mute,
+    Rewind,
+    SaveState,
+    LoadState,
+    ExportState,
+    ImportState,
+    /// Save the screen to a png, in the `screenshots` folder.
+    Screenshot,
+    Fullscreen,
+    Cheats,
+    /// Pause the game and open the game menu.
+    Menu,
+    DebugOverlay,
+    StatsOverlay,
+
+    OpenDebugger,
+    DebugStep,
+    DebugStepback,
+    DebugRun,
+}
+
+impl Action {
+    /// All actions, in the order they are listed in the key bindings dialog.
+    pub const ALL: [Action; ACTION_COUNT] = {
+        use Action::*;
+        [
+            Left,
+            Right,
+            Up,
+            Down,
+            A,
+            B,
+            Select,
+            Start,
+            TurboA,
+            TurboB,
+            LatchA,
+            LatchB,
+            Speed,
+            SlowMotion,
+            SpeedUp,
+            SpeedDown,
+            SpeedReset,
+            VolumeUp,
+            VolumeDown,
+            Mute,
+            Rewind,
+            SaveState,
+            LoadState,
+            ExportState,
+            ImportState,
+            Screenshot,
+            Fullscreen,
+            Cheats,
+            Menu,
+            DebugOverlay,
+            StatsOverlay,
+            OpenDebugger,
+            DebugStep,
+            DebugStepback,
+            DebugRun,
+        ]
+    };
+
+    /// The name of the action shown to the user.
+    pub fn name(self) -> &'static str {
+        use Action::*;
+        match self {
+            Left => "Left",
+            Right => "Right",
+            Up => "Up",
+            Down => "Down",
+            A => "A",
+            B => "B",
+            Select => "Select",
+            Start => "Start",
+            TurboA => "Turbo A",
+            TurboB => "Turbo B",
+            LatchA => "Latch A",
+            LatchB => "Latch B",
+            Speed => "Fast forward",
+            SlowMotion => "Slow motion",
+            SpeedUp => "Speed up",
+            SpeedDown => "Speed down",
+            SpeedReset => "Reset speed",
+            VolumeUp => "Volume up",
+            VolumeDown => "Volume down",
+            Mute => "Mute",
+            Rewind => "Rewind",
+            SaveState => "Save state",
+            LoadState => "Load state",
+            ExportState => "Export state",
+            ImportState => "Import state",
+            Screenshot => "Screenshot",
+            Fullscreen => "Fullscreen",
+            Cheats => "Cheats",
+            Menu => "Pause menu",
+            DebugOverlay => "Debug overlay",
+            StatsOverlay => "Stats overlay",
+            OpenDebugger => "Debugger",
+            DebugStep => "Step",
+            DebugStepback => "Step back",
+            DebugRun => "Run",
+        }
+    }
+
+    /// The key of the action in the `keymap` table of the config.
+    pub fn config_key(self) -> &'static str {
+        use Action::*;
+        match self {
+            Left => "left",
+            Right => "right",
+            Up => "up",
+            Down => "down",
+            A => "a",
+            B => "b",
+            Select => "select",
+            Start => "start",
+            TurboA => "turbo_a",
+            TurboB => "turbo_b",
+            LatchA => "latch_a",
+            LatchB => "latch_b",
+            Speed => "speed",
+            SlowMotion => "slow_motion",
+            SpeedUp => "speed_up",
+            SpeedDown => "speed_down",
+            SpeedReset => "speed_reset",
+            VolumeUp => "volume_up",
+            VolumeDown => "volume_down",
+            Mute => "mute",
+            Rewind => "rewind",
+            SaveState => "save_state",
+            LoadState => "load_state",
+            ExportState => "export_state",
+            ImportState => "import_state",
+            Screenshot => "screenshot",
+            Fullscreen => "fullscreen",
+            Cheats => "cheats",
+            Menu => "menu",
+            DebugOverlay => "debug_overlay",
+            StatsOverlay => "stats_overlay",
+            OpenDebugger => "open_debugger",
+            DebugStep => "debug_step",
+            DebugStepback => "debug_stepback",
+            DebugRun => "debug_run",
+        }
+    }
+
+    /// The bit of the joypad register of the button of this action, if it is a joypad button.
+    pub fn joypad_bit(self) -> Option<u8> {
+        use Action::*;
+        Some(match self {
+            Right => 0,
+            Left => 1,
+            Up => 2,
+            Down => 3,
+            A => 4,
+            B => 5,
+            Select => 6,
+            Start => 7,
+            _ => return None,
+        })
+    }
+
+    /// The bit of the joypad register of the button pressed repeatedly by this action, if it is a
+    /// turbo button.
+    pub fn turbo_bit(self) -> Option<u8> {
+        match self {
+            Action::TurboA => Some(4),
+            Action::TurboB => Some(5),
+            _ => None,
+        }
+    }
+
+    /// The bit of the joypad register of the button kept pressed by this action, if it is a latch
+    /// button.
+    pub fn latch_bit(self) -> Option<u8> {
+        match self {
+            Action::LatchA => Some(4),
+            Action::LatchB => Some(5),
+            _ => None,
+        }
+    }
+}
+
+const ACTION_COUNT: usize = 35;
+
+/// The keys bound to each `Action`. A action can have any number of keys, and a key can be bound
+/// to more than one action, which is reported by `conflicts`.
+///
+/// It is written in the config as a table from the action to its keys, either a single key, like
+/// `a = "A"`, or a list, like `a = ["A", "Z"]`, in the format of `Key`. The actions missing from
+/// the table keep their default keys.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyMap {
+    /// The keys of each action, indexed by `Action as usize`.
+    keys: [Cow<'static, [Key]>; ACTION_COUNT],
+}
+
+impl KeyMap {
+    /// The keys bound to `action`.
+    pub fn keys(&self, action: Action) -> &[Key] {
+        &self.keys[action as usize]
+    }
+
+    /// If `key` is bound to `action`.
+    pub fn is_bound(&self, action: Action, key: Key) -> bool {
+        self.keys(action).contains(&key)
+    }
+
+    /// The actions bound to `key`, in the order of `Action::ALL`.
+    pub fn actions(&self, key: Key) -> impl Iterator<Item = Action> + '_ {
+        Action::ALL
+            .into_iter()
+            .filter(move |&action| self.is_bound(action, key))
+    }
+
+    /// Add `key` to the keys of `action`, if it is not there yet.
+    pub fn bind(&mut self, action: Action, key: Key) {
+        if !self.is_bound(action, key) {
+            self.keys[action as usize].to_mut().push(key);
+        }
+    }
+
+    /// Remove all keys of `action`.
+    pub fn clear(&mut self, action: Action) {
+        self.keys[action as usize] = Cow::Borrowed(&[]);
+    }
+
+    /// The actions other than `action` that `key` is bound to.
+    pub fn conflicts_with(&self, action: Action, key: Key) -> Vec<Action> {
+        self.actions(key).filter(|&x| x != action).collect()
+    }
+
+    /// The keys bound to more than one action, and their actions.
+    pub fn conflicts(&self) -> BTreeMap<Key, Vec<Action>> {
+        let mut actions: BTreeMap<Key, Vec<Action>> = BTreeMap::new();
+        for action in Action::ALL {
+            for &key in self.keys(action) {
+                actions.entry(key).or_default().push(action);
+            }
+        }
+        actions.retain(|_, x| x.len() > 1);
+        actions
+    }
+}
+
+impl Default for KeyMap {
+    fn default() -> Self {
+        DEFAULT_KEYMAP
+    }
+}
+
+pub const DEFAULT_KEYMAP: KeyMap = {
+    use VirtualKeyCode::*;
+    const fn key(key: &'static [Key; 1]) -> Cow<'static, [Key]> {
+        Cow::Borrowed(key)
+    }
+    const NONE: Cow<'static, [Key]> = Cow::Borrowed(&[]);
+    // in the order of `Action`
+    KeyMap {
+        keys: [
+            key(&[Key::us(Left)]),
+            key(&[Key::us(Right)]),
+            key(&[Key::us(Up)]),
+            key(&[Key::us(Down)]),
+            key(&[Key::us(A)]),
+            key(&[Key::us(S)]),
+            key(&[Key::us(Back)]),
+            key(&[Key::us(Return)]),
+            key(&[Key::us(Q)]),
+            key(&[Key::us(W)]),
+            NONE,
+            NONE,
+            key(&[Key::us(LShift)]),
+            key(&[Key::us(LControl)]),
+            key(&[Key::us(Equals)]),
+            key(&[Key::us(Minus)]),
+            key(&[Key::us(Key0)]),
+            key(&[Key::us(PageUp)]),
+            key(&[Key::us(PageDown)]),
+            key(&[Key::us(M)]),
+            key(&[Key::us(R)]),
+            key(&[Key::us(F5)]),
+            key(&[Key::us(F6)]),
+            key(&[Key::us(F2)]),
+            key(&[Key::us(F3)]),
+            key(&[Key::us(C)]),
+            key(&[Key::us(F11)]),
+            key(&[Key::us(F4)]),
+            key(&[Key::us(P)]),
+            key(&[Key::us(F10)]),
+            key(&[Key::us(F1)]),
+            key(&[Key::us(F12)]),
+            key(&[Key::us(F8)]),
+            key(&[Key::us(F7)]),
+            key(&[Key::us(F9)]),
+        ],
+    }
+};
+
+/// The keys of a action in the config: a single key, or a list of them.
+#[derive(Deserialize, Serialize)]
+#[serde(untagged)]
+enum Keys<'a> {
+    One(Key),
+    Many(Cow<'a, [Key]>),
+}
+
+impl Serialize for KeyMap {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(ACTION_COUNT))?;
+        for action in Action::ALL {
+            let keys = match self.keys(action) {
+                &[key] => Keys::One(key),
+                keys => Keys::Many(Cow::Borrowed(keys)),
+            };
+            map.serialize_entry(action.config_key(), &keys)?;
+        }
+        map.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for KeyMap {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct KeyMapVisitor;
+        impl<'de> Visitor<'de> for KeyMapVisitor {
+            type Value = KeyMap;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a table of actions and their keys")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<KeyMap, A::Error> {
+                let mut keymap = KeyMap::default();
+                while let Some((name, keys)) = map.next_entry::<Cow<str>, Keys>()? {
+                    let action = Action::ALL
+                        .into_iter()
+                        .find(|x| x.config_key() == name)
+                        .ok_or_else(|| A::Error::custom(format!("unknown action '{}'", name)))?;
+                    keymap.keys[action as usize] = match keys {
+                        Keys::One(key) => Cow::Owned(vec![key]),
+                        Keys::Many(keys) => Cow::Owned(keys.into_owned()),
+                    };
+                }
+                Ok(keymap)
+            }
+        }
+        deserializer.deserialize_map(KeyMapVisitor)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use winit::event::VirtualKeyCode::{self, *};
+
+    use super::{Action, Key, KeyMap, ACTION_COUNT};
+
+    /// The key in the position of `name` in a US layout.
+    fn key(name: VirtualKeyCode) -> Key {
+        Key::from_name(name).unwrap()
+    }
+
+    #[test]
+    fn actions_order() {
+        for (i, action) in Action::ALL.into_iter().enumerate() {
+            assert_eq!(action as usize, i);
+        }
+        assert_eq!(Action::ALL.len(), ACTION_COUNT);
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut keymap = KeyMap::default();
+        keymap.bind(Action::A, key(Z));
+        keymap.bind(Action::A, key(Return));
+        keymap.clear(Action::Cheats);
+        // the key labeled "A" in a AZERTY layout, where Q is in a US layout
+        keymap.bind(Action::B, Key::new(key(Q).scancode, Some(A)));
+        keymap.bind(Action::Select, Key::new(999, None));
+
+        let text = toml::to_string(&keymap).unwrap();
+        assert!(
+            text.contains("a = [\"A\", \"Z\", \"Return\"]\n"),
+            "{}",
+            text
+        );
+        let azerty_a = format!("b = [\"S\", \"A {}\"]\n", key(Q).scancode);
+        assert!(text.contains(&azerty_a), "{}", text);
+        assert!(text.contains("select = [\"Back\", \"999\"]\n"), "{}", text);
+        assert!(text.contains("cheats = []\n"), "{}", text);
+        assert!(text.contains("turbo_a = \"Q\"\n"), "{}", text);
+        assert!(text.contains("latch_a = []\n"), "{}", text);
+        assert!(text.contains("screenshot = \"C\"\n"), "{}", text);
+        assert!(text.contains("debug_stepback = \"F7\"\n"), "{}", text);
+        let loaded = toml::from_str::<KeyMap>(&text).unwrap();
+        assert_eq!(loaded, keymap);
+        assert_eq!(loaded.keys(Action::B)[1].name, Some(A));
+
+        // the missing actions keep their defaults
+        let keymap: KeyMap = toml::from_str("a = \"Z\"\nstart = [\"Return\", \"Space\"]").unwrap();
+        assert_eq!(keymap.keys(Action::A), [key(Z)]);
+        assert_eq!(keymap.keys(Action::Start), [key(Return), key(Space)]);
+        assert_eq!(keymap.keys(Action::B), [key(S)]);
+        assert!(toml::from_str::<KeyMap>("jump = \"Space\"").is_err());
+        assert!(toml::from_str::<KeyMap>("a = \"NotAKey\"").is_err());
+    }
+
+    #[test]
+    fn parse_keys() {
+        let parse = |text: &str| text.parse::<Key>();
+        assert_eq!(parse("A").unwrap(), key(A));
+        assert_eq!(parse("A").unwrap().name, Some(A));
+        let key_16 = parse("A 16").unwrap();
+        assert_eq!((key_16.scancode, key_16.name), (16, Some(A)));
+        let key_16 = parse("16").unwrap();
+        assert_eq!((key_16.scancode, key_16.name), (16, None));
+        // a key without a known scancode must have one
+        assert!(parse("Snapshot").is_err());
+        assert_eq!(parse("Snapshot 99").unwrap().name, Some(Snapshot));
+
+        assert!(parse("").is_err());
+        assert!(parse("NotAKey 16").is_err());
+        assert!(parse("A x").is_err());
+        assert!(parse("A 1 2").is_err());
+        assert!(parse("-1").is_err());
+
+        assert_eq!(key(F1).to_string(), "F1");
+        assert_eq!(Key::new(16, None).to_string(), "Scancode 16");
+    }
+
+    #[test]
+    fn match_by_scancode() {
+        let mut keymap = KeyMap::default();
+        // bound in a AZERTY layout, where "A" is in the position of Q
+        let azerty_a = Key::new(key(Q).scancode, Some(A));
+        keymap.clear(Action::A);
+        keymap.bind(Action::A, azerty_a);
+
+        // the same physical key, whatever its virtual key is
+        assert!(keymap.is_bound(Action::A, key(Q)));
+        assert!(keymap.is_bound(Action::A, Key::new(key(Q).scancode, None)));
+        assert!(!keymap.is_bound(Action::A, key(A)));
+        assert_eq!(
+            keymap.actions(key(Q)).collect::<Vec<_>>(),
+            [Action::A, Action::TurboA]
+        );
+        assert!(keymap.actions(key(A)).next().is_none());
+    }
+
+    #[test]
+    fn conflicts() {
+        let mut keymap = KeyMap::default();
+        assert!(keymap.conflicts().is_empty());
+
+        keymap.bind(Action::A, key(Return));
+        assert_eq!(
+            keymap.conflicts_with(Action::A, key(Return)),
+            [Action::Start]
+        );
+        assert_eq!(
+            keymap.conflicts_with(Action::Start, key(Return)),
+            [Action::A]
+        );
+        assert!(keymap.conflicts_with(Action::A, key(A)).is_empty());
+        assert_eq!(
+            keymap.conflicts().into_iter().collect::<Vec<_>>(),
+            [(key(Return), vec![Action::A, Action::Start])]
+        );
+        // the key triggers both actions
+        assert_eq!(
+            keymap.actions(key(Return)).collect::<Vec<_>>(),
+            [Action::A, Action::Start]
+        );
+
+        keymap.clear(Action::Start);
+        assert!(keymap.conflicts().is_empty());
+        assert_eq!(keymap.actions(key(Return)).collect::<Vec<_>>(), [Action::A]);
+        assert_eq!(keymap, {
+            let mut x = KeyMap::default();
+            x.bind(Action::A, key(Return));
+            x.clear(Action::Start);
+            x
+        });
+    }
+
+    #[test]
+    fn default_keys_are_distinct() {
+        // all default keys have a scancode, and no two actions share one
+        assert!(KeyMap::default().conflicts().is_empty());
+    }
+}
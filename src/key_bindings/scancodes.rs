@@ -0,0 +1,160 @@
+//! The scancodes of the keys of a US keyboard, in each platform, used for the default key bindings
+//! and for the keys written by their names in the config.
+//!
+//! The scancodes are the ones given by winit: evdev codes on Linux and Android, PS/2 set 1
+//! scancodes on Windows, with 0xE000 added to the extended keys, virtual key codes on macOS, and
+//! the legacy `keyCode` of the keyboard events on the web, which, unlike the others, can depend on
+//! the layout.
+
+use winit::event::VirtualKeyCode;
+
+/// The scancode of each key, in Linux, Windows, macOS and the web.
+#[rustfmt::skip]
+const SCANCODES: &[(VirtualKeyCode, [u32; 4])] = {
+    use VirtualKeyCode::*;
+    &[
+        (Escape, [1, 1, 0x35, 27]),
+        (Key1, [2, 2, 0x12, 49]),
+        (Key2, [3, 3, 0x13, 50]),
+        (Key3, [4, 4, 0x14, 51]),
+        (Key4, [5, 5, 0x15, 52]),
+        (Key5, [6, 6, 0x17, 53]),
+        (Key6, [7, 7, 0x16, 54]),
+        (Key7, [8, 8, 0x1A, 55]),
+        (Key8, [9, 9, 0x1C, 56]),
+        (Key9, [10, 10, 0x19, 57]),
+        (Key0, [11, 11, 0x1D, 48]),
+        (Minus, [12, 12, 0x1B, 189]),
+        (Equals, [13, 13, 0x18, 187]),
+        (Back, [14, 14, 0x33, 8]),
+        (Tab, [15, 15, 0x30, 9]),
+        (Q, [16, 16, 0x0C, 81]),
+        (W, [17, 17, 0x0D, 87]),
+        (E, [18, 18, 0x0E, 69]),
+        (R, [19, 19, 0x0F, 82]),
+        (T, [20, 20, 0x11, 84]),
+        (Y, [21, 21, 0x10, 89]),
+        (U, [22, 22, 0x20, 85]),
+        (I, [23, 23, 0x22, 73]),
+        (O, [24, 24, 0x1F, 79]),
+        (P, [25, 25, 0x23, 80]),
+        (LBracket, [26, 26, 0x21, 219]),
+        (RBracket, [27, 27, 0x1E, 221]),
+        (Return, [28, 28, 0x24, 13]),
+        (LControl, [29, 29, 0x3B, 17]),
+        (A, [30, 30, 0x00, 65]),
+        (S, [31, 31, 0x01, 83]),
+        (D, [32, 32, 0x02, 68]),
+        (F, [33, 33, 0x03, 70]),
+        (G, [34, 34, 0x05, 71]),
+        (H, [35, 35, 0x04, 72]),
+        (J, [36, 36, 0x26, 74]),
+        (K, [37, 37, 0x28, 75]),
+        (L, [38, 38, 0x25, 76]),
+        (Semicolon, [39, 39, 0x29, 186]),
+        (Apostrophe, [40, 40, 0x27, 222]),
+        (Grave, [41, 41, 0x32, 192]),
+        (LShift, [42, 42, 0x38, 16]),
+        (Backslash, [43, 43, 0x2A, 220]),
+        (Z, [44, 44, 0x06, 90]),
+        (X, [45, 45, 0x07, 88]),
+        (C, [46, 46, 0x08, 67]),
+        (V, [47, 47, 0x09, 86]),
+        (B, [48, 48, 0x0B, 66]),
+        (N, [49, 49, 0x2D, 78]),
+        (M, [50, 50, 0x2E, 77]),
+        (Comma, [51, 51, 0x2B, 188]),
+        (Period, [52, 52, 0x2F, 190]),
+        (Slash, [53, 53, 0x2C, 191]),
+        (RShift, [54, 54, 0x3C, 16]),
+        (NumpadMultiply, [55, 55, 0x43, 106]),
+        (LAlt, [56, 56, 0x3A, 18]),
+        (Space, [57, 57, 0x31, 32]),
+        (F1, [59, 59, 0x7A, 112]),
+        (F2, [60, 60, 0x78, 113]),
+        (F3, [61, 61, 0x63, 114]),
+        (F4, [62, 62, 0x76, 115]),
+        (F5, [63, 63, 0x60, 116]),
+        (F6, [64, 64, 0x61, 117]),
+        (F7, [65, 65, 0x62, 118]),
+        (F8, [66, 66, 0x64, 119]),
+        (F9, [67, 67, 0x65, 120]),
+        (F10, [68, 68, 0x6D, 121]),
+        (Numpad7, [71, 71, 0x59, 103]),
+        (Numpad8, [72, 72, 0x5B, 104]),
+        (Numpad9, [73, 73, 0x5C, 105]),
+        (NumpadSubtract, [74, 74, 0x4E, 109]),
+        (Numpad4, [75, 75, 0x56, 100]),
+        (Numpad5, [76, 76, 0x57, 101]),
+        (Numpad6, [77, 77, 0x58, 102]),
+        (NumpadAdd, [78, 78, 0x45, 107]),
+        (Numpad1, [79, 79, 0x53, 97]),
+        (Numpad2, [80, 80, 0x54, 98]),
+        (Numpad3, [81, 81, 0x55, 99]),
+        (Numpad0, [82, 82, 0x52, 96]),
+        (NumpadDecimal, [83, 83, 0x41, 110]),
+        (F11, [87, 87, 0x67, 122]),
+        (F12, [88, 88, 0x6F, 123]),
+        (NumpadEnter, [96, 0xE01C, 0x4C, 13]),
+        (RControl, [97, 0xE01D, 0x3E, 17]),
+        (NumpadDivide, [98, 0xE035, 0x4B, 111]),
+        (RAlt, [100, 0xE038, 0x3D, 18]),
+        (Home, [102, 0xE047, 0x73, 36]),
+        (Up, [103, 0xE048, 0x7E, 38]),
+        (PageUp, [104, 0xE049, 0x74, 33]),
+        (Left, [105, 0xE04B, 0x7B, 37]),
+        (Right, [106, 0xE04D, 0x7C, 39]),
+        (End, [107, 0xE04F, 0x77, 35]),
+        (Down, [108, 0xE050, 0x7D, 40]),
+        (PageDown, [109, 0xE051, 0x79, 34]),
+        (Insert, [110, 0xE052, 0x72, 45]),
+        (Delete, [111, 0xE053, 0x75, 46]),
+    ]
+};
+
+/// The column of `SCANCODES` of the current platform.
+const PLATFORM: usize = if cfg!(target_arch = "wasm32") {
+    3
+} else if cfg!(target_os = "windows") {
+    1
+} else if cfg!(target_os = "macos") {
+    2
+} else {
+    0
+};
+
+/// The scancode of the key in the position of `key` in a US layout, in the current platform, if it
+/// is known.
+pub const fn scancode(key: VirtualKeyCode) -> Option<u32> {
+    let mut i = 0;
+    while i < SCANCODES.len() {
+        let (x, codes) = SCANCODES[i];
+        if x as u32 == key as u32 {
+            return Some(codes[PLATFORM]);
+        }
+        i += 1;
+    }
+    None
+}
+
+#[cfg(test)]
+mod test {
+    use super::SCANCODES;
+
+    #[test]
+    fn unique_scancodes() {
+        // the web can't tell the left and right modifiers apart, or the two enter keys
+        for platform in 0..3 {
+            for (i, (key, codes)) in SCANCODES.iter().enumerate() {
+                for (other, other_codes) in &SCANCODES[i + 1..] {
+                    assert_ne!(key, other);
+                    assert_ne!(
+                        codes[platform], other_codes[platform],
+                        "{:?} and {:?} in platform {}",
+                        key, other, platform
+                    );
+                }
+            }
+        }
+    }
+}
@@ -3,30 +3,48 @@ pub static RESIZE: parking_lot::Mutex<Option<(u32, u32)>> = parking_lot::const_m
 
 mod waker_fn;
 
+#[cfg(feature = "audio-engine")]
+mod audio_buffer;
+mod button_combo;
 mod emulator;
 mod event_table;
+mod frame_blend;
+mod frame_stats;
 pub mod executor;
+pub mod game_database;
+mod key_bindings;
 pub mod rom_loading;
 mod style;
+mod translation;
 mod ui;
 mod widget {
+    pub mod context_menu;
     pub mod fold_view;
     pub mod menu;
     mod pixel_perfect_layout;
     mod split_view;
     pub mod table_item;
+    mod tooltip;
 
-    pub use pixel_perfect_layout::ScreenLayout;
+    pub use pixel_perfect_layout::{clamp_scale, ScreenLayout};
     pub use split_view::SplitView;
 }
 pub mod config;
 
-use std::{any::Any, rc::Rc, sync::Arc, thread};
+use std::{
+    any::Any,
+    rc::Rc,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+};
 
 use emulator::{Emulator, EmulatorEvent};
 pub use gameroy;
 use gameroy::{
-    debugger::{Debugger, DebuggerEvent},
+    debugger::{overlay::Overlay, Debugger, DebuggerEvent},
     gameboy::GameBoy,
     parser::Vbm,
 };
@@ -122,6 +140,37 @@ pub fn main(gb: Option<(RomFile, Box<GameBoy>)>, movie: Option<Vbm>) {
 
     let window = wb.build(&event_loop).unwrap();
 
+    // load the roms dropped on the page, or selected in its file input
+    #[cfg(target_arch = "wasm32")]
+    {
+        let proxy = event_loop.create_proxy();
+        let listening = rom_loading::listen_rom_files(move |file| {
+            let proxy = proxy.clone();
+            wasm_bindgen_futures::spawn_local(async move {
+                log::info!("loading the rom {:?}", file.file_name());
+                let event = match rom_loading::load_rom_file(&file).await {
+                    Ok(game_boy) => UserEvent::LoadRom { file, game_boy },
+                    Err(err) => UserEvent::Error(err),
+                };
+                let _ = proxy.send_event(event);
+            });
+        });
+        if let Err(err) = listening {
+            log::error!("{}", err);
+        }
+    }
+
+    // start with the configured scale, limited to the size of the monitor
+    #[cfg(not(any(target_arch = "wasm32", target_os = "android")))]
+    if let Some(scale) = config::config().scale {
+        let monitor = window
+            .current_monitor()
+            .map_or((u32::MAX, u32::MAX), |x| (x.size().width, x.size().height));
+        let size = (SCREEN_WIDTH as u32, SCREEN_HEIGHT as u32);
+        let scale = widget::clamp_scale(size, scale, monitor);
+        window.set_inner_size(PhysicalSize::new(size.0 * scale, size.1 * scale));
+    }
+
     let proxy = event_loop.create_proxy();
     let mut ui = ui::Ui::new(&window, proxy);
 
@@ -156,18 +205,73 @@ pub fn main(gb: Option<(RomFile, Box<GameBoy>)>, movie: Option<Vbm>) {
 pub struct AppState {
     /// The current state of the joypad. It is a bitmask, where 0 means pressed, and 1 released.
     pub joypad: u8,
+    /// The buttons held by a turbo key, as a bitmask where 1 means held.
+    pub turbo: u8,
+    /// The buttons kept pressed by a latch key, as a bitmask where 1 means pressed.
+    pub latched: u8,
     /// If the emulation is in debug mode.
     pub debug: bool,
+    /// If the CPU and PPU state is shown over the game screen.
+    pub debug_overlay: bool,
+    /// If the frame rate and audio buffer stats are shown over the game screen.
+    pub stats_overlay: bool,
+    /// The emulation speed, as a multiple of the real speed.
+    pub speed: f32,
+    /// The speed that replaces `speed` while the fast forward or slow motion key is active.
+    pub speed_override: Option<emulator::SpeedOverride>,
+    /// The volume of the sound, between 0.0 and `emulator::MAX_VOLUME`.
+    pub volume: f32,
+    /// If the sound is muted.
+    pub muted: bool,
 }
 impl AppState {
     fn new(debug: bool) -> Self {
         Self {
             debug,
+            debug_overlay: false,
+            stats_overlay: config::config().show_stats,
+            speed: config::config()
+                .speed_multiplier
+                .clamp(emulator::MIN_SPEED, emulator::MAX_SPEED),
+            speed_override: None,
+            volume: config::config().volume.clamp(0.0, emulator::MAX_VOLUME),
+            muted: config::config().muted,
             joypad: 0xFF,
+            turbo: 0,
+            latched: 0,
         }
     }
 }
 
+/// Enter or leave borderless fullscreen, in the monitor the window is currently in.
+///
+/// Before entering, the position and size of the window are stored in `windowed`, and they are
+/// restored when leaving.
+#[cfg(not(any(target_arch = "wasm32", target_os = "android")))]
+fn set_fullscreen(
+    window: &Window,
+    fullscreen: bool,
+    windowed: &mut Option<(winit::dpi::PhysicalPosition<i32>, PhysicalSize<u32>)>,
+) {
+    if fullscreen == window.fullscreen().is_some() {
+        return;
+    }
+    if fullscreen {
+        if let Ok(position) = window.outer_position() {
+            *windowed = Some((position, window.inner_size()));
+        }
+        let monitor = window.current_monitor();
+        window.set_fullscreen(Some(winit::window::Fullscreen::Borderless(monitor)));
+    } else {
+        window.set_fullscreen(None);
+        if let Some((position, size)) = windowed.take() {
+            window.set_inner_size(size);
+            window.set_outer_position(position);
+        }
+    }
+    config::config().fullscreen = fullscreen;
+}
+
 fn start_event_loop(
     event_loop: EventLoop<UserEvent>,
     window: Rc<Window>,
@@ -182,6 +286,16 @@ fn start_event_loop(
     window.set_visible(true);
     last(&mut app).build_ui(&mut ui);
     let proxy = event_loop.create_proxy();
+    let initial_scale = config::config().scale;
+    let initial_fullscreen = config::config().fullscreen;
+    let initial_speed = config::config().speed_multiplier;
+    let initial_volume = (config::config().volume, config::config().muted);
+    #[cfg(not(any(target_arch = "wasm32", target_os = "android")))]
+    let mut windowed = None;
+    #[cfg(not(any(target_arch = "wasm32", target_os = "android")))]
+    if initial_fullscreen {
+        set_fullscreen(&window, true, &mut windowed);
+    }
     // winit event loop
     event_loop.run(move |event, _, control| {
         let app = &mut app;
@@ -205,11 +319,38 @@ fn start_event_loop(
                 ui.window_event(&event, &window);
                 match event {
                     WindowEvent::CloseRequested => {
+                        let config = config::config();
+                        if config.scale != initial_scale
+                            || config.fullscreen != initial_fullscreen
+                            || config.speed_multiplier != initial_speed
+                            || (config.volume, config.muted) != initial_volume
+                        {
+                            let _ = config
+                                .save()
+                                .map_err(|x| log::error!("error saving config: {}", x));
+                        }
                         *control = ControlFlow::Exit;
                     }
                     WindowEvent::Resized(size) => {
                         ui.resize(size.clone(), &window);
                     }
+                    #[cfg(not(any(target_arch = "wasm32", target_os = "android")))]
+                    WindowEvent::KeyboardInput {
+                        input:
+                            winit::event::KeyboardInput {
+                                scancode,
+                                state: winit::event::ElementState::Pressed,
+                                ..
+                            },
+                        ..
+                    } if config::config().keymap.is_bound(
+                        key_bindings::Action::Fullscreen,
+                        key_bindings::Key::new(*scancode, None),
+                    ) =>
+                    {
+                        let fullscreen = window.fullscreen().is_none();
+                        set_fullscreen(&window, fullscreen, &mut windowed);
+                    }
                     // Rebuild the UI
                     #[cfg(not(feature = "static"))]
                     WindowEvent::KeyboardInput {
@@ -230,27 +371,13 @@ fn start_event_loop(
                     // Load Dropped File
                     #[cfg(not(any(target_arch = "wasm32", target_os = "android")))]
                     WindowEvent::DroppedFile(path) => {
-                        let proxy = proxy.clone();
-                        let path = path.clone();
-                        let task = async move {
-                            log::info!("The file {:?} was dropped", path);
-                            let file = RomFile::from_path(path);
-                            let rom = file.read().await.unwrap();
-                            let ram = match file.load_ram_data().await {
-                                Ok(x) => Some(x),
-                                Err(err) => {
-                                    log::error!("{}", err);
-                                    None
-                                }
-                            };
-                            proxy
-                                .send_event(UserEvent::LoadRom {
-                                    file,
-                                    game_boy: rom_loading::load_gameboy(rom, ram).unwrap(),
-                                })
-                                .unwrap();
-                        };
-                        executor::Executor::spawn_task(task, &mut ui.gui.get_context());
+                        let in_rom_list = last(app).as_any().is::<RomLoadingApp>();
+                        load_dropped_file(
+                            path.clone(),
+                            in_rom_list,
+                            proxy.clone(),
+                            &mut ui.gui.get_context(),
+                        );
                     }
                     _ => {}
                 }
@@ -279,7 +406,27 @@ fn start_event_loop(
                 last(app).build_ui(&mut ui);
             }
             Event::UserEvent(UserEvent::LoadRom { file, game_boy }) => {
+                // a rom loaded while a game is running, like a dropped one, replaces it. Dropping
+                // the emulator saves its RAM.
+                if last(app).as_any().is::<EmulatorApp>() {
+                    log::info!("closing the running game");
+                    app.pop();
+                }
                 let gb = game_boy;
+                #[cfg(not(target_arch = "wasm32"))]
+                if let Some(key) = file.recent_key() {
+                    let now = rom_loading::now_millis();
+                    let mut library = rom_loading::rom_library();
+                    library.record_launch(&key, now);
+                    library.save();
+                    drop(library);
+
+                    let mut config = config::config();
+                    config.add_recent_rom(key);
+                    let _ = config
+                        .save()
+                        .map_err(|x| log::error!("error saving config: {}", x));
+                }
                 window.set_title(&format!("{} - gameroy", file.file_name()));
                 log::trace!("create emu!!");
                 let emu = EmulatorApp::new(
@@ -296,6 +443,13 @@ fn start_event_loop(
                 last(app).build_ui(&mut ui);
                 return;
             }
+            Event::UserEvent(UserEvent::Error(message)) => {
+                log::error!("{}", message);
+                let game = last(app).as_any().is::<EmulatorApp>();
+                let game = game.then(|| ui::GameInput(ui.gui.get::<ui::GameInput>().0));
+                ui::open_error_dialog(&mut ui.gui.get_context(), message, game);
+                return;
+            }
             Event::UserEvent(UserEvent::SpawnTask(task_id)) => {
                 use std::future::Future;
                 let p = Arc::new(Mutex::new(proxy.clone()));
@@ -318,6 +472,66 @@ fn start_event_loop(
     })
 }
 
+/// Load a file dropped on the window. A rom is played, like when opened from the rom list,
+/// replacing the running game, if any. A folder is added to the rom folders, if dropped on the rom
+/// list.
+#[cfg(not(any(target_arch = "wasm32", target_os = "android")))]
+fn load_dropped_file(
+    path: std::path::PathBuf,
+    in_rom_list: bool,
+    proxy: EventLoopProxy<UserEvent>,
+    ctx: &mut giui::Context,
+) {
+    log::info!("the file {:?} was dropped", path);
+    if path.is_dir() {
+        if !in_rom_list {
+            log::warn!(
+                "folders can only be added in the rom list, ignoring {:?}",
+                path
+            );
+            return;
+        }
+        let folder = path.to_string_lossy().to_string();
+        log::info!("adding rom folder '{}'", folder);
+        let mut conf = config::config();
+        conf.add_rom_folder(folder);
+        let _ = conf
+            .save()
+            .map_err(|x| log::error!("error saving config: {}", x));
+        drop(conf);
+        proxy.send_event(UserEvent::UpdateRomList).unwrap();
+        return;
+    }
+
+    let extension = path.extension().and_then(|x| x.to_str());
+    let is_rom = extension.map_or(false, |x| {
+        rom_loading::ROM_EXTENSIONS
+            .iter()
+            .any(|ext| x.eq_ignore_ascii_case(ext))
+    });
+    if !is_rom {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        let extensions = rom_loading::ROM_EXTENSIONS
+            .iter()
+            .map(|x| format!(".{}", x))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let message = format!("'{}' is not a GameBoy rom ({})", name, extensions);
+        let _ = proxy.send_event(UserEvent::Error(message));
+        return;
+    }
+    let task = async move {
+        // a zip archive plays its first rom, like when opened by the file dialog
+        let file = RomFile::open_path(path);
+        let event = match rom_loading::load_rom_file(&file).await {
+            Ok(game_boy) => UserEvent::LoadRom { file, game_boy },
+            Err(err) => UserEvent::Error(err),
+        };
+        let _ = proxy.send_event(event);
+    };
+    executor::Executor::spawn_task(task, ctx);
+}
+
 trait App: Any {
     fn handle_event(
         &mut self,
@@ -352,10 +566,30 @@ impl App for RomLoadingApp {
     ) {
         match event {
             Event::UserEvent(UserEvent::UpdateRomList) => {
-                ui.gui.get::<ui::RomEntries>().start_loading(proxy.clone());
+                let rom_entries = &mut ui.gui.get_mut::<ui::RomEntries>();
+                rom_entries.clear_filter();
+                rom_entries.start_loading(proxy.clone());
+                let observers = rom_entries.observers.clone();
+                for id in observers {
+                    ui.gui
+                        .send_event_to(id, Box::new(event_table::FilterCleared));
+                }
             }
-            Event::UserEvent(UserEvent::UpdatedRomList { roms }) => {
+            #[cfg(all(
+                feature = "watch",
+                not(any(target_arch = "wasm32", target_os = "android"))
+            ))]
+            Event::UserEvent(UserEvent::RomFoldersChanged) => {
+                // the filter and the selection are kept in the reloaded list
                 let rom_entries = &mut ui.gui.get_mut::<ui::RomEntries>();
+                rom_entries.start_loading(proxy.clone());
+            }
+            Event::UserEvent(UserEvent::UpdatedRomList { generation, roms }) => {
+                let rom_entries = &mut ui.gui.get_mut::<ui::RomEntries>();
+                // a list from a previous loading may arrive after a new one is started
+                if !rom_entries.is_current(generation) {
+                    return;
+                }
                 rom_entries.set_roms(roms);
                 let observers = std::mem::take(&mut rom_entries.observers);
                 log::trace!("send events to {:?}", observers);
@@ -365,11 +599,43 @@ impl App for RomLoadingApp {
                 }
                 ui.gui.get_mut::<ui::RomEntries>().observers = observers;
             }
+            #[cfg(not(target_arch = "wasm32"))]
+            Event::UserEvent(UserEvent::RefreshAges) => {
+                let observers = ui.gui.get::<ui::RomEntries>().observers.clone();
+                for id in observers {
+                    ui.gui.send_event_to(id, Box::new(event_table::RefreshAges));
+                }
+            }
+            #[cfg(not(target_arch = "wasm32"))]
+            Event::UserEvent(UserEvent::LoadedThumbnails {
+                generation,
+                thumbnails,
+            }) => {
+                let rom_entries = &mut ui.gui.get_mut::<ui::RomEntries>();
+                if !rom_entries.is_current(generation) {
+                    return;
+                }
+                rom_entries.add_thumbnails(thumbnails);
+                let observers = rom_entries.observers.clone();
+                ui.update_thumbnails_texture();
+                for id in observers {
+                    ui.gui
+                        .send_event_to(id, Box::new(event_table::UpdatedRomList));
+                }
+            }
             _ => {}
         }
     }
 
     fn build_ui(&self, ui: &mut ui::Ui) {
+        // a rom may have been launched since the list was built
+        #[cfg(not(target_arch = "wasm32"))]
+        ui.gui.get_mut::<RomEntries>().refresh_library();
+        // the texture is recreated when the graphics are reloaded
+        #[cfg(not(target_arch = "wasm32"))]
+        if config::config().thumbnails {
+            ui.update_thumbnails_texture();
+        }
         let gui = &mut ui.gui;
         let style = &gui.get::<style::Style>().clone();
         ui::create_rom_loading_ui(gui, style, ui.event_table.clone());
@@ -384,6 +650,16 @@ struct EmulatorApp {
     lcd_screen: Arc<
         parking_lot::lock_api::Mutex<parking_lot::RawMutex, [u8; SCREEN_WIDTH * SCREEN_HEIGHT]>,
     >,
+    /// Set when a frame is written to `lcd_screen`, and cleared when it is presented.
+    frame_pending: Arc<AtomicBool>,
+    /// The color of each shade of the screen, with the color correction of the PPU applied.
+    screen_colors: [[u8; 3]; 4],
+    /// Blends each frame with the previous one, if frame blending is enabled for this game.
+    frame_blender: Option<frame_blend::FrameBlender>,
+    /// Detects the button combos held in the joypad, and hides them from the game.
+    combos: button_combo::ComboDetector,
+    /// The texts drawn by the debugger scripts, composited over each frame.
+    overlay: Arc<std::sync::Mutex<Overlay>>,
     emu_channel: flume::Sender<EmulatorEvent>,
     #[cfg(feature = "threads")]
     emu_thread: Option<thread::JoinHandle<()>>,
@@ -391,6 +667,9 @@ struct EmulatorApp {
     emulator: Emulator,
     #[cfg(not(feature = "threads"))]
     recv: flume::Receiver<emulator::EmulatorEvent>,
+    /// Records the time the game is played. It is dropped with the emulator, ending the session.
+    #[cfg(not(target_arch = "wasm32"))]
+    play_session: Option<rom_loading::PlaySession>,
 }
 impl EmulatorApp {
     fn new(
@@ -403,18 +682,25 @@ impl EmulatorApp {
     ) -> EmulatorApp {
         let lcd_screen: Arc<Mutex<[u8; SCREEN_WIDTH * SCREEN_HEIGHT]>> =
             Arc::new(Mutex::new([0; SCREEN_WIDTH * SCREEN_HEIGHT]));
+        let frame_pending = Arc::new(AtomicBool::new(false));
         gb.v_blank = Some(Box::new({
             let lcd_screen = lcd_screen.clone();
+            let frame_pending = frame_pending.clone();
             let proxy = proxy.clone();
             move |gb| {
                 {
                     let img_data = &mut lcd_screen.lock();
                     img_data.copy_from_slice(&gb.ppu.borrow().screen);
                 }
-                let _ = proxy.send_event(UserEvent::FrameUpdated);
+                // When fast forwarding, frames are emulated faster than they are presented. Only
+                // the last one is presented, instead of queueing a event for each.
+                if !frame_pending.swap(true, Ordering::Relaxed) {
+                    let _ = proxy.send_event(UserEvent::FrameUpdated);
+                }
             }
         }));
         let gb = Arc::new(Mutex::new(*gb));
+        let counters = Arc::new(frame_stats::EmulatorCounters::default());
         let (emu_channel, recv) = flume::bounded(8);
         if debug {
             proxy.send_event(UserEvent::Debug(debug)).unwrap();
@@ -429,25 +715,50 @@ impl EmulatorApp {
                 use DebuggerEvent::*;
                 match event {
                     Step => emu_channel.send(EmulatorEvent::Step).unwrap(),
-                    StepBack => emu_channel.send(EmulatorEvent::StepBack).unwrap(),
+                    StepBack(count) => emu_channel.send(EmulatorEvent::StepBack(count)).unwrap(),
                     Reset => emu_channel.send(EmulatorEvent::Reset).unwrap(),
                     Run => emu_channel.send(EmulatorEvent::Run).unwrap(),
                     BreakpointsUpdate => proxy.send_event(UserEvent::BreakpointsUpdated).unwrap(),
                     WatchsUpdate => proxy.send_event(UserEvent::WatchsUpdated).unwrap(),
+                    RamSearchUpdate => proxy.send_event(UserEvent::RamSearchUpdated).unwrap(),
                 }
             }));
         }
+        #[cfg(not(target_arch = "wasm32"))]
+        let play_session = rom.recent_key().and_then(rom_loading::PlaySession::start);
+        let rom_hash = game_database::RomHash::from_bytes(&gb.lock().cartridge.rom);
+        rom_loading::load_cheats(&mut gb.lock(), &rom_hash);
+        rom_loading::load_trace(&gb.lock(), &rom_hash);
+        rom_loading::load_symbols(&rom, &gb.lock());
+        let screen_colors = gb.lock().ppu.borrow().color_correction.dmg_colors();
+        let frame_blender = {
+            let config = config::config();
+            let enabled = config.frame_blend
+                || config
+                    .frame_blend_games
+                    .iter()
+                    .any(|x| x.eq_ignore_ascii_case(&rom_hash.sha1_hex()));
+            enabled.then(|| frame_blend::FrameBlender::new(config.frame_blend_weight))
+        };
+        let combos = {
+            let config = config::config();
+            let hold_time = instant::Duration::from_millis(config.combo_hold_time as u64);
+            button_combo::ComboDetector::new(&config.combos, hold_time)
+        };
+        ui.gui.set(rom_hash);
         ui.gui.set::<Arc<Mutex<GameBoy>>>(gb.clone());
         ui.gui.set::<Arc<Mutex<Debugger>>>(debugger.clone());
         ui.gui.set(emu_channel.clone());
         ui.gui.set(AppState::new(debug));
+        ui.gui.set(frame_stats::FrameStats::new(counters.clone()));
+        let overlay = debugger.lock().overlay.clone();
 
         #[cfg(feature = "threads")]
         let emu_thread = {
             let join_handle = thread::Builder::new()
                 .name("emulator".to_string())
                 .spawn(move || {
-                    Emulator::new(gb, debugger, proxy, movie, rom).event_loop(recv);
+                    Emulator::new(gb, debugger, proxy, movie, rom, counters).event_loop(recv);
                 })
                 .unwrap();
             Some(join_handle)
@@ -455,13 +766,20 @@ impl EmulatorApp {
 
         EmulatorApp {
             lcd_screen,
+            frame_pending,
+            screen_colors,
+            frame_blender,
+            combos,
+            overlay,
             emu_channel,
             #[cfg(feature = "threads")]
             emu_thread,
             #[cfg(not(feature = "threads"))]
-            emulator: Emulator::new(gb, debugger, proxy, movie, rom),
+            emulator: Emulator::new(gb, debugger, proxy, movie, rom, counters),
             #[cfg(not(feature = "threads"))]
             recv,
+            #[cfg(not(target_arch = "wasm32"))]
+            play_session,
         }
     }
 
@@ -469,6 +787,10 @@ impl EmulatorApp {
         self.emu_channel.send(EmulatorEvent::Kill).unwrap();
         #[cfg(feature = "threads")]
         self.emu_thread.take().unwrap().join().unwrap();
+        // without a thread, the emulator is dropped before handling the kill, so its RAM is saved
+        // here
+        #[cfg(not(feature = "threads"))]
+        self.emulator.handle_event(EmulatorEvent::SaveRam);
     }
 }
 impl Drop for EmulatorApp {
@@ -492,15 +814,54 @@ impl App for EmulatorApp {
     ) {
         match event {
             Event::RedrawRequested(_) => {
-                let joypad = ui.get::<AppState>().joypad;
+                ui.get::<frame_stats::FrameStats>()
+                    .frame(instant::Instant::now());
+                let app_state = ui.get::<AppState>();
+                let (joypad, turbo) = (app_state.joypad & !app_state.latched, app_state.turbo);
+                let (joypad, action) = self.combos.update(joypad, instant::Instant::now());
                 self.emu_channel
                     .send(EmulatorEvent::SetJoypad(joypad))
                     .unwrap();
+                self.emu_channel
+                    .send(EmulatorEvent::SetTurbo(turbo))
+                    .unwrap();
+                match action {
+                    Some(button_combo::ComboAction::SaveState) => {
+                        self.emu_channel.send(EmulatorEvent::SaveState).unwrap()
+                    }
+                    Some(button_combo::ComboAction::LoadState) => {
+                        self.emu_channel.send(EmulatorEvent::LoadState).unwrap()
+                    }
+                    Some(button_combo::ComboAction::Reset) => {
+                        self.emu_channel.send(EmulatorEvent::Reset).unwrap()
+                    }
+                    Some(button_combo::ComboAction::Menu) => {
+                        ui::open_game_menu(&mut ui.gui.get_context())
+                    }
+                    None => {}
+                }
                 self.emu_channel.send(EmulatorEvent::RunFrame).unwrap();
             }
             Event::Suspended => {
                 self.emu_channel.send(EmulatorEvent::SaveRam).unwrap();
             }
+            Event::WindowEvent {
+                event: WindowEvent::Focused(false),
+                ..
+            } => {
+                self.emu_channel.send(EmulatorEvent::FlushRam).unwrap();
+            }
+            Event::WindowEvent {
+                event: WindowEvent::Resized(size),
+                ..
+            } => {
+                // remember the scale chosen by resizing the window, to use it in the next startup
+                if !ui.get::<AppState>().debug && window.fullscreen().is_none() {
+                    let screen = (SCREEN_WIDTH as u32, SCREEN_HEIGHT as u32);
+                    let scale = widget::clamp_scale(screen, u32::MAX, (size.width, size.height));
+                    config::config().scale = Some(scale);
+                }
+            }
             #[cfg(not(feature = "threads"))]
             Event::MainEventsCleared => {
                 let mut poll = true;
@@ -551,21 +912,34 @@ impl App for EmulatorApp {
                 use UserEvent::*;
                 match event {
                     FrameUpdated => {
+                        // cleared before reading the screen, so a newer frame sends a new event
+                        self.frame_pending.store(false, Ordering::Relaxed);
                         let screen: &[u8] = &{
                             let lock = self.lcd_screen.lock();
                             lock.clone()
                         };
-                        const COLOR: [[u8; 3]; 4] =
-                            [[255, 255, 255], [170, 170, 170], [85, 85, 85], [0, 0, 0]];
                         let mut img_data = vec![255; SCREEN_WIDTH * SCREEN_HEIGHT * 4];
                         for y in 0..SCREEN_HEIGHT {
                             for x in 0..SCREEN_WIDTH {
                                 let i = (x + y * SCREEN_WIDTH) as usize * 4;
                                 let c = screen[i / 4];
-                                img_data[i..i + 3].copy_from_slice(&COLOR[c as usize]);
+                                img_data[i..i + 3].copy_from_slice(&self.screen_colors[c as usize]);
+                            }
+                        }
+                        if let Some(blender) = &mut self.frame_blender {
+                            blender.blend(&mut img_data);
+                        }
+                        {
+                            let overlay = self.overlay.lock().unwrap();
+                            if !overlay.is_empty() {
+                                overlay.draw(&mut img_data, SCREEN_WIDTH, SCREEN_HEIGHT);
                             }
                         }
                         ui.update_screen_texture(&img_data);
+                        #[cfg(not(target_arch = "wasm32"))]
+                        if let Some(session) = &mut self.play_session {
+                            session.update();
+                        }
 
                         ui.notify(event_table::FrameUpdated);
                         window.request_redraw();
@@ -580,12 +954,19 @@ impl App for EmulatorApp {
                     }
                     BreakpointsUpdated => ui.notify(event_table::BreakpointsUpdated),
                     WatchsUpdated => ui.notify(event_table::WatchsUpdated),
+                    RamSearchUpdated => ui.notify(event_table::RamSearchUpdated),
                     Debug(value) => {
                         ui.get::<AppState>().debug = value;
                         ui.notify(event_table::Debug(value));
                         self.emu_channel.send(EmulatorEvent::Debug(value)).unwrap();
                     }
                     UpdateTexture(texture, data) => ui.update_texture(texture, &data),
+                    #[cfg(target_arch = "wasm32")]
+                    StateLoaded(state) => {
+                        self.emu_channel
+                            .send(EmulatorEvent::ImportState(state))
+                            .unwrap();
+                    }
                     _ => {}
                 }
             }
@@ -605,16 +986,44 @@ pub enum UserEvent {
     EmulatorStarted,
     BreakpointsUpdated,
     WatchsUpdated,
+    RamSearchUpdated,
     Debug(bool),
     UpdateTexture(u32, Box<[u8]>),
+    /// A save state read from the IndexedDB, to be loaded by the emulator. See
+    /// `EmulatorEvent::LoadState`.
+    #[cfg(target_arch = "wasm32")]
+    StateLoaded(Vec<u8>),
     PopApp,
     LoadRom {
         file: RomFile,
         game_boy: Box<GameBoy>,
     },
     SpawnTask(u32),
+    /// A error to be shown to the user, like a rom that failed to load.
+    Error(String),
+    /// Reload the rom list, after the user changed the rom folders or asked for a refresh. The
+    /// filter is cleared.
     UpdateRomList,
+    /// Reload the rom list, after the content of the rom folders changed. Unlike `UpdateRomList`,
+    /// the filter and the selection are kept.
+    #[cfg(all(
+        feature = "watch",
+        not(any(target_arch = "wasm32", target_os = "android"))
+    ))]
+    RomFoldersChanged,
+    /// A list of roms, sent while and after loading the roms. `generation` identifies the loading
+    /// that sent it, see `RomEntries::is_current`.
     UpdatedRomList {
+        generation: u64,
         roms: Vec<RomEntry>,
     },
+    /// Sent every minute, so the ages shown in the rom list, like "5min ago", are updated.
+    #[cfg(not(target_arch = "wasm32"))]
+    RefreshAges,
+    /// Thumbnails of the roms, by their keys, sent after the rom list is loaded.
+    #[cfg(not(target_arch = "wasm32"))]
+    LoadedThumbnails {
+        generation: u64,
+        thumbnails: Vec<(String, ui::Thumbnail)>,
+    },
 }
@@ -1,4 +1,14 @@
-use gameroy::gameboy::{cartridge::Cartridge, GameBoy};
+use gameroy::gameboy::{
+    cartridge::{Cartridge, CartridgeHeader},
+    cheats::Cheats,
+    eprint_serial, GameBoy,
+};
+use gameroy::save_state::{SaveState, SaveStateHeader};
+
+use crate::{
+    config::{config, Model},
+    game_database::RomHash,
+};
 
 cfg_if::cfg_if! {
     if #[cfg(target_os = "android")] {
@@ -13,17 +23,250 @@ cfg_if::cfg_if! {
     }
 }
 
+#[cfg(not(target_arch = "wasm32"))]
+mod header_cache;
+#[cfg(not(target_arch = "wasm32"))]
+pub use header_cache::{CachedHeader, FileIdentity, HeaderCache};
+#[cfg(not(target_arch = "wasm32"))]
+mod play_log;
+#[cfg(not(target_arch = "wasm32"))]
+pub use play_log::{PlayLog, PlaySession, PlayStats};
+#[cfg(not(target_arch = "wasm32"))]
+mod rom_library;
+#[cfg(not(target_arch = "wasm32"))]
+pub use rom_library::{rom_library, RomLibrary};
+#[cfg(all(
+    feature = "watch",
+    not(any(target_arch = "wasm32", target_os = "android"))
+))]
+mod watcher;
+#[cfg(all(
+    feature = "watch",
+    not(any(target_arch = "wasm32", target_os = "android"))
+))]
+pub use watcher::RomWatcher;
+
+/// The current time, in milliseconds since epoch.
+pub fn now_millis() -> u64 {
+    instant::SystemTime::now()
+        .duration_since(instant::SystemTime::UNIX_EPOCH)
+        .map_or(0, |x| x.as_millis() as u64)
+}
+
+/// The extensions of the files that can be opened as a rom.
+#[cfg(feature = "zip")]
+pub const ROM_EXTENSIONS: &[&str] = &["gb", "gbc", "zip"];
+#[cfg(not(feature = "zip"))]
+pub const ROM_EXTENSIONS: &[&str] = &["gb", "gbc"];
+
+/// The names of the roms, the files with the `.gb` or `.gbc` extension, inside a zip archive, in
+/// the order they are stored.
+#[cfg(feature = "zip")]
+pub fn zip_rom_names<R: std::io::Read + std::io::Seek>(
+    archive: &mut zip::ZipArchive<R>,
+) -> Vec<String> {
+    (0..archive.len())
+        .filter_map(|i| archive.by_index(i).ok().map(|x| x.name().to_string()))
+        .filter(|name| {
+            let name = name.to_ascii_lowercase();
+            name.ends_with(".gb") || name.ends_with(".gbc")
+        })
+        .collect()
+}
+
+/// Extract the first rom of a zip archive that is in memory. Return its name and its content.
+#[cfg(feature = "zip")]
+pub fn unzip_rom(data: &[u8]) -> Result<(String, Vec<u8>), String> {
+    use std::io::Read;
+
+    let mut archive =
+        zip::ZipArchive::new(std::io::Cursor::new(data)).map_err(|e| e.to_string())?;
+    let name = zip_rom_names(&mut archive)
+        .into_iter()
+        .next()
+        .ok_or_else(|| "the zip archive contains no rom".to_string())?;
+    let mut entry = archive.by_name(&name).map_err(|e| e.to_string())?;
+    let mut rom = Vec::new();
+    entry
+        .read_to_end(&mut rom)
+        .map_err(|e| format!("error reading '{}': {}", name, e))?;
+    let name = name.rsplit('/').next().unwrap_or(&name).to_string();
+    Ok((name, rom))
+}
+
+/// Load the battery save of the given rom, if its cartridge has a battery. Cartridges without a
+/// battery don't persist their RAM, so their saves are not loaded.
+pub async fn load_battery_save(file: &RomFile, rom: &[u8]) -> Option<Vec<u8>> {
+    let has_battery = match CartridgeHeader::from_bytes(rom) {
+        Ok(x) | Err((Some(x), _)) => x.has_battery(),
+        Err((None, _)) => false,
+    };
+    if !has_battery {
+        return None;
+    }
+    match file.load_ram_data().await {
+        Ok(x) => Some(x),
+        Err(err) => {
+            log::error!("{}", err);
+            None
+        }
+    }
+}
+
+/// Read the rom file and its battery save, and create a GameBoy with them. The error message
+/// includes the name of the file.
+pub async fn load_rom_file(file: &RomFile) -> Result<Box<GameBoy>, String> {
+    let error = |err: String| format!("failed to load '{}': {}", file.file_name(), err);
+    let rom = file.read().await.map_err(error)?;
+    let ram = load_battery_save(file, &rom).await;
+    load_gameboy(rom, ram).map_err(error)
+}
+
+/// The model that runs the game with the given header, by the `model` and `prefer_cgb` configs.
+pub fn game_model(header: &CartridgeHeader) -> Model {
+    let config = config();
+    let support = header.cgb_support();
+    config.model.resolve(support, config.prefer_cgb)
+}
+
+/// The size of the largest save state header. Only this many bytes are read from the start of a
+/// save state by `RomFile::read_state_header`.
+pub const STATE_HEADER_LEN: usize = 20;
+
+/// If the save state of a rom can be loaded, as shown in the rom list.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum StateInfo {
+    /// The state can be loaded. Contains the instant it was saved, in milliseconds since epoch, if
+    /// the state records it.
+    Valid(Option<u64>),
+    /// The state can't be loaded, like a state saved by a newer version. Contains the reason.
+    Invalid(String),
+}
+impl StateInfo {
+    /// Check the header at the start of a save state. The rest of the state is not checked.
+    pub fn from_header(data: &[u8]) -> Self {
+        let mut header = SaveStateHeader::default();
+        match header.load_state(&mut &data[..]) {
+            Ok(()) => Self::Valid(header.time),
+            Err(err) => Self::Invalid(err.to_string()),
+        }
+    }
+}
+
+/// Add the saved cheats of the rom with the given hash to the GameBoy. They are saved one code per
+/// line, with the disabled ones prefixed by a `!`.
+pub fn load_cheats(game_boy: &mut GameBoy, hash: &RomHash) {
+    let text = match load_cheats_file(hash) {
+        Ok(x) => x,
+        Err(err) => {
+            log::error!("error loading cheats: {}", err);
+            return;
+        }
+    };
+    for line in text.lines().map(str::trim).filter(|x| !x.is_empty()) {
+        let (code, enabled) = match line.strip_prefix('!') {
+            Some(code) => (code, false),
+            None => (line, true),
+        };
+        match game_boy.add_cheat(code) {
+            Ok(id) => {
+                game_boy.set_cheat_enabled(id, enabled);
+            }
+            Err(err) => log::warn!("{}", err),
+        }
+    }
+}
+
+/// Save the cheats of the rom with the given hash, in the format read by `load_cheats`.
+pub fn save_cheats(cheats: &Cheats, hash: &RomHash) {
+    let text: String = cheats
+        .list()
+        .iter()
+        .map(|x| format!("{}{}\n", if x.enabled { "" } else { "!" }, x.code))
+        .collect();
+    if let Err(err) = save_cheats_file(hash, &text) {
+        log::error!("error saving cheats: {}", err);
+    }
+}
+
+/// Merge the saved disassembly trace of the rom with the given hash into the GameBoy's trace, so
+/// the code discovered in previous sessions is already disassembled.
+pub fn load_trace(game_boy: &GameBoy, hash: &RomHash) {
+    let data = match load_trace_file(hash) {
+        Ok(Some(x)) => x,
+        Ok(None) => return,
+        Err(err) => {
+            log::error!("error loading trace: {}", err);
+            return;
+        }
+    };
+    let result = game_boy
+        .trace
+        .borrow_mut()
+        .load(game_boy, &mut data.as_slice());
+    match result {
+        Ok(count) => log::info!("loaded trace with {} instructions", count),
+        Err(err) => log::warn!("error loading trace: {}", err),
+    }
+}
+
+/// Save the disassembly trace of the GameBoy, to be loaded by `load_trace`.
+pub fn save_trace(game_boy: &GameBoy, hash: &RomHash) {
+    let mut data = Vec::new();
+    if let Err(err) = game_boy.trace.borrow().save(game_boy, &mut data) {
+        log::error!("error saving trace: {}", err);
+        return;
+    }
+    if let Err(err) = save_trace_file(hash, &data) {
+        log::error!("error saving trace: {}", err);
+    }
+}
+
+/// Load the symbol file of the rom into the disassembler, if there is one, to label the addresses
+/// in the debugger.
+pub fn load_symbols(file: &RomFile, game_boy: &GameBoy) {
+    let text = match file.load_symbols_file() {
+        Ok(Some(x)) => x,
+        Ok(None) => return,
+        Err(err) => {
+            log::error!("error loading symbols: {}", err);
+            return;
+        }
+    };
+    match game_boy.trace.borrow_mut().load_symbols(text.as_bytes()) {
+        Ok(count) => log::info!("loaded {} symbols", count),
+        Err(err) => log::error!("error loading symbols: {}", err),
+    }
+}
+
 pub fn load_gameboy(rom: Vec<u8>, ram: Option<Vec<u8>>) -> Result<Box<GameBoy>, String> {
     let boot_rom = load_boot_rom();
 
     let mut cartridge = Cartridge::new(rom)?;
-    log::info!("Cartridge type: {}", cartridge.kind_name());
+    log::info!("Cartridge type: {}", cartridge.mapper_kind());
+    for warning in cartridge.integrity.warnings() {
+        log::warn!("{}", warning);
+    }
+    let model = game_model(&cartridge.header);
+    log::info!("Model: {}", model.name());
+    if model == Model::Cgb {
+        log::warn!("the CGB is not emulated yet, running the game on the DMG");
+    }
 
     if let Some(ram) = ram {
-        cartridge.ram = ram;
+        if let Err(err) = cartridge.load_battery_save(ram) {
+            log::warn!("{}", err);
+        }
     }
 
-    let game_boy = GameBoy::new(boot_rom, cartridge);
+    let mut game_boy = GameBoy::new(boot_rom, cartridge);
+    game_boy.ppu.get_mut().color_correction = config().color_correction.into();
+    if let Some(state) = load_after_boot_sound() {
+        if let Err(err) = game_boy.set_after_boot_sound(Some(state)) {
+            log::error!("invalid after boot sound state, using the default: {}", err);
+        }
+    }
+    game_boy.serial_transfer_callback = Some(Box::new(eprint_serial));
     {
         let mut trace = game_boy.trace.borrow_mut();
 
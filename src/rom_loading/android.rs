@@ -3,6 +3,9 @@ use std::borrow::Cow;
 use gameroy::gameboy::cartridge::CartridgeHeader;
 use jni::objects::{JString, JValue};
 
+use super::FileIdentity;
+use crate::game_database::RomHash;
+
 pub fn show_licenses() {
     log::trace!("show licenses");
     let android_context = ndk_context::android_context();
@@ -20,7 +23,30 @@ pub fn show_licenses() {
         .unwrap();
 }
 
-pub fn load_roms(roms_path: &str) -> Result<Vec<RomFile>, String> {
+/// List the roms in the folders with the given tree URIs. The same URI is listed only once. Folders
+/// that can't be read are logged and skipped.
+pub fn load_roms(roms_paths: &[String]) -> Vec<RomFile> {
+    let mut seen = std::collections::HashSet::new();
+    let mut roms = Vec::new();
+    for roms_path in roms_paths {
+        let childs = match list_children(roms_path) {
+            Ok(x) => x,
+            Err(err) => {
+                log::error!("{}", err);
+                continue;
+            }
+        };
+        for child in childs {
+            if seen.insert(child.clone()) {
+                roms.push(rfd::FileHandle::wrap(child).into());
+            }
+        }
+    }
+    roms
+}
+
+/// The URIs of the files in the folder with the given tree URI.
+fn list_children(roms_path: &str) -> Result<Vec<String>, String> {
     log::trace!("loading rom list in android from uri '{}'", roms_path);
     let android_context = ndk_context::android_context();
     let vm =
@@ -67,16 +93,17 @@ pub fn load_roms(roms_path: &str) -> Result<Vec<RomFile>, String> {
         None => return Err(format!("failed to load children of uri: {}", roms_path)),
     };
 
-    Ok(childs
-        .into_iter()
-        .map(|x| rfd::FileHandle::wrap(x).into())
-        .collect::<Vec<RomFile>>())
+    Ok(childs)
 }
 
 pub fn load_boot_rom() -> Option<[u8; 256]> {
     None
 }
 
+pub fn load_after_boot_sound() -> Option<Vec<u8>> {
+    None
+}
+
 pub fn load_file(file_name: &str) -> Option<Vec<u8>> {
     let android_context = ndk_context::android_context();
     let vm =
@@ -136,7 +163,8 @@ pub fn file_date(file_name: &str) -> Option<u64> {
         .unwrap()
 }
 
-pub fn save_file(file_name: &str, data: &[u8]) {
+/// Write a file in the private storage of the app, through the `saveRam` method of the activity.
+pub fn save_file(file_name: &str, data: &[u8]) -> Result<(), String> {
     let android_context = ndk_context::android_context();
     let vm =
         std::sync::Arc::new(unsafe { jni::JavaVM::from_raw(android_context.vm().cast()).unwrap() });
@@ -157,7 +185,26 @@ pub fn save_file(file_name: &str, data: &[u8]) {
 
             Ok(())
         })
-        .unwrap();
+        .map_err(|e| e.to_string())
+}
+
+/// Delete a file from the private storage of the app. Return false if it doesn't exist.
+pub fn delete_file(file_name: &str) -> bool {
+    let android_context = ndk_context::android_context();
+    let vm =
+        std::sync::Arc::new(unsafe { jni::JavaVM::from_raw(android_context.vm().cast()).unwrap() });
+    jni::Executor::new(vm)
+        .with_attached(|env| {
+            let filename = env.new_string(file_name)?;
+            let deleted = env.call_method(
+                android_context.context() as jni::sys::jobject,
+                "deleteSave",
+                "(Ljava/lang/String;)Z",
+                &[filename.into()],
+            )?;
+            deleted.z()
+        })
+        .unwrap()
 }
 
 fn read_uri(uri: &str, bytes: i32) -> Result<Vec<u8>, String> {
@@ -185,11 +232,130 @@ fn read_uri(uri: &str, bytes: i32) -> Result<Vec<u8>, String> {
         .unwrap())
 }
 
+/// Load the cheats of the rom with the given hash. A missing file is the same as a empty one.
+pub fn load_cheats_file(hash: &RomHash) -> Result<String, String> {
+    let file_name = hash.sha1_hex() + ".cheats";
+    match load_file(&file_name) {
+        Some(data) => String::from_utf8(data).map_err(|e| e.to_string()),
+        None => Ok(String::new()),
+    }
+}
+
+pub fn save_cheats_file(hash: &RomHash, text: &str) -> Result<(), String> {
+    let file_name = hash.sha1_hex() + ".cheats";
+    save_file(&file_name, text.as_bytes())
+}
+
+/// Load the saved disassembly trace of the rom with the given hash, if there is one.
+pub fn load_trace_file(hash: &RomHash) -> Result<Option<Vec<u8>>, String> {
+    let file_name = hash.sha1_hex() + ".trace";
+    Ok(load_file(&file_name))
+}
+
+pub fn save_trace_file(hash: &RomHash, data: &[u8]) -> Result<(), String> {
+    let file_name = hash.sha1_hex() + ".trace";
+    save_file(&file_name, data)
+}
+
+/// Load the header cache of the rom list, if there is one.
+pub fn load_header_cache_file() -> Result<Option<String>, String> {
+    match load_file("header_cache.toml") {
+        Some(data) => String::from_utf8(data).map(Some).map_err(|e| e.to_string()),
+        None => Ok(None),
+    }
+}
+
+pub fn save_header_cache_file(text: &str) -> Result<(), String> {
+    save_file("header_cache.toml", text.as_bytes())
+}
+
+/// Load the favorites and the launch times of the roms, if there are any.
+pub fn load_rom_library_file() -> Result<Option<String>, String> {
+    match load_file("rom_library.toml") {
+        Some(data) => String::from_utf8(data).map(Some).map_err(|e| e.to_string()),
+        None => Ok(None),
+    }
+}
+
+pub fn save_rom_library_file(text: &str) -> Result<(), String> {
+    save_file("rom_library.toml", text.as_bytes())
+}
+
+/// Load the play log, if there is one. See `PlayLog`.
+pub fn load_play_log_file() -> Result<Option<String>, String> {
+    match load_file("play_log.txt") {
+        Some(data) => String::from_utf8(data).map(Some).map_err(|e| e.to_string()),
+        None => Ok(None),
+    }
+}
+
+/// Append a line to the play log. There is only one instance of the app in Android, so the file is
+/// rewritten instead.
+pub fn append_play_log_file(line: &str) -> Result<(), String> {
+    let mut data = load_file("play_log.txt").unwrap_or_default();
+    data.extend_from_slice(line.as_bytes());
+    save_file("play_log.txt", &data)
+}
+
+/// The size and the last modified time of the document with the given URI.
+fn uri_info(uri: &str) -> Option<(u64, u64)> {
+    let android_context = ndk_context::android_context();
+    let vm =
+        std::sync::Arc::new(unsafe { jni::JavaVM::from_raw(android_context.vm().cast()).unwrap() });
+    jni::Executor::new(vm)
+        .with_attached(|env| {
+            let uri = env.new_string(uri)?;
+            let info = env.call_method(
+                android_context.context() as jni::sys::jobject,
+                "getUriInfo",
+                "(Ljava/lang/String;)[J",
+                &[uri.into()],
+            )?;
+            let array = match info {
+                JValue::Object(x) => {
+                    if x.is_null() {
+                        return Ok(None);
+                    }
+                    x.into_inner() as jni::sys::jlongArray
+                }
+                _ => return Err(jni::errors::Error::WrongJValueType("a", "b")),
+            };
+            let mut info = [0; 2];
+            env.get_long_array_region(array, 0, &mut info)?;
+            Ok(Some((info[0] as u64, info[1] as u64)))
+        })
+        .unwrap()
+}
+
 #[derive(Clone, Debug)]
 pub struct RomFile {
     uri: String,
 }
 impl RomFile {
+    /// The URI, size and modification time of the document, used to known if its cached header is
+    /// up to date.
+    pub fn identity(&self) -> Option<FileIdentity> {
+        let (size, modified) = uri_info(&self.uri)?;
+        Some(FileIdentity {
+            path: self.uri.clone(),
+            size,
+            modified,
+        })
+    }
+
+    /// A string that identifies the rom across runs, in the `recent_roms` config and in the
+    /// `RomLibrary`, and from which it can be opened again with `from_recent_key`.
+    pub fn recent_key(&self) -> Option<String> {
+        Some(self.uri.clone())
+    }
+
+    /// The rom identified by a key returned by `recent_key`. The document may no longer exist.
+    pub fn from_recent_key(key: &str) -> Self {
+        Self {
+            uri: key.to_string(),
+        }
+    }
+
     pub async fn get_header(&self) -> Result<CartridgeHeader, String> {
         let header = read_uri(self.uri.as_str(), 0x150)?;
         match CartridgeHeader::from_bytes(&header) {
@@ -198,6 +364,12 @@ impl RomFile {
         }
     }
 
+    /// Compute the hash of the rom. `readUri` can only read from the start of the file, so it is
+    /// hashed entirely.
+    pub async fn hash(&self) -> Result<RomHash, String> {
+        Ok(RomHash::from_bytes(&read_uri(self.uri.as_str(), 0)?))
+    }
+
     pub fn file_name(&self) -> Cow<str> {
         urlencoding::decode(&self.uri)
             .unwrap()
@@ -214,8 +386,7 @@ impl RomFile {
     pub fn save_ram_data(&self, data: &[u8]) -> Result<(), String> {
         let file_name = self.file_name().to_owned() + ".sav";
 
-        save_file(&file_name, data);
-        Ok(())
+        save_file(&file_name, data)
     }
 
     pub async fn load_ram_data(&self) -> Result<Vec<u8>, String> {
@@ -230,18 +401,60 @@ impl RomFile {
         file_date(&file_name).ok_or_else(|| "file date failed".to_string())
     }
 
-    pub fn save_state(&self, state: &[u8]) -> Result<(), String> {
+    /// Delete the battery save of the rom, if there is one. The saves are in the private storage
+    /// of the app, so this never touches the rom.
+    pub fn delete_ram_data(&self) -> Result<(), String> {
+        let file_name = self.file_name().to_owned() + ".sav";
+
+        delete_file(&file_name);
+        Ok(())
+    }
+
+    /// Delete the save state of the rom, if there is one.
+    pub fn delete_state(&self) -> Result<(), String> {
         let file_name = self.file_name().to_owned() + ".save_state";
 
-        save_file(&file_name, state);
+        delete_file(&file_name);
         Ok(())
     }
 
+    pub fn save_state(&self, state: &[u8]) -> Result<(), String> {
+        let file_name = self.file_name().to_owned() + ".save_state";
+
+        save_file(&file_name, state)
+    }
+
     pub fn load_state(&self) -> Result<Vec<u8>, String> {
         let file_name = self.file_name().to_owned() + ".save_state";
 
         load_file(&file_name).ok_or_else(|| "load save state failed".to_string())
     }
+
+    /// Read the start of the save state of the rom, up to `STATE_HEADER_LEN` bytes. Return `None`
+    /// if there is no save state. The storage has no partial reads, so the whole state is loaded.
+    pub fn read_state_header(&self) -> Result<Option<Vec<u8>>, String> {
+        let file_name = self.file_name().to_owned() + ".save_state";
+
+        Ok(load_file(&file_name).map(|mut state| {
+            state.truncate(super::STATE_HEADER_LEN);
+            state
+        }))
+    }
+
+    /// Load the png thumbnail of the rom in the rom list, if there is one.
+    pub fn load_thumbnail(&self) -> Result<Option<Vec<u8>>, String> {
+        let file_name = self.file_name().to_owned() + ".png";
+        Ok(load_file(&file_name))
+    }
+
+    /// Load the symbol file of the rom, if there is one.
+    pub fn load_symbols_file(&self) -> Result<Option<String>, String> {
+        let file_name = self.file_name().to_owned() + ".sym";
+        match load_file(&file_name) {
+            Some(data) => String::from_utf8(data).map(Some).map_err(|e| e.to_string()),
+            None => Ok(None),
+        }
+    }
 }
 #[cfg(feature = "rfd")]
 impl From<rfd::FileHandle> for RomFile {
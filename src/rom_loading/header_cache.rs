@@ -0,0 +1,274 @@
+use std::collections::HashMap;
+
+use gameroy::gameboy::cartridge::{CartridgeHeader, CgbSupport, RomIntegrity};
+use serde::{Deserialize, Serialize};
+
+/// The version of the format of the cache file. A file with a different version is discarded.
+const VERSION: u32 = 2;
+
+/// The identity of a rom file, used to known if its cached header is still up to date.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FileIdentity {
+    /// A string that uniquely identifies the file, like its path or URI.
+    pub path: String,
+    /// The size of the file in bytes.
+    pub size: u64,
+    /// The last modification time of the file, in milliseconds since epoch.
+    pub modified: u64,
+}
+
+/// The information of a rom header shown in the rom list.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CachedHeader {
+    /// The title in the header.
+    pub name: String,
+    /// The size of the rom, as declared in the header.
+    pub rom_size: u64,
+    /// The cartridge type byte of the header.
+    pub cartridge_type: u8,
+    /// If the cartridge has a battery, and so a save file.
+    pub has_battery: bool,
+    /// The name of the memory bank controller, as given by `CartridgeHeader::mapper_name`.
+    pub mapper: String,
+    /// The models supported by the game, like "DMG+CGB" or "DMG+SGB", see `color_support`.
+    pub color: String,
+    /// The name or code of the licensee, as given by `CartridgeHeader::licensee`.
+    pub licensee: String,
+    /// The problems of the header, as given by `RomIntegrity`.
+    pub warning: Option<String>,
+}
+impl CachedHeader {
+    pub fn from_header(header: &CartridgeHeader) -> Self {
+        let warnings = RomIntegrity::from_header(header).warnings();
+        Self {
+            name: header.title_as_string(),
+            rom_size: header.rom_size_in_bytes().unwrap_or(0) as u64,
+            cartridge_type: header.cartridge_type,
+            has_battery: header.has_battery(),
+            mapper: header.mapper_name().to_string(),
+            color: color_support(header),
+            licensee: header.licensee(),
+            warning: (!warnings.is_empty()).then(|| warnings.join(", ")),
+        }
+    }
+}
+
+/// The models supported by the game: "DMG", "CGB" or "DMG+CGB", followed by "+SGB" if it supports
+/// the Super Game Boy.
+fn color_support(header: &CartridgeHeader) -> String {
+    let models = match header.cgb_support() {
+        CgbSupport::None => "DMG",
+        CgbSupport::Enhanced => "DMG+CGB",
+        CgbSupport::Only => "CGB",
+    };
+    if header.supports_sgb() {
+        format!("{}+SGB", models)
+    } else {
+        models.to_string()
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    path: String,
+    size: u64,
+    modified: u64,
+    header: CachedHeader,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CacheFile {
+    version: u32,
+    #[serde(default)]
+    entries: Vec<Entry>,
+}
+
+/// The headers of the roms read in previous launches, so the rom list can be populated without
+/// reading every rom again.
+///
+/// A entry is only used if the size and the modification time of the file are the same as when it
+/// was cached.
+#[derive(Debug, Default)]
+pub struct HeaderCache {
+    /// The size, modification time and header of each file, by its path.
+    entries: HashMap<String, (u64, u64, CachedHeader)>,
+    /// If there are changes that were not saved yet.
+    dirty: bool,
+}
+impl HeaderCache {
+    /// Load the cache from its file. If it doesn't exist, is corrupted or has a different version,
+    /// a empty cache is returned.
+    pub fn load() -> Self {
+        match super::load_header_cache_file() {
+            Ok(Some(text)) => Self::from_text(&text),
+            Ok(None) => Self::default(),
+            Err(err) => {
+                log::debug!("discarding header cache: {}", err);
+                Self::default()
+            }
+        }
+    }
+
+    /// Parse the content of a cache file. If it is corrupted or has a different version, a empty
+    /// cache is returned.
+    pub fn from_text(text: &str) -> Self {
+        let file: CacheFile = match toml::from_str(text) {
+            Ok(x) => x,
+            Err(err) => {
+                log::debug!("discarding header cache: {}", err);
+                return Self::default();
+            }
+        };
+        if file.version != VERSION {
+            log::debug!("discarding header cache of version {}", file.version);
+            return Self::default();
+        }
+        Self {
+            entries: file
+                .entries
+                .into_iter()
+                .map(|x| (x.path, (x.size, x.modified, x.header)))
+                .collect(),
+            dirty: false,
+        }
+    }
+
+    /// Serialize the cache in the format read by `from_text`.
+    pub fn to_text(&self) -> String {
+        let mut entries: Vec<Entry> = self
+            .entries
+            .iter()
+            .map(|(path, (size, modified, header))| Entry {
+                path: path.clone(),
+                size: *size,
+                modified: *modified,
+                header: header.clone(),
+            })
+            .collect();
+        entries.sort_by(|a, b| a.path.cmp(&b.path));
+        toml::to_string(&CacheFile {
+            version: VERSION,
+            entries,
+        })
+        .unwrap()
+    }
+
+    /// The cached header of the file, if it was not changed since it was cached.
+    pub fn get(&self, identity: &FileIdentity) -> Option<&CachedHeader> {
+        match self.entries.get(&identity.path) {
+            Some((size, modified, header))
+                if *size == identity.size && *modified == identity.modified =>
+            {
+                Some(header)
+            }
+            _ => None,
+        }
+    }
+
+    /// Cache the header of the file, replacing any previous entry of the same path.
+    pub fn insert(&mut self, identity: FileIdentity, header: CachedHeader) {
+        self.entries
+            .insert(identity.path, (identity.size, identity.modified, header));
+        self.dirty = true;
+    }
+
+    /// Remove the entries of the files that are not in `paths`, like roms that were deleted or
+    /// whose folder was removed.
+    pub fn retain<'a>(&mut self, paths: impl IntoIterator<Item = &'a str>) {
+        let paths: std::collections::HashSet<&str> = paths.into_iter().collect();
+        let len = self.entries.len();
+        self.entries.retain(|path, _| paths.contains(path.as_str()));
+        self.dirty |= self.entries.len() != len;
+    }
+
+    /// Write the cache to its file, if it has changed since it was loaded or last saved.
+    pub fn save(&mut self) {
+        if !self.dirty {
+            return;
+        }
+        match super::save_header_cache_file(&self.to_text()) {
+            Ok(()) => self.dirty = false,
+            Err(err) => log::error!("error saving header cache: {}", err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{CachedHeader, FileIdentity, HeaderCache};
+
+    fn header(name: &str) -> CachedHeader {
+        CachedHeader {
+            name: name.to_string(),
+            rom_size: 0x8000,
+            cartridge_type: 0x03,
+            has_battery: true,
+            mapper: "MBC1".to_string(),
+            color: "DMG+CGB".to_string(),
+            licensee: "Nintendo".to_string(),
+            warning: Some("invalid header checksum".to_string()),
+        }
+    }
+
+    fn identity(path: &str, size: u64, modified: u64) -> FileIdentity {
+        FileIdentity {
+            path: path.to_string(),
+            size,
+            modified,
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut cache = HeaderCache::default();
+        cache.insert(identity("roms/a.gb", 0x8000, 100), header("A"));
+        cache.insert(identity("roms/b \"quoted\".gb", 0x10000, 200), header("B"));
+
+        let cache = HeaderCache::from_text(&cache.to_text());
+        assert!(!cache.dirty);
+        assert_eq!(
+            cache.get(&identity("roms/a.gb", 0x8000, 100)),
+            Some(&header("A"))
+        );
+        assert_eq!(
+            cache.get(&identity("roms/b \"quoted\".gb", 0x10000, 200)),
+            Some(&header("B"))
+        );
+    }
+
+    #[test]
+    fn staleness() {
+        let mut cache = HeaderCache::default();
+        cache.insert(identity("a.gb", 0x8000, 100), header("A"));
+
+        assert!(cache.get(&identity("a.gb", 0x8000, 100)).is_some());
+        assert!(cache.get(&identity("a.gb", 0x8000, 101)).is_none());
+        assert!(cache.get(&identity("a.gb", 0x4000, 100)).is_none());
+        assert!(cache.get(&identity("b.gb", 0x8000, 100)).is_none());
+
+        // a changed file replaces its old entry
+        cache.insert(identity("a.gb", 0x8000, 101), header("A2"));
+        assert!(cache.get(&identity("a.gb", 0x8000, 100)).is_none());
+        assert_eq!(
+            cache.get(&identity("a.gb", 0x8000, 101)),
+            Some(&header("A2"))
+        );
+
+        cache.retain(["b.gb"]);
+        assert!(cache.get(&identity("a.gb", 0x8000, 101)).is_none());
+    }
+
+    #[test]
+    fn discard_invalid() {
+        let mut cache = HeaderCache::default();
+        cache.insert(identity("a.gb", 0x8000, 100), header("A"));
+        let text = cache.to_text();
+
+        let other_version = text.replace("version = 2", "version = 999");
+        assert!(HeaderCache::from_text(&other_version).entries.is_empty());
+
+        let corrupted = &text[..text.len() / 2];
+        assert!(HeaderCache::from_text(corrupted).entries.is_empty());
+        assert!(HeaderCache::from_text("\u{0}garbage").entries.is_empty());
+    }
+}
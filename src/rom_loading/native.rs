@@ -1,24 +1,100 @@
-use std::{borrow::Cow, path::PathBuf};
+use std::{
+    borrow::Cow,
+    collections::HashSet,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
 use gameroy::gameboy::cartridge::CartridgeHeader;
 
-use crate::config::config;
+use super::FileIdentity;
+use crate::{
+    config::config,
+    game_database::{RomHash, RomHasher},
+};
 
-pub fn load_roms(roms_path: &str) -> Result<Vec<RomFile>, String> {
-    let roms_path = crate::config::normalize_config_path(roms_path);
-
-    let roms = std::fs::read_dir(&roms_path)
-        .map_err(|e| e.to_string())?
-        .flat_map(|x| x.map_err(|e| log::error!("error: {}", e)).ok())
-        .filter_map(|x| {
-            if x.path().extension()? != "gb" {
-                return None;
+/// List the roms in the given folders. The same file is listed only once, even if it is reached
+/// from more than one folder. Folders that can't be read are logged and skipped.
+pub fn load_roms(roms_paths: &[String]) -> Vec<RomFile> {
+    let mut seen = HashSet::new();
+    let mut roms = Vec::new();
+    for roms_path in roms_paths {
+        let roms_path = crate::config::normalize_config_path(roms_path);
+        let entries = match std::fs::read_dir(&roms_path) {
+            Ok(x) => x,
+            Err(err) => {
+                log::error!("error reading '{}': {}", roms_path.display(), err);
+                continue;
             }
-            Some(x)
-        })
-        .map(|x| RomFile::from_path(x.path()))
-        .collect::<Vec<_>>();
-    Ok(roms)
+        };
+        for entry in entries.flat_map(|x| x.map_err(|e| log::error!("error: {}", e)).ok()) {
+            let path = entry.path();
+            let path = std::fs::canonicalize(&path).unwrap_or(path);
+            if seen.contains(&path) {
+                continue;
+            }
+            match path.extension().and_then(|x| x.to_str()) {
+                Some("gb") => roms.push(RomFile::from_path(path.clone())),
+                #[cfg(feature = "zip")]
+                Some("zip") => roms.extend(zip_roms(path.clone())),
+                _ => continue,
+            }
+            seen.insert(path);
+        }
+    }
+    roms
+}
+
+/// The roms inside the zip archive at the given path, each one as its own `RomFile`.
+#[cfg(feature = "zip")]
+fn zip_roms(path: PathBuf) -> Vec<RomFile> {
+    let entries = std::fs::File::open(&path)
+        .map_err(|e| e.to_string())
+        .and_then(|file| zip::ZipArchive::new(file).map_err(|e| e.to_string()))
+        .map(|mut archive| super::zip_rom_names(&mut archive));
+    match entries {
+        Ok(entries) => entries
+            .into_iter()
+            .map(|entry| RomFile::from_zip(path.clone(), entry))
+            .collect(),
+        Err(err) => {
+            log::error!("error reading '{}': {}", path.display(), err);
+            Vec::new()
+        }
+    }
+}
+
+/// Open the file with the given name inside the zip archive at `path`, and pass it to `f`.
+#[cfg(feature = "zip")]
+fn with_zip_entry<T>(
+    path: &Path,
+    name: &str,
+    f: impl FnOnce(&mut dyn std::io::Read) -> Result<T, String>,
+) -> Result<T, String> {
+    let file = std::fs::File::open(path)
+        .map_err(|x| format!("error loading '{}': {}", path.display(), x))?;
+    let mut archive = zip::ZipArchive::new(file)
+        .map_err(|x| format!("error reading '{}': {}", path.display(), x))?;
+    let mut entry = archive
+        .by_name(name)
+        .map_err(|x| format!("error reading '{}' in '{}': {}", name, path.display(), x))?;
+    f(&mut entry)
+}
+
+/// Compute the hash of a rom, reading it in chunks.
+fn hash_reader(reader: &mut dyn std::io::Read, name: &str) -> Result<RomHash, String> {
+    let mut hasher = RomHasher::new();
+    let mut buffer = vec![0; 0x1_0000];
+    loop {
+        let len = match reader.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(len) => len,
+            Err(err) if err.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(format!("error reading '{}': {}", name, err)),
+        };
+        hasher.update(&buffer[..len]);
+    }
+    Ok(hasher.finish())
 }
 
 fn open_and_read(
@@ -32,6 +108,121 @@ fn open_and_read(
         .map_err(|x| format!("error reading '{}': {}", rom_path.display(), x))? as usize)
 }
 
+fn cheats_path(hash: &RomHash) -> PathBuf {
+    crate::config::normalize_config_path(format!("cheats/{}.txt", hash.sha1_hex()))
+}
+
+/// Load the cheats file of the rom with the given hash. A missing file is the same as a empty one.
+pub fn load_cheats_file(hash: &RomHash) -> Result<String, String> {
+    let path = cheats_path(hash);
+    match std::fs::read_to_string(&path) {
+        Ok(x) => Ok(x),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(String::new()),
+        Err(err) => Err(format!("error reading '{}': {}", path.display(), err)),
+    }
+}
+
+pub fn save_cheats_file(hash: &RomHash, text: &str) -> Result<(), String> {
+    let path = cheats_path(hash);
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
+    std::fs::write(&path, text).map_err(|e| format!("error writing '{}': {}", path.display(), e))
+}
+
+fn trace_path(hash: &RomHash) -> PathBuf {
+    crate::config::normalize_config_path(format!("traces/{}.trace", hash.sha1_hex()))
+}
+
+/// Load the saved disassembly trace of the rom with the given hash, if there is one.
+pub fn load_trace_file(hash: &RomHash) -> Result<Option<Vec<u8>>, String> {
+    let path = trace_path(hash);
+    match std::fs::read(&path) {
+        Ok(x) => Ok(Some(x)),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(format!("error reading '{}': {}", path.display(), err)),
+    }
+}
+
+pub fn save_trace_file(hash: &RomHash, data: &[u8]) -> Result<(), String> {
+    let path = trace_path(hash);
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
+    std::fs::write(&path, data).map_err(|e| format!("error writing '{}': {}", path.display(), e))
+}
+
+fn header_cache_path() -> PathBuf {
+    crate::config::normalize_config_path("header_cache.toml")
+}
+
+/// Load the header cache of the rom list, if there is one.
+pub fn load_header_cache_file() -> Result<Option<String>, String> {
+    let path = header_cache_path();
+    match std::fs::read_to_string(&path) {
+        Ok(x) => Ok(Some(x)),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(format!("error reading '{}': {}", path.display(), err)),
+    }
+}
+
+pub fn save_header_cache_file(text: &str) -> Result<(), String> {
+    let path = header_cache_path();
+    std::fs::write(&path, text).map_err(|e| format!("error writing '{}': {}", path.display(), e))
+}
+
+fn rom_library_path() -> PathBuf {
+    crate::config::normalize_config_path("rom_library.toml")
+}
+
+/// Load the favorites and the launch times of the roms, if there are any.
+pub fn load_rom_library_file() -> Result<Option<String>, String> {
+    let path = rom_library_path();
+    match std::fs::read_to_string(&path) {
+        Ok(x) => Ok(Some(x)),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(format!("error reading '{}': {}", path.display(), err)),
+    }
+}
+
+pub fn save_rom_library_file(text: &str) -> Result<(), String> {
+    let path = rom_library_path();
+    std::fs::write(&path, text).map_err(|e| format!("error writing '{}': {}", path.display(), e))
+}
+
+fn play_log_path() -> PathBuf {
+    crate::config::normalize_config_path("play_log.txt")
+}
+
+/// Load the play log, if there is one. See `PlayLog`.
+pub fn load_play_log_file() -> Result<Option<String>, String> {
+    let path = play_log_path();
+    match std::fs::read_to_string(&path) {
+        Ok(x) => Ok(Some(x)),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(format!("error reading '{}': {}", path.display(), err)),
+    }
+}
+
+/// Append a line to the play log, creating it if needed.
+pub fn append_play_log_file(line: &str) -> Result<(), String> {
+    append_line(&play_log_path(), line)
+}
+
+/// Append `line` to the file at `path` with a single write, so the lines appended by other
+/// processes at the same time are not interleaved with it.
+fn append_line(path: &Path, line: &str) -> Result<(), String> {
+    use std::io::Write;
+
+    let error = |e: std::io::Error| format!("error writing '{}': {}", path.display(), e);
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(error)?;
+    file.write_all(line.as_bytes()).map_err(error)
+}
+
 pub fn load_boot_rom() -> Option<[u8; 256]> {
     let boot_rom_path = if let Some(x) = &config().boot_rom {
         PathBuf::from(x)
@@ -49,33 +240,322 @@ pub fn load_boot_rom() -> Option<[u8; 256]> {
     }
 }
 
+/// Load the file set in `after_boot_sound`, if any.
+pub fn load_after_boot_sound() -> Option<Vec<u8>> {
+    let path = crate::config::normalize_config_path(config().after_boot_sound.as_ref()?);
+    match std::fs::read(&path) {
+        Ok(x) => Some(x),
+        Err(e) => {
+            log::error!("error loading '{}': {}", path.display(), e);
+            None
+        }
+    }
+}
+
+/// Open the folder of `path` in the file manager of the system, with `path` selected. On Linux, the
+/// file is only selected if the file manager implements the `org.freedesktop.FileManager1` D-Bus
+/// interface, otherwise only the folder is opened.
+fn reveal_in_file_manager(path: &Path) -> Result<(), String> {
+    use std::process::Command;
+
+    let path = match std::env::current_dir() {
+        Ok(dir) if path.is_relative() => dir.join(path),
+        _ => path.to_path_buf(),
+    };
+    let error = |x: std::io::Error| format!("error opening '{}': {}", path.display(), x);
+    if cfg!(target_os = "windows") {
+        let mut select = std::ffi::OsString::from("/select,");
+        select.push(&path);
+        return Command::new("explorer")
+            .arg(select)
+            .spawn()
+            .map(|_| ())
+            .map_err(error);
+    }
+    if cfg!(target_os = "macos") {
+        return Command::new("open")
+            .arg("-R")
+            .arg(&path)
+            .spawn()
+            .map(|_| ())
+            .map_err(error);
+    }
+
+    let shown = Command::new("dbus-send")
+        .args([
+            "--session",
+            "--dest=org.freedesktop.FileManager1",
+            "--type=method_call",
+            "/org/freedesktop/FileManager1",
+            "org.freedesktop.FileManager1.ShowItems",
+        ])
+        .arg(format!("array:string:{}", file_uri(&path)))
+        .arg("string:")
+        .status()
+        .map_or(false, |x| x.success());
+    if shown {
+        return Ok(());
+    }
+    let folder = path.parent().unwrap_or(&path);
+    Command::new("xdg-open")
+        .arg(folder)
+        .spawn()
+        .map(|_| ())
+        .map_err(error)
+}
+
+/// The `file://` URI of the absolute path `path`. The bytes other than letters, digits, `-`, `.`,
+/// `_`, `~` and `/` are percent-encoded, including the commas that would split the list of items
+/// passed to `dbus-send`.
+fn file_uri(path: &Path) -> String {
+    let mut uri = String::from("file://");
+    for byte in path.to_string_lossy().bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                uri.push(byte as char)
+            }
+            _ => uri.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    uri
+}
+
+/// The kind of a save of a rom.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SaveKind {
+    /// The battery backed RAM of the cartridge.
+    Ram,
+    /// A save state of the emulator.
+    State,
+}
+
+/// A callback that receives the saves of a in-memory rom, see `RomFile::with_save_callback`.
+pub type SaveCallback = Arc<dyn Fn(SaveKind, &[u8]) -> Result<(), String> + Send + Sync>;
+
+#[derive(Clone)]
+enum RomSource {
+    /// A rom file. Its saves are written next to it.
+    Path(PathBuf),
+    /// A rom inside a zip archive, with the given name. Its saves are written next to the archive,
+    /// named after the rom instead of the archive, so each rom of a archive has its own saves.
+    #[cfg(feature = "zip")]
+    Zip { path: PathBuf, name: String },
+    /// The content of a rom given by a application that embeds the emulator. Its saves are passed
+    /// to `on_save`, if any, or discarded.
+    Bytes {
+        name: String,
+        data: Arc<[u8]>,
+        on_save: Option<SaveCallback>,
+    },
+}
+impl std::fmt::Debug for RomSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Path(path) => f.debug_tuple("Path").field(path).finish(),
+            #[cfg(feature = "zip")]
+            Self::Zip { path, name } => f
+                .debug_struct("Zip")
+                .field("path", path)
+                .field("name", name)
+                .finish(),
+            Self::Bytes { name, data, .. } => f
+                .debug_struct("Bytes")
+                .field("name", name)
+                .field("len", &data.len())
+                .finish(),
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct RomFile {
-    path: PathBuf,
+    source: RomSource,
 }
 impl RomFile {
     pub fn from_path(path: PathBuf) -> Self {
-        Self { path }
+        Self {
+            source: RomSource::Path(path),
+        }
+    }
+
+    /// The rom at the given path, like `from_path`, but if it is a zip archive, the first rom in
+    /// it is used.
+    pub fn open_path(path: PathBuf) -> Self {
+        #[cfg(feature = "zip")]
+        if path
+            .extension()
+            .map_or(false, |x| x.eq_ignore_ascii_case("zip"))
+        {
+            if let Some(rom) = zip_roms(path.clone()).into_iter().next() {
+                return rom;
+            }
+        }
+        Self::from_path(path)
+    }
+
+    /// The rom with the given name inside the zip archive at `path`.
+    #[cfg(feature = "zip")]
+    pub fn from_zip(path: PathBuf, name: impl Into<String>) -> Self {
+        Self {
+            source: RomSource::Zip {
+                path,
+                name: name.into(),
+            },
+        }
+    }
+
+    /// A rom backed by the given bytes, instead of a file. Its saves are discarded, unless a
+    /// callback is set with `with_save_callback`, and it never has a save to be loaded.
+    pub fn from_bytes(name: impl Into<String>, data: impl Into<Arc<[u8]>>) -> Self {
+        Self {
+            source: RomSource::Bytes {
+                name: name.into(),
+                data: data.into(),
+                on_save: None,
+            },
+        }
+    }
+
+    /// Pass the saves of a rom created with `from_bytes` to `callback`, instead of discarding
+    /// them. Roms backed by a file keep saving next to the file.
+    pub fn with_save_callback(
+        mut self,
+        callback: impl Fn(SaveKind, &[u8]) -> Result<(), String> + Send + Sync + 'static,
+    ) -> Self {
+        if let RomSource::Bytes { on_save, .. } = &mut self.source {
+            *on_save = Some(Arc::new(callback));
+        }
+        self
+    }
+
+    /// The path of the file the rom is read from, if it is backed by one. For a rom in a zip
+    /// archive, this is the path of the archive.
+    pub fn path(&self) -> Option<&Path> {
+        match &self.source {
+            RomSource::Path(path) => Some(path),
+            #[cfg(feature = "zip")]
+            RomSource::Zip { path, .. } => Some(path),
+            RomSource::Bytes { .. } => None,
+        }
+    }
+
+    /// The path of a file that accompanies the rom, like its saves, with the same name as the rom
+    /// and the given extension, in the same folder.
+    fn companion_path(&self, extension: &str) -> Option<PathBuf> {
+        match &self.source {
+            RomSource::Path(path) => Some(path.with_extension(extension)),
+            #[cfg(feature = "zip")]
+            RomSource::Zip { path, .. } => Some(
+                path.with_file_name(self.file_name().as_ref())
+                    .with_extension(extension),
+            ),
+            RomSource::Bytes { .. } => None,
+        }
+    }
+
+    /// The path, size and modification time of the file the rom is read from, used to known if
+    /// its cached header is up to date. In-memory roms have no identity.
+    pub fn identity(&self) -> Option<FileIdentity> {
+        let (file, path) = match &self.source {
+            RomSource::Path(path) => (path, path.display().to_string()),
+            #[cfg(feature = "zip")]
+            RomSource::Zip { path, name } => (path, format!("{}#{}", path.display(), name)),
+            RomSource::Bytes { .. } => return None,
+        };
+        let metadata = std::fs::metadata(file).ok()?;
+        let modified = metadata
+            .modified()
+            .ok()?
+            .duration_since(instant::SystemTime::UNIX_EPOCH)
+            .map_or(0, |x| x.as_millis() as u64);
+        Some(FileIdentity {
+            path,
+            size: metadata.len(),
+            modified,
+        })
+    }
+
+    /// A string that identifies the rom across runs, in the `recent_roms` config and in the
+    /// `RomLibrary`, and from which it can be opened again with `from_recent_key`. In-memory roms
+    /// can't be reopened, so they have no key.
+    pub fn recent_key(&self) -> Option<String> {
+        match &self.source {
+            RomSource::Path(path) => Some(path.display().to_string()),
+            #[cfg(feature = "zip")]
+            RomSource::Zip { path, name } => Some(format!("{}#{}", path.display(), name)),
+            RomSource::Bytes { .. } => None,
+        }
+    }
+
+    /// The rom identified by a key returned by `recent_key`. The file may no longer exist.
+    pub fn from_recent_key(key: &str) -> Self {
+        #[cfg(feature = "zip")]
+        if let Some((path, name)) = key.split_once(".zip#") {
+            return Self::from_zip(PathBuf::from(format!("{}.zip", path)), name);
+        }
+        Self::from_path(PathBuf::from(key))
     }
 
     pub async fn get_header(&self) -> Result<CartridgeHeader, String> {
-        let path = self.path.clone();
-        let mut file = std::fs::File::open(path).map_err(|e| format!("io error: {}", e))?;
-        match CartridgeHeader::from_reader(&mut file) {
+        let header = match &self.source {
+            RomSource::Path(path) => {
+                let mut file = std::fs::File::open(path).map_err(|e| format!("io error: {}", e))?;
+                CartridgeHeader::from_reader(&mut file)
+            }
+            #[cfg(feature = "zip")]
+            RomSource::Zip { path, name } => with_zip_entry(path, name, |mut entry| {
+                Ok(CartridgeHeader::from_reader(&mut entry))
+            })?,
+            RomSource::Bytes { data, .. } => CartridgeHeader::from_bytes(data),
+        };
+        match header {
             Ok(x) | Err((Some(x), _)) => Ok(x),
             Err((_, e)) => Err(e),
         }
     }
 
+    /// Compute the hash of the rom, reading the file in chunks, instead of loading it entirely.
+    pub async fn hash(&self) -> Result<RomHash, String> {
+        let rom_path = match &self.source {
+            RomSource::Path(path) => path,
+            #[cfg(feature = "zip")]
+            RomSource::Zip { path, name } => {
+                return with_zip_entry(path, name, |entry| hash_reader(entry, name))
+            }
+            RomSource::Bytes { data, .. } => return Ok(RomHash::from_bytes(data)),
+        };
+        let mut file = std::fs::File::open(rom_path)
+            .map_err(|x| format!("error loading '{}': {}", rom_path.display(), x))?;
+
+        hash_reader(&mut file, &rom_path.display().to_string())
+    }
+
     pub fn file_name(&self) -> Cow<str> {
-        self.path
-            .file_name()
-            .map_or("".into(), |x| x.to_string_lossy())
+        match &self.source {
+            RomSource::Path(path) => path.file_name().map_or("".into(), |x| x.to_string_lossy()),
+            // the name may include the folders inside the archive
+            #[cfg(feature = "zip")]
+            RomSource::Zip { name, .. } => name.rsplit('/').next().unwrap_or(name).into(),
+            RomSource::Bytes { name, .. } => name.as_str().into(),
+        }
     }
 
     pub async fn read(&self) -> Result<Vec<u8>, String> {
+        let rom_path = match &self.source {
+            RomSource::Path(path) => path,
+            #[cfg(feature = "zip")]
+            RomSource::Zip { path, name } => {
+                return with_zip_entry(path, name, |entry| {
+                    let mut rom = Vec::new();
+                    entry
+                        .read_to_end(&mut rom)
+                        .map_err(|x| format!("error reading '{}': {}", name, x))?;
+                    Ok(rom)
+                })
+            }
+            RomSource::Bytes { data, .. } => return Ok(data.to_vec()),
+        };
         let mut rom = Vec::new();
-        let rom_path = &self.path;
         let file = &mut std::fs::File::open(&rom_path)
             .map_err(|x| format!("error loading '{}': {}", rom_path.display(), x))?;
 
@@ -86,36 +566,166 @@ impl RomFile {
     }
 
     pub async fn load_ram_data(&self) -> Result<Vec<u8>, String> {
-        let save_path = self.save_path();
+        let save_path = self.save_path()?;
         log::info!("loading save at {}", save_path.display());
         std::fs::read(&save_path).map_err(|x| format!("load save failed: {}", x))
     }
 
-    fn save_path(&self) -> PathBuf {
-        self.path.with_extension("sav")
+    /// Read the symbol file next to the rom, with the same name and the `.sym` extension, if there
+    /// is one.
+    pub fn load_symbols_file(&self) -> Result<Option<String>, String> {
+        let path = match self.companion_path("sym") {
+            Some(path) => path,
+            None => return Ok(None),
+        };
+        match std::fs::read_to_string(&path) {
+            Ok(x) => Ok(Some(x)),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(format!("error reading '{}': {}", path.display(), err)),
+        }
+    }
+
+    /// The path of the thumbnail of the rom in the rom list: a screenshot or boxart next to the
+    /// rom, with the same name and the `.png` extension.
+    pub fn thumbnail_path(&self) -> Option<PathBuf> {
+        self.companion_path("png")
+    }
+
+    /// Read the png thumbnail of the rom, if there is one.
+    pub fn load_thumbnail(&self) -> Result<Option<Vec<u8>>, String> {
+        let path = match self.thumbnail_path() {
+            Some(path) => path,
+            None => return Ok(None),
+        };
+        match std::fs::read(&path) {
+            Ok(x) => Ok(Some(x)),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(format!("error reading '{}': {}", path.display(), err)),
+        }
+    }
+
+    /// The path of a save of the rom, next to the rom file. In-memory roms have no saves.
+    fn save_path_with_extension(&self, extension: &str) -> Result<PathBuf, String> {
+        match self.companion_path(extension) {
+            Some(path) => Ok(path),
+            None => Err(format!(
+                "the in-memory rom '{}' has no saves",
+                self.file_name()
+            )),
+        }
+    }
+
+    fn save_path(&self) -> Result<PathBuf, String> {
+        self.save_path_with_extension("sav")
     }
 
-    fn save_state_path(&self) -> PathBuf {
-        self.path.with_extension("save_state")
+    fn save_state_path(&self) -> Result<PathBuf, String> {
+        self.save_path_with_extension("save_state")
+    }
+
+    /// Pass a save of a in-memory rom to its callback. Return `None` if the rom is backed by a
+    /// file.
+    fn save_to_callback(&self, kind: SaveKind, data: &[u8]) -> Option<Result<(), String>> {
+        match &self.source {
+            RomSource::Bytes { on_save, .. } => Some(match on_save {
+                Some(on_save) => on_save(kind, data),
+                None => Ok(()),
+            }),
+            _ => None,
+        }
     }
 
     pub fn save_ram_data(&self, data: &[u8]) -> Result<(), String> {
-        let save_path = self.save_path();
+        if let Some(result) = self.save_to_callback(SaveKind::Ram, data) {
+            return result;
+        }
+        let save_path = self.save_path()?;
         std::fs::write(save_path, data).map_err(|x| x.to_string())
     }
 
     pub fn save_state(&self, state: &[u8]) -> Result<(), String> {
-        let save_path = self.save_state_path();
+        if let Some(result) = self.save_to_callback(SaveKind::State, state) {
+            return result;
+        }
+        let save_path = self.save_state_path()?;
         std::fs::write(save_path, state).map_err(|x| x.to_string())
     }
 
     pub fn load_state(&self) -> Result<Vec<u8>, String> {
-        let save_path = self.save_state_path();
+        let save_path = self.save_state_path()?;
         std::fs::read(save_path).map_err(|x| x.to_string())
     }
 
+    /// Read the start of the save state of the rom, up to `STATE_HEADER_LEN` bytes, without reading
+    /// the whole state. Return `None` if there is no save state.
+    pub fn read_state_header(&self) -> Result<Option<Vec<u8>>, String> {
+        use std::io::Read;
+
+        let path = match self.companion_path("save_state") {
+            Some(x) => x,
+            None => return Ok(None),
+        };
+        let file = match std::fs::File::open(&path) {
+            Ok(x) => x,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(format!("error opening '{}': {}", path.display(), err)),
+        };
+        let mut header = Vec::with_capacity(super::STATE_HEADER_LEN);
+        file.take(super::STATE_HEADER_LEN as u64)
+            .read_to_end(&mut header)
+            .map_err(|err| format!("error reading '{}': {}", path.display(), err))?;
+        Ok(Some(header))
+    }
+
+    /// Delete the save of the rom with the given extension, if there is one. The rom itself is
+    /// never deleted, even if the save would have its path.
+    fn delete_save(&self, extension: &str) -> Result<(), String> {
+        let path = self.save_path_with_extension(extension)?;
+        if self.path() == Some(path.as_path()) {
+            return Err(format!("refusing to delete the rom '{}'", path.display()));
+        }
+        match std::fs::remove_file(&path) {
+            Ok(()) => {
+                log::info!("deleted '{}'", path.display());
+                Ok(())
+            }
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(err) => Err(format!("error deleting '{}': {}", path.display(), err)),
+        }
+    }
+
+    /// Delete the battery save of the rom, if there is one.
+    pub fn delete_ram_data(&self) -> Result<(), String> {
+        self.delete_save("sav")
+    }
+
+    /// Delete the save state of the rom, if there is one.
+    pub fn delete_state(&self) -> Result<(), String> {
+        self.delete_save("save_state")
+    }
+
+    /// The file selected when opening the containing folder of the rom: its battery save, or else
+    /// its save state, if `exists`, or else the rom file itself, or its zip archive. In-memory roms
+    /// have none.
+    fn reveal_path(&self, exists: impl Fn(&Path) -> bool) -> Option<PathBuf> {
+        ["sav", "save_state"]
+            .iter()
+            .filter_map(|x| self.companion_path(x))
+            .find(|x| exists(x))
+            .or_else(|| self.path().map(Path::to_path_buf))
+    }
+
+    /// Open the folder that contains the rom, or the zip archive of the rom, in the file manager
+    /// of the system, with the save of the rom selected, if there is one. See `reveal_path`.
+    pub fn open_containing_folder(&self) -> Result<(), String> {
+        match self.reveal_path(|x| x.exists()) {
+            Some(path) => reveal_in_file_manager(&path),
+            None => Err(format!("the rom '{}' is not in a folder", self.file_name())),
+        }
+    }
+
     pub fn get_save_time(&self) -> Result<u64, String> {
-        let save_path = self.save_path();
+        let save_path = self.save_path()?;
         let data = std::fs::metadata(&save_path)
             .map_err(|err| format!("Failed getting '{}' metadata: {}", save_path.display(), err))?;
 
@@ -130,12 +740,365 @@ impl RomFile {
             .duration_since(instant::SystemTime::UNIX_EPOCH)
             .map_or(0, |x| x.as_millis() as u64))
     }
+
+    /// Save the screen, given by the color of each pixel in row-major order, as a png in the
+    /// `screenshots` folder, named by the rom file and a number, like "Tetris_3.png". Return the
+    /// path of the png.
+    pub fn save_screenshot(&self, pixels: &[[u8; 3]]) -> Result<PathBuf, String> {
+        let folder = crate::config::normalize_config_path("screenshots");
+        std::fs::create_dir_all(&folder).map_err(|e| e.to_string())?;
+        let path = screenshot_path(&folder, &self.file_name(), |x| x.exists());
+        image::save_buffer_with_format(
+            &path,
+            &pixels.concat(),
+            crate::SCREEN_WIDTH as u32,
+            crate::SCREEN_HEIGHT as u32,
+            image::ColorType::Rgb8,
+            image::ImageFormat::Png,
+        )
+        .map_err(|e| format!("error writing '{}': {}", path.display(), e))?;
+        Ok(path)
+    }
+}
+
+/// The first path in `folder` of a screenshot of the rom with the given file name that doesn't
+/// `exists`, in the format "<file stem>_<number>.png", counting from 1.
+fn screenshot_path(folder: &Path, file_name: &str, exists: impl Fn(&Path) -> bool) -> PathBuf {
+    let stem = Path::new(file_name)
+        .file_stem()
+        .map_or("screenshot".into(), |x| x.to_string_lossy());
+    (1..)
+        .map(|i| folder.join(format!("{}_{}.png", stem, i)))
+        .find(|x| !exists(x))
+        .unwrap()
+}
+impl From<PathBuf> for RomFile {
+    fn from(path: PathBuf) -> Self {
+        Self::from_path(path)
+    }
 }
 #[cfg(feature = "rfd")]
 impl From<rfd::FileHandle> for RomFile {
+    /// The rom at the path of the handle, see `RomFile::open_path`.
     fn from(handle: rfd::FileHandle) -> Self {
-        Self {
-            path: handle.inner().to_path_buf(),
+        Self::open_path(handle.inner().to_path_buf())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::executor::block_on;
+
+    fn rom() -> Vec<u8> {
+        let mut rom = vec![0; 0x8000];
+        rom[0x134..0x138].copy_from_slice(b"TEST");
+        rom[0x147] = 0x03; // MBC1+RAM+BATTERY
+        rom[0x149] = 0x02; // 8 KiB
+        rom
+    }
+
+    #[test]
+    fn in_memory_rom() {
+        let rom = rom();
+        let saves = Arc::new(Mutex::new(Vec::new()));
+        let file = {
+            let saves = saves.clone();
+            RomFile::from_bytes("test.gb", rom.clone()).with_save_callback(move |kind, data| {
+                saves.lock().unwrap().push((kind, data.to_vec()));
+                Ok(())
+            })
+        };
+
+        assert_eq!(file.file_name(), "test.gb");
+        assert_eq!(file.path(), None);
+        let header = block_on(Box::pin(file.get_header()).as_mut()).unwrap();
+        assert_eq!(header.title_as_string(), "TEST");
+        let hash = block_on(Box::pin(file.hash()).as_mut()).unwrap();
+        assert_eq!(hash.sha1_hex(), RomHash::from_bytes(&rom).sha1_hex());
+
+        let data = block_on(Box::pin(file.read()).as_mut()).unwrap();
+        assert_eq!(data, rom);
+        let game_boy = crate::rom_loading::load_gameboy(data, None).unwrap();
+        assert_eq!(game_boy.cartridge.rom, rom);
+
+        // there are no saves to load, and they go to the callback
+        assert!(block_on(Box::pin(file.load_ram_data()).as_mut()).is_err());
+        assert!(file.load_state().is_err());
+        file.save_ram_data(&[1, 2, 3]).unwrap();
+        file.save_state(&[4, 5]).unwrap();
+        assert_eq!(
+            *saves.lock().unwrap(),
+            [
+                (SaveKind::Ram, vec![1, 2, 3]),
+                (SaveKind::State, vec![4, 5])
+            ]
+        );
+
+        // without a callback, the saves are discarded
+        let file = RomFile::from_bytes("test.gb", rom);
+        assert_eq!(file.save_ram_data(&[1, 2, 3]), Ok(()));
+    }
+
+    #[test]
+    fn thumbnail_path() {
+        let file = RomFile::from_path(PathBuf::from("roms/Some Game (v1.1).gb"));
+        assert_eq!(
+            file.thumbnail_path(),
+            Some(PathBuf::from("roms/Some Game (v1.1).png"))
+        );
+
+        #[cfg(feature = "zip")]
+        {
+            let file = RomFile::from_zip(PathBuf::from("roms/pack.zip"), "games/Other.gbc");
+            assert_eq!(file.thumbnail_path(), Some(PathBuf::from("roms/Other.png")));
+        }
+
+        let file = RomFile::from_bytes("test.gb", rom());
+        assert_eq!(file.thumbnail_path(), None);
+        assert_eq!(file.load_thumbnail(), Ok(None));
+    }
+
+    #[test]
+    fn reveal_path() {
+        fn exists(paths: &'static [&'static str]) -> impl Fn(&Path) -> bool {
+            move |x| paths.iter().any(|&p| x == Path::new(p))
+        }
+
+        let file = RomFile::from_path(PathBuf::from("roms/Game.gb"));
+        assert_eq!(
+            file.reveal_path(exists(&[])),
+            Some(PathBuf::from("roms/Game.gb"))
+        );
+        assert_eq!(
+            file.reveal_path(exists(&["roms/Game.save_state"])),
+            Some(PathBuf::from("roms/Game.save_state"))
+        );
+        assert_eq!(
+            file.reveal_path(exists(&["roms/Game.save_state", "roms/Game.sav"])),
+            Some(PathBuf::from("roms/Game.sav"))
+        );
+
+        // the saves of a rom in a zip archive are next to the archive
+        #[cfg(feature = "zip")]
+        {
+            let file = RomFile::from_zip(PathBuf::from("roms/pack.zip"), "games/Other.gb");
+            assert_eq!(
+                file.reveal_path(exists(&[])),
+                Some(PathBuf::from("roms/pack.zip"))
+            );
+            assert_eq!(
+                file.reveal_path(exists(&["roms/Other.sav"])),
+                Some(PathBuf::from("roms/Other.sav"))
+            );
+        }
+
+        let file = RomFile::from_bytes("test.gb", rom());
+        assert_eq!(file.reveal_path(|_| true), None);
+
+        assert_eq!(
+            file_uri(Path::new("/home/me/roms/A, B (v1.1)%.sav")),
+            "file:///home/me/roms/A%2C%20B%20%28v1.1%29%25.sav"
+        );
+    }
+
+    #[test]
+    fn screenshot_paths() {
+        fn taken(paths: &'static [&'static str]) -> impl Fn(&Path) -> bool {
+            move |x| paths.iter().any(|&p| x == Path::new("screenshots").join(p))
         }
+        let folder = Path::new("screenshots");
+        assert_eq!(
+            screenshot_path(folder, "Game (USA).gb", taken(&[])),
+            folder.join("Game (USA)_1.png")
+        );
+        assert_eq!(
+            screenshot_path(folder, "Game.gbc", taken(&["Game_1.png", "Game_2.png"])),
+            folder.join("Game_3.png")
+        );
+        // the numbers taken by other roms don't matter
+        assert_eq!(
+            screenshot_path(folder, "Game.gb", taken(&["Other_1.png"])),
+            folder.join("Game_1.png")
+        );
+        assert_eq!(
+            screenshot_path(folder, "", taken(&[])),
+            folder.join("screenshot_1.png")
+        );
+    }
+
+    #[test]
+    fn delete_saves() {
+        let dir = std::env::temp_dir().join("gameroy_delete_saves");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let rom_path = dir.join("test.gb");
+        std::fs::write(&rom_path, rom()).unwrap();
+
+        let file = RomFile::from_path(rom_path.clone());
+        file.save_ram_data(&[1, 2, 3]).unwrap();
+        file.save_state(&[4, 5]).unwrap();
+        assert!(file.get_save_time().is_ok());
+
+        file.delete_ram_data().unwrap();
+        assert!(!dir.join("test.sav").exists());
+        assert!(file.get_save_time().is_err());
+        assert_eq!(file.load_state().unwrap(), [4, 5]);
+
+        file.delete_state().unwrap();
+        assert!(!dir.join("test.save_state").exists());
+        // deleting a save that doesn't exist is not a error
+        file.delete_state().unwrap();
+
+        // a rom with the extension of a save is never deleted
+        let sav_rom = RomFile::from_path(dir.join("other.sav"));
+        std::fs::write(dir.join("other.sav"), rom()).unwrap();
+        assert!(sav_rom.delete_ram_data().is_err());
+        assert!(dir.join("other.sav").exists());
+
+        assert_eq!(std::fs::read(&rom_path).unwrap(), rom());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn concurrent_appends() {
+        use crate::rom_loading::PlayLog;
+
+        let dir = std::env::temp_dir().join("gameroy_concurrent_appends");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("play_log.txt");
+
+        // like two instances of the app playing at the same time
+        let threads: Vec<_> = ["roms/a.gb", "roms/b.gb"]
+            .into_iter()
+            .map(|key| {
+                let path = path.clone();
+                std::thread::spawn(move || {
+                    for start in 0..200 {
+                        let line = format!("{}\t{}\t{}\n", start, 10, key);
+                        append_line(&path, &line).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        let text = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(text.lines().count(), 400);
+        let log = PlayLog::from_text(&text);
+        assert_eq!(log.stats("roms/a.gb").unwrap().play_time, 2000);
+        assert_eq!(log.stats("roms/b.gb").unwrap().play_time, 2000);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn state_header() {
+        use crate::rom_loading::{StateInfo, STATE_HEADER_LEN};
+        use gameroy::save_state::SaveState;
+
+        let dir = std::env::temp_dir().join("gameroy_state_header");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = RomFile::from_path(dir.join("test.gb"));
+        assert_eq!(file.read_state_header(), Ok(None));
+
+        let game_boy = crate::rom_loading::load_gameboy(rom(), None).unwrap();
+        let mut state = Vec::new();
+        game_boy.save_state(&mut state).unwrap();
+        file.save_state(&state).unwrap();
+        let header = file.read_state_header().unwrap().unwrap();
+        assert_eq!(header, state[..STATE_HEADER_LEN]);
+        assert!(matches!(
+            StateInfo::from_header(&header),
+            StateInfo::Valid(Some(_))
+        ));
+
+        // a state from a newer version can't be loaded
+        state[4..8].copy_from_slice(&99u32.to_be_bytes());
+        file.save_state(&state).unwrap();
+        let header = file.read_state_header().unwrap().unwrap();
+        assert!(matches!(
+            StateInfo::from_header(&header),
+            StateInfo::Invalid(_)
+        ));
+        assert!(matches!(
+            StateInfo::from_header(b"GRST"),
+            StateInfo::Invalid(_)
+        ));
+
+        // the in-memory roms have no save states
+        let file = RomFile::from_bytes("test.gb", rom());
+        assert_eq!(file.read_state_header(), Ok(None));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(feature = "zip")]
+    fn zip_archive(files: &[(&str, &[u8])]) -> Vec<u8> {
+        use std::io::Write;
+
+        let mut writer = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+        for (name, data) in files {
+            writer
+                .start_file(*name, zip::write::FileOptions::default())
+                .unwrap();
+            writer.write_all(data).unwrap();
+        }
+        writer.finish().unwrap().into_inner()
+    }
+
+    #[cfg(feature = "zip")]
+    #[test]
+    fn roms_in_zip() {
+        let rom = rom();
+        let mut other = rom.clone();
+        other[0x134..0x138].copy_from_slice(b"OTHR");
+        let archive = zip_archive(&[
+            ("readme.txt", b"not a rom"),
+            ("games/test.gb", &rom),
+            ("other.GBC", &other),
+        ]);
+
+        let dir = std::env::temp_dir().join("gameroy_roms_in_zip");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("roms.zip"), archive).unwrap();
+
+        // each rom in the archive is its own entry
+        let folder = dir.to_str().unwrap().to_string();
+        let roms = load_roms(&[folder.clone(), folder]);
+        let names: Vec<_> = roms.iter().map(|x| x.file_name().into_owned()).collect();
+        assert_eq!(names, ["test.gb", "other.GBC"]);
+        let header = block_on(Box::pin(roms[1].get_header()).as_mut()).unwrap();
+        assert_eq!(header.title_as_string(), "OTHR");
+
+        let file = &roms[0];
+        let zip_path = std::fs::canonicalize(dir.join("roms.zip")).unwrap();
+        assert_eq!(file.path(), Some(zip_path.as_path()));
+        let header = block_on(Box::pin(file.get_header()).as_mut()).unwrap();
+        assert_eq!(header.title_as_string(), "TEST");
+        let hash = block_on(Box::pin(file.hash()).as_mut()).unwrap();
+        assert_eq!(hash.sha1_hex(), RomHash::from_bytes(&rom).sha1_hex());
+
+        let data = block_on(Box::pin(file.read()).as_mut()).unwrap();
+        assert_eq!(data, rom);
+        let game_boy = crate::rom_loading::load_gameboy(data, None).unwrap();
+        assert_eq!(game_boy.cartridge.rom, rom);
+
+        // the saves are next to the archive, named after the rom
+        file.save_ram_data(&[1, 2, 3]).unwrap();
+        assert_eq!(std::fs::read(dir.join("test.sav")).unwrap(), [1, 2, 3]);
+        let ram = block_on(Box::pin(file.load_ram_data()).as_mut()).unwrap();
+        assert_eq!(ram, [1, 2, 3]);
+
+        // a archive opened directly, like a dropped one, plays its first rom
+        let opened = RomFile::open_path(dir.join("roms.zip"));
+        assert_eq!(opened.file_name(), "test.gb");
+
+        std::fs::remove_dir_all(&dir).unwrap();
     }
 }
@@ -0,0 +1,220 @@
+use std::collections::{BTreeMap, HashMap};
+
+use instant::{Duration, Instant};
+
+/// The interval between the writes of the duration of a running session, so a crash only loses
+/// the time played since the last write.
+const UPDATE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// A session of play of a rom, as recorded in the play log.
+///
+/// The log is a text file with a record per line, in the format `start\tduration\tkey`, where
+/// `start` is when the game was launched and `duration` how long it was played, both in
+/// milliseconds, and `key` the key given by `RomFile::recent_key`. The file is only appended to,
+/// a whole line at once, so two instances writing to it at the same time don't corrupt it. A
+/// running session is written again each `UPDATE_INTERVAL` with its new duration, and the record
+/// with the longest duration is the one that counts.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlayRecord {
+    pub key: String,
+    /// When the session started, in milliseconds since epoch.
+    pub start: u64,
+    /// How long the session lasted, in milliseconds.
+    pub duration: u64,
+}
+impl PlayRecord {
+    /// Parse a line of the play log. Returns `None` for invalid lines, like one cut short by a
+    /// crash.
+    pub fn parse(line: &str) -> Option<Self> {
+        let mut fields = line.splitn(3, '\t');
+        let start = fields.next()?.parse().ok()?;
+        let duration = fields.next()?.parse().ok()?;
+        let key = fields.next()?;
+        if key.is_empty() {
+            return None;
+        }
+        Some(Self {
+            key: key.to_string(),
+            start,
+            duration,
+        })
+    }
+
+    /// The line of the play log for this record, including the line break.
+    pub fn to_line(&self) -> String {
+        format!("{}\t{}\t{}\n", self.start, self.duration, self.key)
+    }
+}
+
+/// The time a rom was played, summed over all its sessions.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct PlayStats {
+    /// The total duration of the sessions, in milliseconds.
+    pub play_time: u64,
+    /// When the last session ended, in milliseconds since epoch.
+    pub last_played: u64,
+}
+
+/// The play time of each rom, aggregated from the play log.
+#[derive(Debug, Default)]
+pub struct PlayLog {
+    roms: HashMap<String, PlayStats>,
+}
+impl PlayLog {
+    /// Load and aggregate the play log file. If it doesn't exist or can't be read, a empty log is
+    /// returned.
+    pub fn load() -> Self {
+        match super::load_play_log_file() {
+            Ok(Some(text)) => Self::from_text(&text),
+            Ok(None) => Self::default(),
+            Err(err) => {
+                log::error!("error loading play log: {}", err);
+                Self::default()
+            }
+        }
+    }
+
+    /// Aggregate the records of a play log file. Invalid lines are ignored.
+    pub fn from_text(text: &str) -> Self {
+        // the last duration written of each session, by its rom and start
+        let mut sessions: BTreeMap<(String, u64), u64> = BTreeMap::new();
+        for line in text.lines() {
+            let record = match PlayRecord::parse(line) {
+                Some(x) => x,
+                None => {
+                    log::warn!("invalid line in the play log: {:?}", line);
+                    continue;
+                }
+            };
+            let duration = sessions.entry((record.key, record.start)).or_default();
+            *duration = (*duration).max(record.duration);
+        }
+
+        let mut roms: HashMap<String, PlayStats> = HashMap::new();
+        for ((key, start), duration) in sessions {
+            let stats = roms.entry(key).or_default();
+            stats.play_time += duration;
+            stats.last_played = stats.last_played.max(start + duration);
+        }
+        Self { roms }
+    }
+
+    /// The play time of the rom with the given key, if it was ever played.
+    pub fn stats(&self, key: &str) -> Option<PlayStats> {
+        self.roms.get(key).copied()
+    }
+}
+
+/// Records a session of play in the play log, while the game is running. The session is written
+/// every `UPDATE_INTERVAL`, and when this is dropped.
+pub struct PlaySession {
+    record: PlayRecord,
+    started: Instant,
+    last_write: Instant,
+}
+impl PlaySession {
+    /// Start a session for the rom with the given key. Returns `None` if the key has a line
+    /// break, which can't be written in the log.
+    pub fn start(key: String) -> Option<Self> {
+        if key.contains(['\n', '\r']) {
+            log::warn!("the play time of {:?} is not recorded", key);
+            return None;
+        }
+        let now = Instant::now();
+        Some(Self {
+            record: PlayRecord {
+                key,
+                start: super::now_millis(),
+                duration: 0,
+            },
+            started: now,
+            last_write: now,
+        })
+    }
+
+    /// Write the session, if `UPDATE_INTERVAL` has passed since the last write.
+    pub fn update(&mut self) {
+        if self.last_write.elapsed() >= UPDATE_INTERVAL {
+            self.write();
+        }
+    }
+
+    fn write(&mut self) {
+        self.last_write = Instant::now();
+        self.record.duration = self.started.elapsed().as_millis() as u64;
+        if let Err(err) = super::append_play_log_file(&self.record.to_line()) {
+            log::error!("error writing play log: {}", err);
+        }
+    }
+}
+impl Drop for PlaySession {
+    fn drop(&mut self) {
+        self.write();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{PlayLog, PlayRecord, PlayStats};
+
+    fn line(key: &str, start: u64, duration: u64) -> String {
+        PlayRecord {
+            key: key.to_string(),
+            start,
+            duration,
+        }
+        .to_line()
+    }
+
+    #[test]
+    fn aggregate() {
+        let text = [
+            // a session written three times, while running
+            line("roms/a.gb", 1000, 60),
+            line("roms/a.gb", 1000, 120),
+            line("roms/b.gb", 1050, 60),
+            line("roms/a.gb", 1000, 150),
+            // a second session, in another instance
+            line("roms/a.gb", 5000, 10),
+            // keys can have tabs
+            line("roms/c\tc.gb", 100, 5),
+            // a line cut short by a crash
+            "6000\t".to_string(),
+            "garbage\n".to_string(),
+        ]
+        .concat();
+
+        let log = PlayLog::from_text(&text);
+        assert_eq!(
+            log.stats("roms/a.gb"),
+            Some(PlayStats {
+                play_time: 160,
+                last_played: 5010,
+            })
+        );
+        assert_eq!(
+            log.stats("roms/b.gb"),
+            Some(PlayStats {
+                play_time: 60,
+                last_played: 1110,
+            })
+        );
+        assert_eq!(log.stats("roms/c\tc.gb").unwrap().play_time, 5);
+        assert_eq!(log.stats("roms/d.gb"), None);
+        assert_eq!(PlayLog::from_text("").stats("roms/a.gb"), None);
+    }
+
+    #[test]
+    fn round_trip() {
+        let record = PlayRecord {
+            key: "roms/b \"quoted\".zip#b.gb".to_string(),
+            start: 1_651_410_300_000,
+            duration: 45_296_000,
+        };
+        let line = record.to_line();
+        assert!(line.ends_with('\n'));
+        assert_eq!(PlayRecord::parse(line.trim_end()), Some(record));
+        assert_eq!(PlayRecord::parse("1\t2\t"), None);
+        assert_eq!(PlayRecord::parse("1\tx\ta.gb"), None);
+    }
+}
@@ -0,0 +1,122 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use once_cell::sync::Lazy;
+use parking_lot::{Mutex, MutexGuard};
+use serde::{Deserialize, Serialize};
+
+/// The information about the roms that is kept across runs, but is not part of the config: the
+/// favorite roms, and when each rom was last launched.
+///
+/// The roms are identified by the key given by `RomFile::recent_key`.
+#[derive(Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RomLibrary {
+    /// The keys of the favorite roms.
+    favorites: BTreeSet<String>,
+    /// The last time each rom was launched, in milliseconds since epoch, by its key.
+    last_launch: BTreeMap<String, u64>,
+}
+
+static LIBRARY: Lazy<Mutex<RomLibrary>> = Lazy::new(|| Mutex::new(RomLibrary::load()));
+
+/// The rom library, loaded from its file on the first access.
+pub fn rom_library() -> MutexGuard<'static, RomLibrary> {
+    LIBRARY.lock()
+}
+
+impl RomLibrary {
+    /// Load the library from its file. If it doesn't exist or is corrupted, a empty library is
+    /// returned.
+    fn load() -> Self {
+        match super::load_rom_library_file() {
+            Ok(Some(text)) => Self::from_text(&text),
+            Ok(None) => Self::default(),
+            Err(err) => {
+                log::error!("error loading rom library: {}", err);
+                Self::default()
+            }
+        }
+    }
+
+    /// Parse the content of a library file. If it is corrupted, a empty library is returned.
+    pub fn from_text(text: &str) -> Self {
+        toml::from_str(text).unwrap_or_else(|err| {
+            log::error!("error parsing rom library: {}", err);
+            Self::default()
+        })
+    }
+
+    /// Serialize the library in the format read by `from_text`.
+    pub fn to_text(&self) -> String {
+        toml::to_string(self).unwrap()
+    }
+
+    /// Write the library to its file.
+    pub fn save(&self) {
+        if let Err(err) = super::save_rom_library_file(&self.to_text()) {
+            log::error!("error saving rom library: {}", err);
+        }
+    }
+
+    pub fn is_favorite(&self, key: &str) -> bool {
+        self.favorites.contains(key)
+    }
+
+    pub fn set_favorite(&mut self, key: &str, favorite: bool) {
+        if favorite {
+            self.favorites.insert(key.to_string());
+        } else {
+            self.favorites.remove(key);
+        }
+    }
+
+    /// The last time the rom was launched, in milliseconds since epoch.
+    pub fn last_launch(&self, key: &str) -> Option<u64> {
+        self.last_launch.get(key).copied()
+    }
+
+    /// Record that the rom was launched at `time`, in milliseconds since epoch.
+    pub fn record_launch(&mut self, key: &str, time: u64) {
+        self.last_launch.insert(key.to_string(), time);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::RomLibrary;
+
+    #[test]
+    fn round_trip() {
+        let mut library = RomLibrary::default();
+        library.set_favorite("roms/a.gb", true);
+        library.set_favorite("roms/b \"quoted\".zip#b.gb", true);
+        library.set_favorite("roms/a.gb", true);
+        library.record_launch("roms/a.gb", 100);
+        library.record_launch("roms/c.gb", 200);
+        library.record_launch("roms/a.gb", 300);
+
+        let library = RomLibrary::from_text(&library.to_text());
+        assert!(library.is_favorite("roms/a.gb"));
+        assert!(library.is_favorite("roms/b \"quoted\".zip#b.gb"));
+        assert!(!library.is_favorite("roms/c.gb"));
+        assert_eq!(library.last_launch("roms/a.gb"), Some(300));
+        assert_eq!(library.last_launch("roms/c.gb"), Some(200));
+        assert_eq!(library.last_launch("roms/b.gb"), None);
+    }
+
+    #[test]
+    fn unfavorite() {
+        let mut library = RomLibrary::default();
+        library.set_favorite("a.gb", true);
+        library.set_favorite("a.gb", false);
+        library.set_favorite("b.gb", false);
+        assert!(!library.is_favorite("a.gb"));
+        assert_eq!(library, RomLibrary::default());
+    }
+
+    #[test]
+    fn discard_invalid() {
+        assert_eq!(RomLibrary::from_text(""), RomLibrary::default());
+        assert_eq!(RomLibrary::from_text("\u{0}garbage"), RomLibrary::default());
+    }
+}
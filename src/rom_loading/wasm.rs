@@ -1,15 +1,22 @@
-use std::borrow::Cow;
+use std::{borrow::Cow, cell::Cell, rc::Rc};
 
 use gameroy::gameboy::cartridge::CartridgeHeader;
 use wasm_bindgen::{closure::Closure, JsCast, JsValue};
+use web_sys::{IdbDatabase, IdbRequest, IdbTransactionMode};
 
-pub fn load_roms(_roms_path: &str) -> Result<Vec<RomFile>, String> {
-    Ok(Vec::new())
+use crate::game_database::RomHash;
+
+pub fn load_roms(_roms_paths: &[String]) -> Vec<RomFile> {
+    Vec::new()
 }
 
 pub fn load_boot_rom() -> Option<[u8; 256]> {
     None
 }
+
+pub fn load_after_boot_sound() -> Option<Vec<u8>> {
+    None
+}
 pub fn load_file(file_name: &str) -> Result<Vec<u8>, String> {
     let window = web_sys::window().ok_or_else(|| "window object is null".to_string())?;
     let local_storage = window
@@ -41,11 +48,275 @@ pub fn save_file(file_name: &str, data: &[u8]) -> Result<(), String> {
     Ok(())
 }
 
+/// The name of the IndexedDB database, where the battery saves and save states are stored.
+const DB_NAME: &str = "gameroy";
+/// The object store of `DB_NAME`, where each file is a `Uint8Array` keyed by its name.
+const STORE_NAME: &str = "files";
+
+/// Wait for a IndexedDB request to complete, and return its result.
+async fn request_result(request: &IdbRequest) -> Result<JsValue, String> {
+    let promise = js_sys::Promise::new(&mut |res, rej| {
+        let req = request.clone();
+        let onsuccess = Closure::once_into_js(move || {
+            let result = req.result().unwrap_or(JsValue::undefined());
+            let _ = res.call1(&JsValue::undefined(), &result);
+        });
+        let onerror = Closure::once_into_js(move || {
+            let _ = rej.call1(&JsValue::undefined(), &JsValue::undefined());
+        });
+        request.set_onsuccess(Some(onsuccess.unchecked_ref()));
+        request.set_onerror(Some(onerror.unchecked_ref()));
+    });
+
+    wasm_bindgen_futures::JsFuture::from(promise)
+        .await
+        .map_err(|_| "IndexedDB request failed".to_string())
+}
+
+/// Open the database, creating its object store if it doesn't exist yet.
+async fn open_db() -> Result<IdbDatabase, String> {
+    let window = web_sys::window().ok_or_else(|| "window object is null".to_string())?;
+    let factory = window
+        .indexed_db()
+        .map_err(|_| "error getting IndexedDB".to_string())?
+        .ok_or_else(|| "IndexedDB is not available".to_string())?;
+    let request = factory
+        .open_with_u32(DB_NAME, 1)
+        .map_err(|_| "error opening the database".to_string())?;
+
+    let req = request.clone();
+    let onupgradeneeded = Closure::once_into_js(move || {
+        let db: IdbDatabase = match req.result() {
+            Ok(x) => x.unchecked_into(),
+            Err(_) => return,
+        };
+        if db.create_object_store(STORE_NAME).is_err() {
+            log::error!("error creating the object store '{}'", STORE_NAME);
+        }
+    });
+    request.set_onupgradeneeded(Some(onupgradeneeded.unchecked_ref()));
+
+    Ok(request_result(&request).await?.unchecked_into())
+}
+
+/// Load a file from the IndexedDB. Return `None` if there is no file with the given name.
+pub async fn load_db_file(file_name: &str) -> Result<Option<Vec<u8>>, String> {
+    let db = open_db().await?;
+    let request = db
+        .transaction_with_str(STORE_NAME)
+        .and_then(|transaction| transaction.object_store(STORE_NAME))
+        .and_then(|store| store.get(&JsValue::from_str(file_name)))
+        .map_err(|_| "error reading from the database".to_string())?;
+
+    let value = request_result(&request).await?;
+    if value.is_undefined() {
+        return Ok(None);
+    }
+    Ok(Some(js_sys::Uint8Array::new(&value).to_vec()))
+}
+
+/// Save a file to the IndexedDB, replacing the previous one with the same name.
+pub async fn save_db_file(file_name: &str, data: &[u8]) -> Result<(), String> {
+    let db = open_db().await?;
+    let request = db
+        .transaction_with_str_and_mode(STORE_NAME, IdbTransactionMode::Readwrite)
+        .and_then(|transaction| transaction.object_store(STORE_NAME))
+        .and_then(|store| {
+            store.put_with_key(
+                &js_sys::Uint8Array::from(data),
+                &JsValue::from_str(file_name),
+            )
+        })
+        .map_err(|_| "error writing to the database".to_string())?;
+
+    request_result(&request).await?;
+    Ok(())
+}
+
+/// Delete a file from the IndexedDB, if it exists.
+pub async fn delete_db_file(file_name: &str) -> Result<(), String> {
+    let db = open_db().await?;
+    let request = db
+        .transaction_with_str_and_mode(STORE_NAME, IdbTransactionMode::Readwrite)
+        .and_then(|transaction| transaction.object_store(STORE_NAME))
+        .and_then(|store| store.delete(&JsValue::from_str(file_name)))
+        .map_err(|_| "error deleting from the database".to_string())?;
+
+    request_result(&request).await?;
+    Ok(())
+}
+
+/// Remove a item from the local storage, if it exists.
+pub fn delete_file(file_name: &str) -> Result<(), String> {
+    let window = web_sys::window().ok_or_else(|| "window object is null".to_string())?;
+    let local_storage = window
+        .local_storage()
+        .map_err(|_| "error getting local storage".to_string())?
+        .ok_or_else(|| "local storage is null".to_string())?;
+
+    local_storage
+        .remove_item(file_name)
+        .map_err(|_| "error removing item from local storage".to_string())
+}
+
+/// Load the cheats of the rom with the given hash, from the local storage. A missing item is the
+/// same as a empty one.
+pub fn load_cheats_file(hash: &RomHash) -> Result<String, String> {
+    let file_name = hash.sha1_hex() + ".cheats";
+    match load_file(&file_name) {
+        Ok(data) => String::from_utf8(data).map_err(|e| e.to_string()),
+        Err(_) => Ok(String::new()),
+    }
+}
+
+pub fn save_cheats_file(hash: &RomHash, text: &str) -> Result<(), String> {
+    let file_name = hash.sha1_hex() + ".cheats";
+    save_file(&file_name, text.as_bytes())
+}
+
+/// Load the saved disassembly trace of the rom with the given hash, if there is one.
+pub fn load_trace_file(hash: &RomHash) -> Result<Option<Vec<u8>>, String> {
+    let file_name = hash.sha1_hex() + ".trace";
+    Ok(load_file(&file_name).ok())
+}
+
+pub fn save_trace_file(hash: &RomHash, data: &[u8]) -> Result<(), String> {
+    let file_name = hash.sha1_hex() + ".trace";
+    save_file(&file_name, data)
+}
+
+/// Read the content of a file selected or dropped by the user.
+async fn read_web_file(web_file: &web_sys::File) -> Result<Vec<u8>, String> {
+    let promise = js_sys::Promise::new(&mut move |res, rej| {
+        let file_reader = match web_sys::FileReader::new() {
+            Ok(x) => x,
+            Err(err) => {
+                let _ = rej.call1(&JsValue::undefined(), &err);
+                return;
+            }
+        };
+
+        let fr = file_reader.clone();
+        let closure = Closure::wrap(Box::new(move || {
+            res.call1(&JsValue::undefined(), &fr.result().unwrap())
+                .unwrap();
+        }) as Box<dyn FnMut()>);
+
+        file_reader.set_onload(Some(closure.as_ref().unchecked_ref()));
+
+        closure.forget();
+
+        if let Err(err) = file_reader.read_as_array_buffer(web_file) {
+            let _ = rej.call1(&JsValue::undefined(), &err);
+        }
+    });
+
+    let res = wasm_bindgen_futures::JsFuture::from(promise)
+        .await
+        .map_err(|_| format!("error reading '{}'", web_file.name()))?;
+
+    Ok(js_sys::Uint8Array::new(&res).to_vec())
+}
+
+/// Listen for roms dropped on the page, or selected in the file input with id `rom_input`, if the
+/// page has one. Each rom is read into memory, and passed to `on_load`.
+pub fn listen_rom_files(on_load: impl Fn(RomFile) + 'static) -> Result<(), String> {
+    let document = web_sys::window()
+        .and_then(|x| x.document())
+        .ok_or_else(|| "document object is null".to_string())?;
+
+    let on_load = Rc::new(on_load);
+    let load_files = Rc::new(move |files: Option<web_sys::FileList>| {
+        let web_file = match files.and_then(|x| x.get(0)) {
+            Some(x) => x,
+            None => return,
+        };
+        let on_load = on_load.clone();
+        wasm_bindgen_futures::spawn_local(async move {
+            match read_web_file(&web_file).await {
+                Ok(data) => on_load(RomFile::from_bytes(web_file.name(), data)),
+                Err(err) => log::error!("{}", err),
+            }
+        });
+    });
+
+    // the default of dragover must be prevented, otherwise the drop event is not fired
+    let ondragover = Closure::wrap(Box::new(|event: web_sys::DragEvent| {
+        event.prevent_default();
+    }) as Box<dyn FnMut(_)>);
+    let load = load_files.clone();
+    let ondrop = Closure::wrap(Box::new(move |event: web_sys::DragEvent| {
+        event.prevent_default();
+        load(event.data_transfer().and_then(|x| x.files()));
+    }) as Box<dyn FnMut(_)>);
+    document
+        .add_event_listener_with_callback("dragover", ondragover.as_ref().unchecked_ref())
+        .and_then(|_| {
+            document.add_event_listener_with_callback("drop", ondrop.as_ref().unchecked_ref())
+        })
+        .map_err(|_| "error listening for dropped files".to_string())?;
+    ondragover.forget();
+    ondrop.forget();
+
+    if let Some(input) = document.get_element_by_id("rom_input") {
+        let input: web_sys::HtmlInputElement = input
+            .dyn_into()
+            .map_err(|_| "'rom_input' is not a input element".to_string())?;
+        let inp = input.clone();
+        let onchange = Closure::wrap(Box::new(move || load_files(inp.files())) as Box<dyn FnMut()>);
+        input
+            .add_event_listener_with_callback("change", onchange.as_ref().unchecked_ref())
+            .map_err(|_| "error listening for the file input".to_string())?;
+        onchange.forget();
+    }
+
+    Ok(())
+}
+
+#[derive(Clone, Debug)]
+enum RomSource {
+    /// A file selected with the file dialog, read each time the rom is loaded.
+    File(web_sys::File),
+    /// The content of a rom that is already in memory, like a dropped file.
+    Bytes { name: String, data: Rc<[u8]> },
+}
+
 #[derive(Clone, Debug)]
 pub struct RomFile {
-    web_file: web_sys::File,
+    source: RomSource,
+    /// The hash of the rom, computed when the file is first read. The saves are keyed by it, so
+    /// they are found even if the file is renamed.
+    hash: Rc<Cell<Option<RomHash>>>,
 }
 impl RomFile {
+    /// A rom backed by the given bytes, instead of a file. Its saves are stored like the ones of
+    /// any other rom, keyed by its hash.
+    pub fn from_bytes(name: impl Into<String>, data: Vec<u8>) -> Self {
+        Self {
+            source: RomSource::Bytes {
+                name: name.into(),
+                data: data.into(),
+            },
+            hash: Rc::default(),
+        }
+    }
+
+    /// The name of a save of the rom, with the given extension, keyed by the hash of the rom if it
+    /// is known, or by the file name otherwise.
+    fn save_name(&self, extension: &str) -> String {
+        match self.hash.get() {
+            Some(hash) => hash.sha1_hex() + extension,
+            None => self.file_name().to_string() + extension,
+        }
+    }
+
+    /// Load a save from the local storage, where older versions saved them, falling back to the one
+    /// keyed by file name.
+    fn load_save_file(&self, extension: &str) -> Result<Vec<u8>, String> {
+        load_file(&self.save_name(extension))
+            .or_else(|_| load_file(&(self.file_name().to_string() + extension)))
+    }
+
     pub async fn get_header(&self) -> Result<CartridgeHeader, String> {
         let mut data = self.read().await?;
         let header = match CartridgeHeader::from_bytes(&mut data) {
@@ -55,67 +326,200 @@ impl RomFile {
         Ok(header)
     }
 
+    /// Compute the hash of the rom. The browser gives the file content all at once, so it is
+    /// hashed entirely.
+    pub async fn hash(&self) -> Result<RomHash, String> {
+        if let Some(hash) = self.hash.get() {
+            return Ok(hash);
+        }
+        let hash = RomHash::from_bytes(&self.read().await?);
+        self.hash.set(Some(hash));
+        Ok(hash)
+    }
+
     pub fn file_name(&self) -> Cow<str> {
-        self.web_file.name().into()
+        match &self.source {
+            RomSource::File(web_file) => web_file.name().into(),
+            RomSource::Bytes { name, .. } => name.as_str().into(),
+        }
     }
 
+    /// Read the rom. If the file is a zip archive, the first rom in it is extracted.
     pub async fn read(&self) -> Result<Vec<u8>, String> {
-        let promise = js_sys::Promise::new(&mut move |res, _rej| {
-            let file_reader = web_sys::FileReader::new().unwrap();
-
-            let fr = file_reader.clone();
-            let closure = Closure::wrap(Box::new(move || {
-                res.call1(&JsValue::undefined(), &fr.result().unwrap())
-                    .unwrap();
-            }) as Box<dyn FnMut()>);
-
-            file_reader.set_onload(Some(closure.as_ref().unchecked_ref()));
-
-            closure.forget();
-
-            file_reader.read_as_array_buffer(&self.web_file).unwrap();
-        });
-
-        let future = wasm_bindgen_futures::JsFuture::from(promise);
-
-        let res = future.await.unwrap();
+        let vec = match &self.source {
+            RomSource::File(web_file) => read_web_file(web_file).await?,
+            RomSource::Bytes { data, .. } => data.to_vec(),
+        };
+        #[cfg(feature = "zip")]
+        let vec = if self.file_name().to_ascii_lowercase().ends_with(".zip") {
+            super::unzip_rom(&vec)?.1
+        } else {
+            vec
+        };
 
-        let buffer: js_sys::Uint8Array = js_sys::Uint8Array::new(&res);
-        let mut vec = vec![0; buffer.length() as usize];
-        buffer.copy_to(&mut vec[..]);
+        if self.hash.get().is_none() {
+            self.hash.set(Some(RomHash::from_bytes(&vec)));
+        }
 
         Ok(vec)
     }
 
-    pub fn save_ram_data(&self, data: &[u8]) -> Result<(), String> {
-        let file_name = self.file_name().to_string() + ".sav";
-
-        save_file(&file_name, data)
+    /// Save the battery RAM to the IndexedDB. It only returns after the write finishes, so the RAM
+    /// is only marked as saved if it succeeded.
+    pub async fn save_ram_data(&self, data: &[u8]) -> Result<(), String> {
+        let file_name = self.hash().await?.sha1_hex() + ".sav";
+        save_db_file(&file_name, data).await
     }
 
     pub async fn load_ram_data(&self) -> Result<Vec<u8>, String> {
-        let file_name = self.file_name().to_string() + ".sav";
+        let file_name = self.hash().await?.sha1_hex() + ".sav";
+        match load_db_file(&file_name).await? {
+            Some(data) => Ok(data),
+            // older versions saved the battery RAM in the local storage, by file name
+            None => load_file(&(self.file_name().to_string() + ".sav")),
+        }
+    }
 
-        load_file(&file_name)
+    /// Delete the battery RAM from the IndexedDB, and the one saved by file name by older
+    /// versions. The delete finishes asynchronously, and its errors are only logged.
+    pub fn delete_ram_data(&self) -> Result<(), String> {
+        let this = self.clone();
+        wasm_bindgen_futures::spawn_local(async move {
+            let result = match this.hash().await {
+                Ok(hash) => delete_db_file(&(hash.sha1_hex() + ".sav")).await,
+                Err(err) => Err(err),
+            };
+            if let Err(err) = result {
+                log::error!("error deleting ram: {}", err);
+            }
+        });
+        delete_file(&(self.file_name().to_string() + ".sav"))
     }
 
-    pub fn save_state(&self, state: &[u8]) -> Result<(), String> {
-        let file_name = self.file_name().to_string() + ".save_state";
+    /// Delete the save state from the IndexedDB, and the ones saved in the local storage by older
+    /// versions, keyed by hash or by file name. The delete from the IndexedDB finishes
+    /// asynchronously, and its errors are only logged.
+    pub fn delete_state(&self) -> Result<(), String> {
+        let this = self.clone();
+        wasm_bindgen_futures::spawn_local(async move {
+            let result = match this.hash().await {
+                Ok(hash) => delete_db_file(&(hash.sha1_hex() + ".save_state")).await,
+                Err(err) => Err(err),
+            };
+            if let Err(err) = result {
+                log::error!("error deleting state: {}", err);
+            }
+        });
+        delete_file(&self.save_name(".save_state"))?;
+        delete_file(&(self.file_name().to_string() + ".save_state"))
+    }
 
-        save_file(&file_name, state)
+    /// Save the save state to the IndexedDB, keyed by the hash of the rom.
+    pub async fn save_state(&self, state: &[u8]) -> Result<(), String> {
+        let file_name = self.hash().await?.sha1_hex() + ".save_state";
+        save_db_file(&file_name, state).await
     }
 
-    pub fn load_state(&self) -> Result<Vec<u8>, String> {
-        let file_name = self.file_name().to_string() + ".save_state";
+    /// Load the save state from the IndexedDB, falling back to the ones saved in the local storage
+    /// by older versions.
+    pub async fn load_state(&self) -> Result<Vec<u8>, String> {
+        let file_name = self.hash().await?.sha1_hex() + ".save_state";
+        match load_db_file(&file_name).await? {
+            Some(data) => Ok(data),
+            None => self.load_save_file(".save_state"),
+        }
+    }
 
-        load_file(&file_name)
+    /// Load the symbol file of the rom from the local storage, if there is one.
+    pub fn load_symbols_file(&self) -> Result<Option<String>, String> {
+        let file_name = self.file_name().to_string() + ".sym";
+        match load_file(&file_name) {
+            Ok(data) => String::from_utf8(data).map(Some).map_err(|e| e.to_string()),
+            Err(_) => Ok(None),
+        }
     }
 }
 #[cfg(feature = "rfd")]
 impl From<rfd::FileHandle> for RomFile {
     fn from(handle: rfd::FileHandle) -> Self {
         Self {
-            web_file: handle.inner().clone(),
+            source: RomSource::File(handle.inner().clone()),
+            hash: Rc::default(),
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use wasm_bindgen_test::*;
+
+    use super::*;
+
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    #[wasm_bindgen_test]
+    async fn ram_round_trip() {
+        let rom = js_sys::Uint8Array::from(&[0x00, 0xC3, 0x50, 0x01][..]);
+        let parts = js_sys::Array::of1(&rom);
+        let web_file = web_sys::File::new_with_u8_array_sequence(&parts, "test.gb").unwrap();
+        let file = RomFile {
+            source: RomSource::File(web_file),
+            hash: Rc::default(),
+        };
+
+        let ram: Vec<u8> = (0..0x2000).map(|x| x as u8).collect();
+        file.save_ram_data(&ram).await.unwrap();
+        assert_eq!(file.load_ram_data().await.unwrap(), ram);
+
+        // the save is keyed by the hash, so it is found even after a rename
+        let hash = file.hash().await.unwrap();
+        let saved = load_db_file(&(hash.sha1_hex() + ".sav")).await.unwrap();
+        assert_eq!(saved, Some(ram));
+    }
+
+    #[wasm_bindgen_test]
+    async fn state_round_trip() {
+        let file = RomFile::from_bytes("state.gb", vec![0x00, 0xC3, 0x50, 0x02]);
+        let hash = file.hash().await.unwrap();
+
+        // a state saved in the local storage by a older version
+        save_file("state.gb.save_state", &[1, 2, 3]).unwrap();
+        assert_eq!(file.load_state().await.unwrap(), [1, 2, 3]);
+
+        // the new states are in the IndexedDB, keyed by the hash
+        let state: Vec<u8> = (0..0x10000).map(|x| x as u8).collect();
+        file.save_state(&state).await.unwrap();
+        assert_eq!(file.load_state().await.unwrap(), state);
+        let saved = load_db_file(&(hash.sha1_hex() + ".save_state"))
+            .await
+            .unwrap();
+        assert_eq!(saved, Some(state));
+
+        delete_db_file(&(hash.sha1_hex() + ".save_state"))
+            .await
+            .unwrap();
+        delete_file("state.gb.save_state").unwrap();
+        assert!(file.load_state().await.is_err());
+    }
+
+    #[wasm_bindgen_test]
+    async fn in_memory_header() {
+        let mut rom = vec![0; 0x8000];
+        rom[0x134..0x138].copy_from_slice(b"TEST");
+        rom[0x147] = 0x03; // MBC1+RAM+BATTERY
+        let file = RomFile::from_bytes("test.gb", rom.clone());
+
+        assert_eq!(file.file_name(), "test.gb");
+        assert_eq!(file.read().await.unwrap(), rom);
+
+        let header = file.get_header().await.unwrap();
+        assert_eq!(header.title_as_string(), "TEST");
+        assert!(header.has_battery());
+
+        // the saves of a in-memory rom are keyed by its hash
+        assert_eq!(
+            file.hash().await.unwrap().sha1_hex(),
+            RomHash::from_bytes(&rom).sha1_hex()
+        );
+    }
+}
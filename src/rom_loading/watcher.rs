@@ -0,0 +1,127 @@
+use std::{
+    path::Path,
+    sync::mpsc::{self, RecvTimeoutError},
+    time::Duration,
+};
+
+use notify::{event::ModifyKind, EventKind, RecursiveMode, Watcher};
+use winit::event_loop::EventLoopProxy;
+
+use crate::UserEvent;
+
+/// How long the folders must go without changes before the rom list is reloaded, so a burst of
+/// changes, like copying many roms at once, reloads it only once.
+const QUIET_TIME: Duration = Duration::from_millis(500);
+
+/// Watches the rom folders, and sends `UserEvent::RomFoldersChanged` when a rom is created, removed or
+/// renamed in them. Like the rom list, the subfolders are not watched.
+pub struct RomWatcher {
+    /// The watched folders, as in the `rom_folders` config.
+    folders: Vec<String>,
+    _watcher: notify::RecommendedWatcher,
+}
+impl RomWatcher {
+    /// Start watching the given folders. Folders that can't be watched are logged and skipped.
+    pub fn new(folders: Vec<String>, proxy: EventLoopProxy<UserEvent>) -> Result<Self, String> {
+        let (sender, receiver) = mpsc::channel();
+        let mut watcher =
+            notify::recommended_watcher(move |event: notify::Result<notify::Event>| match event {
+                Ok(event) if is_rom_change(&event) => {
+                    log::debug!("rom folder changed: {:?}", event);
+                    let _ = sender.send(());
+                }
+                Ok(_) => {}
+                Err(err) => log::error!("error watching the rom folders: {}", err),
+            })
+            .map_err(|err| format!("error creating the rom folder watcher: {}", err))?;
+
+        for folder in &folders {
+            let path = crate::config::normalize_config_path(folder);
+            if let Err(err) = watcher.watch(&path, RecursiveMode::NonRecursive) {
+                log::error!("error watching '{}': {}", path.display(), err);
+            }
+        }
+        std::thread::spawn(move || coalesce_changes(receiver, proxy));
+
+        Ok(Self {
+            folders,
+            _watcher: watcher,
+        })
+    }
+
+    /// The folders being watched.
+    pub fn folders(&self) -> &[String] {
+        &self.folders
+    }
+}
+
+/// Send a `UserEvent::RomFoldersChanged` after each burst of changes, once no change happens for
+/// `QUIET_TIME`. Returns when the watcher is dropped.
+fn coalesce_changes(receiver: mpsc::Receiver<()>, proxy: EventLoopProxy<UserEvent>) {
+    while receiver.recv().is_ok() {
+        loop {
+            match receiver.recv_timeout(QUIET_TIME) {
+                Ok(()) => {}
+                Err(RecvTimeoutError::Timeout) => break,
+                Err(RecvTimeoutError::Disconnected) => return,
+            }
+        }
+        log::info!("the rom folders changed, reloading the rom list");
+        if proxy.send_event(UserEvent::RomFoldersChanged).is_err() {
+            return;
+        }
+    }
+}
+
+/// If the event is a rom being created, removed or renamed. Changes to the saves and others files
+/// next to the roms are ignored.
+fn is_rom_change(event: &notify::Event) -> bool {
+    let kind = matches!(
+        event.kind,
+        EventKind::Create(_) | EventKind::Remove(_) | EventKind::Modify(ModifyKind::Name(_))
+    );
+    kind && event.paths.iter().any(|path| is_rom_path(path))
+}
+
+fn is_rom_path(path: &Path) -> bool {
+    let extension = match path.extension().and_then(|x| x.to_str()) {
+        Some(x) => x,
+        None => return false,
+    };
+    super::ROM_EXTENSIONS
+        .iter()
+        .any(|x| x.eq_ignore_ascii_case(extension))
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+
+    use notify::{
+        event::{CreateKind, DataChange, ModifyKind, RemoveKind, RenameMode},
+        Event, EventKind,
+    };
+
+    use super::is_rom_change;
+
+    fn event(kind: EventKind, path: &str) -> Event {
+        Event::new(kind).add_path(PathBuf::from(path))
+    }
+
+    #[test]
+    fn rom_changes() {
+        let create = EventKind::Create(CreateKind::File);
+        let remove = EventKind::Remove(RemoveKind::File);
+        let rename = EventKind::Modify(ModifyKind::Name(RenameMode::Both));
+        let write = EventKind::Modify(ModifyKind::Data(DataChange::Any));
+
+        assert!(is_rom_change(&event(create, "roms/Tetris.gb")));
+        assert!(is_rom_change(&event(remove, "roms/Zelda.GBC")));
+        assert!(is_rom_change(&event(rename, "roms/Tetris.gb")));
+        // saving a game don't reload the list
+        assert!(!is_rom_change(&event(create, "roms/Tetris.sav")));
+        assert!(!is_rom_change(&event(create, "roms/Tetris.save_state")));
+        assert!(!is_rom_change(&event(write, "roms/Tetris.gb")));
+        assert!(!is_rom_change(&event(create, "roms/README")));
+    }
+}
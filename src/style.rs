@@ -238,6 +238,7 @@ pub struct Style {
     pub delete_icon: Graphic,
     pub open_icon: Graphic,
     pub file_icon: Graphic,
+    pub warning_icon: Graphic,
     pub menu_icon: Graphic,
     pub forward_icon: Graphic,
     pub rewind_icon: Graphic,
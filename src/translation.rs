@@ -0,0 +1,50 @@
+//! The strings of the user interface that are built at runtime, like the age of a save, kept in a
+//! table so they can be translated later. Only english exists for now.
+
+use std::fmt::Display;
+
+/// The strings of a language. A `{}` in a string is replaced by a value, see `fill`.
+pub struct Strings {
+    /// Shown in place of a missing value.
+    pub no_value: &'static str,
+    pub just_now: &'static str,
+    /// A instant after the current time, like a save whose file was modified after a clock change.
+    pub in_the_future: &'static str,
+    pub minutes_ago: &'static str,
+    pub hours_ago: &'static str,
+    pub days_ago: &'static str,
+    pub weeks_ago: &'static str,
+    pub months_ago: &'static str,
+    pub years_ago: &'static str,
+    /// A date and time in UTC, like "2022-05-01 13:05".
+    pub utc_time: &'static str,
+    /// A duration shorter than a hour, in minutes.
+    pub minutes: &'static str,
+    /// A duration in hours, and then minutes.
+    pub hours_minutes: &'static str,
+}
+
+pub const ENGLISH: Strings = Strings {
+    no_value: " - ",
+    just_now: "Just now",
+    in_the_future: "In the future",
+    minutes_ago: "{}min ago",
+    hours_ago: "{}h ago",
+    days_ago: "{}d ago",
+    weeks_ago: "{}w ago",
+    months_ago: "{}mo ago",
+    years_ago: "{}y ago",
+    utc_time: "{} UTC",
+    minutes: "{}m",
+    hours_minutes: "{}h {}m",
+};
+
+/// The strings of the current language.
+pub fn strings() -> &'static Strings {
+    &ENGLISH
+}
+
+/// Replace the first `{}` in `template` by `value`.
+pub fn fill(template: &str, value: impl Display) -> String {
+    template.replacen("{}", &value.to_string(), 1)
+}
@@ -15,12 +15,20 @@ use winit::{
     window::{Window, WindowId},
 };
 
-use crate::{event_table::EventTable, style::Style, UserEvent, SCREEN_HEIGHT, SCREEN_WIDTH};
+use crate::{
+    event_table::EventTable, key_bindings::Key, style::Style, UserEvent, SCREEN_HEIGHT,
+    SCREEN_WIDTH,
+};
 
 mod emulator_ui;
-pub use emulator_ui::create_emulator_ui;
+pub use emulator_ui::{create_emulator_ui, open_game_menu};
+
+mod error_dialog;
+pub use error_dialog::{open_error_dialog, GameInput};
 
 mod rom_loading_ui;
+#[cfg(not(target_arch = "wasm32"))]
+pub use rom_loading_ui::thumbnails::Thumbnail;
 pub use rom_loading_ui::{create_rom_loading_ui, RomEntries, RomEntry};
 
 struct Render<'a>(&'a mut dyn SpriteRender);
@@ -49,8 +57,17 @@ pub struct Textures {
     pub tilemap: u32,
     pub background: u32,
     pub window: u32,
+    /// The atlas with the thumbnails of the rom list, see `ThumbnailAtlas`.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub thumbnails: u32,
 }
 
+/// The key of the keyboard event being handled by the gui. The keyboard events of giui only have
+/// the virtual key, so this is set before each event is passed to it, for the handlers to look up
+/// the key bindings by the scancode.
+#[derive(Clone, Copy)]
+pub struct KeyInput(pub Key);
+
 pub struct Ui {
     pub gui: Gui,
     gui_render: GuiRender,
@@ -82,6 +99,12 @@ impl Ui {
                 tilemap: render.new_texture(128, 192, &[], false),
                 background: render.new_texture(256, 256, &[], false),
                 window: render.new_texture(256, 256, &[], false),
+                thumbnails: render.new_texture(
+                    rom_loading_ui::thumbnails::ATLAS_WIDTH,
+                    rom_loading_ui::thumbnails::ATLAS_HEIGHT,
+                    &[],
+                    false,
+                ),
             };
 
             (render, font_texture, style, textures)
@@ -95,6 +118,7 @@ impl Ui {
         gui.set(proxy);
         gui.set(textures.clone());
         gui.set(style);
+        gui.set(KeyInput(Key::new(0, None)));
 
         let camera = {
             let size = window.inner_size();
@@ -185,6 +209,10 @@ impl Ui {
     }
 
     pub fn window_event(&mut self, event: &WindowEvent, window: &Window) {
+        if let WindowEvent::KeyboardInput { input, .. } = event {
+            let key = Key::new(input.scancode, input.virtual_keycode);
+            self.gui.set(KeyInput(key));
+        }
         // gui receive events
         self.gui.handle_event(event);
         self.update_window(window);
@@ -208,6 +236,14 @@ impl Ui {
         self.render.update_texture(texture, &img_data, None);
     }
 
+    /// Upload the thumbnails of the rom list to their texture.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn update_thumbnails_texture(&mut self) {
+        let atlas = self.gui.get::<RomEntries>().thumbnails();
+        self.render
+            .update_texture(self.textures.thumbnails, atlas.pixels(), None);
+    }
+
     pub fn render(&mut self, window_id: WindowId) {
         let mut ctx = self.gui.get_render_context();
         let (sprites, is_anim) = self
@@ -276,6 +312,13 @@ fn load_graphics(
         tilemap: render.new_texture(128, 192, &[], false),
         background: render.new_texture(256, 256, &[], false),
         window: render.new_texture(256, 256, &[], false),
+        #[cfg(not(target_arch = "wasm32"))]
+        thumbnails: render.new_texture(
+            rom_loading_ui::thumbnails::ATLAS_WIDTH,
+            rom_loading_ui::thumbnails::ATLAS_HEIGHT,
+            &[],
+            false,
+        ),
     };
 
     (render, font_texture, style, textures)
@@ -11,7 +11,9 @@ use giui::{
 use winit::event_loop::EventLoopProxy;
 
 use crate::{
-    event_table::EventTable,
+    emulator::SpeedOverride,
+    event_table::{DebugOverlay, EventTable, StatsOverlay},
+    key_bindings::Action,
     style::Style,
     ui::{Textures, Ui},
     widget::{
@@ -21,9 +23,19 @@ use crate::{
     EmulatorEvent, UserEvent,
 };
 
+mod cheats_dialog;
+mod debug_overlay;
 mod disassembler_viewer;
 mod game_pad;
+mod history_viewer;
+mod memory_viewer;
 mod ppu_viewer;
+#[cfg(all(
+    feature = "rfd",
+    not(any(target_arch = "wasm32", target_os = "android"))
+))]
+mod state_files;
+mod stats_overlay;
 
 pub fn create_emulator_ui(ui: &mut Ui, debug: bool) {
     let style = &ui.gui.get::<Style>().clone();
@@ -46,98 +58,140 @@ pub fn create_gui(
     let root = gui.reserve_id();
     let mut screen_id = gui.reserve_id();
     let mut split_view = gui.reserve_id();
+    gui.set(crate::ui::GameInput(root));
 
     let sty = style.clone();
     let event_table_clone = event_table.clone();
     gui.create_control_reserved(root)
         .behaviour(OnKeyboardEvent::new(move |event, _, ctx| {
             use giui::KeyboardEvent::*;
+            let pressed = match event {
+                Pressed(_) => true,
+                Release(_) => false,
+                _ => return true,
+            };
+            let key = ctx.get::<crate::ui::KeyInput>().0;
             let sender = ctx.get::<flume::Sender<EmulatorEvent>>().clone();
             let debug = ctx.get::<crate::AppState>().debug;
-            let app_state = ctx.get_mut::<crate::AppState>();
-            let mut set_key = |key: u8, value: bool| {
-                app_state.joypad = (app_state.joypad & !(1 << key)) | ((!value as u8) << key)
-            };
-            let km = &crate::config::config().keymap;
-            match event {
-                Pressed(x) if x == km.right => set_key(0, true), // Left
-                Release(x) if x == km.right => set_key(0, false),
-                Pressed(x) if x == km.left => set_key(1, true), // Right
-                Release(x) if x == km.left => set_key(1, false),
-                Pressed(x) if x == km.up => set_key(2, true), // Up
-                Release(x) if x == km.up => set_key(2, false),
-                Pressed(x) if x == km.down => set_key(3, true), // Down
-                Release(x) if x == km.down => set_key(3, false),
-                Pressed(x) if x == km.a => set_key(4, true), // A
-                Release(x) if x == km.a => set_key(4, false),
-                Pressed(x) if x == km.b => set_key(5, true), // B
-                Release(x) if x == km.b => set_key(5, false),
-                Pressed(x) if x == km.select => set_key(6, true), // Select
-                Release(x) if x == km.select => set_key(6, false),
-                Pressed(x) if x == km.start => set_key(7, true), // Start
-                Release(x) if x == km.start => set_key(7, false),
-                event => {
-                    if debug {
-                        match event {
-                            Pressed(x) if x == km.save_state => {
-                                sender.send(EmulatorEvent::SaveState).unwrap();
-                            }
-                            Pressed(x) if x == km.load_state => {
-                                sender.send(EmulatorEvent::LoadState).unwrap();
-                            }
-                            Pressed(x) if x == km.debug_stepback => {
-                                sender.send(EmulatorEvent::StepBack).unwrap();
-                            }
-                            Pressed(x) if x == km.debug_step => {
-                                sender.send(EmulatorEvent::Step).unwrap();
-                            }
-                            Pressed(x) if x == km.debug_run => {
-                                sender.send(EmulatorEvent::Run).unwrap();
-                            }
-                            Pressed(x) if x == km.open_debugger => {
-                                let textures = ctx.get::<Textures>().clone();
-                                close_debug_panel(
-                                    ctx,
-                                    &textures,
-                                    &mut split_view,
-                                    &mut screen_id,
-                                    root,
-                                    &sty,
-                                );
-                            }
-                            _ => {}
+            // collected first, so the config is not locked while the actions run
+            let actions: Vec<Action> = crate::config::config().keymap.actions(key).collect();
+            for action in actions {
+                if let Some(bit) = action.joypad_bit() {
+                    let app_state = ctx.get_mut::<crate::AppState>();
+                    app_state.joypad = (app_state.joypad & !(1 << bit)) | ((!pressed as u8) << bit);
+                    continue;
+                }
+                if let Some(bit) = action.turbo_bit() {
+                    let app_state = ctx.get_mut::<crate::AppState>();
+                    app_state.turbo = (app_state.turbo & !(1 << bit)) | ((pressed as u8) << bit);
+                    continue;
+                }
+                if let Some(bit) = action.latch_bit() {
+                    if pressed {
+                        ctx.get_mut::<crate::AppState>().latched ^= 1 << bit;
+                    }
+                    continue;
+                }
+                match action {
+                    Action::DebugOverlay if pressed => {
+                        let app_state = ctx.get_mut::<crate::AppState>();
+                        app_state.debug_overlay = !app_state.debug_overlay;
+                        let visible = app_state.debug_overlay;
+                        event_table.borrow_mut().notify(DebugOverlay(visible), ctx);
+                    }
+                    Action::StatsOverlay if pressed => {
+                        let app_state = ctx.get_mut::<crate::AppState>();
+                        app_state.stats_overlay = !app_state.stats_overlay;
+                        let visible = app_state.stats_overlay;
+                        event_table.borrow_mut().notify(StatsOverlay(visible), ctx);
+                    }
+                    Action::SaveState if pressed => {
+                        sender.send(EmulatorEvent::SaveState).unwrap();
+                    }
+                    Action::LoadState if pressed => {
+                        sender.send(EmulatorEvent::LoadState).unwrap();
+                    }
+                    Action::OpenDebugger if pressed && debug => {
+                        let textures = ctx.get::<Textures>().clone();
+                        close_debug_panel(
+                            ctx,
+                            &textures,
+                            &mut split_view,
+                            &mut screen_id,
+                            root,
+                            &sty,
+                            &mut event_table.borrow_mut(),
+                        );
+                    }
+                    Action::OpenDebugger if pressed => {
+                        let textures = ctx.get::<Textures>().clone();
+                        // Debug
+                        open_debug_panel(
+                            ctx,
+                            &textures,
+                            split_view,
+                            root,
+                            &sty,
+                            &mut screen_id,
+                            event_table.clone(),
+                        );
+                    }
+                    _ if debug => match action {
+                        Action::DebugStepback if pressed => {
+                            sender.send(EmulatorEvent::StepBack(1)).unwrap();
+                        }
+                        Action::DebugStep if pressed => {
+                            sender.send(EmulatorEvent::Step).unwrap();
                         }
-                    } else {
-                        match event {
-                            Pressed(x) if x == km.save_state => {
-                                sender.send(EmulatorEvent::SaveState).unwrap();
-                            }
-                            Pressed(x) if x == km.load_state => {
-                                sender.send(EmulatorEvent::LoadState).unwrap();
-                            }
-                            Pressed(x) if x == km.open_debugger => {
-                                let textures = ctx.get::<Textures>().clone();
-                                // Debug
-                                open_debug_panel(
-                                    ctx,
-                                    &textures,
-                                    split_view,
-                                    root,
-                                    &sty,
-                                    &mut screen_id,
-                                    event_table.clone(),
-                                );
-                            }
-                            Pressed(x) | Release(x) if x == km.speed => sender
-                                .send(EmulatorEvent::FrameLimit(!matches!(event, Pressed(_))))
-                                .unwrap(),
-                            Pressed(x) | Release(x) if x == km.rewind => sender
-                                .send(EmulatorEvent::Rewind(matches!(event, Pressed(_))))
-                                .unwrap(),
-
-                            _ => {}
+                        Action::DebugRun if pressed => {
+                            sender.send(EmulatorEvent::Run).unwrap();
                         }
+                        _ => {}
+                    },
+                    Action::Cheats if pressed => {
+                        cheats_dialog::open_cheats_dialog(ctx, &sty);
+                    }
+                    Action::Menu if pressed => open_game_menu(ctx),
+                    #[cfg(all(
+                        feature = "rfd",
+                        not(any(target_arch = "wasm32", target_os = "android"))
+                    ))]
+                    Action::ExportState if pressed => {
+                        state_files::export_state(ctx);
+                    }
+                    #[cfg(all(
+                        feature = "rfd",
+                        not(any(target_arch = "wasm32", target_os = "android"))
+                    ))]
+                    Action::ImportState if pressed => {
+                        state_files::import_state(ctx);
+                    }
+                    #[cfg(not(any(target_arch = "wasm32", target_os = "android")))]
+                    Action::Screenshot if pressed => {
+                        sender.send(EmulatorEvent::Screenshot).unwrap();
+                    }
+                    Action::Speed => override_speed(ctx, SpeedOverride::fast_forward(), pressed),
+                    Action::SlowMotion => {
+                        override_speed(ctx, SpeedOverride::slow_motion(), pressed)
                     }
+                    Action::SpeedUp if pressed => {
+                        set_speed(ctx, |x| crate::emulator::step_speed(x, true))
+                    }
+                    Action::SpeedDown if pressed => {
+                        set_speed(ctx, |x| crate::emulator::step_speed(x, false))
+                    }
+                    Action::SpeedReset if pressed => set_speed(ctx, |_| 1.0),
+                    Action::VolumeUp if pressed => set_volume(ctx, |volume, muted| {
+                        *volume = crate::emulator::step_volume(*volume, true);
+                        *muted = false;
+                    }),
+                    Action::VolumeDown if pressed => set_volume(ctx, |volume, muted| {
+                        *volume = crate::emulator::step_volume(*volume, false);
+                        *muted = false;
+                    }),
+                    Action::Mute if pressed => set_volume(ctx, |_, muted| *muted = !*muted),
+                    Action::Rewind => sender.send(EmulatorEvent::Rewind(pressed)).unwrap(),
+                    _ => {}
                 }
             }
             true
@@ -161,6 +215,7 @@ pub fn create_gui(
             &mut screen_id,
             root,
             style,
+            &mut event_table_clone.borrow_mut(),
         );
         gui.set_focus(Some(screen_id));
     }
@@ -173,11 +228,12 @@ fn close_debug_panel(
     screen_id: &mut Id,
     root: Id,
     style: &Style,
+    event_table: &mut EventTable,
 ) {
     ctx.remove(*split_view);
     *split_view = ctx.reserve();
 
-    create_screen(ctx, textures, screen_id, root, style);
+    create_screen(ctx, textures, screen_id, root, style, event_table);
     ctx.set_focus(*screen_id);
     let proxy = ctx.get::<EventLoopProxy<UserEvent>>();
     proxy.send_event(UserEvent::Debug(false)).unwrap();
@@ -200,7 +256,7 @@ fn open_debug_panel(
         .build(ctx);
     ctx.remove(*screen_id);
 
-    create_screen(ctx, textures, screen_id, split_view, style);
+    create_screen(ctx, textures, screen_id, split_view, style, event_table);
 
     // create debug panel
     let debug_panel = ctx
@@ -247,9 +303,18 @@ fn open_debug_panel(
         .expand_y(true)
         .build(ctx);
 
+    let disas_list_id = ctx.reserve();
     let cpu_id = ctx.reserve();
     let ppu_id = ctx.reserve();
-    disassembler_viewer::side_panel(ctx, style, h_box, cpu_id, ppu_id, event_table);
+    disassembler_viewer::side_panel(
+        ctx,
+        style,
+        h_box,
+        disas_list_id,
+        cpu_id,
+        ppu_id,
+        event_table,
+    );
 
     let scroll_log = ctx.reserve();
     let content = ctx.reserve();
@@ -272,7 +337,15 @@ fn open_debug_panel(
     let tab_group = ButtonGroup::new(|_, _| ());
 
     let disas_page = ctx.create_control().parent(tab_page).build(ctx);
-    disassembler_viewer::build(disas_page, ctx, event_table, &style, cpu_id, ppu_id);
+    disassembler_viewer::build(
+        disas_page,
+        ctx,
+        event_table,
+        &style,
+        disas_list_id,
+        cpu_id,
+        ppu_id,
+    );
     let _disas_tab = ctx
         .create_control()
         .parent(tab_header)
@@ -315,10 +388,87 @@ fn open_debug_panel(
         ))
         .build(ctx);
 
+    let memory_page = ctx.create_control().parent(tab_page).build(ctx);
+    memory_viewer::build(memory_page, ctx, event_table, &style);
+    let _memory_tab = ctx
+        .create_control()
+        .parent(tab_header)
+        .child(ctx, |cb, _| {
+            cb.graphic(Text::new(
+                "memory".to_string(),
+                (0, 0),
+                style.text_style.clone(),
+            ))
+            .layout(FitGraphic)
+        })
+        .layout(MarginLayout::default())
+        .behaviour(TabButton::new(
+            tab_group.clone(),
+            memory_page,
+            false,
+            style.tab_style.clone(),
+        ))
+        .build(ctx);
+
+    let history_page = ctx.create_control().parent(tab_page).build(ctx);
+    history_viewer::build(history_page, ctx, event_table, &style);
+    let _history_tab = ctx
+        .create_control()
+        .parent(tab_header)
+        .child(ctx, |cb, _| {
+            cb.graphic(Text::new(
+                "history".to_string(),
+                (0, 0),
+                style.text_style.clone(),
+            ))
+            .layout(FitGraphic)
+        })
+        .layout(MarginLayout::default())
+        .behaviour(TabButton::new(
+            tab_group.clone(),
+            history_page,
+            false,
+            style.tab_style.clone(),
+        ))
+        .build(ctx);
+
     let proxy = ctx.get::<EventLoopProxy<UserEvent>>();
     proxy.send_event(UserEvent::Debug(true)).unwrap();
 }
 
+/// Change the emulation speed with `f`, and send it to the emulator.
+fn set_speed(ctx: &mut Context, f: impl FnOnce(f32) -> f32) {
+    let app_state = ctx.get_mut::<crate::AppState>();
+    app_state.speed = f(app_state.speed);
+    let speed = app_state.speed;
+    send_emu(ctx, EmulatorEvent::SetSpeed(speed));
+}
+
+/// Start or stop running at `speed`, the speed of the fast forward or slow motion key, as the key is
+/// pressed or released. With the `speed_toggle` config, each press starts or stops it instead.
+fn override_speed(ctx: &mut Context, speed: SpeedOverride, pressed: bool) {
+    let toggle = crate::config::config().speed_toggle;
+    let app_state = ctx.get_mut::<crate::AppState>();
+    let active = app_state.speed_override == Some(speed);
+    let speed_override = match (toggle, pressed) {
+        (true, true) if active => None,
+        (true, true) => Some(speed),
+        (false, true) => Some(speed),
+        (false, false) if active => None,
+        _ => return,
+    };
+    app_state.speed_override = speed_override;
+    send_emu(ctx, EmulatorEvent::OverrideSpeed(speed_override));
+}
+
+/// Change the volume and if it is muted with `f`, and send them to the emulator.
+fn set_volume(ctx: &mut Context, f: impl FnOnce(&mut f32, &mut bool)) {
+    let app_state = ctx.get_mut::<crate::AppState>();
+    f(&mut app_state.volume, &mut app_state.muted);
+    let (volume, muted) = (app_state.volume, app_state.muted);
+    send_emu(ctx, EmulatorEvent::SetVolume { volume, muted });
+}
+
 fn send_emu<'a>(ctx: &'a mut Context, event: EmulatorEvent) {
     ctx.get::<flume::Sender<EmulatorEvent>>()
         .send(event)
@@ -331,10 +481,21 @@ fn create_screen(
     screen_id: &mut Id,
     parent: Id,
     style: &Style,
+    event_table: &mut EventTable,
 ) {
     *screen_id = ctx.reserve();
     let screen = ctx.reserve();
     let mut layout = ScreenLayout::new((160, 144));
+    let border_color = {
+        let conf = crate::config::config();
+        layout.integer_scale = conf.integer_scale;
+        conf.border_color
+    };
+    // fills the bars around the screen
+    let background = match border_color.rgb() {
+        Some([r, g, b]) => style.background.clone().with_color([r, g, b, 255].into()),
+        None => style.background.clone(),
+    };
 
     let gamepad = cfg!(target_os = "android");
     if gamepad {
@@ -464,7 +625,10 @@ fn create_screen(
             Box::new(x)
         }
         let other: Vec<game_pad::OtherButton> = vec![
-            (foward_button, bx(|v, ctx| send_emu(ctx, FrameLimit(!v)))),
+            (
+                foward_button,
+                bx(|v, ctx| override_speed(ctx, SpeedOverride::fast_forward(), v)),
+            ),
             (rewind_button, bx(|v, ctx| send_emu(ctx, Rewind(v)))),
         ];
 
@@ -472,13 +636,13 @@ fn create_screen(
 
         ctx.create_control_reserved(*screen_id)
             .parent(parent)
-            .graphic(style.background.clone())
+            .graphic(background)
             .behaviour(game_pad::GamePad::new(joypad, other, sprites))
             .build(ctx);
     } else {
         ctx.create_control_reserved(*screen_id)
             .parent(parent)
-            .graphic(style.background.clone())
+            .graphic(background)
             .build(ctx);
     }
 
@@ -493,6 +657,9 @@ fn create_screen(
 
     ctx.move_to_back(screen);
 
+    debug_overlay::build(ctx, screen, event_table, style);
+    stats_overlay::build(ctx, screen, event_table, style);
+
     let menu = cfg!(target_os = "android");
     if menu {
         let _open_menu = ctx
@@ -512,29 +679,40 @@ fn create_screen(
                     focus: style.button_panel.clone().with_alpha(150),
                 }),
                 true,
-                move |_, ctx| {
-                    let style = ctx.get::<Style>().clone();
-                    fn option<'a>(
-                        a: &'a str,
-                        b: impl FnMut(&mut Context) + 'static,
-                    ) -> MenuOption<'a> {
-                        (a, Box::new(b))
-                    }
-                    send_emu(ctx, EmulatorEvent::Pause);
-                    let options = vec![
-                        option("Save State", |ctx| send_emu(ctx, EmulatorEvent::SaveState)),
-                        option("Load State", |ctx| send_emu(ctx, EmulatorEvent::LoadState)),
-                        option("Reset", |ctx| send_emu(ctx, EmulatorEvent::Reset)),
-                        option("Exit Game", |ctx| {
-                            ctx.get::<EventLoopProxy<UserEvent>>()
-                                .send_event(UserEvent::PopApp)
-                                .unwrap();
-                        }),
-                    ];
-                    let on_close = |ctx: &mut Context| send_emu(ctx, EmulatorEvent::Resume);
-                    create_menu(options, on_close, ctx, &style);
-                },
+                move |_, ctx| open_game_menu(ctx),
             ))
             .build(ctx);
     }
 }
+
+/// Pause the game, and open the menu with the save state, reset and exit options. The game is
+/// resumed when the menu is closed.
+pub fn open_game_menu(ctx: &mut Context) {
+    let style = ctx.get::<Style>().clone();
+    fn option<'a>(a: &'a str, b: impl FnMut(&mut Context) + 'static) -> MenuOption<'a> {
+        (a, Box::new(b))
+    }
+    send_emu(ctx, EmulatorEvent::Pause);
+    let options = vec![
+        option("Save State", |ctx| send_emu(ctx, EmulatorEvent::SaveState)),
+        option("Load State", |ctx| send_emu(ctx, EmulatorEvent::LoadState)),
+        option("Cheats", |ctx| {
+            let style = ctx.get::<Style>().clone();
+            cheats_dialog::open_cheats_dialog(ctx, &style);
+        }),
+        option("Reset", |ctx| send_emu(ctx, EmulatorEvent::Reset)),
+        option("Power Cycle", |ctx| {
+            send_emu(ctx, EmulatorEvent::PowerCycle(false))
+        }),
+        option("Power Cycle and Clear Save", |ctx| {
+            send_emu(ctx, EmulatorEvent::PowerCycle(true))
+        }),
+        option("Exit Game", |ctx| {
+            ctx.get::<EventLoopProxy<UserEvent>>()
+                .send_event(UserEvent::PopApp)
+                .unwrap();
+        }),
+    ];
+    let on_close = |ctx: &mut Context| send_emu(ctx, EmulatorEvent::Resume);
+    create_menu(options, on_close, ctx, &style);
+}
@@ -0,0 +1,205 @@
+use std::sync::Arc;
+
+use gameroy::gameboy::GameBoy;
+use giui::{
+    layouts::{FitGraphic, HBoxLayout, MarginLayout, VBoxLayout},
+    text::Text,
+    widgets::{Blocker, Button, TextField, TextFieldCallback},
+    Context, Id, RectFill,
+};
+use parking_lot::Mutex;
+
+use crate::{game_database::RomHash, rom_loading::save_cheats, style::Style};
+
+const HINT: &str = "Game Genie (ABC-DEF-GHI) or GameShark (01VVLLHH) code:";
+
+/// Change the cheats of the running game, and save them.
+pub(super) fn edit_cheats(ctx: &mut Context, f: impl FnOnce(&mut GameBoy)) {
+    let hash = *ctx.get::<RomHash>();
+    let mut gb = ctx.get::<Arc<Mutex<GameBoy>>>().lock();
+    f(&mut gb);
+    save_cheats(&gb.cheats, &hash);
+}
+
+struct AddCheat {
+    list: Id,
+    status: Id,
+}
+impl TextFieldCallback for AddCheat {
+    fn on_submit(&mut self, _this: Id, ctx: &mut Context, text: &mut String) {
+        if text.trim().is_empty() {
+            return;
+        }
+        let mut result = Ok(0);
+        edit_cheats(ctx, |gb| result = gb.add_cheat(text));
+        match result {
+            Ok(_) => {
+                ctx.get_graphic_mut(self.status).set_text(HINT);
+                text.clear();
+                update_list(ctx, self.list);
+            }
+            Err(err) => ctx.get_graphic_mut(self.status).set_text(&err),
+        }
+    }
+
+    fn on_change(&mut self, _this: Id, _ctx: &mut Context, _text: &str) {}
+
+    fn on_unfocus(&mut self, _this: Id, _ctx: &mut Context, _text: &mut String) {}
+
+    fn on_keyboard_event(
+        &mut self,
+        _event: giui::KeyboardEvent,
+        _this: Id,
+        _ctx: &mut Context,
+    ) -> bool {
+        false
+    }
+}
+
+/// Rebuild the list of cheats.
+fn update_list(ctx: &mut Context, list: Id) {
+    let Style {
+        text_style,
+        header_style,
+        delete_button,
+        delete_icon,
+        ..
+    } = ctx.get::<Style>().clone();
+
+    for child in ctx.get_active_children(list) {
+        ctx.remove(child);
+    }
+
+    let cheats = ctx
+        .get::<Arc<Mutex<GameBoy>>>()
+        .lock()
+        .cheats
+        .list()
+        .to_vec();
+    if cheats.is_empty() {
+        ctx.create_control()
+            .parent(list)
+            .graphic(Text::new(
+                "No cheats".to_string(),
+                (-1, 0),
+                text_style.clone(),
+            ))
+            .layout(FitGraphic)
+            .build(ctx);
+    }
+
+    for cheat in cheats {
+        let id = cheat.id;
+        let enabled = cheat.enabled;
+        let text = format!("[{}] {}", if enabled { "x" } else { " " }, cheat.code);
+        let text_style = text_style.clone();
+        let header_style = header_style.clone();
+        let delete_button = delete_button.clone();
+        let delete_icon = delete_icon.clone();
+        ctx.create_control()
+            .parent(list)
+            .layout(HBoxLayout::new(0.0, [0.0; 4], 1))
+            .child(ctx, |cb, ctx| {
+                cb.behaviour(Button::new(header_style, false, move |_, ctx| {
+                    edit_cheats(ctx, |gb| {
+                        gb.set_cheat_enabled(id, !enabled);
+                    });
+                    update_list(ctx, list);
+                }))
+                .expand_x(true)
+                .child(ctx, |cb, _| {
+                    cb.graphic(Text::new(text, (-1, 0), text_style))
+                        .layout(FitGraphic)
+                })
+            })
+            .child(ctx, |cb, ctx| {
+                cb.behaviour(Button::new(delete_button, true, move |_, ctx| {
+                    edit_cheats(ctx, |gb| {
+                        gb.remove_cheat(id);
+                    });
+                    update_list(ctx, list);
+                }))
+                .min_size([16.0, 16.0])
+                .child(ctx, |cb, _| cb.graphic(delete_icon))
+                .fill_y(RectFill::ShrinkCenter)
+            })
+            .build(ctx);
+    }
+}
+
+/// Open a dialog listing the cheats of the running game, where they can be added, removed,
+/// enabled and disabled. The cheats are saved on each change, keyed by the hash of the rom.
+pub fn open_cheats_dialog(ctx: &mut Context, style: &Style) {
+    let [dialog, blocker, list, status, caret, label] = [(); 6].map(|_| ctx.reserve());
+
+    ctx.create_control_reserved(blocker)
+        .parent(Id::ROOT_ID)
+        .behaviour(Blocker::new(move |_, ctx| {
+            ctx.remove(dialog);
+            ctx.remove(blocker);
+        }))
+        .graphic(style.blocker.clone())
+        .build(ctx);
+
+    ctx.create_control_reserved(dialog)
+        .parent(Id::ROOT_ID)
+        .layout(VBoxLayout::new(4.0, [10.0; 4], -1))
+        .graphic(style.split_background.clone())
+        .fill_x(RectFill::ShrinkCenter)
+        .fill_y(RectFill::ShrinkCenter)
+        .build(ctx);
+
+    ctx.create_control()
+        .parent(dialog)
+        .layout(MarginLayout::new([0.0, 0.0, 0.0, 6.0]))
+        .child(ctx, |cb, _| {
+            cb.graphic(Text::new(
+                "Cheats".to_string(),
+                (-1, 0),
+                style.text_menu.clone(),
+            ))
+            .layout(FitGraphic)
+        })
+        .build(ctx);
+
+    ctx.create_control_reserved(list)
+        .parent(dialog)
+        .layout(VBoxLayout::new(2.0, [0.0; 4], -1))
+        .min_size([300.0, 0.0])
+        .build(ctx);
+
+    ctx.create_control_reserved(status)
+        .parent(dialog)
+        .graphic(Text::new(
+            HINT.to_string(),
+            (-1, 0),
+            style.text_style.clone(),
+        ))
+        .layout(FitGraphic)
+        .build(ctx);
+
+    let text_field = ctx
+        .create_control()
+        .parent(dialog)
+        .behaviour(TextField::new(
+            caret,
+            label,
+            false,
+            style.text_field.clone(),
+            AddCheat { list, status },
+        ))
+        .min_size([20.0; 2])
+        .focus(true)
+        .build(ctx);
+    ctx.create_control_reserved(caret)
+        .parent(text_field)
+        .graphic(style.background.clone().with_color([0, 0, 0, 255].into()))
+        .anchors([0.0; 4])
+        .build(ctx);
+    ctx.create_control_reserved(label)
+        .parent(text_field)
+        .graphic(Text::new(String::new(), (-1, -1), style.text_style.clone()))
+        .build(ctx);
+
+    update_list(ctx, list);
+}
@@ -0,0 +1,113 @@
+use std::sync::Arc;
+
+use gameroy::{debugger::cpu_ppu_state, gameboy::GameBoy};
+use giui::{
+    layouts::{FitGraphic, MarginLayout},
+    text::Text,
+    Behaviour, BuilderContext, Context, Id, RectFill,
+};
+use parking_lot::Mutex;
+
+use crate::{
+    config::Model,
+    event_table::{self, EmulatorUpdated, EventTable, FrameUpdated, Handle},
+    style::Style,
+};
+
+/// A panel over the game screen, that shows the model running the game, the state of the CPU and
+/// the PPU, and the next instruction. It is toggled with the `debug_overlay` key.
+///
+/// The state is gathered with `cpu_ppu_state`, that only uses `peek`, so the overlay has no effect
+/// on the emulation. While hidden, it doesn't lock the GameBoy at all.
+struct DebugOverlay {
+    panel: Id,
+    text: Id,
+    /// The model chosen for the game, see `game_model`.
+    model: Model,
+    _frame_updated_event: Handle<FrameUpdated>,
+    _emulator_updated_event: Handle<EmulatorUpdated>,
+    _debug_overlay_event: Handle<event_table::DebugOverlay>,
+}
+impl DebugOverlay {
+    fn update(&mut self, ctx: &mut Context) {
+        let state = {
+            let gb = ctx.get::<Arc<Mutex<GameBoy>>>().lock();
+            cpu_ppu_state(&gb)
+        };
+        let model = match self.model {
+            Model::Cgb => "CGB (emulated as DMG)",
+            x => x.name(),
+        };
+        let text = format!("Model: {}\n{}", model, state);
+        ctx.get_graphic_mut(self.text).set_text(&text);
+    }
+}
+impl Behaviour for DebugOverlay {
+    fn on_start(&mut self, _this: Id, ctx: &mut Context) {
+        self.model = {
+            let gb = ctx.get::<Arc<Mutex<GameBoy>>>().lock();
+            crate::rom_loading::game_model(&gb.cartridge.header)
+        };
+        if ctx.get::<crate::AppState>().debug_overlay {
+            self.update(ctx);
+        } else {
+            ctx.deactive(self.panel);
+        }
+    }
+
+    fn on_event(&mut self, event: Box<dyn std::any::Any>, _this: Id, ctx: &mut Context) {
+        if let Some(&event_table::DebugOverlay(visible)) = event.downcast_ref() {
+            if visible {
+                self.update(ctx);
+                ctx.active(self.panel);
+            } else {
+                ctx.deactive(self.panel);
+            }
+        } else if event.is::<FrameUpdated>() || event.is::<EmulatorUpdated>() {
+            if ctx.get::<crate::AppState>().debug_overlay {
+                self.update(ctx);
+            }
+        }
+    }
+}
+
+/// Build the debug overlay as a child of `parent`, that is expected to have the size of the game
+/// screen.
+pub fn build(
+    ctx: &mut dyn BuilderContext,
+    parent: Id,
+    event_table: &mut EventTable,
+    style: &Style,
+) {
+    let overlay = ctx.reserve();
+    let panel = ctx.reserve();
+    let text = ctx.reserve();
+
+    ctx.create_control_reserved(overlay)
+        .parent(parent)
+        .behaviour(DebugOverlay {
+            panel,
+            text,
+            model: Model::Dmg,
+            _frame_updated_event: event_table.register(overlay),
+            _emulator_updated_event: event_table.register(overlay),
+            _debug_overlay_event: event_table.register(overlay),
+        })
+        .build(ctx);
+    ctx.create_control_reserved(panel)
+        .parent(overlay)
+        .graphic(style.terminal_background.clone())
+        .layout(MarginLayout::new([2.0; 4]))
+        .fill_x(RectFill::ShrinkStart)
+        .fill_y(RectFill::ShrinkStart)
+        .build(ctx);
+    ctx.create_control_reserved(text)
+        .parent(panel)
+        .graphic(Text::new(
+            String::new(),
+            (-1, -1),
+            style.terminal_text_style.clone(),
+        ))
+        .layout(FitGraphic)
+        .build(ctx);
+}
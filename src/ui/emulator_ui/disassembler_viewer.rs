@@ -1,9 +1,9 @@
 use std::{any::Any, ops::Range, sync::Arc, usize};
 
 use gameroy::{
-    debugger::{break_flags, Debugger},
+    debugger::{break_flags, cartridge_state, ram_search::SearchSize, Debugger},
     disassembler::{Address, Directive},
-    gameboy::GameBoy,
+    gameboy::{cheats::Cheat, GameBoy},
 };
 use giui::{
     event::SetValue,
@@ -20,12 +20,17 @@ use parking_lot::Mutex;
 use winit::event::VirtualKeyCode;
 
 use crate::{
-    event_table::{self, BreakpointsUpdated, EmulatorUpdated, EventTable, Handle, WatchsUpdated},
+    event_table::{
+        self, BreakpointsUpdated, EmulatorUpdated, EventTable, Handle, RamSearchUpdated,
+        WatchsUpdated,
+    },
     style::Style,
     ui,
     widget::fold_view,
 };
 
+use super::cheats_dialog::edit_cheats;
+
 struct Callback {
     log_scroll: Id,
     log: Id,
@@ -46,33 +51,35 @@ impl Callback {
 impl TextFieldCallback for Callback {
     fn on_submit(&mut self, _this: Id, ctx: &mut Context, text: &mut String) {
         let mut args: Vec<&str> = text.split_ascii_whitespace().collect();
-        {
-            let gb = ctx.get::<Arc<Mutex<GameBoy>>>().lock();
+        let output = {
+            let mut gb = ctx.get::<Arc<Mutex<GameBoy>>>().lock();
             let mut debugger = ctx.get::<Arc<Mutex<Debugger>>>().lock();
             if args.len() == 0 {
                 args.push("");
             }
 
-            match debugger.execute_command(&*gb, &args) {
-                Ok(_) => {}
+            match debugger.execute_command(&mut *gb, &args) {
+                Ok(output) => output,
                 Err(m) => {
-                    drop((gb, debugger));
                     eprintln!("{}", m);
-                    let fonts = ctx.get_fonts();
-                    if let (rect, Graphic::Text(x)) = ctx.get_rect_and_graphic(self.log) {
-                        let text_layout = &mut x.get_layout(fonts, rect);
-                        text_layout.append(&(m + "\n"), fonts);
-                    };
-                    // scroll to bottom
-                    ctx.send_event_to(
-                        self.log_scroll,
-                        SetScrollPosition {
-                            vertical: true,
-                            value: 1.0,
-                        },
-                    );
+                    m
                 }
             }
+        };
+        if !output.is_empty() {
+            let fonts = ctx.get_fonts();
+            if let (rect, Graphic::Text(x)) = ctx.get_rect_and_graphic(self.log) {
+                let text_layout = &mut x.get_layout(fonts, rect);
+                text_layout.append(&(output + "\n"), fonts);
+            };
+            // scroll to bottom
+            ctx.send_event_to(
+                self.log_scroll,
+                SetScrollPosition {
+                    vertical: true,
+                    value: 1.0,
+                },
+            );
         }
         if !text.trim().is_empty() {
             // don't add to history if it is the same text again and again
@@ -129,12 +136,18 @@ struct JumpToAddress {
     from_address: Address,
 }
 
+/// Scroll the disassembly view to the given address.
+struct FocusAddress(Address);
+
 struct DissasemblerList {
     list: Id,
     cpu: Id,
     ppu: Id,
     pc: Option<Address>,
     directives: Vec<Directive>,
+    /// The largest execution count in the trace, if executions are being counted with `coverage
+    /// count on`. Used to scale the heatmap tint of each line.
+    max_count: Option<u32>,
     items_are_dirty: bool,
     _emulator_updated_event: Handle<EmulatorUpdated>,
 }
@@ -148,14 +161,8 @@ impl DissasemblerList {
     ) -> (Graphic, Option<Range<usize>>) {
         let curr = direc.address;
         let mut text = format!(
-            "{:04x} {:16} ",
-            {
-                let mut address = curr.address;
-                if address < 0x4000 && curr.bank != 0 {
-                    address += 0x4000;
-                }
-                address
-            },
+            "{} {:16} ",
+            curr,
             trace
                 .labels
                 .get(&curr)
@@ -201,7 +208,7 @@ impl DissasemblerList {
         } else {
             None
         };
-        let op_len = text[22..].find(" ").unwrap();
+        let op_len = text[25..].find(" ").unwrap();
 
         let mut text = Text::new(text, (-1, 0), style);
 
@@ -210,13 +217,32 @@ impl DissasemblerList {
         let number = 0xd79314ff.into();
         let address = 0x6f7e67ff.into();
 
-        text.add_span(0..4, Span::Color(address));
-        text.add_span(5..21, Span::Color(label));
+        text.add_span(0..7, Span::Color(address));
+        text.add_span(8..24, Span::Color(label));
         label_range
             .as_ref()
             .map(|r| text.add_span(r.clone(), Span::Color(label)));
-        text.add_span(22..22 + op_len, Span::Color(op));
+        text.add_span(25..25 + op_len, Span::Color(op));
         address_range.map(|r| text.add_span(r, Span::Color(number)));
+        // ram directives reuse Address with addresses outside of a bank, so they are not tinted.
+        let count = match self.max_count {
+            Some(max) if max > 0 && curr.address < 0x4000 => {
+                trace.execution_count(curr).map(|count| (count, max))
+            }
+            _ => None,
+        };
+        if let Some((count, max)) = count.filter(|&(count, _)| count > 0) {
+            // logarithmic scale, so the lines executed a few times are still visible.
+            let heat = ((count as f32).ln_1p() / (max as f32).ln_1p()).min(1.0);
+            let alpha = (0x20 as f32 + heat * (0xa0 - 0x20) as f32) as u8;
+            text.add_span(
+                0..text.len(),
+                Span::Selection {
+                    bg: [0xd0, 0x30, 0x10, alpha].into(),
+                    fg: None,
+                },
+            );
+        }
         if Some(curr) == pc {
             text.add_span(
                 0..text.len(),
@@ -249,6 +275,12 @@ impl ListBuilder for DissasemblerList {
                 result
             }
 
+            let history = ctx
+                .get::<Arc<Mutex<Debugger>>>()
+                .lock()
+                .history
+                .available_seconds(&gb);
+
             let cpu = &gb.cpu;
             let cpu_text = format!(
                 " clock: {}
@@ -258,7 +290,9 @@ impl ListBuilder for DissasemblerList {
  HL: {:02x} {:02x}
  SP: {:04x}
  PC: {:04x}
- DIV:{:04x}",
+ DIV:{:04x}
+ {}
+ history available: {:.1} s",
                 decimal_mark(gb.clock_count),
                 cpu.a,
                 cpu.f.0,
@@ -271,6 +305,8 @@ impl ListBuilder for DissasemblerList {
                 cpu.sp,
                 cpu.pc,
                 gb.timer.div,
+                cartridge_state(&gb.cartridge),
+                history,
             );
 
             if let Graphic::Text(text) = ctx.get_graphic_mut(self.cpu) {
@@ -318,6 +354,7 @@ impl ListBuilder for DissasemblerList {
             let trace = gb.trace.borrow();
 
             self.items_are_dirty = true;
+            self.max_count = trace.max_execution_count();
             self.directives.clear();
             self.directives.extend(trace.directives.iter().cloned());
             self.directives
@@ -336,7 +373,7 @@ impl ListBuilder for DissasemblerList {
             debug_assert!(self.directives.windows(2).all(|x| x[0] <= x[1]));
 
             let pc = cpu.pc;
-            let bank = gb.cartridge.curr_bank();
+            let bank = gb.cartridge.curr_rom_bank();
             self.pc = Some(Address::from_pc(Some(bank), pc).unwrap_or(Address {
                 address: pc,
                 bank: 0xFF,
@@ -353,6 +390,19 @@ impl ListBuilder for DissasemblerList {
                     },
                 );
             };
+        } else if let Some(&FocusAddress(address)) = event.downcast_ref::<FocusAddress>() {
+            let pos = self
+                .directives
+                .binary_search_by(|x| x.address.cmp(&address));
+            if let Ok(pos) = pos {
+                ctx.send_event_to(
+                    self.list,
+                    FocusItem {
+                        index: pos,
+                        margin: 30.0,
+                    },
+                );
+            };
         } else if let Some(JumpToAddress { from_address }) = event.downcast_ref::<JumpToAddress>() {
             let gb = ctx.get::<Arc<Mutex<GameBoy>>>().lock();
             let trace = gb.trace.borrow_mut();
@@ -459,8 +509,14 @@ impl BreakpointList {
 
             flags_str
         };
-        let text = format!("{} {:04x}", flags, address);
-        text
+        let address = *address;
+        drop(debugger);
+        let gb = ctx.get::<Arc<Mutex<GameBoy>>>().lock();
+        let trace = gb.trace.borrow();
+        match trace.symbol(address.bank, address.address) {
+            Some(name) => format!("{} {} {}", flags, address, name),
+            None => format!("{} {}", flags, address),
+        }
     }
 }
 impl ListBuilder for BreakpointList {
@@ -512,7 +568,7 @@ impl WatchsList {
             .iter()
             .nth(index)
             .unwrap();
-        let value = ctx.get::<Arc<Mutex<GameBoy>>>().lock().read(address);
+        let value = ctx.get::<Arc<Mutex<GameBoy>>>().lock().peek(address);
         let text = format!("{:04x} = {:02x}", address, value);
         (address, text)
     }
@@ -552,6 +608,253 @@ impl ListBuilder for WatchsList {
     }
 }
 
+struct WatchpointsList {
+    _watchs_updated_event: Handle<WatchsUpdated>,
+    _emulator_updated_event: Handle<EmulatorUpdated>,
+}
+impl WatchpointsList {
+    fn get_text(ctx: &mut dyn BuilderContext, index: usize) -> String {
+        let gb = ctx.get::<Arc<Mutex<GameBoy>>>().lock();
+        let watchpoint = gb.watchpoints.list()[index];
+        let flags = format!(
+            "{}{}",
+            if watchpoint.write { 'w' } else { '-' },
+            if watchpoint.read { 'r' } else { '-' },
+        );
+        let range = if watchpoint.start == watchpoint.end {
+            format!("{:04x}", watchpoint.start)
+        } else {
+            format!("{:04x}-{:04x}", watchpoint.start, watchpoint.end)
+        };
+        format!("{} {} hits: {}", flags, range, watchpoint.hits)
+    }
+}
+impl ListBuilder for WatchpointsList {
+    fn on_event(&mut self, event: Box<dyn Any>, this: Id, ctx: &mut Context) {
+        if event.is::<event_table::WatchsUpdated>() || event.is::<event_table::EmulatorUpdated>() {
+            ctx.send_event_to(this, UpdateItems);
+        }
+    }
+
+    fn item_count(&mut self, ctx: &mut dyn BuilderContext) -> usize {
+        ctx.get::<Arc<Mutex<GameBoy>>>()
+            .lock()
+            .watchpoints
+            .list()
+            .len()
+    }
+
+    fn create_item<'a>(
+        &mut self,
+        index: usize,
+        _list_id: Id,
+        cb: ControlBuilder,
+        ctx: &mut dyn BuilderContext,
+    ) -> ControlBuilder {
+        let text = Self::get_text(ctx, index);
+        list_item(ctx, cb, text, move |_: Id, ctx| {
+            let mut gb = ctx.get::<Arc<Mutex<GameBoy>>>().lock();
+            let mut debugger = ctx.get::<Arc<Mutex<Debugger>>>().lock();
+            debugger.remove_watchpoint(&mut *gb, index);
+        })
+    }
+
+    fn update_item(&mut self, _index: usize, _item_id: Id, _ctx: &mut dyn BuilderContext) -> bool {
+        // the index of the watchpoint is captured by the delete button, so the item needs to be
+        // rebuilt.
+        false
+    }
+}
+
+struct RamSearchList {
+    _ram_search_updated_event: Handle<RamSearchUpdated>,
+    _emulator_updated_event: Handle<EmulatorUpdated>,
+}
+impl RamSearchList {
+    /// Get the address of the result, and its size.
+    fn get_result(debugger: &Debugger, index: usize) -> Option<(u16, SearchSize)> {
+        let search = debugger.ram_search.as_ref()?;
+        Some((*search.results().get(index)?, search.size))
+    }
+
+    fn get_text(ctx: &mut dyn BuilderContext, index: usize) -> String {
+        let (address, size, previous) = {
+            let debugger = ctx.get::<Arc<Mutex<Debugger>>>().lock();
+            match Self::get_result(&debugger, index) {
+                Some((address, size)) => {
+                    let search = debugger.ram_search.as_ref().unwrap();
+                    (address, size, search.previous_value(address))
+                }
+                None => return String::new(),
+            }
+        };
+        let value = current_value(&ctx.get::<Arc<Mutex<GameBoy>>>().lock(), address, size);
+        match size {
+            SearchSize::Byte => format!("{:04x} = {:02x} ({:02x})", address, value, previous),
+            SearchSize::Word => format!("{:04x} = {:04x} ({:04x})", address, value, previous),
+        }
+    }
+}
+
+/// The value at `address` in the GameBoy memory, little-endian for words.
+fn current_value(gb: &GameBoy, address: u16, size: SearchSize) -> u16 {
+    let low = gb.peek(address) as u16;
+    match size {
+        SearchSize::Byte => low,
+        SearchSize::Word => low | (gb.peek(address.wrapping_add(1)) as u16) << 8,
+    }
+}
+
+impl ListBuilder for RamSearchList {
+    fn on_event(&mut self, event: Box<dyn Any>, this: Id, ctx: &mut Context) {
+        if event.is::<event_table::RamSearchUpdated>() || event.is::<event_table::EmulatorUpdated>()
+        {
+            ctx.send_event_to(this, UpdateItems);
+        }
+    }
+
+    fn item_count(&mut self, ctx: &mut dyn BuilderContext) -> usize {
+        ctx.get::<Arc<Mutex<Debugger>>>()
+            .lock()
+            .ram_search
+            .as_ref()
+            .map_or(0, |x| x.results().len())
+    }
+
+    fn create_item<'a>(
+        &mut self,
+        index: usize,
+        _list_id: Id,
+        cb: ControlBuilder,
+        ctx: &mut dyn BuilderContext,
+    ) -> ControlBuilder {
+        let text = Self::get_text(ctx, index);
+        let text_style = ctx.get::<Style>().text_style.clone();
+        let watch = move |_: Id, ctx: &mut Context| {
+            let debugger = ctx.get::<Arc<Mutex<Debugger>>>().lock();
+            let (address, size) = match Self::get_result(&debugger, index) {
+                Some(x) => x,
+                None => return,
+            };
+            drop(debugger);
+            let end = address + (size == SearchSize::Word) as u16;
+            let mut gb = ctx.get::<Arc<Mutex<GameBoy>>>().lock();
+            let mut debugger = ctx.get::<Arc<Mutex<Debugger>>>().lock();
+            debugger.add_watchpoint(&mut *gb, address, end, false, true);
+        };
+        // write the current value every frame
+        let freeze = move |_: Id, ctx: &mut Context| {
+            let debugger = ctx.get::<Arc<Mutex<Debugger>>>().lock();
+            let (address, size) = match Self::get_result(&debugger, index) {
+                Some(x) => x,
+                None => return,
+            };
+            drop(debugger);
+            edit_cheats(ctx, |gb| {
+                let value = current_value(gb, address, size).to_le_bytes();
+                let len = if size == SearchSize::Word { 2 } else { 1 };
+                for (i, &value) in value[..len].iter().enumerate() {
+                    let code = Cheat::game_shark_code(address + i as u16, value);
+                    if let Err(err) = gb.add_cheat(&code) {
+                        log::error!("{}", err);
+                    }
+                }
+            });
+        };
+        cb.layout(HBoxLayout::new(0.0, [0.0; 4], 1))
+            .child(ctx, |cb, _| {
+                cb.graphic(Text::new(text, (-1, 0), text_style))
+                    .layout(FitGraphic)
+                    .expand_x(true)
+            })
+            .child(ctx, |cb, ctx| text_button(ctx, cb, "watch", watch))
+            .child(ctx, |cb, ctx| text_button(ctx, cb, "freeze", freeze))
+    }
+
+    fn update_item(&mut self, index: usize, item_id: Id, ctx: &mut dyn BuilderContext) -> bool {
+        let text = Self::get_text(ctx, index);
+        let text_id = ctx.get_active_children(item_id)[0];
+        if let Graphic::Text(x) = ctx.get_graphic_mut(text_id) {
+            x.set_string(&text);
+        }
+        true
+    }
+}
+
+struct CallStackList {
+    /// The disassembly list, that is scrolled to a frame when it is clicked.
+    disassembly: Id,
+    _emulator_updated_event: Handle<EmulatorUpdated>,
+}
+impl CallStackList {
+    /// Get the text of the frame, and its address. Frames are listed from the innermost to the
+    /// outermost.
+    fn get_frame(ctx: &mut dyn BuilderContext, index: usize) -> (Option<Address>, String) {
+        let gb = ctx.get::<Arc<Mutex<GameBoy>>>().lock();
+        let frame = match gb.call_stack().iter().rev().nth(index) {
+            Some(x) => *x,
+            None => return (None, String::new()),
+        };
+        let address = Address::from_pc(frame.bank, frame.address);
+        let bank = frame
+            .bank
+            .map_or("--".to_string(), |x| format!("{:02x}", x));
+        let symbol = gb
+            .trace
+            .borrow()
+            .symbol(frame.bank, frame.address)
+            .unwrap_or("")
+            .to_string();
+        let text = format!(
+            "{}:{:04x} {} {}",
+            bank,
+            frame.address,
+            symbol,
+            if frame.interrupt { "int" } else { "" }
+        );
+        (address, text)
+    }
+}
+impl ListBuilder for CallStackList {
+    fn on_event(&mut self, event: Box<dyn Any>, this: Id, ctx: &mut Context) {
+        if event.is::<event_table::EmulatorUpdated>() {
+            ctx.send_event_to(this, UpdateItems);
+        }
+    }
+
+    fn item_count(&mut self, ctx: &mut dyn BuilderContext) -> usize {
+        ctx.get::<Arc<Mutex<GameBoy>>>().lock().call_stack().len()
+    }
+
+    fn create_item<'a>(
+        &mut self,
+        index: usize,
+        _list_id: Id,
+        cb: ControlBuilder,
+        ctx: &mut dyn BuilderContext,
+    ) -> ControlBuilder {
+        let (address, text) = Self::get_frame(ctx, index);
+        let style = ctx.get::<Style>().clone();
+        let disassembly = self.disassembly;
+        cb.layout(HBoxLayout::new(0.0, [0.0; 4], 1))
+            .behaviour(Button::new(style.header_style, false, move |_, ctx| {
+                if let Some(address) = address {
+                    ctx.send_event_to(disassembly, FocusAddress(address));
+                }
+            }))
+            .child(ctx, |cb, _| {
+                cb.graphic(Text::new(text, (-1, 0), style.text_style))
+                    .layout(FitGraphic)
+                    .expand_x(true)
+            })
+    }
+
+    fn update_item(&mut self, _index: usize, _item_id: Id, _ctx: &mut dyn BuilderContext) -> bool {
+        // the address of the frame is captured by the button, so the item needs to be rebuilt.
+        false
+    }
+}
+
 fn list_item(
     ctx: &mut dyn BuilderContext,
     cb: ControlBuilder,
@@ -578,15 +881,34 @@ fn list_item(
         })
 }
 
+fn text_button(
+    ctx: &mut dyn BuilderContext,
+    cb: ControlBuilder,
+    text: &str,
+    on_click: impl FnMut(Id, &mut Context) + 'static,
+) -> ControlBuilder {
+    let Style {
+        text_style,
+        header_style,
+        ..
+    } = ctx.get::<Style>().clone();
+    cb.behaviour(Button::new(header_style, true, on_click))
+        .layout(FitGraphic)
+        .child(ctx, |cb, _| {
+            cb.graphic(Text::new(text.to_string(), (0, 0), text_style))
+                .layout(FitGraphic)
+        })
+}
+
 pub fn build(
     parent: Id,
     ctx: &mut dyn BuilderContext,
     event_table: &mut EventTable,
     style: &Style,
+    list_id: Id,
     cpu_id: Id,
     ppu_id: Id,
 ) {
-    let list_id = ctx.reserve();
     ui::list(
         ctx.create_control_reserved(list_id),
         ctx,
@@ -598,6 +920,7 @@ pub fn build(
             ppu: ppu_id,
             pc: None,
             directives: Vec::new(),
+            max_count: None,
             items_are_dirty: true,
             _emulator_updated_event: event_table.register(list_id),
         },
@@ -610,6 +933,7 @@ pub fn side_panel(
     ctx: &mut dyn BuilderContext,
     style: &Style,
     parent: Id,
+    disassembly_id: Id,
     cpu_id: Id,
     ppu_id: Id,
     event_table: &mut EventTable,
@@ -672,6 +996,57 @@ pub fn side_panel(
         },
     )
     .build(ctx);
+    let watchpoints = fold_view::folder(ctx, "watchpoints".to_string(), style)
+        .parent(right_panel)
+        .build(ctx);
+    let watchpoints_list = ctx.reserve();
+    ui::list(
+        ctx.create_control_reserved(watchpoints_list)
+            .parent(watchpoints)
+            .min_size([50.0, 100.0]),
+        ctx,
+        style,
+        [10.0, 0.0, 0.0, 0.0],
+        WatchpointsList {
+            _watchs_updated_event: event_table.register(watchpoints_list),
+            _emulator_updated_event: event_table.register(watchpoints_list),
+        },
+    )
+    .build(ctx);
+    let search = fold_view::folder(ctx, "search".to_string(), style)
+        .parent(right_panel)
+        .build(ctx);
+    let search_list = ctx.reserve();
+    ui::list(
+        ctx.create_control_reserved(search_list)
+            .parent(search)
+            .min_size([50.0, 100.0]),
+        ctx,
+        style,
+        [10.0, 0.0, 0.0, 0.0],
+        RamSearchList {
+            _ram_search_updated_event: event_table.register(search_list),
+            _emulator_updated_event: event_table.register(search_list),
+        },
+    )
+    .build(ctx);
+    let stack = fold_view::folder(ctx, "stack".to_string(), style)
+        .parent(right_panel)
+        .build(ctx);
+    let stack_list = ctx.reserve();
+    ui::list(
+        ctx.create_control_reserved(stack_list)
+            .parent(stack)
+            .min_size([50.0, 100.0]),
+        ctx,
+        style,
+        [10.0, 0.0, 0.0, 0.0],
+        CallStackList {
+            disassembly: disassembly_id,
+            _emulator_updated_event: event_table.register(stack_list),
+        },
+    )
+    .build(ctx);
     ctx.create_control()
         .expand_y(true)
         .parent(right_panel)
@@ -0,0 +1,126 @@
+use std::{any::Any, sync::Arc};
+
+use gameroy::gameboy::{instruction_history::HistoryEntry, GameBoy};
+use giui::{
+    layouts::{FitGraphic, VBoxLayout},
+    text::{Span, Text},
+    widgets::{FocusItem, ListBuilder, UpdateItems},
+    BuilderContext, Context, ControlBuilder, Id,
+};
+use parking_lot::Mutex;
+
+use crate::{
+    event_table::{EmulatorUpdated, EventTable, Handle},
+    style::Style,
+    ui,
+};
+
+/// A listing of the last executed instructions, from the most recent to the oldest, recorded
+/// when the history is enabled with the `history on` command.
+///
+/// The history is only read when the emulator is paused, like after a breakpoint or a CPU lock.
+struct HistoryList {
+    status: Id,
+    /// A copy of the recorded instructions, from the most recent to the oldest.
+    entries: Vec<HistoryEntry>,
+    _emulator_updated_event: Handle<EmulatorUpdated>,
+}
+impl HistoryList {
+    fn update(&mut self, gb: &GameBoy) -> String {
+        self.entries.clear();
+        match &gb.instruction_history {
+            Some(history) => {
+                self.entries.extend(history.iter().copied());
+                format!(
+                    "last {} of {} instructions, most recent first",
+                    history.len(),
+                    history.capacity()
+                )
+            }
+            None => "the history is disabled, enable it with 'history on <count>'".to_string(),
+        }
+    }
+}
+impl ListBuilder for HistoryList {
+    fn on_event(&mut self, event: Box<dyn Any>, this: Id, ctx: &mut Context) {
+        if event.is::<EmulatorUpdated>() {
+            let gb = ctx.get::<Arc<Mutex<GameBoy>>>().clone();
+            let status = self.update(&gb.lock());
+            ctx.get_graphic_mut(self.status).set_text(&status);
+            ctx.send_event_to(
+                this,
+                FocusItem {
+                    index: 0,
+                    margin: 0.0,
+                },
+            );
+            ctx.send_event_to(this, UpdateItems);
+        }
+    }
+
+    fn item_count(&mut self, _ctx: &mut dyn BuilderContext) -> usize {
+        self.entries.len()
+    }
+
+    fn create_item<'a>(
+        &mut self,
+        index: usize,
+        _list_id: Id,
+        cb: ControlBuilder,
+        ctx: &mut dyn BuilderContext,
+    ) -> ControlBuilder {
+        let style = ctx.get::<Style>().text_style.clone();
+        let mut line = String::new();
+        {
+            let gb = ctx.get::<Arc<Mutex<GameBoy>>>().clone();
+            let _ = self.entries[index].fmt(&gb.lock(), &mut line);
+        }
+        let mut text = Text::new(line, (-1, 0), style);
+        text.add_span(0..7, Span::Color(0x6f7e67ff.into()));
+        cb.min_size([0.0, 15.0]).graphic(text).layout(FitGraphic)
+    }
+
+    fn update_item(&mut self, _index: usize, _item_id: Id, _ctx: &mut dyn BuilderContext) -> bool {
+        false
+    }
+}
+
+pub fn build(
+    parent: Id,
+    ctx: &mut dyn BuilderContext,
+    event_table: &mut EventTable,
+    style: &Style,
+) {
+    let list = ctx.reserve();
+    let status = ctx.reserve();
+
+    let page = ctx
+        .create_control()
+        .parent(parent)
+        .layout(VBoxLayout::new(2.0, [0.0; 4], -1))
+        .build(ctx);
+
+    let mut history_list = HistoryList {
+        status,
+        entries: Vec::new(),
+        _emulator_updated_event: event_table.register(list),
+    };
+    let text = history_list.update(&ctx.get::<Arc<Mutex<GameBoy>>>().lock());
+
+    ctx.create_control_reserved(status)
+        .parent(page)
+        .graphic(Text::new(text, (-1, 0), style.text_style.clone()))
+        .layout(FitGraphic)
+        .build(ctx);
+
+    ui::list(
+        ctx.create_control_reserved(list),
+        ctx,
+        style,
+        [0.0; 4],
+        history_list,
+    )
+    .parent(page)
+    .expand_y(true)
+    .build(ctx);
+}
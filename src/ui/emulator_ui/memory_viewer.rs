@@ -0,0 +1,410 @@
+use std::{any::Any, ops::Range, sync::Arc};
+
+use gameroy::{
+    debugger::memory_view::{ascii_char, parse_pattern, MemoryRegion},
+    gameboy::GameBoy,
+};
+use giui::{
+    graphics::Graphic,
+    layouts::{FitGraphic, HBoxLayout, VBoxLayout},
+    text::{Span, Text, TextStyle},
+    widgets::{FocusItem, InteractiveText, ListBuilder, TextField, TextFieldCallback, UpdateItems},
+    BuilderContext, Color, Context, ControlBuilder, Id, MouseInfo,
+};
+use parking_lot::Mutex;
+
+use crate::{
+    event_table::{EmulatorUpdated, EventTable, FrameUpdated, Handle},
+    style::Style,
+    ui,
+};
+
+const BYTES_PER_ROW: usize = 16;
+
+/// Events sent to the memory list by the fields above it, or by its rows.
+enum MemoryEvent {
+    SetRegion(MemoryRegion),
+    GoTo(u16),
+    /// Search for the next occurrence of the bytes, after the selected one.
+    Search(Vec<u8>),
+    Select(usize),
+    /// Write the bytes at the selected address, and select the byte after them.
+    Write(Vec<u8>),
+}
+
+/// A hex view of a region of memory, with 16 bytes per row. Bytes that changed since the last
+/// update are highlighted.
+///
+/// The memory is only read when the emulator finishes a frame or is paused, so the emulation is
+/// not perturbed.
+struct MemoryList {
+    status: Id,
+    region: MemoryRegion,
+    /// The content of the region in the last update.
+    memory: Vec<u8>,
+    /// The content of the region in the update before the last one.
+    previous: Vec<u8>,
+    selected: Option<usize>,
+    _frame_updated_event: Handle<FrameUpdated>,
+    _emulator_updated_event: Handle<EmulatorUpdated>,
+}
+impl MemoryList {
+    fn update(&mut self, gb: &GameBoy) {
+        let memory = self.region.snapshot(gb);
+        self.previous = std::mem::replace(&mut self.memory, memory);
+    }
+
+    /// The text range of the byte `i` of a row, in the hex and ASCII columns.
+    fn byte_ranges(&self, i: usize) -> (Range<usize>, Range<usize>) {
+        let hex = self.address_len() + 2 + 3 * i;
+        let ascii = self.address_len() + 2 + 3 * BYTES_PER_ROW + 1 + i;
+        (hex..hex + 2, ascii..ascii + 1)
+    }
+
+    /// The length of the address column. Banked regions are prefixed by their bank.
+    fn address_len(&self) -> usize {
+        match self.region {
+            MemoryRegion::RomBank(_) | MemoryRegion::CartRamBank(_) => 7,
+            _ => 4,
+        }
+    }
+
+    fn row_text(&self, index: usize, style: TextStyle) -> Text {
+        let start = index * BYTES_PER_ROW;
+        let row = &self.memory[start..(start + BYTES_PER_ROW).min(self.memory.len())];
+        let address = self.region.address(start);
+        let mut text = match self.region {
+            MemoryRegion::RomBank(bank) => format!("{:02x}:{:04x}  ", bank, address),
+            MemoryRegion::CartRamBank(bank) => format!("{:02x}:{:04x}  ", bank, address),
+            _ => format!("{:04x}  ", address),
+        };
+        for i in 0..BYTES_PER_ROW {
+            match row.get(i) {
+                Some(value) => text += &format!("{:02x} ", value),
+                None => text += "   ",
+            }
+        }
+        text.push(' ');
+        text.extend(row.iter().map(|&x| ascii_char(x)));
+
+        let mut text = Text::new(text, (-1, 0), style);
+        text.add_span(0..self.address_len(), Span::Color(0x6f7e67ff.into()));
+        for (i, value) in row.iter().enumerate() {
+            let offset = start + i;
+            let (hex, ascii) = self.byte_ranges(i);
+            if self.previous.get(offset).map_or(false, |x| x != value) {
+                text.add_span(hex.clone(), Span::Color(0xff1a1aff.into()));
+                text.add_span(ascii.clone(), Span::Color(0xff1a1aff.into()));
+            }
+            if self.selected == Some(offset) {
+                for range in [hex, ascii] {
+                    text.add_span(
+                        range,
+                        Span::Selection {
+                            bg: Color::BLACK,
+                            fg: None,
+                        },
+                    );
+                }
+            }
+        }
+        text
+    }
+
+    fn set_status(&self, ctx: &mut Context, status: &str) {
+        ctx.get_graphic_mut(self.status).set_text(status);
+    }
+
+    /// Select the byte at `offset`, and show its address and value. If the address is in the ROM
+    /// range, a warning is shown, because writes to it are interpreted as MBC register writes.
+    fn select(&mut self, offset: usize, focus: bool, this: Id, ctx: &mut Context) {
+        self.selected = Some(offset);
+        let address = self.region.address(offset);
+        let status = if self.region.writes_to_mbc(offset) {
+            format!(
+                "{:04x} = {:02x}. Warning: writes to {:04x} don't change the ROM, but are \
+                 interpreted as MBC register writes.",
+                address, self.memory[offset], address
+            )
+        } else {
+            format!("{:04x} = {:02x}", address, self.memory[offset])
+        };
+        self.set_status(ctx, &status);
+        if focus {
+            ctx.send_event_to(
+                this,
+                FocusItem {
+                    index: offset / BYTES_PER_ROW,
+                    margin: 30.0,
+                },
+            );
+        }
+        ctx.send_event_to(this, UpdateItems);
+    }
+}
+impl ListBuilder for MemoryList {
+    fn on_event(&mut self, event: Box<dyn Any>, this: Id, ctx: &mut Context) {
+        let gb = ctx.get::<Arc<Mutex<GameBoy>>>().clone();
+        if event.is::<FrameUpdated>() || event.is::<EmulatorUpdated>() {
+            self.update(&gb.lock());
+            ctx.send_event_to(this, UpdateItems);
+            return;
+        }
+        let event = match event.downcast_ref::<MemoryEvent>() {
+            Some(x) => x,
+            None => return,
+        };
+        match event {
+            &MemoryEvent::SetRegion(region) => {
+                let gb = gb.lock();
+                if region.len(&gb) == 0 {
+                    drop(gb);
+                    self.set_status(ctx, "the cartridge don't have this bank");
+                    return;
+                }
+                self.region = region;
+                self.memory = region.snapshot(&gb);
+                self.previous = self.memory.clone();
+                self.selected = None;
+                drop(gb);
+                self.set_status(ctx, "");
+                ctx.send_event_to(
+                    this,
+                    FocusItem {
+                        index: 0,
+                        margin: 0.0,
+                    },
+                );
+                ctx.send_event_to(this, UpdateItems);
+            }
+            &MemoryEvent::GoTo(address) => {
+                let offset = self.region.offset(&gb.lock(), address);
+                match offset {
+                    Some(offset) => self.select(offset, true, this, ctx),
+                    None => {
+                        let status = format!("{:04x} is outside of the region", address);
+                        self.set_status(ctx, &status);
+                    }
+                }
+            }
+            MemoryEvent::Search(pattern) => {
+                let from = self.selected.map_or(0, |x| x + 1);
+                let offset = self.region.search(&gb.lock(), pattern, from);
+                match offset {
+                    Some(offset) => self.select(offset, true, this, ctx),
+                    None => self.set_status(ctx, "not found"),
+                }
+            }
+            &MemoryEvent::Select(offset) => self.select(offset, false, this, ctx),
+            MemoryEvent::Write(bytes) => {
+                let offset = match self.selected {
+                    Some(x) => x,
+                    None => {
+                        self.set_status(ctx, "select a address to write to");
+                        return;
+                    }
+                };
+                let end = (offset + bytes.len()).min(self.memory.len());
+                {
+                    let mut gb = gb.lock();
+                    for (i, &value) in (offset..end).zip(bytes.iter()) {
+                        self.region.write(&mut gb, i, value);
+                    }
+                    self.memory = self.region.snapshot(&gb);
+                }
+                self.select(end.min(self.memory.len() - 1), true, this, ctx);
+            }
+        }
+    }
+
+    fn item_count(&mut self, _ctx: &mut dyn BuilderContext) -> usize {
+        (self.memory.len() + BYTES_PER_ROW - 1) / BYTES_PER_ROW
+    }
+
+    fn create_item<'a>(
+        &mut self,
+        index: usize,
+        list_id: Id,
+        cb: ControlBuilder,
+        ctx: &mut dyn BuilderContext,
+    ) -> ControlBuilder {
+        let style = ctx.get::<Style>().text_style.clone();
+        let graphic: Graphic = self.row_text(index, style).into();
+        let start = index * BYTES_PER_ROW;
+        let row_len = BYTES_PER_ROW.min(self.memory.len() - start);
+        let mut ranges: Vec<(Range<usize>, Box<dyn FnMut(MouseInfo, Id, &mut Context)>)> =
+            Vec::new();
+        for i in 0..row_len {
+            let offset = start + i;
+            let (hex, ascii) = self.byte_ranges(i);
+            for range in [hex, ascii] {
+                ranges.push((
+                    range,
+                    Box::new(move |mouse: MouseInfo, _this: Id, ctx: &mut Context| {
+                        if mouse.click() {
+                            ctx.send_event_to(list_id, MemoryEvent::Select(offset));
+                        }
+                    }),
+                ));
+            }
+        }
+        cb.min_size([0.0, 15.0]).child(ctx, |cb, _| {
+            cb.graphic(graphic)
+                .layout(FitGraphic)
+                .behaviour(InteractiveText::new(ranges))
+        })
+    }
+
+    fn update_item(&mut self, _index: usize, _item_id: Id, _ctx: &mut dyn BuilderContext) -> bool {
+        // the highlighted bytes are spans of the text, so the item needs to be rebuilt.
+        false
+    }
+}
+
+/// A text field that sends the event parsed from its text to the memory list, or shows the parse
+/// error in the status.
+struct MemoryField {
+    list: Id,
+    status: Id,
+    parse: fn(&str) -> Result<MemoryEvent, String>,
+}
+impl TextFieldCallback for MemoryField {
+    fn on_submit(&mut self, _this: Id, ctx: &mut Context, text: &mut String) {
+        match (self.parse)(text) {
+            Ok(event) => ctx.send_event_to(self.list, event),
+            Err(err) => ctx.get_graphic_mut(self.status).set_text(&err),
+        }
+    }
+
+    fn on_change(&mut self, _this: Id, _ctx: &mut Context, _text: &str) {}
+
+    fn on_unfocus(&mut self, _this: Id, _ctx: &mut Context, _text: &mut String) {}
+
+    fn on_keyboard_event(
+        &mut self,
+        _event: giui::KeyboardEvent,
+        _this: Id,
+        _ctx: &mut Context,
+    ) -> bool {
+        false
+    }
+}
+
+fn memory_field(
+    ctx: &mut dyn BuilderContext,
+    parent: Id,
+    style: &Style,
+    name: &str,
+    callback: MemoryField,
+) {
+    let row = ctx
+        .create_control()
+        .parent(parent)
+        .layout(HBoxLayout::new(2.0, [0.0; 4], -1))
+        .child(ctx, |cb, _| {
+            cb.graphic(Text::new(
+                name.to_string(),
+                (-1, 0),
+                style.text_style.clone(),
+            ))
+            .min_size([50.0, 0.0])
+            .layout(FitGraphic)
+        })
+        .build(ctx);
+    let caret = ctx.reserve();
+    let label = ctx.reserve();
+    let text_field = ctx
+        .create_control()
+        .parent(row)
+        .behaviour(TextField::new(
+            caret,
+            label,
+            false,
+            style.text_field.clone(),
+            callback,
+        ))
+        .min_size([20.0; 2])
+        .expand_x(true)
+        .build(ctx);
+    ctx.create_control_reserved(caret)
+        .parent(text_field)
+        .graphic(style.background.clone().with_color([0, 0, 0, 255].into()))
+        .anchors([0.0; 4])
+        .build(ctx);
+    ctx.create_control_reserved(label)
+        .parent(text_field)
+        .graphic(Text::new(String::new(), (-1, -1), style.text_style.clone()))
+        .build(ctx);
+}
+
+pub fn build(
+    parent: Id,
+    ctx: &mut dyn BuilderContext,
+    event_table: &mut EventTable,
+    style: &Style,
+) {
+    let list = ctx.reserve();
+    let status = ctx.reserve();
+
+    let page = ctx
+        .create_control()
+        .parent(parent)
+        .layout(VBoxLayout::new(2.0, [0.0; 4], -1))
+        .build(ctx);
+
+    let fields: [(&str, fn(&str) -> Result<MemoryEvent, String>); 4] = [
+        ("region:", |text| {
+            MemoryRegion::parse(text).map(MemoryEvent::SetRegion)
+        }),
+        ("goto:", |text| {
+            u16::from_str_radix(text.trim().trim_start_matches('$'), 16)
+                .map(MemoryEvent::GoTo)
+                .map_err(|e| format!("invalid address: {}", e))
+        }),
+        ("search:", |text| {
+            parse_pattern(text).map(MemoryEvent::Search)
+        }),
+        ("write:", |text| parse_pattern(text).map(MemoryEvent::Write)),
+    ];
+    for (name, parse) in fields {
+        let callback = MemoryField {
+            list,
+            status,
+            parse,
+        };
+        memory_field(ctx, page, style, name, callback);
+    }
+
+    ctx.create_control_reserved(status)
+        .parent(page)
+        .graphic(Text::new(
+            "region: 'all', 'rom <bank>', 'vram', 'sram <bank>', 'wram', 'oam' or 'hram'. \
+             search and write: hex bytes or a \"string\"."
+                .to_string(),
+            (-1, 0),
+            style.text_style.clone(),
+        ))
+        .layout(FitGraphic)
+        .build(ctx);
+
+    let region = MemoryRegion::All;
+    let memory = region.snapshot(&ctx.get::<Arc<Mutex<GameBoy>>>().lock());
+    ui::list(
+        ctx.create_control_reserved(list),
+        ctx,
+        style,
+        [0.0; 4],
+        MemoryList {
+            status,
+            region,
+            previous: memory.clone(),
+            memory,
+            selected: None,
+            _frame_updated_event: event_table.register(list),
+            _emulator_updated_event: event_table.register(list),
+        },
+    )
+    .parent(page)
+    .expand_y(true)
+    .build(ctx);
+}
@@ -1,6 +1,9 @@
 use std::sync::Arc;
 
-use gameroy::gameboy::{ppu::Ppu, GameBoy};
+use gameroy::gameboy::{
+    ppu::{map_tile_index, Ppu, TileMap},
+    GameBoy,
+};
 use giui::{
     graphics::{Graphic, Texture},
     layouts::{FitGraphic, GridLayout, HBoxLayout, VBoxLayout},
@@ -121,26 +124,17 @@ impl PpuViewer {
                 tiles.into_boxed_slice(),
             ))
             .unwrap();
-        let mut background = vec![255; 256 * 256 * 4];
-        gameroy::gameboy::ppu::draw_background(&ppu, &mut |x, y, c| {
-            let i = (x + y * 256) as usize * 4;
-            background[i..i + 3].copy_from_slice(&COLOR[c as usize]);
-        });
+        // the maps are shown with the part on the screen outlined
         proxy
             .send_event(UserEvent::UpdateTexture(
                 textures.background,
-                background.into_boxed_slice(),
+                to_rgba(&ppu.render_background()),
             ))
             .unwrap();
-        let mut window = vec![255; 256 * 256 * 4];
-        gameroy::gameboy::ppu::draw_window(&ppu, &mut |x, y, c| {
-            let i = (x + y * 256) as usize * 4;
-            window[i..i + 3].copy_from_slice(&COLOR[c as usize]);
-        });
         proxy
             .send_event(UserEvent::UpdateTexture(
                 textures.window,
-                window.into_boxed_slice(),
+                to_rgba(&ppu.render_window()),
             ))
             .unwrap();
 
@@ -273,17 +267,8 @@ pub fn build(
             let gb = ctx.get::<Arc<Mutex<GameBoy>>>().lock();
             let ppu = gb.ppu.borrow();
 
-            let i = y as u16 * 32 + x as u16;
-            let address = if ppu.lcdc & 0x08 != 0 { 0x9C00 } else { 0x9800 };
-            let mut tile = ppu.vram[address - 0x8000 + i as usize] as usize;
-
-            // if is using 8800 method
-            if ppu.lcdc & 0x10 == 0 {
-                tile += 0x100;
-                if tile >= 0x180 {
-                    tile -= 0x100;
-                }
-            }
+            let map = ppu.tile_map(TileMap::background(ppu.lcdc));
+            let tile = map_tile_index(ppu.lcdc, map[y as usize][x as usize]);
             format!(
                 "background:\ntile number: {:02x}\nx: {:02x} y: {:02x}",
                 tile, x, y
@@ -295,17 +280,8 @@ pub fn build(
         let gb = ctx.get::<Arc<Mutex<GameBoy>>>().lock();
         let ppu = gb.ppu.borrow();
 
-        let i = y as u16 * 32 + x as u16;
-        let address = if ppu.lcdc & 0x40 != 0 { 0x9C00 } else { 0x9800 };
-        let mut tile = ppu.vram[address - 0x8000 + i as usize] as usize;
-
-        // if is using 8800 method
-        if ppu.lcdc & 0x10 == 0 {
-            tile += 0x100;
-            if tile >= 0x180 {
-                tile -= 0x100;
-            }
-        }
+        let map = ppu.tile_map(TileMap::window(ppu.lcdc));
+        let tile = map_tile_index(ppu.lcdc, map[y as usize][x as usize]);
         format!(
             "window:\ntile number: {:02x}\nx: {:02x} y: {:02x}",
             tile, x, y
@@ -473,3 +449,11 @@ fn build_tilemap_viewer(
         })
         .build(ctx);
 }
+
+/// Convert RGB pixels to the RGBA format of the textures.
+fn to_rgba(pixels: &[[u8; 3]]) -> Box<[u8]> {
+    pixels
+        .iter()
+        .flat_map(|&[r, g, b]| [r, g, b, 255])
+        .collect()
+}
@@ -0,0 +1,82 @@
+use std::{rc::Rc, sync::Arc};
+
+use gameroy::gameboy::GameBoy;
+use giui::Context;
+use parking_lot::Mutex;
+use winit::window::Window;
+
+use crate::{executor, EmulatorEvent};
+
+/// Ask for a file, and export the current save state to it, to be shared.
+pub fn export_state(ctx: &mut Context) {
+    let handle = ctx.get::<Rc<Window>>().clone();
+    let sender = ctx.get::<flume::Sender<EmulatorEvent>>().clone();
+    let title = ctx
+        .get::<Arc<Mutex<GameBoy>>>()
+        .lock()
+        .cartridge
+        .header
+        .title_as_string();
+    let file_name: String = title
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .chain(".save_state".chars())
+        .collect();
+    let task = async move {
+        let file = rfd::AsyncFileDialog::new()
+            .set_title("Export Save State")
+            .add_filter("GameRoy save states", &["save_state"])
+            .set_file_name(&file_name)
+            .set_parent(&*handle)
+            .save_file()
+            .await;
+
+        if let Some(file) = file {
+            sender
+                .send(EmulatorEvent::ExportState(file.path().to_path_buf()))
+                .unwrap();
+        }
+    };
+    executor::Executor::spawn_task(task, ctx);
+}
+
+/// Ask for a save state file, and load it. If it was saved with a different rom, or can't be
+/// loaded, a error is shown instead.
+pub fn import_state(ctx: &mut Context) {
+    let handle = ctx.get::<Rc<Window>>().clone();
+    let sender = ctx.get::<flume::Sender<EmulatorEvent>>().clone();
+    let gb = ctx.get::<Arc<Mutex<GameBoy>>>().clone();
+    let task = async move {
+        let file = rfd::AsyncFileDialog::new()
+            .set_title("Import Save State")
+            .add_filter("GameRoy save states", &["save_state"])
+            .set_parent(&*handle)
+            .pick_file()
+            .await;
+        let file = match file {
+            Some(x) => x,
+            None => return,
+        };
+
+        let state = file.read().await;
+        let result = gb.lock().read_state_header(&mut state.as_slice());
+        match result {
+            Ok(_) => sender.send(EmulatorEvent::ImportState(state)).unwrap(),
+            Err(err) => {
+                log::error!("error importing '{}': {}", file.file_name(), err);
+                rfd::AsyncMessageDialog::new()
+                    .set_level(rfd::MessageLevel::Error)
+                    .set_title("Import Save State")
+                    .set_description(&format!(
+                        "Could not import '{}': {}.",
+                        file.file_name(),
+                        err
+                    ))
+                    .set_parent(&*handle)
+                    .show()
+                    .await;
+            }
+        }
+    };
+    executor::Executor::spawn_task(task, ctx);
+}
@@ -0,0 +1,156 @@
+use std::fmt::Write;
+
+use giui::{
+    layouts::{FitGraphic, MarginLayout},
+    text::Text,
+    Behaviour, BuilderContext, Context, Id, RectFill,
+};
+use instant::{Duration, Instant};
+
+use crate::{
+    emulator::{SpeedOverride, MAX_SPEED, MIN_SPEED},
+    event_table::{self, EventTable, FrameUpdated, Handle},
+    frame_stats::FrameStats,
+    style::Style,
+};
+
+/// The minimum time between updates of the text, so it is readable.
+const UPDATE_INTERVAL: Duration = Duration::from_millis(250);
+
+/// A panel in the top right corner of the game screen, that shows the real and emulated frame
+/// rates, the target speed and how much of it is reached, the frame time and the fill of the audio
+/// buffer, from the `FrameStats`. It is toggled with the `stats_overlay` key.
+struct StatsOverlay {
+    panel: Id,
+    text: Id,
+    /// The text is written here, to not allocate a new string on every update.
+    buffer: String,
+    last_update: Option<Instant>,
+    _frame_updated_event: Handle<FrameUpdated>,
+    _stats_overlay_event: Handle<event_table::StatsOverlay>,
+}
+impl StatsOverlay {
+    fn update(&mut self, ctx: &mut Context) {
+        let now = Instant::now();
+        if self
+            .last_update
+            .map_or(false, |x| now.duration_since(x) < UPDATE_INTERVAL)
+        {
+            return;
+        }
+        self.last_update = Some(now);
+
+        // the target speed, or None if unlimited
+        let target = {
+            let app_state = ctx.get::<crate::AppState>();
+            match app_state.speed_override {
+                Some(SpeedOverride::Unlimited) => None,
+                Some(SpeedOverride::Speed(speed)) => Some(speed.clamp(MIN_SPEED, MAX_SPEED)),
+                None => Some(app_state.speed),
+            }
+        };
+
+        self.buffer.clear();
+        {
+            let stats = ctx.get::<FrameStats>();
+            let _ = writeln!(
+                self.buffer,
+                "FPS {:5.1}  emu {:5.1} (x{:.2})",
+                stats.real_fps(),
+                stats.emulated_fps(),
+                stats.speed()
+            );
+            match target {
+                Some(target) => {
+                    let _ = writeln!(
+                        self.buffer,
+                        "target x{:.2} ({:3.0}%)",
+                        target,
+                        stats.speed() / target as f64 * 100.0
+                    );
+                }
+                None => self.buffer.push_str("target unlimited\n"),
+            }
+            let _ = write!(self.buffer, "frame {:5.1} ms", stats.frame_time_ms());
+            match stats.audio_fill() {
+                Some((len, target_len)) => {
+                    let _ = write!(
+                        self.buffer,
+                        "\naudio {}/{} ({:3.0}%)",
+                        len,
+                        target_len,
+                        len as f64 / target_len as f64 * 100.0
+                    );
+                }
+                None => self.buffer.push_str("\naudio off"),
+            }
+        }
+        ctx.get_graphic_mut(self.text).set_text(&self.buffer);
+    }
+}
+impl Behaviour for StatsOverlay {
+    fn on_start(&mut self, _this: Id, ctx: &mut Context) {
+        if ctx.get::<crate::AppState>().stats_overlay {
+            self.update(ctx);
+        } else {
+            ctx.deactive(self.panel);
+        }
+    }
+
+    fn on_event(&mut self, event: Box<dyn std::any::Any>, _this: Id, ctx: &mut Context) {
+        if let Some(&event_table::StatsOverlay(visible)) = event.downcast_ref() {
+            if visible {
+                self.last_update = None;
+                self.update(ctx);
+                ctx.active(self.panel);
+            } else {
+                ctx.deactive(self.panel);
+            }
+        } else if event.is::<FrameUpdated>() {
+            if ctx.get::<crate::AppState>().stats_overlay {
+                self.update(ctx);
+            }
+        }
+    }
+}
+
+/// Build the stats overlay as a child of `parent`, that is expected to have the size of the game
+/// screen.
+pub fn build(
+    ctx: &mut dyn BuilderContext,
+    parent: Id,
+    event_table: &mut EventTable,
+    style: &Style,
+) {
+    let overlay = ctx.reserve();
+    let panel = ctx.reserve();
+    let text = ctx.reserve();
+
+    ctx.create_control_reserved(overlay)
+        .parent(parent)
+        .behaviour(StatsOverlay {
+            panel,
+            text,
+            buffer: String::new(),
+            last_update: None,
+            _frame_updated_event: event_table.register(overlay),
+            _stats_overlay_event: event_table.register(overlay),
+        })
+        .build(ctx);
+    ctx.create_control_reserved(panel)
+        .parent(overlay)
+        .graphic(style.terminal_background.clone())
+        .layout(MarginLayout::new([2.0; 4]))
+        .fill_x(RectFill::ShrinkEnd)
+        .fill_y(RectFill::ShrinkStart)
+        .build(ctx);
+    ctx.create_control_reserved(text)
+        .parent(panel)
+        .graphic(Text::new(
+            String::new(),
+            (-1, -1),
+            style.terminal_text_style.clone(),
+        ))
+        .layout(FitGraphic)
+        .build(ctx);
+}
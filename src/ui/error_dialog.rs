@@ -0,0 +1,111 @@
+use giui::{
+    layouts::{FitGraphic, HBoxLayout, MarginLayout, VBoxLayout},
+    text::Text,
+    widgets::{Blocker, Button, OnKeyboardEvent},
+    Context, Id, RectFill,
+};
+use winit::event::VirtualKeyCode;
+
+use crate::{style::Style, EmulatorEvent};
+
+/// The control that receives the keyboard input of the running game.
+pub struct GameInput(pub Id);
+
+/// Open a modal dialog showing a error. It is closed by its "OK" button, by clicking outside of it,
+/// or by pressing Escape or Enter.
+///
+/// If `game` is the control that receives the input of the running game, the game is paused and
+/// its joypad released while the dialog is open, and the keyboard focus is given back to it when
+/// closed.
+pub fn open_error_dialog(ctx: &mut Context, message: String, game: Option<GameInput>) {
+    let style = ctx.get::<Style>().clone();
+    let [dialog, blocker] = [(); 2].map(|_| ctx.reserve());
+    let game = game.map(|x| x.0);
+
+    if game.is_some() {
+        let app_state = ctx.get_mut::<crate::AppState>();
+        app_state.joypad = 0xFF;
+        app_state.turbo = 0;
+        send_emu(ctx, EmulatorEvent::Pause);
+    }
+    let close = move |ctx: &mut Context| {
+        ctx.remove(dialog);
+        ctx.remove(blocker);
+        if let Some(game) = game {
+            ctx.set_focus(game);
+            send_emu(ctx, EmulatorEvent::Resume);
+        }
+    };
+
+    ctx.create_control_reserved(blocker)
+        .parent(Id::ROOT_ID)
+        .behaviour(Blocker::new(move |_, ctx| close(ctx)))
+        .graphic(style.blocker.clone())
+        .build(ctx);
+
+    ctx.create_control_reserved(dialog)
+        .parent(Id::ROOT_ID)
+        .behaviour(OnKeyboardEvent::new(move |event, _, ctx| {
+            use giui::KeyboardEvent::*;
+            match event {
+                Pressed(VirtualKeyCode::Escape)
+                | Pressed(VirtualKeyCode::Return)
+                | Pressed(VirtualKeyCode::NumpadEnter) => close(ctx),
+                _ => {}
+            }
+            // the other keys don't reach the game
+            true
+        }))
+        .layout(VBoxLayout::new(4.0, [10.0; 4], -1))
+        .graphic(style.split_background.clone())
+        .fill_x(RectFill::ShrinkCenter)
+        .fill_y(RectFill::ShrinkCenter)
+        .focus(true)
+        .build(ctx);
+
+    ctx.create_control()
+        .parent(dialog)
+        .layout(MarginLayout::new([0.0, 0.0, 0.0, 6.0]))
+        .child(ctx, |cb, _| {
+            cb.graphic(Text::new(
+                "Error".to_string(),
+                (-1, 0),
+                style.text_menu.clone(),
+            ))
+            .layout(FitGraphic)
+        })
+        .build(ctx);
+
+    ctx.create_control()
+        .parent(dialog)
+        .graphic(Text::new(message, (-1, 0), style.text_style.clone()))
+        .layout(FitGraphic)
+        .build(ctx);
+
+    ctx.create_control()
+        .parent(dialog)
+        .layout(HBoxLayout::new(4.0, [0.0; 4], 1))
+        .child(ctx, |cb, ctx| {
+            cb.layout(MarginLayout::new([10.0, 4.0, 10.0, 4.0]))
+                .behaviour(Button::new(
+                    style.delete_button.clone(),
+                    true,
+                    move |_, ctx| close(ctx),
+                ))
+                .child(ctx, |cb, _| {
+                    cb.graphic(Text::new(
+                        "OK".to_string(),
+                        (0, 0),
+                        style.text_style.clone(),
+                    ))
+                    .layout(FitGraphic)
+                })
+        })
+        .build(ctx);
+}
+
+fn send_emu(ctx: &mut Context, event: EmulatorEvent) {
+    ctx.get::<flume::Sender<EmulatorEvent>>()
+        .send(event)
+        .unwrap()
+}
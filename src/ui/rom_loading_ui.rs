@@ -1,32 +1,168 @@
-use std::{cell::RefCell, ops::Add, rc::Rc};
+use std::{
+    cell::RefCell,
+    rc::Rc,
+    sync::{
+        atomic::{AtomicU64, Ordering as AtomicOrdering},
+        Arc,
+    },
+};
 
 use giui::{
+    event::SetValue,
     graphics::Graphic,
     layouts::{FitGraphic, HBoxLayout, MarginLayout, VBoxLayout},
     text::Text,
-    widgets::{Button, ListBuilder},
-    Id,
+    widgets::{Button, ListBuilder, TextField, TextFieldCallback},
+    Context, Id,
 };
-use winit::{event_loop::EventLoopProxy, window::Window};
+use winit::{event::VirtualKeyCode, event_loop::EventLoopProxy, window::Window};
 
 use crate::{
     config::config,
     event_table::{self, EventTable},
     executor,
-    rom_loading::{load_gameboy, RomFile},
+    game_database::{game_database, RomHash},
+    rom_loading::{load_gameboy, load_rom_file, RomFile, StateInfo},
     style::Style,
+    translation::strings,
     widget::table_item::{TableGroup, TableItem},
     UserEvent,
 };
 
+mod age;
+#[cfg(not(target_arch = "wasm32"))]
+mod folders_dialog;
+mod keys_dialog;
+mod natural_sort;
+#[cfg(not(target_arch = "wasm32"))]
+mod rom_menu;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod thumbnails;
+
+#[cfg(not(target_arch = "wasm32"))]
+use thumbnails::{Thumbnail, ThumbnailAtlas, THUMBNAIL_HEIGHT, THUMBNAIL_WIDTH};
+
+/// The number of new rom headers read before the header cache is saved.
+#[cfg(not(target_arch = "wasm32"))]
+const CACHE_BATCH: usize = 64;
+
+/// The minimum interval between the partial rom lists sent while the headers are read.
+#[cfg(not(target_arch = "wasm32"))]
+const UPDATE_INTERVAL: instant::Duration = instant::Duration::from_millis(100);
+
+/// The minimum interval between two loadings started by `RomEntries::refresh`, so a double click
+/// on the refresh button only reloads the list once.
+#[cfg(not(target_arch = "wasm32"))]
+const REFRESH_DEBOUNCE: instant::Duration = instant::Duration::from_millis(500);
+
+/// The interval between the updates of the ages shown in the rom list, like "5min ago".
+#[cfg(not(target_arch = "wasm32"))]
+const AGE_REFRESH_INTERVAL: instant::Duration = instant::Duration::from_secs(60);
+
 const COLLUMNS: &[(&'static str, f32)] = &[
     ("File", 490.0),
     ("Header Name", 129.0),
     ("Size", 60.0),
     ("Last played", 100.0),
+    ("Mapper", 100.0),
+    ("Color", 90.0),
+    ("Licensee", 120.0),
+    ("State", 100.0),
+    ("Play time", 80.0),
 ];
 
+/// The collumns that show a age, updated every `AGE_REFRESH_INTERVAL`.
+const AGE_COLLUMNS: &[usize] = &[3, 7];
+
+/// The collumns shown when the `rom_list_columns` config is not set.
+const DEFAULT_COLLUMNS: &[usize] = &[0, 1, 2, 3];
+
+/// The indices of the collumns shown in the rom list, in order, as set in the `rom_list_columns`
+/// config. The "File" collumn is always included.
+fn visible_collumns() -> Vec<usize> {
+    let titles = match &config().rom_list_columns {
+        Some(x) => x.clone(),
+        None => return DEFAULT_COLLUMNS.to_vec(),
+    };
+    let mut collumns = vec![0];
+    for title in titles {
+        match COLLUMNS
+            .iter()
+            .position(|&(x, _)| x.eq_ignore_ascii_case(title.trim()))
+        {
+            Some(index) => collumns.push(index),
+            None => log::error!("Unkown collumn name '{}'", title),
+        }
+    }
+    collumns.sort_unstable();
+    collumns.dedup();
+    collumns
+}
+
+/// The minimum and maximum width of a collumn loaded from the `column_widths` config.
+const MIN_COLLUMN_WIDTH: f32 = 20.0;
+const MAX_COLLUMN_WIDTH: f32 = 2000.0;
+
+/// The width of each collumn in `COLLUMNS`, from the widths saved in the `column_widths` config.
+/// The collumns added after the widths were saved have their default widths. If there are more
+/// widths than collumns, the default widths are used.
+fn collumn_widths(saved: &[f32]) -> Vec<f32> {
+    if saved.len() > COLLUMNS.len() {
+        log::warn!(
+            "ignoring column_widths: expected {} widths, found {}",
+            COLLUMNS.len(),
+            saved.len()
+        );
+        return COLLUMNS.iter().map(|&(_, width)| width).collect();
+    }
+    COLLUMNS
+        .iter()
+        .enumerate()
+        .map(|(i, &(_, default))| match saved.get(i) {
+            Some(&width) if width.is_finite() => width.clamp(MIN_COLLUMN_WIDTH, MAX_COLLUMN_WIDTH),
+            _ => default,
+        })
+        .collect()
+}
+
+/// Set the collumns of the table of the rom list, with the widths from the `column_widths` config.
+/// The "File" collumn expands to fill the width that is not used by the others.
+fn set_table_collumns(table: &mut TableGroup, thumbnails: bool, collumns: &[usize]) {
+    let widths = collumn_widths(&config().column_widths);
+    table.columns.clear();
+    if thumbnails {
+        #[cfg(not(target_arch = "wasm32"))]
+        table.add_column(THUMBNAIL_WIDTH as f32, false);
+    }
+    for &index in collumns {
+        table.add_column(widths[index], index == 0)
+    }
+}
+
+/// The sort key that is not a collumn: the roms launched most recently first.
+const RECENTLY_PLAYED: usize = COLLUMNS.len();
+const RECENTLY_PLAYED_TITLE: &str = "Recently played";
+
+/// The title of the collumn, or of the pseudo collumn `RECENTLY_PLAYED`, with the given index.
+fn sort_title(index: usize) -> Option<&'static str> {
+    match COLLUMNS.get(index) {
+        Some(&(title, _)) => Some(title),
+        None if index == RECENTLY_PLAYED => Some(RECENTLY_PLAYED_TITLE),
+        None => None,
+    }
+}
+
 struct SortBy(usize);
+/// Sent to the rom list when the user finishes resizing a collumn.
+struct CollumnResized;
+/// Sent to the rom list when the text of the filter field changes.
+struct FilterChanged;
+/// Sent to the rom list to show or hide the collumn with the given index.
+#[cfg(not(target_arch = "wasm32"))]
+struct ToggleCollumn(usize);
+/// Sent to the rom list when a rom is marked or unmarked as favorite.
+#[cfg(not(target_arch = "wasm32"))]
+struct FavoriteToggled;
 #[derive(Clone, Copy, Debug)]
 enum SortDirection {
     Ascending,
@@ -37,6 +173,28 @@ pub struct RomEntries {
     roms: Vec<RomEntry>,
     sort_collumn: usize,
     sort_direction: SortDirection,
+    /// The lowercase text typed in the filter field.
+    filter: String,
+    /// The indices of the roms that match the filter, in the sorted order. The favorites come
+    /// first.
+    visible: Vec<usize>,
+    /// The number of favorite roms in `visible`.
+    favorite_count: usize,
+    /// Incremented each time the roms start loading, so a list sent by a previous loading is
+    /// ignored, and the previous loading stops.
+    generation: Arc<AtomicU64>,
+    /// The thumbnails of the roms, if the `thumbnails` config is enabled.
+    #[cfg(not(target_arch = "wasm32"))]
+    thumbnails: ThumbnailAtlas,
+    /// When the last loading was started by `refresh`.
+    #[cfg(not(target_arch = "wasm32"))]
+    last_refresh: Option<instant::Instant>,
+    /// Reloads the list when roms are added to or removed from the rom folders.
+    #[cfg(all(
+        feature = "watch",
+        not(any(target_arch = "wasm32", target_os = "android"))
+    ))]
+    watcher: Option<crate::rom_loading::RomWatcher>,
     pub observers: Vec<giui::Id>,
 }
 impl RomEntries {
@@ -57,9 +215,9 @@ impl RomEntries {
                     SortDirection::Ascending
                 };
 
-                let col = COLLUMNS
-                    .iter()
-                    .position(|&(title, _)| sort_config.contains(&title.to_lowercase()))
+                let col = (0..=RECENTLY_PLAYED)
+                    .rev()
+                    .find(|&i| sort_config.contains(&sort_title(i).unwrap().to_lowercase()))
                     .unwrap_or_else(|| {
                         log::error!("Unkown collumn name '{}'", sort_config);
                         0
@@ -70,12 +228,30 @@ impl RomEntries {
             None => (0, SortDirection::Ascending),
         };
 
-        let this = Self {
+        let mut this = Self {
             roms: Vec::new(),
             observers: Vec::new(),
             sort_collumn,
             sort_direction,
+            filter: String::new(),
+            visible: Vec::new(),
+            favorite_count: 0,
+            generation: Arc::default(),
+            #[cfg(not(target_arch = "wasm32"))]
+            thumbnails: ThumbnailAtlas::default(),
+            #[cfg(not(target_arch = "wasm32"))]
+            last_refresh: None,
+            #[cfg(all(
+                feature = "watch",
+                not(any(target_arch = "wasm32", target_os = "android"))
+            ))]
+            watcher: None,
         };
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            let proxy = proxy.clone();
+            std::thread::spawn(move || send_age_refreshes(proxy));
+        }
         this.start_loading(proxy);
         this
     }
@@ -92,7 +268,7 @@ impl RomEntries {
         self.sort_collumn = collumn_index;
 
         {
-            if let Some((title, _)) = COLLUMNS.get(collumn_index) {
+            if let Some(title) = sort_title(collumn_index) {
                 let dir = match self.sort_direction {
                     SortDirection::Ascending => "+".to_string(),
                     SortDirection::Descending => "-".to_string(),
@@ -112,111 +288,263 @@ impl RomEntries {
 
         let sort_direction = self.sort_direction;
         let sort_collumn = self.sort_collumn;
+        let cmp_names: fn(&str, &str) -> Ordering = if config().natural_sort {
+            natural_sort::natural_cmp
+        } else {
+            |a, b| a.cmp(b)
+        };
 
         self.roms.sort_by(|a, b| {
             let ord = match sort_collumn {
-                0 => a.file.file_name().cmp(&b.file.file_name()),
+                0 => cmp_names(&a.file.file_name(), &b.file.file_name()),
                 1 => a.name.cmp(&b.name),
                 2 => a.size.cmp(&b.size),
-                3 => a.save_time.cmp(&b.save_time).reverse(),
+                3 => a.last_played().cmp(&b.last_played()).reverse(),
+                4 => a.mapper.cmp(&b.mapper),
+                5 => a.color.cmp(&b.color),
+                6 => a.licensee.cmp(&b.licensee),
+                7 => a.state_time().cmp(&b.state_time()).reverse(),
+                8 => a.play_time.cmp(&b.play_time).reverse(),
+                RECENTLY_PLAYED => a.last_launch.cmp(&b.last_launch).reverse(),
                 _ => {
                     log::error!("Unknown collumn index: {}", sort_collumn);
                     Ordering::Equal
                 }
             };
 
-            #[rustfmt::skip]
-            fn fn_<F: for<'a> Fn(&'a RomEntry) -> (std::borrow::Cow<'a, str>, Option<&String>, Option<&u64>)>(x: F) -> F { x }
-
-            let default_key =
-                fn_(|a: &RomEntry| (a.file.file_name(), a.name.as_ref(), a.size.as_ref()));
-
-            let ord = match ord {
-                Ordering::Equal => default_key(a).cmp(&default_key(b)),
-                _ => ord,
-            };
+            // ties are broken by the file name, the name and the size
+            let ord = ord
+                .then_with(|| cmp_names(&a.file.file_name(), &b.file.file_name()))
+                .then_with(|| a.name.cmp(&b.name))
+                .then_with(|| a.size.cmp(&b.size));
 
-            if let SortDirection::Ascending =sort_direction {
+            let ord = if let SortDirection::Ascending = sort_direction {
                 ord
             } else {
                 ord.reverse()
-            }
-        })
+            };
+
+            // the roms that were never launched are listed last, in any direction
+            let never_launched = if sort_collumn == RECENTLY_PLAYED {
+                a.last_launch.is_none().cmp(&b.last_launch.is_none())
+            } else {
+                Ordering::Equal
+            };
+
+            // the favorites are listed first, in any direction
+            b.favorite.cmp(&a.favorite).then(never_launched).then(ord)
+        });
+
+        self.update_filter();
+    }
+
+    pub fn set_filter(&mut self, filter: &str) {
+        self.filter = filter.to_lowercase();
+        self.update_filter();
+    }
+
+    pub fn clear_filter(&mut self) {
+        self.set_filter("");
+    }
+
+    fn update_filter(&mut self) {
+        let filter = &self.filter;
+        self.visible = (0..self.roms.len())
+            .filter(|&i| self.roms[i].matches(filter))
+            .collect();
+        self.favorite_count = self
+            .visible
+            .iter()
+            .filter(|&&i| self.roms[i].favorite)
+            .count();
     }
 
     #[cfg(target_arch = "wasm32")]
-    pub fn start_loading(&self, _: EventLoopProxy<UserEvent>) {}
+    pub fn start_loading(&mut self, _: EventLoopProxy<UserEvent>) {}
 
+    /// Start loading the roms of the folders in the config, in a new thread. The list is sent
+    /// first with the cached headers, and then again every `UPDATE_INTERVAL` while the other
+    /// headers are read, until all of them are loaded. Any loading that was already running is
+    /// stopped.
     #[cfg(not(target_arch = "wasm32"))]
-    pub fn start_loading(&self, proxy: EventLoopProxy<UserEvent>) {
-        let roms_path = &crate::config::config().rom_folder;
-
-        let roms_path = match roms_path {
-            Some(x) => x.clone(),
-            None => {
-                proxy
-                    .send_event(UserEvent::UpdatedRomList { roms: Vec::new() })
-                    .unwrap();
-                return;
-            }
-        };
-        std::thread::spawn(move || {
-            let start = instant::Instant::now();
-
-            let roms = crate::rom_loading::load_roms(&roms_path)
-                .map_err(|e: String| log::error!("error reading roms: {}", e))
-                .ok()
-                .unwrap_or_default();
-            let mut entries: Vec<RomEntry> = roms
-                .into_iter()
-                .map(|x| {
-                    let save_time = x.get_save_time();
-                    log::debug!("{}", x.file_name());
-                    RomEntry {
-                        file: x,
-                        name: None,
-                        size: None,
-                        save_time: save_time.ok(),
-                    }
-                })
-                .collect();
+    pub fn start_loading(&mut self, proxy: EventLoopProxy<UserEvent>) {
+        let generation = self.generation.fetch_add(1, AtomicOrdering::Relaxed) + 1;
+        let roms_paths = crate::config::config().rom_folders.clone();
+        // the thumbnails are read again, as they may have changed
+        self.thumbnails.clear();
+        #[cfg(all(feature = "watch", not(target_os = "android")))]
+        self.update_watcher(&roms_paths, &proxy);
 
+        if roms_paths.is_empty() {
             proxy
                 .send_event(UserEvent::UpdatedRomList {
-                    roms: entries.clone(),
+                    generation,
+                    roms: Vec::new(),
                 })
                 .unwrap();
+            return;
+        }
+        let current = self.generation.clone();
+        std::thread::spawn(move || load_entries(proxy, roms_paths, generation, current));
+    }
 
-            for entry in entries.iter_mut() {
-                let header = {
-                    let mut task = entry.file.get_header();
-                    let task = unsafe { std::pin::Pin::new_unchecked(&mut task) };
-                    executor::block_on(task)
-                };
+    /// Reload the roms and clear the filter, by sending a `UserEvent::UpdateRomList`, unless the
+    /// last refresh was less than `REFRESH_DEBOUNCE` ago.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn refresh(&mut self, proxy: EventLoopProxy<UserEvent>) {
+        let now = instant::Instant::now();
+        if let Some(last) = self.last_refresh {
+            if now.duration_since(last) < REFRESH_DEBOUNCE {
+                log::debug!("ignoring refresh, the rom list was just refreshed");
+                return;
+            }
+        }
+        self.last_refresh = Some(now);
+        let _ = proxy.send_event(UserEvent::UpdateRomList);
+    }
 
-                let header = match header {
-                    Ok(x) => x,
-                    Err(err) => {
-                        entry.name = Some("Error reading header...".to_string());
-                        entry.size = None;
-                        log::error!("error reading '{}' header: {}", entry.file.file_name(), err);
-                        continue;
-                    }
-                };
+    /// Watch the given rom folders, unless they are already being watched.
+    #[cfg(all(
+        feature = "watch",
+        not(any(target_arch = "wasm32", target_os = "android"))
+    ))]
+    fn update_watcher(&mut self, folders: &[String], proxy: &EventLoopProxy<UserEvent>) {
+        if let Some(watcher) = &self.watcher {
+            if watcher.folders() == folders {
+                return;
+            }
+        }
+        // the previous watcher is dropped first, so a change is not reported by both
+        self.watcher = None;
+        if folders.is_empty() {
+            return;
+        }
+        match crate::rom_loading::RomWatcher::new(folders.to_vec(), proxy.clone()) {
+            Ok(watcher) => self.watcher = Some(watcher),
+            Err(err) => log::error!("{}", err),
+        }
+    }
+
+    /// If a list sent by a loading of the given generation is the latest one.
+    pub fn is_current(&self, generation: u64) -> bool {
+        self.generation.load(AtomicOrdering::Relaxed) == generation
+    }
+
+    /// The number of rows of the list, below its header: the roms that match the filter, and,
+    /// if any of them is a favorite, the titles of the favorites and of the other roms.
+    fn row_count(&self) -> usize {
+        let sections = match self.favorite_count {
+            0 => 0,
+            x if x == self.visible.len() => 1,
+            _ => 2,
+        };
+        self.visible.len() + sections
+    }
 
-                entry.name = Some(header.title_as_string());
-                entry.size = Some(header.rom_size_in_bytes().unwrap_or(0) as u64);
+    /// The `index`-th row of the list, below its header.
+    fn row(&self, index: usize) -> Row {
+        let favorites = self.favorite_count;
+        let rom = |i: usize| Row::Rom(&self.roms[self.visible[i]]);
+        if favorites == 0 {
+            rom(index)
+        } else if index == 0 {
+            Row::Section("Favorites")
+        } else if index <= favorites {
+            rom(index - 1)
+        } else if index == favorites + 1 {
+            Row::Section("Others")
+        } else {
+            rom(index - 2)
+        }
+    }
+
+    /// The key of the rom in the `index`-th row, if the row is a rom.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn row_key(&self, index: usize) -> Option<String> {
+        match self.row(index) {
+            Row::Rom(entry) => entry.file.recent_key(),
+            Row::Section(_) => None,
+        }
+    }
+
+    /// The row of the rom with the given key, if it is listed.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn find_row(&self, key: &str) -> Option<usize> {
+        (0..self.row_count()).find(|&i| match self.row(i) {
+            Row::Rom(entry) => entry.file.recent_key().as_deref() == Some(key),
+            Row::Section(_) => false,
+        })
+    }
+
+    /// Mark or unmark the rom with the given key as favorite, and save it in the `RomLibrary`.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn set_favorite(&mut self, key: &str, favorite: bool) {
+        {
+            let mut library = crate::rom_loading::rom_library();
+            library.set_favorite(key, favorite);
+            library.save();
+        }
+        for entry in self.roms.iter_mut() {
+            if entry.file.recent_key().as_deref() == Some(key) {
+                entry.favorite = favorite;
             }
+        }
+        self.update_sort();
+    }
 
-            log::info!("loading roms took: {:?}", start.elapsed());
-            proxy
-                .send_event(UserEvent::UpdatedRomList { roms: entries })
-                .unwrap();
-        });
+    /// Read again the favorites and launch times from the `RomLibrary`, and the play times from
+    /// the `PlayLog`, after a rom was played.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn refresh_library(&mut self) {
+        let play_log = crate::rom_loading::PlayLog::load();
+        for entry in self.roms.iter_mut() {
+            entry.set_play_log(&play_log);
+        }
+        let library = crate::rom_loading::rom_library();
+        self.set_library(&library);
+        // the rom that was played may have saved a state
+        let last_launched = self
+            .roms
+            .iter_mut()
+            .filter(|x| x.last_launch.is_some())
+            .max_by_key(|x| x.last_launch);
+        if let Some(entry) = last_launched {
+            entry.refresh_state();
+            self.update_sort();
+        }
+    }
+
+    /// Fill the information of the roms that comes from the `library`, and sort them again.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn set_library(&mut self, library: &crate::rom_loading::RomLibrary) {
+        for entry in self.roms.iter_mut() {
+            entry.set_library(library);
+        }
+        self.update_sort();
+    }
+
+    fn count_text(&self) -> String {
+        if self.filter.is_empty() {
+            format!("{} roms", self.roms.len())
+        } else {
+            format!("{} of {} roms", self.visible.len(), self.roms.len())
+        }
     }
 
-    fn roms(&self) -> &[RomEntry] {
-        &self.roms
+    /// Read again the save time and the save state of the roms with the given file name, after
+    /// their saves were deleted.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn refresh_saves(&mut self, file_name: &str) {
+        for entry in self.roms.iter_mut() {
+            if entry.file.file_name() != file_name {
+                continue;
+            }
+            if entry.save_time.is_some() {
+                entry.save_time = entry.file.get_save_time().ok();
+            }
+            entry.refresh_state();
+        }
+        self.update_sort();
     }
 
     pub fn set_roms(&mut self, roms: Vec<RomEntry>) {
@@ -227,24 +555,393 @@ impl RomEntries {
     fn register(&mut self, id: Id) {
         self.observers.push(id);
     }
+
+    /// The thumbnails of the roms, to be uploaded to the `thumbnails` texture.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn thumbnails(&self) -> &ThumbnailAtlas {
+        &self.thumbnails
+    }
+
+    /// Add the thumbnails loaded by `start_loading`, by the key of their roms.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn add_thumbnails(&mut self, thumbnails: Vec<(String, Thumbnail)>) {
+        for (key, thumbnail) in thumbnails {
+            if !self.thumbnails.insert(key, &thumbnail) {
+                log::warn!("the thumbnail atlas is full, some roms will have no thumbnail");
+                return;
+            }
+        }
+    }
+}
+
+/// Load the roms in `roms_paths`, sending the list to the UI as it is filled. This runs in its own
+/// thread, while the headers are read by a pool of worker threads. It stops early if a newer
+/// loading is started, that is, if `current` is no longer `generation`.
+#[cfg(not(target_arch = "wasm32"))]
+fn load_entries(
+    proxy: EventLoopProxy<UserEvent>,
+    roms_paths: Vec<String>,
+    generation: u64,
+    current: Arc<AtomicU64>,
+) {
+    use crate::rom_loading::HeaderCache;
+
+    let start = instant::Instant::now();
+    let is_current = || current.load(AtomicOrdering::Relaxed) == generation;
+
+    let mut cache = HeaderCache::load();
+    let roms = crate::rom_loading::load_roms(&roms_paths);
+    let play_log = crate::rom_loading::PlayLog::load();
+    let library = crate::rom_loading::rom_library();
+    let identities: Vec<_> = roms.iter().map(|x| x.identity()).collect();
+    let mut cached = vec![false; roms.len()];
+    let mut entries: Vec<RomEntry> = roms
+        .into_iter()
+        .zip(identities.iter())
+        .zip(cached.iter_mut())
+        .map(|((x, identity), cached)| {
+            log::debug!("{}", x.file_name());
+            let mut entry = RomEntry {
+                file: x,
+                name: None,
+                size: None,
+                save_time: None,
+                header_warning: None,
+                hash: None,
+                mapper: None,
+                color: None,
+                licensee: None,
+                favorite: false,
+                last_launch: None,
+                state: None,
+                play_time: None,
+                last_played: None,
+            };
+            entry.set_library(&library);
+            entry.set_play_log(&play_log);
+            entry.refresh_state();
+            if let Some(header) = identity.as_ref().and_then(|x| cache.get(x)) {
+                entry.set_header(header);
+                *cached = true;
+            }
+            entry
+        })
+        .collect();
+    log::info!(
+        "{} of {} rom headers were cached",
+        cached.iter().filter(|&&x| x).count(),
+        cached.len()
+    );
+    drop(library);
+
+    if !is_current() {
+        return;
+    }
+    proxy
+        .send_event(UserEvent::UpdatedRomList {
+            generation,
+            roms: entries.clone(),
+        })
+        .unwrap();
+
+    // roms are only hashed when there is a database to look them up
+    let has_database = game_database().is_some();
+    let (job_sender, job_receiver) = flume::unbounded();
+    for (index, (entry, &cached)) in entries.iter().zip(cached.iter()).enumerate() {
+        if !cached || has_database {
+            job_sender.send((index, entry.clone(), cached)).unwrap();
+        }
+    }
+    drop(job_sender);
+
+    let threads = std::thread::available_parallelism().map_or(4, |x| x.get().clamp(4, 8));
+    let (result_sender, result_receiver) = flume::unbounded();
+    for _ in 0..threads {
+        let job_receiver = job_receiver.clone();
+        let result_sender = result_sender.clone();
+        std::thread::spawn(move || {
+            for (index, mut entry, cached) in job_receiver.iter() {
+                let header = load_entry(&mut entry, cached);
+                // the receiver is dropped if the loading stops early
+                if result_sender.send((index, entry, header)).is_err() {
+                    break;
+                }
+            }
+        });
+    }
+    drop(result_sender);
+
+    // the new headers are saved in batches, so a interrupted loading still keeps most of them
+    let mut unsaved = 0;
+    let mut last_update = instant::Instant::now();
+    for (index, entry, header) in result_receiver.iter() {
+        if !is_current() {
+            log::info!("rom loading interrupted by a newer one");
+            cache.save();
+            return;
+        }
+        if let (Some(header), Some(identity)) = (header, &identities[index]) {
+            cache.insert(identity.clone(), header);
+            unsaved += 1;
+            if unsaved >= CACHE_BATCH {
+                cache.save();
+                unsaved = 0;
+            }
+        }
+        entries[index] = entry;
+
+        if last_update.elapsed() >= UPDATE_INTERVAL {
+            last_update = instant::Instant::now();
+            proxy
+                .send_event(UserEvent::UpdatedRomList {
+                    generation,
+                    roms: entries.clone(),
+                })
+                .unwrap();
+        }
+    }
+
+    cache.retain(identities.iter().flatten().map(|x| x.path.as_str()));
+    cache.save();
+
+    log::info!(
+        "loading roms took: {:?} ({} threads)",
+        start.elapsed(),
+        threads
+    );
+    proxy
+        .send_event(UserEvent::UpdatedRomList {
+            generation,
+            roms: entries.clone(),
+        })
+        .unwrap();
+
+    if config().thumbnails {
+        load_thumbnails(&proxy, &entries, generation, is_current);
+    }
+}
+
+/// Read the thumbnails of the roms, after their headers, sending them to the UI in batches every
+/// `UPDATE_INTERVAL`. Roms without a thumbnail are skipped.
+#[cfg(not(target_arch = "wasm32"))]
+fn load_thumbnails(
+    proxy: &EventLoopProxy<UserEvent>,
+    entries: &[RomEntry],
+    generation: u64,
+    is_current: impl Fn() -> bool,
+) {
+    let start = instant::Instant::now();
+    let mut batch = Vec::new();
+    let mut count = 0;
+    let mut last_update = instant::Instant::now();
+    for entry in entries {
+        if !is_current() {
+            return;
+        }
+        let key = match entry.file.recent_key() {
+            Some(x) => x,
+            None => continue,
+        };
+        let thumbnail = entry
+            .file
+            .load_thumbnail()
+            .and_then(|x| x.map(|x| thumbnails::decode_thumbnail(&x)).transpose());
+        match thumbnail {
+            Ok(Some(thumbnail)) => batch.push((key, thumbnail)),
+            Ok(None) => {}
+            Err(err) => log::error!(
+                "error loading '{}' thumbnail: {}",
+                entry.file.file_name(),
+                err
+            ),
+        }
+
+        if !batch.is_empty() && last_update.elapsed() >= UPDATE_INTERVAL {
+            last_update = instant::Instant::now();
+            count += batch.len();
+            proxy
+                .send_event(UserEvent::LoadedThumbnails {
+                    generation,
+                    thumbnails: std::mem::take(&mut batch),
+                })
+                .unwrap();
+        }
+    }
+    count += batch.len();
+    log::info!("loading {} thumbnails took: {:?}", count, start.elapsed());
+    if !batch.is_empty() {
+        proxy
+            .send_event(UserEvent::LoadedThumbnails {
+                generation,
+                thumbnails: batch,
+            })
+            .unwrap();
+    }
+}
+
+/// Read the header of the rom, if it is not `cached`, and look up its name in the game database,
+/// if there is one. Return the header that was read, to be cached.
+#[cfg(not(target_arch = "wasm32"))]
+fn load_entry(entry: &mut RomEntry, cached: bool) -> Option<crate::rom_loading::CachedHeader> {
+    let mut new_header = None;
+    if !cached {
+        let header = executor::block_on(Box::pin(entry.file.get_header()).as_mut());
+        match header {
+            Ok(header) => {
+                let header = crate::rom_loading::CachedHeader::from_header(&header);
+                if let Some(warning) = &header.warning {
+                    log::warn!("'{}': {}", entry.file.file_name(), warning);
+                }
+                entry.set_header(&header);
+                new_header = Some(header);
+            }
+            Err(err) => {
+                entry.name = Some("Error reading header...".to_string());
+                entry.size = None;
+                log::error!("error reading '{}' header: {}", entry.file.file_name(), err);
+                return None;
+            }
+        }
+    }
+
+    if let Some(database) = game_database() {
+        let hash = executor::block_on(Box::pin(entry.file.hash()).as_mut());
+        match hash {
+            Ok(hash) => {
+                if let Some(name) = database.lookup(&hash) {
+                    entry.name = Some(name.to_string());
+                }
+                entry.hash = Some(hash);
+            }
+            Err(err) => log::error!("error hashing '{}': {}", entry.file.file_name(), err),
+        }
+    }
+    new_header
 }
 
 #[derive(Clone, Debug)]
 pub struct RomEntry {
-    /// The name of the game as write in the rom header.
+    /// The name of the game as write in the rom header, or as found in the game database.
     name: Option<String>,
     /// The size of the rom file in bytes
     size: Option<u64>,
     /// The instant in millisenconds since epoch of this rom's ram save file
     save_time: Option<u64>,
+    /// A description of the problems with the rom header, like a invalid logo or header checksum,
+    /// which the boot ROM refuses to run. See `RomIntegrity`.
+    header_warning: Option<String>,
+    /// The CRC32 and SHA-1 of the rom. Only computed if there is a game database.
+    pub hash: Option<RomHash>,
+    /// The memory bank controller, the supported models and the licensee, from the header.
+    mapper: Option<String>,
+    color: Option<String>,
+    licensee: Option<String>,
+    /// If the rom is marked as favorite, and so listed above the others.
+    favorite: bool,
+    /// The instant in milliseconds since epoch when this rom was last launched.
+    last_launch: Option<u64>,
+    /// If the rom has a save state, and if it can be loaded.
+    state: Option<StateInfo>,
+    /// The total time the rom was played, in milliseconds, from the `PlayLog`.
+    play_time: Option<u64>,
+    /// The instant in milliseconds since epoch when the rom was last played, from the `PlayLog`.
+    last_played: Option<u64>,
     /// The path to the rom
     pub file: RomFile,
 }
+
+/// A row of the rom list, below its header.
+enum Row<'a> {
+    /// The title of a group of roms.
+    Section(&'static str),
+    Rom(&'a RomEntry),
+}
+
 impl RomEntry {
+    /// Fill the information that comes from the `RomLibrary`.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn set_library(&mut self, library: &crate::rom_loading::RomLibrary) {
+        let key = self.file.recent_key();
+        self.favorite = key.as_ref().map_or(false, |x| library.is_favorite(x));
+        self.last_launch = key.and_then(|x| library.last_launch(&x));
+    }
+
+    /// Fill the information that comes from the `PlayLog`.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn set_play_log(&mut self, play_log: &crate::rom_loading::PlayLog) {
+        let stats = self.file.recent_key().and_then(|x| play_log.stats(&x));
+        self.play_time = stats.map(|x| x.play_time);
+        self.last_played = stats.map(|x| x.last_played);
+    }
+
+    /// When the rom was last played, by the play log, or else by the time of its battery save.
+    fn last_played(&self) -> Option<u64> {
+        self.last_played.or(self.save_time)
+    }
+
+    /// Fill the information that comes from the rom header.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn set_header(&mut self, header: &crate::rom_loading::CachedHeader) {
+        self.name = Some(header.name.clone());
+        self.size = Some(header.rom_size);
+        // only cartridges with a battery have a save file
+        if header.has_battery {
+            self.save_time = self.file.get_save_time().ok();
+        }
+        self.header_warning = header.warning.clone();
+        self.mapper = Some(header.mapper.clone());
+        self.color = Some(header.color.clone());
+        self.licensee = Some(header.licensee.clone());
+    }
+
+    /// The text shown when hovering the collumn with the given index in `COLLUMNS`: the header
+    /// warning on the name, and the exact time of the collumns that show a age.
+    fn tooltip(&self, collumn: usize) -> Option<String> {
+        match collumn {
+            1 => self.header_warning.clone(),
+            3 => self.last_played().map(age::exact_time),
+            7 => match self.state {
+                Some(StateInfo::Valid(time)) => time.map(age::exact_time),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// The text of the collumn with the given index in `COLLUMNS`.
+    fn text(&self, collumn: usize) -> String {
+        let or_dash = |x: &Option<String>| x.clone().unwrap_or_else(|| "-".to_string());
+        match collumn {
+            0 => self.file.file_name().into_owned(),
+            1 => self.name(),
+            2 => self.size(),
+            3 => age(self.last_played()),
+            4 => or_dash(&self.mapper),
+            5 => or_dash(&self.color),
+            6 => or_dash(&self.licensee),
+            7 => self.state_text(),
+            8 => match self.play_time {
+                Some(time) => age::duration_text(time),
+                None => strings().no_value.to_string(),
+            },
+            _ => String::new(),
+        }
+    }
+
     pub fn name(&self) -> String {
         self.name.clone().unwrap_or("Loading...".to_string())
     }
 
+    /// If the file name or the name contains `filter`, which must be lowercase.
+    fn matches(&self, filter: &str) -> bool {
+        filter.is_empty()
+            || self.file.file_name().to_lowercase().contains(filter)
+            || self
+                .name
+                .as_ref()
+                .map_or(false, |x| x.to_lowercase().contains(filter))
+    }
+
     fn size(&self) -> String {
         if let Some(size) = self.size {
             if size < (1 << 20) {
@@ -257,59 +954,199 @@ impl RomEntry {
         }
     }
 
-    fn save_age(&self) -> String {
-        use instant::{Duration, SystemTime};
-
-        let last_played = match self.save_time {
-            Some(x) => x,
-            None => return " - ".to_string(),
+    /// Check again the save state of the rom.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn refresh_state(&mut self) {
+        self.state = match self.file.read_state_header() {
+            Ok(header) => header.map(|x| StateInfo::from_header(&x)),
+            Err(err) => {
+                log::error!("{}", err);
+                None
+            }
         };
-        let then = SystemTime::UNIX_EPOCH.add(Duration::from_millis(last_played));
-        let now = SystemTime::now();
+        if let Some(StateInfo::Invalid(reason)) = &self.state {
+            log::warn!("'{}' save state: {}", self.file.file_name(), reason);
+        }
+    }
 
-        let delta = now.duration_since(then);
+    /// The instant the save state was saved, for sorting. States that don't record it are sorted
+    /// as the oldest ones, and the ones that can't be loaded as if there was no state.
+    fn state_time(&self) -> Option<u64> {
+        match self.state {
+            Some(StateInfo::Valid(time)) => Some(time.unwrap_or(0)),
+            _ => None,
+        }
+    }
 
-        let delta = match delta {
-            Ok(x) => x.as_secs(),
-            Err(_) => return " - ".to_string(),
-        };
+    /// The age of the save state, or a warning sign if it can't be loaded.
+    fn state_text(&self) -> String {
+        match &self.state {
+            Some(StateInfo::Valid(Some(time))) => age(Some(*time)),
+            Some(StateInfo::Valid(None)) => "Saved".to_string(),
+            Some(StateInfo::Invalid(_)) => "(!) Incompatible".to_string(),
+            None => " - ".to_string(),
+        }
+    }
+}
 
-        const SECOND: u64 = 1;
-        const MINUTE: u64 = 60 * SECOND;
-        const HOUR: u64 = 60 * MINUTE;
-        const DAY: u64 = 24 * HOUR;
-        // const WEEK: u64 = 7 * DAY;
-        const MONTH: u64 = 30 * DAY;
-        const YEAR: u64 = 365 * DAY;
+/// How long ago the given instant, in milliseconds since epoch, was.
+fn age(time: Option<u64>) -> String {
+    match time {
+        Some(time) => age::age_text(crate::rom_loading::now_millis(), time),
+        None => strings().no_value.to_string(),
+    }
+}
 
-        match delta {
-            x if x < SECOND => format!("Just Now"),
-            x if x < MINUTE => format!("{}s agi", x / SECOND),
-            x if x < HOUR => format!("{}min ago", x / MINUTE),
-            x if x < DAY => format!("{}h ago", x / HOUR),
-            x if x < MONTH => format!("{}d ago", x / DAY),
-            x => format!("{} years", x / YEAR),
+/// Send a `UserEvent::RefreshAges` every `AGE_REFRESH_INTERVAL`, until the event loop is closed.
+#[cfg(not(target_arch = "wasm32"))]
+fn send_age_refreshes(proxy: EventLoopProxy<UserEvent>) {
+    loop {
+        std::thread::sleep(AGE_REFRESH_INTERVAL);
+        if proxy.send_event(UserEvent::RefreshAges).is_err() {
+            return;
         }
     }
 }
 
+/// Load the rom and start the emulation. Its battery save is only loaded if `with_save` is true.
+fn play_rom(ctx: &mut Context, file: RomFile, with_save: bool) {
+    let proxy = ctx.get::<EventLoopProxy<UserEvent>>().clone();
+    let task = async move {
+        let result = if with_save {
+            load_rom_file(&file).await
+        } else {
+            let rom = file.read().await;
+            rom.and_then(|rom| load_gameboy(rom, None))
+                .map_err(|err| format!("failed to load '{}': {}", file.file_name(), err))
+        };
+        let event = match result {
+            Ok(game_boy) => UserEvent::LoadRom { file, game_boy },
+            Err(err) => UserEvent::Error(err),
+        };
+        log::debug!("sending LoadRom");
+        proxy.send_event(event).unwrap();
+    };
+    executor::Executor::spawn_task(task, ctx);
+}
+
+/// Load the rom with its battery save, load its save state, and start the emulation.
+#[cfg(not(target_arch = "wasm32"))]
+fn play_rom_from_state(ctx: &mut Context, file: RomFile) {
+    use gameroy::save_state::SaveState;
+
+    let proxy = ctx.get::<EventLoopProxy<UserEvent>>().clone();
+    let task = async move {
+        let result = load_rom_file(&file).await.and_then(|mut game_boy| {
+            file.load_state()
+                .and_then(|state| {
+                    game_boy
+                        .load_state(&mut state.as_slice())
+                        .map_err(|err| err.to_string())
+                })
+                .map_err(|err| {
+                    format!(
+                        "failed to load the save state of '{}': {}",
+                        file.file_name(),
+                        err
+                    )
+                })?;
+            Ok(game_boy)
+        });
+        let event = match result {
+            Ok(game_boy) => UserEvent::LoadRom { file, game_boy },
+            Err(err) => UserEvent::Error(err),
+        };
+        log::debug!("sending LoadRom");
+        proxy.send_event(event).unwrap();
+    };
+    executor::Executor::spawn_task(task, ctx);
+}
+
 struct SetSelected(usize);
 
 struct RomList {
     table_group: Rc<RefCell<TableGroup>>,
+    /// The text field used to filter the list.
+    filter_field: Id,
+    /// The label that shows the number of roms that match the filter.
+    count: Id,
+    /// If the first collumn shows the thumbnails of the roms.
+    thumbnails: bool,
+    /// The indices in `COLLUMNS` of the collumns that are shown.
+    collumns: Vec<usize>,
     last_selected: Option<usize>,
     selected: Option<usize>,
+    /// The key of the selected rom, to select it again when the rows change.
+    #[cfg(not(target_arch = "wasm32"))]
+    selected_key: Option<String>,
     rebuild_everthing: bool,
+    /// If the ages of the built rows must be updated, in the next layout.
+    refresh_ages: bool,
 }
 impl RomList {
-    fn new(table_group: Rc<RefCell<TableGroup>>) -> Self {
+    fn new(
+        table_group: Rc<RefCell<TableGroup>>,
+        filter_field: Id,
+        count: Id,
+        thumbnails: bool,
+        collumns: Vec<usize>,
+    ) -> Self {
         Self {
             table_group,
+            filter_field,
+            count,
+            thumbnails,
+            collumns,
             last_selected: None,
             rebuild_everthing: false,
+            refresh_ages: false,
             selected: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            selected_key: None,
+        }
+    }
+
+    /// Update the texts of the age collumns of the row built for the item `index`.
+    fn update_ages(&self, index: usize, item_id: Id, ctx: &mut dyn giui::BuilderContext) {
+        let texts: Vec<(usize, String)> = match ctx.get::<RomEntries>().row(index - 1) {
+            Row::Rom(entry) => self
+                .collumns
+                .iter()
+                .enumerate()
+                .filter(|&(_, i)| AGE_COLLUMNS.contains(i))
+                .map(|(position, &i)| (position, entry.text(i)))
+                .collect(),
+            Row::Section(_) => return,
+        };
+        let cells = ctx.get_active_children(item_id);
+        for (position, text) in texts {
+            // the thumbnail collumn, if any, is before the others
+            let cell = match cells.get(position + self.thumbnails as usize) {
+                Some(&x) => x,
+                None => continue,
+            };
+            if let Some(&label) = ctx.get_active_children(cell).last() {
+                ctx.get_graphic_mut(label).set_text(&text);
+            }
         }
     }
+
+    /// Select the selected rom again, in its new row, after the list was reloaded or sorted. The
+    /// selection is cleared if the rom is no longer listed.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn reselect(&mut self, ctx: &mut Context) {
+        let entries = ctx.get::<RomEntries>();
+        // the header is the item 0
+        self.selected = self
+            .selected_key
+            .as_deref()
+            .and_then(|key| entries.find_row(key))
+            .map(|row| row + 1);
+        if self.selected.is_none() {
+            self.selected_key = None;
+        }
+        self.last_selected = None;
+    }
 }
 impl ListBuilder for RomList {
     fn content_width(&mut self) -> f32 {
@@ -326,6 +1163,10 @@ impl ListBuilder for RomList {
             return false;
         }
 
+        if self.refresh_ages && index != 0 {
+            self.update_ages(index, item_id, ctx);
+        }
+
         if self.last_selected.is_some() {
             if Some(index) == self.last_selected || Some(index) == self.selected {
                 *ctx.get_graphic_mut(item_id) = if self.selected == Some(index) {
@@ -341,10 +1182,11 @@ impl ListBuilder for RomList {
     fn finished_layout(&mut self) {
         self.last_selected = None;
         self.rebuild_everthing = false;
+        self.refresh_ages = false;
     }
 
     fn item_count(&mut self, ctx: &mut dyn giui::BuilderContext) -> usize {
-        ctx.get::<RomEntries>().roms().len() + 1
+        ctx.get::<RomEntries>().row_count() + 1
     }
 
     fn on_event(&mut self, event: Box<dyn std::any::Any>, this: giui::Id, ctx: &mut giui::Context) {
@@ -354,13 +1196,88 @@ impl ListBuilder for RomList {
             }
             self.last_selected = self.selected.or(Some(index));
             self.selected = Some(index);
+            #[cfg(not(target_arch = "wasm32"))]
+            {
+                self.selected_key = ctx.get::<RomEntries>().row_key(index - 1);
+            }
             ctx.dirty_layout(this);
         } else if event.is::<event_table::UpdatedRomList>() {
             log::trace!("rebuilding rom list ui");
+            let count = ctx.get::<RomEntries>().count_text();
+            ctx.get_graphic_mut(self.count).set_text(&count);
+            #[cfg(not(target_arch = "wasm32"))]
+            self.reselect(ctx);
+            self.rebuild_everthing = true;
+            ctx.dirty_layout(this);
+        } else if event.is::<event_table::FilterCleared>() {
+            // the list itself is rebuilt by the `UpdatedRomList` that follows
+            ctx.send_event_to(self.filter_field, SetValue(String::new()));
+        } else if event.is::<FilterChanged>() {
+            let count = ctx.get::<RomEntries>().count_text();
+            ctx.get_graphic_mut(self.count).set_text(&count);
+            self.selected = None;
+            self.last_selected = None;
             self.rebuild_everthing = true;
             ctx.dirty_layout(this);
         } else if let Some(&SortBy(index)) = event.downcast_ref() {
             ctx.get_mut::<RomEntries>().sort_by(index);
+            #[cfg(not(target_arch = "wasm32"))]
+            self.reselect(ctx);
+            self.rebuild_everthing = true;
+            ctx.dirty_layout(this);
+        } else if event.is::<event_table::RefreshAges>() {
+            // only the texts of the ages change, the rows are kept
+            self.refresh_ages = true;
+            ctx.dirty_layout(this);
+        } else if event.is::<CollumnResized>() {
+            let mut widths = collumn_widths(&config().column_widths);
+            {
+                let table = self.table_group.borrow();
+                // the thumbnail collumn, if any, is not in `COLLUMNS`
+                let table_collumns = &table.columns[self.thumbnails as usize..];
+                for (&index, collumn) in self.collumns.iter().zip(table_collumns) {
+                    widths[index] = collumn.width.max(MIN_COLLUMN_WIDTH);
+                }
+            }
+            config().column_widths = widths;
+            let _ = config()
+                .save()
+                .map_err(|x| log::error!("error saving config: {}", x));
+
+            // the rows are rebuilt with the new widths, not only the header
+            self.rebuild_everthing = true;
+            ctx.dirty_layout(this);
+        }
+
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(&ToggleCollumn(index)) = event.downcast_ref() {
+            if let Some(i) = self.collumns.iter().position(|&x| x == index) {
+                self.collumns.remove(i);
+            } else {
+                self.collumns.push(index);
+                self.collumns.sort_unstable();
+            }
+            set_table_collumns(
+                &mut self.table_group.borrow_mut(),
+                self.thumbnails,
+                &self.collumns,
+            );
+
+            let titles = self.collumns.iter().map(|&i| COLLUMNS[i].0.to_string());
+            config().rom_list_columns = Some(titles.collect());
+            let _ = config()
+                .save()
+                .map_err(|x| log::error!("error saving config: {}", x));
+
+            self.rebuild_everthing = true;
+            ctx.dirty_layout(this);
+        }
+
+        #[cfg(not(target_arch = "wasm32"))]
+        if event.is::<FavoriteToggled>() {
+            // the rows move to and from the favorites
+            self.selected = None;
+            self.last_selected = None;
             self.rebuild_everthing = true;
             ctx.dirty_layout(this);
         }
@@ -375,39 +1292,67 @@ impl ListBuilder for RomList {
     ) -> giui::ControlBuilder {
         let style = &ctx.get::<Style>().clone();
         let header = index == 0;
-        let (file, name, size, age, entry) = if !header {
-            let roms = ctx.get::<RomEntries>().roms();
-            let entry = roms[index - 1].clone();
-            let size = entry.size();
-            let age = entry.save_age();
-            (
-                entry.file.file_name().into_owned(),
-                entry.name(),
-                size,
-                age,
-                Some(entry),
-            )
+        let (texts, entry): (Vec<String>, _) = if !header {
+            let entry = match ctx.get::<RomEntries>().row(index - 1) {
+                Row::Rom(entry) => entry.clone(),
+                Row::Section(title) => {
+                    return cb.layout(MarginLayout::new([4.0, 6.0, 4.0, 2.0])).child(
+                        ctx,
+                        move |cb, _| {
+                            cb.graphic(Text::new(
+                                title.to_string(),
+                                (-1, 0),
+                                style.text_style.clone(),
+                            ))
+                            .layout(FitGraphic)
+                        },
+                    );
+                }
+            };
+            let texts = self.collumns.iter().map(|&i| entry.text(i)).collect();
+            (texts, Some(entry))
         } else {
-            (
-                COLLUMNS[0].0.to_string(),
-                COLLUMNS[1].0.to_string(),
-                COLLUMNS[2].0.to_string(),
-                COLLUMNS[3].0.to_string(),
-                None,
-            )
+            let texts = self
+                .collumns
+                .iter()
+                .map(|&i| COLLUMNS[i].0.to_string())
+                .collect();
+            (texts, None)
+        };
+        // the rows are created without the selection, when the list is rebuilt
+        let cb = if !header && self.selected == Some(index) {
+            cb.graphic(style.entry_selected.clone())
+        } else {
+            cb
         };
         let parent = cb.id();
-        for (collumn_index, text) in [file, name, size, age].into_iter().enumerate() {
-            let cb = ctx
-                .create_control()
-                .parent(parent)
-                .child(ctx, move |cb, _| {
-                    let text_style = style.text_style.clone();
-                    // I could use `.layout(FitGraphic)` but I want to the text to be cut off.
-                    cb.min_size([0.0, text_style.font_size])
-                        .graphic(Text::new(text, (-1, 0), text_style).with_wrap(false))
-                        .expand_x(true)
-                });
+        if self.thumbnails {
+            #[cfg(not(target_arch = "wasm32"))]
+            create_thumbnail(ctx, parent, entry.as_ref());
+        }
+        for (&collumn_index, text) in self.collumns.iter().zip(texts) {
+            let cb = ctx.create_control().parent(parent);
+            #[cfg(not(target_arch = "wasm32"))]
+            let cb = match (collumn_index, &entry) {
+                (0, Some(entry)) => {
+                    cb.child(ctx, |cb, ctx| favorite_toggle(ctx, cb, entry, list_id))
+                }
+                _ => cb,
+            };
+            // mark the name of roms with a invalid header, whose warning is in the tooltip
+            let cb = match (collumn_index, &entry) {
+                (1, Some(entry)) if entry.header_warning.is_some() => cb.child(ctx, |cb, _| {
+                    cb.graphic(style.warning_icon.clone()).layout(FitGraphic)
+                }),
+                _ => cb,
+            };
+            let cb = cb.child(ctx, move |cb, _| {
+                let text_style = style.text_style.clone();
+                // I could use `.layout(FitGraphic)` but I want to the text to be cut off.
+                cb.min_size([0.0, text_style.font_size])
+                    .graphic(Text::new(text, (-1, 0), text_style).with_wrap(false))
+                    .expand_x(true)
+            });
 
             if header {
                 let (sort_collumn, sort_direction) = {
@@ -434,6 +1379,8 @@ impl ListBuilder for RomList {
                         log::info!("sort by {collumn_index}");
                         ctx.send_event_to(list_id, SortBy(collumn_index))
                     }))
+            } else if collumn_index == 0 || collumn_index == 1 {
+                cb.layout(HBoxLayout::new(2.0, [2.0; 4], -1))
             } else {
                 cb.layout(MarginLayout::new([2.0; 4]))
             }
@@ -441,34 +1388,27 @@ impl ListBuilder for RomList {
         }
         cb.behaviour_and_layout({
             let mut item = TableItem::new(self.table_group.clone()).with_resizable(header);
+            if header {
+                item.set_on_resize(move |ctx| ctx.send_event_to(list_id, CollumnResized));
+            }
             if let Some(entry) = entry {
+                // the thumbnail collumn, if any, has no tooltip
+                let thumbnail = self.thumbnails.then(|| None);
+                let tooltips = self.collumns.iter().map(|&i| entry.tooltip(i));
+                item.set_tooltips(thumbnail.into_iter().chain(tooltips).collect());
+                #[cfg(not(target_arch = "wasm32"))]
+                item.set_on_context_menu({
+                    let entry = entry.clone();
+                    move |pos, ctx| {
+                        ctx.send_event_to(list_id, SetSelected(index));
+                        rom_menu::open_rom_menu(ctx, &entry, pos, list_id);
+                    }
+                });
                 item.set_on_click(move |click_count, ctx| {
                     if click_count == 1 {
                         ctx.send_event_to(list_id, SetSelected(index))
                     } else if click_count == 2 {
-                        let proxy = ctx.get::<EventLoopProxy<UserEvent>>().clone();
-                        let p = proxy.clone();
-                        let file = entry.file.clone();
-                        let task = async move {
-                            let rom = file.read().await.unwrap();
-                            let ram = match file.load_ram_data().await {
-                                Ok(x) => Some(x),
-                                Err(err) => {
-                                    log::error!("{}", err);
-                                    None
-                                }
-                            };
-                            let game_boy = match load_gameboy(rom, ram) {
-                                Ok(x) => x,
-                                Err(err) => {
-                                    log::error!("failed to load rom: {}", err);
-                                    return;
-                                }
-                            };
-                            log::debug!("sending LoadRom");
-                            p.send_event(UserEvent::LoadRom { file, game_boy }).unwrap();
-                        };
-                        executor::Executor::spawn_task(task, ctx);
+                        play_rom(ctx, entry.file.clone(), true);
                     }
                 });
             }
@@ -477,6 +1417,189 @@ impl ListBuilder for RomList {
     }
 }
 
+/// Create the "Recent" section, with a button for each rom in the `recent_roms` config, that
+/// plays it. Roms whose file no longer exists are grayed out.
+#[cfg(not(target_arch = "wasm32"))]
+fn create_recent_roms(ctx: &mut giui::Gui, parent: Id, style: &Style) {
+    let recent_roms = config().recent_roms.clone();
+    if recent_roms.is_empty() {
+        return;
+    }
+
+    let list = ctx
+        .create_control()
+        .parent(parent)
+        .layout(VBoxLayout::new(0.0, [2.0; 4], -1))
+        .child(ctx, |cb, _| {
+            cb.graphic(Text::new(
+                "Recent".to_string(),
+                (-1, 0),
+                style.text_style.clone(),
+            ))
+            .layout(FitGraphic)
+        })
+        .build(ctx);
+
+    for key in recent_roms {
+        let file = RomFile::from_recent_key(&key);
+        let text = Text::new(
+            file.file_name().into_owned(),
+            (-1, 0),
+            style.text_style.clone(),
+        )
+        .with_wrap(false);
+        let cb = ctx
+            .create_control()
+            .parent(list)
+            .layout(MarginLayout::new([4.0, 2.0, 4.0, 2.0]));
+        if file.identity().is_some() {
+            cb.behaviour(Button::new(
+                style.delete_button.clone(),
+                true,
+                move |_, ctx| play_rom(ctx, file.clone(), true),
+            ))
+            .child(ctx, |cb, _| cb.graphic(text).layout(FitGraphic))
+            .build(ctx);
+        } else {
+            log::debug!("recent rom '{}' not found", key);
+            cb.child(ctx, |cb, _| {
+                cb.graphic(Graphic::from(text).with_alpha(96))
+                    .layout(FitGraphic)
+            })
+            .build(ctx);
+        }
+    }
+}
+
+/// Open the menu that shows or hides the collumns of the rom list, below the button `button`.
+#[cfg(not(target_arch = "wasm32"))]
+fn open_collumns_menu(ctx: &mut Context, button: Id, list: Id) {
+    use crate::widget::{context_menu::create_context_menu, menu::MenuOption};
+
+    let visible = visible_collumns();
+    let labels: Vec<String> = COLLUMNS
+        .iter()
+        .enumerate()
+        .map(|(i, &(title, _))| {
+            let mark = if visible.contains(&i) { "[x]" } else { "[ ]" };
+            format!("{} {}", mark, title)
+        })
+        .collect();
+    let options: Vec<MenuOption> = labels
+        .iter()
+        .enumerate()
+        // the "File" collumn is always shown
+        .skip(1)
+        .map(|(i, label)| -> MenuOption {
+            (
+                label.as_str(),
+                Box::new(move |ctx: &mut Context| ctx.send_event_to(list, ToggleCollumn(i))),
+            )
+        })
+        .collect();
+
+    let rect = ctx.get_rect(button);
+    let style = ctx.get::<Style>().clone();
+    create_context_menu(options, [rect[0], rect[3]], ctx, &style);
+}
+
+/// Build the cell with the thumbnail of the rom, or with a placeholder if it has none. The header
+/// has a empty cell.
+#[cfg(not(target_arch = "wasm32"))]
+fn create_thumbnail(ctx: &mut dyn giui::BuilderContext, parent: Id, entry: Option<&RomEntry>) {
+    use giui::graphics::Texture;
+
+    let cb = ctx.create_control().parent(parent);
+    let entry = match entry {
+        Some(x) => x,
+        None => {
+            cb.build(ctx);
+            return;
+        }
+    };
+    let textures = ctx.get::<crate::ui::Textures>().clone();
+    let uv_rect = entry
+        .file
+        .recent_key()
+        .and_then(|key| ctx.get::<RomEntries>().thumbnails().uv_rect(&key));
+    let graphic = match uv_rect {
+        Some(uv_rect) => Texture::new(textures.thumbnails, uv_rect).into(),
+        None => Graphic::from(Texture::new(textures.white, [0.0, 0.0, 1.0, 1.0]))
+            .with_color([64, 64, 64, 255].into()),
+    };
+    cb.layout(MarginLayout::new([0.0, 2.0, 0.0, 2.0]))
+        .child(ctx, move |cb, _| {
+            cb.min_size([THUMBNAIL_WIDTH as f32, THUMBNAIL_HEIGHT as f32])
+                .graphic(graphic)
+        })
+        .build(ctx);
+}
+
+/// Build the button that marks or unmarks the rom as favorite, shown before its file name.
+#[cfg(not(target_arch = "wasm32"))]
+fn favorite_toggle(
+    ctx: &mut dyn giui::BuilderContext,
+    cb: giui::ControlBuilder,
+    entry: &RomEntry,
+    list_id: Id,
+) -> giui::ControlBuilder {
+    let style = ctx.get::<Style>().clone();
+    let favorite = entry.favorite;
+    let key = entry.file.recent_key();
+    let text = if favorite { "[*]" } else { "[ ]" };
+    cb.behaviour(Button::new(
+        style.header_style.clone(),
+        false,
+        move |_, ctx| {
+            if let Some(key) = &key {
+                ctx.get_mut::<RomEntries>().set_favorite(key, !favorite);
+                ctx.send_event_to(list_id, FavoriteToggled);
+            }
+        },
+    ))
+    .layout(FitGraphic)
+    .child(ctx, move |cb, _| {
+        cb.graphic(Text::new(
+            text.to_string(),
+            (0, 0),
+            style.text_style.clone(),
+        ))
+        .layout(FitGraphic)
+    })
+}
+
+/// The callback of the text field that filters the rom list.
+struct RomFilter {
+    list: Id,
+}
+impl TextFieldCallback for RomFilter {
+    fn on_submit(&mut self, _this: Id, _ctx: &mut Context, _text: &mut String) {}
+
+    fn on_change(&mut self, _this: Id, ctx: &mut Context, text: &str) {
+        ctx.get_mut::<RomEntries>().set_filter(text);
+        ctx.send_event_to(self.list, FilterChanged);
+    }
+
+    fn on_unfocus(&mut self, _this: Id, _ctx: &mut Context, _text: &mut String) {}
+
+    fn on_keyboard_event(
+        &mut self,
+        event: giui::KeyboardEvent,
+        this: Id,
+        ctx: &mut Context,
+    ) -> bool {
+        match event {
+            giui::KeyboardEvent::Pressed(VirtualKeyCode::Escape) => {
+                ctx.send_event_to(this, SetValue(String::new()));
+                ctx.get_mut::<RomEntries>().clear_filter();
+                ctx.send_event_to(self.list, FilterChanged);
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
 pub fn create_rom_loading_ui(
     ctx: &mut giui::Gui,
     style: &Style,
@@ -510,27 +1633,18 @@ pub fn create_rom_loading_ui(
                     let handle = &*handle;
                     let file = rfd::AsyncFileDialog::new()
                         .set_title("Open GameBoy Rom file")
-                        .add_filter("GameBoy roms", &["gb"])
+                        .add_filter("GameBoy roms", crate::rom_loading::ROM_EXTENSIONS)
                         .set_parent(handle)
                         .pick_file()
                         .await;
 
                     if let Some(file) = file {
                         let file: RomFile = file.into();
-                        let rom = file.read().await.unwrap();
-                        let ram = match file.load_ram_data().await {
-                            Ok(x) => Some(x),
-                            Err(err) => {
-                                log::error!("{}", err);
-                                None
-                            }
+                        let event = match load_rom_file(&file).await {
+                            Ok(game_boy) => UserEvent::LoadRom { file, game_boy },
+                            Err(err) => UserEvent::Error(err),
                         };
-                        proxy
-                            .send_event(UserEvent::LoadRom {
-                                file,
-                                game_boy: load_gameboy(rom, ram).unwrap(),
-                            })
-                            .unwrap();
+                        proxy.send_event(event).unwrap();
                     }
                 };
                 executor::Executor::spawn_task(task, ctx);
@@ -571,10 +1685,10 @@ pub fn create_rom_loading_ui(
 
                     if let Some(folder) = folder {
                         let path = folder.path().to_string_lossy().to_string();
-                        log::info!("setting rom folder to '{}'", path);
+                        log::info!("adding rom folder '{}'", path);
 
                         let mut conf = crate::config::config();
-                        conf.rom_folder = Some(path);
+                        conf.add_rom_folder(path);
                         let _ = conf
                             .save()
                             .map_err(|x| log::error!("error saving config: {}", x));
@@ -590,7 +1704,92 @@ pub fn create_rom_loading_ui(
         })
         .child(ctx, |cb, _| {
             cb.graphic(Text::new(
-                "choose folder".to_string(),
+                "add folder".to_string(),
+                (-1, 0),
+                style.text_style.clone(),
+            ))
+            .layout(FitGraphic)
+        })
+        .build(ctx);
+
+    #[cfg(not(target_arch = "wasm32"))]
+    let _refresh_button = ctx
+        .create_control()
+        .parent(h_box)
+        .layout(HBoxLayout::new(0.0, [0.0; 4], -1))
+        .behaviour(Button::new(
+            style.delete_button.clone(),
+            true,
+            move |_, ctx| {
+                let proxy = ctx.get::<EventLoopProxy<UserEvent>>().clone();
+                ctx.get_mut::<RomEntries>().refresh(proxy);
+            },
+        ))
+        .child(ctx, |cb, _| {
+            cb.graphic(Text::new(
+                "refresh".to_string(),
+                (-1, 0),
+                style.text_style.clone(),
+            ))
+            .layout(FitGraphic)
+        })
+        .build(ctx);
+
+    #[cfg(not(target_arch = "wasm32"))]
+    let _folders_button = {
+        let sty = style.clone();
+        ctx.create_control()
+            .parent(h_box)
+            .layout(HBoxLayout::new(0.0, [0.0; 4], -1))
+            .behaviour(Button::new(
+                style.delete_button.clone(),
+                true,
+                move |_, ctx| folders_dialog::open_folders_dialog(ctx, &sty),
+            ))
+            .child(ctx, |cb, _| {
+                cb.graphic(Text::new(
+                    "folders".to_string(),
+                    (-1, 0),
+                    style.text_style.clone(),
+                ))
+                .layout(FitGraphic)
+            })
+            .build(ctx)
+    };
+
+    #[cfg(not(target_arch = "wasm32"))]
+    let _recently_played_button = ctx
+        .create_control()
+        .parent(h_box)
+        .layout(HBoxLayout::new(0.0, [0.0; 4], -1))
+        .behaviour(Button::new(
+            style.delete_button.clone(),
+            true,
+            move |_, ctx| ctx.send_event_to(rom_list_id, SortBy(RECENTLY_PLAYED)),
+        ))
+        .child(ctx, |cb, _| {
+            cb.graphic(Text::new(
+                "recently played".to_string(),
+                (-1, 0),
+                style.text_style.clone(),
+            ))
+            .layout(FitGraphic)
+        })
+        .build(ctx);
+
+    #[cfg(not(target_arch = "wasm32"))]
+    let _collumns_button = ctx
+        .create_control()
+        .parent(h_box)
+        .layout(HBoxLayout::new(0.0, [0.0; 4], -1))
+        .behaviour(Button::new(
+            style.delete_button.clone(),
+            true,
+            move |this, ctx| open_collumns_menu(ctx, this, rom_list_id),
+        ))
+        .child(ctx, |cb, _| {
+            cb.graphic(Text::new(
+                "columns".to_string(),
                 (-1, 0),
                 style.text_style.clone(),
             ))
@@ -598,6 +1797,27 @@ pub fn create_rom_loading_ui(
         })
         .build(ctx);
 
+    let _keys_button = {
+        let sty = style.clone();
+        ctx.create_control()
+            .parent(h_box)
+            .layout(HBoxLayout::new(0.0, [0.0; 4], -1))
+            .behaviour(Button::new(
+                style.delete_button.clone(),
+                true,
+                move |_, ctx| keys_dialog::open_keys_dialog(ctx, &sty),
+            ))
+            .child(ctx, |cb, _| {
+                cb.graphic(Text::new(
+                    "keys".to_string(),
+                    (-1, 0),
+                    style.text_style.clone(),
+                ))
+                .layout(FitGraphic)
+            })
+            .build(ctx)
+    };
+
     let _remain = ctx
         .create_control()
         .graphic(style.background.clone())
@@ -628,11 +1848,60 @@ pub fn create_rom_loading_ui(
         })
         .build(ctx);
 
+    #[cfg(not(target_arch = "wasm32"))]
+    create_recent_roms(ctx, v_box, style);
+
+    let filter_field = ctx.reserve_id();
+    let count = ctx.reserve_id();
+    let filter_row = ctx
+        .create_control()
+        .parent(v_box)
+        .layout(HBoxLayout::new(2.0, [0.0; 4], -1))
+        .child(ctx, |cb, _| {
+            cb.graphic(Text::new(
+                "filter".to_string(),
+                (-1, 0),
+                style.text_style.clone(),
+            ))
+            .layout(FitGraphic)
+        })
+        .build(ctx);
+    let caret = ctx.reserve_id();
+    let label = ctx.reserve_id();
+    ctx.create_control_reserved(filter_field)
+        .parent(filter_row)
+        .behaviour(TextField::new(
+            caret,
+            label,
+            false,
+            style.text_field.clone(),
+            RomFilter { list: rom_list_id },
+        ))
+        .min_size([20.0; 2])
+        .expand_x(true)
+        .build(ctx);
+    ctx.create_control_reserved(caret)
+        .parent(filter_field)
+        .graphic(style.background.clone().with_color([0, 0, 0, 255].into()))
+        .anchors([0.0; 4])
+        .build(ctx);
+    ctx.create_control_reserved(label)
+        .parent(filter_field)
+        .graphic(Text::new(String::new(), (-1, -1), style.text_style.clone()))
+        .build(ctx);
+    let count_text = ctx.get::<RomEntries>().count_text();
+    ctx.create_control_reserved(count)
+        .parent(filter_row)
+        .graphic(Text::new(count_text, (1, 0), style.text_style.clone()))
+        .min_size([100.0, 0.0])
+        .layout(FitGraphic)
+        .build(ctx);
+
+    let thumbnails = cfg!(not(target_arch = "wasm32")) && config().thumbnails;
+    let collumns = visible_collumns();
     let table = {
         let mut tg = TableGroup::new(4.0, 2.0, [1.0, 1.0]);
-        for &(_, width) in COLLUMNS.iter() {
-            tg.add_column(width, false)
-        }
+        set_table_collumns(&mut tg, thumbnails, &collumns);
         tg
     };
 
@@ -642,10 +1911,121 @@ pub fn create_rom_loading_ui(
         ctx,
         style,
         [0.0; 4],
-        RomList::new(Rc::new(RefCell::new(table))),
+        RomList::new(
+            Rc::new(RefCell::new(table)),
+            filter_field,
+            count,
+            thumbnails,
+            collumns,
+        ),
     )
     .graphic(style.background.clone())
     .parent(v_box)
     .expand_y(true)
     .build(ctx);
 }
+
+#[cfg(all(test, not(any(target_arch = "wasm32", target_os = "android"))))]
+mod test {
+    use super::*;
+    use crate::rom_loading::RomLibrary;
+
+    fn entries(names: &[&str]) -> RomEntries {
+        RomEntries {
+            roms: names
+                .iter()
+                .map(|name| RomEntry {
+                    name: None,
+                    size: None,
+                    save_time: None,
+                    header_warning: None,
+                    hash: None,
+                    mapper: None,
+                    color: None,
+                    licensee: None,
+                    favorite: false,
+                    last_launch: None,
+                    state: None,
+                    play_time: None,
+                    last_played: None,
+                    file: RomFile::from_path((*name).into()),
+                })
+                .collect(),
+            sort_collumn: RECENTLY_PLAYED,
+            sort_direction: SortDirection::Ascending,
+            filter: String::new(),
+            visible: Vec::new(),
+            favorite_count: 0,
+            generation: Arc::default(),
+            thumbnails: ThumbnailAtlas::default(),
+            last_refresh: None,
+            #[cfg(feature = "watch")]
+            watcher: None,
+            observers: Vec::new(),
+        }
+    }
+
+    fn sorted_names(entries: &RomEntries) -> Vec<String> {
+        (0..entries.roms.len())
+            .map(|i| entries.roms[i].file.file_name().into_owned())
+            .collect()
+    }
+
+    #[test]
+    fn sort_by_last_launch() {
+        let mut entries = entries(&["a.gb", "b.gb", "c.gb", "d.gb"]);
+        let mut library = RomLibrary::default();
+        library.record_launch("c.gb", 100);
+        library.record_launch("a.gb", 200);
+        entries.set_library(&library);
+        assert_eq!(sorted_names(&entries), ["a.gb", "c.gb", "b.gb", "d.gb"]);
+
+        // launching a rom moves it to the front
+        library.record_launch("d.gb", 300);
+        entries.set_library(&library);
+        assert_eq!(sorted_names(&entries), ["d.gb", "a.gb", "c.gb", "b.gb"]);
+
+        // the roms never launched stay last in the reverse order
+        entries.sort_direction = SortDirection::Descending;
+        entries.update_sort();
+        assert_eq!(sorted_names(&entries), ["c.gb", "a.gb", "d.gb", "b.gb"]);
+    }
+
+    #[test]
+    fn find_rows() {
+        let mut entries = entries(&["a.gb", "b.gb", "c.gb"]);
+        entries.sort_collumn = 0;
+        entries.update_sort();
+        assert_eq!(entries.find_row("b.gb"), Some(1));
+        assert_eq!(entries.row_key(1).as_deref(), Some("b.gb"));
+
+        // a favorite moves to the top, below the "Favorites" title
+        entries.roms[2].favorite = true;
+        entries.update_sort();
+        assert_eq!(entries.row_key(0), None);
+        assert_eq!(entries.find_row("c.gb"), Some(1));
+        assert_eq!(entries.find_row("b.gb"), Some(4));
+
+        entries.set_filter("a");
+        assert_eq!(entries.find_row("a.gb"), Some(0));
+        assert_eq!(entries.find_row("b.gb"), None);
+    }
+
+    #[test]
+    fn widths_of_new_collumns() {
+        let defaults: Vec<f32> = COLLUMNS.iter().map(|&(_, width)| width).collect();
+        assert_eq!(collumn_widths(&[]), defaults);
+
+        // widths saved before the last collumn was added
+        let mut saved = defaults[..COLLUMNS.len() - 1].to_vec();
+        saved[0] = 300.0;
+        saved[1] = f32::NAN;
+        saved[2] = 1.0;
+        let widths = collumn_widths(&saved);
+        assert_eq!(widths[..3], [300.0, defaults[1], MIN_COLLUMN_WIDTH]);
+        assert_eq!(widths[COLLUMNS.len() - 1], defaults[COLLUMNS.len() - 1]);
+
+        saved.resize(COLLUMNS.len() + 1, 100.0);
+        assert_eq!(collumn_widths(&saved), defaults);
+    }
+}
@@ -0,0 +1,123 @@
+use crate::translation::{fill, strings};
+
+const MINUTE: u64 = 60 * 1000;
+const HOUR: u64 = 60 * MINUTE;
+const DAY: u64 = 24 * HOUR;
+const WEEK: u64 = 7 * DAY;
+const MONTH: u64 = 30 * DAY;
+const YEAR: u64 = 365 * DAY;
+
+/// How long before `now` the instant `time` was, like "3d ago", both in milliseconds since epoch.
+/// Each step is rounded down, so it only changes once a minute at most.
+pub fn age_text(now: u64, time: u64) -> String {
+    let strings = strings();
+    if time > now {
+        return strings.in_the_future.to_string();
+    }
+    let delta = now - time;
+    let (unit, template) = match delta {
+        x if x < MINUTE => return strings.just_now.to_string(),
+        x if x < HOUR => (MINUTE, strings.minutes_ago),
+        x if x < DAY => (HOUR, strings.hours_ago),
+        x if x < WEEK => (DAY, strings.days_ago),
+        x if x < MONTH => (WEEK, strings.weeks_ago),
+        x if x < YEAR => (MONTH, strings.months_ago),
+        _ => (YEAR, strings.years_ago),
+    };
+    fill(template, delta / unit)
+}
+
+/// A duration in milliseconds, in hours and minutes, like "12h 34m".
+pub fn duration_text(duration: u64) -> String {
+    let strings = strings();
+    let minutes = duration / MINUTE;
+    if minutes < 60 {
+        fill(strings.minutes, minutes)
+    } else {
+        fill(&fill(strings.hours_minutes, minutes / 60), minutes % 60)
+    }
+}
+
+/// The date and time of `time`, in milliseconds since epoch, in UTC, like "2022-05-01 13:05 UTC".
+pub fn exact_time(time: u64) -> String {
+    let days = time / DAY;
+    let minutes = (time % DAY) / MINUTE;
+    let (year, month, day) = civil_from_days(days);
+    let date = format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}",
+        year,
+        month,
+        day,
+        minutes / 60,
+        minutes % 60
+    );
+    fill(strings().utc_time, date)
+}
+
+/// Convert a number of days since 1970-01-01 to a (year, month, day) date, in the proleptic
+/// Gregorian calendar. See http://howardhinnant.github.io/date_algorithms.html#civil_from_days.
+fn civil_from_days(days: u64) -> (u64, u64, u64) {
+    // days since 0000-03-01, the start of a 400 years era
+    let days = days + 719468;
+    let era = days / 146097;
+    let day_of_era = days % 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    // the months start in March
+    let month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month + 2) / 5 + 1;
+    let month = if month < 10 { month + 3 } else { month - 9 };
+    let year = year_of_era + era * 400 + (month <= 2) as u64;
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn age_boundaries() {
+        let now = 100 * YEAR;
+        let ago = |delta: u64| age_text(now, now - delta);
+
+        assert_eq!(ago(0), "Just now");
+        assert_eq!(ago(MINUTE - 1), "Just now");
+        assert_eq!(ago(MINUTE), "1min ago");
+        assert_eq!(ago(HOUR - 1), "59min ago");
+        assert_eq!(ago(HOUR), "1h ago");
+        assert_eq!(ago(DAY - 1), "23h ago");
+        assert_eq!(ago(DAY), "1d ago");
+        assert_eq!(ago(WEEK - 1), "6d ago");
+        assert_eq!(ago(WEEK), "1w ago");
+        assert_eq!(ago(MONTH - 1), "4w ago");
+        assert_eq!(ago(MONTH), "1mo ago");
+        assert_eq!(ago(YEAR - 1), "12mo ago");
+        assert_eq!(ago(YEAR), "1y ago");
+        assert_eq!(ago(3 * YEAR + MONTH), "3y ago");
+    }
+
+    #[test]
+    fn durations() {
+        assert_eq!(duration_text(0), "0m");
+        assert_eq!(duration_text(HOUR - 1), "59m");
+        assert_eq!(duration_text(HOUR), "1h 0m");
+        assert_eq!(duration_text(12 * HOUR + 34 * MINUTE + 59_999), "12h 34m");
+        assert_eq!(duration_text(30 * DAY), "720h 0m");
+    }
+
+    #[test]
+    fn age_in_the_future() {
+        let now = 100 * YEAR;
+        assert_eq!(age_text(now, now + 1), "In the future");
+        assert_eq!(age_text(now, now + YEAR), "In the future");
+    }
+
+    #[test]
+    fn exact_times() {
+        assert_eq!(exact_time(0), "1970-01-01 00:00 UTC");
+        assert_eq!(exact_time(951_782_400_000), "2000-02-29 00:00 UTC");
+        assert_eq!(exact_time(1_651_410_300_000), "2022-05-01 13:05 UTC");
+        assert_eq!(exact_time(1_704_067_199_999), "2023-12-31 23:59 UTC");
+    }
+}
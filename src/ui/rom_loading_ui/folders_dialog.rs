@@ -0,0 +1,121 @@
+use giui::{
+    layouts::{FitGraphic, HBoxLayout, MarginLayout, VBoxLayout},
+    text::Text,
+    widgets::{Blocker, Button},
+    Context, Id, RectFill,
+};
+use winit::event_loop::EventLoopProxy;
+
+use crate::{config::config, style::Style, UserEvent};
+
+/// Remove the rom folder at `index`, save the config, and reload the rom list.
+fn remove_folder(ctx: &mut Context, index: usize) {
+    {
+        let mut conf = config();
+        if index < conf.rom_folders.len() {
+            let folder = conf.rom_folders.remove(index);
+            log::info!("removing rom folder '{}'", folder);
+        }
+        let _ = conf
+            .save()
+            .map_err(|x| log::error!("error saving config: {}", x));
+    }
+    ctx.get::<EventLoopProxy<UserEvent>>()
+        .send_event(UserEvent::UpdateRomList)
+        .unwrap();
+}
+
+/// Rebuild the list of folders.
+fn update_list(ctx: &mut Context, list: Id) {
+    let Style {
+        text_style,
+        delete_button,
+        delete_icon,
+        ..
+    } = ctx.get::<Style>().clone();
+
+    for child in ctx.get_active_children(list) {
+        ctx.remove(child);
+    }
+
+    let folders = config().rom_folders.clone();
+    if folders.is_empty() {
+        ctx.create_control()
+            .parent(list)
+            .graphic(Text::new(
+                "No folders. Add one with \"add folder\".".to_string(),
+                (-1, 0),
+                text_style.clone(),
+            ))
+            .layout(FitGraphic)
+            .build(ctx);
+    }
+
+    for (index, folder) in folders.into_iter().enumerate() {
+        let text_style = text_style.clone();
+        let delete_button = delete_button.clone();
+        let delete_icon = delete_icon.clone();
+        ctx.create_control()
+            .parent(list)
+            .layout(HBoxLayout::new(4.0, [0.0; 4], 1))
+            .child(ctx, |cb, _| {
+                cb.graphic(Text::new(folder, (-1, 0), text_style))
+                    .layout(FitGraphic)
+                    .expand_x(true)
+            })
+            .child(ctx, |cb, ctx| {
+                cb.behaviour(Button::new(delete_button, true, move |_, ctx| {
+                    remove_folder(ctx, index);
+                    update_list(ctx, list);
+                }))
+                .min_size([16.0, 16.0])
+                .child(ctx, |cb, _| cb.graphic(delete_icon))
+                .fill_y(RectFill::ShrinkCenter)
+            })
+            .build(ctx);
+    }
+}
+
+/// Open a dialog listing the folders where the roms are searched, where they can be removed. New
+/// folders are added with the "add folder" button.
+pub fn open_folders_dialog(ctx: &mut Context, style: &Style) {
+    let [dialog, blocker, list] = [(); 3].map(|_| ctx.reserve());
+
+    ctx.create_control_reserved(blocker)
+        .parent(Id::ROOT_ID)
+        .behaviour(Blocker::new(move |_, ctx| {
+            ctx.remove(dialog);
+            ctx.remove(blocker);
+        }))
+        .graphic(style.blocker.clone())
+        .build(ctx);
+
+    ctx.create_control_reserved(dialog)
+        .parent(Id::ROOT_ID)
+        .layout(VBoxLayout::new(4.0, [10.0; 4], -1))
+        .graphic(style.split_background.clone())
+        .fill_x(RectFill::ShrinkCenter)
+        .fill_y(RectFill::ShrinkCenter)
+        .build(ctx);
+
+    ctx.create_control()
+        .parent(dialog)
+        .layout(MarginLayout::new([0.0, 0.0, 0.0, 6.0]))
+        .child(ctx, |cb, _| {
+            cb.graphic(Text::new(
+                "Rom folders".to_string(),
+                (-1, 0),
+                style.text_menu.clone(),
+            ))
+            .layout(FitGraphic)
+        })
+        .build(ctx);
+
+    ctx.create_control_reserved(list)
+        .parent(dialog)
+        .layout(VBoxLayout::new(2.0, [0.0; 4], -1))
+        .min_size([300.0, 0.0])
+        .build(ctx);
+
+    update_list(ctx, list);
+}
@@ -0,0 +1,305 @@
+use std::{cell::Cell, rc::Rc};
+
+use giui::{
+    layouts::{FitGraphic, HBoxLayout, MarginLayout, VBoxLayout},
+    text::Text,
+    widgets::{Blocker, Button, OnKeyboardEvent},
+    Context, Id, RectFill,
+};
+use winit::event::VirtualKeyCode;
+
+use crate::{
+    config::config,
+    emulator::step_turbo_rate,
+    key_bindings::{Action, Key, KeyMap},
+    style::Style,
+    ui::KeyInput,
+};
+
+const HINT: &str = "Click on the keys of a action to add a key to it.";
+
+/// The state of the open dialog.
+#[derive(Clone)]
+struct KeysDialog {
+    dialog: Id,
+    list: Id,
+    status: Id,
+    /// The action waiting for a key press to be bound, if any.
+    capturing: Rc<Cell<Option<Action>>>,
+}
+
+/// Change the key bindings, and save them.
+fn edit_keymap(f: impl FnOnce(&mut KeyMap)) {
+    let mut conf = config();
+    f(&mut conf.keymap);
+    let _ = conf
+        .save()
+        .map_err(|x| log::error!("error saving config: {}", x));
+}
+
+/// The names of the keys, like "A, Return", or "None".
+fn keys_text(keys: &[Key]) -> String {
+    if keys.is_empty() {
+        return "None".to_string();
+    }
+    let names: Vec<String> = keys.iter().map(|x| x.to_string()).collect();
+    names.join(", ")
+}
+
+/// The text of the turbo rate, like "Turbo rate: 15/s".
+fn turbo_rate_text(rate: f32) -> String {
+    format!("Turbo rate: {}/s", rate)
+}
+
+/// Step the turbo rate up or down, save it, and update its text.
+fn step_rate(ctx: &mut Context, rate_text: Id, up: bool) {
+    let rate = {
+        let mut conf = config();
+        conf.turbo_rate = step_turbo_rate(conf.turbo_rate, up);
+        let _ = conf
+            .save()
+            .map_err(|x| log::error!("error saving config: {}", x));
+        conf.turbo_rate
+    };
+    ctx.get_graphic_mut(rate_text)
+        .set_text(&turbo_rate_text(rate));
+}
+
+/// The text listing the keys bound to more than one action, or the hint if there are none.
+fn conflicts_text(keymap: &KeyMap) -> String {
+    let conflicts = keymap.conflicts();
+    if conflicts.is_empty() {
+        return HINT.to_string();
+    }
+    let lines: Vec<String> = conflicts
+        .iter()
+        .map(|(key, actions)| {
+            let names: Vec<&str> = actions.iter().map(|x| x.name()).collect();
+            format!("{} is bound to {}", key, names.join(", "))
+        })
+        .collect();
+    format!("Conflicts:\n{}", lines.join("\n"))
+}
+
+impl KeysDialog {
+    /// Bind the next pressed key to `action`.
+    fn start_capture(&self, ctx: &mut Context, action: Action) {
+        self.capturing.set(Some(action));
+        ctx.get_graphic_mut(self.status).set_text(&format!(
+            "Press a key for \"{}\", or Escape to cancel.",
+            action.name()
+        ));
+        ctx.set_focus(self.dialog);
+        self.update_list(ctx);
+    }
+
+    /// Handle a key press while capturing. Return false if not capturing.
+    fn capture(&self, ctx: &mut Context, key: Key) -> bool {
+        let action = match self.capturing.take() {
+            Some(x) => x,
+            None => return false,
+        };
+        if key.name != Some(VirtualKeyCode::Escape) {
+            edit_keymap(|keymap| keymap.bind(action, key));
+        }
+        self.update_list(ctx);
+        true
+    }
+
+    /// Rebuild the list of actions, and the status text.
+    fn update_list(&self, ctx: &mut Context) {
+        let Style {
+            text_style,
+            header_style,
+            delete_button,
+            delete_icon,
+            ..
+        } = ctx.get::<Style>().clone();
+
+        for child in ctx.get_active_children(self.list) {
+            ctx.remove(child);
+        }
+
+        let keymap = config().keymap.clone();
+        let capturing = self.capturing.get();
+        if capturing.is_none() {
+            ctx.get_graphic_mut(self.status)
+                .set_text(&conflicts_text(&keymap));
+        }
+
+        for action in Action::ALL {
+            let keys = if capturing == Some(action) {
+                "Press a key...".to_string()
+            } else {
+                keys_text(keymap.keys(action))
+            };
+            let text_style = text_style.clone();
+            let header_style = header_style.clone();
+            let delete_button = delete_button.clone();
+            let delete_icon = delete_icon.clone();
+            let this = self.clone();
+            let this_clear = self.clone();
+            ctx.create_control()
+                .parent(self.list)
+                .layout(HBoxLayout::new(4.0, [0.0; 4], 1))
+                .child(ctx, |cb, _| {
+                    cb.graphic(Text::new(
+                        action.name().to_string(),
+                        (-1, 0),
+                        text_style.clone(),
+                    ))
+                    .layout(FitGraphic)
+                    .min_size([110.0, 0.0])
+                })
+                .child(ctx, |cb, ctx| {
+                    cb.behaviour(Button::new(header_style, false, move |_, ctx| {
+                        this.start_capture(ctx, action)
+                    }))
+                    .expand_x(true)
+                    .child(ctx, |cb, _| {
+                        cb.graphic(Text::new(keys, (-1, 0), text_style))
+                            .layout(FitGraphic)
+                    })
+                })
+                .child(ctx, |cb, ctx| {
+                    cb.behaviour(Button::new(delete_button, true, move |_, ctx| {
+                        this_clear.capturing.set(None);
+                        edit_keymap(|keymap| keymap.clear(action));
+                        this_clear.update_list(ctx);
+                    }))
+                    .min_size([16.0, 16.0])
+                    .child(ctx, |cb, _| cb.graphic(delete_icon))
+                    .fill_y(RectFill::ShrinkCenter)
+                })
+                .build(ctx);
+        }
+    }
+}
+
+/// Open a dialog listing the joypad buttons and emulator actions, and the keys bound to them. A
+/// key is added to a action by clicking on its keys and pressing the key, and the keys of a action
+/// are removed by its delete button. The keys bound to more than one action are listed at the
+/// bottom, followed by the rate of the turbo keys. The bindings are saved on each change.
+pub fn open_keys_dialog(ctx: &mut Context, style: &Style) {
+    let [dialog, blocker, scroll_view, list, status, rate_text] = [(); 6].map(|_| ctx.reserve());
+    let this = KeysDialog {
+        dialog,
+        list,
+        status,
+        capturing: Rc::new(Cell::new(None)),
+    };
+
+    ctx.create_control_reserved(blocker)
+        .parent(Id::ROOT_ID)
+        .behaviour(Blocker::new(move |_, ctx| {
+            ctx.remove(dialog);
+            ctx.remove(blocker);
+        }))
+        .graphic(style.blocker.clone())
+        .build(ctx);
+
+    let this_key = this.clone();
+    ctx.create_control_reserved(dialog)
+        .parent(Id::ROOT_ID)
+        .behaviour(OnKeyboardEvent::new(move |event, _, ctx| match event {
+            giui::KeyboardEvent::Pressed(_) => {
+                let key = ctx.get::<KeyInput>().0;
+                this_key.capture(ctx, key)
+            }
+            _ => false,
+        }))
+        .layout(VBoxLayout::new(4.0, [10.0; 4], -1))
+        .graphic(style.split_background.clone())
+        .fill_x(RectFill::ShrinkCenter)
+        .fill_y(RectFill::ShrinkCenter)
+        .build(ctx);
+
+    ctx.create_control()
+        .parent(dialog)
+        .layout(MarginLayout::new([0.0, 0.0, 0.0, 6.0]))
+        .child(ctx, |cb, _| {
+            cb.graphic(Text::new(
+                "Key bindings".to_string(),
+                (-1, 0),
+                style.text_menu.clone(),
+            ))
+            .layout(FitGraphic)
+        })
+        .build(ctx);
+
+    crate::ui::scroll_viewer(ctx, scroll_view, list, style, (false, true))
+        .parent(dialog)
+        .min_size([340.0, 360.0])
+        .build(ctx);
+
+    ctx.create_control_reserved(status)
+        .parent(dialog)
+        .graphic(Text::new(
+            HINT.to_string(),
+            (-1, 0),
+            style.text_style.clone(),
+        ))
+        .layout(FitGraphic)
+        .build(ctx);
+
+    let rate_button = |up: bool| {
+        Button::new(style.header_style.clone(), true, move |_, ctx| {
+            step_rate(ctx, rate_text, up)
+        })
+    };
+    let rate = config().turbo_rate;
+    ctx.create_control()
+        .parent(dialog)
+        .layout(HBoxLayout::new(4.0, [0.0; 4], -1))
+        .child(ctx, |cb, ctx| {
+            cb.behaviour(rate_button(false))
+                .min_size([20.0, 0.0])
+                .child(ctx, |cb, _| {
+                    cb.graphic(Text::new("-".to_string(), (0, 0), style.text_style.clone()))
+                        .layout(FitGraphic)
+                })
+        })
+        .child_reserved(rate_text, ctx, |cb, _| {
+            cb.graphic(Text::new(
+                turbo_rate_text(rate),
+                (0, 0),
+                style.text_style.clone(),
+            ))
+            .layout(FitGraphic)
+            .min_size([110.0, 0.0])
+        })
+        .child(ctx, |cb, ctx| {
+            cb.behaviour(rate_button(true))
+                .min_size([20.0, 0.0])
+                .child(ctx, |cb, _| {
+                    cb.graphic(Text::new("+".to_string(), (0, 0), style.text_style.clone()))
+                        .layout(FitGraphic)
+                })
+        })
+        .build(ctx);
+
+    let this_reset = this.clone();
+    ctx.create_control()
+        .parent(dialog)
+        .behaviour(Button::new(
+            style.delete_button.clone(),
+            true,
+            move |_, ctx| {
+                this_reset.capturing.set(None);
+                edit_keymap(|keymap| *keymap = KeyMap::default());
+                this_reset.update_list(ctx);
+            },
+        ))
+        .fill_x(RectFill::ShrinkStart)
+        .child(ctx, |cb, _| {
+            cb.graphic(Text::new(
+                "reset to defaults".to_string(),
+                (-1, 0),
+                style.text_style.clone(),
+            ))
+            .layout(FitGraphic)
+        })
+        .build(ctx);
+
+    this.update_list(ctx);
+}
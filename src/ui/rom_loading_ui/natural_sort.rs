@@ -0,0 +1,128 @@
+use std::cmp::Ordering;
+
+/// Compare two names in natural order: letters are compared ignoring case, and runs of digits are
+/// compared by their numeric value, so "Game 2" comes before "Game 10".
+///
+/// Names that only differ by case or by leading zeros are still ordered, by the first such
+/// difference, with uppercase letters and fewer zeros first, so the order is total. Non-ASCII
+/// characters are compared by their lowercase code points, without any locale specific collation.
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    // the first difference in case or in leading zeros, used if there is no other difference
+    let mut tie = Ordering::Equal;
+    let (mut x, mut y) = (a, b);
+    loop {
+        let (cx, cy) = match (x.chars().next(), y.chars().next()) {
+            (None, None) => return tie,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(cx), Some(cy)) => (cx, cy),
+        };
+
+        if cx.is_ascii_digit() && cy.is_ascii_digit() {
+            let (dx, rest_x) = split_digits(x);
+            let (dy, rest_y) = split_digits(y);
+            let (nx, ny) = (dx.trim_start_matches('0'), dy.trim_start_matches('0'));
+            let ord = nx.len().cmp(&ny.len()).then_with(|| nx.cmp(ny));
+            if ord != Ordering::Equal {
+                return ord;
+            }
+            tie = tie.then(dx.len().cmp(&dy.len()));
+            x = rest_x;
+            y = rest_y;
+        } else {
+            let ord = cx.to_lowercase().cmp(cy.to_lowercase());
+            if ord != Ordering::Equal {
+                return ord;
+            }
+            tie = tie.then(cx.cmp(&cy));
+            x = &x[cx.len_utf8()..];
+            y = &y[cy.len_utf8()..];
+        }
+    }
+}
+
+/// Split the leading ASCII digits of `s` from the rest.
+fn split_digits(s: &str) -> (&str, &str) {
+    let end = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    s.split_at(end)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn sorted(names: &[&'static str]) -> Vec<&'static str> {
+        let mut names = names.to_vec();
+        names.sort_by(|a, b| natural_cmp(a, b));
+        names
+    }
+
+    #[test]
+    fn numbers() {
+        assert_eq!(
+            sorted(&["Game 10.gb", "Game 2.gb", "Game 1.gb", "Game.gb"]),
+            ["Game 1.gb", "Game 2.gb", "Game 10.gb", "Game.gb"]
+        );
+        assert_eq!(
+            sorted(&["a100", "a20", "a3", "a0003b", "a3a"]),
+            ["a3", "a3a", "a0003b", "a20", "a100"]
+        );
+        assert_eq!(
+            natural_cmp("99999999999999999999999", "100000000000000000000000"),
+            Ordering::Less
+        );
+    }
+
+    #[test]
+    fn leading_zeros() {
+        assert_eq!(
+            sorted(&["track 010", "track 9", "track 09", "track 10", "track 1"]),
+            ["track 1", "track 9", "track 09", "track 10", "track 010"]
+        );
+        assert_eq!(natural_cmp("x 001 b", "x 1 a"), Ordering::Greater);
+        assert_eq!(natural_cmp("x 01 a", "x 1 a"), Ordering::Greater);
+    }
+
+    #[test]
+    fn case() {
+        assert_eq!(
+            sorted(&["zelda.gb", "Metroid.gb", "mario.gb", "Mario.gb", "MARIO.gb"]),
+            ["MARIO.gb", "Mario.gb", "mario.gb", "Metroid.gb", "zelda.gb"]
+        );
+        assert_eq!(natural_cmp("Tetris", "tetris"), Ordering::Less);
+        assert_eq!(natural_cmp("tetris", "tetris"), Ordering::Equal);
+    }
+
+    #[test]
+    fn non_ascii() {
+        let names = [
+            "Pokémon Blue",
+            "Pokemon Red",
+            "Ökonomie",
+            "Zelda",
+            "ポケモン",
+            "pokémon red",
+        ];
+        let once = sorted(&names);
+        assert_eq!(
+            once,
+            [
+                "Pokemon Red",
+                "Pokémon Blue",
+                "pokémon red",
+                "Zelda",
+                "Ökonomie",
+                "ポケモン"
+            ]
+        );
+        // the order does not depend on the initial order
+        let mut reversed = names;
+        reversed.reverse();
+        assert_eq!(sorted(&reversed), once);
+        for a in names {
+            for b in names {
+                assert_eq!(natural_cmp(a, b), natural_cmp(b, a).reverse());
+            }
+        }
+    }
+}
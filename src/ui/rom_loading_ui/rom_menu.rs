@@ -0,0 +1,257 @@
+use std::fmt::Write;
+
+use gameroy::gameboy::cartridge::{Cartridge, CartridgeHeader, RomIntegrity};
+use giui::{
+    layouts::{FitGraphic, HBoxLayout, MarginLayout, VBoxLayout},
+    text::Text,
+    widgets::{Blocker, Button},
+    Context, Id, RectFill,
+};
+
+use super::{play_rom, play_rom_from_state, RomEntries, RomEntry};
+use crate::{
+    event_table, executor,
+    game_database::RomHash,
+    rom_loading::{RomFile, StateInfo},
+    style::Style,
+    widget::{context_menu::create_context_menu, menu::MenuOption},
+};
+
+/// Open the context menu of a entry of the rom list, at `pos`. `list` is the rom list, that is
+/// rebuilt when a save is deleted.
+pub fn open_rom_menu(ctx: &mut Context, entry: &RomEntry, pos: [f32; 2], list: Id) {
+    fn option<'a>(a: &'a str, b: impl FnMut(&mut Context) + 'static) -> MenuOption<'a> {
+        (a, Box::new(b))
+    }
+
+    let style = ctx.get::<Style>().clone();
+    let file = entry.file.clone();
+    let mut options = vec![option("Play", {
+        let file = file.clone();
+        move |ctx| play_rom(ctx, file.clone(), true)
+    })];
+    if let Some(StateInfo::Valid(_)) = entry.state {
+        options.push(option("Play from save state", {
+            let file = file.clone();
+            move |ctx| play_rom_from_state(ctx, file.clone())
+        }));
+    }
+    options.extend([
+        option("Play without save RAM", {
+            let file = file.clone();
+            move |ctx| play_rom(ctx, file.clone(), false)
+        }),
+        option("ROM info", {
+            let file = file.clone();
+            move |ctx| open_rom_info(ctx, &file)
+        }),
+        option("Delete battery save", {
+            let file = file.clone();
+            move |ctx| {
+                let message = format!("Delete the battery save of '{}'?", file.file_name());
+                let file = file.clone();
+                open_confirm_dialog(ctx, message, move |ctx| {
+                    if let Err(err) = file.delete_ram_data() {
+                        log::error!("error deleting battery save: {}", err);
+                    }
+                    refresh_saves(ctx, &file, list);
+                });
+            }
+        }),
+        option("Delete save state", {
+            let file = file.clone();
+            move |ctx| {
+                let message = format!("Delete the save state of '{}'?", file.file_name());
+                let file = file.clone();
+                open_confirm_dialog(ctx, message, move |ctx| {
+                    if let Err(err) = file.delete_state() {
+                        log::error!("error deleting save state: {}", err);
+                    }
+                    refresh_saves(ctx, &file, list);
+                });
+            }
+        }),
+    ]);
+    #[cfg(not(target_os = "android"))]
+    options.push(option("Open containing folder", move |_| {
+        if let Err(err) = file.open_containing_folder() {
+            log::error!("{}", err);
+        }
+    }));
+
+    create_context_menu(options, pos, ctx, &style);
+}
+
+/// Read again the save time and the save state of the rom, and rebuild the rom list.
+fn refresh_saves(ctx: &mut Context, file: &RomFile, list: Id) {
+    ctx.get_mut::<RomEntries>().refresh_saves(&file.file_name());
+    ctx.send_event_to(list, event_table::UpdatedRomList);
+}
+
+/// The information of the rom shown in the "ROM info" dialog, one field per line.
+fn rom_info(file: &RomFile, rom: &[u8]) -> Result<String, String> {
+    let header = match CartridgeHeader::from_bytes(rom) {
+        Ok(x) | Err((Some(x), _)) => x,
+        Err((None, err)) => return Err(err),
+    };
+    let integrity = RomIntegrity::from_rom(rom)?;
+    let mapper = Cartridge::new(rom.to_vec()).map_or_else(|err| err, |x| x.mapper_kind().into());
+    let hash = RomHash::from_bytes(rom);
+    let valid = |x: bool| if x { "valid" } else { "invalid" };
+
+    let mut text = String::new();
+    let _ = writeln!(text, "File: {}", file.file_name());
+    let _ = writeln!(text, "Title: {}", header.title_as_string());
+    let _ = writeln!(text, "Mapper: {}", mapper);
+    let _ = writeln!(text, "Cartridge type: {:02x}", header.cartridge_type);
+    match header.rom_size_in_bytes() {
+        Ok(size) => {
+            let _ = writeln!(
+                text,
+                "ROM size: {} KiB ({} bytes read)",
+                size >> 10,
+                rom.len()
+            );
+        }
+        Err(err) => {
+            let _ = writeln!(text, "ROM size: {} ({} bytes read)", err, rom.len());
+        }
+    }
+    let _ = writeln!(text, "RAM size: {:02x}", header.ram_size);
+    let _ = writeln!(
+        text,
+        "Battery: {}",
+        if header.has_battery() { "yes" } else { "no" }
+    );
+    let _ = writeln!(text, "CGB flag: {:02x}", header.cgb_flag);
+    let _ = writeln!(text, "SGB flag: {:02x}", header.sgb_flag);
+    let _ = writeln!(text, "Destination: {:02x}", header.destination_code);
+    let _ = writeln!(text, "Version: {:02x}", header.version);
+    let _ = writeln!(text, "Logo: {}", valid(integrity.logo_valid));
+    let _ = writeln!(
+        text,
+        "Header checksum: {:02x} ({})",
+        header.header_checksum,
+        valid(integrity.header_checksum_valid)
+    );
+    let _ = writeln!(
+        text,
+        "Global checksum: {:04x} ({})",
+        header.global_checksum,
+        valid(integrity.global_checksum_valid.unwrap_or(false))
+    );
+    let _ = writeln!(text, "CRC32: {:08x}", hash.crc32);
+    let _ = write!(text, "SHA-1: {}", hash.sha1_hex());
+    Ok(text)
+}
+
+/// Open a dialog with the header fields, the mapper, the checksums and the hash of the rom.
+fn open_rom_info(ctx: &mut Context, file: &RomFile) {
+    // the rom list is only filled on desktop and Android, where reading a rom completes
+    // immediately
+    let rom = executor::block_on(Box::pin(file.read()).as_mut());
+    let text = match rom.and_then(|rom| rom_info(file, &rom)) {
+        Ok(x) => x,
+        Err(err) => format!("error reading '{}': {}", file.file_name(), err),
+    };
+
+    let style = ctx.get::<Style>().clone();
+    let [dialog, _] = open_dialog(ctx, &style, "ROM info");
+    ctx.create_control()
+        .parent(dialog)
+        .graphic(Text::new(text, (-1, -1), style.text_style.clone()))
+        .layout(FitGraphic)
+        .build(ctx);
+}
+
+/// Open a dialog asking to confirm a destructive action, that calls `on_confirm` if confirmed.
+fn open_confirm_dialog(
+    ctx: &mut Context,
+    message: String,
+    on_confirm: impl FnOnce(&mut Context) + 'static,
+) {
+    let style = ctx.get::<Style>().clone();
+    let ids = open_dialog(ctx, &style, "Confirm");
+    let dialog = ids[0];
+    ctx.create_control()
+        .parent(dialog)
+        .graphic(Text::new(message, (-1, 0), style.text_style.clone()))
+        .layout(FitGraphic)
+        .build(ctx);
+
+    let buttons = ctx
+        .create_control()
+        .parent(dialog)
+        .layout(HBoxLayout::new(4.0, [0.0; 4], 1))
+        .build(ctx);
+    let mut on_confirm = Some(on_confirm);
+    for (text, confirm) in [("Cancel", false), ("Delete", true)] {
+        let mut on_confirm = confirm.then(|| on_confirm.take()).flatten();
+        ctx.create_control()
+            .parent(buttons)
+            .layout(MarginLayout::new([10.0, 4.0, 10.0, 4.0]))
+            .behaviour(Button::new(
+                style.delete_button.clone(),
+                true,
+                move |_, ctx| {
+                    close_dialog(ctx, ids);
+                    if let Some(on_confirm) = on_confirm.take() {
+                        on_confirm(ctx);
+                    }
+                },
+            ))
+            .child(ctx, |cb, _| {
+                cb.graphic(Text::new(
+                    text.to_string(),
+                    (0, 0),
+                    style.text_style.clone(),
+                ))
+                .layout(FitGraphic)
+            })
+            .build(ctx);
+    }
+}
+
+/// Create a empty dialog with the given title, in the center of the window, that is closed when
+/// clicking outside of it. Return the dialog, where the content is added, and the blocker behind
+/// it.
+fn open_dialog(ctx: &mut Context, style: &Style, title: &str) -> [Id; 2] {
+    let [dialog, blocker] = [(); 2].map(|_| ctx.reserve());
+
+    ctx.create_control_reserved(blocker)
+        .parent(Id::ROOT_ID)
+        .behaviour(Blocker::new(move |_, ctx| {
+            close_dialog(ctx, [dialog, blocker])
+        }))
+        .graphic(style.blocker.clone())
+        .build(ctx);
+
+    ctx.create_control_reserved(dialog)
+        .parent(Id::ROOT_ID)
+        .layout(VBoxLayout::new(4.0, [10.0; 4], -1))
+        .graphic(style.split_background.clone())
+        .fill_x(RectFill::ShrinkCenter)
+        .fill_y(RectFill::ShrinkCenter)
+        .build(ctx);
+
+    ctx.create_control()
+        .parent(dialog)
+        .layout(MarginLayout::new([0.0, 0.0, 0.0, 6.0]))
+        .child(ctx, |cb, _| {
+            cb.graphic(Text::new(
+                title.to_string(),
+                (-1, 0),
+                style.text_menu.clone(),
+            ))
+            .layout(FitGraphic)
+        })
+        .build(ctx);
+
+    [dialog, blocker]
+}
+
+/// Close a dialog created by `open_dialog`.
+fn close_dialog(ctx: &mut Context, [dialog, blocker]: [Id; 2]) {
+    ctx.remove(dialog);
+    ctx.remove(blocker);
+}
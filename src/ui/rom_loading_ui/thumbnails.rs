@@ -0,0 +1,187 @@
+use std::collections::HashMap;
+
+use image::{imageops, RgbaImage};
+
+/// The size of the thumbnails in the rom list, a quarter of the GameBoy screen.
+pub const THUMBNAIL_WIDTH: u32 = 40;
+pub const THUMBNAIL_HEIGHT: u32 = 36;
+
+/// The number of thumbnails in each row and column of the atlas.
+const ATLAS_COLUMNS: u32 = 16;
+const ATLAS_ROWS: u32 = 16;
+
+pub const ATLAS_WIDTH: u32 = THUMBNAIL_WIDTH * ATLAS_COLUMNS;
+pub const ATLAS_HEIGHT: u32 = THUMBNAIL_HEIGHT * ATLAS_ROWS;
+
+/// A thumbnail of a rom, with `THUMBNAIL_WIDTH` x `THUMBNAIL_HEIGHT` RGBA pixels.
+#[derive(Clone, Debug)]
+pub struct Thumbnail {
+    pixels: Box<[u8]>,
+}
+
+/// Decode a png image as a thumbnail. The image is scaled down to fit in the thumbnail, keeping its
+/// aspect ratio, and centered in it.
+pub fn decode_thumbnail(png: &[u8]) -> Result<Thumbnail, String> {
+    let image = image::load_from_memory_with_format(png, image::ImageFormat::Png)
+        .map_err(|e| e.to_string())?;
+    let image = if image.width() > THUMBNAIL_WIDTH || image.height() > THUMBNAIL_HEIGHT {
+        image.thumbnail(THUMBNAIL_WIDTH, THUMBNAIL_HEIGHT)
+    } else {
+        image
+    };
+
+    let mut thumbnail = RgbaImage::new(THUMBNAIL_WIDTH, THUMBNAIL_HEIGHT);
+    imageops::overlay(
+        &mut thumbnail,
+        &image.to_rgba8(),
+        ((THUMBNAIL_WIDTH - image.width()) / 2) as i64,
+        ((THUMBNAIL_HEIGHT - image.height()) / 2) as i64,
+    );
+    Ok(Thumbnail {
+        pixels: thumbnail.into_raw().into_boxed_slice(),
+    })
+}
+
+/// The thumbnails of the roms, packed in a single texture of `ATLAS_WIDTH` x `ATLAS_HEIGHT`
+/// pixels.
+pub struct ThumbnailAtlas {
+    pixels: Vec<u8>,
+    /// The slot of the thumbnail of each rom, by the rom key.
+    slots: HashMap<String, usize>,
+}
+impl Default for ThumbnailAtlas {
+    fn default() -> Self {
+        Self {
+            pixels: vec![0; (ATLAS_WIDTH * ATLAS_HEIGHT * 4) as usize],
+            slots: HashMap::new(),
+        }
+    }
+}
+impl ThumbnailAtlas {
+    /// The RGBA pixels of the atlas, to be uploaded to its texture.
+    pub fn pixels(&self) -> &[u8] {
+        &self.pixels
+    }
+
+    /// Remove all thumbnails.
+    pub fn clear(&mut self) {
+        self.slots.clear();
+    }
+
+    /// Add the thumbnail of the rom with the given key, replacing its previous one. Return false if
+    /// the atlas is full.
+    pub fn insert(&mut self, key: String, thumbnail: &Thumbnail) -> bool {
+        let len = self.slots.len();
+        let slot = *self.slots.entry(key).or_insert(len);
+        if slot >= (ATLAS_COLUMNS * ATLAS_ROWS) as usize {
+            self.slots.retain(|_, x| *x != slot);
+            return false;
+        }
+
+        let (x, y) = self.slot_position(slot);
+        let row_len = (THUMBNAIL_WIDTH * 4) as usize;
+        for (i, row) in thumbnail.pixels.chunks(row_len).enumerate() {
+            let start = (((y + i as u32) * ATLAS_WIDTH + x) * 4) as usize;
+            self.pixels[start..start + row_len].copy_from_slice(row);
+        }
+        true
+    }
+
+    /// The texture coordinates of the thumbnail of the rom with the given key, if it has one.
+    pub fn uv_rect(&self, key: &str) -> Option<[f32; 4]> {
+        let slot = *self.slots.get(key)?;
+        let (x, y) = self.slot_position(slot);
+        Some([
+            x as f32 / ATLAS_WIDTH as f32,
+            y as f32 / ATLAS_HEIGHT as f32,
+            THUMBNAIL_WIDTH as f32 / ATLAS_WIDTH as f32,
+            THUMBNAIL_HEIGHT as f32 / ATLAS_HEIGHT as f32,
+        ])
+    }
+
+    /// The position in pixels of the top left corner of the given slot.
+    fn slot_position(&self, slot: usize) -> (u32, u32) {
+        let slot = slot as u32;
+        (
+            (slot % ATLAS_COLUMNS) * THUMBNAIL_WIDTH,
+            (slot / ATLAS_COLUMNS) * THUMBNAIL_HEIGHT,
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn png(width: u32, height: u32, color: [u8; 4]) -> Vec<u8> {
+        let image = RgbaImage::from_pixel(width, height, image::Rgba(color));
+        let mut png = Vec::new();
+        image::DynamicImage::ImageRgba8(image)
+            .write_to(
+                &mut std::io::Cursor::new(&mut png),
+                image::ImageOutputFormat::Png,
+            )
+            .unwrap();
+        png
+    }
+
+    fn pixel(thumbnail: &Thumbnail, x: u32, y: u32) -> [u8; 4] {
+        let i = ((y * THUMBNAIL_WIDTH + x) * 4) as usize;
+        thumbnail.pixels[i..i + 4].try_into().unwrap()
+    }
+
+    #[test]
+    fn decode_keeps_aspect_ratio() {
+        const RED: [u8; 4] = [255, 0, 0, 255];
+
+        // a screenshot fills the thumbnail
+        let thumbnail = decode_thumbnail(&png(160, 144, RED)).unwrap();
+        assert_eq!(pixel(&thumbnail, 0, 0), RED);
+        assert_eq!(pixel(&thumbnail, 39, 35), RED);
+
+        // a wide image is centered vertically
+        let thumbnail = decode_thumbnail(&png(80, 36, RED)).unwrap();
+        assert_eq!(pixel(&thumbnail, 0, 8), [0; 4]);
+        assert_eq!(pixel(&thumbnail, 0, 9), RED);
+        assert_eq!(pixel(&thumbnail, 39, 26), RED);
+        assert_eq!(pixel(&thumbnail, 39, 27), [0; 4]);
+
+        // a small image is not scaled up
+        let thumbnail = decode_thumbnail(&png(2, 2, RED)).unwrap();
+        assert_eq!(pixel(&thumbnail, 19, 17), RED);
+        assert_eq!(pixel(&thumbnail, 21, 17), [0; 4]);
+
+        assert!(decode_thumbnail(b"not a png").is_err());
+    }
+
+    #[test]
+    fn atlas_slots() {
+        let thumbnail = decode_thumbnail(&png(40, 36, [1, 2, 3, 4])).unwrap();
+        let mut atlas = ThumbnailAtlas::default();
+        assert_eq!(atlas.uv_rect("a.gb"), None);
+
+        for i in 0..ATLAS_COLUMNS * ATLAS_ROWS {
+            assert!(atlas.insert(format!("{}.gb", i), &thumbnail));
+        }
+        // replacing a thumbnail reuses its slot
+        assert!(atlas.insert("17.gb".to_string(), &thumbnail));
+        assert!(!atlas.insert("a.gb".to_string(), &thumbnail));
+        assert_eq!(atlas.uv_rect("a.gb"), None);
+
+        let [x, y, w, h] = atlas.uv_rect("17.gb").unwrap();
+        assert_eq!(
+            (x * ATLAS_WIDTH as f32, y * ATLAS_HEIGHT as f32),
+            (40.0, 36.0)
+        );
+        assert_eq!(
+            (w * ATLAS_WIDTH as f32, h * ATLAS_HEIGHT as f32),
+            (40.0, 36.0)
+        );
+        let i = ((36 * ATLAS_WIDTH + 40) * 4) as usize;
+        assert_eq!(atlas.pixels()[i..i + 4], [1, 2, 3, 4]);
+
+        atlas.clear();
+        assert_eq!(atlas.uv_rect("17.gb"), None);
+        assert!(atlas.insert("a.gb".to_string(), &thumbnail));
+    }
+}
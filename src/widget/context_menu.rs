@@ -0,0 +1,95 @@
+use std::rc::Rc;
+
+use giui::{
+    layouts::{FitGraphic, MarginLayout, VBoxLayout},
+    text::Text,
+    widgets::{Blocker, Button},
+    Context, Id, RectFill,
+};
+
+use super::menu::MenuOption;
+use crate::style::Style;
+
+/// Open a popup menu at `pos`, with a button for each option, like the ones opened by a right
+/// click. It opens toward the side of the window with more space, and is closed when a option is
+/// chosen or when clicking outside of it.
+pub fn create_context_menu(
+    options: Vec<MenuOption>,
+    pos: [f32; 2],
+    ctx: &mut Context,
+    style: &Style,
+) -> Id {
+    let [menu, blocker] = [(); 2].map(|_| ctx.reserve());
+
+    let close = Rc::new(move |ctx: &mut Context| {
+        ctx.remove(menu);
+        ctx.remove(blocker);
+    });
+
+    let close_ = close.clone();
+    ctx.create_control_reserved(blocker)
+        .parent(Id::ROOT_ID)
+        .behaviour(Blocker::new(move |_, ctx| close_(ctx)))
+        .graphic(style.blocker.clone().with_alpha(0))
+        .build(ctx);
+
+    let (margins, fill_x, fill_y) = popup_placement(pos, ctx);
+    ctx.create_control_reserved(menu)
+        .parent(Id::ROOT_ID)
+        .anchors([0.0; 4])
+        .margins(margins)
+        .layout(VBoxLayout::new(1.0, [0.0, 4.0, 0.0, 4.0], -1))
+        .graphic(style.split_background.clone())
+        .fill_x(fill_x)
+        .fill_y(fill_y)
+        .build(ctx);
+
+    for (text, function) in options {
+        let close = close.clone();
+        // taken by the first click, so a option is never called twice
+        let mut function = Some(function);
+        ctx.create_control()
+            .parent(menu)
+            .layout(MarginLayout::new([10.0, 4.0, 10.0, 4.0]))
+            .behaviour(Button::new(
+                style.delete_button.clone(),
+                false,
+                move |_, ctx| {
+                    if let Some(mut function) = function.take() {
+                        close(ctx);
+                        function(ctx);
+                    }
+                },
+            ))
+            .child(ctx, |cb, _| {
+                cb.graphic(Text::new(
+                    text.to_string(),
+                    (-1, 0),
+                    style.text_style.clone(),
+                ))
+                .layout(FitGraphic)
+            })
+            .build(ctx);
+    }
+
+    menu
+}
+
+/// The margins and fills of a popup opened at `pos`, relative to the root. It is placed in the
+/// space between `pos` and the farthest edge of the window, shrunk toward `pos`.
+pub(super) fn popup_placement(pos: [f32; 2], ctx: &mut Context) -> ([f32; 4], RectFill, RectFill) {
+    let root = ctx.get_rect(Id::ROOT_ID);
+    let [x, y] = [pos[0] - root[0], pos[1] - root[1]];
+    let [width, height] = [root[2] - root[0], root[3] - root[1]];
+    let (left, right, fill_x) = if x < width / 2.0 {
+        (x, width, RectFill::ShrinkStart)
+    } else {
+        (0.0, x, RectFill::ShrinkEnd)
+    };
+    let (top, bottom, fill_y) = if y < height / 2.0 {
+        (y, height, RectFill::ShrinkStart)
+    } else {
+        (0.0, y, RectFill::ShrinkEnd)
+    };
+    ([left, top, right, bottom], fill_x, fill_y)
+}
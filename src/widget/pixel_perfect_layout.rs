@@ -1,7 +1,39 @@
 use giui::{Id, Layout, LayoutContext, MinSizeContext};
 
+/// The rect where a screen of the given `size` is drawn inside `rect`: the largest one that keeps
+/// the aspect ratio of the screen, centered, leaving bars in the remaining space, of the
+/// `border_color` of the config. If
+/// `integer_scale` is true, the scale is also rounded down to a integer, unless the screen doesn't
+/// fit in `rect` even at scale 1.
+pub fn screen_rect(size: (u32, u32), rect: [f32; 4], integer_scale: bool) -> [f32; 4] {
+    let width = rect[2] - rect[0];
+    let height = rect[3] - rect[1];
+
+    let mut scale = (width / size.0 as f32).min(height / size.1 as f32);
+    if integer_scale && scale >= 1.0 {
+        scale = scale.floor();
+    }
+
+    let des_width = size.0 as f32 * scale;
+    let des_height = size.1 as f32 * scale;
+    let x = (rect[0] + (width - des_width) / 2.0).floor();
+    let y = (rect[1] + (height - des_height) / 2.0).floor();
+    [x, y, x + des_width, y + des_height]
+}
+
+/// The largest integer scale, up to `scale`, in which a screen of the given `size` fits in the
+/// `available` size. The scale is at least 1.
+pub fn clamp_scale(size: (u32, u32), scale: u32, available: (u32, u32)) -> u32 {
+    scale
+        .min(available.0 / size.0)
+        .min(available.1 / size.1)
+        .max(1)
+}
+
 pub struct ScreenLayout {
     size: (u32, u32),
+    /// Only scale the screen by integer multiples of its size.
+    pub integer_scale: bool,
     pub foward_button: Option<Id>,
     pub rewind_button: Option<Id>,
 }
@@ -9,6 +41,7 @@ impl ScreenLayout {
     pub fn new(size: (u32, u32)) -> Self {
         Self {
             size,
+            integer_scale: true,
             foward_button: None,
             rewind_button: None,
         }
@@ -31,50 +64,119 @@ impl Layout for ScreenLayout {
     }
 
     fn update_layouts(&mut self, this: Id, ctx: &mut LayoutContext) {
-        let rect = ctx.get_layouting(this);
-        let mut x = rect.get_rect()[0];
-        let mut y = rect.get_rect()[1];
-
-        let width = rect.get_width();
-        let height = rect.get_height();
-
-        let des_width;
-        let des_height;
-        let ratio = self.size.0 as f32 / self.size.1 as f32;
-
-        if width / height < ratio {
-            des_width = self.size.0 as f32 * (width / self.size.0 as f32).floor();
-            des_height = (des_width / ratio).round();
-        } else {
-            des_height = self.size.1 as f32 * (height / self.size.1 as f32).floor();
-            des_width = des_height * ratio;
-        }
-
-        x += (width - des_width) / 2.0;
-
-        if width / height >= ratio {
-            y += (height - des_height) / 2.0;
-        } else {
-            y += (width - des_width) / 2.0;
-        }
-
-        let des_rect = [x.floor(), y.floor(), x + des_width, y + des_height];
+        let rect = ctx.get_layouting(this).get_rect();
+        let des_rect = screen_rect(self.size, rect, self.integer_scale);
         for child in ctx.get_active_children(this) {
             ctx.set_designed_rect(child, des_rect);
         }
 
+        let [x, _, right, bottom] = des_rect;
+
         if let Some(button) = self.foward_button {
             let [w, h] = ctx.get_min_size(button);
-            let x = x + des_width;
-            let y = 10.0 + y + des_height;
-            ctx.set_designed_rect(button, [x - w, y, x, y + h]);
+            let y = 10.0 + bottom;
+            ctx.set_designed_rect(button, [right - w, y, right, y + h]);
         }
 
         if let Some(button) = self.rewind_button {
             let [w, h] = ctx.get_min_size(button);
-            let x = x;
-            let y = 10.0 + y + des_height;
+            let y = 10.0 + bottom;
             ctx.set_designed_rect(button, [x, y, x + w, y + h]);
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const SIZE: (u32, u32) = (160, 144);
+
+    #[test]
+    fn exact_multiple() {
+        assert_eq!(
+            screen_rect(SIZE, [0.0, 0.0, 480.0, 432.0], true),
+            [0.0, 0.0, 480.0, 432.0]
+        );
+        assert_eq!(
+            screen_rect(SIZE, [10.0, 20.0, 170.0, 164.0], false),
+            [10.0, 20.0, 170.0, 164.0]
+        );
+    }
+
+    #[test]
+    fn letterbox() {
+        // wider than the screen: bars on the left and right
+        assert_eq!(
+            screen_rect(SIZE, [0.0, 0.0, 800.0, 288.0], true),
+            [240.0, 0.0, 560.0, 288.0]
+        );
+        // taller than the screen: bars on the top and bottom
+        assert_eq!(
+            screen_rect(SIZE, [0.0, 0.0, 320.0, 500.0], false),
+            [0.0, 106.0, 320.0, 394.0]
+        );
+    }
+
+    #[test]
+    fn integer_scale() {
+        // 2.5x is rounded down to 2x, and centered
+        assert_eq!(
+            screen_rect(SIZE, [0.0, 0.0, 400.0, 360.0], true),
+            [40.0, 36.0, 360.0, 324.0]
+        );
+        assert_eq!(
+            screen_rect(SIZE, [0.0, 0.0, 400.0, 360.0], false),
+            [0.0, 0.0, 400.0, 360.0]
+        );
+        // smaller than 1x is not rounded down to zero
+        assert_eq!(
+            screen_rect(SIZE, [0.0, 0.0, 80.0, 72.0], true),
+            [0.0, 0.0, 80.0, 72.0]
+        );
+    }
+
+    #[test]
+    fn bar_sizes() {
+        // the size of the bars on the left, top, right and bottom of the screen
+        let bars = |rect: [f32; 4], integer_scale: bool| {
+            let screen = screen_rect(SIZE, rect, integer_scale);
+            [
+                screen[0] - rect[0],
+                screen[1] - rect[1],
+                rect[2] - screen[2],
+                rect[3] - screen[3],
+            ]
+        };
+        assert_eq!(bars([0.0, 0.0, 480.0, 432.0], true), [0.0; 4]);
+        assert_eq!(
+            bars([0.0, 0.0, 800.0, 288.0], false),
+            [240.0, 0.0, 240.0, 0.0]
+        );
+        // a odd remaining space leaves the extra pixel in the right or bottom bar
+        assert_eq!(bars([0.0, 0.0, 481.0, 432.0], true), [0.0, 0.0, 1.0, 0.0]);
+        assert_eq!(
+            bars([0.0, 0.0, 400.0, 361.0], true),
+            [40.0, 36.0, 40.0, 37.0]
+        );
+        // the bars are relative to the rect
+        assert_eq!(
+            bars([10.0, 20.0, 810.0, 308.0], false),
+            [240.0, 0.0, 240.0, 0.0]
+        );
+        // 1366x768, at 5x with integer scaling
+        assert_eq!(
+            bars([0.0, 0.0, 1366.0, 768.0], true),
+            [283.0, 24.0, 283.0, 24.0]
+        );
+    }
+
+    #[test]
+    fn clamp_to_monitor() {
+        assert_eq!(clamp_scale(SIZE, 4, (1920, 1080)), 4);
+        assert_eq!(clamp_scale(SIZE, 10, (1920, 1080)), 7);
+        assert_eq!(clamp_scale(SIZE, 10, (3840, 2160)), 10);
+        assert_eq!(clamp_scale(SIZE, 4, (100, 100)), 1);
+        assert_eq!(clamp_scale(SIZE, 0, (1920, 1080)), 1);
+    }
+}
@@ -1,10 +1,14 @@
 use std::{cell::RefCell, rc::Rc};
 
 use giui::{Behaviour, Context, Id, InputFlags, Layout, MouseEvent};
+use instant::{Duration, Instant};
 use winit::window::CursorIcon;
 
 type ColumnIndex = u16;
 
+/// The minimum time a touch must be held to open the context menu, on Android.
+const LONG_PRESS: Duration = Duration::from_millis(500);
+
 /// Contains the layout data that is common to all TableItems.
 #[derive(Default)]
 pub struct TableGroup {
@@ -18,6 +22,9 @@ pub struct TableGroup {
     dragging_anchor: f32,
     /// The width of the table. Updates every time is queried, unless a dragging is happening.
     total_width: f32,
+    /// The item and the column whose tooltip is open, and the tooltip control. It is kept here,
+    /// instead of in the item, so only one tooltip is open, even if the item is rebuilt.
+    open_tooltip: Option<(Id, usize, Id)>,
 }
 impl TableGroup {
     pub fn new(h_spacing: f32, v_spacing: f32, h_margins: [f32; 2]) -> Self {
@@ -74,6 +81,14 @@ pub struct TableItem {
     group: Rc<RefCell<TableGroup>>,
     pub resizable: bool,
     on_click: Option<Box<dyn FnMut(u8, &mut Context)>>,
+    /// Called with the mouse position on a right click, or on a long press on Android.
+    on_context_menu: Option<Box<dyn FnMut([f32; 2], &mut Context)>>,
+    /// Called when the user finishes resizing a column.
+    on_resize: Option<Box<dyn FnMut(&mut Context)>>,
+    /// When the left button was last pressed, to detect long presses.
+    pressed_at: Option<Instant>,
+    /// The text shown when hovering each column, if any.
+    tooltips: Vec<Option<String>>,
 }
 impl TableItem {
     pub fn new(group: Rc<RefCell<TableGroup>>) -> Self {
@@ -81,6 +96,10 @@ impl TableItem {
             group,
             resizable: false,
             on_click: None,
+            on_context_menu: None,
+            on_resize: None,
+            pressed_at: None,
+            tooltips: Vec::new(),
         }
     }
 
@@ -93,6 +112,72 @@ impl TableItem {
         self.on_click = Some(Box::new(on_click));
     }
 
+    pub fn set_on_context_menu(
+        &mut self,
+        on_context_menu: impl FnMut([f32; 2], &mut Context) + 'static,
+    ) {
+        self.on_context_menu = Some(Box::new(on_context_menu));
+    }
+
+    pub fn set_on_resize(&mut self, on_resize: impl FnMut(&mut Context) + 'static) {
+        self.on_resize = Some(Box::new(on_resize));
+    }
+
+    /// Set the text shown when hovering each column. Columns without a text show no tooltip.
+    pub fn set_tooltips(&mut self, tooltips: Vec<Option<String>>) {
+        self.tooltips = tooltips;
+    }
+
+    /// Open the tooltip of the column under the mouse, closing the one of the previous column.
+    fn update_tooltip(&mut self, mouse_pos: [f32; 2], this: Id, ctx: &mut Context) {
+        let rect = ctx.get_rect(this);
+        let column = self.column_at(mouse_pos, rect, &self.group.borrow());
+        let open = self
+            .group
+            .borrow()
+            .open_tooltip
+            .map(|(item, x, _)| (item, x));
+        if column.is_some() && open == column.map(|x| (this, x)) {
+            return;
+        }
+        self.close_tooltip(ctx);
+        let text = column.and_then(|x| Some((x, self.tooltips.get(x)?.clone()?)));
+        if let Some((column, text)) = text {
+            let id = super::tooltip::open_tooltip(text, mouse_pos, ctx);
+            self.group.borrow_mut().open_tooltip = Some((this, column, id));
+        }
+    }
+
+    fn close_tooltip(&mut self, ctx: &mut Context) {
+        let open_tooltip = self.group.borrow_mut().open_tooltip.take();
+        if let Some((_, _, id)) = open_tooltip {
+            ctx.remove(id);
+        }
+    }
+
+    /// The index of the column under the mouse, if any.
+    fn column_at(&self, mouse_pos: [f32; 2], rect: [f32; 4], g: &TableGroup) -> Option<usize> {
+        let mut x = rect[0] + g.h_margins[0];
+        for (i, c) in g.columns.iter().enumerate() {
+            if mouse_pos[0] < x {
+                return None;
+            }
+            if mouse_pos[0] < x + c.curr_width {
+                return Some(i);
+            }
+            x += c.curr_width + g.h_spacing;
+        }
+        None
+    }
+
+    /// If the left button was released after being held long enough to open the context menu.
+    fn is_long_press(&mut self) -> bool {
+        let pressed_at = self.pressed_at.take();
+        cfg!(target_os = "android")
+            && self.on_context_menu.is_some()
+            && pressed_at.map_or(false, |x| x.elapsed() >= LONG_PRESS)
+    }
+
     /// If hovering a split, return the index of the column to be resized, and if it is in
     /// reverse.
     fn to_be_dragged(
@@ -136,9 +221,28 @@ impl Behaviour for TableItem {
     }
 
     fn on_mouse_event(&mut self, mouse: giui::MouseInfo, this: Id, ctx: &mut giui::Context) {
-        if let MouseEvent::Up(giui::MouseButton::Left) = mouse.event {
-            self.on_click.as_mut().map(|x| x(mouse.click_count, ctx));
-        };
+        if !self.tooltips.is_empty() {
+            match mouse.event {
+                MouseEvent::Moved => self.update_tooltip(mouse.pos, this, ctx),
+                MouseEvent::Exit | MouseEvent::Down(_) => self.close_tooltip(ctx),
+                _ => {}
+            }
+        }
+        match mouse.event {
+            MouseEvent::Down(giui::MouseButton::Left) => self.pressed_at = Some(Instant::now()),
+            MouseEvent::Up(giui::MouseButton::Left) => {
+                if self.is_long_press() {
+                    self.on_context_menu.as_mut().map(|x| x(mouse.pos, ctx));
+                } else {
+                    self.on_click.as_mut().map(|x| x(mouse.click_count, ctx));
+                }
+            }
+            MouseEvent::Up(giui::MouseButton::Right) => {
+                self.on_context_menu.as_mut().map(|x| x(mouse.pos, ctx));
+            }
+            MouseEvent::Exit => self.pressed_at = None,
+            _ => {}
+        }
         if !self.resizable {
             return;
         }
@@ -154,14 +258,20 @@ impl Behaviour for TableItem {
                 }
             }
             MouseEvent::Up(giui::MouseButton::Left) => {
-                let g = &mut self.group.borrow_mut();
-                g.dragging = None;
-                ctx.lock_cursor(false, mouse.id);
+                let resized = {
+                    let g = &mut self.group.borrow_mut();
+                    let resized = g.dragging.take().is_some();
+                    ctx.lock_cursor(false, mouse.id);
 
-                // update total width
-                g.total_width();
+                    // update total width
+                    g.total_width();
+                    resized
+                };
 
                 ctx.dirty_layout(this);
+                if resized {
+                    self.on_resize.as_mut().map(|x| x(ctx));
+                }
             }
             MouseEvent::Exit => ctx.set_cursor(CursorIcon::Default),
             MouseEvent::Moved => {
@@ -0,0 +1,32 @@
+use giui::{
+    layouts::{FitGraphic, MarginLayout},
+    text::Text,
+    Context, Id,
+};
+
+use super::context_menu::popup_placement;
+use crate::style::Style;
+
+/// The distance between the mouse and the tooltip, so the tooltip is not under the cursor.
+const OFFSET: [f32; 2] = [0.0, 16.0];
+
+/// Open a popup showing `text` near `pos`, the position of the mouse. Unlike the context menu, it
+/// don't block the input, and must be closed by removing the returned control.
+pub fn open_tooltip(text: String, pos: [f32; 2], ctx: &mut Context) -> Id {
+    let style = ctx.get::<Style>().clone();
+    let pos = [pos[0] + OFFSET[0], pos[1] + OFFSET[1]];
+    let (margins, fill_x, fill_y) = popup_placement(pos, ctx);
+    ctx.create_control()
+        .parent(Id::ROOT_ID)
+        .anchors([0.0; 4])
+        .margins(margins)
+        .layout(MarginLayout::new([4.0; 4]))
+        .graphic(style.split_background.clone())
+        .fill_x(fill_x)
+        .fill_y(fill_y)
+        .child(ctx, |cb, _| {
+            cb.graphic(Text::new(text, (-1, 0), style.text_style.clone()))
+                .layout(FitGraphic)
+        })
+        .build(ctx)
+}
@@ -7,6 +7,10 @@ pub fn run() {
     panic::set_hook(Box::new(console_error_panic_hook::hook));
     let _logger = wasm_logger::init(wasm_logger::Config::default().module_prefix("gameroy"));
     gameroy_lib::log_panic();
+    // there is no saved config in the first run, so the default one is kept
+    if let Ok(config) = gameroy_lib::config::Config::load() {
+        gameroy_lib::config::init_config(config);
+    }
     gameroy_lib::main(None, None);
 }
 